@@ -1,45 +1,227 @@
-use crate::backend::ai_backend::{AiBackend, AiDocumentContext, AiRequestHandle, AiRequestId};
+use crate::backend::ai_backend::{
+    AiBackend, AiChatMessage, AiDocumentContext, AiError, AiProgressEvent, AiRequestHandle,
+    AiRequestId, AiSelectionContext,
+};
+use crate::backend::ai_dispatch::AiDispatcher;
+use crate::backend::ai_panel_backend::AiPanelBackend;
+use crate::backend::daily_log::DailyLogBackend;
 use crate::backend::editor_backend::EditorBackend;
+use crate::backend::file_settings::{FileSettings, FileSettingsBackend};
+use crate::backend::global_search::{self, SearchCancel};
+use crate::backend::marked_files::marked_files;
+use crate::backend::productivity::ProductivityTracker;
+use crate::backend::recovery::RecoveryBackend;
+use crate::backend::redaction::redact_document;
 use crate::backend::sidebar_backend::{Mark, SidebarBackend};
 use crate::backend::time_backend::TimeBackend;
-use crate::file::FileData;
-use crate::messages::ResponseMessage;
+use crate::backend::usage_log::{
+    TokenUsage, UsageLog, UsageLogBackend, day_total, estimate_cost, format_tokens, record_usage,
+};
+use crate::busy::{BusyKind, BusyTasks};
+use crate::config::{Session, SessionTab, Settings};
+use crate::constant::NEW_WINDOW_POSITION_ENV;
+use crate::controller::{
+    AppController, DiskStore, Outcome, SaveState, TitleEffect, apply_loaded, apply_saved,
+    spawn_thread, take_autosave,
+};
+use crate::crash_guard::{BufferMirror, MIRROR_INTERVAL};
+use crate::datetime::expand_placeholders;
+use crate::deeplink::{self, DeepLink};
+use crate::error::{AppError, ErrorLog, read_document};
+use crate::file::{ExitAction, FileData, exit_action, write_atomic};
+use crate::file_manager;
+use crate::file_watcher::{FileChange, FileWatcher};
+use crate::messages::{MAX_MESSAGES_PER_FRAME, ResponseMessage, drain_messages};
 use crate::plugin::{PluginContext, PluginManager};
+use crate::print::{self, PrintJob};
+use crate::shortcuts::{self, Action};
 use crate::style::configure_style;
+use crate::tabs::{TabId, Tabs};
 use crate::ui::ai_panel::AiPanelAction;
-use crate::ui::editor::Editor;
+use crate::ui::ai_panel_frame::show_ai_panel_frame;
+use crate::ui::ai_review::AiReviewWindow;
+use crate::ui::all_marks::{AllMarksAction, AllMarksWindow};
+use crate::ui::editor::{Editor, EditorAppearance, TextStats, content_hash};
+use crate::ui::error_log::ErrorLogWindow;
+use crate::ui::font::{FontWeight, SystemFonts};
+use crate::ui::font_preview::FontPreviews;
+use crate::ui::frequent_words::{FrequentWordsAction, FrequentWordsWindow};
+use crate::ui::global_search::{GlobalSearchAction, GlobalSearchWindow};
 use crate::ui::history::{HistoryAction, HistoryWindow};
+use crate::ui::log_viewer::LogViewerWindow;
+use crate::ui::onboarding::{
+    OnboardingAction, OnboardingChoices, OnboardingContext, OnboardingStep,
+};
 use crate::ui::plugins::{
     GithubPublishConfigWindow, PluginOutputWindow, PrintDialog, PublishDialog,
 };
-use crate::ui::settings::SettingsWindow;
-
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::ui::settings::{SettingsAction, SettingsWindow};
+use crate::ui::stats::StatsWindow;
+use crate::ui::tab_bar::{TabBarAction, TabInfo};
+use crate::ui::time_debug::TimeDebugWindow;
+use crate::ui::title_bar::{DetailedStats, TitleBarAction, stats_popover_id};
+use crate::ui::toast::Toasts;
+use crate::ui::window_frame::WindowFrame;
+use chrono::NaiveDate;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::{Duration, Instant};
+
+/// The last chat request as it was sent, so regenerating repeats it exactly
+/// instead of re-reading a buffer that may have changed since
+struct SentAiPrompt {
+    document: AiDocumentContext,
+    conversation: Vec<AiChatMessage>,
+    /// Number of times the prompt has been regenerated
+    generation: u32,
+}
 
-type LoadFileResult = (FileData, HashMap<usize, Mark>);
+/// How often session writing time is appended to the per-day log
+const DAILY_LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// First-run flow, shown when no settings file existed
+enum Onboarding {
+    /// Not a first run, or the flow was finished or skipped
+    Inactive,
+    Active {
+        step: OnboardingStep,
+        choices: OnboardingChoices,
+        /// What skipping goes back to
+        defaults: OnboardingChoices,
+    },
+}
 
-pub struct PaperShellApp {
+/// How close to the top edge the pointer has to come to bring back the title
+/// bar in distraction-free mode
+const FOCUS_REVEAL_EDGE: f32 = 4.0;
+
+/// Threads reading documents for the 全局搜索 palette
+const GLOBAL_SEARCH_WORKERS: usize = 4;
+
+/// Words listed in the 高频词 window
+const FREQUENT_WORDS_SHOWN: usize = 50;
+/// Longest document counted for the 高频词 window on the UI thread, in bytes;
+/// longer ones are counted in the background
+const FREQUENT_WORDS_INLINE_LIMIT: usize = 20_000;
+
+/// The fonts loaded into egui, compared with what the settings ask for to
+/// tell when to load others
+#[derive(Clone, Default, PartialEq)]
+struct LoadedFonts {
+    /// Chinese font family; `None` is the system default
+    family: Option<String>,
+    /// Font file used in place of `family`
+    file: Option<PathBuf>,
+    /// Latin font in front of the Chinese font
+    latin: Option<String>,
+    weight: FontWeight,
+    y_offsets: BTreeMap<String, f32>,
+}
+
+/// Distraction-free writing: fullscreen with only the text column. Holds
+/// what entering it changed, so leaving puts everything back.
+struct FocusMode {
+    ai_panel_visible: bool,
+    was_fullscreen: bool,
+    /// The window has gone fullscreen since; leaving fullscreen some other
+    /// way, e.g. through the system, ends the mode too
+    reached_fullscreen: bool,
+    /// The title bar is shown for now because the pointer went to the top
+    title_bar_revealed: bool,
+    /// Bottom of the title bar when last shown; moving below it hides the
+    /// bar again
+    title_bar_bottom: f32,
+}
+
+/// Everything that belongs to one open document. The active tab's is
+/// [`PaperShellApp::doc`]; the others wait in [`PaperShellApp::tabs`].
+struct Document {
     editor: Editor,
+    /// Overrides of the file, merged over the global settings
+    file_settings: FileSettings,
+    productivity: ProductivityTracker,
+    last_ai_prompt: Option<SentAiPrompt>,
+    /// In-flight narrative map extraction and the uuid of the file it is for
+    narrative_map_request: Option<(AiRequestHandle, String)>,
+    saves: SaveState,
+    /// Focused seconds spent on the document while it was active before,
+    /// not yet saved with it
+    unsaved_writing_secs: u64,
+    /// Another program changed or deleted the file and the user has not
+    /// dealt with it yet
+    external_change: Option<FileChange>,
+    /// How far the text is scrolled down, in points
+    scroll_offset: f32,
+    /// Scroll offset to apply on the next frame, e.g. from the last session
+    pending_scroll: Option<f32>,
+}
+
+impl Document {
+    fn new(editor: Editor) -> Self {
+        Self {
+            editor,
+            file_settings: FileSettings::default(),
+            productivity: ProductivityTracker::new(),
+            last_ai_prompt: None,
+            narrative_map_request: None,
+            saves: SaveState::default(),
+            unsaved_writing_secs: 0,
+            external_change: None,
+            scroll_offset: 0.0,
+            pending_scroll: None,
+        }
+    }
+}
+
+pub struct PaperShellApp {
+    /// Document of the active tab
+    doc: Document,
+    tabs: Tabs<Document>,
+    /// Set while another tab's document is swapped in to take a reply to
+    /// work it started; the tab the user is on
+    home_tab: Option<TabId>,
     pub response_sender: Sender<ResponseMessage>,
+    /// Unsaved content of the open tabs, for the panic hook
+    pub buffer_mirror: BufferMirror,
+    last_mirror_update: Instant,
     response_receiver: Receiver<ResponseMessage>,
 
     history_window: HistoryWindow,
 
-    current_font: String,
-    available_fonts: Vec<String>,
+    current_fonts: LoadedFonts,
+    /// Weights the current font family has; `None` until they are known
+    font_weights: Option<Vec<FontWeight>>,
+    available_fonts: SystemFonts,
+    font_previews: FontPreviews,
+    /// The font scan has not reported back yet
+    fonts_loading: bool,
 
     last_focus_state: bool,
     config: crate::config::Config,
 
     editor_backend: Arc<EditorBackend>,
+    /// Opening, saving, auto-saving and history of the documents
+    controller: AppController,
     sidebar_backend: Arc<SidebarBackend>,
+    file_settings_backend: Arc<FileSettingsBackend>,
+    /// The effective editor settings changed and are applied on the next frame
+    editor_settings_outdated: bool,
     time_backend: TimeBackend,
+    daily_log: Arc<DailyLogBackend>,
+    /// Session focus/typing milliseconds already written to the daily log
+    logged_time_ms: (u64, u64),
+    last_daily_log_flush: Instant,
+    /// Focused seconds in the daily log for the day, as of this session's
+    /// last flush; a new day starts from zero
+    today_logged: (NaiveDate, u64),
     ai_backend: Arc<AiBackend>,
-    next_ai_request_id: AiRequestId,
-    active_ai_request: Option<AiRequestHandle>,
+    ai_requests: AiDispatcher,
+    usage_log_backend: Arc<UsageLogBackend>,
+    /// Token usage per day, kept in memory for the panel and settings
+    usage_log: UsageLog,
+    ai_panel_backend: Arc<AiPanelBackend>,
 
     plugin_manager: PluginManager,
     plugin_metadata: Vec<crate::plugin::PluginMetadata>,
@@ -48,44 +230,169 @@ pub struct PaperShellApp {
     publish_dialog: PublishDialog,
     print_dialog: PrintDialog,
     settings_window: SettingsWindow,
+    time_debug_window: TimeDebugWindow,
+    log_viewer: LogViewerWindow,
+    stats_window: StatsWindow,
+    all_marks_window: AllMarksWindow,
+    frequent_words_window: FrequentWordsWindow,
+    global_search_window: GlobalSearchWindow,
+    /// The global search running, by id
+    global_search: Option<(u64, SearchCancel)>,
+    error_window: ErrorLogWindow,
+    /// Errors of this session, for the 最近错误 window
+    error_log: ErrorLog,
+    ai_review_window: AiReviewWindow,
+    /// AI panel moved or resized since the settings were last written
+    ai_panel_layout_dirty: bool,
+    /// Restored window geometry checked against the monitor
+    window_fitted: bool,
+    /// Folder of the file opened or saved last, where file dialogs start
+    last_dialog_dir: Option<PathBuf>,
+    /// Problem with the settings file, shown until dismissed
+    config_warning: Option<String>,
+    /// Title last given to the OS window
+    window_title: String,
+    /// Short-lived message in the bottom bar and when it was shown
+    toasts: Toasts,
+    /// Opens, saves and history loads running in the background
+    busy: BusyTasks,
+    /// Decorations the window was created with; changing them needs a restart
+    window_frame: WindowFrame,
+    focus_mode: Option<FocusMode>,
+    onboarding: Onboarding,
+    /// The user went through the unsaved documents when closing the window;
+    /// what they chose to discard stays discarded on exit
+    close_confirmed: bool,
+    /// Reports outside changes to the open files; `None` if it could not start
+    file_watcher: Option<FileWatcher>,
+    /// The files the watcher was last told about
+    watched_files: Vec<PathBuf>,
+    /// Last session's files, reopened on the first frame unless a file is
+    /// opened on purpose before that
+    pending_session: Option<Session>,
+}
+
+/// What a new window starts with
+pub enum Startup {
+    /// An empty document, e.g. for 新窗口 or `--new`
+    Empty,
+    /// A file given on the command line
+    File(PathBuf),
+    /// The files of the last session, if restoring them is enabled
+    LastSession,
 }
 
 impl Default for PaperShellApp {
     fn default() -> Self {
-        // Initialize tracing subscriber for console output
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::DEBUG)
-            .with_thread_ids(true)
-            .init();
+        Self::with_config(crate::config::Config::default())
+    }
+}
 
+impl PaperShellApp {
+    /// The app with its backends opened in the data dir of `config`
+    fn with_config(config: crate::config::Config) -> Self {
         let (sender, receiver) = channel();
-        let editor = Editor::default();
-        let sidebar_backend = Arc::new(SidebarBackend::new().unwrap_or_else(|e| {
-            tracing::error!("Failed to initialize SidebarBackend: {}", e);
-            panic!("Cannot continue without SidebarBackend");
-        }));
-        let available_fonts = crate::ui::font::enumerate_chinese_fonts();
-        let config = crate::config::Config::default();
+        let mut editor = Editor::default();
+        // Resolved once, so that `--data-dir` and portable mode reach every backend alike
+        let data_dir = config.data_dir();
+        let sidebar_backend = Arc::new(
+            SidebarBackend::with_data_dir(data_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!("Failed to initialize SidebarBackend: {}", e);
+                panic!("Cannot continue without SidebarBackend");
+            }),
+        );
+        let file_settings_backend = Arc::new(
+            FileSettingsBackend::with_data_dir(data_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!("Failed to initialize FileSettingsBackend: {}", e);
+                panic!("Cannot continue without FileSettingsBackend");
+            }),
+        );
+        let ai_panel_backend = Arc::new(
+            AiPanelBackend::with_data_dir(data_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!("Failed to initialize AiPanelBackend: {}", e);
+                panic!("Cannot continue without AiPanelBackend");
+            }),
+        );
+        let daily_log = Arc::new(
+            DailyLogBackend::with_data_dir(data_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!("Failed to initialize DailyLogBackend: {}", e);
+                panic!("Cannot continue without DailyLogBackend");
+            }),
+        );
+        let today_logged = logged_today(&daily_log);
+        let usage_log_backend = Arc::new(
+            UsageLogBackend::with_data_dir(data_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!("Failed to initialize UsageLogBackend: {}", e);
+                panic!("Cannot continue without UsageLogBackend");
+            }),
+        );
+        let usage_log = usage_log_backend.load().unwrap_or_else(|e| {
+            tracing::error!("Failed to load AI usage log: {}", e);
+            UsageLog::new()
+        });
+        // The full list comes from a background scan, see `start_font_scan`
+        let available_fonts = SystemFonts::preferred();
         let ai_backend = Arc::new(AiBackend::from_config(&config.settings.ai_panel));
+        editor
+            .get_ai_panel_mut()
+            .set_unavailable_reason(ai_backend.unavailable_reason());
+        editor
+            .get_ai_panel_mut()
+            .set_prompt_templates(config.settings.ai_panel.prompt_templates.clone());
+        editor.get_ai_panel_mut().is_visible = config.settings.ai_panel_layout.visible;
 
         let plugins_dir = config.data_dir().join("plugins");
         let plugin_manager =
             PluginManager::new(plugins_dir, config.settings.github_publish.clone());
         let plugin_metadata = plugin_manager.metadata();
+        let mut settings_window = SettingsWindow::new();
+        settings_window.set_available_fonts(available_fonts.chinese.clone());
+        let window_frame = WindowFrame::new(config.settings.native_decorations);
+
+        let editor_backend = Arc::new(
+            EditorBackend::with_data_dir(data_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!("Failed to initialize EditorBackend: {}", e);
+                panic!("Cannot continue without EditorBackend");
+            }),
+        );
+        let controller = AppController::new(
+            Arc::new(DiskStore::new(
+                Arc::clone(&editor_backend),
+                Arc::clone(&sidebar_backend),
+            )),
+            sender.clone(),
+            spawn_thread(),
+        );
 
         Self {
-            editor,
-            editor_backend: Arc::new(EditorBackend::default()),
+            doc: Document::new(editor),
+            tabs: Tabs::new(),
+            home_tab: None,
+            editor_backend,
+            controller,
             sidebar_backend,
+            file_settings_backend,
+            editor_settings_outdated: false,
             time_backend: TimeBackend::default(),
+            daily_log,
+            logged_time_ms: (0, 0),
+            last_daily_log_flush: Instant::now(),
+            today_logged,
             ai_backend,
-            next_ai_request_id: 1,
-            active_ai_request: None,
+            ai_requests: AiDispatcher::new(),
+            usage_log_backend,
+            usage_log,
+            ai_panel_backend,
             response_receiver: receiver,
             response_sender: sender,
+            buffer_mirror: BufferMirror::new(data_dir),
+            last_mirror_update: Instant::now(),
             history_window: HistoryWindow::new(),
             available_fonts,
-            current_font: "Default".to_string(),
+            font_previews: FontPreviews::new(),
+            fonts_loading: false,
+            current_fonts: LoadedFonts::default(),
+            font_weights: None,
             last_focus_state: false,
             config,
             plugin_manager,
@@ -94,128 +401,558 @@ impl Default for PaperShellApp {
             plugin_config_window: GithubPublishConfigWindow::new(),
             publish_dialog: PublishDialog::new(),
             print_dialog: PrintDialog::new(),
-            settings_window: SettingsWindow::new(),
+            settings_window,
+            time_debug_window: TimeDebugWindow::new(),
+            log_viewer: LogViewerWindow::new(),
+            stats_window: StatsWindow::new(),
+            all_marks_window: AllMarksWindow::new(),
+            frequent_words_window: FrequentWordsWindow::new(),
+            global_search_window: GlobalSearchWindow::new(),
+            global_search: None,
+            error_window: ErrorLogWindow::new(),
+            error_log: ErrorLog::new(),
+            ai_review_window: AiReviewWindow::new(),
+            ai_panel_layout_dirty: false,
+            window_fitted: false,
+            last_dialog_dir: None,
+            config_warning: None,
+            window_title: String::new(),
+            toasts: Toasts::new(),
+            busy: BusyTasks::new(),
+            window_frame,
+            focus_mode: None,
+            onboarding: Onboarding::Inactive,
+            close_confirmed: false,
+            file_watcher: None,
+            watched_files: Vec::new(),
+            pending_session: None,
         }
     }
-}
-
-impl PaperShellApp {
-    pub fn new(cc: &eframe::CreationContext<'_>, initial_file: Option<PathBuf>) -> Self {
-        configure_style(&cc.egui_ctx);
 
-        let mut app = Self::default();
-        if let Some(path) = initial_file {
-            app.open_file(path);
+    /// The app as started from `main`, `data_dir_arg` being the data dir
+    /// given with `--data-dir`
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        startup: Startup,
+        data_dir_arg: Option<PathBuf>,
+    ) -> Self {
+        let mut config = crate::config::Config::default();
+        config.set_data_dir_arg(data_dir_arg);
+        let mut app = Self::with_config(config);
+        let sender = app.response_sender.clone();
+        let ctx = cc.egui_ctx.clone();
+        app.config.start_writer(move |e| {
+            let _ = sender.send(ResponseMessage::Failed {
+                what: "设置保存失败",
+                error: e.into(),
+            });
+            ctx.request_repaint();
+        });
+        if let Some(reason) = app.config.read_only_reason() {
+            app.config_warning = Some(format!(
+                "设置文件来自更新的版本，本次修改不会保存（{}）",
+                reason
+            ));
+        }
+        app.apply_settings(&cc.egui_ctx);
+        app.start_font_scan(&cc.egui_ctx);
+        app.report_recovered_files();
+        let ctx = cc.egui_ctx.clone();
+        match FileWatcher::new(app.response_sender.clone(), move || ctx.request_repaint()) {
+            Ok(watcher) => app.file_watcher = Some(watcher),
+            Err(e) => {
+                app.log_error("无法监视文件的外部修改", &AppError::from(e));
+            }
+        }
+        if app.config.is_first_run() {
+            let defaults = OnboardingChoices {
+                data_dir: app.config.settings.data_dir.clone(),
+                font_family: app.config.settings.font_family.clone(),
+                theme: app.config.settings.theme.clone(),
+            };
+            app.onboarding = Onboarding::Active {
+                step: OnboardingStep::DataDir,
+                choices: defaults.clone(),
+                defaults,
+            };
+        }
+        // The first-run dialog does not hold up a file passed on the command line
+        match startup {
+            Startup::File(path) => app.open_file(path),
+            Startup::LastSession if app.config.settings.restore_session => {
+                app.pending_session = Some(app.config.settings.session.clone());
+            }
+            Startup::LastSession | Startup::Empty => {}
         }
 
         app
     }
 
-    fn spawn_new_window(&self) {
-        // Spawn a new instance of the application
-        if let Err(e) = std::process::Command::new(std::env::current_exe().unwrap()).spawn() {
-            tracing::error!("Failed to spawn new window: {}", e);
+    /// List the system's fonts off the UI thread; scanning can take a while
+    /// on systems with many fonts
+    fn start_font_scan(&mut self, ctx: &egui::Context) {
+        self.fonts_loading = true;
+        let sender = self.response_sender.clone();
+        let ctx = ctx.clone();
+        let data_dir = self.config.data_dir();
+        std::thread::spawn(move || {
+            let fonts = crate::backend::font_cache::cached_system_fonts(&data_dir);
+            let _ = sender.send(ResponseMessage::FontsEnumerated(fonts));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Find the weights `family` comes in off the UI thread; every face of
+    /// the family has to be loaded for that
+    fn start_font_weight_scan(&mut self, ctx: &egui::Context, family: Option<String>) {
+        self.font_weights = None;
+        let Some(family) = family else {
+            return;
+        };
+        let sender = self.response_sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let weights = crate::ui::font::family_weights(&family);
+            let _ = sender.send(ResponseMessage::FontWeightsFound { family, weights });
+            ctx.request_repaint();
+        });
+    }
+
+    /// Start another window of the app, opening `file` if given
+    fn spawn_new_window(&mut self, file: Option<&Path>) {
+        let mut command = match crate::cli::window_command(file, self.config.data_dir_arg()) {
+            Ok(command) => command,
+            Err(e) => {
+                self.report("无法打开新窗口", AppError::NewWindow(e));
+                return;
+            }
+        };
+        match self.config.settings.window.position {
+            Some([x, y]) => command.env(NEW_WINDOW_POSITION_ENV, format!("{x},{y}")),
+            None => command.env_remove(NEW_WINDOW_POSITION_ENV),
+        };
+        if let Err(e) = command.spawn() {
+            self.report("无法打开新窗口", AppError::NewWindow(e));
         }
     }
+
+    /// Tell the user that `summary` failed, and keep the error in the log
+    fn report(&mut self, summary: &str, error: AppError) {
+        let message = self.log_error(summary, &error);
+        self.toasts.error(message);
+    }
+
+    /// Tell the user the `what` file was unreadable and kept as `set_aside`,
+    /// so what was lost can still be looked up there
+    fn report_set_aside(&mut self, what: &str, set_aside: &Path) {
+        self.toasts.error(format!(
+            "{}文件已损坏，已另存为 {}",
+            what,
+            set_aside.display()
+        ));
+    }
+
+    /// Keep the error in the log without a toast, for errors shown elsewhere
+    fn log_error(&mut self, summary: &str, error: &AppError) -> String {
+        tracing::error!("{}: {:?}", summary, error);
+        self.error_log.push(summary, error)
+    }
 }
 
 // file related operations without UI
 impl PaperShellApp {
-    fn load_file_data(&self, path: &PathBuf) -> Result<LoadFileResult, String> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e: std::io::Error| format!("Failed to read file {:?}: {}", path, e))?;
-
-        let (uuid, total_time) = self
-            .editor_backend
-            .get_file_metadata(path, &content)
-            .map_err(|e| format!("Failed to get metadata: {}", e))?;
-
-        let marks = self
-            .sidebar_backend
-            .load_marks(&uuid)
-            .map_err(|e| format!("Failed to load marks: {}", e))?;
-
-        Ok((
-            FileData {
-                uuid,
-                path: path.to_path_buf(),
-                total_time,
-                content,
-            },
-            marks,
-        ))
+    /// Open `path` in a tab of its own, reading it in the background
+    fn try_load_file_data(&mut self, path: PathBuf) {
+        if self.switch_to_file(&path) {
+            return;
+        }
+        self.make_room_for_file();
+        self.busy.begin(BusyKind::Open, file_name(&path));
+        self.controller.open_file(self.tabs.active(), path);
     }
 
-    // this is mostly the same process with load_file_data but in a thread with messaging
-    fn try_load_file_data(&mut self, path: PathBuf) {
-        let backend = Arc::clone(&self.editor_backend);
-        let sidebar_backend = Arc::clone(&self.sidebar_backend);
-        let sender = self.response_sender.clone();
+    /// Carry out a title bar action the controller left to the app
+    fn handle_title_action(&mut self, ctx: &egui::Context, action: TitleBarAction) {
+        match action {
+            TitleBarAction::NewFile => {
+                self.open_tab();
+                self.apply_new_file_template();
+                tracing::info!("Started a new document");
+            }
+            TitleBarAction::CloseFile => {
+                if self.close_document() {
+                    tracing::info!("Closed the document");
+                }
+            }
+            TitleBarAction::NewWindow => self.spawn_new_window(None),
+            TitleBarAction::Save => self.try_save_file(),
+            TitleBarAction::Open => self.try_open_file_from_selector(),
+            TitleBarAction::OpenFile(path) => self.open_file(path),
+            TitleBarAction::RevealFile(path) => {
+                if let Err(e) = file_manager::reveal_in_file_manager(&path) {
+                    self.report("无法打开文件管理器", e.into());
+                }
+            }
+            TitleBarAction::History => self.try_load_history(),
+            TitleBarAction::GlobalSearch => self.global_search_window.open(),
+            TitleBarAction::Settings => {
+                let file = self
+                    .doc
+                    .editor
+                    .get_sidebar_uuid()
+                    .map(|_| (self.document_title(), self.doc.file_settings.clone()));
+                self.settings_window
+                    .open(&self.config, &self.usage_log, file);
+            }
+            TitleBarAction::PreviewFont(font_name) => self.font_previews.request(ctx, &font_name),
+            TitleBarAction::LoadFontFile => self.pick_font_file(),
+            TitleBarAction::ToggleAiPanel => self.handle_shortcut(ctx, Action::ToggleAi),
+            TitleBarAction::RunPlugin(id) if id == "github_publish" => {
+                if self.config.settings.github_publish.repo.trim().is_empty() {
+                    self.plugin_config_window
+                        .open(&self.config.settings.github_publish, true);
+                } else {
+                    self.publish_dialog
+                        .open(&self.config.settings.github_publish);
+                }
+            }
+            TitleBarAction::RunPlugin(id) => self.run_plugin(id),
+            TitleBarAction::ShowStats => self.try_load_daily_log(),
+            TitleBarAction::ShowErrors => self.error_window.open(),
+            TitleBarAction::ShowAllMarks => self.try_load_all_marks(),
+            TitleBarAction::Print => self.open_print_dialog(),
+            TitleBarAction::ShowFrequentWords => self.try_count_frequent_words(),
+            TitleBarAction::OpenPluginsFolder => {
+                self.open_plugins_folder();
+            }
+            TitleBarAction::ConfigurePlugin(id) => {
+                if id == "github_publish" {
+                    self.plugin_config_window
+                        .open(&self.config.settings.github_publish, false);
+                }
+            }
+            TitleBarAction::Format
+            | TitleBarAction::SearchReplace
+            | TitleBarAction::RemoveRecentFile(_)
+            | TitleBarAction::TogglePinnedFile(_)
+            | TitleBarAction::ClearRecentFiles
+            | TitleBarAction::FontChange(_)
+            | TitleBarAction::FontWeightChange(_)
+            | TitleBarAction::LatinFontChange(_) => {
+                // Taken by `AppController::handle_title_action`
+            }
+        }
+    }
 
-        std::thread::spawn(move || match std::fs::read_to_string(&path) {
-            Ok(content) => match backend.get_file_metadata(&path, &content) {
-                Ok((uuid, total_time)) => {
-                    let _ = sender.send(ResponseMessage::FileLoaded(Ok(FileData {
-                        path,
-                        content,
-                        uuid: uuid.clone(),
-                        total_time,
-                    })));
-
-                    let marks_result = sidebar_backend.load_marks(&uuid).map_err(|e| e.to_string());
-                    let _ = sender.send(ResponseMessage::MarksLoaded(marks_result));
-                }
-                Err(e) => {
-                    let _ = sender.send(ResponseMessage::FileLoaded(Err(format!(
-                        "Failed to get metadata: {}",
-                        e
-                    ))));
+    fn handle_shortcut(&mut self, ctx: &egui::Context, action: Action) {
+        match action {
+            Action::Save => self.try_save_file(),
+            Action::Open => self.try_open_file_from_selector(),
+            Action::NewWindow => self.spawn_new_window(None),
+            Action::Print => self.open_print_dialog(),
+            Action::Find => self.doc.editor.open_search_replace(),
+            Action::GlobalSearch => self.global_search_window.open(),
+            Action::Format => self.doc.editor.format(),
+            Action::History => self.try_load_history(),
+            Action::ToggleAi => {
+                let panel = self.doc.editor.get_ai_panel_mut();
+                panel.is_visible = !panel.is_visible;
+            }
+            Action::ToggleMark => self.doc.editor.toggle_mark_at_cursor(),
+            Action::FocusMode => {
+                if self.focus_mode.is_some() {
+                    self.exit_focus_mode(ctx);
+                } else {
+                    self.enter_focus_mode(ctx);
                 }
-            },
-            Err(e) => {
-                let _ = sender.send(ResponseMessage::FileLoaded(Err(format!(
-                    "Failed to read file {:?}: {}",
-                    path, e
-                ))));
             }
+            Action::NextTab => self.switch_tab(self.tabs.cycled(1)),
+            Action::PreviousTab => self.switch_tab(self.tabs.cycled(-1)),
+        }
+    }
+
+    fn enter_focus_mode(&mut self, ctx: &egui::Context) {
+        let panel = self.doc.editor.get_ai_panel_mut();
+        self.focus_mode = Some(FocusMode {
+            ai_panel_visible: panel.is_visible,
+            was_fullscreen: ctx.input(|i| i.viewport().fullscreen.unwrap_or(false)),
+            reached_fullscreen: false,
+            title_bar_revealed: false,
+            title_bar_bottom: 0.0,
         });
+        panel.is_visible = false;
+        self.doc.editor.set_marks_hidden(true);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
     }
 
-    fn try_load_history(&mut self) {
-        let current_file = self.editor.get_current_file().cloned();
-        if let Some(path) = current_file {
-            let backend = Arc::clone(&self.editor_backend);
-            let sender = self.response_sender.clone();
+    fn exit_focus_mode(&mut self, ctx: &egui::Context) {
+        let Some(focus) = self.focus_mode.take() else {
+            return;
+        };
+        self.doc.editor.get_ai_panel_mut().is_visible = focus.ai_panel_visible;
+        self.doc.editor.set_marks_hidden(false);
+        let is_fullscreen = ctx.input(|i| i.viewport().fullscreen.unwrap_or(false));
+        if is_fullscreen != focus.was_fullscreen {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(focus.was_fullscreen));
+        }
+    }
 
-            std::thread::spawn(move || {
-                let result = backend.load_history(&path).map_err(|e| e.to_string());
-                let _ = sender.send(ResponseMessage::HistoryLoaded(result));
-            });
+    /// Leave distraction-free mode on Escape, and show the title bar while
+    /// the pointer is at the top of the screen
+    fn update_focus_mode(&mut self, ctx: &egui::Context) {
+        let Some(focus) = &mut self.focus_mode else {
+            return;
+        };
+        let is_fullscreen = ctx.input(|i| i.viewport().fullscreen.unwrap_or(false));
+        let left_fullscreen = focus.reached_fullscreen && !is_fullscreen;
+        focus.reached_fullscreen |= is_fullscreen;
+        let menu_open = egui::Popup::is_any_open(ctx);
+        if left_fullscreen
+            || (!menu_open
+                && ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape)))
+        {
+            self.exit_focus_mode(ctx);
+            return;
+        }
 
+        let top = ctx.content_rect().top();
+        let pointer_y = ctx.input(|i| i.pointer.hover_pos()).map(|pos| pos.y - top);
+        if pointer_y.is_some_and(|y| y <= FOCUS_REVEAL_EDGE) {
+            focus.title_bar_revealed = true;
+        } else if focus.title_bar_revealed
+            && !menu_open
+            && pointer_y.is_none_or(|y| y > focus.title_bar_bottom.max(FOCUS_REVEAL_EDGE))
+        {
+            focus.title_bar_revealed = false;
+        }
+    }
+
+    fn try_load_history(&mut self) {
+        let current_file = self.doc.editor.get_current_file().cloned();
+        if let Some(path) = current_file {
+            self.busy.begin(BusyKind::History, file_name(&path));
+            self.controller.load_history(path);
             self.history_window.open();
         }
     }
 
+    /// Open the document of `link`, and the history window at its version
+    /// if it names one
+    fn open_link(&mut self, link: DeepLink) {
+        tracing::info!("Opening link to {:?}", link.path);
+        self.open_file(link.path.clone());
+        if let Some(hash) = link.hash
+            && self.doc.editor.get_current_file() == Some(&link.path)
+        {
+            self.history_window.select_version(hash);
+            self.try_load_history();
+        }
+    }
+
     fn open_file(&mut self, path: PathBuf) {
-        match self.load_file_data(&path) {
+        if self.switch_to_file(&path) {
+            return;
+        }
+        match self.controller.load(&path) {
             Ok((file_data, marks)) => {
-                self.apply_load_file_data(file_data, Some(marks));
+                self.make_room_for_file();
+                self.apply_load_file_data(file_data, Some(marks.value));
+                if let Some(set_aside) = marks.set_aside {
+                    self.report_set_aside("标记", &set_aside);
+                }
             }
-            Err(e) => {
-                tracing::error!("{}", e);
+            Err(e) => self.report("打开文件失败", e),
+        }
+    }
+
+    /// Ask what to do with unsaved changes before they would be lost.
+    ///
+    /// Returns whether to go ahead: the changes were saved, or the user
+    /// chose to discard them.
+    fn confirm_discard_changes(&mut self) -> bool {
+        if !self.doc.editor.is_dirty() || self.doc.editor.is_blank_untitled() {
+            return true;
+        }
+        let name = crate::ui::title_bar::document_label(
+            self.doc.editor.get_current_file().map(PathBuf::as_path),
+            false,
+        );
+        let choice = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title(crate::constant::DEFAULT_WINDOW_TITLE)
+            .set_description(format!("“{}”有未保存的修改，是否先保存？", name))
+            .set_buttons(rfd::MessageButtons::YesNoCancel)
+            .show();
+        match choice {
+            rfd::MessageDialogResult::Yes => {
+                self.save_file();
+                // Still dirty if saving failed or the Save As dialog was cancelled
+                !self.doc.editor.is_dirty()
             }
+            rfd::MessageDialogResult::No => true,
+            _ => false,
+        }
+    }
+
+    /// Ask about every document with unsaved changes before the window
+    /// closes, one after the other.
+    ///
+    /// Returns whether to close: each was saved or discarded.
+    fn confirm_close(&mut self) -> bool {
+        self.tab_ids().into_iter().all(|id| {
+            let mut go_ahead = true;
+            self.with_tab(id, |app| go_ahead = app.confirm_discard_changes());
+            go_ahead
+        })
+    }
+
+    /// Close the active tab, switching to its neighbour. The last tab is
+    /// left with an empty, untitled document instead; the next save goes
+    /// through the Save As dialog.
+    ///
+    /// Returns false if the user chose to keep the document open.
+    fn close_document(&mut self) -> bool {
+        if !self.confirm_discard_changes() {
+            return false;
+        }
+        // Marks changed this frame still belong to the old file
+        self.try_save_marks_if_changed();
+        if let Some(request_id) = self.doc.editor.get_ai_panel().active_request_id() {
+            self.ai_requests.cancel(request_id);
+            self.doc.editor.cancel_ai_request(request_id);
+        }
+        // Time spent on the old document is not carried over to the new one
+        self.time_backend.get_and_reset_writing_time();
+
+        let ai_panel_visible = self.doc.editor.get_ai_panel().is_visible;
+        if let Some(closed) = self.tabs.close_active(&mut self.doc) {
+            if let Some((request, _)) = closed.narrative_map_request {
+                request.cancel();
+            }
+            self.document_switched(ai_panel_visible);
+            return true;
+        }
+        self.doc.editor.get_ai_panel_mut().clear_conversation();
+        self.doc.editor.reset();
+        self.doc.productivity = ProductivityTracker::new();
+        self.doc.saves = SaveState::default();
+        self.doc.unsaved_writing_secs = 0;
+        self.doc.file_settings = FileSettings::default();
+        self.editor_settings_outdated = true;
+        self.try_load_narrative_map();
+        true
+    }
+
+    /// Open an empty, untitled document in a new tab
+    fn open_tab(&mut self) {
+        let ai_panel_visible = self.leave_document();
+        self.tabs
+            .open(&mut self.doc, Document::new(Editor::default()));
+        self.document_switched(ai_panel_visible);
+    }
+
+    fn switch_tab(&mut self, id: TabId) {
+        if id == self.tabs.active() || !self.tabs.contains(id) {
+            return;
+        }
+        let ai_panel_visible = self.leave_document();
+        self.tabs.activate(id, &mut self.doc);
+        self.document_switched(ai_panel_visible);
+    }
+
+    /// Wrap up the active document before another tab's takes its place.
+    ///
+    /// Returns whether the AI panel is shown, which stays the same across
+    /// tabs.
+    fn leave_document(&mut self) -> bool {
+        // Marks changed this frame still belong to the old file
+        self.try_save_marks_if_changed();
+        if self.last_focus_state {
+            self.last_focus_state = false;
+            self.time_backend.update_focus(false);
+            let words = self.doc.editor.get_word_count();
+            self.doc.productivity.on_focus_change(false, words);
+        }
+        self.doc.unsaved_writing_secs += self.time_backend.get_and_reset_writing_time();
+        self.doc.editor.get_ai_panel().is_visible
+    }
+
+    /// Bring the document that just became active in line with the window
+    fn document_switched(&mut self, ai_panel_visible: bool) {
+        self.doc.editor.get_ai_panel_mut().is_visible = ai_panel_visible;
+        self.doc.editor.set_marks_hidden(self.focus_mode.is_some());
+        self.sync_ai_panel();
+        // Each file may override the fonts and layout
+        self.editor_settings_outdated = true;
+    }
+
+    /// Make the tab that has `path` open the active one.
+    ///
+    /// Returns false if no tab has it open.
+    fn switch_to_file(&mut self, path: &Path) -> bool {
+        let is_open =
+            |doc: &Document| doc.editor.get_current_file().map(PathBuf::as_path) == Some(path);
+        if is_open(&self.doc) {
+            return true;
+        }
+        match self.tabs.find(is_open) {
+            Some(id) => {
+                self.switch_tab(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Open a new tab for a file about to be loaded, unless the active
+    /// document is empty and untitled and can take it
+    fn make_room_for_file(&mut self) {
+        // An untitled document is dirty unless it is empty or an untouched
+        // template
+        if self.doc.editor.get_current_file().is_some() || self.doc.editor.is_dirty() {
+            self.open_tab();
+        }
+    }
+
+    /// Fill the new, empty document with the new file template, if there is
+    /// one. The template alone counts as unchanged, so closing the document
+    /// right away asks nothing.
+    fn apply_new_file_template(&mut self) {
+        let template = &self.config.settings.new_file_template;
+        if template.is_empty() {
+            return;
+        }
+        let content = expand_placeholders(template, chrono::Local::now().naive_local());
+        let hash = content_hash(&content);
+        let end = content.chars().count();
+        self.doc.editor.set_content(content);
+        self.doc.editor.mark_saved(hash);
+        self.doc.editor.set_cursor(end);
+    }
+
+    fn tab_ids(&self) -> Vec<TabId> {
+        self.tabs.iter().map(|(id, _)| id).collect()
+    }
+
+    /// Focused seconds spent on the active document since it was last saved
+    fn take_writing_secs(&mut self) -> u64 {
+        let banked = std::mem::take(&mut self.doc.unsaved_writing_secs);
+        // The running count belongs to the tab the user is on
+        if self.home_tab.is_some() {
+            banked
+        } else {
+            banked + self.time_backend.get_and_reset_writing_time()
         }
     }
 
     fn try_open_file_from_selector(&self) {
-        let backend = Arc::clone(&self.editor_backend);
-        let data_dir = backend.data_dir().to_path_buf();
+        let dialog_dir = self.config.dialog_dir(self.last_dialog_dir.as_deref());
 
         // Keep a reference to the sender to use in the outer scope
         let sender = self.response_sender.clone();
         std::thread::spawn(move || {
             if let Some(path) = rfd::FileDialog::new()
-                .set_directory(&data_dir)
+                .set_directory(&dialog_dir)
                 .add_filter("Text", &["txt"])
                 .pick_file()
             {
@@ -224,269 +961,1407 @@ impl PaperShellApp {
         });
     }
 
+    fn pick_font_file(&self) {
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("字体", &crate::ui::font::FONT_FILE_EXTENSIONS)
+                .pick_file()
+            {
+                let _ = sender.send(ResponseMessage::FontFilePicked(path));
+            }
+        });
+    }
+
     fn try_save_marks_if_changed(&mut self) {
         // Check if marks have changed and save in background if needed
-        if self.editor.marks_changed()
-            && let Some(uuid) = self.editor.get_sidebar_uuid()
+        if self.doc.editor.marks_changed()
+            && let Some(uuid) = self.doc.editor.get_sidebar_uuid()
         {
-            let marks = self.editor.get_marks().clone();
+            let marks = self.doc.editor.get_marks().clone();
             let uuid = uuid.clone();
             let sidebar_backend = Arc::clone(&self.sidebar_backend);
+            let sender = self.response_sender.clone();
 
             // Reset the changed flag immediately to avoid duplicate saves
-            self.editor.reset_marks_changed();
+            self.doc.editor.reset_marks_changed();
 
             std::thread::spawn(move || {
                 if let Err(e) = sidebar_backend.save_marks(&uuid, &marks) {
-                    tracing::error!("Failed to save marks in background: {}", e);
+                    let _ = sender.send(ResponseMessage::Failed {
+                        what: "标记保存失败",
+                        error: e.into(),
+                    });
                 }
             });
         }
     }
 
     fn save_file(&mut self) {
-        let current_file = self.editor.get_current_file().cloned();
-        let content = self.editor.get_content();
-        if content.trim().is_empty() {
+        let current_file = self.doc.editor.get_current_file().cloned();
+        if self.doc.editor.is_blank_untitled() {
             return;
         }
-        let time_spent = self.time_backend.get_and_reset_writing_time();
+        let content = self.doc.editor.get_content();
+        let time_spent = self.take_writing_secs();
 
         if let Some(path) = current_file {
-            // First write the actual file content
-            if let Err(e) = std::fs::write(&path, &content) {
-                tracing::error!("Failed to write file: {}", e);
-                return;
-            }
-
-            // Then track with backend (CAS + history)
-            let result = self
-                .editor_backend
-                .save(&path, &content, time_spent)
-                .map_err(|e| e.to_string());
-            if let Ok((uuid, total_time)) = result.as_ref() {
-                self.apply_save_file(uuid.clone(), *total_time);
-            } else {
-                tracing::error!("Failed to save file: {}", result.err().unwrap());
+            match self
+                .controller
+                .write_and_record(&path, &content, time_spent)
+            {
+                Ok((uuid, total_time)) => {
+                    self.doc.editor.mark_saved(content_hash(&content));
+                    self.apply_save_file(uuid, total_time);
+                }
+                Err(e) => self.report("保存失败", e),
             }
         } else {
             // Show save dialog for new file
-            let data_dir = self.editor_backend.data_dir().to_path_buf();
+            let dialog_dir = self.config.dialog_dir(self.last_dialog_dir.as_deref());
             if let Some(path) = rfd::FileDialog::new()
-                .set_directory(&data_dir)
+                .set_directory(&dialog_dir)
                 .add_filter("Text", &["txt"])
                 .save_file()
             {
-                // First write the actual file content
-                if let Err(e) = std::fs::write(&path, &content) {
-                    tracing::error!("Failed to write file: {}", e);
-                    return;
+                match self
+                    .controller
+                    .write_and_record(&path, &content, time_spent)
+                {
+                    Ok((uuid, total_time)) => {
+                        self.apply_save_file(uuid.clone(), total_time);
+                        self.apply_load_file_data(
+                            FileData {
+                                uuid,
+                                path,
+                                total_time,
+                                content,
+                            },
+                            None,
+                        );
+                    }
+                    Err(e) => self.report("保存失败", e),
                 }
+            }
+        }
+    }
 
-                // Then track with backend (CAS + history)
-                let result = self
-                    .editor_backend
-                    .save(&path, &content, time_spent)
-                    .map_err(|e| e.to_string());
-
-                // Add to recent files on successful save
-                if let Ok((uuid, total_time)) = result.as_ref() {
-                    self.apply_save_file(uuid.clone(), *total_time);
-                    self.apply_load_file_data(
-                        FileData {
-                            uuid: uuid.clone(),
-                            path,
-                            total_time: *total_time,
-                            content,
-                        },
-                        None,
-                    );
-                } else {
-                    tracing::error!("Failed to save file: {}", result.err().unwrap());
+    /// Write the open document's mark changes right away, for when there is
+    /// no time left for a background save
+    fn save_marks_now(&mut self) {
+        if self.doc.editor.marks_changed()
+            && let Some(uuid) = self.doc.editor.get_sidebar_uuid()
+            && let Err(e) = self
+                .sidebar_backend
+                .save_marks(uuid, self.doc.editor.get_marks())
+        {
+            tracing::error!("Failed to save marks on exit: {}", e);
+        }
+    }
+
+    /// Keep the open document's changes and marks as the app quits, without
+    /// opening any dialog: a file is written in place, an untitled document
+    /// goes to the recovery folder.
+    fn keep_document_on_exit(&mut self) {
+        self.save_marks_now();
+        let content = self.doc.editor.get_content();
+        let path = self.doc.editor.get_current_file().cloned();
+        let action = exit_action(
+            path.is_some(),
+            self.doc.editor.is_dirty(),
+            content.trim().is_empty(),
+        );
+        match (action, path) {
+            (ExitAction::Save, Some(path)) => {
+                if let Err(e) = write_atomic(&path, &content) {
+                    tracing::error!("Failed to write {:?} on exit: {}", path, e);
+                    return;
+                }
+                let time_spent = self.take_writing_secs();
+                if let Err(e) = self.editor_backend.save(&path, &content, time_spent) {
+                    tracing::error!("Failed to record {:?} on exit: {}", path, e);
+                }
+            }
+            (ExitAction::Recover, _) => {
+                match RecoveryBackend::with_data_dir(self.config.data_dir())
+                    .and_then(|backend| backend.store(&content))
+                {
+                    Ok(path) => tracing::info!("Kept untitled document in {:?}", path),
+                    Err(e) => tracing::error!("Failed to keep untitled document: {}", e),
                 }
             }
+            _ => {}
         }
     }
 
-    fn try_save_file(&self) {
-        let current_file = self.editor.get_current_file().cloned();
-        let content = self.editor.get_content();
-        if content.trim().is_empty() {
+    fn try_save_file(&mut self) {
+        let current_file = self.doc.editor.get_current_file().cloned();
+        if self.doc.editor.is_blank_untitled() {
             return;
         }
-
-        let backend = Arc::clone(&self.editor_backend);
-        let sender = self.response_sender.clone();
-        let time_spent = self.time_backend.get_and_reset_writing_time();
+        let content = self.doc.editor.get_content();
+        let time_spent = self.take_writing_secs();
+        let tab = self.tabs.active();
 
         if let Some(path) = current_file {
-            // Save to existing file in background thread
-            std::thread::spawn(move || {
-                // First write the actual file content
-                if let Err(e) = std::fs::write(&path, &content) {
-                    let _ = sender.send(ResponseMessage::FileSaved(Err(format!(
-                        "Failed to write file: {}",
-                        e
-                    ))));
-                    return;
-                }
-
-                // Then track with backend (CAS + history)
-                let result = backend
-                    .save(&path, &content, time_spent)
-                    .map_err(|e| e.to_string());
-                let _ = sender.send(ResponseMessage::FileSaved(result));
-            });
+            self.busy.begin(BusyKind::Save, file_name(&path));
+            self.controller
+                .save_file(tab, &mut self.doc.saves, path, content, time_spent);
         } else {
-            // Show save dialog for new file
-            let data_dir = backend.data_dir().to_path_buf();
+            // Show save dialog for new file. Not shown as busy: the dialog
+            // may be cancelled without any reply.
+            self.doc.saves.saving_content_hash = Some(content_hash(&content));
+            let dialog_dir = self.config.dialog_dir(self.last_dialog_dir.as_deref());
+            let controller = self.controller.clone();
             std::thread::spawn(move || {
                 if let Some(path) = rfd::FileDialog::new()
-                    .set_directory(&data_dir)
+                    .set_directory(&dialog_dir)
                     .add_filter("Text", &["txt"])
                     .save_file()
                 {
-                    // First write the actual file content
-                    if let Err(e) = std::fs::write(&path, &content) {
-                        let _ = sender.send(ResponseMessage::FileSaved(Err(format!(
-                            "Failed to write file: {}",
-                            e
-                        ))));
-                        return;
-                    }
+                    controller.save_as(tab, path, &content, time_spent);
+                }
+            });
+        }
+    }
+
+    fn apply_save_file(&mut self, uuid: String, total_time: u64) {
+        let outcome = apply_saved(&mut self.doc.editor, uuid, total_time);
+        self.apply_outcome(outcome);
+    }
+
+    fn apply_load_file_data(&mut self, data: FileData, marks: Option<HashMap<usize, Mark>>) {
+        let outcome = apply_loaded(&mut self.doc.editor, &mut self.doc.saves, data, marks);
+        self.apply_outcome(outcome);
+    }
+
+    /// Show what a step of the document lifecycle came to
+    fn apply_outcome(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Opened { path, uuid } => {
+                if let Some(uuid) = uuid {
+                    self.load_file_settings(&uuid);
+                }
+                self.config.add_recent_file(path.clone());
+                self.last_dialog_dir = path.parent().map(Path::to_path_buf);
+                self.try_load_narrative_map();
+                tracing::info!("File opened: {:?}", path);
+            }
+            Outcome::Saved { uuid, uuid_changed } => {
+                if uuid_changed {
+                    self.load_file_settings(&uuid);
+                }
+                if let Some(path) = self.doc.editor.get_current_file() {
+                    tracing::info!("File saved path: {:?}", path);
+                    self.config.add_recent_file(path.clone());
+                }
+            }
+            Outcome::MarksApplied { set_aside } => {
+                if let Some(set_aside) = set_aside {
+                    self.report_set_aside("标记", &set_aside);
+                }
+            }
+            Outcome::History(entries) => {
+                if let Err(e) = self
+                    .history_window
+                    .set_history(entries, &self.editor_backend)
+                {
+                    tracing::info!("Failed to set history: {}", e);
+                }
+            }
+            Outcome::Failed { what, error } => self.report(what, error),
+        }
+    }
+
+    fn try_load_narrative_map(&mut self) {
+        if let Some((request, _)) = self.doc.narrative_map_request.take() {
+            request.cancel();
+        }
+        self.doc.editor.get_ai_panel_mut().set_narrative_map(None);
+
+        let Some(uuid) = self.doc.editor.get_sidebar_uuid().cloned() else {
+            return;
+        };
+        let backend = Arc::clone(&self.ai_panel_backend);
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let result = backend.load_narrative_map(&uuid).map_err(AppError::from);
+            let _ = sender.send(ResponseMessage::NarrativeMapLoaded { uuid, result });
+        });
+    }
+
+    /// Take over the overrides of the file with `uuid`, replacing those of
+    /// the file open before. The overrides are a small local file, so they
+    /// are read right away.
+    fn load_file_settings(&mut self, uuid: &str) {
+        self.doc.file_settings = match self.file_settings_backend.load(uuid) {
+            Ok(file_settings) => file_settings,
+            Err(e) => {
+                self.report("读取文件单独设置失败", e.into());
+                FileSettings::default()
+            }
+        };
+        self.editor_settings_outdated = true;
+    }
+
+    fn save_file_settings(&self) {
+        let Some(uuid) = self.doc.editor.get_sidebar_uuid().cloned() else {
+            return;
+        };
+        let backend = Arc::clone(&self.file_settings_backend);
+        let file_settings = self.doc.file_settings.clone();
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = backend.save(&uuid, &file_settings) {
+                let _ = sender.send(ResponseMessage::Failed {
+                    what: "文件单独设置保存失败",
+                    error: e.into(),
+                });
+            }
+        });
+    }
 
-                    // Then track with backend (CAS + history)
-                    let result = backend
-                        .save(&path, &content, time_spent)
-                        .map_err(|e| e.to_string());
-
-                    // Add to recent files on successful save
-                    if result.is_ok() {
-                        let _ = sender.send(ResponseMessage::FileSaved(result.inspect(|_res| {
-                            // Ensure the path is updated on Save As
-                            let _ = sender.send(ResponseMessage::FileLoaded(Ok({
-                                FileData {
-                                    uuid: "".to_string(),
-                                    path,
-                                    total_time: 0,
-                                    content: "".to_string(),
-                                }
-                            })));
-                        })));
-                    } else {
-                        let _ = sender.send(ResponseMessage::FileSaved(result));
+    /// Apply the settings that the open file can override, merged over the
+    /// global ones: fonts, editor layout and formatting.
+    fn apply_editor_settings(&mut self, ctx: &egui::Context) {
+        self.editor_settings_outdated = false;
+        let settings = self.doc.file_settings.apply_to(&self.config.settings);
+        // A font picked for the open file wins over the global font file
+        let mut wanted = LoadedFonts {
+            family: settings.font_family.clone(),
+            file: (self.doc.file_settings.font_family.is_none())
+                .then(|| settings.custom_font_path.clone())
+                .flatten(),
+            latin: settings.latin_font_family.clone(),
+            weight: settings.font_weight,
+            y_offsets: settings.font_y_offsets.clone(),
+        };
+        if wanted != self.current_fonts {
+            let from_file = wanted.file.as_deref().map(|path| {
+                crate::ui::font::apply_font_file(
+                    path,
+                    wanted.latin.as_deref(),
+                    wanted.weight,
+                    &wanted.y_offsets,
+                )
+            });
+            let fonts = match from_file {
+                Some(Ok(fonts)) => fonts,
+                result => {
+                    if let Some(Err(e)) = result {
+                        tracing::error!("Failed to load font file {:?}: {}", wanted.file, e);
+                        self.toasts
+                            .error(format!("字体文件不可用，已改用其他字体：{}", e));
+                        self.config.settings.custom_font_path = None;
+                        self.config.mark_dirty();
+                        wanted.file = None;
                     }
+                    crate::ui::font::apply_font_pair(
+                        wanted.latin.as_deref(),
+                        wanted.family.as_deref(),
+                        wanted.weight,
+                        &wanted.y_offsets,
+                    )
                 }
+            };
+            self.font_previews.set_fonts(ctx, fonts);
+            tracing::info!(
+                "Font changed to: {:?} (file {:?}) with Latin font {:?}, {:?}",
+                wanted.family,
+                wanted.file,
+                wanted.latin,
+                wanted.weight
+            );
+            if wanted.family != self.current_fonts.family {
+                self.start_font_weight_scan(ctx, wanted.family.clone());
+            }
+            self.current_fonts = wanted;
+        }
+        self.doc.editor.set_appearance(EditorAppearance {
+            font_size: settings.font_size(),
+            line_spacing: settings.line_spacing(),
+            line_width: settings.line_width,
+        });
+        self.doc.editor.set_format_indent(settings.format_indent);
+        self.history_window.set_font_size(settings.font_size());
+        self.ai_review_window.set_font_size(settings.font_size());
+    }
+
+    /// Take over settings edited in the Settings window or in the settings
+    /// file and apply them.
+    fn apply_settings_edits(&mut self, ctx: &egui::Context, edited: Settings) {
+        self.config.settings.apply_edits(edited);
+        self.sync_api_key();
+        self.apply_settings(ctx);
+    }
+
+    /// Store the API key where the settings say, warning if the keychain
+    /// could not be used
+    fn sync_api_key(&mut self) {
+        if let Err(e) = self.config.sync_api_key() {
+            tracing::warn!("API key kept in the settings file: {}", e);
+            self.config_warning = Some(format!(
+                "无法使用系统钥匙串，API Key 仍保存在设置文件中：{}",
+                e
+            ));
+        }
+    }
+
+    /// Pick up edits made to the settings file outside the app.
+    ///
+    /// Unsaved changes in the Settings window win: the file is not applied
+    /// and is overwritten once the window saves.
+    fn reload_settings_if_changed(&mut self, ctx: &egui::Context) {
+        let Some(result) = self.config.reload_if_changed() else {
+            return;
+        };
+        match result {
+            Ok(_) if self.settings_window.has_unsaved_edits() => {
+                tracing::warn!("Settings file changed while the Settings window has edits");
+                self.config_warning = Some(
+                    "设置文件已在外部修改；设置窗口中有未保存的修改，保存时将以窗口为准"
+                        .to_string(),
+                );
+            }
+            Ok(settings) => {
+                // The window placement and panel layout belong to the running app
+                self.config.settings.recent_files = settings.recent_files.clone();
+                self.config.settings.pinned_files = settings.pinned_files.clone();
+                self.config.settings.writing_goals = settings.writing_goals;
+                self.apply_settings_edits(ctx, settings);
+                self.toasts.info("配置已重新加载");
+            }
+            Err(e) => {
+                tracing::error!("Failed to reload settings: {}", e);
+                self.config_warning = Some(format!("设置文件已在外部修改，但无法读取：{}", e));
+            }
+        }
+    }
+
+    fn show_onboarding(&mut self, ctx: &egui::Context) {
+        let Onboarding::Active { step, choices, .. } = &mut self.onboarding else {
+            return;
+        };
+        let before = choices.clone();
+        let action = crate::ui::onboarding::show(
+            ctx,
+            *step,
+            choices,
+            &OnboardingContext {
+                fonts: &self.available_fonts.chinese,
+                default_data_dir: &crate::paths::storage().data_dir,
+                portable: crate::paths::storage().portable,
+            },
+        );
+        // Font and theme are previewed right away
+        if *choices != before {
+            let choices = choices.clone();
+            self.preview_onboarding_choices(ctx, &choices);
+        }
+
+        let Onboarding::Active {
+            step,
+            choices,
+            defaults,
+        } = &mut self.onboarding
+        else {
+            return;
+        };
+        match action {
+            None => {}
+            Some(OnboardingAction::Back) => *step = step.previous().unwrap_or(*step),
+            Some(OnboardingAction::Next) => *step = step.next().unwrap_or(*step),
+            Some(OnboardingAction::Finish) => {
+                let choices = choices.clone();
+                self.onboarding = Onboarding::Inactive;
+                self.finish_onboarding(ctx, choices);
+            }
+            Some(OnboardingAction::Skip) => {
+                let defaults = defaults.clone();
+                self.onboarding = Onboarding::Inactive;
+                self.preview_onboarding_choices(ctx, &defaults);
+                // Saved so the flow is not offered again
+                self.config.mark_dirty();
+                tracing::info!("First-run setup skipped");
+            }
+        }
+    }
+
+    fn preview_onboarding_choices(&mut self, ctx: &egui::Context, choices: &OnboardingChoices) {
+        self.config.settings.theme = choices.theme.clone();
+        self.config.settings.font_family = choices.font_family.clone();
+        configure_style(ctx, &self.config.settings.theme);
+        self.apply_editor_settings(ctx);
+    }
+
+    fn finish_onboarding(&mut self, ctx: &egui::Context, choices: OnboardingChoices) {
+        self.preview_onboarding_choices(ctx, &choices);
+        if choices.data_dir != self.config.settings.data_dir {
+            let previous =
+                std::mem::replace(&mut self.config.settings.data_dir, choices.data_dir.clone());
+            if let Err(e) = self.reopen_storage() {
+                tracing::error!("Failed to use data dir {:?}: {}", choices.data_dir, e);
+                self.config.settings.data_dir = previous;
+                self.config_warning = Some(format!("无法使用所选的数据文件夹：{}", e));
+            }
+        }
+        self.config.mark_dirty();
+        tracing::info!("First-run setup finished");
+    }
+
+    /// Open every backend again at the data dir the settings now point to.
+    ///
+    /// Nothing is replaced unless all of them open.
+    fn reopen_storage(&mut self) -> Result<(), AppError> {
+        let data_dir = self.config.data_dir();
+        let editor_backend = EditorBackend::with_data_dir(data_dir.clone())?;
+        let sidebar_backend = SidebarBackend::with_data_dir(data_dir.clone())?;
+        let file_settings_backend = FileSettingsBackend::with_data_dir(data_dir.clone())?;
+        let ai_panel_backend = AiPanelBackend::with_data_dir(data_dir.clone())?;
+        let daily_log = DailyLogBackend::with_data_dir(data_dir.clone())?;
+        let usage_log_backend = UsageLogBackend::with_data_dir(data_dir.clone())?;
+
+        self.flush_daily_log();
+        self.editor_backend = Arc::new(editor_backend);
+        self.sidebar_backend = Arc::new(sidebar_backend);
+        self.controller.set_store(Arc::new(DiskStore::new(
+            Arc::clone(&self.editor_backend),
+            Arc::clone(&self.sidebar_backend),
+        )));
+        self.file_settings_backend = Arc::new(file_settings_backend);
+        self.ai_panel_backend = Arc::new(ai_panel_backend);
+        self.today_logged = logged_today(&daily_log);
+        self.daily_log = Arc::new(daily_log);
+        self.usage_log = usage_log_backend.load().unwrap_or_default();
+        self.usage_log_backend = Arc::new(usage_log_backend);
+        self.refresh_usage_summary();
+        self.buffer_mirror.set_data_dir(data_dir.clone());
+
+        let plugins_dir = data_dir.join("plugins");
+        self.plugin_manager = crate::plugin::PluginManager::new(
+            plugins_dir,
+            self.config.settings.github_publish.clone(),
+        );
+        self.plugin_metadata = self.plugin_manager.metadata();
+        tracing::info!("Data dir is now {:?}", data_dir);
+        Ok(())
+    }
+
+    /// Apply settings that take effect while running: look, editor layout
+    /// and the AI connection.
+    fn apply_settings(&mut self, ctx: &egui::Context) {
+        configure_style(ctx, &self.config.settings.theme);
+        self.apply_editor_settings(ctx);
+        let settings = &self.config.settings;
+        self.history_window
+            .set_datetime_format(&settings.datetime_format);
+        crate::logging::set_level(settings.log_level);
+
+        self.ai_backend = Arc::new(AiBackend::from_config(&settings.ai_panel));
+        self.sync_ai_panel();
+    }
+
+    /// Tell the active document's AI panel about the AI connection, prompt
+    /// templates and today's usage
+    fn sync_ai_panel(&mut self) {
+        let panel = self.doc.editor.get_ai_panel_mut();
+        panel.set_unavailable_reason(self.ai_backend.unavailable_reason());
+        panel.set_prompt_templates(self.config.settings.ai_panel.prompt_templates.clone());
+        self.refresh_usage_summary();
+    }
+
+    /// Save the open files in the background if the auto-save interval has
+    /// passed and their content changed since the last auto-save.
+    fn autosave_if_due(&mut self) {
+        let interval = self.config.settings.autosave_interval;
+        if !self.controller.tick(Instant::now(), interval) {
+            return;
+        }
+        for id in self.tab_ids() {
+            self.with_tab(id, Self::autosave_document);
+        }
+    }
+
+    /// Reopen the files of the last session, skipping those that are gone
+    fn restore_session(&mut self) {
+        let Some(session) = self.pending_session.take() else {
+            return;
+        };
+        let mut missing = Vec::new();
+        let mut active = None;
+        for (index, tab) in session.tabs.into_iter().enumerate() {
+            if !tab.path.is_file() {
+                missing.push(file_name(&tab.path));
+                continue;
+            }
+            self.open_file(tab.path.clone());
+            // Failed to open; the error was reported
+            if self.doc.editor.get_current_file() != Some(&tab.path) {
+                continue;
+            }
+            if let Some(cursor) = tab.cursor {
+                self.doc.editor.set_cursor(cursor);
+            }
+            self.doc.pending_scroll = Some(tab.scroll);
+            if index == session.active {
+                active = Some(tab.path);
+            }
+        }
+        if let Some(path) = active {
+            self.switch_to_file(&path);
+        }
+        if !missing.is_empty() {
+            self.toasts
+                .info(format!("上次打开的文件已不存在：{}", missing.join("、")));
+        }
+    }
+
+    /// The files open now, to restore on the next start
+    fn current_session(&self) -> Session {
+        let active = self.tabs.active();
+        let mut session = Session::default();
+        for (id, doc) in self.tabs.iter() {
+            let doc = doc.unwrap_or(&self.doc);
+            let Some(path) = doc.editor.get_current_file() else {
+                continue;
+            };
+            if id == active {
+                session.active = session.tabs.len();
+            }
+            session.tabs.push(SessionTab {
+                path: path.clone(),
+                cursor: doc.editor.cursor(),
+                scroll: doc.scroll_offset,
             });
         }
+        session
+    }
+
+    /// Point the file watcher at the files open in the tabs, when they changed
+    fn update_watched_files(&mut self) {
+        let Some(watcher) = &mut self.file_watcher else {
+            return;
+        };
+        let files: Vec<PathBuf> = self
+            .tabs
+            .iter()
+            .filter_map(|(_, doc)| doc.unwrap_or(&self.doc).editor.get_current_file())
+            .cloned()
+            .collect();
+        if files != self.watched_files {
+            watcher.watch_files(&files);
+            self.watched_files = files;
+        }
+    }
+
+    /// Take in what another program did to the file of the active document
+    fn apply_external_change(&mut self, path: &Path, change: FileChange) {
+        match change {
+            FileChange::Modified => self.check_external_edit(path),
+            FileChange::Removed => {
+                self.config.remove_recent_file(path);
+                self.doc.external_change = Some(FileChange::Removed);
+            }
+            FileChange::Renamed(to) => {
+                self.config.rename_recent_file(path, &to);
+                self.doc.editor.set_current_file(Some(to.clone()));
+                self.doc.external_change = None;
+                self.toasts
+                    .info(format!("“{}”已移动为“{}”", file_name(path), file_name(&to)));
+            }
+        }
+    }
+
+    /// Compare the file on disk with the document after a change was seen;
+    /// the app's own saves are seen too and change nothing
+    fn check_external_edit(&mut self, path: &Path) {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            // Moved away or deleted by the time it was read
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.apply_external_change(path, FileChange::Removed);
+                return;
+            }
+            Err(source) => {
+                let error = AppError::Read {
+                    path: path.to_path_buf(),
+                    source,
+                };
+                self.report("读取外部修改失败", error);
+                return;
+            }
+        };
+        let hash = content_hash(&content);
+        if content_hash(&self.doc.editor.get_content()) == hash {
+            self.doc.editor.mark_saved(hash);
+            self.doc.external_change = None;
+            return;
+        }
+        if self.doc.editor.saved_content_hash() == Some(hash)
+            || self.doc.saves.saving_content_hash == Some(hash)
+        {
+            return;
+        }
+        if self.config.settings.auto_reload_external_changes && !self.doc.editor.is_dirty() {
+            self.reload_document(content);
+            self.toasts
+                .info(format!("已载入“{}”在外部的修改", file_name(path)));
+        } else {
+            self.doc.external_change = Some(FileChange::Modified);
+        }
+    }
+
+    /// Replace the active document's content with `content`, as now on disk
+    fn reload_document(&mut self, content: String) {
+        let hash = content_hash(&content);
+        self.doc.editor.set_content(content);
+        self.doc.editor.mark_saved(hash);
+        self.doc.saves.autosaved_content_hash = hash;
+        self.doc.external_change = None;
+    }
+
+    /// Load the active document from disk again, dropping what is unsaved
+    fn reload_from_disk(&mut self) {
+        let Some(path) = self.doc.editor.get_current_file().cloned() else {
+            return;
+        };
+        match read_document(&path) {
+            Ok(content) => self.reload_document(content),
+            Err(e) => self.report("重新载入失败", e),
+        }
+    }
+
+    /// Keep the panic hook's copy of every tab with unsaved changes
+    /// current, at most once per [`MIRROR_INTERVAL`]
+    fn update_buffer_mirror(&mut self) {
+        if self.last_mirror_update.elapsed() < MIRROR_INTERVAL {
+            return;
+        }
+        self.last_mirror_update = Instant::now();
+        let mut contents = Vec::new();
+        for (_, doc) in self.tabs.iter_mut() {
+            let doc = doc.unwrap_or(&mut self.doc);
+            if doc.editor.is_dirty() {
+                contents.push(doc.editor.get_content());
+            }
+        }
+        self.buffer_mirror.set(contents);
+    }
+
+    /// Tell the user about text the panic hook kept when the app last crashed
+    fn report_recovered_files(&mut self) {
+        match RecoveryBackend::with_data_dir(self.config.data_dir())
+            .and_then(|backend| backend.take_panic_files())
+        {
+            Ok(files) => {
+                if let [file] = files.as_slice() {
+                    self.toasts.info(format!(
+                        "纸壳上次意外退出，未保存的内容已保存到 {}",
+                        file.display()
+                    ));
+                } else if let Some(dir) = files.last().and_then(|file| file.parent()) {
+                    self.toasts.info(format!(
+                        "纸壳上次意外退出，{} 篇未保存的内容已保存到 {}",
+                        files.len(),
+                        dir.display()
+                    ));
+                }
+            }
+            Err(e) => tracing::warn!("Failed to look for recovered files: {}", e),
+        }
+    }
+
+    fn autosave_document(&mut self) {
+        if take_autosave(&self.doc.editor, &mut self.doc.saves) {
+            tracing::info!("Auto-saving current file");
+            self.try_save_file();
+        }
+    }
+
+    /// Keep the window geometry in the settings current so it is saved on exit.
+    ///
+    /// On the first frame the monitor is known, the restored geometry is
+    /// fitted to it instead.
+    fn track_window_geometry(&mut self, ctx: &egui::Context) {
+        let (inner, outer, monitor, maximized, minimized, fullscreen) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.inner_rect,
+                viewport.outer_rect,
+                viewport.monitor_size,
+                viewport.maximized.unwrap_or(false),
+                viewport.minimized.unwrap_or(false),
+                viewport.fullscreen.unwrap_or(false),
+            )
+        });
+        let window = &mut self.config.settings.window;
+
+        if !self.window_fitted {
+            let Some(monitor) = monitor else {
+                return;
+            };
+            self.window_fitted = true;
+            let fitted = window.fit_to_monitor([monitor.x, monitor.y]);
+            if fitted.size != window.size {
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(fitted.size.into()));
+            }
+            if fitted.position != window.position
+                && let Some(position) = fitted.position
+            {
+                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(position.into()));
+            }
+            *window = fitted;
+            return;
+        }
+
+        if minimized || fullscreen {
+            return;
+        }
+        window.maximized = maximized;
+        // Keep the size to go back to when the window is un-maximized
+        if !maximized {
+            if let Some(inner) = inner {
+                window.size = inner.size().into();
+            }
+            if let Some(outer) = outer {
+                window.position = Some(outer.min.into());
+            }
+        }
+    }
+
+    fn document_title(&self) -> String {
+        self.doc
+            .editor
+            .get_current_file()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("未命名文档")
+            .to_string()
+    }
+
+    /// Name the OS window after the open document, so it can be told apart
+    /// in the task switcher
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let is_dirty = self.doc.editor.is_dirty();
+        let mut document = crate::ui::title_bar::document_label(
+            self.doc.editor.get_current_file().map(PathBuf::as_path),
+            is_dirty,
+        );
+        if self.doc.external_change == Some(FileChange::Removed) {
+            document.push_str("（已删除）");
+        }
+        let suffix = &self.config.settings.window_title_suffix;
+        let title = if suffix.is_empty() {
+            document
+        } else {
+            format!("{} - {}", document, suffix)
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    fn update_time_backend_if_focus_changed(&mut self) {
+        let is_focused = self.doc.editor.is_focused();
+        if is_focused != self.last_focus_state {
+            self.time_backend.update_focus(is_focused);
+            self.doc
+                .productivity
+                .on_focus_change(is_focused, self.doc.editor.get_word_count());
+            self.last_focus_state = is_focused;
+        } else if is_focused {
+            self.doc
+                .productivity
+                .maybe_sample(self.doc.editor.get_word_count());
+        }
+        if self.doc.editor.take_typing_activity() {
+            self.time_backend.record_typing();
+        }
+    }
+
+    /// Whole seconds of session focus/typing time not yet in the daily log
+    fn take_unlogged_time(&mut self) -> (u64, u64) {
+        let focused_secs = (self.time_backend.session_writing_ms() - self.logged_time_ms.0) / 1000;
+        let typing_secs = (self.time_backend.session_typing_ms() - self.logged_time_ms.1) / 1000;
+        self.logged_time_ms.0 += focused_secs * 1000;
+        self.logged_time_ms.1 += typing_secs * 1000;
+
+        let today = chrono::Local::now().date_naive();
+        if self.today_logged.0 != today {
+            self.today_logged = (today, 0);
+        }
+        self.today_logged.1 += focused_secs;
+        (focused_secs, typing_secs)
+    }
+
+    /// Give back time from `take_unlogged_time` that did not reach the log,
+    /// so the next flush writes it
+    fn return_unlogged_time(&mut self, focused_secs: u64, typing_secs: u64) {
+        self.logged_time_ms.0 = self.logged_time_ms.0.saturating_sub(focused_secs * 1000);
+        self.logged_time_ms.1 = self.logged_time_ms.1.saturating_sub(typing_secs * 1000);
+        self.today_logged.1 = self.today_logged.1.saturating_sub(focused_secs);
+    }
+
+    fn record_ai_usage(&mut self, model: &str, usage: TokenUsage) {
+        let today = chrono::Local::now().date_naive();
+        record_usage(&mut self.usage_log, today, model, usage);
+        self.refresh_usage_summary();
+
+        let backend = Arc::clone(&self.usage_log_backend);
+        let model = model.to_string();
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = backend.add(today, &model, usage) {
+                let _ = sender.send(ResponseMessage::Failed {
+                    what: "AI 用量记录失败",
+                    error: e.into(),
+                });
+            }
+        });
+    }
+
+    /// Update the AI panel's "今日" token line
+    fn refresh_usage_summary(&mut self) {
+        let today = chrono::Local::now().date_naive();
+        let summary = self.usage_log.get(&today).map(|models| {
+            let mut summary = format!("今日: {} tokens", format_tokens(day_total(models).total()));
+            if let Some(cost) = estimate_cost(models, &self.config.settings.ai_panel.model_prices) {
+                summary.push_str(&format!(" · 约 {:.2}", cost));
+            }
+            summary
+        });
+        self.doc
+            .editor
+            .get_ai_panel_mut()
+            .set_usage_summary(summary);
+    }
+
+    fn try_flush_daily_log(&mut self) {
+        if self.last_daily_log_flush.elapsed() < DAILY_LOG_FLUSH_INTERVAL {
+            return;
+        }
+        self.last_daily_log_flush = Instant::now();
+
+        let (focused_secs, typing_secs) = self.take_unlogged_time();
+        if focused_secs == 0 && typing_secs == 0 {
+            return;
+        }
+        let daily_log = self.daily_log.clone();
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let today = chrono::Local::now().date_naive();
+            if let Err(e) = daily_log.add(today, focused_secs, typing_secs) {
+                let _ = sender.send(ResponseMessage::DailyLogNotWritten {
+                    focused_secs,
+                    typing_secs,
+                    error: e.into(),
+                });
+            }
+        });
+    }
+
+    fn flush_daily_log(&mut self) {
+        let (focused_secs, typing_secs) = self.take_unlogged_time();
+        if focused_secs == 0 && typing_secs == 0 {
+            return;
+        }
+        let today = chrono::Local::now().date_naive();
+        if let Err(e) = self.daily_log.add(today, focused_secs, typing_secs) {
+            tracing::error!("Failed to update daily log: {}", e);
+            self.return_unlogged_time(focused_secs, typing_secs);
+        }
+    }
+
+    /// Focused seconds written today, including this session
+    fn today_writing_secs(&self) -> u64 {
+        let (day, logged_secs) = self.today_logged;
+        let logged_secs = if day == chrono::Local::now().date_naive() {
+            logged_secs
+        } else {
+            0
+        };
+        logged_secs + (self.time_backend.session_writing_ms() - self.logged_time_ms.0) / 1000
+    }
+
+    fn daily_goal_progress(&self) -> Option<f32> {
+        let goal_secs = self.config.settings.writing_goals.daily_minutes * 60;
+        if goal_secs == 0 {
+            return None;
+        }
+        Some((self.today_writing_secs() as f32 / goal_secs as f32).min(1.0))
+    }
+
+    fn try_load_daily_log(&mut self) {
+        self.stats_window.open(self.config.settings.writing_goals);
+        let (focused_secs, typing_secs) = self.take_unlogged_time();
+        let daily_log = self.daily_log.clone();
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let today = chrono::Local::now().date_naive();
+            if (focused_secs > 0 || typing_secs > 0)
+                && let Err(e) = daily_log.add(today, focused_secs, typing_secs)
+            {
+                let _ = sender.send(ResponseMessage::DailyLogNotWritten {
+                    focused_secs,
+                    typing_secs,
+                    error: e.into(),
+                });
+            }
+            let result = daily_log.load().map_err(AppError::from);
+            let _ = sender.send(ResponseMessage::DailyLogLoaded(result));
+        });
+    }
+
+    fn try_load_all_marks(&mut self) {
+        self.all_marks_window.open();
+        // The open document's marks are written first, so the list has them
+        if self.doc.editor.marks_changed()
+            && let Some(uuid) = self.doc.editor.get_sidebar_uuid().cloned()
+        {
+            match self
+                .sidebar_backend
+                .save_marks(&uuid, self.doc.editor.get_marks())
+            {
+                Ok(()) => self.doc.editor.reset_marks_changed(),
+                Err(e) => self.report("标记保存失败", e.into()),
+            }
+        }
+        let sidebar_backend = Arc::clone(&self.sidebar_backend);
+        let editor_backend = Arc::clone(&self.editor_backend);
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let result = marked_files(&sidebar_backend, &editor_backend);
+            let _ = sender.send(ResponseMessage::AllMarksLoaded(result));
+        });
+    }
+
+    /// Search the saved versions of every document for `query`, stopping
+    /// the search before it
+    fn start_global_search(&mut self, query: String) {
+        if let Some((_, cancel)) = self.global_search.take() {
+            cancel.cancel();
+        }
+        let search = self.global_search_window.start();
+        let cancel = SearchCancel::new();
+        self.global_search = Some((search, cancel.clone()));
+        let editor_backend = Arc::clone(&self.editor_backend);
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let result = global_search::search_all(
+                &editor_backend,
+                &query,
+                GLOBAL_SEARCH_WORKERS,
+                &cancel,
+                |hits| {
+                    let _ = sender.send(ResponseMessage::GlobalSearchHits { search, hits });
+                },
+            )
+            .map_err(AppError::from);
+            let _ = sender.send(ResponseMessage::GlobalSearchFinished { search, result });
+        });
+    }
+
+    fn handle_global_search_action(&mut self, action: GlobalSearchAction) {
+        match action {
+            GlobalSearchAction::Search(query) => self.start_global_search(query),
+            GlobalSearchAction::Cancel => {
+                if let Some((search, cancel)) = self.global_search.take() {
+                    cancel.cancel();
+                    tracing::info!("Stopped global search {}", search);
+                }
+            }
+            GlobalSearchAction::Open { path, line } => {
+                self.open_file(path.clone());
+                if self.doc.editor.get_current_file() == Some(&path)
+                    && !self.doc.editor.reveal_line(line)
+                {
+                    self.toasts.info(format!("文档已没有第 {} 行", line + 1));
+                }
+            }
+        }
+    }
+
+    /// Count the words of the active document for the 高频词 window
+    fn try_count_frequent_words(&mut self) {
+        let tab = self.tabs.active();
+        let document_name = crate::ui::title_bar::document_label(
+            self.doc.editor.get_current_file().map(PathBuf::as_path),
+            false,
+        );
+        self.frequent_words_window.open(tab, document_name);
+        let content = self.doc.editor.get_content();
+        if content.len() <= FREQUENT_WORDS_INLINE_LIMIT {
+            let words = crate::word_frequency::frequent_words(&content, FREQUENT_WORDS_SHOWN);
+            self.frequent_words_window.set_words(tab, words);
+            return;
+        }
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let words = crate::word_frequency::frequent_words(&content, FREQUENT_WORDS_SHOWN);
+            let _ = sender.send(ResponseMessage::FrequentWordsCounted { tab, words });
+        });
+    }
+
+    fn handle_frequent_words_action(&mut self, action: FrequentWordsAction) {
+        match action {
+            FrequentWordsAction::Find { tab, word } => {
+                self.switch_tab(tab);
+                if self.tabs.active() == tab
+                    && self.doc.editor.find_all(&word.text, word.latin) == 0
+                {
+                    self.toasts.info(format!("文中已没有“{}”", word.text));
+                }
+            }
+            FrequentWordsAction::Refresh => self.try_count_frequent_words(),
+        }
+    }
+
+    fn handle_all_marks_action(&mut self, action: AllMarksAction) {
+        match action {
+            AllMarksAction::Open { path, line } => {
+                self.open_file(path.clone());
+                if self.doc.editor.get_current_file() == Some(&path)
+                    && !self.doc.editor.reveal_line(line)
+                {
+                    self.toasts.info(format!("文档已没有第 {} 行", line + 1));
+                }
+            }
+            AllMarksAction::DeleteOrphans(uuids) => {
+                for uuid in &uuids {
+                    if let Err(e) = self.sidebar_backend.delete_marks(uuid) {
+                        self.report("删除批注失败", e.into());
+                        break;
+                    }
+                }
+                tracing::info!("Deleted {} orphaned marks files", uuids.len());
+                self.try_load_all_marks();
+            }
+        }
+    }
+
+    /// Quietly tell the user how far they got if today's goal was missed
+    /// Tell the user how far they got when the daily goal is not reached,
+    /// at most once a day. Only asked on a close the user started, never
+    /// from `on_exit`.
+    fn show_goal_summary_if_unmet(&mut self) {
+        let goal_minutes = self.config.settings.writing_goals.daily_minutes;
+        let today_minutes = self.today_writing_secs() / 60;
+        let today = chrono::Local::now().date_naive();
+        if goal_minutes == 0
+            || today_minutes >= goal_minutes
+            || self.config.settings.goal_summary_shown_on == Some(today)
+        {
+            return;
+        }
+        self.config.settings.goal_summary_shown_on = Some(today);
+        self.config.mark_dirty();
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Info)
+            .set_title(crate::constant::DEFAULT_WINDOW_TITLE)
+            .set_description(format!(
+                "今天写作 {} 分钟 / 目标 {} 分钟",
+                today_minutes, goal_minutes
+            ))
+            .set_buttons(rfd::MessageButtons::Ok)
+            .show();
     }
 
-    fn apply_save_file(&mut self, uuid: String, total_time: u64) {
-        self.editor.set_uuid(uuid);
-        self.editor.set_current_file_total_time(total_time);
-        if let Some(path) = self.editor.get_current_file() {
-            tracing::info!("File saved path: {:?}", path);
-            self.config.add_recent_file(path.clone());
+    /// Apply the replies of background work that arrived since the last
+    /// frame, so e.g. a file's marks show in the same frame as its text.
+    ///
+    /// Returns true if more replies are waiting than one frame takes.
+    fn check_response_messages(&mut self) -> bool {
+        let responses = drain_messages(&self.response_receiver, MAX_MESSAGES_PER_FRAME);
+        let more_waiting = responses.len() == MAX_MESSAGES_PER_FRAME;
+        for response in responses {
+            self.dispatch_response(response);
         }
+        more_waiting
     }
 
-    fn apply_load_file_data(&mut self, data: FileData, marks: Option<HashMap<usize, Mark>>) {
-        if !data.content.is_empty() {
-            self.editor.set_content(data.content);
+    fn dispatch_response(&mut self, response: ResponseMessage) {
+        match &response {
+            ResponseMessage::FileLoaded { .. } => self.busy.finish(BusyKind::Open),
+            ResponseMessage::FileSaved { .. } => self.busy.finish(BusyKind::Save),
+            ResponseMessage::HistoryLoaded(_) => self.busy.finish(BusyKind::History),
+            _ => {}
         }
-        self.editor.set_current_file(Some(data.path.clone()));
-        if !data.uuid.is_empty() {
-            self.editor.set_uuid(data.uuid);
+        let tab = self.response_tab(&response);
+        if !self.with_tab(tab, |app| app.handle_response(response)) {
+            tracing::info!("Dropping a reply for closed tab {}", tab);
         }
-        if data.total_time > 0 {
-            self.editor.set_current_file_total_time(data.total_time);
+    }
+
+    /// The tab a response is for: the one whose document started the work,
+    /// or the active one for work that is not about a document
+    fn response_tab(&self, response: &ResponseMessage) -> TabId {
+        match response {
+            ResponseMessage::FileSaved { tab, .. }
+            | ResponseMessage::FileLoaded { tab, .. }
+            | ResponseMessage::FileRenamed { tab, .. }
+            | ResponseMessage::MarksLoaded { tab, .. } => *tab,
+            ResponseMessage::AiProgress { request_id, .. }
+            | ResponseMessage::AiResponse { request_id, .. }
+            | ResponseMessage::ProofreadChecked { request_id, .. } => self
+                .tab_of(|doc| doc.editor.get_ai_panel().active_request_id() == Some(*request_id)),
+            ResponseMessage::NarrativeMapLoaded { uuid, .. } => {
+                self.tab_of(|doc| doc.editor.get_sidebar_uuid() == Some(uuid))
+            }
+            ResponseMessage::ExternalChange { path, .. } => {
+                self.tab_of(|doc| doc.editor.get_current_file().map(PathBuf::as_path) == Some(path))
+            }
+            ResponseMessage::NarrativeMapExtracted { request_id, .. } => self.tab_of(|doc| {
+                doc.narrative_map_request
+                    .as_ref()
+                    .is_some_and(|(request, _)| request.id == *request_id)
+            }),
+            _ => self.tabs.active(),
         }
-        self.config.add_recent_file(data.path.clone());
-        if let Some(data) = marks {
-            self.editor.apply_marks(data);
+    }
+
+    /// The first tab whose document matches `predicate`, or the active tab
+    /// if none does
+    fn tab_of(&self, predicate: impl Fn(&Document) -> bool) -> TabId {
+        if predicate(&self.doc) {
+            return self.tabs.active();
         }
-        tracing::info!("File opened: {:?}", data.path);
+        self.tabs.find(predicate).unwrap_or(self.tabs.active())
     }
 
-    fn update_time_backend_if_focus_changed(&mut self) {
-        let is_focused = self.editor.is_focused();
-        if is_focused != self.last_focus_state {
-            self.time_backend.update_focus(is_focused);
-            self.last_focus_state = is_focused;
+    /// Run `f` with tab `id`'s document swapped in as the active one, then
+    /// switch back. Returns false if the tab is not open any more.
+    fn with_tab(&mut self, id: TabId, f: impl FnOnce(&mut Self)) -> bool {
+        let active = self.tabs.active();
+        if id == active {
+            f(self);
+            return true;
         }
+        if !self.tabs.activate(id, &mut self.doc) {
+            return false;
+        }
+        self.home_tab = Some(active);
+        f(self);
+        self.home_tab = None;
+        self.tabs.activate(active, &mut self.doc);
+        true
     }
 
-    fn check_response_messages(&mut self) {
-        if let Ok(response) = self.response_receiver.try_recv() {
-            match response {
-                ResponseMessage::FileSaved(result) => match result {
-                    Ok((uuid, total_time)) => {
-                        self.apply_save_file(uuid, total_time);
-                    }
-                    Err(e) => tracing::error!("Failed to save file: {}", e),
-                },
-                ResponseMessage::FileLoaded(result) => match result {
-                    Ok(data) => {
-                        self.apply_load_file_data(data, None);
-                    }
-                    Err(e) => tracing::error!("Failed to load file: {}", e),
-                },
-                ResponseMessage::HistoryLoaded(result) => match result {
-                    Ok(entries) => {
-                        if let Err(e) = self
-                            .history_window
-                            .set_history(entries, &self.editor_backend)
-                        {
-                            tracing::info!("Failed to set history: {}", e);
+    fn handle_response(&mut self, response: ResponseMessage) {
+        match response {
+            response @ (ResponseMessage::FileSaved { .. }
+            | ResponseMessage::FileLoaded { .. }
+            | ResponseMessage::FileRenamed { .. }
+            | ResponseMessage::HistoryLoaded(_)
+            | ResponseMessage::MarksLoaded { .. }) => {
+                if let Some(outcome) = self.controller.handle_message(
+                    &mut self.doc.editor,
+                    &mut self.doc.saves,
+                    response,
+                ) {
+                    self.apply_outcome(outcome);
+                }
+            }
+            ResponseMessage::Printed(result) => match result {
+                Ok(message) => self.toasts.success(message),
+                Err(e) => self.report("打印失败", e),
+            },
+            ResponseMessage::FrequentWordsCounted { tab, words } => {
+                self.frequent_words_window.set_words(tab, words);
+            }
+            ResponseMessage::GlobalSearchHits { search, hits } => {
+                self.global_search_window.add_hits(search, hits);
+            }
+            ResponseMessage::GlobalSearchFinished { search, result } => {
+                self.global_search
+                    .take_if(|(running, _)| *running == search);
+                self.global_search_window
+                    .finish(search, result.map_err(|e| e.to_string()));
+            }
+            ResponseMessage::AllMarksLoaded(result) => {
+                self.all_marks_window
+                    .set_files(result.map_err(|e| e.to_string()));
+            }
+            ResponseMessage::DailyLogLoaded(result) => {
+                self.stats_window.set_log(
+                    result.map_err(|e| e.to_string()),
+                    chrono::Local::now().date_naive(),
+                );
+            }
+            ResponseMessage::DailyLogNotWritten {
+                focused_secs,
+                typing_secs,
+                error,
+            } => {
+                self.return_unlogged_time(focused_secs, typing_secs);
+                self.report("写作日志更新失败", error);
+            }
+            ResponseMessage::OpenFile(path) => {
+                // A file opened on purpose, e.g. the one macOS launched the
+                // app for, wins over the last session
+                self.pending_session = None;
+                self.try_load_file_data(path);
+            }
+            ResponseMessage::OpenLink(url) => match deeplink::parse(&url) {
+                Ok(link) => {
+                    self.pending_session = None;
+                    self.open_link(link);
+                }
+                Err(e) => self.report("无法打开链接", e.into()),
+            },
+            ResponseMessage::NarrativeMapLoaded { uuid, result } => {
+                if self.doc.editor.get_sidebar_uuid() == Some(&uuid) {
+                    match result {
+                        Ok(beats) => {
+                            self.doc
+                                .editor
+                                .get_ai_panel_mut()
+                                .set_narrative_map(beats.value);
+                            if let Some(set_aside) = beats.set_aside {
+                                self.report_set_aside("叙事地图", &set_aside);
+                            }
                         }
+                        Err(e) => self.report("读取叙事地图失败", e),
                     }
-                    Err(e) => tracing::error!("Failed to load history: {}", e),
-                },
-                ResponseMessage::MarksLoaded(result) => match result {
-                    Ok(marks) => {
-                        self.editor.apply_marks(marks);
+                }
+            }
+            ResponseMessage::NarrativeMapExtracted { request_id, result } => {
+                // Results for a cancelled request or a file no longer open are dropped
+                if let Some((_, uuid)) = self
+                    .doc
+                    .narrative_map_request
+                    .take_if(|(request, _)| request.id == request_id)
+                {
+                    if let Ok(beats) = &result {
+                        let backend = Arc::clone(&self.ai_panel_backend);
+                        let beats = beats.clone();
+                        let sender = self.response_sender.clone();
+                        std::thread::spawn(move || {
+                            if let Err(e) = backend.save_narrative_map(&uuid, &beats) {
+                                let _ = sender.send(ResponseMessage::Failed {
+                                    what: "叙事地图保存失败",
+                                    error: e.into(),
+                                });
+                            }
+                        });
                     }
-                    Err(e) => tracing::error!("Failed to load marks: {}", e),
-                },
-                ResponseMessage::OpenFile(path) => {
-                    self.try_load_file_data(path);
-                }
-                ResponseMessage::AiProgress { request_id, event } => {
-                    self.editor.apply_ai_progress(request_id, event);
-                }
-                ResponseMessage::AiResponse { request_id, result } => {
-                    if self
-                        .active_ai_request
-                        .as_ref()
-                        .is_some_and(|request| request.id == request_id)
-                    {
-                        self.active_ai_request = None;
+                    self.doc
+                        .editor
+                        .get_ai_panel_mut()
+                        .set_narrative_map_result(result);
+                }
+            }
+            ResponseMessage::AiProgress { request_id, event } => {
+                // Tokens spent by a stopped request still count
+                if let AiProgressEvent::Usage { model, usage } = &event {
+                    self.record_ai_usage(model, *usage);
+                }
+                if self.ai_requests.is_active(request_id) {
+                    self.doc.editor.apply_ai_progress(request_id, event);
+                }
+            }
+            ResponseMessage::AiResponse { request_id, result } => {
+                if !self.ai_requests.accept(request_id) {
+                    tracing::info!("Dropping reply of stale AI request {}", request_id);
+                    return;
+                }
+                match result {
+                    Ok(response) => {
+                        tracing::info!(
+                            "AI response received: content_chars={}, tool_calls={}",
+                            response.content.chars().count(),
+                            response.tool_calls.len()
+                        );
+                        self.doc.editor.set_ai_response(request_id, response);
                     }
-                    match result {
-                        Ok(response) => {
-                            tracing::info!(
-                                "AI response received: content_chars={}, tool_calls={}",
-                                response.content.chars().count(),
-                                response.tool_calls.len()
-                            );
-                            self.editor.set_ai_response(request_id, response);
-                        }
-                        Err(e) => {
-                            tracing::error!("AI request failed: {}", e);
-                            self.editor.set_ai_error(request_id, e);
-                        }
+                    Err(e) => {
+                        self.report("AI 请求失败", e.clone().into());
+                        self.doc.editor.set_ai_error(request_id, e);
                     }
                 }
-                ResponseMessage::PluginFinished { name, result } => {
-                    if let Err(e) = &result {
-                        tracing::error!("Plugin '{}' failed: {}", name, e);
-                    } else {
-                        tracing::info!("Plugin '{}' finished", name);
-                    }
-                    self.plugin_output.finish(name, result);
+            }
+            ResponseMessage::ProofreadChecked { request_id, result } => {
+                if !self.ai_requests.accept(request_id) {
+                    tracing::info!("Dropping proofreading of stale request {}", request_id);
+                    return;
+                }
+                match &result {
+                    Ok(issues) => tracing::info!("Proofreading found {} issues", issues.len()),
+                    Err(e) => self.report("校对失败", e.clone().into()),
+                }
+                self.doc.editor.set_proofread_result(request_id, result);
+            }
+            ResponseMessage::AiConnectionTested(result) => {
+                self.settings_window
+                    .set_connection_result(result.map_err(|e| e.to_string()));
+            }
+            ResponseMessage::Failed { what, error } => self.report(what, error),
+            ResponseMessage::FontsEnumerated(fonts) => {
+                self.fonts_loading = false;
+                self.settings_window
+                    .set_available_fonts(fonts.chinese.clone());
+                self.available_fonts = fonts;
+            }
+            ResponseMessage::FontWeightsFound { family, weights } => {
+                if self.current_fonts.family.as_ref() == Some(&family) {
+                    self.font_weights = Some(weights);
+                }
+            }
+            ResponseMessage::FontFilePicked(path) => {
+                self.config.settings.custom_font_path = Some(path.clone());
+                self.config.mark_dirty();
+                // Load the file again even if it is the one in use, it may
+                // have been replaced
+                self.current_fonts.file = None;
+                self.editor_settings_outdated = true;
+                if self.doc.file_settings.font_family.is_some() {
+                    self.toasts
+                        .info("当前文件使用单独设置的字体，字体文件将用于其他文件");
                 }
             }
+            ResponseMessage::ExternalChange { path, change } => {
+                // The file may have been closed or saved elsewhere since
+                if self.doc.editor.get_current_file() == Some(&path) {
+                    self.apply_external_change(&path, change);
+                }
+            }
+            ResponseMessage::PluginFinished { name, result } => {
+                let result = result.map_err(|e| {
+                    // The output window shows the error; keep it in the log too
+                    let error = AppError::from(e);
+                    self.log_error(&format!("插件“{}”运行失败", name), &error);
+                    error.to_string()
+                });
+                if result.is_ok() {
+                    tracing::info!("Plugin '{}' finished", name);
+                }
+                self.plugin_output.finish(name, result);
+            }
+        }
+    }
+
+    /// Build the context for an AI request with private lines withheld,
+    /// telling the panel how much was left out.
+    fn ai_document(
+        &mut self,
+        request_id: AiRequestId,
+        content: String,
+        selection: Option<AiSelectionContext>,
+    ) -> AiDocumentContext {
+        let mut document = AiDocumentContext {
+            title: self.document_title(),
+            content,
+            selection,
+        };
+        let withheld = redact_document(&mut document, self.doc.editor.get_marks());
+        if withheld > 0 {
+            tracing::info!("Withheld {} private characters from AI request", withheld);
+            self.doc.editor.apply_ai_progress(
+                request_id,
+                AiProgressEvent::Notice(format!("已隐去 {} 个私密字符，未发送给模型", withheld)),
+            );
         }
+        document
     }
 
     fn handle_ai_panel_action(&mut self, action: AiPanelAction) {
@@ -495,47 +2370,161 @@ impl PaperShellApp {
                 conversation,
                 selection,
             } => {
-                let content = self.editor.get_content();
-                let request_id = self.next_ai_request_id;
-                self.next_ai_request_id = self.next_ai_request_id.wrapping_add(1).max(1);
-                let title = self
-                    .editor
-                    .get_current_file()
-                    .and_then(|path| path.file_name())
-                    .and_then(|name| name.to_str())
-                    .unwrap_or("未命名文档")
-                    .to_string();
+                // Without an explicit selection, fall back to whatever is
+                // selected in the editor; the whole document stays reachable
+                // through the backend's retrieval tools either way.
+                let selection = selection.or_else(|| self.doc.editor.selection_context());
+                let content = self.doc.editor.get_content();
+                let Some(request_id) = self.ai_requests.dispatch() else {
+                    tracing::warn!("Another AI request is still running; ignoring this one");
+                    return;
+                };
 
-                self.editor
+                self.doc
+                    .editor
                     .begin_ai_request(request_id, content.clone(), selection.clone());
+                if let Some(reason) = self.ai_backend.unavailable_reason() {
+                    self.doc
+                        .editor
+                        .set_ai_error(request_id, AiError::ConfigError(reason));
+                    self.ai_requests.accept(request_id);
+                    return;
+                }
                 tracing::info!("Sending AI request {}", request_id);
 
-                let ai_backend = Arc::clone(&self.ai_backend);
-                let response_sender = self.response_sender.clone();
-
-                let handle = ai_backend.discuss_writing_context(
-                    AiDocumentContext {
-                        title,
-                        content,
-                        selection,
-                    },
+                let document = self.ai_document(request_id, content, selection);
+                self.doc.last_ai_prompt = Some(SentAiPrompt {
+                    document: document.clone(),
+                    conversation: conversation.clone(),
+                    generation: 0,
+                });
+                let handle = self.ai_backend.discuss_writing_context(
+                    document,
                     conversation,
                     request_id,
-                    response_sender,
+                    self.response_sender.clone(),
                 );
-                self.active_ai_request = Some(handle);
+                self.ai_requests.started(handle);
             }
-            AiPanelAction::CancelRequest { request_id } => {
-                if self
-                    .active_ai_request
-                    .as_ref()
-                    .is_some_and(|request| request.id == request_id)
-                {
-                    if let Some(request) = self.active_ai_request.take() {
-                        request.cancel();
-                    }
+            AiPanelAction::Regenerate => {
+                let Some(prompt) = self.doc.last_ai_prompt.as_mut() else {
+                    tracing::warn!("Nothing to regenerate");
+                    return;
+                };
+                let Some(request_id) = self.ai_requests.dispatch() else {
+                    tracing::warn!("Another AI request is still running; not regenerating");
+                    return;
+                };
+                prompt.generation += 1;
+
+                self.doc.editor.begin_ai_request(
+                    request_id,
+                    prompt.document.content.clone(),
+                    prompt.document.selection.clone(),
+                );
+                if let Some(reason) = self.ai_backend.unavailable_reason() {
+                    self.doc
+                        .editor
+                        .set_ai_error(request_id, AiError::ConfigError(reason));
+                    self.ai_requests.accept(request_id);
+                    return;
+                }
+                tracing::info!(
+                    "Regenerating AI request {} (generation {})",
+                    request_id,
+                    prompt.generation
+                );
+
+                let handle = self.ai_backend.discuss_writing_context(
+                    prompt.document.clone(),
+                    prompt.conversation.clone(),
+                    request_id,
+                    self.response_sender.clone(),
+                );
+                self.ai_requests.started(handle);
+            }
+            AiPanelAction::ExtractNarrativeMap => {
+                let Some(uuid) = self.doc.editor.get_sidebar_uuid().cloned() else {
+                    self.doc
+                        .editor
+                        .get_ai_panel_mut()
+                        .set_narrative_map_result(Err(AiError::ConfigError(
+                            "请先保存文件，再生成叙事地图".to_string(),
+                        )));
+                    return;
+                };
+                if let Some(reason) = self.ai_backend.unavailable_reason() {
+                    self.doc
+                        .editor
+                        .get_ai_panel_mut()
+                        .set_narrative_map_result(Err(AiError::ConfigError(reason)));
+                    return;
+                }
+                if let Some((request, _)) = self.doc.narrative_map_request.take() {
+                    request.cancel();
+                }
+
+                let request_id = self.ai_requests.next_id();
+                self.doc.editor.get_ai_panel_mut().begin_narrative_map();
+                tracing::info!("Extracting narrative map, request {}", request_id);
+
+                let document = self.ai_document(request_id, self.doc.editor.get_content(), None);
+                let handle = self.ai_backend.extract_narrative_map(
+                    document,
+                    request_id,
+                    self.response_sender.clone(),
+                );
+                self.doc.narrative_map_request = Some((handle, uuid));
+            }
+            AiPanelAction::Proofread { selection } => {
+                let selection = selection.or_else(|| self.doc.editor.selection_context());
+                let content = self.doc.editor.get_content();
+                let Some(request_id) = self.ai_requests.dispatch() else {
+                    tracing::warn!("Another AI request is still running; ignoring this one");
+                    return;
+                };
+
+                self.doc
+                    .editor
+                    .begin_ai_request(request_id, content.clone(), selection.clone());
+                if let Some(reason) = self.ai_backend.unavailable_reason() {
+                    self.doc
+                        .editor
+                        .set_ai_error(request_id, AiError::ConfigError(reason));
+                    self.ai_requests.accept(request_id);
+                    return;
+                }
+                tracing::info!("Proofreading, request {}", request_id);
+
+                let document = self.ai_document(request_id, content, selection);
+                let handle =
+                    self.ai_backend
+                        .proofread(document, request_id, self.response_sender.clone());
+                self.ai_requests.started(handle);
+            }
+            AiPanelAction::ApplyProofread {
+                entry_index,
+                issue_index,
+                original,
+                suggestion,
+                search_from,
+            } => {
+                let result = self
+                    .doc
+                    .editor
+                    .apply_proofread(&original, &suggestion, search_from);
+                self.doc
+                    .editor
+                    .set_proofread_item_result(entry_index, issue_index, result);
+            }
+            AiPanelAction::JumpToBeat { beat } => {
+                if !self.doc.editor.reveal_beat(&beat) {
+                    tracing::info!("Narrative beat not found in text: {}", beat);
                 }
-                self.editor.cancel_ai_request(request_id);
+            }
+            AiPanelAction::CancelRequest { request_id } => {
+                self.ai_requests.cancel(request_id);
+                self.doc.editor.cancel_ai_request(request_id);
                 tracing::info!("Stopped AI request {}", request_id);
             }
             AiPanelAction::ApplyEdit {
@@ -545,27 +2534,28 @@ impl PaperShellApp {
                 replacement_text,
             } => {
                 let result =
-                    self.editor
+                    self.doc
+                        .editor
                         .apply_ai_edit(&base_content, &original_text, &replacement_text);
                 if let Err(error) = &result {
                     tracing::warn!("AI edit was not applied: {}", error);
                 } else {
                     tracing::info!("AI edit applied after user confirmation");
                 }
-                self.editor.set_ai_edit_result(proposal_index, result);
+                self.doc.editor.set_ai_edit_result(proposal_index, result);
             }
             AiPanelAction::PreviewEdit { proposal_index } => {
-                self.editor.preview_ai_edit(proposal_index);
+                self.doc.editor.preview_ai_edit(proposal_index);
             }
             AiPanelAction::RejectEdit { proposal_index } => {
-                self.editor.reject_ai_edit(proposal_index);
+                self.doc.editor.reject_ai_edit(proposal_index);
                 tracing::info!("AI edit proposal ignored by user");
             }
             AiPanelAction::NavigateEdit { direction } => {
-                self.editor.navigate_ai_edit(direction);
+                self.doc.editor.navigate_ai_edit(direction);
             }
             AiPanelAction::ApplyAllEdits => {
-                let (applied, failed) = self.editor.apply_all_ai_edits();
+                let (applied, failed) = self.doc.editor.apply_all_ai_edits();
                 tracing::info!(
                     "AI batch review finished: applied={}, failed={}",
                     applied,
@@ -573,9 +2563,15 @@ impl PaperShellApp {
                 );
             }
             AiPanelAction::RejectAllEdits => {
-                self.editor.reject_all_ai_edits();
+                self.doc.editor.reject_all_ai_edits();
                 tracing::info!("All pending AI edit proposals rejected");
             }
+            AiPanelAction::InsertResponse { text } => {
+                self.doc.editor.insert_at_cursor(&text);
+            }
+            AiPanelAction::ReplaceSelection { selection, text } => {
+                self.ai_review_window.open(selection, &text);
+            }
         }
     }
     fn handle_history_action(&mut self, action: HistoryAction) {
@@ -583,12 +2579,10 @@ impl PaperShellApp {
             HistoryAction::RollbackToVersion(hash) => {
                 match self.editor_backend.restore_version(&hash) {
                     Ok(content) => {
-                        self.editor.set_content(content);
+                        self.doc.editor.set_content(content);
                         tracing::info!("Rolled back to version: {}", hash);
                     }
-                    Err(e) => {
-                        tracing::error!("Failed to rollback to version {}: {}", hash, e);
-                    }
+                    Err(e) => self.report("恢复历史版本失败", e.into()),
                 }
             }
         }
@@ -605,166 +2599,363 @@ impl PaperShellApp {
         let name = plugin.metadata().name;
         self.plugin_output.start(&name);
 
+        // The file's own indent, if it has one
+        let settings = self.doc.file_settings.apply_to(&self.config.settings);
         let ctx = PluginContext {
-            file_path: self.editor.get_current_file().cloned(),
-            content: self.editor.get_content(),
+            file_path: self.doc.editor.get_current_file().cloned(),
+            content: self.doc.editor.get_content(),
             data_dir: self.config.data_dir(),
             title: None,
             description: None,
             collection: None,
             printer: None,
-            print_margin_points: None,
+            print: settings.print,
+            format_indent: settings.format_indent,
         };
         let sender = self.response_sender.clone();
 
         std::thread::spawn(move || {
-            let result = plugin.run(&ctx).map_err(|e| e.to_string());
+            let result = plugin.run(&ctx);
             let _ = sender.send(ResponseMessage::PluginFinished { name, result });
         });
     }
 
+    /// Preview the current document in the 打印预览 window
+    fn open_print_dialog(&mut self) {
+        let content = self.doc.editor.get_content();
+        if content.trim().is_empty() {
+            self.toasts.info("当前文档为空，无法打印");
+            return;
+        }
+        let document_name = self
+            .doc
+            .editor
+            .get_current_file()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("未命名文档")
+            .to_string();
+        // The file's own indent, if it has one
+        let settings = self.doc.file_settings.apply_to(&self.config.settings);
+        let indent = settings
+            .print
+            .indent_paragraphs
+            .then(|| settings.format_indent.as_str());
+        self.print_dialog
+            .open(document_name, content, &settings.print, indent);
+    }
+
+    /// Hand `job` to the system's print spooler in the background
+    fn print(&mut self, job: PrintJob) {
+        let sender = self.response_sender.clone();
+        std::thread::spawn(move || {
+            let result = print::print(&job)
+                .map(|()| {
+                    let printer = job.printer.as_deref().unwrap_or("默认打印机");
+                    format!("已发送到 {}：{}", printer, job.document_name)
+                })
+                .map_err(AppError::from);
+            let _ = sender.send(ResponseMessage::Printed(result));
+        });
+    }
+
     /// Ensures the plugins directory exists and opens it in the system file
     /// manager so users can install plugins by dropping folders into it.
-    fn open_plugins_folder(&self) {
+    fn open_plugins_folder(&mut self) {
         let dir = self.config.data_dir().join("plugins");
-        if let Err(e) = std::fs::create_dir_all(&dir) {
-            tracing::error!("Failed to create plugins dir {:?}: {}", dir, e);
+        if let Err(source) = std::fs::create_dir_all(&dir) {
+            self.report("无法创建插件目录", AppError::Write { path: dir, source });
             return;
         }
-
-        #[cfg(target_os = "macos")]
-        let opener = "open";
-        #[cfg(target_os = "windows")]
-        let opener = "explorer";
-        #[cfg(all(unix, not(target_os = "macos")))]
-        let opener = "xdg-open";
-
-        if let Err(e) = std::process::Command::new(opener).arg(&dir).spawn() {
-            tracing::error!("Failed to open plugins dir: {}", e);
+        if let Err(e) = file_manager::open_folder(&dir) {
+            self.report("无法打开插件目录", e.into());
         }
     }
 }
 
 impl eframe::App for PaperShellApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        self.check_response_messages();
-        if self.active_ai_request.is_some() {
+        if self.check_response_messages() {
+            ctx.request_repaint();
+        }
+        self.restore_session();
+        if self.ai_requests.is_busy() {
             // Background mpsc messages do not wake eframe on their own. Keep a light
             // repaint heartbeat so streamed tokens and completions appear even when
             // the AI panel is hidden and the user is not moving the pointer.
             ctx.request_repaint_after(std::time::Duration::from_millis(50));
         }
+        if self.editor_settings_outdated {
+            self.apply_editor_settings(ctx);
+        }
+        if ctx.input(|i| i.viewport().close_requested()) && !self.close_confirmed {
+            if self.confirm_close() {
+                self.close_confirmed = true;
+                // Every document is settled, so nothing waits on the dialog
+                self.show_goal_summary_if_unmet();
+            } else {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            }
+        }
         self.try_save_marks_if_changed();
+        self.autosave_if_due();
+        self.update_buffer_mirror();
+        self.update_watched_files();
+        self.track_window_geometry(ctx);
+        self.update_window_title(ctx);
+        self.reload_settings_if_changed(ctx);
+        // Keep checking the settings file while idle
+        ctx.request_repaint_after(crate::config::RELOAD_CHECK_INTERVAL);
         self.update_time_backend_if_focus_changed();
+        if self.last_focus_state {
+            // Keep the title-bar timer ticking while the user is writing.
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+        self.try_flush_daily_log();
+        self.time_debug_window.handle_shortcut(ctx);
+        self.log_viewer.handle_shortcut(ctx);
+        if !self.settings_window.is_capturing_shortcut()
+            && let Some(action) = ctx.input_mut(|input| {
+                shortcuts::match_action(input, &self.config.settings.keybindings)
+            })
+        {
+            self.handle_shortcut(ctx, action);
+        }
 
-        // Title Bar
-        egui::TopBottomPanel::top("title_bar_panel").show(ctx, |ui| {
-            let (total_words, cursor_words) = self.editor.get_stats();
-            if let Some(action) = crate::ui::title_bar::TitleBar::show(
-                ui,
-                frame,
-                crate::ui::title_bar::TitleBarState {
-                    title: crate::constant::DEFAULT_WINDOW_TITLE,
-                    word_count: total_words,
-                    cursor_word_count: cursor_words,
-                    writing_time: self.editor.get_current_file_total_time()
+        self.update_focus_mode(ctx);
+        self.window_frame.show(ctx);
+
+        // Title Bar, hidden in distraction-free mode until the pointer goes up
+        let show_title_bar = self
+            .focus_mode
+            .as_ref()
+            .is_none_or(|focus| focus.title_bar_revealed);
+        let panel_frame = self
+            .window_frame
+            .panel(ctx, egui::Frame::side_top_panel(&ctx.style()));
+        let title_bar = egui::TopBottomPanel::top("title_bar_panel")
+            .frame(panel_frame)
+            .show_animated(ctx, show_title_bar, |ui| {
+                let (total_words, cursor_words) = self.doc.editor.get_stats();
+                // Counting the selection is only worth it while it is shown
+                let selection = egui::Popup::is_id_open(ctx, stats_popover_id())
+                    .then(|| self.doc.editor.get_selected_text())
+                    .flatten()
+                    .map(|text| TextStats::of(&text));
+                let stats = DetailedStats {
+                    text: self.doc.editor.get_text_stats(),
+                    selection,
+                    cursor_words,
+                    writing_time: self.doc.editor.get_current_file_total_time()
+                        + self.doc.unsaved_writing_secs
                         + self.time_backend.get_writing_time(),
-                    has_current_file: self.editor.get_current_file().is_some(),
-                    chinese_fonts: &self.available_fonts,
-                    current_font: &self.current_font,
-                    recent_files: &self.config.settings.recent_files,
-                    is_ai_panel_visible: self.editor.get_ai_panel_mut().is_visible,
-                    plugins: &self.plugin_metadata,
-                },
-            ) {
-                match action {
-                    crate::ui::title_bar::TitleBarAction::NewWindow => self.spawn_new_window(),
-                    crate::ui::title_bar::TitleBarAction::Save => self.try_save_file(),
-                    crate::ui::title_bar::TitleBarAction::Open => {
-                        self.try_open_file_from_selector()
-                    }
-                    crate::ui::title_bar::TitleBarAction::OpenFile(path) => self.open_file(path),
-                    crate::ui::title_bar::TitleBarAction::Format => self.editor.format(),
-                    crate::ui::title_bar::TitleBarAction::History => self.try_load_history(),
-                    crate::ui::title_bar::TitleBarAction::SearchReplace => {
-                        self.editor.open_search_replace();
-                    }
-                    crate::ui::title_bar::TitleBarAction::Settings => {
-                        self.settings_window.open(&self.config.settings.ai_panel);
-                    }
-                    crate::ui::title_bar::TitleBarAction::FontChange(font_name) => {
-                        let new_fonts = crate::ui::font::apply_font(&font_name);
-                        ctx.set_fonts(new_fonts);
-                        self.current_font = font_name.clone();
-                        tracing::info!("Font changed to: {}", font_name);
-                    }
-                    crate::ui::title_bar::TitleBarAction::ToggleAiPanel => {
-                        let panel = self.editor.get_ai_panel_mut();
-                        panel.is_visible = !panel.is_visible;
-                    }
-                    crate::ui::title_bar::TitleBarAction::RunPlugin(id) => {
-                        if id == "github_publish" {
-                            if self.config.settings.github_publish.repo.trim().is_empty() {
-                                self.plugin_config_window
-                                    .open(&self.config.settings.github_publish, true);
-                            } else {
-                                self.publish_dialog
-                                    .open(&self.config.settings.github_publish);
-                            }
-                        } else {
-                            if id == "print" {
-                                let document_name = self
-                                    .editor
-                                    .get_current_file()
-                                    .and_then(|path| path.file_name())
-                                    .and_then(|name| name.to_str())
-                                    .unwrap_or("未命名文档")
-                                    .to_string();
-                                self.print_dialog
-                                    .open(document_name, self.editor.get_content());
+                    today_writing_time: self.today_writing_secs(),
+                };
+                let chars_per_page = self.config.settings.chars_per_page;
+                let is_dirty = self.doc.editor.is_dirty();
+                let is_ai_panel_visible = self.doc.editor.get_ai_panel_mut().is_visible;
+                if let Some(action) = crate::ui::title_bar::TitleBar::show(
+                    ui,
+                    frame,
+                    crate::ui::title_bar::TitleBarState {
+                        title: crate::constant::DEFAULT_WINDOW_TITLE,
+                        stats,
+                        chars_per_page,
+                        productivity: self.doc.productivity.metrics(total_words),
+                        session_writing_time: self.time_backend.session_writing_ms() / 1000,
+                        session_typing_time: self.time_backend.session_typing_ms() / 1000,
+                        goal_progress: self.daily_goal_progress(),
+                        has_current_file: self.doc.editor.get_current_file().is_some(),
+                        current_file: self.doc.editor.get_current_file().map(PathBuf::as_path),
+                        is_dirty,
+                        busy_status: self.busy.status(),
+                        errors: self.error_log.len(),
+                        unseen_errors: self.error_log.unseen(),
+                        chinese_fonts: &self.available_fonts.chinese,
+                        latin_fonts: &self.available_fonts.latin,
+                        current_latin_font: self.current_fonts.latin.as_deref(),
+                        font_weight: self.current_fonts.weight,
+                        font_weights: self.font_weights.as_deref(),
+                        fonts_loading: self.fonts_loading,
+                        current_font: match &self.current_fonts.file {
+                            Some(_) => "",
+                            None => self.current_fonts.family.as_deref().unwrap_or_default(),
+                        },
+                        font_file: self.current_fonts.file.as_deref(),
+                        font_previews: &self.font_previews,
+                        recent_files: &self.config.settings.recent_files,
+                        pinned_files: &self.config.settings.pinned_files,
+                        is_ai_panel_visible,
+                        plugins: &self.plugin_metadata,
+                        frameless: self.window_frame.is_frameless(),
+                    },
+                ) {
+                    let effect = self.controller.handle_title_action(
+                        &mut self.doc.editor,
+                        &mut self.doc.file_settings,
+                        &mut self.config.settings,
+                        action,
+                    );
+                    match effect {
+                        TitleEffect::Done => {}
+                        TitleEffect::SettingsChanged => self.config.mark_dirty(),
+                        TitleEffect::FontChanged { file } => {
+                            if file {
+                                self.save_file_settings();
                             } else {
-                                self.run_plugin(id);
+                                self.config.mark_dirty();
                             }
+                            self.apply_editor_settings(ctx);
                         }
+                        TitleEffect::Unhandled(action) => self.handle_title_action(ctx, action),
                     }
-                    crate::ui::title_bar::TitleBarAction::OpenPluginsFolder => {
-                        self.open_plugins_folder();
-                    }
-                    crate::ui::title_bar::TitleBarAction::ConfigurePlugin(id) => {
-                        if id == "github_publish" {
-                            self.plugin_config_window
-                                .open(&self.config.settings.github_publish, false);
+                }
+            });
+
+        let tab_bar = egui::TopBottomPanel::top("tab_bar_panel")
+            .frame(panel_frame)
+            .show_animated(ctx, show_title_bar, |ui| {
+                let tabs: Vec<TabInfo> = self
+                    .tabs
+                    .iter_mut()
+                    .map(|(id, doc)| {
+                        let doc = match doc {
+                            Some(doc) => doc,
+                            None => &mut self.doc,
+                        };
+                        TabInfo {
+                            id,
+                            path: doc.editor.get_current_file().cloned(),
+                            is_dirty: doc.editor.is_dirty(),
                         }
-                    }
+                    })
+                    .collect();
+                crate::ui::tab_bar::show(ui, &tabs, self.tabs.active())
+            });
+        match tab_bar.and_then(|response| response.inner) {
+            Some(TabBarAction::Select(id)) => self.switch_tab(id),
+            Some(TabBarAction::Close(id)) => {
+                self.switch_tab(id);
+                if self.close_document() {
+                    tracing::info!("Closed a tab");
                 }
             }
-        });
+            Some(TabBarAction::New) => self.open_tab(),
+            None => {}
+        }
+
+        if let (Some(focus), Some(title_bar)) = (&mut self.focus_mode, title_bar) {
+            focus.title_bar_bottom = title_bar.response.rect.bottom() - ctx.content_rect().top();
+        }
+
+        // The panel can also be opened from the inline AI popup, so compare
+        // against the saved state instead of hooking each place that shows it.
+        // Distraction-free mode hides it only for a while, which is not saved.
+        let is_ai_panel_visible = self.doc.editor.get_ai_panel_mut().is_visible;
+        if self.focus_mode.is_none()
+            && self.config.settings.ai_panel_layout.visible != is_ai_panel_visible
+        {
+            self.config.settings.ai_panel_layout.visible = is_ai_panel_visible;
+            self.ai_panel_layout_dirty = true;
+        }
 
         let mut ai_panel_action = None;
-        if self.editor.get_ai_panel_mut().is_visible {
-            egui::SidePanel::right("ai_panel_side")
-                .default_width(320.0)
-                .min_width(260.0)
-                .max_width(520.0)
-                .resizable(true)
-                .show(ctx, |ui| {
-                    ai_panel_action = self.editor.get_ai_panel_mut().show(ui);
-                });
+        if is_ai_panel_visible {
+            let is_processing = self.doc.editor.get_ai_panel_mut().is_processing;
+            let editor = &mut self.doc.editor;
+            if show_ai_panel_frame(
+                ctx,
+                &self.window_frame,
+                &mut self.config.settings.ai_panel_layout,
+                is_processing,
+                |ui| ai_panel_action = editor.show_ai_panel(ui),
+            ) {
+                self.ai_panel_layout_dirty = true;
+            }
+        }
+        // Persist once a drag or resize has finished rather than every frame
+        if self.ai_panel_layout_dirty && !ctx.input(|i| i.pointer.any_down()) {
+            self.ai_panel_layout_dirty = false;
+            self.config.mark_dirty();
         }
         if let Some(action) = ai_panel_action {
             self.handle_ai_panel_action(action);
         }
 
+        if let Some(warning) = &self.config_warning {
+            let mut dismissed = false;
+            egui::TopBottomPanel::bottom("config_warning")
+                .frame(panel_frame)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(ui.visuals().error_fg_color, warning);
+                        if ui.small_button("✕").clicked() {
+                            dismissed = true;
+                        }
+                    });
+                });
+            if dismissed {
+                self.config_warning = None;
+            }
+        }
+        if let Some(change) = &self.doc.external_change {
+            let name = crate::ui::title_bar::document_label(
+                self.doc.editor.get_current_file().map(PathBuf::as_path),
+                false,
+            );
+            let mut reload = false;
+            let mut dismissed = false;
+            egui::TopBottomPanel::bottom("external_change")
+                .frame(panel_frame)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        let warning = ui.visuals().warn_fg_color;
+                        if *change == FileChange::Removed {
+                            ui.colored_label(
+                                warning,
+                                format!("“{}”已在外部删除或移走，保存时会重新创建", name),
+                            );
+                            dismissed = ui.small_button("知道了").clicked();
+                        } else {
+                            ui.colored_label(warning, format!("“{}”已在外部修改", name));
+                            reload = ui.small_button("重新载入").clicked();
+                            dismissed = ui.small_button("保留当前内容").clicked();
+                        }
+                    });
+                });
+            if reload {
+                self.reload_from_disk();
+            } else if dismissed {
+                self.doc.external_change = None;
+            }
+        }
+        self.toasts.show(ctx);
+
         // Main Content
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.vertical_centered(|ui| {
-                    if let Some(action) = self.editor.show(ui) {
-                        self.handle_ai_panel_action(action);
-                    }
+        egui::CentralPanel::default()
+            .frame(
+                self.window_frame
+                    .panel(ctx, egui::Frame::central_panel(&ctx.style())),
+            )
+            .show(ctx, |ui| {
+                // Each tab keeps its own scroll position, caret and undo history
+                let mut scroll_area = egui::ScrollArea::vertical().id_salt(self.tabs.active());
+                if let Some(offset) = self.doc.pending_scroll.take() {
+                    scroll_area = scroll_area.vertical_scroll_offset(offset);
+                }
+                let output = scroll_area.show(ui, |ui| {
+                    ui.push_id(self.tabs.active(), |ui| {
+                        ui.vertical_centered(|ui| {
+                            if let Some(action) = self.doc.editor.show(ui) {
+                                self.handle_ai_panel_action(action);
+                            }
+                        });
+                    });
                 });
+                self.doc.scroll_offset = output.state.offset.y;
             });
-        });
+
+        self.show_onboarding(ctx);
 
         // History Window
         self.history_window.show(ctx);
@@ -775,25 +2966,105 @@ impl eframe::App for PaperShellApp {
         // Plugin output window
         self.plugin_output.show(ctx);
 
-        if let Some(ai_config) = self.settings_window.show(ctx) {
-            self.config.settings.ai_panel = ai_config;
-            self.ai_backend = Arc::new(AiBackend::from_config(&self.config.settings.ai_panel));
-            let settings = self.config.settings.clone();
-            std::thread::spawn(move || {
-                if let Err(e) = confy::store(crate::constant::APP_NAME, None, &settings) {
-                    tracing::error!("Failed to save settings: {}", e);
+        self.time_debug_window.show(ctx, &self.time_backend);
+        self.log_viewer
+            .show(ctx, &crate::logging::log_dir(&self.config.data_dir()));
+        self.error_window.show(
+            ctx,
+            &mut self.error_log,
+            &self.config.settings.datetime_format,
+        );
+
+        if let Some((selection, text)) = self.ai_review_window.show(ctx)
+            && let Err(e) = self.doc.editor.replace_selection(&selection, &text)
+        {
+            tracing::warn!("AI reply not applied: {}", e);
+            self.toasts.error(format!("AI 回复未应用：{}", e));
+        }
+
+        if let Some(action) = self
+            .all_marks_window
+            .show(ctx, &self.config.settings.datetime_format)
+        {
+            self.handle_all_marks_action(action);
+        }
+
+        if let Some(action) = self.global_search_window.show(ctx) {
+            self.handle_global_search_action(action);
+        }
+
+        if let Some(action) = self.frequent_words_window.show(ctx) {
+            self.handle_frequent_words_action(action);
+        }
+
+        if let Some(goals) = self.stats_window.show(ctx) {
+            self.config.settings.writing_goals = goals;
+            self.config.mark_dirty();
+        }
+
+        match self.settings_window.show(ctx) {
+            Some(SettingsAction::Apply { settings, file }) => {
+                if let Some(file) = file
+                    && file != self.doc.file_settings
+                {
+                    self.doc.file_settings = file;
+                    self.save_file_settings();
                 }
-            });
+                self.apply_settings_edits(ctx, *settings);
+                self.config.mark_dirty();
+            }
+            Some(SettingsAction::Import(settings)) => {
+                self.config.settings = *settings;
+                self.sync_api_key();
+                self.apply_settings(ctx);
+                self.config.mark_dirty();
+                let plugins_dir = self.config.data_dir().join("plugins");
+                self.plugin_manager = crate::plugin::PluginManager::new(
+                    plugins_dir,
+                    self.config.settings.github_publish.clone(),
+                );
+                self.plugin_metadata = self.plugin_manager.metadata();
+                tracing::info!("Settings imported");
+                self.toasts.success("设置已导入");
+            }
+            Some(SettingsAction::OpenDataFolder) => {
+                let dir = self.config.data_dir();
+                if let Err(e) = file_manager::open_folder(&dir) {
+                    self.report("无法打开数据文件夹", e.into());
+                }
+            }
+            Some(SettingsAction::OpenLogFolder) => {
+                let dir = crate::logging::log_dir(&self.config.data_dir());
+                if let Err(source) = std::fs::create_dir_all(&dir) {
+                    self.report("无法创建日志文件夹", AppError::Write { path: dir, source });
+                } else if let Err(e) = file_manager::open_folder(&dir) {
+                    self.report("无法打开日志文件夹", e.into());
+                }
+            }
+            Some(SettingsAction::RegisterFileAssociation) => {
+                match crate::open_with::register_file_association() {
+                    Ok(message) => self.toasts.success(message),
+                    Err(e) => self.report("无法注册文件关联", e.into()),
+                }
+            }
+            Some(SettingsAction::ClearFontCache) => {
+                crate::ui::font::clear_font_cache();
+                self.toasts.success("已清除字体缓存");
+            }
+            Some(SettingsAction::TestConnection(ai_config)) => {
+                let backend = AiBackend::from_config(&ai_config);
+                let sender = self.response_sender.clone();
+                std::thread::spawn(move || {
+                    let result = backend.test_connection();
+                    let _ = sender.send(ResponseMessage::AiConnectionTested(result));
+                });
+            }
+            None => {}
         }
 
         if let Some(new_config) = self.plugin_config_window.show(ctx) {
             self.config.settings.github_publish = new_config.clone();
-            let settings = self.config.settings.clone();
-            std::thread::spawn(move || {
-                if let Err(e) = confy::store(crate::constant::APP_NAME, None, &settings) {
-                    tracing::error!("Failed to save plugin config: {}", e);
-                }
-            });
+            self.config.mark_dirty();
             let plugins_dir = self.config.data_dir().join("plugins");
             self.plugin_manager = crate::plugin::PluginManager::new(plugins_dir, new_config);
             self.plugin_metadata = self.plugin_manager.metadata();
@@ -804,19 +3075,20 @@ impl eframe::App for PaperShellApp {
                 let name = plugin.metadata().name;
                 self.plugin_output.start(&name);
                 let plugin_ctx = PluginContext {
-                    file_path: self.editor.get_current_file().cloned(),
-                    content: self.editor.get_content(),
+                    file_path: self.doc.editor.get_current_file().cloned(),
+                    content: self.doc.editor.get_content(),
                     data_dir: self.config.data_dir(),
                     title: Some(params.title),
                     description: params.description,
                     collection: Some(params.collection_dir),
                     printer: None,
-                    print_margin_points: None,
+                    print: self.config.settings.print,
+                    format_indent: self.config.settings.format_indent,
                 };
                 let sender = self.response_sender.clone();
 
                 std::thread::spawn(move || {
-                    let result = plugin.run(&plugin_ctx).map_err(|e| e.to_string());
+                    let result = plugin.run(&plugin_ctx);
                     let _ = sender.send(ResponseMessage::PluginFinished { name, result });
                 });
             } else {
@@ -824,33 +3096,54 @@ impl eframe::App for PaperShellApp {
             }
         }
 
-        if let Some(params) = self.print_dialog.show(ctx) {
-            if let Some(plugin) = self.plugin_manager.get("print") {
-                let name = plugin.metadata().name;
-                self.plugin_output.start(&name);
-                let plugin_ctx = PluginContext {
-                    file_path: self.editor.get_current_file().cloned(),
-                    content: self.editor.get_content(),
-                    data_dir: self.config.data_dir(),
-                    title: None,
-                    description: None,
-                    collection: None,
-                    printer: params.printer,
-                    print_margin_points: Some(params.margin_points),
-                };
-                let sender = self.response_sender.clone();
-
-                std::thread::spawn(move || {
-                    let result = plugin.run(&plugin_ctx).map_err(|e| e.to_string());
-                    let _ = sender.send(ResponseMessage::PluginFinished { name, result });
-                });
-            } else {
-                tracing::warn!("Plugin not found: print");
-            }
+        if let Some(job) = self.print_dialog.show(ctx) {
+            self.print(job);
         }
     }
 
+    fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        self.window_frame.clear_color(visuals)
+    }
+
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        self.save_file();
+        // Nothing the watcher reports could be taken in any more
+        self.file_watcher = None;
+        self.flush_daily_log();
+        self.doc.unsaved_writing_secs += self.time_backend.get_and_reset_writing_time();
+        // After the close prompt every document was saved or discarded on
+        // purpose; otherwise, e.g. when the session ends, keep what is there
+        let keep = if self.close_confirmed {
+            Self::save_marks_now
+        } else {
+            Self::keep_document_on_exit
+        };
+        for id in self.tab_ids() {
+            self.with_tab(id, keep);
+        }
+        // With several windows open, the one closed last is the session
+        self.config.settings.session = self.current_session();
+        // Write pending settings, including the window geometry, before the process ends
+        self.config.mark_dirty();
+        if let Err(e) = self.config.flush() {
+            tracing::error!("Failed to save settings: {}", e);
+        }
     }
 }
+
+/// Today's date and the focused seconds the daily log has for it
+fn logged_today(daily_log: &DailyLogBackend) -> (NaiveDate, u64) {
+    let today = chrono::Local::now().date_naive();
+    let logged_secs = daily_log
+        .load()
+        .ok()
+        .and_then(|log| log.get(&today).copied())
+        .map_or(0, |totals| totals.focused_secs);
+    (today, logged_secs)
+}
+
+/// Name of `path` for status messages
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
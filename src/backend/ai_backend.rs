@@ -1,25 +1,25 @@
-use reqwest::StatusCode;
-use reqwest::blocking::{Client, Response};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::cmp::Reverse;
-use std::io::{BufRead, BufReader};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::future::Future;
+use std::sync::OnceLock;
 use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::Duration;
 use thiserror::Error;
+use tokio::runtime::Runtime;
+use tokio::time::{Instant, timeout_at};
+use tokio_util::sync::CancellationToken;
 
-use crate::config::AiPanelConfig;
+use crate::backend::usage_log::TokenUsage;
+use crate::config::{AiPanelConfig, DEFAULT_SYSTEM_INSTRUCTION, OversizeStrategy};
 use crate::messages::ResponseMessage;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum AiError {
     #[error("API error: {0}")]
     ApiError(String),
 
-    #[allow(dead_code)]
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
@@ -35,6 +35,10 @@ pub struct AiSelectionContext {
     pub start_char: usize,
     pub end_char: usize,
     pub text: String,
+    /// The sentence leading into the selection, for orientation only
+    pub context_before: String,
+    /// The sentence following the selection, for orientation only
+    pub context_after: String,
 }
 
 #[derive(Clone, Debug)]
@@ -54,19 +58,155 @@ pub enum AiProgressEvent {
     },
     Retrying {
         attempt: usize,
+        max_attempts: usize,
         reason: String,
     },
+    /// Something the user should know about how the request was sent
+    Notice(String),
+    /// Tokens reported by the provider for one round of the request
+    Usage {
+        model: String,
+        usage: TokenUsage,
+    },
 }
 
 #[derive(Clone, Debug)]
 pub struct AiRequestHandle {
     pub id: AiRequestId,
-    cancelled: Arc<AtomicBool>,
+    cancelled: CancellationToken,
 }
 
 impl AiRequestHandle {
     pub fn cancel(&self) {
-        self.cancelled.store(true, Ordering::Release);
+        self.cancelled.cancel();
+    }
+}
+
+/// Which chat API an [`AiBackend`] talks to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AiProvider {
+    /// Ollama's native `/api/chat`
+    Ollama,
+    /// Moonshot / Kimi, OpenAI-style with `max_completion_tokens`
+    Kimi,
+    /// Any `/chat/completions` endpoint: OpenAI, DeepSeek, OpenRouter, LM Studio…
+    OpenAiCompatible,
+}
+
+impl AiProvider {
+    pub const ALL: [AiProvider; 3] = [
+        AiProvider::Ollama,
+        AiProvider::Kimi,
+        AiProvider::OpenAiCompatible,
+    ];
+
+    /// Parse the `provider` value stored in settings
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "kimi" | "moonshot" => Some(AiProvider::Kimi),
+            "ollama" => Some(AiProvider::Ollama),
+            "openai" | "openai-compatible" | "openai_compatible" => {
+                Some(AiProvider::OpenAiCompatible)
+            }
+            _ => None,
+        }
+    }
+
+    /// The value written back to settings
+    pub fn config_value(self) -> &'static str {
+        match self {
+            AiProvider::Ollama => "ollama",
+            AiProvider::Kimi => "kimi",
+            AiProvider::OpenAiCompatible => "openai",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AiProvider::Ollama => "Ollama 本地",
+            AiProvider::Kimi => "Kimi for Coding",
+            AiProvider::OpenAiCompatible => "OpenAI 兼容",
+        }
+    }
+
+    pub fn default_model(self) -> &'static str {
+        match self {
+            AiProvider::Ollama => "qwen3:8b",
+            AiProvider::Kimi => "kimi-k2.7-code",
+            AiProvider::OpenAiCompatible => "gpt-4o-mini",
+        }
+    }
+
+    pub fn default_api_url(self) -> &'static str {
+        match self {
+            AiProvider::Ollama => "http://localhost:11434/api/chat",
+            AiProvider::Kimi => "https://api.moonshot.ai/v1/chat/completions",
+            AiProvider::OpenAiCompatible => "https://api.openai.com/v1/chat/completions",
+        }
+    }
+
+    /// Whether requests use the OpenAI chat/completions wire format.
+    ///
+    /// Ollama also serves that format under `/v1/chat/completions`.
+    fn uses_chat_completions(self, api_url: &str) -> bool {
+        match self {
+            AiProvider::Ollama => api_url.contains("/chat/completions"),
+            AiProvider::Kimi | AiProvider::OpenAiCompatible => true,
+        }
+    }
+
+    /// Serialize one chat round for this provider's API
+    fn chat_request_body(
+        self,
+        model: &str,
+        api_url: &str,
+        messages: Vec<Value>,
+        tools: Vec<Value>,
+        generation: &GenerationConfig,
+    ) -> Value {
+        let temperature = generation.temperature;
+        let body = if !self.uses_chat_completions(api_url) {
+            serde_json::to_value(OllamaChatRequest {
+                model: model.to_string(),
+                stream: true,
+                think: false,
+                options: OllamaOptions {
+                    num_predict: generation
+                        .max_output_tokens
+                        .unwrap_or(DEFAULT_OLLAMA_NUM_PREDICT),
+                    temperature,
+                },
+                messages,
+                tools,
+            })
+        } else if self == AiProvider::Kimi {
+            serde_json::to_value(OpenAiChatRequest {
+                model: model.to_string(),
+                stream: true,
+                stream_options: None,
+                temperature,
+                max_completion_tokens: Some(
+                    generation
+                        .max_output_tokens
+                        .unwrap_or_else(|| max_completion_tokens_for(api_url)),
+                ),
+                max_tokens: None,
+                messages,
+                tools,
+            })
+        } else {
+            serde_json::to_value(OpenAiChatRequest {
+                model: model.to_string(),
+                stream: true,
+                stream_options: Some(json!({"include_usage": true})),
+                temperature,
+                max_completion_tokens: None,
+                max_tokens: Some(generation.max_output_tokens.unwrap_or(DEFAULT_MAX_TOKENS)),
+                messages,
+                tools,
+            })
+        };
+        body.expect("chat request is serializable")
     }
 }
 
@@ -80,13 +220,25 @@ struct OllamaChatRequest {
     options: OllamaOptions,
 }
 
+/// Request body for OpenAI-style `/chat/completions` endpoints
 #[derive(Serialize)]
-struct KimiChatRequest {
+struct OpenAiChatRequest {
     model: String,
     messages: Vec<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     tools: Vec<Value>,
     stream: bool,
-    max_completion_tokens: i32,
+    /// Asks for a final chunk with token usage; Moonshot reports it unasked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// Moonshot's name for the output limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    /// The name most compatible servers understand
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -118,9 +270,56 @@ pub enum AiToolCall {
     },
 }
 
+/// One issue found by a proofreading pass
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProofreadIssue {
+    /// Verbatim text from the document
+    pub original: String,
+    pub suggestion: String,
+    pub reason: String,
+}
+
 #[derive(Serialize)]
 struct OllamaOptions {
-    num_predict: i32,
+    num_predict: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+/// Sampling settings and persona applied to every request
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationConfig {
+    /// `None` leaves the provider's default
+    pub temperature: Option<f32>,
+    /// `None` uses a per-provider default
+    pub max_output_tokens: Option<u32>,
+    /// Opening of the system prompt, before the tool rules
+    pub system_instruction: String,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            temperature: None,
+            max_output_tokens: None,
+            system_instruction: DEFAULT_SYSTEM_INSTRUCTION.to_string(),
+        }
+    }
+}
+
+impl GenerationConfig {
+    pub fn from_config(config: &AiPanelConfig) -> Self {
+        let system_instruction = config.system_instruction.trim();
+        Self {
+            temperature: config.temperature.map(|t| t.clamp(0.0, 2.0)),
+            max_output_tokens: config.max_output_tokens.filter(|tokens| *tokens > 0),
+            system_instruction: if system_instruction.is_empty() {
+                DEFAULT_SYSTEM_INSTRUCTION.to_string()
+            } else {
+                system_instruction.to_string()
+            },
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -207,16 +406,41 @@ struct StreamingToolCall {
 }
 
 const MAX_AGENT_ROUNDS: usize = 8;
-const MAX_RETRIES: usize = 2;
+const MAX_RETRIES: usize = 3;
+/// Delay before the first retry; doubles on each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
 const DOCUMENT_CHUNK_CHARS: usize = 1_600;
 const MAX_READ_CHUNKS: usize = 8;
 const MAX_READ_CHARS: usize = 12_000;
 const MAX_MAP_CHUNKS: usize = 120;
+const NARRATIVE_MAP_PROMPT: &str = "请通读全文（先用 document_map，再用 read_document 按顺序读取），\
+按情节或论述推进提炼叙事地图：每个节拍一句话。\
+只输出一个 JSON 字符串数组，不要代码围栏或其他说明。\
+每一项写成「概要｜关键短语」，关键短语必须逐字摘自正文、不超过 15 个字，用来在正文中定位该节拍。";
+
+const PROOFREAD_PROMPT: &str = "请校对{scope}，找出错别字、语病、标点和用词不当。\
+只输出一个 JSON 数组，不要代码围栏或其他说明，\
+每项形如 {\"original\": \"有问题的原文片段\", \"suggestion\": \"改正后的片段\", \"reason\": \"简短原因\"}。\
+original 必须逐字摘自正文，尽量短但要能在正文中唯一定位；没有问题就输出 []。";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Local models can take much longer to generate on modest hardware
+const LOCAL_OLLAMA_TIMEOUT: Duration = Duration::from_secs(600);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+const TIMEOUT_MESSAGE: &str = "模型响应超时，Paper Shell 已保留你的问题，可以直接重试";
+const DEFAULT_MAX_PROMPT_CHARS: usize = 12_000;
+
+/// Output limit sent to generic OpenAI-compatible servers
+const DEFAULT_MAX_TOKENS: u32 = 1536;
+const DEFAULT_OLLAMA_NUM_PREDICT: u32 = 768;
 
 struct RawAgentResponse {
     content: String,
     tool_calls: Vec<RawToolCall>,
     finish_reason: Option<String>,
+    usage: Option<TokenUsage>,
 }
 
 #[derive(Debug)]
@@ -227,31 +451,48 @@ struct RoundError {
 }
 
 pub struct AiBackend {
-    provider: String,
+    provider: AiProvider,
     model: String,
     api_url: String,
     api_key: String,
+    timeout: Duration,
+    max_prompt_chars: usize,
+    oversize_strategy: OversizeStrategy,
+    generation: GenerationConfig,
 }
 
 impl Default for AiBackend {
     fn default() -> Self {
         AiBackend {
-            provider: "ollama".to_string(),
-            model: "qwen3:8b".to_string(),
-            api_url: "http://localhost:11434/api/chat".to_string(),
+            provider: AiProvider::Ollama,
+            model: AiProvider::Ollama.default_model().to_string(),
+            api_url: AiProvider::Ollama.default_api_url().to_string(),
             api_key: String::new(),
+            timeout: default_timeout(AiProvider::Ollama.default_api_url()),
+            max_prompt_chars: DEFAULT_MAX_PROMPT_CHARS,
+            oversize_strategy: OversizeStrategy::default(),
+            generation: GenerationConfig::default(),
         }
     }
 }
 
 impl AiBackend {
     pub fn from_config(config: &AiPanelConfig) -> Self {
-        Self::new(
+        let backend = Self::new(
             Some(config.provider.clone()),
             Some(config.model_name.clone()),
             Some(config.api_url.clone()),
             Some(config.api_key.clone()),
-        )
+        );
+        Self {
+            timeout: config
+                .timeout_secs
+                .map_or(backend.timeout, |secs| Duration::from_secs(secs.max(1))),
+            max_prompt_chars: config.max_prompt_chars.max(1),
+            oversize_strategy: config.oversize_strategy,
+            generation: GenerationConfig::from_config(config),
+            ..backend
+        }
     }
 
     pub fn new(
@@ -261,31 +502,91 @@ impl AiBackend {
         api_key: Option<String>,
     ) -> Self {
         let provider = provider
-            .and_then(normalize_provider)
+            .as_deref()
+            .and_then(AiProvider::from_config_value)
             .or_else(|| infer_provider(api_url.as_deref()))
-            .unwrap_or_else(|| "ollama".to_string());
+            .unwrap_or(AiProvider::Ollama);
 
-        let model = model_env_for_provider(&provider)
-            .or_else(|| model.and_then(|model| normalize_model(&provider, model)))
-            .unwrap_or_else(|| default_model_for_provider(&provider));
+        let model = model_env_for_provider(provider)
+            .or_else(|| model.and_then(|model| normalize_model(provider, model)))
+            .unwrap_or_else(|| provider.default_model().to_string());
 
-        let api_url = api_url_env_for_provider(&provider)
-            .or_else(|| api_url.and_then(|api_url| normalize_api_url(&provider, api_url)))
-            .unwrap_or_else(|| default_api_url_for_provider(&provider));
+        let api_url = api_url_env_for_provider(provider)
+            .or_else(|| api_url.and_then(|api_url| normalize_api_url(provider, api_url)))
+            .unwrap_or_else(|| provider.default_api_url().to_string());
 
         let api_key = api_key
             .filter(|s| !s.trim().is_empty())
-            .or_else(|| std::env::var("MOONSHOT_API_KEY").ok())
-            .or_else(|| std::env::var("KIMI_API_KEY").ok())
-            .or_else(|| std::env::var("GEMINI_API_KEY").ok())
+            .or_else(|| api_key_env_for_provider(provider))
             .unwrap_or_default();
 
         Self {
             provider,
             model,
+            timeout: default_timeout(&api_url),
             api_url,
             api_key,
+            max_prompt_chars: DEFAULT_MAX_PROMPT_CHARS,
+            oversize_strategy: OversizeStrategy::default(),
+            generation: GenerationConfig::default(),
+        }
+    }
+
+    /// Why requests cannot be sent with this configuration, if anything
+    pub fn unavailable_reason(&self) -> Option<String> {
+        if requires_api_key(self.provider, &self.api_url) && self.api_key.trim().is_empty() {
+            Some("未设置 API Key，请先在设置中填写".to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Check that the endpoint is reachable and accepts the configured key.
+    ///
+    /// Blocking, so call it off the UI thread; lists models instead of
+    /// running a chat so no tokens are spent.
+    pub fn test_connection(&self) -> Result<String, AiError> {
+        if let Some(reason) = self.unavailable_reason() {
+            return Err(AiError::ConfigError(reason));
         }
+
+        let is_local_ollama = is_local_ollama_url(&self.api_url);
+        let client = build_client(is_local_ollama)?;
+        let mut request = client.get(connection_check_url(self.provider, &self.api_url));
+        if !self.api_key.is_empty() && !is_local_ollama {
+            request = request.bearer_auth(&self.api_key);
+        }
+
+        self.runtime().block_on(async {
+            let response = tokio::time::timeout(CONNECTION_TEST_TIMEOUT, request.send())
+                .await
+                .map_err(|_| AiError::ApiError("连接模型服务超时".to_string()))?
+                .map_err(|e| AiError::ApiError(format!("无法连接模型服务：{}", e)))?;
+
+            let status = response.status();
+            if status.is_success() {
+                Ok(format!("连接成功（{}）", self.model))
+            } else {
+                let body = response.text().await.unwrap_or_default();
+                Err(AiError::ApiError(api_status_error(status, &body)))
+            }
+        })
+    }
+
+    /// The runtime requests run on, started on first use.
+    ///
+    /// One runtime serves every backend, so rebuilding the backend after a
+    /// settings change leaves requests already in flight running.
+    fn runtime(&self) -> &'static Runtime {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name("paper-shell-ai")
+                .enable_all()
+                .build()
+                .expect("failed to start the AI runtime")
+        })
     }
 
     pub fn discuss_writing_context(
@@ -295,26 +596,120 @@ impl AiBackend {
         request_id: AiRequestId,
         sender: Sender<ResponseMessage>,
     ) -> AiRequestHandle {
-        let provider = self.provider.clone();
+        self.spawn_agent(document, conversation, request_id, sender, move |result| {
+            ResponseMessage::AiResponse { request_id, result }
+        })
+    }
+
+    /// Ask the model for a beat-by-beat outline of the whole document.
+    ///
+    /// Replies with `ResponseMessage::NarrativeMapExtracted`; progress events
+    /// carry `request_id` like a normal chat request.
+    pub fn extract_narrative_map(
+        &self,
+        mut document: AiDocumentContext,
+        request_id: AiRequestId,
+        sender: Sender<ResponseMessage>,
+    ) -> AiRequestHandle {
+        document.selection = None;
+        let conversation = vec![AiChatMessage {
+            role: "user".to_string(),
+            content: NARRATIVE_MAP_PROMPT.to_string(),
+        }];
+        self.spawn_agent(document, conversation, request_id, sender, move |result| {
+            ResponseMessage::NarrativeMapExtracted {
+                request_id,
+                result: result.and_then(|response| {
+                    parse_narrative_map(&response.content).map_err(AiError::ApiError)
+                }),
+            }
+        })
+    }
+
+    /// Ask the model for a list of concrete proofreading issues.
+    ///
+    /// Covers the selection when there is one, otherwise the whole document.
+    /// Replies with `ResponseMessage::ProofreadChecked`.
+    pub fn proofread(
+        &self,
+        document: AiDocumentContext,
+        request_id: AiRequestId,
+        sender: Sender<ResponseMessage>,
+    ) -> AiRequestHandle {
+        let scope = if document.selection.is_some() {
+            "当前选区"
+        } else {
+            "全文（先用 document_map，再用 read_document 按顺序读取）"
+        };
+        let conversation = vec![AiChatMessage {
+            role: "user".to_string(),
+            content: PROOFREAD_PROMPT.replace("{scope}", scope),
+        }];
+        self.spawn_agent(document, conversation, request_id, sender, move |result| {
+            ResponseMessage::ProofreadChecked {
+                request_id,
+                result: result.and_then(|response| {
+                    parse_proofread_issues(&response.content).map_err(AiError::ApiError)
+                }),
+            }
+        })
+    }
+
+    fn spawn_agent(
+        &self,
+        document: AiDocumentContext,
+        conversation: Vec<AiChatMessage>,
+        request_id: AiRequestId,
+        sender: Sender<ResponseMessage>,
+        finish: impl FnOnce(Result<AiAgentResponse, AiError>) -> ResponseMessage + Send + 'static,
+    ) -> AiRequestHandle {
+        let provider = self.provider;
         let model = self.model.clone();
         let api_url = self.api_url.clone();
         let api_key = self.api_key.clone();
-        let cancelled = Arc::new(AtomicBool::new(false));
-        let worker_cancelled = Arc::clone(&cancelled);
-
-        thread::spawn(move || {
-            let result = Self::blocking_send_request(
-                provider,
-                model,
-                api_url,
-                api_key,
+        let timeout = self.timeout;
+        let budget = self.max_prompt_chars;
+        let strategy = self.oversize_strategy;
+        let generation = self.generation.clone();
+        let cancelled = CancellationToken::new();
+        let worker_cancelled = cancelled.clone();
+
+        self.runtime().spawn(async move {
+            let agent = send_within_budget(
                 document,
                 conversation,
-                request_id,
-                &sender,
-                &worker_cancelled,
+                budget,
+                strategy,
+                |notice| emit_progress(&sender, request_id, AiProgressEvent::Notice(notice)),
+                |document, conversation| {
+                    let model = model.clone();
+                    let api_url = api_url.clone();
+                    let api_key = api_key.clone();
+                    let generation = generation.clone();
+                    let sender = sender.clone();
+                    async move {
+                        Self::send_request(
+                            provider,
+                            model,
+                            api_url,
+                            api_key,
+                            timeout,
+                            &generation,
+                            document,
+                            conversation,
+                            request_id,
+                            &sender,
+                        )
+                        .await
+                    }
+                },
             );
-            let _ = sender.send(ResponseMessage::AiResponse { request_id, result });
+            // Dropping the request future at its next await stops it
+            let result = worker_cancelled
+                .run_until_cancelled(agent)
+                .await
+                .unwrap_or(Err(AiError::Cancelled));
+            let _ = sender.send(finish(result));
         });
 
         AiRequestHandle {
@@ -324,33 +719,25 @@ impl AiBackend {
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn blocking_send_request(
-        provider: String,
+    async fn send_request(
+        provider: AiProvider,
         model: String,
         api_url: String,
         api_key: String,
+        timeout: Duration,
+        generation: &GenerationConfig,
         document: AiDocumentContext,
         conversation: Vec<AiChatMessage>,
         request_id: AiRequestId,
         sender: &Sender<ResponseMessage>,
-        cancelled: &AtomicBool,
     ) -> Result<AiAgentResponse, AiError> {
         let is_local_ollama = is_local_ollama_url(&api_url);
-        let mut client_builder = Client::builder()
-            .user_agent(concat!("Paper-Shell/", env!("CARGO_PKG_VERSION")))
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(if is_local_ollama { 600 } else { 300 }));
-        if is_local_ollama {
-            client_builder = client_builder.no_proxy();
-        }
-        let client = client_builder
-            .build()
-            .map_err(|e| AiError::ApiError(format!("Failed to build AI client: {}", e)))?;
+        let client = build_client(is_local_ollama)?;
 
         let index = DocumentIndex::new(&document.title, &document.content);
         let mut messages = vec![AiChatMessage {
             role: "system".to_string(),
-            content: system_prompt(&document, &index),
+            content: system_prompt(&generation.system_instruction, &document, &index),
         }];
         let start = conversation.len().saturating_sub(12);
         messages.extend(conversation[start..].iter().cloned());
@@ -365,9 +752,6 @@ impl AiBackend {
         let mut accumulated_content = String::new();
 
         for round in 0..MAX_AGENT_ROUNDS {
-            if cancelled.load(Ordering::Acquire) {
-                return Err(AiError::Cancelled);
-            }
             emit_progress(
                 sender,
                 request_id,
@@ -380,18 +764,30 @@ impl AiBackend {
 
             let response = send_agent_round_with_retry(
                 &client,
-                &provider,
+                provider,
                 &model,
                 &api_url,
                 &api_key,
                 is_local_ollama,
+                timeout,
+                generation,
                 transcript.clone(),
                 tools.clone(),
                 request_id,
                 sender,
-                cancelled,
-            )?;
+            )
+            .await?;
 
+            if let Some(usage) = response.usage {
+                emit_progress(
+                    sender,
+                    request_id,
+                    AiProgressEvent::Usage {
+                        model: model.clone(),
+                        usage,
+                    },
+                );
+            }
             tracing::info!(
                 "AI agent round {} finished: reason={}, content_chars={}, tool_calls={}",
                 round + 1,
@@ -421,16 +817,13 @@ impl AiBackend {
             transcript.push(assistant_tool_message(&response.content, &raw_calls));
             append_agent_content(&mut accumulated_content, &response.content);
             for (raw, invocation) in raw_calls.iter().zip(parsed_calls.iter()) {
-                if cancelled.load(Ordering::Acquire) {
-                    return Err(AiError::Cancelled);
-                }
                 let (result, visible) =
                     execute_invocation(invocation, &index, &mut retrieval, request_id, sender);
                 if let Some(tool) = visible {
                     completed_tools.push(tool);
                 }
                 transcript.push(tool_result_message(
-                    provider == "kimi" || api_url.contains("/chat/completions"),
+                    provider.uses_chat_completions(&api_url),
                     raw,
                     result,
                 ));
@@ -457,6 +850,112 @@ impl AiBackend {
     }
 }
 
+/// Send a request whose selection fits in `budget` characters.
+///
+/// Oversized selections are truncated or split into chunks sent one after
+/// another according to `strategy`; `notify` is told which happened. Chunk
+/// replies are joined in order and their tool calls concatenated.
+async fn send_within_budget<F>(
+    mut document: AiDocumentContext,
+    conversation: Vec<AiChatMessage>,
+    budget: usize,
+    strategy: OversizeStrategy,
+    notify: impl Fn(String),
+    mut send: impl FnMut(AiDocumentContext, Vec<AiChatMessage>) -> F,
+) -> Result<AiAgentResponse, AiError>
+where
+    F: Future<Output = Result<AiAgentResponse, AiError>>,
+{
+    let Some(selection) = document.selection.take() else {
+        return send(document, conversation).await;
+    };
+    let total = selection.text.chars().count();
+    if total <= budget {
+        document.selection = Some(selection);
+        return send(document, conversation).await;
+    }
+
+    match strategy {
+        OversizeStrategy::Truncate => {
+            notify(format!(
+                "选区共 {} 字，超过 {} 字上限，只发送了前 {} 字",
+                total, budget, budget
+            ));
+            let mut truncated = selection;
+            truncated.text = format!(
+                "{}\n…（选区过长，其余 {} 字未发送）",
+                truncated.text.chars().take(budget).collect::<String>(),
+                total - budget
+            );
+            truncated.end_char = truncated.start_char + budget;
+            truncated.context_after.clear();
+            document.selection = Some(truncated);
+            send(document, conversation).await
+        }
+        OversizeStrategy::Chunk => {
+            let chunks = split_into_chunks(&selection.text, budget);
+            notify(format!(
+                "选区共 {} 字，超过 {} 字上限，已分成 {} 段依次处理",
+                total,
+                budget,
+                chunks.len()
+            ));
+            let mut combined = AiAgentResponse {
+                content: String::new(),
+                tool_calls: Vec::new(),
+            };
+            let mut offset = selection.start_char;
+            for (index, chunk) in chunks.iter().enumerate() {
+                let len = chunk.chars().count();
+                let mut part = selection.clone();
+                part.text = chunk.clone();
+                part.start_char = offset;
+                part.end_char = offset + len;
+                if index > 0 {
+                    part.context_before.clear();
+                }
+                if index + 1 < chunks.len() {
+                    part.context_after.clear();
+                }
+                offset += len;
+
+                let mut chunk_document = document.clone();
+                chunk_document.selection = Some(part);
+                let response = send(chunk_document, conversation.clone()).await?;
+                append_agent_content(&mut combined.content, &response.content);
+                combined.tool_calls.extend(response.tool_calls);
+            }
+            Ok(combined)
+        }
+    }
+}
+
+/// Split `text` into pieces of at most `budget` characters, preferring to
+/// break after a line or sentence end in the last fifth of each piece.
+fn split_into_chunks(text: &str, budget: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let budget = budget.max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() {
+        let hard_end = (start + budget).min(chars.len());
+        let end = if hard_end == chars.len() {
+            hard_end
+        } else {
+            let earliest = start + budget - budget / 5;
+            (earliest..hard_end)
+                .rev()
+                .find(|&i| matches!(chars[i - 1], '\n' | '。' | '！' | '？' | '.' | '!' | '?'))
+                .unwrap_or(hard_end)
+        };
+        chunks.push(chars[start..end].iter().collect());
+        start = end;
+    }
+
+    chunks
+}
+
 fn append_agent_content(target: &mut String, content: &str) {
     if content.trim().is_empty() {
         return;
@@ -468,23 +967,21 @@ fn append_agent_content(target: &mut String, content: &str) {
 }
 
 #[allow(clippy::too_many_arguments)]
-fn send_agent_round_with_retry(
+async fn send_agent_round_with_retry(
     client: &Client,
-    provider: &str,
+    provider: AiProvider,
     model: &str,
     api_url: &str,
     api_key: &str,
     is_local_ollama: bool,
+    timeout: Duration,
+    generation: &GenerationConfig,
     messages: Vec<Value>,
     tools: Vec<Value>,
     request_id: AiRequestId,
     sender: &Sender<ResponseMessage>,
-    cancelled: &AtomicBool,
 ) -> Result<RawAgentResponse, AiError> {
     for attempt in 0..=MAX_RETRIES {
-        if cancelled.load(Ordering::Acquire) {
-            return Err(AiError::Cancelled);
-        }
         match send_agent_round(
             client,
             provider,
@@ -492,29 +989,30 @@ fn send_agent_round_with_retry(
             api_url,
             api_key,
             is_local_ollama,
+            Instant::now() + timeout,
+            generation,
             messages.clone(),
             tools.clone(),
             request_id,
             sender,
-            cancelled,
-        ) {
+        )
+        .await
+        {
             Ok(response) => return Ok(response),
-            Err(error) if error.retryable && !error.had_output && attempt < MAX_RETRIES => {
+            Err(error) if should_retry(&error, attempt) => {
                 emit_progress(
                     sender,
                     request_id,
                     AiProgressEvent::Retrying {
                         attempt: attempt + 1,
+                        max_attempts: MAX_RETRIES,
                         reason: error.message.clone(),
                     },
                 );
-                let steps = (attempt + 1) * 10;
-                for _ in 0..steps {
-                    if cancelled.load(Ordering::Acquire) {
-                        return Err(AiError::Cancelled);
-                    }
-                    thread::sleep(Duration::from_millis(100));
-                }
+                let jitter = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0.5, |now| f64::from(now.subsec_nanos() % 1000) / 1000.0);
+                tokio::time::sleep(retry_delay(attempt, jitter)).await;
             }
             Err(error) => return Err(AiError::ApiError(error.message)),
         }
@@ -523,52 +1021,42 @@ fn send_agent_round_with_retry(
     Err(AiError::ApiError("模型请求未完成，请重试".to_string()))
 }
 
+/// Send one chat round and read its streamed reply.
+///
+/// Everything, including reading the stream, has to finish by `deadline`.
 #[allow(clippy::too_many_arguments)]
-fn send_agent_round(
+async fn send_agent_round(
     client: &Client,
-    provider: &str,
+    provider: AiProvider,
     model: &str,
     api_url: &str,
     api_key: &str,
     is_local_ollama: bool,
+    deadline: Instant,
+    generation: &GenerationConfig,
     messages: Vec<Value>,
     tools: Vec<Value>,
     request_id: AiRequestId,
     sender: &Sender<ResponseMessage>,
-    cancelled: &AtomicBool,
 ) -> Result<RawAgentResponse, RoundError> {
     let mut request = client.post(api_url);
     if !api_key.is_empty() && !is_local_ollama {
         request = request.bearer_auth(api_key);
     }
 
-    let is_openai_compatible = provider == "kimi" || api_url.contains("/chat/completions");
-    let response_result = if is_openai_compatible {
-        request
-            .json(&KimiChatRequest {
-                model: model.to_string(),
-                stream: true,
-                max_completion_tokens: max_completion_tokens_for(api_url),
-                messages,
-                tools,
-            })
-            .send()
-    } else {
+    let is_openai_compatible = provider.uses_chat_completions(api_url);
+    let response_result = timeout_at(
+        deadline,
         request
-            .json(&OllamaChatRequest {
-                model: model.to_string(),
-                stream: true,
-                think: false,
-                options: OllamaOptions { num_predict: 768 },
-                messages,
-                tools,
-            })
-            .send()
-    };
+            .json(&provider.chat_request_body(model, api_url, messages, tools, generation))
+            .send(),
+    )
+    .await
+    .map_err(|_| timeout_round_error(false))?;
 
     let response = response_result.map_err(|error| RoundError {
         message: if error.is_timeout() {
-            "模型响应超时，Paper Shell 已保留你的问题，可以直接重试".to_string()
+            TIMEOUT_MESSAGE.to_string()
         } else if error.is_connect() {
             "无法连接到模型服务，请检查地址、网络或本地模型是否已启动".to_string()
         } else {
@@ -580,50 +1068,90 @@ fn send_agent_round(
 
     if !response.status().is_success() {
         let status = response.status();
-        let error_text = response
-            .text()
-            .unwrap_or_else(|_| "unknown error".to_string());
+        let error_text = timeout_at(deadline, response.text())
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or_else(|| "unknown error".to_string());
         return Err(RoundError {
             message: api_status_error(status, &error_text),
-            retryable: status == StatusCode::REQUEST_TIMEOUT
-                || status == StatusCode::TOO_MANY_REQUESTS
-                || status.is_server_error(),
+            retryable: is_retryable_status(status),
             had_output: false,
         });
     }
 
+    let mut lines = StreamLines::new(response, deadline);
     if is_openai_compatible {
-        read_openai_stream(response, request_id, sender, cancelled)
+        read_openai_stream(&mut lines, request_id, sender).await
     } else {
-        read_ollama_stream(response, request_id, sender, cancelled)
+        read_ollama_stream(&mut lines, request_id, sender).await
     }
 }
 
-fn read_openai_stream(
+/// Splits a streamed response body into lines as chunks arrive
+struct StreamLines {
     response: Response,
+    deadline: Instant,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl StreamLines {
+    fn new(response: Response, deadline: Instant) -> Self {
+        Self {
+            response,
+            deadline,
+            buffer: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// The next line without its line break, or `None` at the end of the body
+    async fn next_line(&mut self, had_output: bool) -> Result<Option<String>, RoundError> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                return Ok(Some(
+                    String::from_utf8_lossy(&line[..end])
+                        .trim_end_matches('\r')
+                        .to_string(),
+                ));
+            }
+            if self.finished {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                let rest = std::mem::take(&mut self.buffer);
+                return Ok(Some(String::from_utf8_lossy(&rest).into_owned()));
+            }
+
+            let chunk = timeout_at(self.deadline, self.response.chunk())
+                .await
+                .map_err(|_| timeout_round_error(had_output))?
+                .map_err(|error| RoundError {
+                    message: format!("读取模型流时中断：{}。已生成的内容仍保留在界面中", error),
+                    retryable: true,
+                    had_output,
+                })?;
+            match chunk {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => self.finished = true,
+            }
+        }
+    }
+}
+
+async fn read_openai_stream(
+    lines: &mut StreamLines,
     request_id: AiRequestId,
     sender: &Sender<ResponseMessage>,
-    cancelled: &AtomicBool,
 ) -> Result<RawAgentResponse, RoundError> {
-    let mut reader = BufReader::new(response);
-    let mut line = String::new();
     let mut content = String::new();
     let mut calls = Vec::<StreamingToolCall>::new();
     let mut finish_reason = None;
+    let mut usage = None;
 
-    loop {
-        if cancelled.load(Ordering::Acquire) {
-            return Err(cancelled_round_error(!content.is_empty()));
-        }
-        line.clear();
-        let count = reader.read_line(&mut line).map_err(|error| RoundError {
-            message: format!("读取模型流时中断：{}。已生成的内容仍保留在界面中", error),
-            retryable: true,
-            had_output: !content.is_empty(),
-        })?;
-        if count == 0 {
-            break;
-        }
+    while let Some(line) = lines.next_line(!content.is_empty()).await? {
         let Some(payload) = openai_sse_payload(&line) else {
             continue;
         };
@@ -642,6 +1170,10 @@ fn read_openai_stream(
                 had_output: !content.is_empty(),
             });
         }
+        // The usage chunk usually has no choices
+        if let Some(reported) = TokenUsage::from_response(&value) {
+            usage = Some(reported);
+        }
         let Some(choice) = value
             .get("choices")
             .and_then(Value::as_array)
@@ -679,6 +1211,7 @@ fn read_openai_stream(
         content,
         tool_calls: finish_streaming_tools(calls),
         finish_reason,
+        usage,
     })
 }
 
@@ -690,29 +1223,17 @@ fn openai_sse_payload(line: &str) -> Option<&str> {
     trimmed.strip_prefix("data:").map(str::trim)
 }
 
-fn read_ollama_stream(
-    response: Response,
+async fn read_ollama_stream(
+    lines: &mut StreamLines,
     request_id: AiRequestId,
     sender: &Sender<ResponseMessage>,
-    cancelled: &AtomicBool,
 ) -> Result<RawAgentResponse, RoundError> {
-    let reader = BufReader::new(response);
     let mut content = String::new();
     let mut calls = Vec::<StreamingToolCall>::new();
     let mut finish_reason = None;
+    let mut usage = None;
 
-    for line in reader.lines() {
-        if cancelled.load(Ordering::Acquire) {
-            return Err(cancelled_round_error(!content.is_empty()));
-        }
-        let line = line.map_err(|error| RoundError {
-            message: format!(
-                "读取本地模型流时中断：{}。已生成的内容仍保留在界面中",
-                error
-            ),
-            retryable: true,
-            had_output: !content.is_empty(),
-        })?;
+    while let Some(line) = lines.next_line(!content.is_empty()).await? {
         if line.trim().is_empty() {
             continue;
         }
@@ -731,6 +1252,9 @@ fn read_ollama_stream(
         if let Some(reason) = value.get("done_reason").and_then(Value::as_str) {
             finish_reason = Some(reason.to_string());
         }
+        if let Some(reported) = TokenUsage::from_response(&value) {
+            usage = Some(reported);
+        }
         if let Some(message) = value.get("message") {
             if let Some(text) = message.get("content").and_then(Value::as_str) {
                 push_delta(&mut content, text, request_id, sender);
@@ -745,6 +1269,7 @@ fn read_ollama_stream(
         content,
         tool_calls: finish_streaming_tools(calls),
         finish_reason,
+        usage,
     })
 }
 
@@ -843,20 +1368,60 @@ fn finish_streaming_tools(calls: Vec<StreamingToolCall>) -> Vec<RawToolCall> {
         .collect()
 }
 
-fn cancelled_round_error(had_output: bool) -> RoundError {
+/// Whether a failed round is worth sending again.
+///
+/// Only failures before any output arrived are retried, so a partially
+/// streamed reply is never duplicated.
+fn should_retry(error: &RoundError, attempt: usize) -> bool {
+    error.retryable && !error.had_output && attempt < MAX_RETRIES
+}
+
+/// Rate limiting, request timeouts and server errors are transient
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+/// Exponential backoff for retry `attempt` (0-based), scaled by a jitter
+/// factor in `[0, 1)` to between 75% and 125% of the nominal delay.
+fn retry_delay(attempt: usize, jitter: f64) -> Duration {
+    let nominal = RETRY_BASE_DELAY
+        .saturating_mul(1u32 << attempt.min(16))
+        .min(RETRY_MAX_DELAY);
+    nominal.mul_f64(0.75 + 0.5 * jitter.clamp(0.0, 1.0))
+}
+
+fn timeout_round_error(had_output: bool) -> RoundError {
     RoundError {
-        message: "请求已停止".to_string(),
-        retryable: false,
+        message: TIMEOUT_MESSAGE.to_string(),
+        retryable: true,
         had_output,
     }
 }
 
+fn build_client(is_local_ollama: bool) -> Result<Client, AiError> {
+    let mut client_builder = Client::builder()
+        .user_agent(concat!("Paper-Shell/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(CONNECT_TIMEOUT);
+    if is_local_ollama {
+        client_builder = client_builder.no_proxy();
+    }
+    client_builder
+        .build()
+        .map_err(|e| AiError::ApiError(format!("Failed to build AI client: {}", e)))
+}
+
 fn api_status_error(status: StatusCode, body: &str) -> String {
-    let detail = truncate_chars(body.trim(), 360);
+    let detail = truncate_chars(&api_error_detail(body), 360);
     match status {
-        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN if detail.is_empty() => {
             "模型服务拒绝了凭证，请检查 API Key 和服务地址".to_string()
         }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            format!("模型服务拒绝了凭证，请检查 API Key 和服务地址：{}", detail)
+        }
+        StatusCode::BAD_REQUEST => format!("模型服务无法处理这次请求（400）：{}", detail),
         StatusCode::TOO_MANY_REQUESTS => {
             "模型服务当前请求过多，Paper Shell 会自动重试；稍后也可以手动重试".to_string()
         }
@@ -873,7 +1438,25 @@ fn api_status_error(status: StatusCode, body: &str) -> String {
     }
 }
 
-fn max_completion_tokens_for(api_url: &str) -> i32 {
+/// The human-readable part of an error body.
+///
+/// OpenAI-style servers send `{"error": {"message": ...}}`, Ollama sends
+/// `{"error": "..."}`; anything else is returned as-is.
+fn api_error_detail(body: &str) -> String {
+    let body = body.trim();
+    let Ok(value) = serde_json::from_str::<Value>(body) else {
+        return body.to_string();
+    };
+    let error = &value["error"];
+    error["message"]
+        .as_str()
+        .or_else(|| error.as_str())
+        .or_else(|| value["message"].as_str())
+        .map(|message| message.trim().to_string())
+        .unwrap_or_else(|| body.to_string())
+}
+
+fn max_completion_tokens_for(api_url: &str) -> u32 {
     if api_url.contains("api.kimi.com/coding/") {
         4096
     } else {
@@ -1297,19 +1880,25 @@ fn push_document_chunk(
     });
 }
 
-fn system_prompt(document: &AiDocumentContext, index: &DocumentIndex) -> String {
+fn system_prompt(
+    system_instruction: &str,
+    document: &AiDocumentContext,
+    index: &DocumentIndex,
+) -> String {
     let selection = document.selection.as_ref().map(|selection| {
         json!({
             "anchor_id": selection.anchor_id,
             "start_char": selection.start_char,
             "end_char": selection.end_char,
-            "text": truncate_chars(&selection.text, 12_000),
-            "truncated": selection.text.chars().count() > 12_000,
+            "text": selection.text,
+            "context_before": selection.context_before,
+            "context_after": selection.context_after,
         })
         .to_string()
     });
     format!(
-        "你是 Paper Shell 里的写作伙伴和受限执行代理。文档属于用户，正文始终是主角。\n\n\
+        "{}\n\n\
+你也是受限执行代理，只能通过下列工具读取文档或提出修改。\n\n\
 基本原则：\n\
 - 默认通过讨论、反问、辨析和反馈帮助思考，不主动代写。\n\
 - 除了用户明确提供的当前选区，正文没有直接放进提示词。需要文档依据时，先用 document_map、search_document、read_document 按需读取。\n\
@@ -1320,11 +1909,12 @@ fn system_prompt(document: &AiDocumentContext, index: &DocumentIndex) -> String
 - 不得声称修改已应用。是否执行以界面状态为准。\n\n\
 工作方式：\n\
 - 普通回复简洁自然，优先给出最有用的观察，通常不超过 300 个中文字。\n\
-- 有当前选区时优先围绕选区回答，同时可检索全文补充相关上下文。\n\
+- 有当前选区时只处理选区，context_before/context_after 仅供理解语境，不要修改它们；确有必要时再检索全文。\n\
 - 没有文档依据时明确说明，不要猜测正文。\n\n\
 <document_metadata>\n\
 title={}\nchars={}\nlines={}\nchunks={}\nselection={}\n\
 </document_metadata>",
+        system_instruction,
         serde_json::to_string(&index.title).unwrap_or_else(|_| "\"未命名文档\"".to_string()),
         index.total_chars,
         index.total_lines,
@@ -1491,6 +2081,121 @@ fn normalize_mermaid_source(source: &str) -> String {
         .to_string()
 }
 
+/// Separates a beat's summary from the verbatim phrase used to locate it
+pub const NARRATIVE_BEAT_SEPARATOR: char = '｜';
+
+/// Parse the model's narrative map reply into a list of beats.
+///
+/// Accepts a JSON array of strings, optionally inside a code fence or
+/// surrounded by prose; falls back to numbered or bulleted lines.
+pub fn parse_narrative_map(reply: &str) -> Result<Vec<String>, String> {
+    let source = reply.trim();
+    let beats = first_json_array(source)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::String(text) => Some(text.trim().to_string()),
+                    Value::Object(fields) => fields
+                        .values()
+                        .find_map(Value::as_str)
+                        .map(|text| text.trim().to_string()),
+                    _ => None,
+                })
+                .filter(|beat| !beat.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|beats| !beats.is_empty())
+        .unwrap_or_else(|| list_item_lines(source));
+
+    if beats.is_empty() {
+        Err("模型没有返回可识别的叙事地图，请重试".to_string())
+    } else {
+        Ok(beats)
+    }
+}
+
+/// The first complete JSON array in `text`, ignoring fences and prose around it
+fn first_json_array(text: &str) -> Option<Vec<Value>> {
+    text.char_indices()
+        .filter(|(_, c)| *c == '[')
+        .find_map(|(start, _)| {
+            serde_json::Deserializer::from_str(&text[start..])
+                .into_iter::<Vec<Value>>()
+                .next()
+                .and_then(Result::ok)
+        })
+}
+
+/// Parse the model's proofreading reply into issues.
+///
+/// Expects a JSON array of `{original, suggestion, reason}` objects, possibly
+/// fenced or wrapped in prose. Common alternative key names are accepted, and
+/// items without usable `original`/`suggestion` strings are dropped. An empty
+/// array means nothing was found; a reply with no array at all is an error.
+pub fn parse_proofread_issues(reply: &str) -> Result<Vec<ProofreadIssue>, String> {
+    let source = reply.trim();
+    let items = first_json_array(source)
+        .or_else(|| {
+            // A lone object instead of a one-item array
+            let start = source.find('{')?;
+            serde_json::Deserializer::from_str(&source[start..])
+                .into_iter::<Value>()
+                .next()
+                .and_then(Result::ok)
+                .filter(Value::is_object)
+                .map(|item| vec![item])
+        })
+        .ok_or_else(|| "模型没有返回可识别的校对结果，请重试".to_string())?;
+
+    let field = |item: &Value, keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| item.get(key).and_then(Value::as_str))
+            .map(|text| text.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    Ok(items
+        .iter()
+        .filter(|item| item.is_object())
+        .map(|item| ProofreadIssue {
+            original: field(item, &["original", "original_text", "text", "原文"]),
+            suggestion: field(
+                item,
+                &[
+                    "suggestion",
+                    "replacement",
+                    "replacement_text",
+                    "correction",
+                    "建议",
+                    "修改",
+                ],
+            ),
+            reason: field(item, &["reason", "explanation", "说明", "原因"]),
+        })
+        .filter(|issue| !issue.original.is_empty() && issue.original != issue.suggestion)
+        .collect())
+}
+
+fn list_item_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let rest = line
+                .strip_prefix("- ")
+                .or_else(|| line.strip_prefix("* "))
+                .or_else(|| {
+                    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+                    (digits > 0)
+                        .then(|| &line[digits..])
+                        .and_then(|rest| rest.strip_prefix('.').or(rest.strip_prefix('、')))
+                })?;
+            let rest = rest.trim();
+            (!rest.is_empty()).then(|| rest.to_string())
+        })
+        .collect()
+}
+
 fn default_mindmap_title() -> String {
     "文档脑图".to_string()
 }
@@ -1499,30 +2204,24 @@ fn default_tool_call_type() -> String {
     "function".to_string()
 }
 
-fn normalize_provider(provider: String) -> Option<String> {
-    match provider.trim().to_ascii_lowercase().as_str() {
-        "kimi" | "moonshot" => Some("kimi".to_string()),
-        "ollama" => Some("ollama".to_string()),
-        _ => None,
-    }
-}
-
-fn infer_provider(api_url: Option<&str>) -> Option<String> {
+fn infer_provider(api_url: Option<&str>) -> Option<AiProvider> {
     let api_url = api_url?;
-    if api_url.contains("moonshot.ai") || api_url.contains("/chat/completions") {
-        Some("kimi".to_string())
+    if api_url.contains("moonshot.ai") || api_url.contains("moonshot.cn") {
+        Some(AiProvider::Kimi)
     } else if is_local_ollama_url(api_url) {
-        Some("ollama".to_string())
+        Some(AiProvider::Ollama)
+    } else if api_url.contains("/chat/completions") {
+        Some(AiProvider::OpenAiCompatible)
     } else {
         None
     }
 }
 
-fn normalize_model(provider: &str, model: String) -> Option<String> {
+fn normalize_model(provider: AiProvider, model: String) -> Option<String> {
     let trimmed = model.trim();
     if trimmed.is_empty()
         || trimmed.starts_with("gemini-")
-        || (provider == "ollama" && trimmed.starts_with("kimi-"))
+        || (provider == AiProvider::Ollama && trimmed.starts_with("kimi-"))
     {
         None
     } else {
@@ -1530,12 +2229,12 @@ fn normalize_model(provider: &str, model: String) -> Option<String> {
     }
 }
 
-fn normalize_api_url(provider: &str, api_url: String) -> Option<String> {
+fn normalize_api_url(provider: AiProvider, api_url: String) -> Option<String> {
     let trimmed = api_url.trim();
     if trimmed.is_empty()
         || trimmed.contains("generativelanguage.googleapis.com")
         || trimmed.contains(":generateContent")
-        || (provider == "ollama" && trimmed.contains("moonshot.ai"))
+        || (provider == AiProvider::Ollama && trimmed.contains("moonshot.ai"))
     {
         None
     } else {
@@ -1543,51 +2242,584 @@ fn normalize_api_url(provider: &str, api_url: String) -> Option<String> {
     }
 }
 
-fn is_local_ollama_url(api_url: &str) -> bool {
-    api_url.contains("localhost:11434") || api_url.contains("127.0.0.1:11434")
+fn requires_api_key(provider: AiProvider, api_url: &str) -> bool {
+    match provider {
+        AiProvider::Ollama => false,
+        AiProvider::Kimi => !is_local_ollama_url(api_url),
+        // Local servers such as LM Studio usually run without a key
+        AiProvider::OpenAiCompatible => !is_loopback_url(api_url),
+    }
 }
 
-fn default_model_for_provider(provider: &str) -> String {
-    match provider {
-        "kimi" => "kimi-k2.7-code".to_string(),
-        _ => "qwen3:8b".to_string(),
+/// A cheap authenticated GET endpoint next to the chat endpoint
+fn connection_check_url(provider: AiProvider, api_url: &str) -> String {
+    let trimmed = api_url.trim_end_matches('/');
+    if provider.uses_chat_completions(trimmed) {
+        match trimmed.strip_suffix("/chat/completions") {
+            Some(base) => format!("{}/models", base),
+            None => trimmed.to_string(),
+        }
+    } else {
+        match trimmed.strip_suffix("/api/chat") {
+            Some(base) => format!("{}/api/tags", base),
+            None => format!("{}/api/tags", trimmed),
+        }
     }
 }
 
-fn default_api_url_for_provider(provider: &str) -> String {
-    match provider {
-        "kimi" => "https://api.moonshot.ai/v1/chat/completions".to_string(),
-        _ => "http://localhost:11434/api/chat".to_string(),
+/// How long a request to `api_url` may take when no timeout is set
+fn default_timeout(api_url: &str) -> Duration {
+    if is_local_ollama_url(api_url) {
+        LOCAL_OLLAMA_TIMEOUT
+    } else {
+        DEFAULT_TIMEOUT
     }
 }
 
-fn model_env_for_provider(provider: &str) -> Option<String> {
+fn is_local_ollama_url(api_url: &str) -> bool {
+    api_url.contains("localhost:11434") || api_url.contains("127.0.0.1:11434")
+}
+
+fn is_loopback_url(api_url: &str) -> bool {
+    let rest = api_url.split_once("://").map_or(api_url, |(_, rest)| rest);
+    rest.starts_with("localhost") || rest.starts_with("127.") || rest.starts_with("[::1]")
+}
+
+fn model_env_for_provider(provider: AiProvider) -> Option<String> {
     match provider {
-        "kimi" => std::env::var("KIMI_MODEL")
+        AiProvider::Kimi => std::env::var("KIMI_MODEL")
             .ok()
             .or_else(|| std::env::var("MOONSHOT_MODEL").ok())
             .or_else(|| std::env::var("PAPER_SHELL_AI_MODEL").ok()),
-        _ => std::env::var("OLLAMA_MODEL")
+        AiProvider::OpenAiCompatible => std::env::var("OPENAI_MODEL")
+            .ok()
+            .or_else(|| std::env::var("PAPER_SHELL_AI_MODEL").ok()),
+        AiProvider::Ollama => std::env::var("OLLAMA_MODEL")
             .ok()
             .or_else(|| std::env::var("PAPER_SHELL_AI_MODEL").ok()),
     }
 }
 
-fn api_url_env_for_provider(provider: &str) -> Option<String> {
+fn api_url_env_for_provider(provider: AiProvider) -> Option<String> {
     match provider {
-        "kimi" => std::env::var("KIMI_API_URL")
+        AiProvider::Kimi => std::env::var("KIMI_API_URL")
             .ok()
             .or_else(|| std::env::var("MOONSHOT_API_URL").ok())
             .or_else(|| std::env::var("PAPER_SHELL_AI_API_URL").ok()),
-        _ => std::env::var("OLLAMA_API_URL")
+        AiProvider::OpenAiCompatible => std::env::var("OPENAI_API_URL")
+            .ok()
+            .or_else(|| {
+                std::env::var("OPENAI_BASE_URL")
+                    .ok()
+                    .map(|base| format!("{}/chat/completions", base.trim_end_matches('/')))
+            })
+            .or_else(|| std::env::var("PAPER_SHELL_AI_API_URL").ok()),
+        AiProvider::Ollama => std::env::var("OLLAMA_API_URL")
             .ok()
             .or_else(|| std::env::var("PAPER_SHELL_AI_API_URL").ok()),
     }
 }
 
+fn api_key_env_for_provider(provider: AiProvider) -> Option<String> {
+    match provider {
+        AiProvider::OpenAiCompatible => std::env::var("OPENAI_API_KEY").ok(),
+        AiProvider::Kimi | AiProvider::Ollama => std::env::var("MOONSHOT_API_KEY")
+            .ok()
+            .or_else(|| std::env::var("KIMI_API_KEY").ok())
+            .or_else(|| std::env::var("GEMINI_API_KEY").ok()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{self, Receiver};
+
+    #[test]
+    fn parses_fenced_narrative_map_with_trailing_prose() {
+        let reply = "```json\n[\"开场｜雨夜\", \"冲突｜他推开门\"]\n```\n以上是全文节拍。";
+        assert_eq!(
+            parse_narrative_map(reply).unwrap(),
+            vec!["开场｜雨夜".to_string(), "冲突｜他推开门".to_string()]
+        );
+
+        let prose = "好的，叙事地图如下：[\"a\", \"\", \"b\"] 希望有帮助 [1]";
+        assert_eq!(parse_narrative_map(prose).unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn narrative_map_falls_back_to_list_lines() {
+        let reply = "1. 主角登场\n2、 危机出现\n- 结局\n说明文字";
+        assert_eq!(
+            parse_narrative_map(reply).unwrap(),
+            vec!["主角登场", "危机出现", "结局"]
+        );
+        assert!(parse_narrative_map("抱歉，我无法完成").is_err());
+    }
+
+    #[test]
+    fn parses_fenced_proofread_issues_with_prose() {
+        let reply = "找到两处问题：\n```json\n[\n  {\"original\": \"在次\", \"suggestion\": \"再次\", \"reason\": \"错别字\"},\n  {\"original\": \"非常的好\", \"suggestion\": \"非常好\"}\n]\n```\n以上。";
+        let issues = parse_proofread_issues(reply).unwrap();
+
+        assert_eq!(
+            issues,
+            vec![
+                ProofreadIssue {
+                    original: "在次".to_string(),
+                    suggestion: "再次".to_string(),
+                    reason: "错别字".to_string(),
+                },
+                ProofreadIssue {
+                    original: "非常的好".to_string(),
+                    suggestion: "非常好".to_string(),
+                    reason: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn proofread_parsing_drops_unusable_items() {
+        // Alternative keys, a bare string, a missing original, a no-op
+        // suggestion and a non-string original
+        let reply = r#"[
+            {"原文": "的地得", "建议": "得", "原因": "助词"},
+            "随便一句话",
+            {"suggestion": "无原文"},
+            {"original": "不变", "suggestion": "不变"},
+            {"original": 42, "suggestion": "数字"},
+            {"original": "重复重复", "replacement": "重复"}
+        ]"#;
+        let issues = parse_proofread_issues(reply).unwrap();
+
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].original, "的地得");
+        assert_eq!(issues[0].reason, "助词");
+        assert_eq!(issues[1].suggestion, "重复");
+    }
+
+    #[test]
+    fn proofread_parsing_handles_malformed_replies() {
+        assert_eq!(parse_proofread_issues("[]").unwrap(), Vec::new());
+        // A single object instead of an array
+        let single = parse_proofread_issues(r#"{"original": "a", "suggestion": "b"}"#).unwrap();
+        assert_eq!(single.len(), 1);
+        // Brackets in prose before the real array are skipped
+        let issues = parse_proofread_issues(
+            r#"见 [注1]：[{"original": "x", "suggestion": "y", "reason": "r"}]"#,
+        )
+        .unwrap();
+        assert_eq!(issues[0].original, "x");
+
+        assert!(parse_proofread_issues("全文没有明显问题。").is_err());
+        assert!(parse_proofread_issues(r#"[{"original": "截断"#).is_err());
+        assert!(parse_proofread_issues("").is_err());
+    }
+
+    #[test]
+    fn retries_only_transient_failures_before_output() {
+        let error = |retryable, had_output| RoundError {
+            message: String::new(),
+            retryable,
+            had_output,
+        };
+
+        assert!(should_retry(&error(true, false), 0));
+        assert!(should_retry(&error(true, false), MAX_RETRIES - 1));
+        assert!(!should_retry(&error(true, false), MAX_RETRIES));
+        assert!(!should_retry(&error(false, false), 0));
+        assert!(!should_retry(&error(true, true), 0));
+
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable_status(StatusCode::REQUEST_TIMEOUT));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn retry_delay_backs_off_exponentially_with_jitter() {
+        assert_eq!(retry_delay(0, 0.5), Duration::from_secs(1));
+        assert_eq!(retry_delay(1, 0.5), Duration::from_secs(2));
+        assert_eq!(retry_delay(2, 0.5), Duration::from_secs(4));
+        assert_eq!(retry_delay(10, 0.5), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(1, 0.0), Duration::from_millis(1500));
+        assert_eq!(retry_delay(1, 1.0), Duration::from_millis(2500));
+    }
+
+    fn selection_document(text: &str) -> AiDocumentContext {
+        AiDocumentContext {
+            title: "t".to_string(),
+            content: text.to_string(),
+            selection: Some(AiSelectionContext {
+                anchor_id: 1,
+                start_char: 0,
+                end_char: text.chars().count(),
+                text: text.to_string(),
+                context_before: String::new(),
+                context_after: "后文".to_string(),
+            }),
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn truncates_oversized_selection_and_says_so() {
+        let notices = std::cell::RefCell::new(Vec::new());
+        let mut sent = Vec::new();
+        block_on(send_within_budget(
+            selection_document("一二三四五六"),
+            Vec::new(),
+            4,
+            OversizeStrategy::Truncate,
+            |notice| notices.borrow_mut().push(notice),
+            |document, _| {
+                sent.push(document.selection.unwrap());
+                std::future::ready(Ok(AiAgentResponse {
+                    content: "ok".to_string(),
+                    tool_calls: Vec::new(),
+                }))
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].text.starts_with("一二三四\n…"));
+        assert_eq!(sent[0].end_char, 4);
+        assert!(notices.borrow()[0].contains("只发送了前 4 字"));
+    }
+
+    #[test]
+    fn chunks_oversized_selection_and_joins_replies() {
+        let mut sent = Vec::new();
+        let response = block_on(send_within_budget(
+            selection_document("第一句。第二句。第三句。"),
+            Vec::new(),
+            8,
+            OversizeStrategy::Chunk,
+            |_| {},
+            |document, _| {
+                let selection = document.selection.unwrap();
+                let reply = format!("[{}]", selection.text);
+                sent.push((selection.start_char, selection.end_char));
+                std::future::ready(Ok(AiAgentResponse {
+                    content: reply,
+                    tool_calls: Vec::new(),
+                }))
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(sent, vec![(0, 8), (8, 12)]);
+        assert_eq!(response.content, "[第一句。第二句。]\n\n[第三句。]");
+    }
+
+    #[test]
+    fn small_selection_is_sent_unchanged() {
+        let mut calls = 0;
+        block_on(send_within_budget(
+            selection_document("短"),
+            Vec::new(),
+            10,
+            OversizeStrategy::Chunk,
+            |_| panic!("no notice expected"),
+            |document, _| {
+                calls += 1;
+                assert_eq!(document.selection.unwrap().text, "短");
+                std::future::ready(Ok(AiAgentResponse {
+                    content: String::new(),
+                    tool_calls: Vec::new(),
+                }))
+            },
+        ))
+        .unwrap();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn split_into_chunks_prefers_sentence_breaks() {
+        assert_eq!(
+            split_into_chunks("abcd. efghij", 8),
+            vec!["abcd. ef".to_string(), "ghij".to_string()]
+        );
+        assert_eq!(
+            split_into_chunks("一二三。四五六七八九十", 5),
+            vec!["一二三。", "四五六七八", "九十"]
+        );
+        assert!(split_into_chunks("", 5).is_empty());
+    }
+
+    #[test]
+    fn connection_check_targets_model_listing() {
+        assert_eq!(
+            connection_check_url(
+                AiProvider::Kimi,
+                "https://api.moonshot.ai/v1/chat/completions"
+            ),
+            "https://api.moonshot.ai/v1/models"
+        );
+        assert_eq!(
+            connection_check_url(AiProvider::Ollama, "http://localhost:11434/api/chat"),
+            "http://localhost:11434/api/tags"
+        );
+    }
+
+    #[test]
+    fn remote_provider_without_key_is_unavailable() {
+        let backend = AiBackend {
+            provider: AiProvider::Kimi,
+            model: "kimi-k2.7-code".to_string(),
+            api_url: "https://api.moonshot.ai/v1/chat/completions".to_string(),
+            api_key: String::new(),
+            ..AiBackend::default()
+        };
+        assert!(backend.unavailable_reason().is_some());
+        assert!(AiBackend::default().unavailable_reason().is_none());
+
+        assert!(requires_api_key(
+            AiProvider::OpenAiCompatible,
+            "https://api.deepseek.com/chat/completions"
+        ));
+        assert!(!requires_api_key(
+            AiProvider::OpenAiCompatible,
+            "http://localhost:1234/v1/chat/completions"
+        ));
+    }
+
+    #[test]
+    fn serializes_ollama_chat_request() {
+        let body = AiProvider::Ollama.chat_request_body(
+            "qwen3:8b",
+            "http://localhost:11434/api/chat",
+            vec![json!({"role": "user", "content": "你好"})],
+            vec![json!({"type": "function"})],
+            &GenerationConfig::default(),
+        );
+
+        assert_eq!(
+            body,
+            json!({
+                "model": "qwen3:8b",
+                "messages": [{"role": "user", "content": "你好"}],
+                "tools": [{"type": "function"}],
+                "stream": true,
+                "think": false,
+                "options": {"num_predict": 768}
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_openai_compatible_chat_request() {
+        let body = AiProvider::OpenAiCompatible.chat_request_body(
+            "deepseek-chat",
+            "https://api.deepseek.com/chat/completions",
+            vec![json!({"role": "user", "content": "hi"})],
+            Vec::new(),
+            &GenerationConfig::default(),
+        );
+
+        assert_eq!(
+            body,
+            json!({
+                "model": "deepseek-chat",
+                "messages": [{"role": "user", "content": "hi"}],
+                "stream": true,
+                "max_tokens": 1536,
+                "stream_options": {"include_usage": true}
+            })
+        );
+
+        let kimi = AiProvider::Kimi.chat_request_body(
+            "kimi-k2.7-code",
+            "https://api.kimi.com/coding/v1/chat/completions",
+            vec![json!({"role": "user", "content": "hi"})],
+            vec![json!({"type": "function"})],
+            &GenerationConfig::default(),
+        );
+
+        assert_eq!(
+            kimi,
+            json!({
+                "model": "kimi-k2.7-code",
+                "messages": [{"role": "user", "content": "hi"}],
+                "tools": [{"type": "function"}],
+                "stream": true,
+                "max_completion_tokens": 4096
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_generation_settings_for_each_provider() {
+        let generation = GenerationConfig {
+            temperature: Some(0.5),
+            max_output_tokens: Some(2000),
+            system_instruction: "你是编辑".to_string(),
+        };
+        let messages = || vec![json!({"role": "user", "content": "hi"})];
+
+        assert_eq!(
+            AiProvider::Ollama.chat_request_body(
+                "qwen3:8b",
+                "http://localhost:11434/api/chat",
+                messages(),
+                Vec::new(),
+                &generation,
+            ),
+            json!({
+                "model": "qwen3:8b",
+                "messages": [{"role": "user", "content": "hi"}],
+                "tools": [],
+                "stream": true,
+                "think": false,
+                "options": {"num_predict": 2000, "temperature": 0.5}
+            })
+        );
+        assert_eq!(
+            AiProvider::OpenAiCompatible.chat_request_body(
+                "gpt-4o-mini",
+                "https://api.openai.com/v1/chat/completions",
+                messages(),
+                Vec::new(),
+                &generation,
+            ),
+            json!({
+                "model": "gpt-4o-mini",
+                "messages": [{"role": "user", "content": "hi"}],
+                "stream": true,
+                "temperature": 0.5,
+                "max_tokens": 2000,
+                "stream_options": {"include_usage": true}
+            })
+        );
+        assert_eq!(
+            AiProvider::Kimi.chat_request_body(
+                "kimi-k2.7-code",
+                "https://api.kimi.com/coding/v1/chat/completions",
+                messages(),
+                Vec::new(),
+                &generation,
+            ),
+            json!({
+                "model": "kimi-k2.7-code",
+                "messages": [{"role": "user", "content": "hi"}],
+                "stream": true,
+                "temperature": 0.5,
+                "max_completion_tokens": 2000
+            })
+        );
+    }
+
+    #[test]
+    fn local_ollama_gets_the_longer_default_timeout() {
+        assert_eq!(
+            default_timeout(AiProvider::Ollama.default_api_url()),
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            default_timeout(AiProvider::Kimi.default_api_url()),
+            Duration::from_secs(300)
+        );
+    }
+
+    #[test]
+    fn generation_config_falls_back_to_defaults() {
+        let config = AiPanelConfig {
+            temperature: Some(5.0),
+            max_output_tokens: Some(0),
+            system_instruction: "  ".to_string(),
+            ..AiPanelConfig::default()
+        };
+        let generation = GenerationConfig::from_config(&config);
+
+        assert_eq!(generation.temperature, Some(2.0));
+        assert_eq!(generation.max_output_tokens, None);
+        assert_eq!(generation.system_instruction, DEFAULT_SYSTEM_INSTRUCTION);
+
+        let index = DocumentIndex::new("t", "正文");
+        let document = AiDocumentContext {
+            title: "t".to_string(),
+            content: "正文".to_string(),
+            selection: None,
+        };
+        assert!(
+            system_prompt("你是一位严格的编辑。", &document, &index)
+                .starts_with("你是一位严格的编辑。\n\n")
+        );
+    }
+
+    #[test]
+    fn ollama_openai_endpoint_uses_chat_completions_format() {
+        let body = AiProvider::Ollama.chat_request_body(
+            "qwen3:8b",
+            "http://localhost:11434/v1/chat/completions",
+            Vec::new(),
+            Vec::new(),
+            &GenerationConfig::default(),
+        );
+        assert_eq!(body["max_tokens"], 1536);
+        assert!(body.get("options").is_none());
+    }
+
+    #[test]
+    fn resolves_provider_from_settings_and_url() {
+        assert_eq!(
+            AiProvider::from_config_value("OpenAI"),
+            Some(AiProvider::OpenAiCompatible)
+        );
+        assert_eq!(
+            AiProvider::from_config_value("moonshot"),
+            Some(AiProvider::Kimi)
+        );
+        assert_eq!(AiProvider::from_config_value("gemini"), None);
+        for provider in AiProvider::ALL {
+            assert_eq!(
+                AiProvider::from_config_value(provider.config_value()),
+                Some(provider)
+            );
+        }
+
+        assert_eq!(
+            infer_provider(Some("https://openrouter.ai/api/v1/chat/completions")),
+            Some(AiProvider::OpenAiCompatible)
+        );
+        assert_eq!(
+            infer_provider(Some("https://api.moonshot.ai/v1/chat/completions")),
+            Some(AiProvider::Kimi)
+        );
+    }
+
+    #[test]
+    fn maps_error_bodies_to_their_message() {
+        let openai =
+            r#"{"error": {"message": "Model not found", "type": "invalid_request_error"}}"#;
+        assert_eq!(
+            api_status_error(StatusCode::NOT_FOUND, openai),
+            "模型服务返回 404 Not Found：Model not found"
+        );
+
+        let ollama = r#"{"error": "model 'llama9' not found"}"#;
+        assert!(
+            api_status_error(StatusCode::NOT_FOUND, ollama).ends_with("model 'llama9' not found")
+        );
+
+        assert!(
+            api_status_error(StatusCode::BAD_GATEWAY, "<html>bad gateway</html>")
+                .contains("<html>bad gateway</html>")
+        );
+        assert!(api_status_error(StatusCode::UNAUTHORIZED, openai).contains("API Key"));
+    }
 
     #[test]
     fn parses_ollama_object_tool_arguments() {
@@ -1746,13 +2978,16 @@ mod tests {
                 start_char: 0,
                 end_char: 2,
                 text: "选区内容".to_string(),
+                context_before: "前一句。".to_string(),
+                context_after: String::new(),
             }),
         };
         let index = DocumentIndex::new(&document.title, &document.content);
-        let prompt = system_prompt(&document, &index);
+        let prompt = system_prompt(DEFAULT_SYSTEM_INSTRUCTION, &document, &index);
 
         assert!(!prompt.contains("正文秘密"));
         assert!(prompt.contains("选区内容"));
+        assert!(prompt.contains("前一句。"));
         assert!(prompt.contains("document_map"));
     }
 
@@ -1819,4 +3054,196 @@ mod tests {
             Some("{\"choices\":[]}")
         );
     }
+
+    /// Serves one canned reply per connection and reports each request body
+    fn mock_server(replies: Vec<(String, String)>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/v1/chat/completions",
+            listener.local_addr().unwrap()
+        );
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for (head, body) in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = sender.send(read_request_body(&mut stream));
+                // An empty head never answers
+                if !head.is_empty() {
+                    let _ = write!(stream, "{}\r\n\r\n{}", head, body);
+                }
+                // Hold the connection open so a short body reads as stalled
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_secs(10));
+                    drop(stream);
+                });
+            }
+        });
+        (url, receiver)
+    }
+
+    fn read_request_body(stream: &mut TcpStream) -> String {
+        let mut data = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let count = stream.read(&mut buffer).unwrap();
+            if count == 0 {
+                return String::new();
+            }
+            data.extend_from_slice(&buffer[..count]);
+            let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&data[..end]).to_ascii_lowercase();
+            let length = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if data.len() >= end + 4 + length {
+                return String::from_utf8_lossy(&data[end + 4..end + 4 + length]).into_owned();
+            }
+        }
+    }
+
+    fn sse_reply(events: &[&str]) -> (String, String) {
+        let body: String = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: {}\r\nConnection: close",
+            body.len()
+        );
+        (head, body)
+    }
+
+    fn mock_backend(url: String) -> AiBackend {
+        AiBackend {
+            provider: AiProvider::OpenAiCompatible,
+            model: "test-model".to_string(),
+            api_url: url,
+            ..AiBackend::default()
+        }
+    }
+
+    fn plain_document() -> AiDocumentContext {
+        AiDocumentContext {
+            title: "测试".to_string(),
+            content: "正文".to_string(),
+            selection: None,
+        }
+    }
+
+    fn ask(text: &str) -> Vec<AiChatMessage> {
+        vec![AiChatMessage {
+            role: "user".to_string(),
+            content: text.to_string(),
+        }]
+    }
+
+    /// Progress events received before the final reply, and the reply
+    fn collect_reply(
+        receiver: &Receiver<ResponseMessage>,
+    ) -> (Vec<AiProgressEvent>, Result<AiAgentResponse, AiError>) {
+        let mut events = Vec::new();
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(10)).unwrap() {
+                ResponseMessage::AiProgress { event, .. } => events.push(event),
+                ResponseMessage::AiResponse { result, .. } => return (events, result),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn streams_reply_from_server_after_retrying_server_error() {
+        let (url, requests) = mock_server(vec![
+            (
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close"
+                    .to_string(),
+                String::new(),
+            ),
+            sse_reply(&[
+                r#"{"choices":[{"delta":{"content":"你好"}}]}"#,
+                r#"{"choices":[{"delta":{"content":"，世界"},"finish_reason":"stop"}]}"#,
+                r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3}}"#,
+                "[DONE]",
+            ]),
+        ]);
+        let (sender, receiver) = mpsc::channel();
+
+        mock_backend(url).discuss_writing_context(plain_document(), ask("在吗"), 7, sender);
+        let (events, result) = collect_reply(&receiver);
+
+        assert_eq!(result.unwrap().content, "你好，世界");
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, AiProgressEvent::Retrying { attempt: 1, .. }))
+        );
+        let deltas: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                AiProgressEvent::Delta(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, vec!["你好", "，世界"]);
+        assert!(events.contains(&AiProgressEvent::Usage {
+            model: "test-model".to_string(),
+            usage: TokenUsage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+            },
+        }));
+
+        let bodies: Vec<Value> = requests
+            .try_iter()
+            .map(|body| serde_json::from_str(&body).unwrap())
+            .collect();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[1]["model"], "test-model");
+        assert_eq!(bodies[1]["stream"], true);
+        assert_eq!(bodies[1]["messages"][1]["content"], "在吗");
+    }
+
+    #[test]
+    fn stalled_stream_times_out_without_retrying_partial_output() {
+        let (url, requests) = mock_server(vec![(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: 4096\r\nConnection: close"
+                .to_string(),
+            format!(
+                "data: {}\n\n",
+                r#"{"choices":[{"delta":{"content":"半句"}}]}"#
+            ),
+        )]);
+        let (sender, receiver) = mpsc::channel();
+        let backend = AiBackend {
+            timeout: Duration::from_millis(500),
+            ..mock_backend(url)
+        };
+
+        backend.discuss_writing_context(plain_document(), ask("写点什么"), 1, sender);
+        let (events, result) = collect_reply(&receiver);
+
+        assert!(events.contains(&AiProgressEvent::Delta("半句".to_string())));
+        assert!(matches!(result, Err(AiError::ApiError(message)) if message == TIMEOUT_MESSAGE));
+        assert_eq!(requests.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn cancelling_stops_a_request_waiting_on_the_server() {
+        let (url, requests) = mock_server(vec![(String::new(), String::new())]);
+        let (sender, receiver) = mpsc::channel();
+
+        let handle =
+            mock_backend(url).discuss_writing_context(plain_document(), ask("在吗"), 3, sender);
+        requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        let started = std::time::Instant::now();
+        handle.cancel();
+        let (_, result) = collect_reply(&receiver);
+
+        assert!(matches!(result, Err(AiError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
 }
@@ -0,0 +1,143 @@
+//! Bookkeeping for the chat request in flight.
+//!
+//! Only one chat or proofreading request runs at a time. Every request gets a
+//! fresh id, and a response is only accepted from the request that currently
+//! holds the slot, so a late reply from a stopped or superseded request can
+//! never overwrite the current one or end its processing state.
+
+use crate::backend::ai_backend::{AiRequestHandle, AiRequestId};
+
+struct ActiveRequest {
+    id: AiRequestId,
+    /// `None` until the worker has been started
+    handle: Option<AiRequestHandle>,
+}
+
+pub struct AiDispatcher {
+    next_id: AiRequestId,
+    active: Option<ActiveRequest>,
+}
+
+impl Default for AiDispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AiDispatcher {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            active: None,
+        }
+    }
+
+    /// A fresh id for a request that does not occupy the slot, such as a
+    /// narrative map extraction.
+    pub fn next_id(&mut self) -> AiRequestId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    /// Claim the slot for a new request.
+    ///
+    /// Returns `None` while another request is running, including before its
+    /// worker has started.
+    pub fn dispatch(&mut self) -> Option<AiRequestId> {
+        if self.active.is_some() {
+            return None;
+        }
+        let id = self.next_id();
+        self.active = Some(ActiveRequest { id, handle: None });
+        Some(id)
+    }
+
+    /// Attach the worker started for the dispatched request.
+    ///
+    /// A worker whose request no longer holds the slot is stopped at once.
+    pub fn started(&mut self, handle: AiRequestHandle) {
+        match self.active.as_mut() {
+            Some(active) if active.id == handle.id => active.handle = Some(handle),
+            _ => handle.cancel(),
+        }
+    }
+
+    /// Take the final response of `request_id`, freeing the slot.
+    ///
+    /// Returns `false` for a stale request whose response should be dropped.
+    pub fn accept(&mut self, request_id: AiRequestId) -> bool {
+        self.active
+            .take_if(|active| active.id == request_id)
+            .is_some()
+    }
+
+    /// Stop `request_id` and free the slot; `false` if it was not running.
+    pub fn cancel(&mut self, request_id: AiRequestId) -> bool {
+        match self.active.take_if(|active| active.id == request_id) {
+            Some(active) => {
+                if let Some(handle) = active.handle {
+                    handle.cancel();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_active(&self, request_id: AiRequestId) -> bool {
+        self.active
+            .as_ref()
+            .is_some_and(|active| active.id == request_id)
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_second_request_until_the_first_is_answered() {
+        let mut dispatcher = AiDispatcher::new();
+        let first = dispatcher.dispatch().unwrap();
+
+        // The worker has not started yet, but the slot is already taken
+        assert!(dispatcher.is_busy());
+        assert_eq!(dispatcher.dispatch(), None);
+
+        assert!(dispatcher.accept(first));
+        assert!(!dispatcher.is_busy());
+        let second = dispatcher.dispatch().unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn drops_responses_from_stopped_requests() {
+        let mut dispatcher = AiDispatcher::new();
+        let stopped = dispatcher.dispatch().unwrap();
+        assert!(dispatcher.cancel(stopped));
+        let current = dispatcher.dispatch().unwrap();
+
+        // The stopped worker still reports back; it must not end the current request
+        assert!(!dispatcher.accept(stopped));
+        assert!(dispatcher.is_active(current));
+        assert!(!dispatcher.cancel(stopped));
+
+        assert!(dispatcher.accept(current));
+        assert!(!dispatcher.accept(current));
+    }
+
+    #[test]
+    fn ids_stay_unique_across_slot_and_side_requests() {
+        let mut dispatcher = AiDispatcher::new();
+        let side = dispatcher.next_id();
+        let chat = dispatcher.dispatch().unwrap();
+        assert_ne!(side, chat);
+        assert!(!dispatcher.accept(side));
+        assert!(dispatcher.is_active(chat));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::config::Config;
+use crate::file::{Loaded, read_json_or_set_aside, write_atomic};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
@@ -26,9 +26,8 @@ pub struct AiPanelBackend {
 }
 
 impl AiPanelBackend {
-    pub fn new() -> Result<Self, AiPanelError> {
-        let config = Config::default();
-        let data_dir = config.data_dir();
+    /// Keep the narrative maps in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, AiPanelError> {
         let narrative_maps_dir = data_dir.join(NARRATIVE_MAPS_DIR);
 
         fs::create_dir_all(&narrative_maps_dir)?;
@@ -42,37 +41,31 @@ impl AiPanelBackend {
             items: map.to_owned(),
         };
         let content = serde_json::to_string_pretty(&narrative_map)?;
-        fs::write(file_path, content)?;
+        write_atomic(&file_path, &content)?;
         Ok(())
     }
 
-    pub fn load_narrative_map(&self, uuid: &str) -> Result<Option<Vec<String>>, AiPanelError> {
+    /// The narrative map of the file `uuid`; `None` if it has none yet, or
+    /// if its map file is corrupt, which is then kept aside
+    pub fn load_narrative_map(
+        &self,
+        uuid: &str,
+    ) -> Result<Loaded<Option<Vec<String>>>, AiPanelError> {
         let file_path = self.narrative_maps_dir.join(format!("{}.json", uuid));
-
-        if !file_path.exists() {
-            return Ok(None);
-        }
-
-        let content = fs::read_to_string(file_path)?;
-        let narrative_map: NarrativeMap = serde_json::from_str(&content)?;
-        Ok(Some(narrative_map.items))
+        let narrative_map: Loaded<Option<NarrativeMap>> = read_json_or_set_aside(&file_path)?;
+        Ok(narrative_map.map(|map| map.map(|map| map.items)))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::Path;
     use uuid::Uuid;
 
     fn setup_test_backend() -> (AiPanelBackend, PathBuf) {
         let test_dir = std::env::temp_dir().join(format!("test_ai_panel_{}", Uuid::new_v4()));
-        let narrative_maps_dir = test_dir.join(NARRATIVE_MAPS_DIR);
-        fs::create_dir_all(&narrative_maps_dir).unwrap();
-
-        let backend = AiPanelBackend {
-            narrative_maps_dir: narrative_maps_dir.clone(),
-        };
-
+        let backend = AiPanelBackend::with_data_dir(test_dir.clone()).unwrap();
         (backend, test_dir)
     }
 
@@ -92,7 +85,7 @@ mod tests {
 
         backend.save_narrative_map(&uuid, &map).unwrap();
 
-        let loaded_map = backend.load_narrative_map(&uuid).unwrap();
+        let loaded_map = backend.load_narrative_map(&uuid).unwrap().value;
         assert!(loaded_map.is_some());
         let loaded_map = loaded_map.unwrap();
         assert_eq!(loaded_map.len(), 2);
@@ -107,9 +100,44 @@ mod tests {
         let (backend, test_dir) = setup_test_backend();
         let uuid = Uuid::new_v4().to_string();
 
-        let loaded_map = backend.load_narrative_map(&uuid).unwrap();
+        let loaded_map = backend.load_narrative_map(&uuid).unwrap().value;
         assert!(loaded_map.is_none());
 
         cleanup_test_dir(&test_dir);
     }
+
+    #[test]
+    fn test_truncated_narrative_map_is_set_aside() {
+        let (backend, test_dir) = setup_test_backend();
+        let uuid = Uuid::new_v4().to_string();
+        let path = test_dir
+            .join(NARRATIVE_MAPS_DIR)
+            .join(format!("{}.json", uuid));
+        fs::write(&path, "{\"items\": [\"主角登场\", \"冲突").unwrap();
+
+        let loaded = backend.load_narrative_map(&uuid).unwrap();
+        assert!(loaded.value.is_none());
+        assert!(!path.exists());
+        let copies = corrupt_copies(&path);
+        assert_eq!(copies.len(), 1);
+        assert_eq!(loaded.set_aside.as_ref(), Some(&copies[0]));
+        assert!(fs::read_to_string(&copies[0]).unwrap().ends_with("\"冲突"));
+
+        cleanup_test_dir(&test_dir);
+    }
+
+    /// Files next to `path` that a corrupt copy of it was moved to
+    fn corrupt_copies(path: &Path) -> Vec<PathBuf> {
+        let prefix = format!("{}.corrupt-", path.file_name().unwrap().to_string_lossy());
+        fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|copy| {
+                copy.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with(&prefix)
+            })
+            .collect()
+    }
 }
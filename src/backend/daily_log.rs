@@ -0,0 +1,220 @@
+use crate::file::{read_json_or_set_aside, write_atomic};
+use chrono::{Days, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+
+const DAILY_LOG_FILE: &str = "daily_log.json";
+
+/// Writing time recorded for a single calendar day
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DayTotals {
+    /// Seconds the editor was focused
+    #[serde(default)]
+    pub focused_secs: u64,
+    /// Seconds spent actively typing
+    #[serde(default)]
+    pub typing_secs: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum DailyLogError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Totals for the Monday-to-Sunday week containing a given day
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WeekSummary {
+    /// One entry per day of the week, Monday first
+    pub days: Vec<(NaiveDate, DayTotals)>,
+    pub total: DayTotals,
+}
+
+/// Summarise the week that contains `day`
+pub fn week_summary(log: &BTreeMap<NaiveDate, DayTotals>, day: NaiveDate) -> WeekSummary {
+    let monday = day.week(Weekday::Mon).first_day();
+    let mut summary = WeekSummary::default();
+
+    for offset in 0..7 {
+        let Some(date) = monday.checked_add_days(Days::new(offset)) else {
+            break;
+        };
+        let totals = log.get(&date).copied().unwrap_or_default();
+        summary.total.focused_secs += totals.focused_secs;
+        summary.total.typing_secs += totals.typing_secs;
+        summary.days.push((date, totals));
+    }
+
+    summary
+}
+
+/// Persists per-day writing totals in a single JSON file
+pub struct DailyLogBackend {
+    log_path: PathBuf,
+    /// Held while a change is read, applied and written, so two writers
+    /// cannot drop each other's time
+    write_lock: Mutex<()>,
+}
+
+impl DailyLogBackend {
+    /// Keep the daily totals in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, DailyLogError> {
+        fs::create_dir_all(&data_dir)?;
+
+        Ok(Self::at(data_dir.join(DAILY_LOG_FILE)))
+    }
+
+    fn at(log_path: PathBuf) -> Self {
+        Self {
+            log_path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Load all recorded days, oldest first. A log that does not parse is
+    /// set aside and the days start over.
+    pub fn load(&self) -> Result<BTreeMap<NaiveDate, DayTotals>, DailyLogError> {
+        Ok(read_json_or_set_aside(&self.log_path)?
+            .value
+            .unwrap_or_default())
+    }
+
+    /// Add time to the totals for `date`
+    pub fn add(
+        &self,
+        date: NaiveDate,
+        focused_secs: u64,
+        typing_secs: u64,
+    ) -> Result<DayTotals, DailyLogError> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut log = self.load()?;
+        let totals = log.entry(date).or_default();
+        totals.focused_secs += focused_secs;
+        totals.typing_secs += typing_secs;
+        let totals = *totals;
+
+        let content = serde_json::to_string_pretty(&log)?;
+        write_atomic(&self.log_path, &content)?;
+        Ok(totals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_week_summary_covers_monday_to_sunday() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+        let mut log = BTreeMap::new();
+        // Sunday before the week, inside the week, and the following Monday
+        log.insert(
+            day(2),
+            DayTotals {
+                focused_secs: 1000,
+                typing_secs: 0,
+            },
+        );
+        log.insert(
+            day(5),
+            DayTotals {
+                focused_secs: 120,
+                typing_secs: 60,
+            },
+        );
+        log.insert(
+            day(9),
+            DayTotals {
+                focused_secs: 30,
+                typing_secs: 10,
+            },
+        );
+        log.insert(
+            day(10),
+            DayTotals {
+                focused_secs: 1000,
+                typing_secs: 0,
+            },
+        );
+
+        let summary = week_summary(&log, day(6));
+
+        assert_eq!(summary.days.len(), 7);
+        assert_eq!(summary.days[0].0, day(3));
+        assert_eq!(summary.days[6].0, day(9));
+        assert_eq!(summary.total.focused_secs, 150);
+        assert_eq!(summary.total.typing_secs, 70);
+    }
+
+    #[test]
+    fn test_add_accumulates_per_day() {
+        let test_dir = std::env::temp_dir().join(format!("test_daily_log_{}", Uuid::new_v4()));
+        fs::create_dir_all(&test_dir).unwrap();
+        let backend = DailyLogBackend::at(test_dir.join(DAILY_LOG_FILE));
+        let monday = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let tuesday = NaiveDate::from_ymd_opt(2025, 3, 4).unwrap();
+
+        backend.add(monday, 60, 20).unwrap();
+        let totals = backend.add(monday, 30, 10).unwrap();
+        backend.add(tuesday, 5, 0).unwrap();
+
+        assert_eq!(
+            totals,
+            DayTotals {
+                focused_secs: 90,
+                typing_secs: 30
+            }
+        );
+        let log = backend.load().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[&tuesday].focused_secs, 5);
+
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_concurrent_adds_keep_all_time() {
+        let test_dir = std::env::temp_dir().join(format!("test_daily_log_{}", Uuid::new_v4()));
+        let backend = DailyLogBackend::with_data_dir(test_dir.clone()).unwrap();
+        let day = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        backend.add(day, 1, 1).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(backend.load().unwrap()[&day].focused_secs, 40);
+
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_corrupt_log_is_set_aside() {
+        let test_dir = std::env::temp_dir().join(format!("test_daily_log_{}", Uuid::new_v4()));
+        let backend = DailyLogBackend::with_data_dir(test_dir.clone()).unwrap();
+        fs::write(test_dir.join(DAILY_LOG_FILE), "{\"2025-03-03\": {\"focu").unwrap();
+        let day = NaiveDate::from_ymd_opt(2025, 3, 4).unwrap();
+
+        backend.add(day, 30, 10).unwrap();
+
+        assert_eq!(backend.load().unwrap()[&day].focused_secs, 30);
+        // The cut-off log is kept beside the new one
+        assert_eq!(fs::read_dir(&test_dir).unwrap().count(), 2);
+
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+}
@@ -1,9 +1,9 @@
-use crate::config::Config;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 use uuid::Uuid;
 use xxhash_rust::xxh64::xxh64;
@@ -13,6 +13,10 @@ const TOTAL_TIME_KEY: &str = "user.myeditor.total_time";
 const BLOB_DIR: &str = "blobs";
 const HISTORY_DIR: &str = "history";
 
+/// Blobs younger than this are kept by [`EditorBackend::collect_garbage`]: a
+/// running app writes the blob of a version before its history entry
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
 /// Custom error types for the backend
 #[derive(Error, Debug)]
 pub enum BackendError {
@@ -49,6 +53,68 @@ pub struct HistoryEntry {
     pub time_spent: Option<u64>,
 }
 
+/// A version found by [`EditorBackend::entries_between`], with the file it
+/// belongs to
+#[derive(Debug, Clone)]
+pub struct DatedEntry {
+    pub uuid: String,
+    /// Where the file was saved last, from the newest version that says
+    pub latest_path: Option<PathBuf>,
+    pub entry: HistoryEntry,
+}
+
+/// A file the store has versions of, found by
+/// [`EditorBackend::list_tracked_files`]
+#[derive(Debug, Clone)]
+pub struct TrackedFile {
+    pub uuid: String,
+    /// Where the file was saved last, from the newest version that says
+    pub latest_path: Option<PathBuf>,
+    /// The newest version
+    pub latest: HistoryEntry,
+}
+
+/// What [`EditorBackend::collect_garbage`] removed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    pub removed_blobs: usize,
+    pub freed_bytes: u64,
+}
+
+/// Something wrong found by [`EditorBackend::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreProblem {
+    /// A history file that cannot be read or parsed
+    UnreadableHistory { path: PathBuf, reason: String },
+    /// A version whose content is gone
+    MissingBlob { uuid: String, hash: String },
+    /// A blob whose content does not match its hash
+    CorruptBlob { hash: String },
+}
+
+impl std::fmt::Display for StoreProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreProblem::UnreadableHistory { path, reason } => {
+                write!(f, "unreadable history {}: {}", path.display(), reason)
+            }
+            StoreProblem::MissingBlob { uuid, hash } => {
+                write!(f, "missing blob {} in the history of {}", hash, uuid)
+            }
+            StoreProblem::CorruptBlob { hash } => write!(f, "corrupt blob {}", hash),
+        }
+    }
+}
+
+/// What [`EditorBackend::verify`] checked and found
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub histories: usize,
+    pub versions: usize,
+    pub blobs: usize,
+    pub problems: Vec<StoreProblem>,
+}
+
 /// Main backend interface for content-addressable storage
 pub struct EditorBackend {
     data_dir: PathBuf,
@@ -57,11 +123,23 @@ pub struct EditorBackend {
 }
 
 impl EditorBackend {
-    /// Initialize the backend and create necessary directories
-    pub fn new() -> Result<Self, BackendError> {
-        let config = Config::default();
-        let data_dir = config.data_dir();
-
+    /// Initialize the backend on the data dir `data_dir`, e.g. to embed the
+    /// version store somewhere other than the app's own data dir
+    ///
+    /// ```no_run
+    /// use paper_shell::backend::editor_backend::EditorBackend;
+    /// use std::path::Path;
+    ///
+    /// let backend = EditorBackend::with_data_dir("/tmp/paper-shell-data".into())?;
+    /// let file = Path::new("/tmp/草稿.txt");
+    /// std::fs::write(file, "第一稿")?;
+    /// backend.save(file, "第一稿", 0)?;
+    ///
+    /// let history = backend.load_history(file)?;
+    /// assert_eq!(backend.restore_version(&history[0].hash)?, "第一稿");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, BackendError> {
         let blobs_dir = data_dir.join(BLOB_DIR);
         let history_dir = data_dir.join(HISTORY_DIR);
 
@@ -148,8 +226,8 @@ impl EditorBackend {
             .ok_or_else(|| BackendError::InvalidHash("No matching history found".to_string()))
     }
 
-    /// Load history for a UUID
-    fn load_history_by_uuid(&self, uuid: &str) -> Result<Vec<HistoryEntry>, BackendError> {
+    /// Load history for a UUID; empty if there is none
+    pub fn load_history_by_uuid(&self, uuid: &str) -> Result<Vec<HistoryEntry>, BackendError> {
         let history_path = self.history_dir.join(format!("{}.json", uuid));
 
         if !history_path.exists() {
@@ -235,11 +313,173 @@ impl EditorBackend {
         Ok(content)
     }
 
+    /// Size in bytes of the content stored under `hash`
+    pub fn blob_size(&self, hash: &str) -> Result<u64, BackendError> {
+        let blob_path = self.blobs_dir.join(hash);
+        match fs::metadata(blob_path) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(BackendError::InvalidHash(
+                format!("Blob not found for hash: {}", hash),
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every file with at least one version, by UUID
+    pub fn list_tracked_files(&self) -> Result<Vec<TrackedFile>, BackendError> {
+        let mut files = Vec::new();
+        for (uuid, path) in self.all_histories()? {
+            let history: Vec<HistoryEntry> = serde_json::from_str(&fs::read_to_string(path)?)?;
+            let latest_path = history
+                .iter()
+                .filter(|entry| entry.file_path.is_some())
+                .max_by_key(|entry| entry.timestamp)
+                .and_then(|entry| entry.file_path.clone());
+            // The last of equally old versions is the one saved last
+            let Some(latest) = history.into_iter().max_by_key(|entry| entry.timestamp) else {
+                continue;
+            };
+            files.push(TrackedFile {
+                uuid,
+                latest_path,
+                latest,
+            });
+        }
+        Ok(files)
+    }
+
     /// Get the data directory path
     pub fn data_dir(&self) -> &Path {
         &self.data_dir
     }
 
+    /// All histories in the store, by UUID
+    fn all_histories(&self) -> Result<Vec<(String, PathBuf)>, BackendError> {
+        let mut histories = Vec::new();
+        for entry in fs::read_dir(&self.history_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("json")
+                && let Some(uuid) = path.file_stem().and_then(|s| s.to_str())
+            {
+                histories.push((uuid.to_string(), path.clone()));
+            }
+        }
+        histories.sort();
+        Ok(histories)
+    }
+
+    /// The versions of the file `uuid` saved from `from` up to but not
+    /// including `to`, oldest first
+    pub fn entries_between(
+        &self,
+        uuid: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<DatedEntry>, BackendError> {
+        let history = self.load_history_by_uuid(uuid)?;
+        let mut entries = dated_entries(uuid, history, from, to);
+        entries.sort_by_key(|dated| dated.entry.timestamp);
+        Ok(entries)
+    }
+
+    /// The versions of every file saved from `from` up to but not including
+    /// `to`, oldest first. Each history is read once.
+    pub fn all_entries_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<DatedEntry>, BackendError> {
+        let mut entries = Vec::new();
+        for (uuid, path) in self.all_histories()? {
+            let history: Vec<HistoryEntry> = serde_json::from_str(&fs::read_to_string(path)?)?;
+            entries.extend(dated_entries(&uuid, history, from, to));
+        }
+        // Stable, so versions saved at once stay in the order of their ids
+        entries.sort_by_key(|dated| dated.entry.timestamp);
+        Ok(entries)
+    }
+
+    /// Delete blobs no history refers to. Stops without deleting anything
+    /// when a history cannot be read, since its blobs would look unused.
+    pub fn collect_garbage(&self) -> Result<GcReport, BackendError> {
+        let cutoff = SystemTime::now()
+            .checked_sub(GC_GRACE_PERIOD)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        self.collect_garbage_before(cutoff)
+    }
+
+    fn collect_garbage_before(&self, cutoff: SystemTime) -> Result<GcReport, BackendError> {
+        let mut used = std::collections::HashSet::new();
+        for (_, path) in self.all_histories()? {
+            let entries: Vec<HistoryEntry> = serde_json::from_str(&fs::read_to_string(path)?)?;
+            used.extend(entries.into_iter().map(|entry| entry.hash));
+        }
+
+        let mut report = GcReport::default();
+        for entry in fs::read_dir(&self.blobs_dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let hash = entry.file_name().to_string_lossy().into_owned();
+            if !metadata.is_file()
+                || used.contains(&hash)
+                || metadata.modified().is_ok_and(|modified| modified > cutoff)
+            {
+                continue;
+            }
+            fs::remove_file(entry.path())?;
+            report.removed_blobs += 1;
+            report.freed_bytes += metadata.len();
+        }
+        Ok(report)
+    }
+
+    /// Check that every version in every history has its blob and that
+    /// every blob matches its hash
+    pub fn verify(&self) -> Result<VerifyReport, BackendError> {
+        let mut report = VerifyReport::default();
+        for (uuid, path) in self.all_histories()? {
+            report.histories += 1;
+            let entries = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| {
+                    serde_json::from_str::<Vec<HistoryEntry>>(&content).map_err(|e| e.to_string())
+                });
+            let entries = match entries {
+                Ok(entries) => entries,
+                Err(reason) => {
+                    report
+                        .problems
+                        .push(StoreProblem::UnreadableHistory { path, reason });
+                    continue;
+                }
+            };
+            report.versions += entries.len();
+            for entry in entries {
+                if !self.blobs_dir.join(&entry.hash).is_file() {
+                    report.problems.push(StoreProblem::MissingBlob {
+                        uuid: uuid.clone(),
+                        hash: entry.hash,
+                    });
+                }
+            }
+        }
+
+        for entry in fs::read_dir(&self.blobs_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            report.blobs += 1;
+            let hash = entry.file_name().to_string_lossy().into_owned();
+            let intact = fs::read_to_string(entry.path())
+                .is_ok_and(|content| Self::calculate_hash(&content) == hash);
+            if !intact {
+                report.problems.push(StoreProblem::CorruptBlob { hash });
+            }
+        }
+        Ok(report)
+    }
+
     /// Get UUID for a file, creating one if it doesn't exist
     #[allow(dead_code)]
     pub fn get_uuid(&self, file_path: &Path, content: &str) -> Result<String, BackendError> {
@@ -260,10 +500,27 @@ impl EditorBackend {
     }
 }
 
-impl Default for EditorBackend {
-    fn default() -> Self {
-        Self::new().expect("Failed to initialize EditorBackend")
-    }
+/// The versions of `history`, the history of `uuid`, saved in `from..to`
+fn dated_entries(
+    uuid: &str,
+    history: Vec<HistoryEntry>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<DatedEntry> {
+    let latest_path = history
+        .iter()
+        .filter(|entry| entry.file_path.is_some())
+        .max_by_key(|entry| entry.timestamp)
+        .and_then(|entry| entry.file_path.clone());
+    history
+        .into_iter()
+        .filter(|entry| from <= entry.timestamp && entry.timestamp < to)
+        .map(|entry| DatedEntry {
+            uuid: uuid.to_string(),
+            latest_path: latest_path.clone(),
+            entry,
+        })
+        .collect()
 }
 
 // ============================================================================
@@ -387,15 +644,7 @@ mod tests {
 
     fn setup_test_backend() -> (EditorBackend, PathBuf) {
         let test_dir = std::env::temp_dir().join(format!("test_backend_{}", Uuid::new_v4()));
-        let backend = EditorBackend {
-            data_dir: test_dir.clone(),
-            blobs_dir: test_dir.join(BLOB_DIR),
-            history_dir: test_dir.join(HISTORY_DIR),
-        };
-
-        fs::create_dir_all(&backend.blobs_dir).unwrap();
-        fs::create_dir_all(&backend.history_dir).unwrap();
-
+        let backend = EditorBackend::with_data_dir(test_dir.clone()).unwrap();
         (backend, test_dir)
     }
 
@@ -563,4 +812,177 @@ mod tests {
 
         cleanup_test_dir(&test_dir);
     }
+
+    #[test]
+    fn test_gc_and_verify() {
+        let (backend, test_dir) = setup_test_backend();
+
+        let kept = "Version in the history";
+        let kept_hash = EditorBackend::calculate_hash(kept);
+        backend.save_blob(&kept_hash, kept).unwrap();
+        let entry = |hash: &str| HistoryEntry {
+            hash: hash.to_string(),
+            timestamp: Utc::now(),
+            file_path: None,
+            time_spent: None,
+        };
+        backend
+            .save_history("doc", &[entry(&kept_hash), entry("0000000000000000")])
+            .unwrap();
+        let orphan = "Left behind";
+        let orphan_hash = EditorBackend::calculate_hash(orphan);
+        backend.save_blob(&orphan_hash, orphan).unwrap();
+        fs::write(backend.blobs_dir.join("1111111111111111"), "Tampered").unwrap();
+
+        let report = backend.verify().unwrap();
+        assert_eq!((report.histories, report.versions, report.blobs), (1, 2, 3));
+        assert_eq!(
+            report.problems,
+            [
+                StoreProblem::MissingBlob {
+                    uuid: "doc".to_string(),
+                    hash: "0000000000000000".to_string(),
+                },
+                StoreProblem::CorruptBlob {
+                    hash: "1111111111111111".to_string(),
+                },
+            ]
+        );
+
+        // Fresh blobs may still be waiting for their history entry
+        assert_eq!(backend.collect_garbage().unwrap(), GcReport::default());
+        let report = backend
+            .collect_garbage_before(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(report.removed_blobs, 2);
+        assert_eq!(report.freed_bytes, (orphan.len() + "Tampered".len()) as u64);
+        assert_eq!(backend.restore_version(&kept_hash).unwrap(), kept);
+        assert!(backend.restore_version(&orphan_hash).is_err());
+
+        cleanup_test_dir(&test_dir);
+    }
+
+    #[test]
+    fn test_entries_between_dates() {
+        use chrono::TimeZone;
+
+        let (backend, test_dir) = setup_test_backend();
+        let at = |hour: u32| Utc.with_ymd_and_hms(2026, 5, 1, hour, 0, 0).unwrap();
+        let entry = |hash: &str, hour: u32, path: Option<&str>| HistoryEntry {
+            hash: hash.to_string(),
+            timestamp: at(hour),
+            file_path: path.map(PathBuf::from),
+            time_spent: None,
+        };
+        backend
+            .save_history(
+                "novel",
+                &[
+                    entry("n1", 8, Some("/old/novel.txt")),
+                    entry("n2", 12, Some("/books/novel.txt")),
+                    entry("n3", 20, None),
+                ],
+            )
+            .unwrap();
+        backend
+            .save_history("notes", &[entry("m1", 10, None), entry("m2", 12, None)])
+            .unwrap();
+
+        let hashes = |entries: Vec<DatedEntry>| -> Vec<String> {
+            entries.into_iter().map(|dated| dated.entry.hash).collect()
+        };
+
+        // From is included, to is not
+        let novel = backend.entries_between("novel", at(8), at(20)).unwrap();
+        assert_eq!(
+            novel[0].latest_path.as_deref(),
+            Some(Path::new("/books/novel.txt"))
+        );
+        assert_eq!(hashes(novel), ["n1", "n2"]);
+        assert_eq!(
+            hashes(backend.entries_between("novel", at(9), at(21)).unwrap()),
+            ["n2", "n3"]
+        );
+
+        // Across files, oldest first; at the same instant by file id
+        let all = backend.all_entries_between(at(0), at(23)).unwrap();
+        assert_eq!(all[1].uuid, "notes");
+        assert_eq!(all[1].latest_path, None);
+        assert_eq!(hashes(all), ["n1", "m1", "m2", "n2", "n3"]);
+        assert_eq!(
+            hashes(backend.all_entries_between(at(12), at(13)).unwrap()),
+            ["m2", "n2"]
+        );
+
+        // Empty and backward ranges, and files without a history
+        assert!(
+            backend
+                .all_entries_between(at(12), at(12))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            backend
+                .all_entries_between(at(20), at(8))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            backend
+                .all_entries_between(at(21), at(23))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            backend
+                .entries_between("missing", at(0), at(23))
+                .unwrap()
+                .is_empty()
+        );
+
+        cleanup_test_dir(&test_dir);
+    }
+
+    #[test]
+    fn test_list_tracked_files() {
+        use chrono::TimeZone;
+
+        let (backend, test_dir) = setup_test_backend();
+        let entry = |hash: &str, hour: u32, path: Option<&str>| HistoryEntry {
+            hash: hash.to_string(),
+            timestamp: Utc.with_ymd_and_hms(2026, 5, 1, hour, 0, 0).unwrap(),
+            file_path: path.map(PathBuf::from),
+            time_spent: None,
+        };
+        backend
+            .save_history(
+                "novel",
+                &[
+                    entry("n1", 8, Some("/books/novel.txt")),
+                    entry("n2", 12, None),
+                    entry("n3", 12, None),
+                ],
+            )
+            .unwrap();
+        backend.save_history("empty", &[]).unwrap();
+        backend.save_blob("n3", "第三稿").unwrap();
+
+        let files = backend.list_tracked_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].uuid, "novel");
+        assert_eq!(
+            files[0].latest_path.as_deref(),
+            Some(Path::new("/books/novel.txt"))
+        );
+        // Of versions saved at once, the one saved last
+        assert_eq!(files[0].latest.hash, "n3");
+
+        assert_eq!(backend.blob_size("n3").unwrap(), "第三稿".len() as u64);
+        assert!(matches!(
+            backend.blob_size("n1"),
+            Err(BackendError::InvalidHash(_))
+        ));
+
+        cleanup_test_dir(&test_dir);
+    }
 }
@@ -0,0 +1,163 @@
+//! Settings a single file keeps apart from the global ones, e.g. a journal
+//! that wants full-width indentation and a serif font.
+//!
+//! Overrides are stored per file UUID as JSON in the data dir and merged
+//! over the global [`Settings`] while that file is open.
+
+use crate::config::{FormatIndent, Settings};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+const FILE_SETTINGS_DIR: &str = "file_settings";
+
+/// Values that replace the global setting for one file; `None` keeps the
+/// global value
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_indent: Option<FormatIndent>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_family: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_width: Option<f32>,
+}
+
+impl FileSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `global` with these overrides applied
+    pub fn apply_to(&self, global: &Settings) -> Settings {
+        let mut settings = global.clone();
+        if let Some(indent) = self.format_indent {
+            settings.format_indent = indent;
+        }
+        if let Some(family) = &self.font_family {
+            settings.font_family = Some(family.clone());
+        }
+        if let Some(size) = self.font_size {
+            settings.font_size = size;
+        }
+        if let Some(width) = self.line_width {
+            settings.line_width = width;
+        }
+        settings.validate();
+        settings
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FileSettingsError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub struct FileSettingsBackend {
+    dir: PathBuf,
+}
+
+impl FileSettingsBackend {
+    /// Keep the per-file overrides in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, FileSettingsError> {
+        let dir = data_dir.join(FILE_SETTINGS_DIR);
+
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Store the overrides of the file with `uuid`; empty overrides remove
+    /// the stored ones.
+    pub fn save(&self, uuid: &str, settings: &FileSettings) -> Result<(), FileSettingsError> {
+        let file_path = self.dir.join(format!("{}.json", uuid));
+        if settings.is_empty() {
+            if file_path.exists() {
+                fs::remove_file(file_path)?;
+            }
+            return Ok(());
+        }
+        let content = serde_json::to_string_pretty(settings)?;
+        fs::write(file_path, content)?;
+        Ok(())
+    }
+
+    pub fn load(&self, uuid: &str) -> Result<FileSettings, FileSettingsError> {
+        let file_path = self.dir.join(format!("{}.json", uuid));
+
+        if !file_path.exists() {
+            return Ok(FileSettings::default());
+        }
+
+        let content = fs::read_to_string(file_path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn setup_test_backend() -> (FileSettingsBackend, PathBuf) {
+        let test_dir = std::env::temp_dir().join(format!("test_file_settings_{}", Uuid::new_v4()));
+        let dir = test_dir.join(FILE_SETTINGS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+
+        (FileSettingsBackend { dir }, test_dir)
+    }
+
+    #[test]
+    fn test_overrides_replace_only_what_they_set() {
+        let global = Settings::default();
+        let overrides = FileSettings {
+            format_indent: Some(FormatIndent::FullWidth),
+            font_family: Some("Songti SC".to_string()),
+            font_size: Some(99.0),
+            line_width: None,
+        };
+
+        let merged = overrides.apply_to(&global);
+        assert_eq!(merged.format_indent, FormatIndent::FullWidth);
+        assert_eq!(merged.font_family.as_deref(), Some("Songti SC"));
+        // Overrides are held to the same ranges as global settings
+        assert_eq!(merged.font_size, 36.0);
+        assert_eq!(merged.line_width, global.line_width);
+        assert_eq!(merged.theme, global.theme);
+
+        let plain = FileSettings::default().apply_to(&global);
+        assert_eq!(plain.font_size, global.font_size);
+        assert_eq!(plain.font_family, None);
+    }
+
+    #[test]
+    fn test_save_load_and_clear() {
+        let (backend, test_dir) = setup_test_backend();
+        let uuid = Uuid::new_v4().to_string();
+        assert!(backend.load(&uuid).unwrap().is_empty());
+
+        let overrides = FileSettings {
+            font_size: Some(18.0),
+            ..Default::default()
+        };
+        backend.save(&uuid, &overrides).unwrap();
+        assert_eq!(backend.load(&uuid).unwrap(), overrides);
+
+        backend.save(&uuid, &FileSettings::default()).unwrap();
+        assert!(!backend.dir.join(format!("{}.json", uuid)).exists());
+        assert!(backend.load(&uuid).unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(test_dir);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::fonts::SystemFonts;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use xxhash_rust::xxh64::Xxh64;
+
+const FONT_CACHE_FILE: &str = "font_cache.json";
+/// Bumped when the scan sorts fonts differently, so lists made the old way
+/// are not reused
+const SCAN_VERSION: u32 = 2;
+
+#[derive(Error, Debug)]
+pub enum FontCacheError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFonts {
+    /// [`fingerprint`] of the font folders when the list was made
+    fingerprint: u64,
+    fonts: SystemFonts,
+}
+
+/// The font lists from the last scan, kept in the data dir so later
+/// launches skip scanning while the installed fonts stay the same
+pub struct FontCache {
+    cache_path: PathBuf,
+}
+
+impl FontCache {
+    /// Keep the font lists in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, FontCacheError> {
+        fs::create_dir_all(&data_dir)?;
+
+        Ok(Self {
+            cache_path: data_dir.join(FONT_CACHE_FILE),
+        })
+    }
+
+    /// The cached list, if it was made with the same `fingerprint`
+    pub fn load(&self, fingerprint: u64) -> Result<Option<SystemFonts>, FontCacheError> {
+        if !self.cache_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&self.cache_path)?;
+        let cached: CachedFonts = serde_json::from_str(&content)?;
+        Ok((cached.fingerprint == fingerprint).then_some(cached.fonts))
+    }
+
+    pub fn store(&self, fingerprint: u64, fonts: &SystemFonts) -> Result<(), FontCacheError> {
+        let cached = CachedFonts {
+            fingerprint,
+            fonts: fonts.clone(),
+        };
+        fs::write(&self.cache_path, serde_json::to_string(&cached)?)?;
+        Ok(())
+    }
+}
+
+/// The fonts on this system, from the cache when the font folders have not
+/// changed since the last scan
+pub fn cached_system_fonts(data_dir: &Path) -> SystemFonts {
+    let fingerprint = fingerprint(&font_dirs());
+    let cache = match FontCache::with_data_dir(data_dir.to_path_buf()) {
+        Ok(cache) => cache,
+        Err(e) => {
+            tracing::warn!("Font cache unavailable: {}", e);
+            return crate::ui::font::enumerate_fonts();
+        }
+    };
+    match cache.load(fingerprint) {
+        Ok(Some(fonts)) => {
+            tracing::debug!(
+                "Using {} cached Chinese and {} other fonts",
+                fonts.chinese.len(),
+                fonts.latin.len()
+            );
+            return fonts;
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to read font cache: {}", e),
+    }
+
+    let fonts = crate::ui::font::enumerate_fonts();
+    if let Err(e) = cache.store(fingerprint, &fonts) {
+        tracing::warn!("Failed to write font cache: {}", e);
+    }
+    fonts
+}
+
+/// Folders fonts get installed to on this system
+fn font_dirs() -> Vec<PathBuf> {
+    let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    let mut dirs = Vec::new();
+    match std::env::consts::OS {
+        "macos" => {
+            dirs.push(PathBuf::from("/System/Library/Fonts"));
+            dirs.push(PathBuf::from("/Library/Fonts"));
+            dirs.extend(home.map(|home| home.join("Library/Fonts")));
+        }
+        "windows" => {
+            let windir = std::env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+            dirs.push(Path::new(&windir).join("Fonts"));
+            if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+                dirs.push(Path::new(&local).join("Microsoft\\Windows\\Fonts"));
+            }
+        }
+        _ => {
+            dirs.push(PathBuf::from("/usr/share/fonts"));
+            dirs.push(PathBuf::from("/usr/local/share/fonts"));
+            if let Some(home) = home {
+                dirs.push(home.join(".local/share/fonts"));
+                dirs.push(home.join(".fonts"));
+            }
+        }
+    }
+    dirs
+}
+
+/// Hash of the names and modification times of everything directly inside
+/// `dirs`; installing or removing a font changes it
+fn fingerprint(dirs: &[PathBuf]) -> u64 {
+    let mut hasher = Xxh64::new(0);
+    hasher.update(&SCAN_VERSION.to_le_bytes());
+    for dir in dirs {
+        hasher.update(dir.to_string_lossy().as_bytes());
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut entries: Vec<(String, u128)> = entries
+            .flatten()
+            .map(|entry| {
+                let modified = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_nanos());
+                (entry.file_name().to_string_lossy().into_owned(), modified)
+            })
+            .collect();
+        entries.sort();
+        for (name, modified) in entries {
+            hasher.update(name.as_bytes());
+            hasher.update(&modified.to_le_bytes());
+        }
+    }
+    hasher.digest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_cache_is_used_until_the_fonts_change() {
+        let test_dir = std::env::temp_dir().join(format!("test_font_cache_{}", Uuid::new_v4()));
+        let fonts_dir = test_dir.join("fonts");
+        fs::create_dir_all(&fonts_dir).unwrap();
+        let cache = FontCache {
+            cache_path: test_dir.join(FONT_CACHE_FILE),
+        };
+        let dirs = [fonts_dir.clone(), test_dir.join("missing")];
+
+        let before = fingerprint(&dirs);
+        assert_eq!(cache.load(before).unwrap(), None);
+        let fonts = SystemFonts {
+            chinese: vec!["宋体".to_string()],
+            latin: vec!["Georgia".to_string()],
+        };
+        cache.store(before, &fonts).unwrap();
+        assert_eq!(cache.load(before).unwrap(), Some(fonts));
+
+        fs::write(fonts_dir.join("NewFont.ttf"), b"").unwrap();
+        let after = fingerprint(&dirs);
+        assert_ne!(before, after);
+        assert_eq!(cache.load(after).unwrap(), None);
+
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+}
@@ -0,0 +1,316 @@
+//! Search across every document the store tracks, for the 全局搜索 palette:
+//! the newest version of each file is matched line by line on worker
+//! threads, and the lines found are handed over file by file as they come.
+//!
+//! Matching is by substring, so words in CJK text, which has no spaces to
+//! find them by, are found wherever they occur. Latin letters match
+//! regardless of case, and full-width letters and digits match their
+//! half-width forms.
+
+use crate::backend::editor_backend::{BackendError, EditorBackend, TrackedFile};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Files larger than this, in bytes, are skipped rather than searched
+pub const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Lines reported per file; a file matching on more says so instead
+pub const MAX_HITS_PER_FILE: usize = 100;
+
+/// Characters kept on each side of a match in its snippet
+const SNIPPET_CONTEXT: usize = 24;
+
+/// A line that has the query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    /// Zero-based line number
+    pub line: usize,
+    /// The line around its first match, shortened with "…" where cut
+    pub snippet: String,
+    /// Byte range of the match within `snippet`
+    pub highlight: Range<usize>,
+}
+
+/// The lines of one file that have the query
+#[derive(Debug, Clone)]
+pub struct FileHits {
+    pub uuid: String,
+    /// Where the file was saved last; `None` if its history never says
+    pub path: Option<PathBuf>,
+    pub hits: Vec<SearchHit>,
+    /// More than [`MAX_HITS_PER_FILE`] lines matched
+    pub truncated: bool,
+}
+
+/// How a [`search_all`] went
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchSummary {
+    pub searched: usize,
+    /// Files over [`MAX_FILE_BYTES`] or whose content is gone
+    pub skipped: usize,
+    /// Stopped through [`SearchCancel::cancel`] before every file was read
+    pub cancelled: bool,
+}
+
+/// Stops a running [`search_all`] after the files being read; clones share
+/// the flag
+#[derive(Debug, Default, Clone)]
+pub struct SearchCancel(Arc<AtomicBool>);
+
+impl SearchCancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Search the newest version of every tracked file for `query` on up to
+/// `workers` threads, most recently saved files first. `found` is called,
+/// from the worker threads, for each file with at least one hit.
+pub fn search_all(
+    editor: &EditorBackend,
+    query: &str,
+    workers: usize,
+    cancel: &SearchCancel,
+    found: impl Fn(FileHits) + Sync,
+) -> Result<SearchSummary, BackendError> {
+    let mut files = editor.list_tracked_files()?;
+    files.sort_by_key(|file| std::cmp::Reverse(file.latest.timestamp));
+
+    let next = AtomicUsize::new(0);
+    let searched = AtomicUsize::new(0);
+    let skipped = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, files.len().max(1)) {
+            scope.spawn(|| {
+                while !cancel.is_cancelled() {
+                    let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let Some(content) = latest_content(editor, file) else {
+                        skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    };
+                    searched.fetch_add(1, Ordering::Relaxed);
+                    let (hits, truncated) = search_text(&content, query);
+                    if !hits.is_empty() {
+                        found(FileHits {
+                            uuid: file.uuid.clone(),
+                            path: file.latest_path.clone(),
+                            hits,
+                            truncated,
+                        });
+                    }
+                }
+            });
+        }
+    });
+
+    let searched = searched.into_inner();
+    let skipped = skipped.into_inner();
+    Ok(SearchSummary {
+        searched,
+        skipped,
+        cancelled: searched + skipped < files.len(),
+    })
+}
+
+/// The newest version of `file`, unless it is too large or gone
+fn latest_content(editor: &EditorBackend, file: &TrackedFile) -> Option<String> {
+    let hash = &file.latest.hash;
+    match editor.blob_size(hash) {
+        Ok(size) if size <= MAX_FILE_BYTES => editor.restore_version(hash).ok(),
+        Ok(size) => {
+            tracing::debug!("Global search skipped {} ({} bytes)", file.uuid, size);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Global search could not read {}: {}", file.uuid, e);
+            None
+        }
+    }
+}
+
+/// The lines of `text` that have `query`, first line first, and whether
+/// there were more than [`MAX_HITS_PER_FILE`]
+pub fn search_text(text: &str, query: &str) -> (Vec<SearchHit>, bool) {
+    let query: Vec<char> = query.chars().map(fold).collect();
+    if query.is_empty() {
+        return (Vec::new(), false);
+    }
+    let mut hits = text.lines().enumerate().filter_map(|(line, content)| {
+        let found = find_in_line(content, &query)?;
+        let (snippet, highlight) = snippet(content, found);
+        Some(SearchHit {
+            line,
+            snippet,
+            highlight,
+        })
+    });
+    let found: Vec<SearchHit> = hits.by_ref().take(MAX_HITS_PER_FILE).collect();
+    let truncated = hits.next().is_some();
+    (found, truncated)
+}
+
+/// Byte range of the first match of `query`, already folded, in `line`
+fn find_in_line(line: &str, query: &[char]) -> Option<Range<usize>> {
+    line.char_indices().find_map(|(start, _)| {
+        let mut chars = line[start..].chars();
+        let mut end = start;
+        for wanted in query {
+            let c = chars.next()?;
+            if fold(c) != *wanted {
+                return None;
+            }
+            end += c.len_utf8();
+        }
+        Some(start..end)
+    })
+}
+
+/// `c` as compared: full-width ASCII as half-width, letters in lower case
+fn fold(c: char) -> char {
+    let c = match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{3000}' => ' ',
+        _ => c,
+    };
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    }
+}
+
+/// `line` cut to [`SNIPPET_CONTEXT`] characters around `found`, and where
+/// `found` ends up in it
+fn snippet(line: &str, found: Range<usize>) -> (String, Range<usize>) {
+    let before = &line[..found.start];
+    let after = &line[found.end..];
+    let before = match before.char_indices().rev().nth(SNIPPET_CONTEXT - 1) {
+        Some((start, _)) if start > 0 => format!("…{}", &before[start..]),
+        _ => before.trim_start().to_string(),
+    };
+    let after = match after.char_indices().nth(SNIPPET_CONTEXT) {
+        Some((end, _)) => format!("{}…", &after[..end]),
+        None => after.trim_end().to_string(),
+    };
+    let highlight = before.len()..before.len() + found.len();
+    (format!("{}{}{}", before, &line[found], after), highlight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn highlighted(hit: &SearchHit) -> &str {
+        &hit.snippet[hit.highlight.clone()]
+    }
+
+    #[test]
+    fn test_matches_cjk_substrings_and_folds_latin_case() {
+        let text = "　　他推开门，雨还在下。\n没有人。\n\
+                    她说：“Ｒａｉｎ，又是rain。”\n雨声里，RAIN 停了。";
+
+        let (hits, truncated) = search_text(text, "雨");
+        assert!(!truncated);
+        let lines: Vec<usize> = hits.iter().map(|hit| hit.line).collect();
+        assert_eq!(lines, [0, 3]);
+        // Leading indentation is not part of the snippet
+        assert_eq!(hits[0].snippet, "他推开门，雨还在下。");
+        assert_eq!(highlighted(&hits[0]), "雨");
+
+        // Case and full-width forms fold; the first match of a line is shown
+        let (hits, _) = search_text(text, "rain");
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].line, highlighted(&hits[0])), (2, "Ｒａｉｎ"));
+        assert_eq!((hits[1].line, highlighted(&hits[1])), (3, "RAIN"));
+
+        assert!(search_text(text, "晴").0.is_empty());
+        assert!(search_text(text, "").0.is_empty());
+    }
+
+    #[test]
+    fn test_snippets_are_cut_around_the_match() {
+        let line = format!("{}雨{}", "前".repeat(30), "后".repeat(30));
+        let (hits, _) = search_text(&line, "雨");
+        let hit = &hits[0];
+        assert_eq!(
+            hit.snippet,
+            format!("…{}雨{}…", "前".repeat(24), "后".repeat(24))
+        );
+        assert_eq!(highlighted(hit), "雨");
+
+        // One line too many is left out and said so
+        let (hits, truncated) = search_text(&"雨\n".repeat(MAX_HITS_PER_FILE + 1), "雨");
+        assert_eq!(hits.len(), MAX_HITS_PER_FILE);
+        assert!(truncated);
+        assert!(!search_text(&"雨\n".repeat(MAX_HITS_PER_FILE), "雨").1);
+    }
+
+    #[test]
+    fn test_search_all_reads_the_newest_version_of_every_file() {
+        let dir = std::env::temp_dir().join(format!("test_global_search_{}", uuid::Uuid::new_v4()));
+        let editor = EditorBackend::with_data_dir(dir.clone()).unwrap();
+        let document = |versions: &[&str]| {
+            let file = dir.join(format!("{}.txt", uuid::Uuid::new_v4()));
+            let mut uuid = String::new();
+            for content in versions {
+                std::fs::write(&file, content).unwrap();
+                uuid = editor.save(&file, content, 0).unwrap().0;
+            }
+            (uuid, file)
+        };
+
+        let (novel, novel_path) = document(&["雨夜", "晴天\n后来下了雨"]);
+        let (diary, _) = document(&["雨，还是雨"]);
+        document(&["晴"]);
+        let huge = "雨".repeat(MAX_FILE_BYTES as usize / 3 + 1);
+        document(&[&huge]);
+
+        let found = Mutex::new(Vec::new());
+        let summary = search_all(&editor, "雨", 2, &SearchCancel::new(), |hits| {
+            found.lock().unwrap().push(hits)
+        })
+        .unwrap();
+        assert_eq!(
+            summary,
+            SearchSummary {
+                searched: 3,
+                skipped: 1,
+                cancelled: false,
+            }
+        );
+        let found = found.into_inner().unwrap();
+        assert_eq!(found.len(), 2);
+        let by_uuid = |uuid: &str| found.iter().find(|file| file.uuid == uuid).unwrap();
+        // Only the newest version counts: 雨夜 was rewritten
+        let novel = by_uuid(&novel);
+        assert_eq!(novel.path.as_ref(), Some(&novel_path));
+        assert_eq!(novel.hits.len(), 1);
+        assert_eq!(
+            (novel.hits[0].line, novel.hits[0].snippet.as_str()),
+            (1, "后来下了雨")
+        );
+        assert_eq!(by_uuid(&diary).hits[0].snippet, "雨，还是雨");
+
+        // Cancelled before it started, nothing is read
+        let cancel = SearchCancel::new();
+        cancel.cancel();
+        let summary = search_all(&editor, "雨", 2, &cancel, |_| panic!("cancelled")).unwrap();
+        assert_eq!(summary.searched, 0);
+        assert!(summary.cancelled);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,147 @@
+//! The marks of every document, for the 所有批注 window: each marks file
+//! joined with the document it belongs to, as its history knows it.
+
+use crate::backend::editor_backend::EditorBackend;
+use crate::backend::sidebar_backend::{Mark, SidebarBackend};
+use crate::error::AppError;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[derive(Clone, Debug)]
+pub struct MarkedFile {
+    pub uuid: String,
+    /// Where the document was saved last; `None` if its history never says
+    pub path: Option<PathBuf>,
+    /// No history has the UUID, so the marks belong to no document the
+    /// app knows of, e.g. one deleted together with its history
+    pub orphaned: bool,
+    pub last_modified: SystemTime,
+    /// The marks by line, first line first
+    pub marks: Vec<(usize, Mark)>,
+}
+
+/// Every document with marks, and every orphaned marks file, the most
+/// recently marked first
+pub fn marked_files(
+    sidebar: &SidebarBackend,
+    editor: &EditorBackend,
+) -> Result<Vec<MarkedFile>, AppError> {
+    let mut files = Vec::new();
+    for summary in sidebar.list_all_marks()? {
+        let history = editor.load_history_by_uuid(&summary.uuid)?;
+        let orphaned = history.is_empty();
+        if summary.mark_count == 0 && !orphaned {
+            continue;
+        }
+        let path = history
+            .iter()
+            .filter(|entry| entry.file_path.is_some())
+            .max_by_key(|entry| entry.timestamp)
+            .and_then(|entry| entry.file_path.clone());
+        let mut marks: Vec<(usize, Mark)> = sidebar
+            .load_marks(&summary.uuid)?
+            .value
+            .into_iter()
+            .collect();
+        marks.sort_by_key(|(line, _)| *line);
+        files.push(MarkedFile {
+            uuid: summary.uuid,
+            path,
+            orphaned,
+            last_modified: summary.last_modified,
+            marks,
+        });
+    }
+    files.sort_by_key(|file| std::cmp::Reverse(file.last_modified));
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_marks_are_joined_with_their_documents() {
+        let dir = std::env::temp_dir().join(format!("test_marked_files_{}", uuid::Uuid::new_v4()));
+        let sidebar = SidebarBackend::with_data_dir(dir.clone()).unwrap();
+        let editor = EditorBackend::with_data_dir(dir.clone()).unwrap();
+
+        let marks = |lines: &[(usize, &str)]| -> HashMap<usize, Mark> {
+            lines
+                .iter()
+                .map(|(line, note)| {
+                    let mark = Mark {
+                        note: note.to_string(),
+                        ..Default::default()
+                    };
+                    (*line, mark)
+                })
+                .collect()
+        };
+        // A document saved once, with its history; the file itself is gone
+        let document = |keeps_path: bool| {
+            let name = uuid::Uuid::new_v4().to_string();
+            let file = dir.join(format!("{}.txt", name));
+            std::fs::write(&file, &name).unwrap();
+            let (uuid, _) = editor.save(&file, &name, 0).unwrap();
+            std::fs::remove_file(&file).unwrap();
+            if !keeps_path {
+                // A history from before versions kept their path
+                let mut history = editor.load_history_by_uuid(&uuid).unwrap();
+                history[0].file_path = None;
+                let history_path = dir.join("history").join(format!("{}.json", uuid));
+                std::fs::write(history_path, serde_json::to_string(&history).unwrap()).unwrap();
+            }
+            uuid
+        };
+
+        let novel = document(true);
+        sidebar
+            .save_marks(&novel, &marks(&[(9, "伏笔"), (2, "改名")]))
+            .unwrap();
+        let untitled = document(false);
+        sidebar.save_marks(&untitled, &marks(&[(0, "")])).unwrap();
+        // Every mark removed again, so there is nothing to show
+        let unmarked = document(true);
+        sidebar.save_marks(&unmarked, &HashMap::new()).unwrap();
+        // Marks of documents no history knows
+        sidebar.save_marks("gone", &marks(&[(4, "孤立")])).unwrap();
+        sidebar.save_marks("gone-empty", &HashMap::new()).unwrap();
+
+        let files = marked_files(&sidebar, &editor).unwrap();
+        let by_uuid = |uuid: &str| files.iter().find(|file| file.uuid == uuid).unwrap();
+        assert_eq!(files.len(), 4);
+        assert!(files.iter().all(|file| file.uuid != unmarked));
+
+        let novel = by_uuid(&novel);
+        assert!(!novel.orphaned);
+        assert!(novel.path.is_some());
+        let lines: Vec<usize> = novel.marks.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 9]);
+
+        let untitled = by_uuid(&untitled);
+        assert!(!untitled.orphaned);
+        assert_eq!(untitled.path, None);
+
+        let orphans: Vec<&str> = files
+            .iter()
+            .filter(|file| file.orphaned)
+            .map(|file| file.uuid.as_str())
+            .collect();
+        assert_eq!(orphans.len(), 2);
+        assert!(orphans.contains(&"gone") && orphans.contains(&"gone-empty"));
+        assert_eq!(by_uuid("gone").marks[0].1.note, "孤立");
+
+        // Deleting the orphans leaves the rest alone
+        for uuid in orphans {
+            sidebar.delete_marks(uuid).unwrap();
+        }
+        sidebar.delete_marks("gone").unwrap();
+        let files = marked_files(&sidebar, &editor).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| !file.orphaned));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
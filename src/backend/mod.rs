@@ -1,5 +1,16 @@
 pub mod ai_backend;
+pub mod ai_dispatch;
 pub mod ai_panel_backend;
+pub mod daily_log;
 pub mod editor_backend;
+pub mod file_settings;
+#[cfg(feature = "gui")]
+pub mod font_cache;
+pub mod global_search;
+pub mod marked_files;
+pub mod productivity;
+pub mod recovery;
+pub mod redaction;
 pub mod sidebar_backend;
 pub mod time_backend;
+pub mod usage_log;
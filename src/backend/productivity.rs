@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often word counts are sampled while the editor is focused
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+/// Width of the rolling WPM window
+pub const ROLLING_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Enough samples to cover the rolling window at the sampling interval
+const MAX_SAMPLES: usize = 64;
+
+/// Word count observed at a point in time, relative to tracker start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WordSample {
+    pub at: Duration,
+    pub words: usize,
+}
+
+/// Numbers shown when hovering the title-bar stats
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProductivityMetrics {
+    /// Net words per minute over the current (or last) focus session
+    pub session_wpm: f64,
+    /// Words added minus words removed during that session
+    pub session_net_words: i64,
+    /// Net words per minute over the last `ROLLING_WINDOW`
+    pub rolling_wpm: f64,
+}
+
+/// Tracks word-count samples across focus sessions
+pub struct ProductivityTracker {
+    started: Instant,
+    session_start: Option<WordSample>,
+    session_end: Option<WordSample>,
+    samples: VecDeque<WordSample>,
+}
+
+impl ProductivityTracker {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            session_start: None,
+            session_end: None,
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+        }
+    }
+
+    /// Record the word count at a focus transition
+    pub fn on_focus_change(&mut self, focused: bool, words: usize) {
+        let sample = self.sample_now(words);
+        if focused {
+            self.session_start = Some(sample);
+            self.session_end = None;
+        } else {
+            self.session_end = Some(sample);
+        }
+        self.push(sample);
+    }
+
+    /// Record a sample if the sampling interval has passed since the last one
+    pub fn maybe_sample(&mut self, words: usize) {
+        let sample = self.sample_now(words);
+        let due = self
+            .samples
+            .back()
+            .is_none_or(|last| sample.at.saturating_sub(last.at) >= SAMPLE_INTERVAL);
+        if due {
+            self.push(sample);
+        }
+    }
+
+    /// Metrics as of now, given the current word count
+    pub fn metrics(&self, words: usize) -> ProductivityMetrics {
+        let now = self.sample_now(words);
+        let samples: Vec<WordSample> = self.samples.iter().copied().collect();
+        compute_metrics(self.session_start, self.session_end, &samples, now)
+    }
+
+    fn sample_now(&self, words: usize) -> WordSample {
+        WordSample {
+            at: self.started.elapsed(),
+            words,
+        }
+    }
+
+    fn push(&mut self, sample: WordSample) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+}
+
+impl Default for ProductivityTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compute session and rolling WPM from recorded samples.
+///
+/// A session runs from `session_start` to `session_end`, or to `now` while the
+/// editor is still focused. The rolling rate compares `now` with the oldest
+/// sample inside `ROLLING_WINDOW`. Rates are net: deleting text lowers them.
+pub fn compute_metrics(
+    session_start: Option<WordSample>,
+    session_end: Option<WordSample>,
+    samples: &[WordSample],
+    now: WordSample,
+) -> ProductivityMetrics {
+    let mut metrics = ProductivityMetrics::default();
+
+    if let Some(start) = session_start {
+        let end = session_end.unwrap_or(now);
+        metrics.session_net_words = end.words as i64 - start.words as i64;
+        metrics.session_wpm = rate(start, end);
+    }
+
+    let window_start = now.at.saturating_sub(ROLLING_WINDOW);
+    if let Some(oldest) = samples.iter().find(|s| s.at >= window_start) {
+        metrics.rolling_wpm = rate(*oldest, now);
+    }
+
+    metrics
+}
+
+fn rate(from: WordSample, to: WordSample) -> f64 {
+    let minutes = to.at.saturating_sub(from.at).as_secs_f64() / 60.0;
+    if minutes <= 0.0 {
+        return 0.0;
+    }
+    (to.words as f64 - from.words as f64) / minutes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(secs: u64, words: usize) -> WordSample {
+        WordSample {
+            at: Duration::from_secs(secs),
+            words,
+        }
+    }
+
+    #[test]
+    fn test_session_wpm_uses_focus_span() {
+        let start = sample(60, 100);
+        let end = sample(180, 160);
+        let metrics = compute_metrics(Some(start), Some(end), &[start, end], sample(600, 160));
+
+        assert_eq!(metrics.session_net_words, 60);
+        assert!((metrics.session_wpm - 30.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_open_session_runs_until_now() {
+        let start = sample(0, 0);
+        let metrics = compute_metrics(Some(start), None, &[start], sample(120, 50));
+
+        assert_eq!(metrics.session_net_words, 50);
+        assert!((metrics.session_wpm - 25.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_rolling_wpm_ignores_samples_outside_window() {
+        let samples = [sample(0, 0), sample(300, 1000), sample(900, 1100)];
+        // Window is [300, 900]; the first sample is too old to count
+        let metrics = compute_metrics(None, None, &samples, sample(900, 1100));
+
+        assert!((metrics.rolling_wpm - 10.0).abs() < f64::EPSILON);
+        assert_eq!(metrics.session_wpm, 0.0);
+    }
+
+    #[test]
+    fn test_deleting_text_gives_negative_rate() {
+        let start = sample(0, 200);
+        let metrics = compute_metrics(Some(start), None, &[start], sample(60, 150));
+
+        assert_eq!(metrics.session_net_words, -50);
+        assert!(metrics.session_wpm < 0.0);
+    }
+
+    #[test]
+    fn test_zero_elapsed_time_has_no_rate() {
+        let start = sample(10, 10);
+        let metrics = compute_metrics(Some(start), None, &[start], sample(10, 10));
+
+        assert_eq!(metrics, ProductivityMetrics::default());
+    }
+}
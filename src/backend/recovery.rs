@@ -0,0 +1,114 @@
+use crate::file::write_atomic;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+const RECOVERY_DIR: &str = "recovery";
+/// Name prefix of documents kept on exit
+const UNTITLED_PREFIX: &str = "未命名";
+/// Name prefix of documents kept by the panic hook, until the user is told
+const PANIC_PREFIX: &str = "panic";
+/// What a panic file is renamed to once the user was told about it
+const RECOVERED_PREFIX: &str = "已恢复";
+
+#[derive(Error, Debug)]
+pub enum RecoveryError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Documents that had to be kept without asking for a file name: untitled
+/// ones when the app quit, and the unsaved ones when it crashed
+pub struct RecoveryBackend {
+    recovery_dir: PathBuf,
+}
+
+impl RecoveryBackend {
+    /// Keep recovered documents in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, RecoveryError> {
+        let recovery_dir = data_dir.join(RECOVERY_DIR);
+        fs::create_dir_all(&recovery_dir)?;
+        Ok(Self { recovery_dir })
+    }
+
+    /// Keep `content` in a file of its own; returns where it went
+    pub fn store(&self, content: &str) -> Result<PathBuf, RecoveryError> {
+        self.write(UNTITLED_PREFIX, content)
+    }
+
+    /// Keep the content of one of the documents open when the app panicked
+    pub fn store_panic(&self, content: &str) -> Result<PathBuf, RecoveryError> {
+        self.write(PANIC_PREFIX, content)
+    }
+
+    /// Panic files the user was not told about yet, renamed so they are
+    /// only reported once; returns their new paths
+    pub fn take_panic_files(&self) -> Result<Vec<PathBuf>, RecoveryError> {
+        let mut recovered = Vec::new();
+        for entry in fs::read_dir(&self.recovery_dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if let Some(rest) = name.strip_prefix(PANIC_PREFIX) {
+                let new_path = path.with_file_name(format!("{}{}", RECOVERED_PREFIX, rest));
+                fs::rename(&path, &new_path)?;
+                recovered.push(new_path);
+            }
+        }
+        recovered.sort();
+        Ok(recovered)
+    }
+
+    fn write(&self, prefix: &str, content: &str) -> Result<PathBuf, RecoveryError> {
+        let name = format!(
+            "{}-{}-{}.txt",
+            prefix,
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        let path = self.recovery_dir.join(name);
+        write_atomic(&path, content)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_backend() -> RecoveryBackend {
+        let recovery_dir =
+            std::env::temp_dir().join(format!("test_recovery_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&recovery_dir).unwrap();
+        RecoveryBackend { recovery_dir }
+    }
+
+    #[test]
+    fn test_each_document_gets_its_own_file() {
+        let backend = test_backend();
+
+        let first = backend.store("第一篇").unwrap();
+        let second = backend.store("第二篇").unwrap();
+        assert_ne!(first, second);
+        assert!(first.starts_with(&backend.recovery_dir));
+        assert_eq!(fs::read_to_string(&second).unwrap(), "第二篇");
+
+        let _ = fs::remove_dir_all(&backend.recovery_dir);
+    }
+
+    #[test]
+    fn test_panic_files_are_reported_once() {
+        let backend = test_backend();
+        backend.store("未命名的").unwrap();
+        backend.store_panic("崩溃前的").unwrap();
+
+        let recovered = backend.take_panic_files().unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(fs::read_to_string(&recovered[0]).unwrap(), "崩溃前的");
+        assert!(backend.take_panic_files().unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(&backend.recovery_dir);
+    }
+}
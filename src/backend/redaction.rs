@@ -0,0 +1,232 @@
+//! Keeps private parts of a document out of AI requests.
+//!
+//! Lines carrying a mark flagged private, and the body of a leading `---`
+//! frontmatter block, are replaced line by line with [`REDACTED`] so line
+//! numbers in the redacted text still match the editor.
+
+use crate::backend::ai_backend::{AiDocumentContext, AiSelectionContext};
+use crate::backend::sidebar_backend::Mark;
+use std::collections::HashMap;
+use std::ops::Range;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Leeway for whitespace between a selection and its context sentences
+const CONTEXT_SLACK_CHARS: usize = 4;
+
+/// Result of redacting a document
+#[derive(Debug, Default, PartialEq)]
+pub struct Redaction {
+    pub content: String,
+    /// Characters replaced, not counting line breaks
+    pub withheld_chars: usize,
+    /// Char ranges of withheld text in the original, in order
+    ranges: Vec<Range<usize>>,
+}
+
+/// Redact private lines and the frontmatter body of `content`.
+pub fn redact(content: &str, marks: &HashMap<usize, Mark>) -> Redaction {
+    let frontmatter = frontmatter_body(content);
+    let mut redaction = Redaction {
+        content: String::with_capacity(content.len()),
+        ..Default::default()
+    };
+    let mut char_pos = 0;
+
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let text = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[text.len()..];
+        let text_chars = text.chars().count();
+        let private = frontmatter
+            .as_ref()
+            .is_some_and(|body| body.contains(&index))
+            || marks.get(&index).is_some_and(|mark| mark.private);
+
+        if private && !text.trim().is_empty() {
+            redaction.content.push_str(REDACTED);
+            redaction.content.push_str(ending);
+            redaction.ranges.push(char_pos..char_pos + text_chars);
+            redaction.withheld_chars += text_chars;
+        } else {
+            redaction.content.push_str(line);
+        }
+        char_pos += line.chars().count();
+    }
+
+    redaction
+}
+
+/// Line indices between the opening and closing `---` of a frontmatter block
+/// at the very start of `content`, if there is one.
+fn frontmatter_body(content: &str) -> Option<Range<usize>> {
+    let mut lines = content.lines();
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+    let closing = lines.position(|line| matches!(line.trim_end(), "---" | "..."))?;
+    Some(1..closing + 1)
+}
+
+impl Redaction {
+    /// Apply the same redaction to a selection taken from the original text.
+    ///
+    /// Withheld parts of the selected text are replaced, and a context
+    /// sentence is dropped when withheld text lies within it.
+    pub fn redact_selection(&self, selection: &mut AiSelectionContext) {
+        let (start, end) = (selection.start_char, selection.end_char);
+        let chars: Vec<char> = selection.text.chars().collect();
+        let mut text = String::with_capacity(selection.text.len());
+        let mut pos = 0;
+        for range in &self.ranges {
+            let overlap_start = range.start.max(start);
+            let overlap_end = range.end.min(end);
+            if overlap_start >= overlap_end {
+                continue;
+            }
+            let from = (overlap_start - start).min(chars.len());
+            text.extend(&chars[pos.min(from)..from]);
+            text.push_str(REDACTED);
+            pos = (overlap_end - start).min(chars.len());
+        }
+        text.extend(&chars[pos.min(chars.len())..]);
+        selection.text = text;
+
+        let withheld_within = |from: usize, to: usize| {
+            self.ranges
+                .iter()
+                .any(|range| range.start < to && from < range.end)
+        };
+        let before_from =
+            start.saturating_sub(selection.context_before.chars().count() + CONTEXT_SLACK_CHARS);
+        if withheld_within(before_from, start) {
+            selection.context_before.clear();
+        }
+        let after_to = end + selection.context_after.chars().count() + CONTEXT_SLACK_CHARS;
+        if withheld_within(end, after_to) {
+            selection.context_after.clear();
+        }
+    }
+}
+
+/// Redact a request's document and selection in place.
+///
+/// Returns the number of characters withheld.
+pub fn redact_document(document: &mut AiDocumentContext, marks: &HashMap<usize, Mark>) -> usize {
+    let redaction = redact(&document.content, marks);
+    if redaction.withheld_chars == 0 {
+        return 0;
+    }
+    if let Some(selection) = document.selection.as_mut() {
+        redaction.redact_selection(selection);
+    }
+    document.content = redaction.content;
+    redaction.withheld_chars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn private_marks(lines: &[usize]) -> HashMap<usize, Mark> {
+        lines
+            .iter()
+            .map(|line| {
+                (
+                    *line,
+                    Mark {
+                        private: true,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_redacts_private_marks_and_frontmatter() {
+        let content = "---\nauthor: 张三\nphone: 123\n---\n正文第一段\n电话 138\n\n结尾";
+        let mut marks = private_marks(&[5, 6]);
+        // Ordinary marks are sent as usual
+        marks.insert(4, Mark::default());
+
+        let redaction = redact(content, &marks);
+
+        assert_eq!(
+            redaction.content,
+            "---\n[REDACTED]\n[REDACTED]\n---\n正文第一段\n[REDACTED]\n\n结尾"
+        );
+        assert_eq!(redaction.withheld_chars, 10 + 10 + 6);
+        assert_eq!(redaction.content.lines().count(), content.lines().count());
+    }
+
+    #[test]
+    fn test_leaves_documents_without_private_parts_alone() {
+        // A horizontal rule later on, or an unclosed block, is not frontmatter
+        for content in [
+            "正文\n---\n更多\n---",
+            "---\n没有结束",
+            "",
+            "一行\r\n两行\r\n",
+        ] {
+            let redaction = redact(content, &private_marks(&[99]));
+            assert_eq!(redaction.content, content);
+            assert_eq!(redaction.withheld_chars, 0);
+        }
+    }
+
+    #[test]
+    fn test_keeps_crlf_line_endings() {
+        let redaction = redact("公开\r\n私密\r\n公开", &private_marks(&[1]));
+        assert_eq!(redaction.content, "公开\r\n[REDACTED]\r\n公开");
+        assert_eq!(redaction.withheld_chars, 2);
+    }
+
+    #[test]
+    fn test_redacts_overlapping_part_of_selection() {
+        let content = "第一句。\n秘密地址\n第三句。";
+        let mut document = AiDocumentContext {
+            title: "t".to_string(),
+            content: content.to_string(),
+            selection: Some(AiSelectionContext {
+                anchor_id: 1,
+                start_char: 2,
+                end_char: 12,
+                text: "句。\n秘密地址\n第三".to_string(),
+                context_before: "第一".to_string(),
+                context_after: "句。".to_string(),
+            }),
+        };
+
+        let withheld = redact_document(&mut document, &private_marks(&[1]));
+
+        assert_eq!(withheld, 4);
+        assert_eq!(document.content, "第一句。\n[REDACTED]\n第三句。");
+        let selection = document.selection.unwrap();
+        assert_eq!(selection.text, "句。\n[REDACTED]\n第三");
+        assert_eq!(selection.context_before, "第一");
+        assert_eq!(selection.context_after, "句。");
+    }
+
+    #[test]
+    fn test_drops_context_that_touches_withheld_text() {
+        let content = "秘密\n选中的句子";
+        let mut document = AiDocumentContext {
+            title: "t".to_string(),
+            content: content.to_string(),
+            selection: Some(AiSelectionContext {
+                anchor_id: 1,
+                start_char: 3,
+                end_char: 8,
+                text: "选中的句子".to_string(),
+                context_before: "秘密".to_string(),
+                context_after: String::new(),
+            }),
+        };
+
+        redact_document(&mut document, &private_marks(&[0]));
+
+        let selection = document.selection.unwrap();
+        assert_eq!(selection.text, "选中的句子");
+        assert!(selection.context_before.is_empty());
+    }
+}
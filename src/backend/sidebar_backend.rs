@@ -1,9 +1,10 @@
-use crate::config::Config;
+use crate::file::{Loaded, read_json_or_set_aside, write_atomic};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
+use std::time::SystemTime;
 use thiserror::Error;
 
 const MARKS_DIR: &str = "marks";
@@ -11,6 +12,9 @@ const MARKS_DIR: &str = "marks";
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Mark {
     pub note: String,
+    /// Keep the marked line out of AI requests
+    #[serde(default)]
+    pub private: bool,
 }
 
 #[derive(Error, Debug)]
@@ -22,14 +26,21 @@ pub enum SidebarError {
     Json(#[from] serde_json::Error),
 }
 
+/// A marks file, as found by [`SidebarBackend::list_all_marks`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarksSummary {
+    pub uuid: String,
+    pub mark_count: usize,
+    pub last_modified: SystemTime,
+}
+
 pub struct SidebarBackend {
     marks_dir: PathBuf,
 }
 
 impl SidebarBackend {
-    pub fn new() -> Result<Self, SidebarError> {
-        let config = Config::default();
-        let data_dir = config.data_dir();
+    /// Keep the marks in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, SidebarError> {
         let marks_dir = data_dir.join(MARKS_DIR);
 
         fs::create_dir_all(&marks_dir)?;
@@ -40,20 +51,50 @@ impl SidebarBackend {
     pub fn save_marks(&self, uuid: &str, marks: &HashMap<usize, Mark>) -> Result<(), SidebarError> {
         let file_path = self.marks_dir.join(format!("{}.json", uuid));
         let content = serde_json::to_string_pretty(marks)?;
-        fs::write(file_path, content)?;
+        write_atomic(&file_path, &content)?;
         Ok(())
     }
 
-    pub fn load_marks(&self, uuid: &str) -> Result<HashMap<usize, Mark>, SidebarError> {
+    /// The marks of the file `uuid`; none if it has none yet, or if its
+    /// marks file is corrupt, which is then kept aside
+    pub fn load_marks(&self, uuid: &str) -> Result<Loaded<HashMap<usize, Mark>>, SidebarError> {
         let file_path = self.marks_dir.join(format!("{}.json", uuid));
+        Ok(read_json_or_set_aside(&file_path)?.map(Option::unwrap_or_default))
+    }
 
-        if !file_path.exists() {
-            return Ok(HashMap::new());
+    /// Every marks file, by UUID. Corrupt ones are set aside as in
+    /// [`Self::load_marks`] and left out.
+    pub fn list_all_marks(&self) -> Result<Vec<MarksSummary>, SidebarError> {
+        let mut summaries = Vec::new();
+        for entry in fs::read_dir(&self.marks_dir)? {
+            let path = entry?.path();
+            let Some(uuid) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .filter(|_| path.extension().and_then(|s| s.to_str()) == Some("json"))
+            else {
+                continue;
+            };
+            let last_modified = fs::metadata(&path)?.modified()?;
+            let mark_count = self.load_marks(uuid)?.value.len();
+            if path.exists() {
+                summaries.push(MarksSummary {
+                    uuid: uuid.to_string(),
+                    mark_count,
+                    last_modified,
+                });
+            }
         }
+        summaries.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+        Ok(summaries)
+    }
 
-        let content = fs::read_to_string(file_path)?;
-        let marks = serde_json::from_str(&content)?;
-        Ok(marks)
+    /// Delete the marks of the file `uuid`, if it has any
+    pub fn delete_marks(&self, uuid: &str) -> Result<(), SidebarError> {
+        match fs::remove_file(self.marks_dir.join(format!("{}.json", uuid))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -65,13 +106,7 @@ mod tests {
 
     fn setup_test_backend() -> (SidebarBackend, PathBuf) {
         let test_dir = std::env::temp_dir().join(format!("test_sidebar_{}", Uuid::new_v4()));
-        let marks_dir = test_dir.join(MARKS_DIR);
-        fs::create_dir_all(&marks_dir).unwrap();
-
-        let backend = SidebarBackend {
-            marks_dir: marks_dir.clone(),
-        };
-
+        let backend = SidebarBackend::with_data_dir(test_dir.clone()).unwrap();
         (backend, test_dir)
     }
 
@@ -89,15 +124,70 @@ mod tests {
             1,
             Mark {
                 note: "Test note".to_string(),
+                ..Default::default()
             },
         );
 
         backend.save_marks(&uuid, &marks).unwrap();
 
-        let loaded_marks = backend.load_marks(&uuid).unwrap();
+        let loaded_marks = backend.load_marks(&uuid).unwrap().value;
         assert_eq!(loaded_marks.len(), 1);
         assert_eq!(loaded_marks.get(&1).unwrap().note, "Test note");
 
         cleanup_test_dir(&test_dir);
     }
+
+    #[test]
+    fn test_truncated_marks_are_set_aside() {
+        let (backend, test_dir) = setup_test_backend();
+        let uuid = Uuid::new_v4().to_string();
+        let mut marks = HashMap::new();
+        marks.insert(
+            3,
+            Mark {
+                note: "伏笔，第十章回收".to_string(),
+                ..Default::default()
+            },
+        );
+        backend.save_marks(&uuid, &marks).unwrap();
+
+        // A crash mid-write, cut inside a Chinese character
+        let path = test_dir.join(MARKS_DIR).join(format!("{}.json", uuid));
+        let full = fs::read(&path).unwrap();
+        let cut = full.windows(3).position(|w| w == "章".as_bytes()).unwrap() + 1;
+        fs::write(&path, &full[..cut]).unwrap();
+
+        let loaded = backend.load_marks(&uuid).unwrap();
+        assert!(loaded.value.is_empty());
+        assert!(!path.exists());
+        let copies = corrupt_copies(&path);
+        assert_eq!(copies.len(), 1);
+        assert_eq!(loaded.set_aside.as_ref(), Some(&copies[0]));
+        assert_eq!(fs::read(&copies[0]).unwrap(), &full[..cut]);
+
+        // Marks made afterwards save and load as usual
+        backend.save_marks(&uuid, &marks).unwrap();
+        assert_eq!(
+            backend.load_marks(&uuid).unwrap().value[&3].note,
+            "伏笔，第十章回收"
+        );
+        assert_eq!(corrupt_copies(&path).len(), 1);
+
+        cleanup_test_dir(&test_dir);
+    }
+
+    /// Files next to `path` that a corrupt copy of it was moved to
+    fn corrupt_copies(path: &Path) -> Vec<PathBuf> {
+        let prefix = format!("{}.corrupt-", path.file_name().unwrap().to_string_lossy());
+        fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|copy| {
+                copy.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with(&prefix)
+            })
+            .collect()
+    }
 }
@@ -1,21 +1,173 @@
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
 
+/// Number of events kept in the tracking thread's diagnostic ring buffer
+pub const MAX_RECENT_EVENTS: usize = 200;
+
+/// How often focused time is folded into the shared counter
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A keystroke counts the following interval of this length as typing
+pub const TYPING_WINDOW: Duration = Duration::from_secs(10);
+
+/// How long `recent_events` waits for the tracking thread to answer
+const EVENTS_REPLY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How long the tracking thread waits for a message before checking the time
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where the tracking thread gets the time and its messages from.
+///
+/// [`SystemClock`] is the real one. Another clock can hand out messages at
+/// moments of its choosing and move time forward without waiting, so the
+/// tracking logic runs as fast as the clock allows; tests use this to
+/// replay hours of focus and idle time in milliseconds.
+pub trait Clock: Send + 'static {
+    /// The current moment
+    fn now(&self) -> Instant;
+
+    /// Wait up to `timeout` for the next message on `receiver`, as
+    /// [`Receiver::recv_timeout`] does
+    fn recv_timeout(
+        &self,
+        receiver: &Receiver<TimeMessage>,
+        timeout: Duration,
+    ) -> Result<TimeMessage, RecvTimeoutError>;
+}
+
+/// The wall clock, waiting on the channel for real
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn recv_timeout(
+        &self,
+        receiver: &Receiver<TimeMessage>,
+        timeout: Duration,
+    ) -> Result<TimeMessage, RecvTimeoutError> {
+        receiver.recv_timeout(timeout)
+    }
+}
+
 /// Messages sent to the time tracking thread
 pub enum TimeMessage {
     /// Update focus state: true for focused, false for not focused
     FocusUpdate(bool),
+    /// The editor saw a text or key event
+    TypingActivity,
+    /// The app took `u64` milliseconds out of the counter (e.g. on save)
+    Flushed(u64),
+    /// Ask the thread for a snapshot of its recent events
+    RecentEvents(Sender<Vec<TimeEvent>>),
     /// Stop the time tracking thread
     Stop,
 }
 
+/// What the tracking thread decided to do at a given moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeEventKind {
+    /// A focus update was received
+    FocusUpdate(bool),
+    /// Milliseconds moved out of the counter by `get_and_reset_writing_time`
+    Flush(u64),
+    /// Milliseconds added to the counter
+    Accumulate(u64),
+}
+
+/// A single entry in the tracking thread's event history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeEvent {
+    pub timestamp: DateTime<Local>,
+    pub kind: TimeEventKind,
+}
+
+/// Bounded history of tracking decisions, oldest first
+#[derive(Debug, Default)]
+struct EventLog {
+    events: VecDeque<TimeEvent>,
+}
+
+impl EventLog {
+    fn record(&mut self, kind: TimeEventKind) {
+        if self.events.len() == MAX_RECENT_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(TimeEvent {
+            timestamp: Local::now(),
+            kind,
+        });
+    }
+
+    fn snapshot(&self) -> Vec<TimeEvent> {
+        self.events.iter().copied().collect()
+    }
+}
+
+/// Counts typing time as the union of `TYPING_WINDOW`-long intervals that
+/// start at each keystroke
+struct TypingClock {
+    active_until: Option<Instant>,
+    last_fold: Instant,
+}
+
+impl TypingClock {
+    fn new(now: Instant) -> Self {
+        Self {
+            active_until: None,
+            last_fold: now,
+        }
+    }
+
+    /// Register a keystroke at `at`, returning typing time completed before it
+    fn activity(&mut self, at: Instant) -> u64 {
+        let ms = self.fold(at);
+        self.active_until = Some(at + TYPING_WINDOW);
+        ms
+    }
+
+    /// Milliseconds of typing between the last fold and `now`
+    fn fold(&mut self, now: Instant) -> u64 {
+        let ms = self.active_until.map_or(0, |until| {
+            until
+                .min(now)
+                .saturating_duration_since(self.last_fold)
+                .as_millis() as u64
+        });
+        self.last_fold = self.last_fold.max(now);
+        ms
+    }
+
+    /// Close any open typing interval, e.g. when focus is lost
+    fn stop(&mut self, now: Instant) -> u64 {
+        let ms = self.fold(now);
+        self.active_until = None;
+        ms
+    }
+}
+
+/// Millisecond counters shared between the app and the tracking thread
+#[derive(Default)]
+struct Counters {
+    /// Focused time since the last `get_and_reset_writing_time`
+    writing: AtomicU64,
+    /// Focused time since the backend was created
+    session_writing: AtomicU64,
+    /// Typing time since the backend was created
+    typing: AtomicU64,
+}
+
 /// Time backend for tracking writing time when editor is focused
 pub struct TimeBackend {
-    /// Total writing time in milliseconds
-    writing_time: Arc<AtomicU64>,
+    counters: Arc<Counters>,
     /// Sender to communicate with the time tracking thread
     sender: Sender<TimeMessage>,
     /// Handle to the time tracking thread
@@ -25,16 +177,21 @@ pub struct TimeBackend {
 impl TimeBackend {
     /// Create a new TimeBackend
     pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+
+    /// Create a TimeBackend whose tracking thread runs on `clock`
+    pub fn with_clock(clock: impl Clock) -> Self {
         let (sender, receiver) = mpsc::channel();
-        let writing_time = Arc::new(AtomicU64::new(0));
+        let counters = Arc::new(Counters::default());
 
-        let writing_time_clone = Arc::clone(&writing_time);
+        let counters_clone = Arc::clone(&counters);
         let thread_handle = thread::spawn(move || {
-            Self::time_tracking_loop(receiver, writing_time_clone);
+            Self::time_tracking_loop(receiver, counters_clone, clock);
         });
 
         Self {
-            writing_time,
+            counters,
             sender,
             _thread_handle: thread_handle,
         }
@@ -42,13 +199,29 @@ impl TimeBackend {
 
     /// Get the current writing time in seconds and reset the counter
     pub fn get_and_reset_writing_time(&self) -> u64 {
-        let time_ms = self.writing_time.swap(0, Ordering::Relaxed);
+        let time_ms = self.counters.writing.swap(0, Ordering::Relaxed);
+        let _ = self.sender.send(TimeMessage::Flushed(time_ms));
         time_ms / 1000
     }
 
     /// Get the current writing time in seconds
     pub fn get_writing_time(&self) -> u64 {
-        self.writing_time.load(Ordering::Relaxed) / 1000
+        self.counters.writing.load(Ordering::Relaxed) / 1000
+    }
+
+    /// Get the focused time since startup in milliseconds, unaffected by resets
+    pub fn session_writing_ms(&self) -> u64 {
+        self.counters.session_writing.load(Ordering::Relaxed)
+    }
+
+    /// Get the active typing time since startup in milliseconds
+    pub fn session_typing_ms(&self) -> u64 {
+        self.counters.typing.load(Ordering::Relaxed)
+    }
+
+    /// Tell the tracker that the user just typed something
+    pub fn record_typing(&self) {
+        let _ = self.sender.send(TimeMessage::TypingActivity);
     }
 
     /// Update the focus state
@@ -56,39 +229,97 @@ impl TimeBackend {
         let _ = self.sender.send(TimeMessage::FocusUpdate(focused));
     }
 
+    /// Snapshot of the tracking thread's most recent decisions, oldest first.
+    ///
+    /// Returns an empty list if the thread does not answer in time.
+    pub fn recent_events(&self) -> Vec<TimeEvent> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        if self
+            .sender
+            .send(TimeMessage::RecentEvents(reply_tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+        reply_rx
+            .recv_timeout(EVENTS_REPLY_TIMEOUT)
+            .unwrap_or_default()
+    }
+
     /// The main time tracking loop that runs in a separate thread
-    fn time_tracking_loop(receiver: Receiver<TimeMessage>, writing_time: Arc<AtomicU64>) {
+    ///
+    /// While focused, elapsed time is folded into the counters once per
+    /// `TICK_INTERVAL` so readers see it advance live. `focus_start_time` always
+    /// marks the last fold, so each millisecond is only ever added once.
+    fn time_tracking_loop(
+        receiver: Receiver<TimeMessage>,
+        counters: Arc<Counters>,
+        clock: impl Clock,
+    ) {
         let mut is_focused = false;
-        let mut focus_start_time = Instant::now();
+        let mut focus_start_time = clock.now();
+        let mut typing = TypingClock::new(clock.now());
+        let mut log = EventLog::default();
 
         loop {
             // Check for messages with a timeout
-            match receiver.recv_timeout(Duration::from_millis(100)) {
+            let mut typed_ms = 0;
+            match clock.recv_timeout(&receiver, POLL_INTERVAL) {
                 Ok(TimeMessage::FocusUpdate(focused)) => {
+                    log.record(TimeEventKind::FocusUpdate(focused));
                     if focused && !is_focused {
                         // Just gained focus, start timing
-                        focus_start_time = Instant::now();
+                        focus_start_time = clock.now();
                     } else if !focused && is_focused {
-                        // Just lost focus, add accumulated time
-                        let elapsed_ms = focus_start_time.elapsed().as_millis() as u64;
-                        writing_time.fetch_add(elapsed_ms, Ordering::Relaxed);
+                        // Just lost focus, add the time since the last tick
+                        let elapsed_ms =
+                            Self::fold_elapsed(&mut focus_start_time, clock.now(), &counters);
+                        log.record(TimeEventKind::Accumulate(elapsed_ms));
+                        typed_ms += typing.stop(clock.now());
                     }
                     is_focused = focused;
                 }
+                Ok(TimeMessage::TypingActivity) => {
+                    typed_ms += typing.activity(clock.now());
+                }
+                Ok(TimeMessage::Flushed(ms)) => {
+                    log.record(TimeEventKind::Flush(ms));
+                }
+                Ok(TimeMessage::RecentEvents(reply)) => {
+                    let _ = reply.send(log.snapshot());
+                }
                 Ok(TimeMessage::Stop) => {
                     // Add any remaining time before stopping
                     if is_focused {
-                        let elapsed_ms = focus_start_time.elapsed().as_millis() as u64;
-                        writing_time.fetch_add(elapsed_ms, Ordering::Relaxed);
+                        Self::fold_elapsed(&mut focus_start_time, clock.now(), &counters);
                     }
+                    let typed_ms = typing.stop(clock.now());
+                    counters.typing.fetch_add(typed_ms, Ordering::Relaxed);
                     break;
                 }
-                Err(_) => {
-                    // Timeout, no action needed - timing is handled on focus changes
-                }
+                Err(_) => {}
+            }
+
+            let now = clock.now();
+            if is_focused && now.saturating_duration_since(focus_start_time) >= TICK_INTERVAL {
+                Self::fold_elapsed(&mut focus_start_time, now, &counters);
             }
+            typed_ms += typing.fold(now);
+            counters.typing.fetch_add(typed_ms, Ordering::Relaxed);
         }
     }
+
+    /// Add the time from `since` to `now` to the focus counters and restart
+    /// `since` from `now`
+    fn fold_elapsed(since: &mut Instant, now: Instant, counters: &Counters) -> u64 {
+        let elapsed_ms = now.saturating_duration_since(*since).as_millis() as u64;
+        counters.writing.fetch_add(elapsed_ms, Ordering::Relaxed);
+        counters
+            .session_writing
+            .fetch_add(elapsed_ms, Ordering::Relaxed);
+        *since = now;
+        elapsed_ms
+    }
 }
 
 impl Default for TimeBackend {
@@ -108,42 +339,325 @@ impl Drop for TimeBackend {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-    use std::time::Duration;
+    use std::cell::{Cell, RefCell};
+
+    /// One step of a [`ScriptedClock`]'s script
+    enum Step {
+        /// A message arrives
+        Send(TimeMessage),
+        /// No message arrives for this long, while the thread keeps polling
+        Idle(Duration),
+        /// The thread does not run for this long, e.g. while the machine sleeps
+        Suspend(Duration),
+        /// The app does something, at no cost in time
+        Run(Box<dyn FnOnce() + Send>),
+    }
+
+    /// A clock that plays a script in virtual time, then stops the loop
+    struct ScriptedClock {
+        now: Cell<Instant>,
+        steps: RefCell<VecDeque<Step>>,
+    }
+
+    impl Clock for ScriptedClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        fn recv_timeout(
+            &self,
+            _receiver: &Receiver<TimeMessage>,
+            timeout: Duration,
+        ) -> Result<TimeMessage, RecvTimeoutError> {
+            loop {
+                let step = self.steps.borrow_mut().pop_front();
+                match step {
+                    None => return Ok(TimeMessage::Stop),
+                    Some(Step::Send(message)) => return Ok(message),
+                    Some(Step::Idle(left)) => {
+                        if left > timeout {
+                            self.steps
+                                .borrow_mut()
+                                .push_front(Step::Idle(left - timeout));
+                        }
+                        self.now.set(self.now.get() + left.min(timeout));
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    Some(Step::Suspend(gap)) => {
+                        self.now.set(self.now.get() + gap);
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    Some(Step::Run(action)) => action(),
+                }
+            }
+        }
+    }
+
+    /// Run the tracking loop on `counters` until the script `steps` is done
+    fn run(counters: &Arc<Counters>, steps: Vec<Step>) {
+        let clock = ScriptedClock {
+            now: Cell::new(Instant::now()),
+            steps: RefCell::new(steps.into()),
+        };
+        let (_sender, receiver) = mpsc::channel();
+        TimeBackend::time_tracking_loop(receiver, Arc::clone(counters), clock);
+    }
+
+    fn focus(focused: bool) -> Step {
+        Step::Send(TimeMessage::FocusUpdate(focused))
+    }
+
+    fn idle_ms(ms: u64) -> Step {
+        Step::Idle(Duration::from_millis(ms))
+    }
+
+    /// Check the writing counter at this point of the script
+    fn expect_writing_ms(counters: &Arc<Counters>, expected: u64) -> Step {
+        let counters = Arc::clone(counters);
+        Step::Run(Box::new(move || {
+            assert_eq!(counters.writing.load(Ordering::Relaxed), expected);
+        }))
+    }
 
     #[test]
     fn test_time_accumulation() {
-        let backend = TimeBackend::new();
+        let counters = Arc::new(Counters::default());
 
-        // Initially should be 0
-        assert_eq!(backend.get_writing_time(), 0);
+        run(
+            &counters,
+            vec![
+                // Initially should be 0
+                expect_writing_ms(&counters, 0),
+                focus(true),
+                idle_ms(1100),
+                focus(false),
+                expect_writing_ms(&counters, 1100),
+                // Unfocused time does not count
+                idle_ms(5000),
+                expect_writing_ms(&counters, 1100),
+                focus(true),
+                idle_ms(1500),
+                focus(false),
+            ],
+        );
 
-        // Simulate focus
-        backend.update_focus(true);
-        thread::sleep(Duration::from_millis(1100)); // Sleep for more than 1 second
-        backend.update_focus(false);
-        thread::sleep(Duration::from_millis(200)); // Allow thread to process
+        assert_eq!(counters.writing.load(Ordering::Relaxed), 2600);
+        assert_eq!(counters.session_writing.load(Ordering::Relaxed), 2600);
+    }
 
-        // Should have accumulated at least 1 second
-        let time = backend.get_writing_time();
-        assert!(time >= 1, "Expected at least 1 second, got {}", time);
+    #[test]
+    fn test_writing_time_ticks_while_focused() {
+        let counters = Arc::new(Counters::default());
+
+        run(
+            &counters,
+            vec![
+                focus(true),
+                idle_ms(1300),
+                // No focus change yet, but the counter has ticked once
+                expect_writing_ms(&counters, 1000),
+                idle_ms(1000),
+                expect_writing_ms(&counters, 2000),
+                focus(false),
+            ],
+        );
 
-        // Simulate focus again
+        assert_eq!(counters.writing.load(Ordering::Relaxed), 2300);
+    }
+
+    #[test]
+    fn test_reset_while_focused_does_not_double_count() {
+        let counters = Arc::new(Counters::default());
+        let first = Arc::new(AtomicU64::new(0));
+
+        let reset = {
+            let counters = Arc::clone(&counters);
+            let first = Arc::clone(&first);
+            Step::Run(Box::new(move || {
+                let ms = counters.writing.swap(0, Ordering::Relaxed);
+                first.store(ms, Ordering::Relaxed);
+            }))
+        };
+        run(
+            &counters,
+            vec![
+                focus(true),
+                idle_ms(1300),
+                reset,
+                idle_ms(500),
+                focus(false),
+            ],
+        );
+
+        // The two halves together cover the focused span exactly once
+        let first = first.load(Ordering::Relaxed);
+        let second = counters.writing.load(Ordering::Relaxed);
+        assert_eq!(first, 1000);
+        assert_eq!(first + second, 1800);
+        assert_eq!(counters.session_writing.load(Ordering::Relaxed), 1800);
+    }
+
+    #[test]
+    fn test_idle_while_focused_is_writing_but_not_typing() {
+        let counters = Arc::new(Counters::default());
+
+        run(
+            &counters,
+            vec![
+                focus(true),
+                Step::Send(TimeMessage::TypingActivity),
+                idle_ms(60_000),
+                focus(false),
+            ],
+        );
+
+        assert_eq!(counters.writing.load(Ordering::Relaxed), 60_000);
+        assert_eq!(counters.typing.load(Ordering::Relaxed), 10_000);
+    }
+
+    #[test]
+    fn test_sleep_while_unfocused_is_not_counted() {
+        let counters = Arc::new(Counters::default());
+
+        run(
+            &counters,
+            vec![
+                focus(true),
+                Step::Send(TimeMessage::TypingActivity),
+                idle_ms(2000),
+                focus(false),
+                // The lid is closed overnight
+                Step::Suspend(Duration::from_secs(8 * 3600)),
+                focus(true),
+                Step::Send(TimeMessage::TypingActivity),
+                idle_ms(3000),
+                focus(false),
+            ],
+        );
+
+        assert_eq!(counters.writing.load(Ordering::Relaxed), 5000);
+        assert_eq!(counters.session_writing.load(Ordering::Relaxed), 5000);
+        assert_eq!(counters.typing.load(Ordering::Relaxed), 5000);
+    }
+
+    #[test]
+    fn test_stop_folds_the_time_still_open() {
+        let counters = Arc::new(Counters::default());
+
+        // The script ends while focused and within a typing window
+        run(
+            &counters,
+            vec![
+                focus(true),
+                Step::Send(TimeMessage::TypingActivity),
+                idle_ms(2500),
+            ],
+        );
+
+        assert_eq!(counters.writing.load(Ordering::Relaxed), 2500);
+        assert_eq!(counters.typing.load(Ordering::Relaxed), 2500);
+    }
+
+    #[test]
+    fn test_typing_clock_counts_window_after_each_keystroke() {
+        let t0 = Instant::now();
+        let mut clock = TypingClock::new(t0);
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+
+        // Keystrokes at 0 s and 4 s overlap: typing runs until 14 s
+        let mut total = clock.activity(at(0));
+        total += clock.activity(at(4));
+        total += clock.fold(at(30));
+        assert_eq!(total, 14_000);
+
+        // A keystroke after a long pause starts a fresh interval
+        total += clock.activity(at(60));
+        total += clock.fold(at(65));
+        assert_eq!(total, 19_000);
+    }
+
+    #[test]
+    fn test_typing_clock_gap_longer_than_window_is_not_typing() {
+        let t0 = Instant::now();
+        let mut clock = TypingClock::new(t0);
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+
+        // No fold in between: the second keystroke must only credit the
+        // first interval up to its window, not the idle gap
+        let mut total = clock.activity(at(0));
+        total += clock.activity(at(25));
+        total += clock.stop(at(27));
+        assert_eq!(total, 12_000);
+
+        // Nothing accrues after stop
+        assert_eq!(clock.fold(at(40)), 0);
+    }
+
+    #[test]
+    fn test_no_typing_without_activity() {
+        let t0 = Instant::now();
+        let mut clock = TypingClock::new(t0);
+
+        assert_eq!(clock.fold(t0 + Duration::from_secs(120)), 0);
+    }
+
+    #[test]
+    fn test_recent_events_record_focus_and_accumulation() {
+        let counters = Arc::new(Counters::default());
+        let (reply_tx, reply_rx) = mpsc::channel();
+
+        run(
+            &counters,
+            vec![
+                focus(true),
+                idle_ms(50),
+                focus(false),
+                Step::Send(TimeMessage::Flushed(50)),
+                Step::Send(TimeMessage::RecentEvents(reply_tx)),
+            ],
+        );
+
+        let kinds: Vec<TimeEventKind> = reply_rx.recv().unwrap().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TimeEventKind::FocusUpdate(true),
+                TimeEventKind::FocusUpdate(false),
+                TimeEventKind::Accumulate(50),
+                TimeEventKind::Flush(50),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backend_runs_on_a_given_clock() {
+        let backend = TimeBackend::with_clock(SystemClock);
+
+        assert_eq!(backend.get_writing_time(), 0);
         backend.update_focus(true);
-        thread::sleep(Duration::from_millis(1500));
         backend.update_focus(false);
-        thread::sleep(Duration::from_millis(200)); // Allow thread to process
-
-        // Should have more time now
-        let new_time = backend.get_writing_time();
-        assert!(
-            new_time > time,
-            "Expected more time after second focus period, was {} now {}",
-            time,
-            new_time
+        let kinds: Vec<TimeEventKind> = backend.recent_events().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds[..2],
+            [
+                TimeEventKind::FocusUpdate(true),
+                TimeEventKind::FocusUpdate(false)
+            ]
         );
     }
 
+    #[test]
+    fn test_event_log_is_bounded() {
+        let mut log = EventLog::default();
+        for i in 0..(MAX_RECENT_EVENTS as u64 + 5) {
+            log.record(TimeEventKind::Accumulate(i));
+        }
+
+        let events = log.snapshot();
+        assert_eq!(events.len(), MAX_RECENT_EVENTS);
+        assert_eq!(events[0].kind, TimeEventKind::Accumulate(5));
+    }
+
     #[test]
     fn test_format_writing_time() {
         // Test seconds and minutes
@@ -0,0 +1,267 @@
+use crate::config::ModelPrice;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+const USAGE_LOG_FILE: &str = "ai_usage.json";
+
+/// Tokens consumed by model requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+
+    /// Read the usage block of one response chunk, whichever provider sent it.
+    ///
+    /// Understands OpenAI-style `usage` (top level or inside the first choice,
+    /// as Moonshot sends it), Gemini's `usageMetadata` and Ollama's
+    /// `prompt_eval_count`/`eval_count`.
+    pub fn from_response(value: &Value) -> Option<TokenUsage> {
+        let count = |object: &Value, keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| object.get(key).and_then(Value::as_u64))
+        };
+
+        let openai = value
+            .get("usage")
+            .filter(|usage| usage.is_object())
+            .or_else(|| {
+                value
+                    .get("choices")
+                    .and_then(Value::as_array)
+                    .and_then(|choices| choices.first())
+                    .and_then(|choice| choice.get("usage"))
+                    .filter(|usage| usage.is_object())
+            });
+        if let Some(usage) = openai {
+            let prompt = count(usage, &["prompt_tokens", "input_tokens"]);
+            let completion = count(usage, &["completion_tokens", "output_tokens"]);
+            if prompt.is_some() || completion.is_some() {
+                return Some(TokenUsage {
+                    prompt_tokens: prompt.unwrap_or(0),
+                    completion_tokens: completion.unwrap_or(0),
+                });
+            }
+        }
+
+        if let Some(metadata) = value.get("usageMetadata") {
+            return Some(TokenUsage {
+                prompt_tokens: count(metadata, &["promptTokenCount"]).unwrap_or(0),
+                completion_tokens: count(metadata, &["candidatesTokenCount"]).unwrap_or(0),
+            });
+        }
+
+        let prompt = count(value, &["prompt_eval_count"]);
+        let completion = count(value, &["eval_count"]);
+        (prompt.is_some() || completion.is_some()).then(|| TokenUsage {
+            prompt_tokens: prompt.unwrap_or(0),
+            completion_tokens: completion.unwrap_or(0),
+        })
+    }
+}
+
+/// Token usage per day, then per model name
+pub type UsageLog = BTreeMap<NaiveDate, BTreeMap<String, TokenUsage>>;
+
+/// Add `usage` for `model` on `date`
+pub fn record_usage(log: &mut UsageLog, date: NaiveDate, model: &str, usage: TokenUsage) {
+    log.entry(date)
+        .or_default()
+        .entry(model.to_string())
+        .or_default()
+        .add(usage);
+}
+
+/// All models' usage on one day
+pub fn day_total(models: &BTreeMap<String, TokenUsage>) -> TokenUsage {
+    models
+        .values()
+        .fold(TokenUsage::default(), |mut total, usage| {
+            total.add(*usage);
+            total
+        })
+}
+
+/// Estimated cost of one day's usage; `None` when no model used has a price
+pub fn estimate_cost(models: &BTreeMap<String, TokenUsage>, prices: &[ModelPrice]) -> Option<f64> {
+    let mut cost = None;
+    for (model, usage) in models {
+        if let Some(price) = prices
+            .iter()
+            .find(|price| price.model.trim().eq_ignore_ascii_case(model))
+        {
+            *cost.get_or_insert(0.0) += usage.prompt_tokens as f64 / 1_000_000.0
+                * price.input_per_million
+                + usage.completion_tokens as f64 / 1_000_000.0 * price.output_per_million;
+        }
+    }
+    cost
+}
+
+/// Compact token count such as "950", "12.3k" or "1.2M"
+pub fn format_tokens(tokens: u64) -> String {
+    if tokens >= 1_000_000 {
+        format!("{:.1}M", tokens as f64 / 1_000_000.0)
+    } else if tokens >= 1_000 {
+        format!("{:.1}k", tokens as f64 / 1_000.0)
+    } else {
+        tokens.to_string()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UsageLogError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Persists per-day token usage in a single JSON file
+pub struct UsageLogBackend {
+    log_path: PathBuf,
+}
+
+impl UsageLogBackend {
+    /// Keep the usage log in the data dir `data_dir`
+    pub fn with_data_dir(data_dir: PathBuf) -> Result<Self, UsageLogError> {
+        fs::create_dir_all(&data_dir)?;
+
+        Ok(Self {
+            log_path: data_dir.join(USAGE_LOG_FILE),
+        })
+    }
+
+    pub fn load(&self) -> Result<UsageLog, UsageLogError> {
+        if !self.log_path.exists() {
+            return Ok(UsageLog::new());
+        }
+
+        let content = fs::read_to_string(&self.log_path)?;
+        let log = serde_json::from_str(&content)?;
+        Ok(log)
+    }
+
+    /// Add usage for `model` on `date` and save
+    pub fn add(
+        &self,
+        date: NaiveDate,
+        model: &str,
+        usage: TokenUsage,
+    ) -> Result<(), UsageLogError> {
+        let mut log = self.load()?;
+        record_usage(&mut log, date, model, usage);
+
+        let content = serde_json::to_string_pretty(&log)?;
+        fs::write(&self.log_path, content)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parses_usage_from_each_provider() {
+        let openai =
+            json!({"choices": [], "usage": {"prompt_tokens": 120, "completion_tokens": 30}});
+        let moonshot = json!({"choices": [{"delta": {}, "usage": {"prompt_tokens": 5, "completion_tokens": 7}}]});
+        let gemini = json!({"usageMetadata": {"promptTokenCount": 40, "candidatesTokenCount": 2}});
+        let ollama = json!({"done": true, "prompt_eval_count": 64, "eval_count": 16});
+
+        let usage = |prompt, completion| {
+            Some(TokenUsage {
+                prompt_tokens: prompt,
+                completion_tokens: completion,
+            })
+        };
+        assert_eq!(TokenUsage::from_response(&openai), usage(120, 30));
+        assert_eq!(TokenUsage::from_response(&moonshot), usage(5, 7));
+        assert_eq!(TokenUsage::from_response(&gemini), usage(40, 2));
+        assert_eq!(TokenUsage::from_response(&ollama), usage(64, 16));
+
+        // Ordinary stream chunks carry no usage
+        assert_eq!(
+            TokenUsage::from_response(&json!({"choices": [{"delta": {"content": "hi"}}]})),
+            None
+        );
+        assert_eq!(TokenUsage::from_response(&json!({"usage": null})), None);
+    }
+
+    #[test]
+    fn test_aggregates_per_day_and_estimates_cost() {
+        let day = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let mut log = UsageLog::new();
+        let usage = |prompt, completion| TokenUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+        };
+        record_usage(&mut log, day, "gpt-4o-mini", usage(600_000, 100_000));
+        record_usage(&mut log, day, "gpt-4o-mini", usage(400_000, 100_000));
+        record_usage(&mut log, day, "qwen3:8b", usage(5_000, 1_000));
+
+        let models = &log[&day];
+        assert_eq!(models["gpt-4o-mini"], usage(1_000_000, 200_000));
+        assert_eq!(day_total(models).total(), 1_206_000);
+
+        let prices = vec![ModelPrice {
+            model: "GPT-4o-mini".to_string(),
+            input_per_million: 0.15,
+            output_per_million: 0.6,
+        }];
+        let cost = estimate_cost(models, &prices).unwrap();
+        assert!((cost - 0.27).abs() < 1e-9);
+        assert_eq!(estimate_cost(models, &[]), None);
+    }
+
+    #[test]
+    fn test_formats_token_counts() {
+        assert_eq!(format_tokens(950), "950");
+        assert_eq!(format_tokens(12_345), "12.3k");
+        assert_eq!(format_tokens(1_240_000), "1.2M");
+    }
+
+    #[test]
+    fn test_add_persists_usage() {
+        let test_dir = std::env::temp_dir().join(format!("test_usage_log_{}", Uuid::new_v4()));
+        fs::create_dir_all(&test_dir).unwrap();
+        let backend = UsageLogBackend {
+            log_path: test_dir.join(USAGE_LOG_FILE),
+        };
+        let day = NaiveDate::from_ymd_opt(2025, 3, 4).unwrap();
+        let usage = TokenUsage {
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        };
+
+        backend.add(day, "kimi", usage).unwrap();
+        backend.add(day, "kimi", usage).unwrap();
+
+        let log = backend.load().unwrap();
+        assert_eq!(log[&day]["kimi"].total(), 30);
+
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+}
@@ -0,0 +1,80 @@
+//! Background operations the user is waiting on.
+//!
+//! The app notes an operation when it hands it to a thread and finishes it
+//! when the matching response arrives, so the title bar can say what is still
+//! going on.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusyKind {
+    Open,
+    Save,
+    History,
+}
+
+#[derive(Default)]
+pub struct BusyTasks {
+    /// Running operations with the name of the file each is about, oldest first
+    tasks: Vec<(BusyKind, String)>,
+}
+
+impl BusyTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin(&mut self, kind: BusyKind, name: impl Into<String>) {
+        self.tasks.push((kind, name.into()));
+    }
+
+    /// Finish the oldest running operation of `kind`; responses arrive in
+    /// the order the operations were started.
+    pub fn finish(&mut self, kind: BusyKind) {
+        if let Some(index) = self.tasks.iter().position(|(k, _)| *k == kind) {
+            self.tasks.remove(index);
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    /// What the newest operation is doing, plus how many others are running
+    pub fn status(&self) -> Option<String> {
+        let (kind, name) = self.tasks.last()?;
+        let status = match kind {
+            BusyKind::Open => format!("正在打开 {}…", name),
+            BusyKind::Save => format!("正在保存 {}…", name),
+            BusyKind::History => format!("正在读取 {} 的历史版本…", name),
+        };
+        Some(match self.tasks.len() {
+            1 => status,
+            count => format!("{}（另有 {} 项）", status, count - 1),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_follows_the_newest_operation() {
+        let mut busy = BusyTasks::new();
+        assert_eq!(busy.status(), None);
+
+        busy.begin(BusyKind::Save, "draft.txt");
+        busy.begin(BusyKind::Open, "novel.txt");
+        assert_eq!(
+            busy.status().as_deref(),
+            Some("正在打开 novel.txt…（另有 1 项）")
+        );
+
+        busy.finish(BusyKind::Open);
+        assert_eq!(busy.status().as_deref(), Some("正在保存 draft.txt…"));
+        // Finishing something that is not running changes nothing
+        busy.finish(BusyKind::History);
+        assert!(busy.is_busy());
+        busy.finish(BusyKind::Save);
+        assert!(!busy.is_busy());
+    }
+}
@@ -0,0 +1,391 @@
+//! Command-line arguments.
+//!
+//! `paper-shell [options] [files...]`: the first file opens in this window
+//! and every other one in a window of its own. In single-instance mode all
+//! of them go to the window already running.
+//!
+//! `papershell:` links, see [`crate::deeplink`], may stand in for files,
+//! and `file://` URLs are taken as the files they name.
+//!
+//! `paper-shell [options] <command> [arguments]` runs a maintenance
+//! [`Task`] on the data store instead and exits without a window.
+
+use crate::constant::PORTABLE_ARG;
+use crate::deeplink;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+
+const DATA_DIR_ARG: &str = "--data-dir";
+const NEW_ARG: &str = "--new";
+const SPAWNED_FROM_ARG: &str = "--spawned-from";
+const OUTPUT_ARG: &str = "--output";
+
+const HISTORY_COMMAND: &str = "history";
+const RESTORE_COMMAND: &str = "restore";
+const GC_COMMAND: &str = "gc";
+const VERIFY_COMMAND: &str = "verify";
+const COMMANDS: [&str; 4] = [HISTORY_COMMAND, RESTORE_COMMAND, GC_COMMAND, VERIFY_COMMAND];
+
+pub const USAGE: &str = "\
+Usage: paper-shell [options] [files or papershell: links...]
+       paper-shell [options] <command> [arguments]
+
+Opens each file in a window of its own, the first one in this window, and
+the documents of the links in this window.
+
+Commands, run without a window:
+  history <file>         List the saved versions of <file>
+  restore <file> <hash> [-o <path>]
+                         Print the saved version <hash> of <file>, or write
+                         it to <path>; a unique start of the hash will do
+  gc                     Delete saved content no history refers to anymore
+  verify                 Check the saved versions for missing or damaged
+                         content
+
+To open a file named like a command, write ./<name>.
+
+Options:
+      --data-dir <path>  Keep history, marks and logs in <path> for this run
+      --new              Start with an empty document in a new window, even
+                         in single-instance mode
+      --portable         Keep settings and data beside the executable
+      --spawned-from <pid>
+                         Set by the app for windows it opens itself
+  -o, --output <path>    Where restore writes the version
+  -h, --help             Show this help
+  -V, --version          Show the version";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CliError {
+    #[error("{0} needs a value")]
+    MissingValue(&'static str),
+
+    #[error("unknown option: {0}")]
+    UnknownOption(String),
+
+    #[error("{0} needs a process id, got {1}")]
+    InvalidPid(&'static str, String),
+
+    #[error("--new opens an empty document and cannot be given files")]
+    NewWithFiles,
+
+    #[error("{0} needs {1}")]
+    MissingArgument(&'static str, &'static str),
+
+    #[error("unexpected argument for {0}: {1}")]
+    UnexpectedArgument(&'static str, String),
+
+    #[error("{0} does not apply to {1}")]
+    NotApplicable(&'static str, &'static str),
+}
+
+/// What to do with a window started from the command line
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LaunchArgs {
+    pub files: Vec<PathBuf>,
+    /// `papershell:` links, checked once the app runs
+    pub links: Vec<String>,
+    pub data_dir: Option<PathBuf>,
+    /// Open an empty document here instead of handing over to a running
+    /// instance
+    pub new_document: bool,
+    /// Process id of the window that opened this one on purpose, e.g. with
+    /// 新窗口; such a window never hands itself over to a running instance
+    pub spawned_from: Option<u32>,
+}
+
+/// A maintenance task run on the data store without a window
+#[derive(Debug, PartialEq, Eq)]
+pub enum Task {
+    /// List the saved versions of a file
+    History(PathBuf),
+    /// Print a saved version of `file`, or write it to `output`
+    Restore {
+        file: PathBuf,
+        /// The hash of the version, or the start of it
+        hash: String,
+        output: Option<PathBuf>,
+    },
+    /// Delete blobs no history refers to
+    Gc,
+    /// Check every history against the blobs
+    Verify,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TaskArgs {
+    pub task: Task,
+    pub data_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CliCommand {
+    Launch(LaunchArgs),
+    Run(TaskArgs),
+    Help,
+    Version,
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<CliCommand, CliError> {
+    let mut launch = LaunchArgs::default();
+    let mut command: Option<&'static str> = None;
+    let mut operands: Vec<OsString> = Vec::new();
+    let mut output = None;
+    let mut args = args.into_iter();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy();
+        if only_files || !text.starts_with('-') {
+            let first = launch.files.is_empty() && launch.links.is_empty() && !only_files;
+            if command.is_some() {
+                operands.push(arg);
+            } else if let Some(name) = COMMANDS.into_iter().find(|name| first && text == *name) {
+                command = Some(name);
+            } else if deeplink::is_link(&text) {
+                launch.links.push(text.into_owned());
+            } else if let Some(path) = deeplink::file_url_path(&text) {
+                launch.files.push(path);
+            } else {
+                launch.files.push(PathBuf::from(arg));
+            }
+            continue;
+        }
+        match text.as_ref() {
+            "--" => only_files = true,
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-V" | "--version" => return Ok(CliCommand::Version),
+            NEW_ARG => launch.new_document = true,
+            "-o" | OUTPUT_ARG => {
+                let path = args.next().ok_or(CliError::MissingValue(OUTPUT_ARG))?;
+                output = Some(PathBuf::from(path));
+            }
+            PORTABLE_ARG => {} // Read by `paths::storage`
+            DATA_DIR_ARG => {
+                let dir = args.next().ok_or(CliError::MissingValue(DATA_DIR_ARG))?;
+                launch.data_dir = Some(PathBuf::from(dir));
+            }
+            SPAWNED_FROM_ARG => {
+                let pid = args
+                    .next()
+                    .ok_or(CliError::MissingValue(SPAWNED_FROM_ARG))?;
+                let pid = pid.to_string_lossy();
+                launch.spawned_from = Some(
+                    pid.parse()
+                        .map_err(|_| CliError::InvalidPid(SPAWNED_FROM_ARG, pid.into_owned()))?,
+                );
+            }
+            // Finder adds a process serial number on older macOS versions
+            _ if text.starts_with("-psn_") => {}
+            _ => match text.strip_prefix("--data-dir=") {
+                Some("") => return Err(CliError::MissingValue(DATA_DIR_ARG)),
+                Some(dir) => launch.data_dir = Some(PathBuf::from(dir)),
+                None => return Err(CliError::UnknownOption(text.into_owned())),
+            },
+        }
+    }
+    if let Some(name) = command {
+        if launch.new_document {
+            return Err(CliError::NotApplicable(NEW_ARG, name));
+        }
+        if launch.spawned_from.is_some() {
+            return Err(CliError::NotApplicable(SPAWNED_FROM_ARG, name));
+        }
+        return Ok(CliCommand::Run(TaskArgs {
+            task: task(name, operands, output)?,
+            data_dir: launch.data_dir,
+        }));
+    }
+    if output.is_some() {
+        return Err(CliError::NotApplicable(OUTPUT_ARG, "opening files"));
+    }
+    if launch.new_document && !(launch.files.is_empty() && launch.links.is_empty()) {
+        return Err(CliError::NewWithFiles);
+    }
+    Ok(CliCommand::Launch(launch))
+}
+
+/// The task of command `name` given `operands`
+fn task(
+    name: &'static str,
+    operands: Vec<OsString>,
+    output: Option<PathBuf>,
+) -> Result<Task, CliError> {
+    if output.is_some() && name != RESTORE_COMMAND {
+        return Err(CliError::NotApplicable(OUTPUT_ARG, name));
+    }
+    let wanted = match name {
+        HISTORY_COMMAND => "<file>",
+        RESTORE_COMMAND => "<file> <hash>",
+        _ => "",
+    };
+    let expected = wanted.split_whitespace().count();
+    if operands.len() < expected {
+        return Err(CliError::MissingArgument(name, wanted));
+    }
+    if let Some(extra) = operands.get(expected) {
+        return Err(CliError::UnexpectedArgument(
+            name,
+            extra.to_string_lossy().into_owned(),
+        ));
+    }
+    let mut operands = operands.into_iter();
+    let mut next = || operands.next().unwrap_or_default();
+    Ok(match name {
+        HISTORY_COMMAND => Task::History(PathBuf::from(next())),
+        RESTORE_COMMAND => Task::Restore {
+            file: PathBuf::from(next()),
+            hash: next().to_string_lossy().into_owned(),
+            output,
+        },
+        GC_COMMAND => Task::Gc,
+        _ => Task::Verify,
+    })
+}
+
+/// A command starting another window of the app, with the storage options
+/// of this one, `data_dir_arg` being its `--data-dir`, and marked as opened
+/// from it
+pub fn window_command(
+    file: Option<&Path>,
+    data_dir_arg: Option<&Path>,
+) -> std::io::Result<Command> {
+    let mut command = Command::new(std::env::current_exe()?);
+    if crate::paths::storage().portable {
+        command.arg(PORTABLE_ARG);
+    }
+    if let Some(dir) = data_dir_arg {
+        command.arg(DATA_DIR_ARG).arg(dir);
+    }
+    command
+        .arg(SPAWNED_FROM_ARG)
+        .arg(std::process::id().to_string());
+    if let Some(file) = file {
+        command.arg("--").arg(file);
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<CliCommand, CliError> {
+        parse(args.iter().map(OsString::from))
+    }
+
+    fn launch(args: &[&str]) -> LaunchArgs {
+        match parse_args(args) {
+            Ok(CliCommand::Launch(launch)) => launch,
+            other => panic!("expected a launch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_files_and_options_in_any_order() {
+        assert_eq!(launch(&[]), LaunchArgs::default());
+
+        let args = launch(&["a.txt", "--data-dir", "/tmp/data", "b.md", "--portable"]);
+        assert_eq!(args.files, [PathBuf::from("a.txt"), PathBuf::from("b.md")]);
+        assert_eq!(args.data_dir, Some(PathBuf::from("/tmp/data")));
+
+        // Later values win, and `--` ends the options
+        let args = launch(&["--data-dir=/a", "--data-dir", "/b", "--", "--new"]);
+        assert_eq!(args.data_dir, Some(PathBuf::from("/b")));
+        assert_eq!(args.files, [PathBuf::from("--new")]);
+        assert!(!args.new_document);
+
+        // Links stand in for files, and file URLs are the files they name
+        let args = launch(&["papershell://open?path=%2Fa.txt", "file:///tmp/b%20c.md"]);
+        assert_eq!(args.links, ["papershell://open?path=%2Fa.txt"]);
+        assert_eq!(args.files, [PathBuf::from("/tmp/b c.md")]);
+
+        assert!(launch(&["--new"]).new_document);
+        assert_eq!(launch(&["--spawned-from", "4242"]).spawned_from, Some(4242));
+        assert_eq!(launch(&["-psn_0_12345"]), LaunchArgs::default());
+    }
+
+    #[test]
+    fn test_help_and_version_win_and_mistakes_are_reported() {
+        assert_eq!(
+            parse_args(&["a.txt", "--help", "--bogus"]),
+            Ok(CliCommand::Help)
+        );
+        assert_eq!(parse_args(&["-V"]), Ok(CliCommand::Version));
+
+        assert_eq!(
+            parse_args(&["--bogus"]),
+            Err(CliError::UnknownOption("--bogus".to_string()))
+        );
+        assert_eq!(
+            parse_args(&["--data-dir"]),
+            Err(CliError::MissingValue(DATA_DIR_ARG))
+        );
+        assert_eq!(
+            parse_args(&["--data-dir="]),
+            Err(CliError::MissingValue(DATA_DIR_ARG))
+        );
+        assert_eq!(
+            parse_args(&["--spawned-from", "me"]),
+            Err(CliError::InvalidPid(SPAWNED_FROM_ARG, "me".to_string()))
+        );
+        assert_eq!(parse_args(&["--new", "a.txt"]), Err(CliError::NewWithFiles));
+    }
+
+    #[test]
+    fn test_commands_run_tasks() {
+        let run = |args: &[&str]| match parse_args(args) {
+            Ok(CliCommand::Run(args)) => args,
+            other => panic!("expected a task, got {:?}", other),
+        };
+
+        assert_eq!(
+            run(&["--data-dir", "/tmp/data", "history", "a.txt"]),
+            TaskArgs {
+                task: Task::History(PathBuf::from("a.txt")),
+                data_dir: Some(PathBuf::from("/tmp/data")),
+            }
+        );
+        assert_eq!(
+            run(&["restore", "a.txt", "9f3c", "-o", "old.txt"]).task,
+            Task::Restore {
+                file: PathBuf::from("a.txt"),
+                hash: "9f3c".to_string(),
+                output: Some(PathBuf::from("old.txt")),
+            }
+        );
+        assert_eq!(run(&["gc"]).task, Task::Gc);
+        assert_eq!(run(&["verify", "--portable"]).task, Task::Verify);
+
+        // Only the first argument names a command
+        assert_eq!(
+            launch(&["a.txt", "gc"]).files,
+            [PathBuf::from("a.txt"), PathBuf::from("gc")]
+        );
+        assert_eq!(launch(&["./gc"]).files, [PathBuf::from("./gc")]);
+        assert_eq!(launch(&["--", "gc"]).files, [PathBuf::from("gc")]);
+
+        assert_eq!(
+            parse_args(&["restore", "a.txt"]),
+            Err(CliError::MissingArgument(RESTORE_COMMAND, "<file> <hash>"))
+        );
+        assert_eq!(
+            parse_args(&["gc", "now"]),
+            Err(CliError::UnexpectedArgument(GC_COMMAND, "now".to_string()))
+        );
+        assert_eq!(
+            parse_args(&["history", "a.txt", "-o", "out.txt"]),
+            Err(CliError::NotApplicable(OUTPUT_ARG, HISTORY_COMMAND))
+        );
+        assert_eq!(
+            parse_args(&["--new", "verify"]),
+            Err(CliError::NotApplicable(NEW_ARG, VERIFY_COMMAND))
+        );
+        assert_eq!(
+            parse_args(&["a.txt", "-o", "out.txt"]),
+            Err(CliError::NotApplicable(OUTPUT_ARG, "opening files"))
+        );
+    }
+}
@@ -1,12 +1,26 @@
 //! Application configuration module
 //!
 //! This module centralizes all application configuration settings using `confy`
-//! for automatic serialization and OS-specific config directory management.
+//! for serialization; the file lives where [`crate::paths`] says.
 
-use crate::constant::{APP_NAME, APP_ORGANIZATION, APP_QUALIFIER, MAX_RECENT_FILES};
-use directories::ProjectDirs;
+use crate::config_migration::{CURRENT_VERSION, MigrationOutcome, migrate};
+use crate::config_writer::{ConfigWriter, ConfyStore, WRITE_INTERVAL};
+use crate::constant::{
+    DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_TITLE, DEFAULT_WINDOW_WIDTH, MAX_RECENT_FILES,
+    MIN_WINDOW_WIDTH,
+};
+use crate::datetime::{DEFAULT_DATETIME_FORMAT, validate_format};
+use crate::paths;
+use crate::print::PageSize;
+use crate::secrets::{self, SecretError, SecretStore};
+use crate::shortcuts::{Keybindings, complete_keybindings, default_keybindings};
+use directories::UserDirs;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 use tracing::info;
 
@@ -17,73 +31,580 @@ pub enum ConfigError {
 
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("Invalid settings file: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Settings were written by a newer version (schema {0}) and are not overwritten")]
+    NewerVersion(u32),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Settings use schema version {0}; this version reads up to {max}", max = CURRENT_VERSION)]
+    IncompatibleVersion(u32),
+
+    #[error("Not a settings export: {0}")]
+    NotAnExport(String),
 }
 
 pub struct Config {
     #[allow(dead_code)]
     pub settings: Settings,
+    /// Set once the app starts persisting changes in the background
+    writer: Option<ConfigWriter>,
+    /// Schema version of a settings file from a newer build; such a file is
+    /// never overwritten
+    newer_version: Option<u32>,
+    /// No settings file existed when loading
+    first_run: bool,
+    /// The settings file as this app last read or wrote it
+    stamp: FileStamp,
+    last_reload_check: Instant,
+    /// Where the API key goes when it is kept out of the settings file;
+    /// `None` if this build or system has no keychain
+    secrets: Option<Box<dyn SecretStore>>,
+    /// The API key as last read from or written to `secrets`
+    stored_api_key: Option<String>,
+    /// Data dir given with `--data-dir` for this run, if any
+    data_dir_arg: Option<PathBuf>,
 }
 
 impl Config {
-    /// Load configuration from disk, creating default if it doesn't exist
+    /// Load configuration from disk, using defaults if it doesn't exist
+    ///
+    /// A missing file is only written once settings are saved, so every load
+    /// before that counts as the first run. Files from older versions are
+    /// migrated, keeping a copy of the original next to it; if the copy
+    /// cannot be made, the migrated settings are used without writing them
+    /// back. Files from newer versions are loaded but left untouched.
     pub fn load() -> Result<Self, ConfigError> {
-        let settings: Settings = confy::load(APP_NAME, None)?;
-        info!("Load config from {:?}", Self::config_path()?);
-        Ok(Self { settings })
+        let path = Self::config_path()?;
+        let first_run = !path.exists();
+        let (mut settings, outcome) = if first_run {
+            (Settings::default(), MigrationOutcome::Current)
+        } else {
+            parse_settings(&std::fs::read_to_string(&path)?)?
+        };
+        settings.validate();
+        prune_recent_files(
+            &mut settings.recent_files,
+            settings.max_recent_files,
+            |path| path.exists(),
+        );
+        info!("Load config from {:?}", path);
+
+        let newer_version = match outcome {
+            MigrationOutcome::Current => None,
+            MigrationOutcome::Migrated { from } => {
+                let mut backup = path.file_name().unwrap_or_default().to_os_string();
+                backup.push(format!(".v{}.bak", from));
+                // Without a copy the original is not overwritten; the
+                // migrated settings are still used
+                match std::fs::copy(&path, path.with_file_name(&backup)) {
+                    Ok(_) => {
+                        info!(
+                            "Migrated config from version {} (backup {:?})",
+                            from, backup
+                        );
+                        if let Err(e) = confy::store_path(&path, &settings) {
+                            tracing::warn!("Failed to write migrated config: {}", e);
+                        }
+                    }
+                    Err(e) => tracing::warn!(
+                        "Failed to back up config version {} as {:?}, not writing the migration: {}",
+                        from,
+                        backup,
+                        e
+                    ),
+                }
+                None
+            }
+            MigrationOutcome::Newer(version) => {
+                tracing::warn!(
+                    "Config version {} is newer than {}; changes will not be saved",
+                    version,
+                    CURRENT_VERSION
+                );
+                Some(version)
+            }
+        };
+
+        let mut config = Self {
+            settings,
+            writer: None,
+            newer_version,
+            first_run,
+            stamp: FileStamp::new(&path),
+            last_reload_check: Instant::now(),
+            secrets: secrets::api_key_store(),
+            stored_api_key: None,
+            data_dir_arg: None,
+        };
+        config.load_api_key();
+        Ok(config)
+    }
+
+    /// Read the API key from the keychain if the settings say it is kept there.
+    ///
+    /// Without a usable keychain the key stays as the settings file has it,
+    /// usually empty, until it is entered again.
+    fn load_api_key(&mut self) {
+        if !self.settings.ai_panel.api_key_in_keychain {
+            return;
+        }
+        let result = match &self.secrets {
+            Some(store) => store.get(),
+            None => Err(SecretError::Unavailable),
+        };
+        match result {
+            Ok(key) => {
+                self.settings.ai_panel.api_key = key.clone().unwrap_or_default();
+                self.stored_api_key = key;
+            }
+            Err(e) => tracing::warn!("Could not read the API key from the keychain: {}", e),
+        }
+    }
+
+    /// Whether the API key can be kept in the OS keychain
+    pub fn keychain_available(&self) -> bool {
+        self.secrets.is_some()
+    }
+
+    /// Move the API key to or from the keychain as `api_key_in_keychain`
+    /// says, and store it there if it changed.
+    ///
+    /// If the keychain cannot be used the key falls back to the settings
+    /// file and the error is returned.
+    pub fn sync_api_key(&mut self) -> Result<(), SecretError> {
+        let ai_panel = &mut self.settings.ai_panel;
+        if ai_panel.api_key_in_keychain {
+            if self.stored_api_key.as_ref() == Some(&ai_panel.api_key) {
+                return Ok(());
+            }
+            let result = match &self.secrets {
+                Some(store) => store.set(&ai_panel.api_key),
+                None => Err(SecretError::Unavailable),
+            };
+            match result {
+                Ok(()) => self.stored_api_key = Some(ai_panel.api_key.clone()),
+                Err(e) => {
+                    ai_panel.api_key_in_keychain = false;
+                    return Err(e);
+                }
+            }
+        } else if self.stored_api_key.take().is_some()
+            && let Some(store) = &self.secrets
+            && let Err(e) = store.delete()
+        {
+            // The key is in the settings file again; a stale copy does no harm
+            tracing::warn!("Could not remove the API key from the keychain: {}", e);
+        }
+        Ok(())
+    }
+
+    /// The settings as written to disk, without a key kept in the keychain
+    fn storable_settings(&self) -> Settings {
+        let mut settings = self.settings.clone();
+        if settings.ai_panel.api_key_in_keychain {
+            settings.ai_panel.api_key.clear();
+        }
+        settings
+    }
+
+    /// Settings from the file if it was changed outside the app since it
+    /// was last read or written, checked every [`RELOAD_CHECK_INTERVAL`].
+    ///
+    /// The returned settings are not applied. A file from a newer version
+    /// makes the config read-only and is reported as an error.
+    pub fn reload_if_changed(&mut self) -> Option<Result<Settings, ConfigError>> {
+        if self.last_reload_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return None;
+        }
+        self.last_reload_check = Instant::now();
+        let path = Self::config_path().ok()?;
+        if !self.stamp.take_change(&path) {
+            return None;
+        }
+        info!("Settings file changed outside the app, reloading");
+
+        let result = std::fs::read_to_string(&path)
+            .map_err(ConfigError::from)
+            .and_then(|text| parse_settings(&text));
+        Some(result.and_then(|(mut settings, outcome)| {
+            if let MigrationOutcome::Newer(version) = outcome {
+                self.newer_version = Some(version);
+                return Err(ConfigError::NewerVersion(version));
+            }
+            self.newer_version = None;
+            settings.validate();
+            let ai_panel = &mut settings.ai_panel;
+            if ai_panel.api_key_in_keychain && ai_panel.api_key.is_empty() {
+                ai_panel.api_key = self.stored_api_key.clone().unwrap_or_default();
+            }
+            Ok(settings)
+        }))
+    }
+
+    /// Whether the app runs for the first time on this machine
+    pub fn is_first_run(&self) -> bool {
+        self.first_run
+    }
+
+    /// Why changes to the settings are not being saved, if they aren't
+    pub fn read_only_reason(&self) -> Option<ConfigError> {
+        self.newer_version.map(ConfigError::NewerVersion)
     }
 
     /// Save current configuration to disk
-    #[allow(dead_code)]
     pub fn save(&self) -> Result<(), ConfigError> {
-        confy::store(APP_NAME, None, &self.settings)?;
-        info!("Save config to {:?}", Self::config_path()?);
+        if let Some(error) = self.read_only_reason() {
+            return Err(error);
+        }
+        let path = Self::config_path()?;
+        self.stamp
+            .record_write(&path, || confy::store_path(&path, self.storable_settings()))?;
+        info!("Save config to {:?}", path);
         Ok(())
     }
 
-    /// Get the application data directory
-    /// Falls back to a local "data" directory if platform dirs are unavailable
+    /// Get the application data directory, see [`crate::paths`]
     pub fn data_dir(&self) -> PathBuf {
-        if let Some(proj_dirs) = ProjectDirs::from(APP_QUALIFIER, APP_ORGANIZATION, APP_NAME) {
-            proj_dirs.data_dir().to_path_buf()
-        } else {
-            PathBuf::from("data")
-        }
+        paths::data_dir(
+            self.data_dir_arg.as_deref(),
+            self.settings.data_dir.as_deref(),
+        )
+    }
+
+    /// Use `dir` as the data dir for this run, whatever the settings or
+    /// portable mode say
+    pub fn set_data_dir_arg(&mut self, dir: Option<PathBuf>) {
+        self.data_dir_arg = dir;
+    }
+
+    /// The data dir given on the command line, to pass on to new windows
+    pub fn data_dir_arg(&self) -> Option<&Path> {
+        self.data_dir_arg.as_deref()
+    }
+
+    /// Folder the open and save dialogs start in.
+    ///
+    /// Prefers the folder last used this session, then the configured default
+    /// save folder, then the user's Documents; the data dir is the last resort.
+    pub fn dialog_dir(&self, last_used: Option<&Path>) -> PathBuf {
+        let documents = UserDirs::new().and_then(|dirs| dirs.document_dir().map(Path::to_path_buf));
+        first_existing_dir(
+            [
+                last_used.map(Path::to_path_buf),
+                self.settings.default_save_dir.clone(),
+                documents,
+            ],
+            self.data_dir(),
+        )
     }
 
     /// Get the configuration file path
     #[allow(dead_code)]
     pub fn config_path() -> Result<PathBuf, ConfigError> {
-        Ok(confy::get_configuration_file_path(APP_NAME, None)?)
+        Ok(paths::storage().config_file.clone())
+    }
+
+    /// Persist later changes through a background [`ConfigWriter`].
+    ///
+    /// `on_error` is called from the writer thread when a write fails.
+    pub fn start_writer(&mut self, on_error: impl Fn(ConfigError) + Send + 'static) {
+        let store = ConfyStore {
+            stamp: self.stamp.clone(),
+        };
+        self.writer = Some(ConfigWriter::spawn(store, WRITE_INTERVAL, on_error));
     }
 
-    /// Add a file to the recent files list
+    /// Note that `settings` changed so they get written soon.
+    ///
+    /// Without a writer the settings are saved right away.
+    pub fn mark_dirty(&self) {
+        if self.newer_version.is_some() {
+            return;
+        }
+        match &self.writer {
+            Some(writer) => writer.changed(self.storable_settings()),
+            None => {
+                if let Err(e) = self.save() {
+                    tracing::error!("Failed to save settings: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Write pending changes now, e.g. before exiting.
+    pub fn flush(&self) -> Result<(), ConfigError> {
+        match &self.writer {
+            Some(writer) if self.newer_version.is_none() => {
+                writer.flush();
+                Ok(())
+            }
+            _ => self.save(),
+        }
+    }
+
+    /// Add a file to the recent files list, unless it is pinned
     pub fn add_recent_file(&mut self, path: PathBuf) {
+        let path = recent_file_path(path);
+        if self.settings.pinned_files.contains(&path) {
+            return;
+        }
         // Move the path to the front
-        self.settings.recent_files.retain(|p| p != &path);
-        self.settings.recent_files.insert(0, path);
-        self.settings.recent_files.truncate(MAX_RECENT_FILES);
-
-        // Save changes in background since it's synchronous IO
-        let settings = self.settings.clone();
-        std::thread::spawn(move || {
-            if let Err(e) = confy::store(APP_NAME, None, &settings) {
-                tracing::error!("Failed to save recent files: {}", e);
-            }
-        });
+        let max = self.settings.max_recent_files();
+        insert_recent_file(&mut self.settings.recent_files, path, max);
+        self.mark_dirty();
+    }
+
+    /// See [`Settings::remove_recent_file`]
+    pub fn remove_recent_file(&mut self, path: &Path) {
+        self.settings.remove_recent_file(path);
+        self.mark_dirty();
+    }
+
+    /// See [`Settings::toggle_pinned_file`]
+    pub fn toggle_pinned_file(&mut self, path: &Path) {
+        self.settings.toggle_pinned_file(path);
+        self.mark_dirty();
+    }
+
+    /// See [`Settings::rename_recent_file`]
+    pub fn rename_recent_file(&mut self, from: &Path, to: &Path) {
+        self.settings.rename_recent_file(from, to);
+        self.mark_dirty();
+    }
+
+    pub fn clear_recent_files(&mut self) {
+        self.settings.recent_files.clear();
+        self.mark_dirty();
+    }
+}
+
+fn first_existing_dir(
+    candidates: impl IntoIterator<Item = Option<PathBuf>>,
+    fallback: PathBuf,
+) -> PathBuf {
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|dir| dir.is_dir())
+        .unwrap_or(fallback)
+}
+
+/// Read a settings file, migrating it from older versions.
+fn parse_settings(text: &str) -> Result<(Settings, MigrationOutcome), ConfigError> {
+    let mut table: toml::Table = text.parse()?;
+    let outcome = migrate(&mut table);
+    Ok((table.try_into()?, outcome))
+}
+
+/// Settings tied to this machine, left out of exports unless asked for
+const MACHINE_SPECIFIC: [&str; 7] = [
+    "/recent_files",
+    "/session",
+    "/custom_font_path",
+    "/pinned_files",
+    "/window",
+    "/default_save_dir",
+    "/data_dir",
+];
+/// Settings never written to an export
+const SECRETS: [&str; 2] = ["/ai_panel/api_key", "/ai_panel/api_key_in_keychain"];
+
+/// `settings` as JSON to carry over to another machine.
+///
+/// The API key is never included, paths and window placement only with
+/// `include_machine_specific`.
+pub fn export_settings(
+    settings: &Settings,
+    include_machine_specific: bool,
+) -> Result<String, ConfigError> {
+    let mut value = serde_json::to_value(settings)?;
+    let machine_specific: &[&str] = if include_machine_specific {
+        &[]
+    } else {
+        &MACHINE_SPECIFIC
+    };
+    for pointer in SECRETS.iter().chain(machine_specific) {
+        let (parent, key) = pointer.rsplit_once('/').unwrap_or_default();
+        if let Some(serde_json::Value::Object(map)) = value.pointer_mut(parent) {
+            map.remove(key);
+        }
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+/// Read settings written by [`export_settings`], migrating older schemas.
+///
+/// Whatever the export left out, like the API key, is kept from `current`.
+pub fn import_settings(text: &str, current: &Settings) -> Result<Settings, ConfigError> {
+    let mut value: serde_json::Value = serde_json::from_str(text)?;
+    if value.get("version").is_none() {
+        return Err(ConfigError::NotAnExport("no schema version".to_string()));
+    }
+    let current = serde_json::to_value(current)?;
+    for pointer in SECRETS.iter().chain(&MACHINE_SPECIFIC) {
+        let (parent, key) = pointer.rsplit_once('/').unwrap_or_default();
+        if value.pointer(pointer).is_none()
+            && let Some(kept) = current.pointer(pointer)
+            && let Some(serde_json::Value::Object(map)) = value.pointer_mut(parent)
+        {
+            map.insert(key.to_string(), kept.clone());
+        }
+    }
+    // TOML has no null; a missing key reads as `None` all the same
+    remove_nulls(&mut value);
+    let toml::Value::Table(mut table) =
+        toml::Value::try_from(value).map_err(|e| ConfigError::NotAnExport(e.to_string()))?
+    else {
+        return Err(ConfigError::NotAnExport("not an object".to_string()));
+    };
+    if let MigrationOutcome::Newer(version) = migrate(&mut table) {
+        return Err(ConfigError::IncompatibleVersion(version));
     }
+    let mut settings: Settings = table.try_into()?;
+    settings.validate();
+    Ok(settings)
+}
+
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// The form a path is kept in the recent files list, so `./a.txt` and its
+/// absolute path count as the same file
+fn recent_file_path(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path)
+        .or_else(|_| std::path::absolute(&path))
+        .unwrap_or(path)
+}
+
+/// Put `path` at the front of `files`, dropping an older entry for it and
+/// keeping at most `max` entries
+fn insert_recent_file(files: &mut Vec<PathBuf>, path: PathBuf, max: usize) {
+    files.retain(|p| p != &path);
+    files.insert(0, path);
+    files.truncate(max);
+}
+
+/// Drop duplicates, keeping the most recent, and paths that no longer exist
+fn prune_recent_files(files: &mut Vec<PathBuf>, max: usize, exists: impl Fn(&Path) -> bool) {
+    let mut seen = HashSet::new();
+    files.retain(|path| exists(path) && seen.insert(path.clone()));
+    files.truncate(max);
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self::load().unwrap_or_else(|_| Self {
             settings: Settings::default(),
+            writer: None,
+            newer_version: None,
+            first_run: false,
+            stamp: FileStamp::default(),
+            last_reload_check: Instant::now(),
+            secrets: secrets::api_key_store(),
+            stored_api_key: None,
+            data_dir_arg: None,
         })
     }
 }
 
+/// How often the settings file is checked for edits made outside the app
+pub const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Modification time of the settings file as the app last read or wrote it.
+///
+/// Shared with the writer thread, so the app's own writes are not mistaken
+/// for outside edits.
+#[derive(Clone, Default)]
+pub struct FileStamp(Arc<Mutex<Option<SystemTime>>>);
+
+impl FileStamp {
+    fn new(path: &Path) -> Self {
+        Self(Arc::new(Mutex::new(modified_time(path))))
+    }
+
+    /// Run `write` and remember the file's modification time afterwards.
+    pub fn record_write<T>(&self, path: &Path, write: impl FnOnce() -> T) -> T {
+        let mut stamp = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let result = write();
+        *stamp = modified_time(path);
+        result
+    }
+
+    /// Whether the file was modified since it was last read or written;
+    /// the new time counts as read from then on.
+    ///
+    /// A missing file is no change, as there is nothing to reload.
+    fn take_change(&self, path: &Path) -> bool {
+        let mut stamp = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(modified) = modified_time(path) else {
+            return false;
+        };
+        if *stamp == Some(modified) {
+            return false;
+        }
+        *stamp = Some(modified);
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Themes offered in Settings: (config value, label)
+pub const THEMES: [(&str, &str); 2] = [("light", "浅色"), ("sepia", "纸张")];
+pub const DEFAULT_FONT_SIZE: f32 = 14.0;
+pub const FONT_SIZE_RANGE: RangeInclusive<f32> = 10.0..=36.0;
+pub const LINE_SPACING_RANGE: RangeInclusive<f32> = 1.0..=2.5;
+/// Vertical offset of CJK glyphs, as a fraction of the row height
+pub const FONT_Y_OFFSET_RANGE: RangeInclusive<f32> = -0.5..=0.5;
+/// Narrowest and widest limited text column in points, apart from 0 (no limit)
+pub const LINE_WIDTH_RANGE: RangeInclusive<f32> = 320.0..=1600.0;
+/// Shortest and longest recent files list
+pub const RECENT_FILES_RANGE: RangeInclusive<usize> = 1..=50;
+/// Characters on a page of Chinese text, for page estimates
+pub const DEFAULT_CHARS_PER_PAGE: usize = 1000;
+pub const CHARS_PER_PAGE_RANGE: RangeInclusive<usize> = 100..=5000;
+/// Shortest and longest auto-save interval in seconds, apart from 0 (off)
+pub const AUTOSAVE_RANGE: RangeInclusive<u64> = 10..=3600;
+/// Narrowest and widest docked AI panel; the narrowest still fits the
+/// composer's buttons, and a floating panel is no narrower
+pub const AI_PANEL_WIDTH_RANGE: RangeInclusive<f32> = 260.0..=520.0;
+pub const AI_PANEL_MIN_HEIGHT: f32 = 280.0;
+/// Narrowest and widest print margin in points
+pub const PRINT_MARGIN_RANGE: RangeInclusive<u16> = 18..=144;
+
+/// `value` clamped to `range`, or `fallback` if it is not a positive number
+fn clamp_size(value: f32, range: RangeInclusive<f32>, fallback: f32) -> f32 {
+    if value.is_finite() && value > 0.0 {
+        value.clamp(*range.start(), *range.end())
+    } else {
+        fallback
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
-    /// Application theme (for future use)
+    /// Schema version, see [`crate::config_migration`]
+    #[serde(default = "current_version")]
+    pub version: u32,
+
+    /// Application theme, one of [`THEMES`]
     #[serde(default)]
     pub theme: String,
 
@@ -91,16 +612,84 @@ pub struct Settings {
     #[serde(default)]
     pub autosave_interval: u64,
 
-    /// Font size (for future use)
+    /// Editor font size in points
     #[serde(default)]
     pub font_size: f32,
 
+    /// Editor row height as a multiple of the font's natural line height
+    #[serde(default = "default_line_spacing")]
+    pub line_spacing: f32,
+
+    /// Chinese font picked in the 字体 menu; `None` uses the system default
+    #[serde(default)]
+    pub font_family: Option<String>,
+
+    /// Font for Latin text and numerals, drawn ahead of the Chinese font;
+    /// `None` leaves them to the Chinese font
+    #[serde(default)]
+    pub latin_font_family: Option<String>,
+
+    /// Weight of the text fonts; families without it use their closest one
+    #[serde(default)]
+    pub font_weight: crate::fonts::FontWeight,
+
+    /// Vertical offset of the CJK glyphs by font family (or font file
+    /// name), set in Settings; families not listed use a built-in value
+    #[serde(default)]
+    pub font_y_offsets: BTreeMap<String, f32>,
+
+    /// Font file loaded with 加载字体文件…; used instead of `font_family`
+    /// while set
+    #[serde(default)]
+    pub custom_font_path: Option<PathBuf>,
+
+    /// Widest the text column gets, in points (0 = fill the window)
+    #[serde(default)]
+    pub line_width: f32,
+
+    /// What 格式化 puts before each paragraph
+    #[serde(default)]
+    pub format_indent: FormatIndent,
+
     /// Recently opened file paths
     /// since the path is a string(heap data),
     /// Using fixed-size array won't make much difference on performance
     #[serde(default)]
     pub recent_files: Vec<PathBuf>,
 
+    /// Files kept at the top of the recent files menu, never dropped by
+    /// the recent files limit
+    #[serde(default)]
+    pub pinned_files: Vec<PathBuf>,
+
+    /// Length of the recent files list
+    #[serde(default = "default_max_recent_files")]
+    pub max_recent_files: usize,
+
+    /// Shown after the document name in the window title, e.g. in the task
+    /// switcher; empty shows the document name alone
+    #[serde(default = "default_window_title_suffix")]
+    pub window_title_suffix: String,
+
+    /// Characters per page for the page estimate in the stats tooltip
+    #[serde(default = "default_chars_per_page")]
+    pub chars_per_page: usize,
+
+    /// Use the system's title bar and window frame instead of the app's own;
+    /// takes effect on the next start
+    #[serde(default)]
+    pub native_decorations: bool,
+
+    /// Hand files opened later to the window already running instead of
+    /// starting another process; takes effect on the next start
+    #[serde(default)]
+    pub single_instance: bool,
+
+    /// Load an open file again when another program changes it and it has
+    /// no unsaved changes here; otherwise a banner asks first
+    #[serde(default = "default_auto_reload_external_changes")]
+    pub auto_reload_external_changes: bool,
+
     /// AI Panel configuration
     #[serde(default)]
     pub ai_panel: AiPanelConfig,
@@ -108,24 +697,550 @@ pub struct Settings {
     /// GitHub publish plugin configuration
     #[serde(default)]
     pub github_publish: crate::plugin::builtin::github_publish::GithubPublishConfig,
+
+    /// Daily/weekly writing-time goals
+    #[serde(default)]
+    pub writing_goals: WritingGoals,
+
+    /// Where the AI panel sits and how big it is
+    #[serde(default)]
+    pub ai_panel_layout: AiPanelLayout,
+
+    /// Main window size and position at the last exit
+    #[serde(default)]
+    pub window: WindowGeometry,
+
+    /// How much goes into the log files; `RUST_LOG` beats it when set
+    #[serde(default)]
+    pub log_level: crate::logging::LogLevel,
+
+    /// Reopen the files of the last session when started without one
+    #[serde(default = "default_restore_session")]
+    pub restore_session: bool,
+
+    /// Files open at the last exit, with where the caret and view were
+    #[serde(default)]
+    pub session: Session,
+
+    /// Day the summary of an unmet daily goal was last shown on exit; it is
+    /// shown once a day at most
+    #[serde(default)]
+    pub goal_summary_shown_on: Option<chrono::NaiveDate>,
+
+    /// How dates and times are shown, as a chrono format string
+    #[serde(default = "default_datetime_format")]
+    pub datetime_format: String,
+
+    /// Text a new document starts with, see `datetime::expand_placeholders`;
+    /// empty for none
+    #[serde(default)]
+    pub new_file_template: String,
+
+    /// Where history, marks and logs are kept; `None` uses the platform's
+    /// data dir. Ignored in portable mode.
+    #[serde(default)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Folder the save dialog starts in for new files; `None` uses Documents
+    #[serde(default)]
+    pub default_save_dir: Option<PathBuf>,
+
+    /// Keyboard shortcut for each app action
+    #[serde(default = "default_keybindings")]
+    pub keybindings: Keybindings,
+
+    /// How 打印 lays out the pages
+    #[serde(default)]
+    pub print: PrintSettings,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            version: CURRENT_VERSION,
             theme: "light".to_string(),
             autosave_interval: 300, // 5 minutes
-            font_size: 14.0,
+            font_size: DEFAULT_FONT_SIZE,
+            line_spacing: default_line_spacing(),
+            font_family: None,
+            latin_font_family: None,
+            font_weight: crate::fonts::FontWeight::default(),
+            font_y_offsets: BTreeMap::new(),
+            custom_font_path: None,
+            line_width: 0.0,
+            format_indent: FormatIndent::default(),
             recent_files: Vec::new(),
+            pinned_files: Vec::new(),
+            max_recent_files: MAX_RECENT_FILES,
+            window_title_suffix: default_window_title_suffix(),
+            chars_per_page: DEFAULT_CHARS_PER_PAGE,
+            native_decorations: false,
+            single_instance: false,
+            auto_reload_external_changes: default_auto_reload_external_changes(),
             ai_panel: AiPanelConfig::default(),
             github_publish: crate::plugin::builtin::github_publish::GithubPublishConfig::default(),
+            writing_goals: WritingGoals::default(),
+            ai_panel_layout: AiPanelLayout::default(),
+            window: WindowGeometry::default(),
+            log_level: crate::logging::LogLevel::default(),
+            restore_session: default_restore_session(),
+            session: Session::default(),
+            goal_summary_shown_on: None,
+            datetime_format: default_datetime_format(),
+            new_file_template: String::new(),
+            data_dir: None,
+            default_save_dir: None,
+            keybindings: default_keybindings(),
+            print: PrintSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Bring out-of-range values back into range.
+    ///
+    /// Missing or nonsensical values fall back to their defaults; values that
+    /// are merely too large or too small are clamped.
+    pub fn validate(&mut self) {
+        if !THEMES.iter().any(|(value, _)| *value == self.theme) {
+            self.theme = THEMES[0].0.to_string();
+        }
+        self.set_font_size(self.font_size);
+        self.set_line_spacing(self.line_spacing);
+        self.font_y_offsets.retain(|_, offset| offset.is_finite());
+        for offset in self.font_y_offsets.values_mut() {
+            *offset = offset.clamp(*FONT_Y_OFFSET_RANGE.start(), *FONT_Y_OFFSET_RANGE.end());
+        }
+        self.line_width = if self.line_width.is_finite() && self.line_width > 0.0 {
+            self.line_width
+                .clamp(*LINE_WIDTH_RANGE.start(), *LINE_WIDTH_RANGE.end())
+        } else {
+            0.0
+        };
+        self.set_max_recent_files(self.max_recent_files);
+        let mut seen = HashSet::new();
+        self.pinned_files.retain(|path| seen.insert(path.clone()));
+        // A pinned file is listed once, with the pinned ones
+        self.recent_files.retain(|path| !seen.contains(path));
+        self.recent_files.truncate(self.max_recent_files);
+        self.window_title_suffix = self.window_title_suffix.trim().to_string();
+        self.chars_per_page = self
+            .chars_per_page
+            .clamp(*CHARS_PER_PAGE_RANGE.start(), *CHARS_PER_PAGE_RANGE.end());
+        if self.autosave_interval != 0 {
+            self.autosave_interval = self
+                .autosave_interval
+                .clamp(*AUTOSAVE_RANGE.start(), *AUTOSAVE_RANGE.end());
+        }
+        if validate_format(&self.datetime_format).is_err() {
+            self.datetime_format = default_datetime_format();
+        }
+        self.window.normalize();
+        self.ai_panel_layout.validate();
+        self.print.margin_points = self
+            .print
+            .margin_points
+            .clamp(*PRINT_MARGIN_RANGE.start(), *PRINT_MARGIN_RANGE.end());
+        complete_keybindings(&mut self.keybindings);
+    }
+
+    /// Font size in points, within [`FONT_SIZE_RANGE`]
+    pub fn font_size(&self) -> f32 {
+        clamp_size(self.font_size, FONT_SIZE_RANGE, DEFAULT_FONT_SIZE)
+    }
+
+    pub fn set_font_size(&mut self, size: f32) {
+        self.font_size = clamp_size(size, FONT_SIZE_RANGE, DEFAULT_FONT_SIZE);
+    }
+
+    /// Line spacing as a multiple of the row height, within
+    /// [`LINE_SPACING_RANGE`]
+    pub fn line_spacing(&self) -> f32 {
+        clamp_size(
+            self.line_spacing,
+            LINE_SPACING_RANGE,
+            default_line_spacing(),
+        )
+    }
+
+    pub fn set_line_spacing(&mut self, spacing: f32) {
+        self.line_spacing = clamp_size(spacing, LINE_SPACING_RANGE, default_line_spacing());
+    }
+
+    /// Length of the recent files list, within [`RECENT_FILES_RANGE`]
+    pub fn max_recent_files(&self) -> usize {
+        self.max_recent_files
+            .clamp(*RECENT_FILES_RANGE.start(), *RECENT_FILES_RANGE.end())
+    }
+
+    pub fn set_max_recent_files(&mut self, count: usize) {
+        self.max_recent_files = count.clamp(*RECENT_FILES_RANGE.start(), *RECENT_FILES_RANGE.end());
+    }
+
+    /// Remove one file from the recent files list, pinned or not
+    pub fn remove_recent_file(&mut self, path: &Path) {
+        self.recent_files.retain(|p| p != path);
+        self.pinned_files.retain(|p| p != path);
+    }
+
+    /// Pin a recent file, or unpin it back to the front of the recent files
+    pub fn toggle_pinned_file(&mut self, path: &Path) {
+        if self.pinned_files.iter().any(|p| p == path) {
+            self.pinned_files.retain(|p| p != path);
+            let max = self.max_recent_files();
+            insert_recent_file(&mut self.recent_files, path.to_path_buf(), max);
+        } else {
+            self.recent_files.retain(|p| p != path);
+            self.pinned_files.push(path.to_path_buf());
+        }
+    }
+
+    /// Follow a recent or pinned file moved from `from` to `to`, keeping
+    /// its place in the list
+    pub fn rename_recent_file(&mut self, from: &Path, to: &Path) {
+        for path in self
+            .recent_files
+            .iter_mut()
+            .chain(self.pinned_files.iter_mut())
+        {
+            if path == from {
+                *path = to.to_path_buf();
+            }
+        }
+    }
+
+    /// Take over the values edited in the Settings window.
+    ///
+    /// State the window does not edit, such as recent files or the AI panel
+    /// layout, may have changed while it was open and is kept.
+    pub fn apply_edits(&mut self, edited: Settings) {
+        self.theme = edited.theme;
+        self.font_size = edited.font_size;
+        self.line_spacing = edited.line_spacing;
+        self.font_y_offsets = edited.font_y_offsets;
+        self.line_width = edited.line_width;
+        self.format_indent = edited.format_indent;
+        self.autosave_interval = edited.autosave_interval;
+        self.ai_panel = edited.ai_panel;
+        self.keybindings = edited.keybindings;
+        self.default_save_dir = edited.default_save_dir;
+        self.datetime_format = edited.datetime_format;
+        self.new_file_template = edited.new_file_template;
+        self.max_recent_files = edited.max_recent_files;
+        self.window_title_suffix = edited.window_title_suffix;
+        self.chars_per_page = edited.chars_per_page;
+        self.native_decorations = edited.native_decorations;
+        self.single_instance = edited.single_instance;
+        self.auto_reload_external_changes = edited.auto_reload_external_changes;
+        self.restore_session = edited.restore_session;
+        self.log_level = edited.log_level;
+        self.print = edited.print;
+        self.validate();
+    }
+}
+
+fn default_max_recent_files() -> usize {
+    MAX_RECENT_FILES
+}
+
+fn default_chars_per_page() -> usize {
+    DEFAULT_CHARS_PER_PAGE
+}
+
+fn default_window_title_suffix() -> String {
+    DEFAULT_WINDOW_TITLE.to_string()
+}
+
+fn default_datetime_format() -> String {
+    DEFAULT_DATETIME_FORMAT.to_string()
+}
+
+fn current_version() -> u32 {
+    CURRENT_VERSION
+}
+
+fn default_line_spacing() -> f32 {
+    1.0
+}
+
+fn default_auto_reload_external_changes() -> bool {
+    true
+}
+
+fn default_restore_session() -> bool {
+    true
+}
+
+/// Paragraph indentation inserted by 格式化
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatIndent {
+    #[default]
+    TwoSpaces,
+    FourSpaces,
+    /// Two ideographic spaces, the usual indent in Chinese prose
+    FullWidth,
+}
+
+impl FormatIndent {
+    pub const ALL: [FormatIndent; 3] = [
+        FormatIndent::TwoSpaces,
+        FormatIndent::FourSpaces,
+        FormatIndent::FullWidth,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FormatIndent::TwoSpaces => "  ",
+            FormatIndent::FourSpaces => "    ",
+            FormatIndent::FullWidth => "\u{3000}\u{3000}",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FormatIndent::TwoSpaces => "两个空格",
+            FormatIndent::FourSpaces => "四个空格",
+            FormatIndent::FullWidth => "两个全角空格",
         }
     }
 }
 
+/// Page setup for printing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrintSettings {
+    #[serde(default)]
+    pub page_size: PageSize,
+
+    /// Margin on every side, in points
+    #[serde(default = "default_print_margin")]
+    pub margin_points: u16,
+
+    /// Start each paragraph with the 格式化 indent; otherwise paragraphs
+    /// are printed flush left
+    #[serde(default = "default_indent_paragraphs")]
+    pub indent_paragraphs: bool,
+}
+
+impl Default for PrintSettings {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::default(),
+            margin_points: default_print_margin(),
+            indent_paragraphs: default_indent_paragraphs(),
+        }
+    }
+}
+
+fn default_print_margin() -> u16 {
+    72
+}
+
+fn default_indent_paragraphs() -> bool {
+    true
+}
+
+/// Writing-time goals in minutes (0 = no goal)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WritingGoals {
+    #[serde(default)]
+    pub daily_minutes: u64,
+
+    #[serde(default)]
+    pub weekly_minutes: u64,
+}
+
+/// Placement of the AI panel: docked to the right edge or floating in the
+/// main window, optionally collapsed to a small pill
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AiPanelLayout {
+    /// Whether the panel is shown at all; hidden until the user opens it
+    #[serde(default)]
+    pub visible: bool,
+
+    #[serde(default)]
+    pub floating: bool,
+
+    #[serde(default)]
+    pub collapsed: bool,
+
+    /// Width when docked
+    #[serde(default = "default_ai_panel_width")]
+    pub docked_width: f32,
+
+    /// Top-left corner of the floating window; `None` until it is first moved
+    #[serde(default)]
+    pub position: Option<[f32; 2]>,
+
+    /// Size of the floating window
+    #[serde(default = "default_ai_panel_size")]
+    pub size: [f32; 2],
+}
+
+impl Default for AiPanelLayout {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            floating: false,
+            collapsed: false,
+            docked_width: default_ai_panel_width(),
+            position: None,
+            size: default_ai_panel_size(),
+        }
+    }
+}
+
+impl AiPanelLayout {
+    /// Repair sizes and positions a hand-edited or corrupt config may hold.
+    pub fn validate(&mut self) {
+        self.set_docked_width(self.docked_width);
+        let default = default_ai_panel_size();
+        let min = [*AI_PANEL_WIDTH_RANGE.start(), AI_PANEL_MIN_HEIGHT];
+        for axis in 0..2 {
+            let value = self.size[axis];
+            self.size[axis] = if value.is_finite() && value > 0.0 {
+                value.max(min[axis])
+            } else {
+                default[axis]
+            };
+        }
+        if self
+            .position
+            .is_some_and(|position| !position.iter().all(|value| value.is_finite()))
+        {
+            self.position = None;
+        }
+    }
+
+    /// Width when docked, within [`AI_PANEL_WIDTH_RANGE`]
+    pub fn docked_width(&self) -> f32 {
+        clamp_size(
+            self.docked_width,
+            AI_PANEL_WIDTH_RANGE,
+            default_ai_panel_width(),
+        )
+    }
+
+    pub fn set_docked_width(&mut self, width: f32) {
+        self.docked_width = clamp_size(width, AI_PANEL_WIDTH_RANGE, default_ai_panel_width());
+    }
+}
+
+/// The files open when the app last closed, reopened on the next start
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// In tab order; untitled documents are not part of it
+    #[serde(default)]
+    pub tabs: Vec<SessionTab>,
+
+    /// Index in `tabs` of the tab that was active
+    #[serde(default)]
+    pub active: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionTab {
+    pub path: PathBuf,
+
+    /// Caret position in chars
+    #[serde(default)]
+    pub cursor: Option<usize>,
+
+    /// How far the text was scrolled down, in points
+    #[serde(default)]
+    pub scroll: f32,
+}
+
+/// Main window geometry in points, restored on the next launch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Inner size; the size before maximizing while maximized
+    #[serde(default = "default_window_size")]
+    pub size: [f32; 2],
+
+    /// Top-left corner of the window; `None` lets the system place it
+    #[serde(default)]
+    pub position: Option<[f32; 2]>,
+
+    #[serde(default)]
+    pub maximized: bool,
+}
+
+impl Default for WindowGeometry {
+    fn default() -> Self {
+        Self {
+            size: default_window_size(),
+            position: None,
+            maximized: false,
+        }
+    }
+}
+
+impl WindowGeometry {
+    /// Part of the window that must stay on screen to be grabbed again
+    const MIN_VISIBLE: f32 = 80.0;
+
+    /// Repair sizes and positions a hand-edited or corrupt config may hold.
+    pub fn normalize(&mut self) {
+        let default = default_window_size();
+        let min = [MIN_WINDOW_WIDTH, 100.0];
+        for axis in 0..2 {
+            let value = self.size[axis];
+            self.size[axis] = if value.is_finite() && value > 0.0 {
+                value.max(min[axis])
+            } else {
+                default[axis]
+            };
+        }
+        if self
+            .position
+            .is_some_and(|position| !position.iter().all(|value| value.is_finite()))
+        {
+            self.position = None;
+        }
+    }
+
+    /// Fit the window onto a monitor of `monitor_size`.
+    ///
+    /// The size shrinks to the monitor, and a window that would end up (almost)
+    /// entirely off screen, e.g. after a second display was unplugged, is
+    /// centered instead.
+    pub fn fit_to_monitor(&self, monitor_size: [f32; 2]) -> WindowGeometry {
+        let mut fitted = *self;
+        for (size, limit) in fitted.size.iter_mut().zip(monitor_size) {
+            *size = size.min(limit);
+        }
+        if let Some(position) = fitted.position {
+            let visible = (0..2).all(|axis| {
+                position[axis] + fitted.size[axis] >= Self::MIN_VISIBLE
+                    && position[axis] <= monitor_size[axis] - Self::MIN_VISIBLE
+            });
+            if !visible {
+                fitted.position = Some([
+                    ((monitor_size[0] - fitted.size[0]) / 2.0).max(0.0),
+                    ((monitor_size[1] - fitted.size[1]) / 2.0).max(0.0),
+                ]);
+            }
+        }
+        fitted
+    }
+}
+
+fn default_window_size() -> [f32; 2] {
+    [DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT]
+}
+
+fn default_ai_panel_width() -> f32 {
+    320.0
+}
+
+fn default_ai_panel_size() -> [f32; 2] {
+    [340.0, 520.0]
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiPanelConfig {
-    /// AI provider: "ollama" or "kimi"
+    /// AI provider: "ollama", "kimi" or "openai" (any OpenAI-compatible endpoint)
     #[serde(default = "default_ai_provider")]
     pub provider: String,
 
@@ -134,6 +1249,11 @@ pub struct AiPanelConfig {
     #[serde(default)]
     pub api_key: String,
 
+    /// The API key is kept in the OS keychain and `api_key` is left empty
+    /// in the settings file
+    #[serde(default)]
+    pub api_key_in_keychain: bool,
+
     /// API URL for AI service
     #[serde(default)]
     pub api_url: String,
@@ -141,6 +1261,60 @@ pub struct AiPanelConfig {
     /// Model name for AI service
     #[serde(default)]
     pub model_name: String,
+
+    /// Reusable prompts offered in the AI panel
+    #[serde(default = "default_prompt_templates")]
+    pub prompt_templates: Vec<PromptTemplate>,
+
+    /// Seconds before a request to the model is abandoned; `None` allows
+    /// a local Ollama longer than a hosted service
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+
+    /// Longest selection, in characters, sent to the model in one request
+    #[serde(default = "default_max_prompt_chars")]
+    pub max_prompt_chars: usize,
+
+    /// What to do with a selection longer than `max_prompt_chars`
+    #[serde(default)]
+    pub oversize_strategy: OversizeStrategy,
+
+    /// Sampling temperature; `None` leaves it to the provider
+    #[serde(default)]
+    pub temperature: Option<f32>,
+
+    /// Output token limit; `None` uses the provider's default
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+
+    /// Persona placed at the top of the system prompt
+    #[serde(default = "default_system_instruction")]
+    pub system_instruction: String,
+
+    /// Per-model prices used to estimate spending
+    #[serde(default)]
+    pub model_prices: Vec<ModelPrice>,
+}
+
+/// Price of a model per million tokens, in whatever currency the user bills in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub model: String,
+    #[serde(default)]
+    pub input_per_million: f64,
+    #[serde(default)]
+    pub output_per_million: f64,
+}
+
+/// Handling of selections that exceed the prompt budget
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizeStrategy {
+    /// Send only the first `max_prompt_chars` characters
+    #[default]
+    Truncate,
+    /// Send the selection in pieces, one request each, and join the replies
+    Chunk,
 }
 
 impl Default for AiPanelConfig {
@@ -148,12 +1322,602 @@ impl Default for AiPanelConfig {
         Self {
             provider: default_ai_provider(),
             api_key: String::new(),
+            api_key_in_keychain: false,
             api_url: "http://localhost:11434/api/chat".to_string(),
             model_name: "qwen3:8b".to_string(),
+            prompt_templates: default_prompt_templates(),
+            timeout_secs: None,
+            max_prompt_chars: default_max_prompt_chars(),
+            oversize_strategy: OversizeStrategy::default(),
+            temperature: None,
+            max_output_tokens: None,
+            system_instruction: default_system_instruction(),
+            model_prices: Vec::new(),
         }
     }
 }
 
+/// Persona used when the setting is left empty
+pub const DEFAULT_SYSTEM_INSTRUCTION: &str = "你是 Paper Shell 里的写作伙伴：认真读、坦率说，\
+帮助作者把想法想清楚，而不是替作者写。文档属于用户，正文始终是主角。";
+
+fn default_system_instruction() -> String {
+    DEFAULT_SYSTEM_INSTRUCTION.to_string()
+}
+
 fn default_ai_provider() -> String {
     "ollama".to_string()
 }
+
+fn default_max_prompt_chars() -> usize {
+    12_000
+}
+
+/// Placeholder replaced by the text a template is applied to
+pub const PROMPT_TEXT_PLACEHOLDER: &str = "{text}";
+
+/// Put in for the placeholder when there is no selection, so the template
+/// asks about the whole document
+pub const PROMPT_WHOLE_DOCUMENT: &str = "（当前文档）";
+
+/// A named prompt with a `{text}` placeholder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub template: String,
+}
+
+impl PromptTemplate {
+    pub fn new(name: &str, template: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            template: template.to_string(),
+        }
+    }
+
+    /// Substitute `text` for every `{text}` placeholder.
+    ///
+    /// Templates without a placeholder get the text appended on a new line,
+    /// so a user-written template never silently drops the input.
+    pub fn render(&self, text: &str) -> String {
+        if self.template.contains(PROMPT_TEXT_PLACEHOLDER) {
+            self.template.replace(PROMPT_TEXT_PLACEHOLDER, text)
+        } else if text.is_empty() {
+            self.template.clone()
+        } else {
+            format!("{}\n{}", self.template.trim_end(), text)
+        }
+    }
+}
+
+fn default_prompt_templates() -> Vec<PromptTemplate> {
+    vec![
+        PromptTemplate::new("润色", "请润色下面的文字，保持原意和语气：\n{text}"),
+        PromptTemplate::new("续写", "请顺着下面的文字续写一段，风格保持一致：\n{text}"),
+        PromptTemplate::new("摘要", "请用三五句话概括下面的内容：\n{text}"),
+        PromptTemplate::new(
+            "找错别字",
+            "请找出下面文字中的错别字和病句，逐条列出：\n{text}",
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::MemoryStore;
+
+    fn config_with_store(store: &MemoryStore) -> Config {
+        Config {
+            settings: Settings::default(),
+            writer: None,
+            newer_version: None,
+            first_run: false,
+            stamp: FileStamp::default(),
+            last_reload_check: Instant::now(),
+            secrets: Some(Box::new(store.clone())),
+            stored_api_key: None,
+            data_dir_arg: None,
+        }
+    }
+
+    #[test]
+    fn test_data_dir_arg_beats_the_chosen_data_dir() {
+        let mut config = config_with_store(&MemoryStore::new());
+        let chosen = PathBuf::from("/srv/writing");
+        config.settings.data_dir = Some(chosen.clone());
+        assert_eq!(config.data_dir_arg(), None);
+
+        let arg = PathBuf::from("/tmp/paper-shell-test");
+        config.set_data_dir_arg(Some(arg.clone()));
+        assert_eq!(config.data_dir(), arg);
+        assert_eq!(config.data_dir_arg(), Some(arg.as_path()));
+    }
+
+    #[test]
+    fn test_render_replaces_every_placeholder() {
+        let template = PromptTemplate::new("对比", "原文：{text}\n再看一遍：{text}");
+        assert_eq!(template.render("春眠"), "原文：春眠\n再看一遍：春眠");
+    }
+
+    #[test]
+    fn test_render_without_placeholder_appends_text() {
+        let template = PromptTemplate::new("自由", "帮我看看  ");
+        assert_eq!(template.render("这段话"), "帮我看看\n这段话");
+        assert_eq!(template.render(""), "帮我看看  ");
+    }
+
+    #[test]
+    fn test_ai_panel_layout_from_older_config_is_hidden() {
+        let layout: AiPanelLayout =
+            toml::from_str("floating = true\ndocked_width = 400.0").unwrap();
+        assert!(!layout.visible);
+        assert!(layout.floating);
+        assert_eq!(layout.size, default_ai_panel_size());
+
+        let shown = AiPanelLayout {
+            visible: true,
+            ..AiPanelLayout::default()
+        };
+        let restored: AiPanelLayout = toml::from_str(&toml::to_string(&shown).unwrap()).unwrap();
+        assert_eq!(restored, shown);
+    }
+
+    #[test]
+    fn test_accessors_keep_values_in_range() {
+        let mut settings = Settings {
+            font_size: -3.0,
+            line_spacing: f32::INFINITY,
+            max_recent_files: 500,
+            ..Settings::default()
+        };
+        // Read through the accessors, fields set directly are still in range
+        assert_eq!(settings.font_size(), DEFAULT_FONT_SIZE);
+        assert_eq!(settings.line_spacing(), default_line_spacing());
+        assert_eq!(settings.max_recent_files(), 50);
+
+        settings.set_font_size(8.0);
+        settings.set_line_spacing(1.5);
+        settings.set_max_recent_files(0);
+        assert_eq!(
+            (
+                settings.font_size,
+                settings.line_spacing,
+                settings.max_recent_files
+            ),
+            (10.0, 1.5, 1)
+        );
+
+        let mut layout = AiPanelLayout {
+            docked_width: 0.0,
+            ..AiPanelLayout::default()
+        };
+        assert_eq!(layout.docked_width(), default_ai_panel_width());
+        layout.set_docked_width(100.0);
+        assert_eq!(layout.docked_width, 260.0);
+        layout.set_docked_width(f32::NAN);
+        assert_eq!(layout.docked_width, default_ai_panel_width());
+    }
+
+    #[test]
+    fn test_validate_clamps_and_repairs_values() {
+        let mut settings: Settings = toml::from_str("theme = \"neon\"").unwrap();
+        settings.validate();
+        // Fields missing from an older config deserialize as zero
+        assert_eq!(settings.theme, "light");
+        assert_eq!(settings.font_size, DEFAULT_FONT_SIZE);
+        assert_eq!(settings.line_spacing, 1.0);
+        assert_eq!(settings.autosave_interval, 0);
+
+        settings.font_size = 99.0;
+        settings.line_spacing = 0.5;
+        settings.autosave_interval = 3;
+        settings.line_width = 100.0;
+        settings.max_recent_files = 0;
+        settings.recent_files = vec![PathBuf::from("/a.txt"), PathBuf::from("/b.txt")];
+        settings.datetime_format = "%Y-%Q".to_string();
+        settings.font_y_offsets =
+            BTreeMap::from([("宋体".to_string(), 2.0), ("黑体".to_string(), f32::NAN)]);
+        settings.validate();
+        assert_eq!(settings.font_size, 36.0);
+        assert_eq!(settings.line_width, 320.0);
+        assert_eq!(settings.max_recent_files, 1);
+        assert_eq!(settings.recent_files, [PathBuf::from("/a.txt")]);
+        assert_eq!(settings.line_spacing, 1.0);
+        assert_eq!(settings.autosave_interval, 10);
+        assert_eq!(settings.datetime_format, DEFAULT_DATETIME_FORMAT);
+        assert_eq!(
+            settings.font_y_offsets,
+            BTreeMap::from([("宋体".to_string(), 0.5)])
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_settings_file_is_clamped_on_load() {
+        let text = format!(
+            r#"
+version = {CURRENT_VERSION}
+font_size = -3.0
+line_spacing = 40.0
+line_width = 5000.0
+max_recent_files = 900
+chars_per_page = 0
+autosave_interval = 99999
+
+[print]
+page_size = "letter"
+margin_points = 2
+
+[ai_panel_layout]
+visible = true
+docked_width = 0.0
+size = [10.0, -1.0]
+position = [nan, 20.0]
+
+[window]
+size = [0.0, 50.0]
+"#
+        );
+        let (mut settings, outcome) = parse_settings(&text).unwrap();
+        assert_eq!(outcome, MigrationOutcome::Current);
+        settings.validate();
+
+        assert_eq!(settings.font_size, DEFAULT_FONT_SIZE);
+        assert_eq!(settings.line_spacing, 2.5);
+        assert_eq!(settings.line_width, 1600.0);
+        assert_eq!(settings.max_recent_files, 50);
+        assert_eq!(settings.chars_per_page, 100);
+        assert_eq!(settings.autosave_interval, 3600);
+        assert_eq!(settings.window.size, [DEFAULT_WINDOW_WIDTH, 100.0]);
+        let layout = settings.ai_panel_layout;
+        assert!(layout.visible);
+        assert_eq!(layout.docked_width, default_ai_panel_width());
+        assert_eq!(layout.size, [260.0, default_ai_panel_size()[1]]);
+        assert_eq!(layout.position, None);
+
+        assert_eq!(
+            settings.print,
+            PrintSettings {
+                page_size: PageSize::Letter,
+                margin_points: 18,
+                indent_paragraphs: true,
+            }
+        );
+
+        // A panel dragged wider than the range is pulled back
+        let mut layout = AiPanelLayout {
+            docked_width: 2000.0,
+            ..Default::default()
+        };
+        layout.validate();
+        assert_eq!(layout.docked_width, 520.0);
+    }
+
+    #[test]
+    fn test_apply_edits_keeps_state_the_window_does_not_edit() {
+        let mut settings = Settings::default();
+        let mut edited = settings.clone();
+        settings
+            .recent_files
+            .push(PathBuf::from("/tmp/opened-meanwhile.txt"));
+        settings.ai_panel_layout.visible = true;
+        edited.theme = "sepia".to_string();
+        edited.font_size = 5.0;
+        edited.format_indent = FormatIndent::FullWidth;
+        edited.ai_panel.model_name = "qwen3:14b".to_string();
+
+        settings.apply_edits(edited);
+
+        assert_eq!(settings.theme, "sepia");
+        assert_eq!(settings.font_size, 10.0);
+        assert_eq!(settings.format_indent.as_str(), "\u{3000}\u{3000}");
+        assert_eq!(settings.ai_panel.model_name, "qwen3:14b");
+        assert_eq!(settings.recent_files.len(), 1);
+        assert!(settings.ai_panel_layout.visible);
+    }
+
+    #[test]
+    fn test_export_round_trips_without_machine_specific_values() {
+        let mut settings = Settings {
+            theme: "sepia".to_string(),
+            ..Settings::default()
+        };
+        settings.ai_panel.api_key = "sk-secret".to_string();
+        settings.ai_panel.temperature = None;
+        settings
+            .recent_files
+            .push(PathBuf::from("/home/me/journal.txt"));
+        settings.session.tabs.push(SessionTab {
+            path: PathBuf::from("/home/me/draft.txt"),
+            cursor: Some(12),
+            scroll: 240.0,
+        });
+        settings
+            .keybindings
+            .insert(crate::shortcuts::Action::Format, "Alt+F".parse().unwrap());
+
+        let exported = export_settings(&settings, false).unwrap();
+        assert!(!exported.contains("sk-secret"));
+        assert!(!exported.contains("journal.txt"));
+        assert!(!exported.contains("draft.txt"));
+
+        let mut current = Settings::default();
+        current.ai_panel.api_key = "sk-local".to_string();
+        current
+            .recent_files
+            .push(PathBuf::from("/Users/me/notes.txt"));
+        let imported = import_settings(&exported, &current).unwrap();
+        assert_eq!(imported.theme, "sepia");
+        assert_eq!(imported.keybindings, settings.keybindings);
+        // What the export left out stays as it is on this machine
+        assert_eq!(imported.ai_panel.api_key, "sk-local");
+        assert_eq!(imported.recent_files, current.recent_files);
+
+        let with_paths = export_settings(&settings, true).unwrap();
+        let imported = import_settings(&with_paths, &current).unwrap();
+        assert_eq!(imported.recent_files, settings.recent_files);
+        assert_eq!(imported.ai_panel.api_key, "sk-local");
+    }
+
+    #[test]
+    fn test_api_key_moves_between_keychain_and_settings_file() {
+        let store = MemoryStore::new();
+        let mut config = config_with_store(&store);
+        config.settings.ai_panel.api_key = "sk-secret".to_string();
+        config.settings.ai_panel.api_key_in_keychain = true;
+        config.sync_api_key().unwrap();
+        assert_eq!(store.get().unwrap().as_deref(), Some("sk-secret"));
+        let written = toml::to_string(&config.storable_settings()).unwrap();
+        assert!(!written.contains("sk-secret"));
+
+        // The next start reads it back from the keychain
+        let mut restarted = config_with_store(&store);
+        restarted.settings = toml::from_str(&written).unwrap();
+        restarted.load_api_key();
+        assert_eq!(restarted.settings.ai_panel.api_key, "sk-secret");
+
+        restarted.settings.ai_panel.api_key_in_keychain = false;
+        restarted.sync_api_key().unwrap();
+        assert_eq!(store.get().unwrap(), None);
+        let written = toml::to_string(&restarted.storable_settings()).unwrap();
+        assert!(written.contains("sk-secret"));
+    }
+
+    #[test]
+    fn test_api_key_stays_in_settings_file_without_a_working_keychain() {
+        let store = MemoryStore::failing();
+        let mut config = config_with_store(&store);
+        config.settings.ai_panel.api_key = "sk-secret".to_string();
+        config.settings.ai_panel.api_key_in_keychain = true;
+        assert!(config.sync_api_key().is_err());
+        assert!(!config.settings.ai_panel.api_key_in_keychain);
+        let written = toml::to_string(&config.storable_settings()).unwrap();
+        assert!(written.contains("sk-secret"));
+
+        config.secrets = None;
+        config.settings.ai_panel.api_key_in_keychain = true;
+        assert!(matches!(
+            config.sync_api_key(),
+            Err(SecretError::Unavailable)
+        ));
+        assert!(!config.settings.ai_panel.api_key_in_keychain);
+    }
+
+    #[test]
+    fn test_import_rejects_newer_schemas_and_other_files() {
+        let current = Settings::default();
+        let newer = format!(
+            r#"{{"version": {}, "theme": "sepia"}}"#,
+            CURRENT_VERSION + 1
+        );
+        assert!(matches!(
+            import_settings(&newer, &current),
+            Err(ConfigError::IncompatibleVersion(v)) if v == CURRENT_VERSION + 1
+        ));
+        assert!(matches!(
+            import_settings(r#"{"theme": "sepia"}"#, &current),
+            Err(ConfigError::NotAnExport(_))
+        ));
+        assert!(matches!(
+            import_settings("[1, 2]", &current),
+            Err(ConfigError::NotAnExport(_))
+        ));
+        assert!(import_settings("theme = 'sepia'", &current).is_err());
+
+        // Older schemas are migrated like the settings file
+        let old = import_settings(r#"{"version": 1, "theme": "sepia"}"#, &current).unwrap();
+        assert_eq!(old.font_size, DEFAULT_FONT_SIZE);
+        assert_eq!(old.version, CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_window_geometry_is_repaired_and_fitted_to_the_monitor() {
+        let mut settings: Settings = toml::from_str("[window]\nsize = [0.0, 900.0]").unwrap();
+        settings.validate();
+        assert_eq!(settings.window.size, [DEFAULT_WINDOW_WIDTH, 900.0]);
+        assert_eq!(settings.window.position, None);
+
+        let window = WindowGeometry {
+            size: [1600.0, 900.0],
+            position: Some([100.0, 50.0]),
+            maximized: false,
+        };
+        let fitted = window.fit_to_monitor([1280.0, 800.0]);
+        assert_eq!(fitted.size, [1280.0, 800.0]);
+        assert_eq!(fitted.position, Some([100.0, 50.0]));
+
+        // Left behind on a display that is no longer connected
+        let stranded = WindowGeometry {
+            size: [800.0, 600.0],
+            position: Some([2500.0, 100.0]),
+            maximized: false,
+        };
+        assert_eq!(
+            stranded.fit_to_monitor([1280.0, 800.0]).position,
+            Some([240.0, 100.0])
+        );
+    }
+
+    #[test]
+    fn test_insert_recent_file_moves_existing_entry_to_front() {
+        let mut files = vec![PathBuf::from("/a.txt"), PathBuf::from("/b.txt")];
+        insert_recent_file(&mut files, PathBuf::from("/b.txt"), MAX_RECENT_FILES);
+        assert_eq!(files, [PathBuf::from("/b.txt"), PathBuf::from("/a.txt")]);
+
+        for i in 0..MAX_RECENT_FILES {
+            insert_recent_file(
+                &mut files,
+                PathBuf::from(format!("/{i}.txt")),
+                MAX_RECENT_FILES,
+            );
+        }
+        assert_eq!(files.len(), MAX_RECENT_FILES);
+        assert_eq!(
+            files[0],
+            PathBuf::from(format!("/{}.txt", MAX_RECENT_FILES - 1))
+        );
+
+        insert_recent_file(&mut files, PathBuf::from("/new.txt"), 3);
+        assert_eq!(files.len(), 3);
+    }
+
+    #[test]
+    fn test_pinned_files_are_kept_apart_from_recent_files() {
+        let mut settings = Settings {
+            max_recent_files: 2,
+            recent_files: ["/a.txt", "/b.txt"].map(PathBuf::from).to_vec(),
+            ..Settings::default()
+        };
+        settings.toggle_pinned_file(Path::new("/b.txt"));
+        assert_eq!(settings.pinned_files, [PathBuf::from("/b.txt")]);
+        assert_eq!(settings.recent_files, [PathBuf::from("/a.txt")]);
+
+        // The recent limit does not push pinned files out
+        settings.recent_files = ["/c.txt", "/d.txt", "/b.txt"].map(PathBuf::from).to_vec();
+        settings.validate();
+        assert_eq!(settings.recent_files.len(), 2);
+        assert!(!settings.recent_files.contains(&PathBuf::from("/b.txt")));
+        assert_eq!(settings.pinned_files, [PathBuf::from("/b.txt")]);
+
+        settings.toggle_pinned_file(Path::new("/b.txt"));
+        assert!(settings.pinned_files.is_empty());
+        assert_eq!(settings.recent_files[0], PathBuf::from("/b.txt"));
+    }
+
+    #[test]
+    fn test_renamed_files_keep_their_place() {
+        let mut settings = Settings {
+            recent_files: ["/a.txt", "/b.txt"].map(PathBuf::from).to_vec(),
+            pinned_files: vec![PathBuf::from("/c.txt")],
+            ..Settings::default()
+        };
+        settings.rename_recent_file(Path::new("/b.txt"), Path::new("/草稿/b.txt"));
+        settings.rename_recent_file(Path::new("/c.txt"), Path::new("/d.txt"));
+        assert_eq!(
+            settings.recent_files,
+            ["/a.txt", "/草稿/b.txt"].map(PathBuf::from)
+        );
+        assert_eq!(settings.pinned_files, [PathBuf::from("/d.txt")]);
+    }
+
+    #[test]
+    fn test_prune_recent_files_drops_missing_and_duplicate_paths() {
+        let mut files = ["/a.txt", "/gone.txt", "/b.txt", "/a.txt"]
+            .map(PathBuf::from)
+            .to_vec();
+        prune_recent_files(&mut files, MAX_RECENT_FILES, |path| {
+            path != Path::new("/gone.txt")
+        });
+        assert_eq!(files, [PathBuf::from("/a.txt"), PathBuf::from("/b.txt")]);
+    }
+
+    #[test]
+    fn test_recent_file_path_resolves_relative_segments() {
+        let dir = std::env::temp_dir().join(format!("test_recent_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "").unwrap();
+
+        let roundabout = dir.join("sub").join("..").join(".").join("a.txt");
+        assert_eq!(recent_file_path(roundabout), recent_file_path(file.clone()));
+        assert!(recent_file_path(file).is_absolute());
+        // A path that does not exist (yet) is still made absolute
+        assert!(recent_file_path(PathBuf::from("missing.txt")).is_absolute());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_settings_migrates_old_files() {
+        let (settings, outcome) = parse_settings("theme = \"sepia\"").unwrap();
+        assert_eq!(outcome, MigrationOutcome::Migrated { from: 1 });
+        assert_eq!(settings.version, CURRENT_VERSION);
+        assert_eq!(settings.theme, "sepia");
+        assert_eq!(settings.autosave_interval, 300);
+
+        // A newer file's unknown fields are ignored rather than failing the load
+        let (settings, outcome) =
+            parse_settings("version = 9\ntheme = \"light\"\nfuture = 1").unwrap();
+        assert_eq!(outcome, MigrationOutcome::Newer(9));
+        assert_eq!(settings.version, 9);
+
+        let saved = toml::to_string(&Settings::default()).unwrap();
+        assert_eq!(parse_settings(&saved).unwrap().1, MigrationOutcome::Current);
+    }
+
+    #[test]
+    fn test_file_stamp_tells_outside_edits_from_own_writes() {
+        let dir = std::env::temp_dir().join(format!("test_stamp_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let stamp = FileStamp::new(&path);
+        assert!(!stamp.take_change(&path));
+
+        stamp.record_write(&path, || {
+            std::fs::write(&path, "theme = \"light\"").unwrap()
+        });
+        assert!(!stamp.take_change(&path));
+
+        // Edited by hand a little later
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        assert!(stamp.take_change(&path));
+        assert!(!stamp.take_change(&path));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!stamp.take_change(&path));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_first_existing_dir_skips_missing_folders() {
+        let existing = std::env::temp_dir();
+        let missing = existing.join(format!("missing_{}", uuid::Uuid::new_v4()));
+        let fallback = PathBuf::from("data");
+
+        assert_eq!(
+            first_existing_dir(
+                [None, Some(missing.clone()), Some(existing.clone())],
+                fallback.clone()
+            ),
+            existing
+        );
+        assert_eq!(
+            first_existing_dir([Some(missing), None], fallback.clone()),
+            fallback
+        );
+    }
+
+    #[test]
+    fn test_default_templates_include_placeholder() {
+        let templates = default_prompt_templates();
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["润色", "续写", "摘要", "找错别字"]);
+        assert!(
+            templates
+                .iter()
+                .all(|t| t.template.contains(PROMPT_TEXT_PLACEHOLDER))
+        );
+    }
+}
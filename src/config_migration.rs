@@ -0,0 +1,160 @@
+//! Upgrades stored settings written by older versions of the app.
+//!
+//! The settings file carries a `version`; files from before versioning have
+//! none and count as version 1. Each migration takes the raw TOML table one
+//! version forward, so renamed or retyped fields can be carried over instead
+//! of silently falling back to their defaults.
+
+use crate::shortcuts::{Action, KeyCombo};
+use toml::{Table, Value};
+
+/// Schema version written by this build
+pub const CURRENT_VERSION: u32 = 3;
+
+/// `MIGRATIONS[n]` takes a table from version `n + 1` to `n + 2`
+const MIGRATIONS: [fn(&mut Table); (CURRENT_VERSION - 1) as usize] =
+    [migrate_v1_to_v2, migrate_v2_to_v3];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrationOutcome {
+    /// Already at [`CURRENT_VERSION`]
+    Current,
+    /// Upgraded from the given version
+    Migrated { from: u32 },
+    /// Written by a newer build; left untouched
+    Newer(u32),
+}
+
+/// Version the table was written with
+pub fn stored_version(table: &Table) -> u32 {
+    table
+        .get("version")
+        .and_then(Value::as_integer)
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Bring `table` up to [`CURRENT_VERSION`].
+pub fn migrate(table: &mut Table) -> MigrationOutcome {
+    let from = stored_version(table);
+    if from > CURRENT_VERSION {
+        return MigrationOutcome::Newer(from);
+    }
+    if from == CURRENT_VERSION {
+        return MigrationOutcome::Current;
+    }
+    for migration in &MIGRATIONS[(from - 1) as usize..] {
+        migration(table);
+    }
+    table.insert(
+        "version".to_string(),
+        Value::Integer(CURRENT_VERSION.into()),
+    );
+    MigrationOutcome::Migrated { from }
+}
+
+/// Files from before versioning may lack `font_size` and `autosave_interval`,
+/// which would read as 0 (tiny font, auto-save off) rather than the defaults.
+fn migrate_v1_to_v2(table: &mut Table) {
+    table
+        .entry("font_size")
+        .or_insert(Value::Float(crate::config::DEFAULT_FONT_SIZE.into()));
+    table
+        .entry("autosave_interval")
+        .or_insert(Value::Integer(300));
+}
+
+/// Cmd+Shift+F went from formatting to the new global search. Files store
+/// every binding, so one still on the old default is moved to the new one;
+/// otherwise formatting would keep the combo and shadow the search.
+fn migrate_v2_to_v3(table: &mut Table) {
+    let Some(Value::Table(bindings)) = table.get_mut("keybindings") else {
+        return;
+    };
+    let old_default: KeyCombo = "Cmd+Shift+F".parse().expect("valid shortcut");
+    let on_old_default = bindings
+        .get("format")
+        .and_then(Value::as_str)
+        .and_then(|combo| combo.parse::<KeyCombo>().ok())
+        == Some(old_default);
+    if on_old_default && !bindings.contains_key("global_search") {
+        bindings.insert(
+            "format".to_string(),
+            Value::String(Action::Format.default_combo().to_string()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Table {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_v1_file_gets_missing_defaults_and_a_version() {
+        let mut table = parse(
+            r#"
+theme = "light"
+recent_files = ["/tmp/a.txt"]
+
+[ai_panel]
+provider = "ollama"
+"#,
+        );
+
+        assert_eq!(migrate(&mut table), MigrationOutcome::Migrated { from: 1 });
+
+        assert_eq!(table["version"].as_integer(), Some(CURRENT_VERSION.into()));
+        assert_eq!(table["font_size"].as_float(), Some(14.0));
+        assert_eq!(table["autosave_interval"].as_integer(), Some(300));
+        // Everything else is carried over as is
+        assert_eq!(table["recent_files"].as_array().unwrap().len(), 1);
+        assert_eq!(table["ai_panel"]["provider"].as_str(), Some("ollama"));
+    }
+
+    #[test]
+    fn test_v1_migration_keeps_values_the_user_chose() {
+        let mut table = parse("font_size = 18.0\nautosave_interval = 0");
+        migrate(&mut table);
+        assert_eq!(table["font_size"].as_float(), Some(18.0));
+        assert_eq!(table["autosave_interval"].as_integer(), Some(0));
+    }
+
+    #[test]
+    fn test_v2_moves_format_off_the_global_search_shortcut() {
+        let mut table = parse(
+            r#"
+version = 2
+
+[keybindings]
+save = "Cmd+S"
+format = "ctrl+shift+f"
+"#,
+        );
+        assert_eq!(migrate(&mut table), MigrationOutcome::Migrated { from: 2 });
+        assert_eq!(table["keybindings"]["format"].as_str(), Some("Cmd+Shift+I"));
+        assert_eq!(table["keybindings"]["save"].as_str(), Some("Cmd+S"));
+
+        // A shortcut the user chose stays
+        let mut table = parse("version = 2\n[keybindings]\nformat = \"Alt+F\"");
+        migrate(&mut table);
+        assert_eq!(table["keybindings"]["format"].as_str(), Some("Alt+F"));
+    }
+
+    #[test]
+    fn test_current_and_newer_files_are_left_alone() {
+        let text = &format!("version = {}\nfont_size = 12.0", CURRENT_VERSION);
+        let mut table = parse(text);
+        assert_eq!(migrate(&mut table), MigrationOutcome::Current);
+        assert_eq!(table, parse(text));
+
+        let text = "version = 7\nshiny_new_option = true";
+        let mut table = parse(text);
+        assert_eq!(migrate(&mut table), MigrationOutcome::Newer(7));
+        assert_eq!(table, parse(text));
+    }
+}
@@ -0,0 +1,201 @@
+//! Background persistence of [`Settings`].
+//!
+//! Changing a setting only hands a snapshot to a single writer thread. The
+//! writer waits [`WRITE_INTERVAL`] after the first change so a burst of
+//! changes ends up as one write, and [`ConfigWriter::flush`] writes whatever
+//! is still pending right away, e.g. on exit.
+
+use crate::config::{Config, ConfigError, FileStamp, Settings};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// Shortest time between two writes of the settings file
+pub const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where the settings end up
+pub trait SettingsStore: Send + 'static {
+    fn store(&mut self, settings: &Settings) -> Result<(), ConfigError>;
+}
+
+/// The settings file managed by confy
+pub struct ConfyStore {
+    /// Told about each write, so it is not taken for an outside edit
+    pub stamp: FileStamp,
+}
+
+impl SettingsStore for ConfyStore {
+    fn store(&mut self, settings: &Settings) -> Result<(), ConfigError> {
+        let path = Config::config_path()?;
+        self.stamp
+            .record_write(&path, || confy::store_path(&path, settings))?;
+        tracing::debug!("Settings written");
+        Ok(())
+    }
+}
+
+enum WriteRequest {
+    Changed(Box<Settings>),
+    /// Write pending changes now and answer once done
+    Flush(Sender<()>),
+}
+
+pub struct ConfigWriter {
+    sender: Sender<WriteRequest>,
+}
+
+impl ConfigWriter {
+    /// Start the writer thread; `on_error` is called from it for every failed write.
+    pub fn spawn(
+        store: impl SettingsStore,
+        interval: Duration,
+        on_error: impl Fn(ConfigError) + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("config-writer".to_string())
+            .spawn(move || run(receiver, store, interval, on_error))
+            .expect("failed to spawn config writer thread");
+        Self { sender }
+    }
+
+    /// Queue `settings` to be written; replaces anything not yet written.
+    pub fn changed(&self, settings: Settings) {
+        let _ = self.sender.send(WriteRequest::Changed(Box::new(settings)));
+    }
+
+    /// Write pending changes and wait until they are on disk.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(WriteRequest::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+fn run(
+    receiver: Receiver<WriteRequest>,
+    mut store: impl SettingsStore,
+    interval: Duration,
+    on_error: impl Fn(ConfigError),
+) {
+    let mut pending: Option<(Box<Settings>, Instant)> = None;
+    let mut write = |settings: &Settings| {
+        if let Err(e) = store.store(settings) {
+            tracing::error!("Failed to save settings: {}", e);
+            on_error(e);
+        }
+    };
+
+    loop {
+        let request = match &pending {
+            None => match receiver.recv() {
+                Ok(request) => request,
+                Err(_) => return,
+            },
+            Some((settings, deadline)) => {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(request) => request,
+                    Err(RecvTimeoutError::Timeout) => {
+                        write(settings);
+                        pending = None;
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        write(settings);
+                        return;
+                    }
+                }
+            }
+        };
+
+        match request {
+            WriteRequest::Changed(settings) => {
+                let deadline = pending
+                    .take()
+                    .map_or_else(|| Instant::now() + interval, |(_, deadline)| deadline);
+                pending = Some((settings, deadline));
+            }
+            WriteRequest::Flush(done) => {
+                if let Some((settings, _)) = pending.take() {
+                    write(&settings);
+                }
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Remembers the font size of every write
+    #[derive(Clone, Default)]
+    struct RecordingStore {
+        writes: Arc<Mutex<Vec<f32>>>,
+        fail: bool,
+    }
+
+    impl SettingsStore for RecordingStore {
+        fn store(&mut self, settings: &Settings) -> Result<(), ConfigError> {
+            self.writes.lock().unwrap().push(settings.font_size);
+            if self.fail {
+                return Err(std::io::Error::other("disk full").into());
+            }
+            Ok(())
+        }
+    }
+
+    fn settings_with_font_size(font_size: f32) -> Settings {
+        Settings {
+            font_size,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn rapid_changes_are_written_once() {
+        let store = RecordingStore::default();
+        let writer = ConfigWriter::spawn(store.clone(), Duration::from_millis(100), |_| {});
+
+        for size in 10..30 {
+            writer.changed(settings_with_font_size(size as f32));
+        }
+        std::thread::sleep(Duration::from_millis(400));
+
+        assert_eq!(*store.writes.lock().unwrap(), [29.0]);
+    }
+
+    #[test]
+    fn flush_writes_pending_changes_immediately() {
+        let store = RecordingStore::default();
+        let writer = ConfigWriter::spawn(store.clone(), Duration::from_secs(60), |_| {});
+
+        writer.flush();
+        assert!(store.writes.lock().unwrap().is_empty());
+
+        writer.changed(settings_with_font_size(12.0));
+        writer.changed(settings_with_font_size(16.0));
+        writer.flush();
+        assert_eq!(*store.writes.lock().unwrap(), [16.0]);
+    }
+
+    #[test]
+    fn failed_writes_are_reported() {
+        let store = RecordingStore {
+            fail: true,
+            ..Default::default()
+        };
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&errors);
+        let writer = ConfigWriter::spawn(store, Duration::from_secs(60), move |e| {
+            reported.lock().unwrap().push(e.to_string())
+        });
+
+        writer.changed(Settings::default());
+        writer.flush();
+
+        assert_eq!(*errors.lock().unwrap(), ["I/O error: disk full"]);
+    }
+}
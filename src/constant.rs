@@ -1,12 +1,27 @@
 // Window size constants
 pub const DEFAULT_WINDOW_WIDTH: f32 = 750.0;
 pub const DEFAULT_WINDOW_HEIGHT: f32 = 468.0;
+pub const MIN_WINDOW_WIDTH: f32 = 300.0;
 pub const DEFAULT_WINDOW_TITLE: &str = "纸壳";
+/// How far a window opened with 新窗口 is moved from the one that opened it
+pub const NEW_WINDOW_OFFSET: f32 = 28.0;
+/// Tells a window opened with 新窗口 where the window that opened it is, as "x,y"
+pub const NEW_WINDOW_POSITION_ENV: &str = "PAPER_SHELL_OPENER_POSITION";
 
 /// Application name and metadata constants
 pub const APP_QUALIFIER: &str = "com";
 pub const APP_ORGANIZATION: &str = "RetricSu";
 pub const APP_NAME: &str = "Paper Shell";
 
+/// Portable mode: a file with this name next to the executable, or the
+/// argument, keeps all data in PORTABLE_DATA_DIR beside the executable
+pub const PORTABLE_FLAG_FILE: &str = "portable.flag";
+pub const PORTABLE_ARG: &str = "--portable";
+pub const PORTABLE_DATA_DIR: &str = "paper-shell-data";
+
+/// Extensions of the documents the app offers to open, as in
+/// InfoAdditions.plist for macOS
+pub const DOCUMENT_EXTENSIONS: &[&str] = &["txt", "text", "md", "markdown", "mdown"];
+
 /// App related Magic Numbers
 pub const MAX_RECENT_FILES: usize = 10;
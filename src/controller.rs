@@ -0,0 +1,784 @@
+//! The document lifecycle without a window: opening, saving, auto-saving
+//! and loading history, and the title bar actions that only change state.
+//!
+//! [`PaperShellApp`](crate::app::PaperShellApp) hands the controller the
+//! active document, the replies of the work it started and the time, and
+//! shows what comes back as an [`Outcome`]. Files, history and marks are
+//! reached through a [`DocumentStore`] and background work is started
+//! through a [`Spawn`] function, so tests can run whole flows against an
+//! in-memory store without threads.
+
+use crate::backend::editor_backend::{EditorBackend, HistoryEntry};
+use crate::backend::file_settings::FileSettings;
+use crate::backend::sidebar_backend::{Mark, SidebarBackend};
+use crate::config::Settings;
+use crate::error::{AppError, read_document, write_document};
+use crate::file::{FileData, Loaded};
+use crate::messages::ResponseMessage;
+use crate::tabs::TabId;
+use crate::ui::editor::{Editor, content_hash};
+use crate::ui::title_bar::TitleBarAction;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// Where documents, their history and their marks are kept
+pub trait DocumentStore: Send + Sync {
+    fn read(&self, path: &Path) -> Result<String, AppError>;
+    fn write(&self, path: &Path, content: &str) -> Result<(), AppError>;
+    /// The uuid of the file and the seconds spent writing it so far
+    fn metadata(&self, path: &Path, content: &str) -> Result<(String, u64), AppError>;
+    /// Add `content` to the history of the file; returns its uuid and the
+    /// seconds spent writing it, `time_spent` included
+    fn record_version(
+        &self,
+        path: &Path,
+        content: &str,
+        time_spent: u64,
+    ) -> Result<(String, u64), AppError>;
+    fn load_marks(&self, uuid: &str) -> Result<Loaded<HashMap<usize, Mark>>, AppError>;
+    fn load_history(&self, path: &Path) -> Result<Vec<HistoryEntry>, AppError>;
+}
+
+/// The documents on disk, with history and marks in the data dir
+pub struct DiskStore {
+    editor_backend: Arc<EditorBackend>,
+    sidebar_backend: Arc<SidebarBackend>,
+}
+
+impl DiskStore {
+    pub fn new(editor_backend: Arc<EditorBackend>, sidebar_backend: Arc<SidebarBackend>) -> Self {
+        Self {
+            editor_backend,
+            sidebar_backend,
+        }
+    }
+}
+
+impl DocumentStore for DiskStore {
+    fn read(&self, path: &Path) -> Result<String, AppError> {
+        read_document(path)
+    }
+
+    fn write(&self, path: &Path, content: &str) -> Result<(), AppError> {
+        write_document(path, content)
+    }
+
+    fn metadata(&self, path: &Path, content: &str) -> Result<(String, u64), AppError> {
+        Ok(self.editor_backend.get_file_metadata(path, content)?)
+    }
+
+    fn record_version(
+        &self,
+        path: &Path,
+        content: &str,
+        time_spent: u64,
+    ) -> Result<(String, u64), AppError> {
+        Ok(self.editor_backend.save(path, content, time_spent)?)
+    }
+
+    fn load_marks(&self, uuid: &str) -> Result<Loaded<HashMap<usize, Mark>>, AppError> {
+        Ok(self.sidebar_backend.load_marks(uuid)?)
+    }
+
+    fn load_history(&self, path: &Path) -> Result<Vec<HistoryEntry>, AppError> {
+        Ok(self.editor_backend.load_history(path)?)
+    }
+}
+
+/// Starts background work: on a thread of its own in the app, right away
+/// in tests
+pub type Spawn = Arc<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+/// Run each piece of background work on a new thread
+pub fn spawn_thread() -> Spawn {
+    Arc::new(|work| {
+        std::thread::spawn(work);
+    })
+}
+
+/// What saving has done to one document so far
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SaveState {
+    /// Hash of the content when the file was opened or last auto-saved
+    pub autosaved_content_hash: u64,
+    /// Hash of the content being saved in the background
+    pub saving_content_hash: Option<u64>,
+}
+
+/// What a step of the lifecycle means for the rest of the app
+#[derive(Debug)]
+pub enum Outcome {
+    /// The document is now the file at `path`. `uuid` is `None` when only
+    /// the path changed, as after Save As.
+    Opened {
+        path: PathBuf,
+        uuid: Option<String>,
+    },
+    /// The document was saved as the file with `uuid`; `uuid_changed` when
+    /// it was another file's before
+    Saved {
+        uuid: String,
+        uuid_changed: bool,
+    },
+    /// Marks of the document arrived and are shown; `set_aside` if its
+    /// marks file was unreadable and moved there
+    MarksApplied {
+        set_aside: Option<PathBuf>,
+    },
+    History(Vec<HistoryEntry>),
+    Failed {
+        what: &'static str,
+        error: AppError,
+    },
+}
+
+/// What is left to do after [`AppController::handle_title_action`]
+#[derive(Debug, PartialEq)]
+pub enum TitleEffect {
+    Done,
+    /// The settings changed and need saving
+    SettingsChanged,
+    /// The font changed and needs applying to the editor; `file` when it is
+    /// the open file's own and is saved with its file settings
+    FontChanged {
+        file: bool,
+    },
+    /// An action for the app: it opens a window, a dialog or a document
+    Unhandled(TitleBarAction),
+}
+
+#[derive(Clone)]
+pub struct AppController {
+    store: Arc<dyn DocumentStore>,
+    sender: Sender<ResponseMessage>,
+    spawn: Spawn,
+    last_autosave: Instant,
+}
+
+impl AppController {
+    pub fn new(
+        store: Arc<dyn DocumentStore>,
+        sender: Sender<ResponseMessage>,
+        spawn: Spawn,
+    ) -> Self {
+        Self {
+            store,
+            sender,
+            spawn,
+            last_autosave: Instant::now(),
+        }
+    }
+
+    /// Keep documents in `store` from now on, e.g. after the data dir moved
+    pub fn set_store(&mut self, store: Arc<dyn DocumentStore>) {
+        self.store = store;
+    }
+
+    /// Read the file at `path` with its metadata and marks right away
+    pub fn load(&self, path: &Path) -> Result<(FileData, Loaded<HashMap<usize, Mark>>), AppError> {
+        let content = self.store.read(path)?;
+        let (uuid, total_time) = self.store.metadata(path, &content)?;
+        let marks = self.store.load_marks(&uuid)?;
+        let data = FileData {
+            uuid,
+            path: path.to_path_buf(),
+            total_time,
+            content,
+        };
+        Ok((data, marks))
+    }
+
+    /// Read the file at `path` for tab `tab` in the background; the
+    /// document and then its marks come back as replies
+    pub fn open_file(&self, tab: TabId, path: PathBuf) {
+        let store = Arc::clone(&self.store);
+        let sender = self.sender.clone();
+        (self.spawn)(Box::new(move || {
+            let loaded = store.read(&path).and_then(|content| {
+                let (uuid, total_time) = store.metadata(&path, &content)?;
+                Ok(FileData {
+                    uuid,
+                    path,
+                    total_time,
+                    content,
+                })
+            });
+            let uuid = loaded.as_ref().ok().map(|data| data.uuid.clone());
+            let _ = sender.send(ResponseMessage::FileLoaded {
+                tab,
+                result: loaded,
+            });
+            if let Some(uuid) = uuid {
+                let result = store.load_marks(&uuid);
+                let _ = sender.send(ResponseMessage::MarksLoaded { tab, result });
+            }
+        }));
+    }
+
+    /// Write `content` to the file at `path` and add it to the history
+    pub fn write_and_record(
+        &self,
+        path: &Path,
+        content: &str,
+        time_spent: u64,
+    ) -> Result<(String, u64), AppError> {
+        self.store.write(path, content)?;
+        self.store.record_version(path, content, time_spent)
+    }
+
+    /// Save `content` of the document in tab `tab` to its file `path` in
+    /// the background
+    pub fn save_file(
+        &self,
+        tab: TabId,
+        saves: &mut SaveState,
+        path: PathBuf,
+        content: String,
+        time_spent: u64,
+    ) {
+        saves.saving_content_hash = Some(content_hash(&content));
+        let controller = self.clone();
+        (self.spawn)(Box::new(move || {
+            let result = controller.write_and_record(&path, &content, time_spent);
+            let _ = controller
+                .sender
+                .send(ResponseMessage::FileSaved { tab, result });
+        }));
+    }
+
+    /// Save `content` of the untitled document in tab `tab` as `path`,
+    /// where the user chose in the save dialog. Runs on the caller's thread,
+    /// which is the dialog's.
+    pub fn save_as(&self, tab: TabId, path: PathBuf, content: &str, time_spent: u64) {
+        let result = self.write_and_record(&path, content, time_spent);
+        if result.is_ok() {
+            // The document takes the new path before the save is reported
+            let _ = self.sender.send(ResponseMessage::FileRenamed { tab, path });
+        }
+        let _ = self.sender.send(ResponseMessage::FileSaved { tab, result });
+    }
+
+    /// Load the history of the file at `path` in the background
+    pub fn load_history(&self, path: PathBuf) {
+        let store = Arc::clone(&self.store);
+        let sender = self.sender.clone();
+        (self.spawn)(Box::new(move || {
+            let result = store.load_history(&path);
+            let _ = sender.send(ResponseMessage::HistoryLoaded(result));
+        }));
+    }
+
+    /// Take in a reply to the work above for the document in `editor`.
+    /// Other replies are not the controller's and give `None`.
+    pub fn handle_message(
+        &self,
+        editor: &mut Editor,
+        saves: &mut SaveState,
+        message: ResponseMessage,
+    ) -> Option<Outcome> {
+        let outcome = match message {
+            ResponseMessage::FileLoaded { result, .. } => match result {
+                Ok(data) => apply_loaded(editor, saves, data, None),
+                Err(error) => Outcome::Failed {
+                    what: "打开文件失败",
+                    error,
+                },
+            },
+            ResponseMessage::FileRenamed { path, .. } => apply_renamed(editor, saves, path),
+            ResponseMessage::FileSaved { result, .. } => match result {
+                Ok((uuid, total_time)) => {
+                    if let Some(hash) = saves.saving_content_hash.take() {
+                        editor.mark_saved(hash);
+                    }
+                    apply_saved(editor, uuid, total_time)
+                }
+                Err(error) => {
+                    saves.saving_content_hash = None;
+                    Outcome::Failed {
+                        what: "保存失败",
+                        error,
+                    }
+                }
+            },
+            ResponseMessage::MarksLoaded { result, .. } => match result {
+                Ok(marks) => {
+                    editor.apply_marks(marks.value);
+                    Outcome::MarksApplied {
+                        set_aside: marks.set_aside,
+                    }
+                }
+                Err(error) => Outcome::Failed {
+                    what: "读取标记失败",
+                    error,
+                },
+            },
+            ResponseMessage::HistoryLoaded(result) => match result {
+                Ok(entries) => Outcome::History(entries),
+                Err(error) => Outcome::Failed {
+                    what: "读取历史版本失败",
+                    error,
+                },
+            },
+            _ => return None,
+        };
+        Some(outcome)
+    }
+
+    /// Whether the auto-save interval of `interval_secs` (0 = off) has
+    /// passed by `now`; starts the next interval if so
+    pub fn tick(&mut self, now: Instant, interval_secs: u64) -> bool {
+        if interval_secs == 0
+            || now.saturating_duration_since(self.last_autosave)
+                < Duration::from_secs(interval_secs)
+        {
+            return false;
+        }
+        self.last_autosave = now;
+        true
+    }
+
+    /// Carry out a title bar action on the document in `editor`, its
+    /// `file_settings` and the app's `settings` as far as it only changes
+    /// them
+    pub fn handle_title_action(
+        &self,
+        editor: &mut Editor,
+        file_settings: &mut FileSettings,
+        settings: &mut Settings,
+        action: TitleBarAction,
+    ) -> TitleEffect {
+        match action {
+            TitleBarAction::Format => editor.format(),
+            TitleBarAction::SearchReplace => editor.open_search_replace(),
+            TitleBarAction::RemoveRecentFile(path) => {
+                settings.remove_recent_file(&path);
+                return TitleEffect::SettingsChanged;
+            }
+            TitleBarAction::TogglePinnedFile(path) => {
+                settings.toggle_pinned_file(&path);
+                return TitleEffect::SettingsChanged;
+            }
+            TitleBarAction::ClearRecentFiles => {
+                settings.recent_files.clear();
+                return TitleEffect::SettingsChanged;
+            }
+            TitleBarAction::FontChange(font_name) => {
+                // A file with its own font keeps the choice to itself
+                let file = file_settings.font_family.is_some();
+                if file {
+                    file_settings.font_family = Some(font_name);
+                } else {
+                    settings.font_family = Some(font_name);
+                    settings.custom_font_path = None;
+                }
+                return TitleEffect::FontChanged { file };
+            }
+            TitleBarAction::FontWeightChange(weight) => {
+                settings.font_weight = weight;
+                return TitleEffect::FontChanged { file: false };
+            }
+            TitleBarAction::LatinFontChange(font_name) => {
+                settings.latin_font_family = font_name;
+                return TitleEffect::FontChanged { file: false };
+            }
+            TitleBarAction::RunPlugin(id) if id == "print" => {
+                return TitleEffect::Unhandled(TitleBarAction::Print);
+            }
+            action => return TitleEffect::Unhandled(action),
+        }
+        TitleEffect::Done
+    }
+}
+
+/// Make the document in `editor` the file in `data`, with `marks` if they
+/// were read along with it
+pub fn apply_loaded(
+    editor: &mut Editor,
+    saves: &mut SaveState,
+    data: FileData,
+    marks: Option<HashMap<usize, Mark>>,
+) -> Outcome {
+    editor.set_content(data.content);
+    editor.set_current_file(Some(data.path.clone()));
+    let uuid = (!data.uuid.is_empty()).then_some(data.uuid);
+    if let Some(uuid) = &uuid {
+        editor.set_uuid(uuid.clone());
+    }
+    if data.total_time > 0 {
+        editor.set_current_file_total_time(data.total_time);
+    }
+    let hash = content_hash(&editor.get_content());
+    saves.autosaved_content_hash = hash;
+    editor.mark_saved(hash);
+    if let Some(marks) = marks {
+        editor.apply_marks(marks);
+    }
+    Outcome::Opened {
+        path: data.path,
+        uuid,
+    }
+}
+
+/// Give the document in `editor` the path `path` it was saved as, keeping
+/// its content
+pub fn apply_renamed(editor: &mut Editor, saves: &mut SaveState, path: PathBuf) -> Outcome {
+    editor.set_current_file(Some(path.clone()));
+    saves.autosaved_content_hash = content_hash(&editor.get_content());
+    Outcome::Opened { path, uuid: None }
+}
+
+/// Note that the document in `editor` was saved as the file with `uuid`
+pub fn apply_saved(editor: &mut Editor, uuid: String, total_time: u64) -> Outcome {
+    let uuid_changed = editor.get_sidebar_uuid() != Some(&uuid);
+    editor.set_uuid(uuid.clone());
+    editor.set_current_file_total_time(total_time);
+    Outcome::Saved { uuid, uuid_changed }
+}
+
+/// Whether the document in `editor` should be auto-saved: it has a file
+/// and changed since it was last auto-saved. Notes the content as
+/// auto-saved if so.
+pub fn take_autosave(editor: &Editor, saves: &mut SaveState) -> bool {
+    if editor.get_current_file().is_none() {
+        return false;
+    }
+    let hash = content_hash(&editor.get_content());
+    if hash == saves.autosaved_content_hash {
+        return false;
+    }
+    saves.autosaved_content_hash = hash;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::sync::Mutex;
+    use std::sync::mpsc::{Receiver, channel};
+
+    /// Files, history and marks in memory
+    #[derive(Default)]
+    struct MemoryStore {
+        files: Mutex<HashMap<PathBuf, String>>,
+        history: Mutex<HashMap<PathBuf, Vec<HistoryEntry>>>,
+        marks: HashMap<usize, Mark>,
+    }
+
+    impl DocumentStore for MemoryStore {
+        fn read(&self, path: &Path) -> Result<String, AppError> {
+            self.files
+                .lock()
+                .unwrap()
+                .get(path)
+                .cloned()
+                .ok_or_else(|| AppError::Read {
+                    path: path.to_path_buf(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                })
+        }
+
+        fn write(&self, path: &Path, content: &str) -> Result<(), AppError> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf(), content.to_string());
+            Ok(())
+        }
+
+        fn metadata(&self, path: &Path, _content: &str) -> Result<(String, u64), AppError> {
+            let history = self.history.lock().unwrap();
+            let total = history
+                .get(path)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.time_spent)
+                .sum();
+            Ok((format!("uuid:{}", path.display()), total))
+        }
+
+        fn record_version(
+            &self,
+            path: &Path,
+            content: &str,
+            time_spent: u64,
+        ) -> Result<(String, u64), AppError> {
+            self.history
+                .lock()
+                .unwrap()
+                .entry(path.to_path_buf())
+                .or_default()
+                .push(HistoryEntry {
+                    hash: format!("{:x}", content_hash(content)),
+                    timestamp: Utc::now(),
+                    file_path: Some(path.to_path_buf()),
+                    time_spent: Some(time_spent),
+                });
+            self.metadata(path, content)
+        }
+
+        fn load_marks(&self, _uuid: &str) -> Result<Loaded<HashMap<usize, Mark>>, AppError> {
+            Ok(Loaded {
+                value: self.marks.clone(),
+                set_aside: None,
+            })
+        }
+
+        fn load_history(&self, path: &Path) -> Result<Vec<HistoryEntry>, AppError> {
+            Ok(self
+                .history
+                .lock()
+                .unwrap()
+                .get(path)
+                .cloned()
+                .unwrap_or_default())
+        }
+    }
+
+    const TAB: TabId = 0;
+
+    fn controller(store: MemoryStore) -> (AppController, Receiver<ResponseMessage>) {
+        let (sender, receiver) = channel();
+        let inline: Spawn = Arc::new(|work| work());
+        (
+            AppController::new(Arc::new(store), sender, inline),
+            receiver,
+        )
+    }
+
+    /// Hand every waiting reply to the controller, as the app does each frame
+    fn pump(
+        controller: &AppController,
+        receiver: &Receiver<ResponseMessage>,
+        editor: &mut Editor,
+        saves: &mut SaveState,
+    ) -> Vec<Outcome> {
+        receiver
+            .try_iter()
+            .filter_map(|message| controller.handle_message(editor, saves, message))
+            .collect()
+    }
+
+    #[test]
+    fn test_open_edit_save_and_load_history() {
+        let path = PathBuf::from("/notes/草稿.txt");
+        let store = MemoryStore::default();
+        store.write(&path, "第一稿").unwrap();
+        store.record_version(&path, "第一稿", 60).unwrap();
+        let (controller, receiver) = controller(store);
+        let mut editor = Editor::default();
+        let mut saves = SaveState::default();
+
+        controller.open_file(TAB, path.clone());
+        let outcomes = pump(&controller, &receiver, &mut editor, &mut saves);
+        assert!(matches!(
+            &outcomes[..],
+            [
+                Outcome::Opened { uuid: Some(_), .. },
+                Outcome::MarksApplied { set_aside: None }
+            ]
+        ));
+        assert_eq!(editor.get_content(), "第一稿");
+        assert_eq!(editor.get_current_file(), Some(&path));
+        assert_eq!(editor.get_current_file_total_time(), 60);
+        assert!(!editor.is_dirty());
+
+        editor.set_content("第二稿".to_string());
+        assert!(take_autosave(&editor, &mut saves));
+        assert!(!take_autosave(&editor, &mut saves));
+        controller.save_file(TAB, &mut saves, path.clone(), editor.get_content(), 30);
+        assert!(saves.saving_content_hash.is_some());
+        let outcomes = pump(&controller, &receiver, &mut editor, &mut saves);
+        assert!(matches!(
+            &outcomes[..],
+            [Outcome::Saved {
+                uuid_changed: false,
+                ..
+            }]
+        ));
+        assert!(!editor.is_dirty());
+        assert_eq!(saves.saving_content_hash, None);
+        assert_eq!(editor.get_current_file_total_time(), 90);
+
+        controller.load_history(path);
+        let outcomes = pump(&controller, &receiver, &mut editor, &mut saves);
+        let [Outcome::History(entries)] = &outcomes[..] else {
+            panic!("expected the history, got {:?}", outcomes);
+        };
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].time_spent, Some(30));
+    }
+
+    #[test]
+    fn test_save_as_gives_the_document_its_path() {
+        let (controller, receiver) = controller(MemoryStore::default());
+        let mut editor = Editor::default();
+        let mut saves = SaveState::default();
+        editor.set_content("新文档".to_string());
+        assert!(!take_autosave(&editor, &mut saves));
+
+        let path = PathBuf::from("/notes/新文档.txt");
+        saves.saving_content_hash = Some(content_hash("新文档"));
+        controller.save_as(TAB, path.clone(), "新文档", 5);
+        let outcomes = pump(&controller, &receiver, &mut editor, &mut saves);
+        assert!(matches!(
+            &outcomes[..],
+            [
+                Outcome::Opened { uuid: None, .. },
+                Outcome::Saved {
+                    uuid_changed: true,
+                    ..
+                }
+            ]
+        ));
+        assert_eq!(editor.get_current_file(), Some(&path));
+        assert_eq!(editor.get_content(), "新文档");
+        assert!(!editor.is_dirty());
+    }
+
+    #[test]
+    fn test_opening_an_empty_file_leaves_it_saved() {
+        let path = PathBuf::from("/notes/空白.txt");
+        let store = MemoryStore::default();
+        store.write(&path, "").unwrap();
+        let (controller, receiver) = controller(store);
+        let mut editor = Editor::default();
+        let mut saves = SaveState::default();
+        editor.set_content("上一篇".to_string());
+
+        controller.open_file(TAB, path.clone());
+        let outcomes = pump(&controller, &receiver, &mut editor, &mut saves);
+        assert!(matches!(&outcomes[0], Outcome::Opened { .. }));
+        assert_eq!(editor.get_content(), "");
+        assert_eq!(editor.get_current_file(), Some(&path));
+        assert!(!editor.is_dirty());
+    }
+
+    #[test]
+    fn test_failures_come_back_as_outcomes() {
+        let (controller, receiver) = controller(MemoryStore::default());
+        let mut editor = Editor::default();
+        let mut saves = SaveState::default();
+
+        controller.open_file(TAB, PathBuf::from("/notes/不存在.txt"));
+        let outcomes = pump(&controller, &receiver, &mut editor, &mut saves);
+        assert!(matches!(
+            &outcomes[..],
+            [Outcome::Failed {
+                what: "打开文件失败",
+                error: AppError::Read { .. }
+            }]
+        ));
+        assert_eq!(editor.get_current_file(), None);
+
+        // Replies that are not about the document lifecycle are left alone
+        let other = ResponseMessage::OpenFile(PathBuf::from("/notes/a.txt"));
+        assert!(
+            controller
+                .handle_message(&mut editor, &mut saves, other)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_font_changes_go_to_the_file_that_has_its_own_font() {
+        let (controller, _receiver) = controller(MemoryStore::default());
+        let mut editor = Editor::default();
+        let mut file_settings = FileSettings::default();
+        let mut settings = Settings {
+            custom_font_path: Some(PathBuf::from("/fonts/手写.ttf")),
+            ..Settings::default()
+        };
+        let mut act = |file_settings: &mut FileSettings, action| {
+            controller.handle_title_action(&mut editor, file_settings, &mut settings, action)
+        };
+
+        let effect = act(
+            &mut file_settings,
+            TitleBarAction::FontChange("宋体".to_string()),
+        );
+        assert_eq!(effect, TitleEffect::FontChanged { file: false });
+
+        let mut own_font = FileSettings {
+            font_family: Some("楷体".to_string()),
+            ..FileSettings::default()
+        };
+        let effect = act(
+            &mut own_font,
+            TitleBarAction::FontChange("黑体".to_string()),
+        );
+        assert_eq!(effect, TitleEffect::FontChanged { file: true });
+        assert_eq!(own_font.font_family.as_deref(), Some("黑体"));
+
+        assert_eq!(settings.font_family.as_deref(), Some("宋体"));
+        assert_eq!(settings.custom_font_path, None);
+    }
+
+    #[test]
+    fn test_title_actions_change_state_or_go_back_to_the_app() {
+        let (controller, _receiver) = controller(MemoryStore::default());
+        let mut editor = Editor::default();
+        let mut file_settings = FileSettings::default();
+        let mut settings = Settings::default();
+        let (a, b) = (PathBuf::from("/notes/a.txt"), PathBuf::from("/notes/b.txt"));
+        settings.recent_files = vec![a.clone(), b.clone()];
+        let mut act = |editor: &mut Editor, settings: &mut Settings, action| {
+            controller.handle_title_action(editor, &mut file_settings, settings, action)
+        };
+
+        let effect = act(
+            &mut editor,
+            &mut settings,
+            TitleBarAction::TogglePinnedFile(b.clone()),
+        );
+        assert_eq!(effect, TitleEffect::SettingsChanged);
+        assert_eq!(settings.pinned_files, std::slice::from_ref(&b));
+        act(
+            &mut editor,
+            &mut settings,
+            TitleBarAction::RemoveRecentFile(b),
+        );
+        assert!(settings.pinned_files.is_empty());
+        act(&mut editor, &mut settings, TitleBarAction::ClearRecentFiles);
+        assert!(settings.recent_files.is_empty());
+
+        editor.set_content("第一段\n第二段".to_string());
+        assert_eq!(
+            act(&mut editor, &mut settings, TitleBarAction::Format),
+            TitleEffect::Done
+        );
+        assert_ne!(editor.get_content(), "第一段\n第二段");
+
+        // The print plugin is the print preview
+        assert_eq!(
+            act(
+                &mut editor,
+                &mut settings,
+                TitleBarAction::RunPlugin("print".to_string())
+            ),
+            TitleEffect::Unhandled(TitleBarAction::Print)
+        );
+        assert_eq!(
+            act(
+                &mut editor,
+                &mut settings,
+                TitleBarAction::OpenFile(a.clone())
+            ),
+            TitleEffect::Unhandled(TitleBarAction::OpenFile(a))
+        );
+    }
+
+    #[test]
+    fn test_tick_fires_once_per_interval() {
+        let (mut controller, _receiver) = controller(MemoryStore::default());
+        let start = controller.last_autosave;
+        assert!(!controller.tick(start + Duration::from_secs(299), 300));
+        assert!(controller.tick(start + Duration::from_secs(300), 300));
+        assert!(!controller.tick(start + Duration::from_secs(301), 300));
+        // Turned off
+        assert!(!controller.tick(start + Duration::from_secs(3600), 0));
+    }
+}
@@ -0,0 +1,106 @@
+//! Keeping unsaved text when the app panics.
+//!
+//! The app copies every open document with unsaved changes into a
+//! [`BufferMirror`], at most once per [`MIRROR_INTERVAL`] so typing does not
+//! pay for it. The hook installed by [`install_panic_hook`] writes each of
+//! them to a file of its own in the recovery folder before the process goes
+//! down; the next start tells the user where they went.
+
+use crate::backend::recovery::RecoveryBackend;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// How often the mirror is brought up to date at most
+pub const MIRROR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The unsaved content of the open documents, shared with the panic hook
+#[derive(Clone)]
+pub struct BufferMirror {
+    contents: Arc<Mutex<Vec<String>>>,
+    /// Data dir whose recovery folder the content goes to
+    data_dir: Arc<Mutex<PathBuf>>,
+}
+
+impl BufferMirror {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            contents: Arc::default(),
+            data_dir: Arc::new(Mutex::new(data_dir)),
+        }
+    }
+
+    /// Keep the content in the recovery folder of `data_dir` from now on
+    pub fn set_data_dir(&self, data_dir: PathBuf) {
+        *self.data_dir.lock().unwrap_or_else(PoisonError::into_inner) = data_dir;
+    }
+
+    fn data_dir(&self) -> PathBuf {
+        self.data_dir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Note `contents`, one per document, as what would be lost; empty when
+    /// nothing would be
+    pub fn set(&self, contents: Vec<String>) {
+        *self.contents.lock().unwrap_or_else(PoisonError::into_inner) = contents;
+    }
+
+    /// The contents to keep; does not wait if the lock is held, since the
+    /// panic may have happened while holding it
+    fn take(&self) -> Vec<String> {
+        match self.contents.try_lock() {
+            Ok(mut contents) => std::mem::take(&mut *contents),
+            Err(std::sync::TryLockError::Poisoned(poisoned)) => {
+                std::mem::take(&mut *poisoned.into_inner())
+            }
+            Err(std::sync::TryLockError::WouldBlock) => Vec::new(),
+        }
+    }
+}
+
+/// Write what `mirror` holds to the recovery folder on a panic, then carry
+/// on with the hook that was installed before
+pub fn install_panic_hook(mirror: BufferMirror) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let contents = mirror.take();
+        if !contents.is_empty() {
+            match RecoveryBackend::with_data_dir(mirror.data_dir()) {
+                Ok(backend) => {
+                    for content in &contents {
+                        match backend.store_panic(content) {
+                            Ok(path) => eprintln!("Unsaved text kept in {}", path.display()),
+                            Err(e) => eprintln!("Failed to keep unsaved text: {}", e),
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Failed to keep unsaved text: {}", e),
+            }
+        }
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_is_kept_once() {
+        let mirror = BufferMirror::new(std::env::temp_dir());
+        assert!(mirror.take().is_empty());
+
+        mirror.set(vec!["写到一半".to_string(), "另一篇".to_string()]);
+        let shared = mirror.clone();
+        assert_eq!(shared.take(), ["写到一半", "另一篇"]);
+        assert!(mirror.take().is_empty());
+
+        // A lock held by the panicking code does not block the hook
+        mirror.set(vec!["写到一半".to_string()]);
+        let _held = mirror.contents.lock().unwrap();
+        assert!(shared.take().is_empty());
+    }
+}
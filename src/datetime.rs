@@ -0,0 +1,95 @@
+//! Rendering timestamps for display.
+//!
+//! Timestamps are stored in UTC but always shown in the local time zone,
+//! using the strftime-style format from `Settings::datetime_format`.
+//! Text written into documents, such as the new file template, uses
+//! [`expand_placeholders`] instead.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+pub const DEFAULT_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Placeholders [`expand_placeholders`] fills in, with their chrono formats
+pub const PLACEHOLDERS: [(&str, &str); 2] = [("{date}", "%Y-%m-%d"), ("{time}", "%H:%M")];
+
+/// Check that `format` is a usable chrono format string.
+pub fn validate_format(format: &str) -> Result<(), String> {
+    if format.trim().is_empty() {
+        return Err("格式不能为空".to_string());
+    }
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("无法识别的格式：{}", format));
+    }
+    Ok(())
+}
+
+/// `time` in local time, laid out by `format`; an invalid format falls back
+/// to [`DEFAULT_DATETIME_FORMAT`] instead of failing.
+pub fn format_local<Tz: TimeZone>(time: &DateTime<Tz>, format: &str) -> String {
+    let format = if validate_format(format).is_ok() {
+        format
+    } else {
+        DEFAULT_DATETIME_FORMAT
+    };
+    time.with_timezone(&Local).format(format).to_string()
+}
+
+/// `template` with each of the [`PLACEHOLDERS`] replaced by `now`, a local
+/// time; anything else in braces is left as it is
+pub fn expand_placeholders(template: &str, now: NaiveDateTime) -> String {
+    PLACEHOLDERS
+        .iter()
+        .fold(template.to_string(), |text, (placeholder, format)| {
+            text.replace(placeholder, &now.format(format).to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_validates_format_strings() {
+        assert!(validate_format(DEFAULT_DATETIME_FORMAT).is_ok());
+        assert!(validate_format("%Y年%m月%d日 %H:%M").is_ok());
+        assert!(validate_format("%Q").is_err());
+        assert!(validate_format("%").is_err());
+        assert!(validate_format("  ").is_err());
+    }
+
+    #[test]
+    fn test_formats_in_local_time_whatever_the_source_zone() {
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let time = tokyo.with_ymd_and_hms(2025, 3, 4, 8, 30, 0).unwrap();
+        let utc = time.with_timezone(&Utc);
+
+        assert_eq!(
+            format_local(&time, "%Y-%m-%d %H:%M"),
+            format_local(&utc, "%Y-%m-%d %H:%M")
+        );
+        assert_eq!(
+            format_local(&utc, "%H:%M"),
+            utc.with_timezone(&Local).format("%H:%M").to_string()
+        );
+        // A broken format does not panic
+        assert_eq!(
+            format_local(&utc, "%Q"),
+            format_local(&utc, DEFAULT_DATETIME_FORMAT)
+        );
+    }
+
+    #[test]
+    fn test_placeholders_are_filled_in() {
+        let now = chrono::NaiveDate::from_ymd_opt(2026, 10, 16)
+            .unwrap()
+            .and_hms_opt(7, 5, 0)
+            .unwrap();
+        assert_eq!(
+            expand_placeholders("# {date} 日记\n{time} 起床，{date}。{weather}\n", now),
+            "# 2026-10-16 日记\n07:05 起床，2026-10-16。{weather}\n"
+        );
+        assert_eq!(expand_placeholders("", now), "");
+    }
+}
@@ -0,0 +1,261 @@
+//! `papershell://` links that open a document, and optionally one of its
+//! saved versions in the history window, e.g. from a task manager:
+//!
+//! `papershell://open?path=%2FUsers%2Fme%2F%E8%8D%89%E7%A8%BF.txt&hash=9f3c2a1b`
+//!
+//! The system hands links over like files, see [`crate::open_with`]: as an
+//! Apple event on macOS, as an argument on Windows and Linux. Any program
+//! can make such a link, so only absolute paths of the document types in
+//! [`DOCUMENT_EXTENSIONS`] are opened.
+
+use crate::constant::DOCUMENT_EXTENSIONS;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+pub const SCHEME: &str = "papershell";
+const OPEN_ACTION: &str = "open";
+/// Shortest start of a version hash taken, as the history lists 16 digits
+const MIN_HASH_LEN: usize = 4;
+const MAX_HASH_LEN: usize = 16;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DeepLinkError {
+    #[error("不是纸壳链接：{0}")]
+    NotALink(String),
+
+    #[error("链接不支持“{0}”")]
+    UnknownAction(String),
+
+    #[error("链接缺少 path 参数")]
+    MissingPath,
+
+    #[error("链接的编码有误")]
+    BadEncoding,
+
+    #[error("链接只能打开完整路径：{}", .0.display())]
+    RelativePath(PathBuf),
+
+    #[error("链接不能打开这种文件：{}", .0.display())]
+    NotADocument(PathBuf),
+
+    #[error("链接中的版本号有误：{0}")]
+    InvalidHash(String),
+}
+
+/// What a link asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeepLink {
+    pub path: PathBuf,
+    /// The saved version to show, or the start of its hash
+    pub hash: Option<String>,
+}
+
+/// Whether `text` is a `papershell:` link rather than a file
+pub fn is_link(text: &str) -> bool {
+    strip_scheme(text, SCHEME).is_some()
+}
+
+/// Take `url` apart and check what it asks to open
+pub fn parse(url: &str) -> Result<DeepLink, DeepLinkError> {
+    let rest =
+        strip_scheme(url.trim(), SCHEME).ok_or_else(|| DeepLinkError::NotALink(url.to_string()))?;
+    let rest = rest.strip_prefix("//").unwrap_or(rest);
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (action, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let action = action.trim_end_matches('/');
+    if !action.eq_ignore_ascii_case(OPEN_ACTION) {
+        return Err(DeepLinkError::UnknownAction(action.to_string()));
+    }
+
+    let mut path = None;
+    let mut hash = None;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        // Other parameters are left to later versions of the app
+        match key {
+            "path" => path = Some(percent_decode(value, true)?),
+            "hash" => hash = Some(percent_decode(value, true)?),
+            _ => {}
+        }
+    }
+
+    let path = PathBuf::from(
+        path.filter(|path| !path.is_empty())
+            .ok_or(DeepLinkError::MissingPath)?,
+    );
+    check_path(&path)?;
+    let hash = hash
+        .filter(|hash| !hash.is_empty())
+        .map(check_hash)
+        .transpose()?;
+    Ok(DeepLink { path, hash })
+}
+
+/// The local path of a `file://` URL, as some Linux file managers pass
+/// files to the app
+pub fn file_url_path(text: &str) -> Option<PathBuf> {
+    let rest = strip_scheme(text, "file")?.strip_prefix("//")?;
+    // The host part is empty or `localhost` for local files
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+    let path = percent_decode(rest, false).ok()?;
+    // `/C:/Users/...` on Windows
+    let drive = path.as_bytes().get(1..3);
+    if cfg!(windows)
+        && drive.is_some_and(|drive| drive[0].is_ascii_alphabetic() && drive[1] == b':')
+    {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
+
+/// What follows `scheme:` in `text`, the scheme in any case
+fn strip_scheme<'a>(text: &'a str, scheme: &str) -> Option<&'a str> {
+    let (head, rest) = text.split_once(':')?;
+    head.eq_ignore_ascii_case(scheme).then_some(rest)
+}
+
+/// Decode `%XX` escapes, and with `plus_is_space` `+` as a space as in
+/// form data
+fn percent_decode(text: &str, plus_is_space: bool) -> Result<String, DeepLinkError> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'%' => {
+                let high = input.next().and_then(|digit| (digit as char).to_digit(16));
+                let low = input.next().and_then(|digit| (digit as char).to_digit(16));
+                match (high, low) {
+                    (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+                    _ => return Err(DeepLinkError::BadEncoding),
+                }
+            }
+            b'+' if plus_is_space => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| DeepLinkError::BadEncoding)
+}
+
+/// Only absolute paths of documents, without `..` to climb out of a folder
+fn check_path(path: &Path) -> Result<(), DeepLinkError> {
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(DeepLinkError::RelativePath(path.to_path_buf()));
+    }
+    let is_document = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            DOCUMENT_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        });
+    if !is_document {
+        return Err(DeepLinkError::NotADocument(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+fn check_hash(hash: String) -> Result<String, DeepLinkError> {
+    let valid = (MIN_HASH_LEN..=MAX_HASH_LEN).contains(&hash.len())
+        && hash.chars().all(|c| c.is_ascii_hexdigit());
+    if valid {
+        Ok(hash.to_ascii_lowercase())
+    } else {
+        Err(DeepLinkError::InvalidHash(hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `path` as a link parameter, every byte but letters and digits escaped
+    fn encode(path: &Path) -> String {
+        path.to_string_lossy()
+            .bytes()
+            .map(|byte| match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => (byte as char).to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_links_open_documents_and_versions() {
+        let draft = std::env::temp_dir().join("书稿").join("第一章 草稿.md");
+        let url = format!("papershell://open?path={}&hash=9F3C2A1B", encode(&draft));
+        assert!(is_link(&url));
+        assert_eq!(
+            parse(&url),
+            Ok(DeepLink {
+                path: draft.clone(),
+                hash: Some("9f3c2a1b".to_string()),
+            })
+        );
+
+        // Scheme in any case, a trailing slash, unknown parameters and a
+        // fragment are fine
+        let url = format!("PaperShell://open/?from=todo&path={}#top", encode(&draft));
+        assert_eq!(parse(&url).map(|link| link.hash), Ok(None));
+        assert!(!is_link(&draft.to_string_lossy()));
+    }
+
+    #[test]
+    fn test_links_are_checked() {
+        let draft = std::env::temp_dir().join("草稿.txt");
+        let open = |query: String| parse(&format!("papershell://open?{}", query));
+
+        assert_eq!(
+            parse("papershell://delete?path=x"),
+            Err(DeepLinkError::UnknownAction("delete".to_string()))
+        );
+        assert_eq!(
+            open("hash=9f3c".to_string()),
+            Err(DeepLinkError::MissingPath)
+        );
+        assert_eq!(
+            open("path=%E8%8D%89%2".to_string()),
+            Err(DeepLinkError::BadEncoding)
+        );
+        assert_eq!(
+            open("path=notes%2Fa.txt".to_string()),
+            Err(DeepLinkError::RelativePath(PathBuf::from("notes/a.txt")))
+        );
+        let climbing = std::env::temp_dir().join("..").join("a.txt");
+        assert_eq!(
+            open(format!("path={}", encode(&climbing))),
+            Err(DeepLinkError::RelativePath(climbing))
+        );
+        let secret = std::env::temp_dir().join("id_rsa");
+        assert_eq!(
+            open(format!("path={}", encode(&secret))),
+            Err(DeepLinkError::NotADocument(secret))
+        );
+        assert_eq!(
+            open(format!("path={}&hash=9f3", encode(&draft))),
+            Err(DeepLinkError::InvalidHash("9f3".to_string()))
+        );
+        assert_eq!(
+            open(format!("path={}&hash=zzzzzzzz", encode(&draft))),
+            Err(DeepLinkError::InvalidHash("zzzzzzzz".to_string()))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_urls_become_paths() {
+        assert_eq!(
+            file_url_path("file:///home/me/%E8%8D%89%E7%A8%BF.txt"),
+            Some(PathBuf::from("/home/me/草稿.txt"))
+        );
+        assert_eq!(
+            file_url_path("file://localhost/tmp/a+b.txt"),
+            Some(PathBuf::from("/tmp/a+b.txt"))
+        );
+        assert_eq!(file_url_path("file://server/share/a.txt"), None);
+        assert_eq!(file_url_path("/tmp/a.txt"), None);
+    }
+}
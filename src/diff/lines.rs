@@ -0,0 +1,722 @@
+use super::types::{DiffLine, DiffLineType, DiffRow};
+use similar::{Algorithm, ChangeTag, DiffTag, TextDiff, capture_diff_slices};
+use std::time::Duration;
+
+// Limits that keep pathological version pairs, like a full rewrite, from
+// freezing the window
+
+/// Most lines of either text compared in the history window
+pub const MAX_DIFF_LINES: usize = 5000;
+/// Longest line, in characters, highlighted character by character
+const MAX_CHAR_DIFF_LEN: usize = 1000;
+/// Least similarity of a removed and an added line for highlighting their
+/// differing characters to help
+const MIN_CHAR_DIFF_SIMILARITY: f32 = 0.3;
+/// How long a character diff may look for the smallest answer before it
+/// settles for a rougher one
+const CHAR_DIFF_TIMEOUT: Duration = Duration::from_millis(20);
+/// Least similarity, as `similar` rates it, for an added block to count as a
+/// removed block moved elsewhere
+const MOVE_SIMILARITY: f32 = 0.8;
+/// Shorter blocks are never taken as moved, short lines match by chance
+const MIN_MOVED_CHARS: usize = 10;
+/// Most pairs of added and removed blocks compared for moves; diffs with
+/// more hunks than this, like many scattered edits, are shown without moves
+const MAX_MOVE_COMPARISONS: usize = 10_000;
+
+/// The ideographic space, typed on purpose in Chinese prose
+const FULL_WIDTH_SPACE: char = '\u{3000}';
+
+/// How lines are compared in [`compute_diff_with`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Compare lines with runs of whitespace collapsed into one space and
+    /// none at either end; full-width spaces still count
+    pub ignore_whitespace: bool,
+    /// With `ignore_whitespace`, also drop full-width spaces at the start of
+    /// lines, the paragraph indent of Chinese prose
+    pub ignore_full_width_indent: bool,
+}
+
+/// Compute line-based diff between old and new text
+pub fn compute_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    compute_diff_with(old, new, DiffOptions::default())
+}
+
+/// Compute line-based diff between old and new text, comparing lines as
+/// `options` say. Lines keep their own text; unchanged lines show the one
+/// in `new`.
+///
+/// ```
+/// use paper_shell::diff::{DiffLineType, DiffOptions, compute_diff_with};
+///
+/// let options = DiffOptions {
+///     ignore_whitespace: true,
+///     ..Default::default()
+/// };
+/// let diff = compute_diff_with("第一段。\n", "  第一段。\n", options);
+/// assert_eq!(diff[0].line_type, DiffLineType::Unchanged);
+/// assert_eq!(diff[0].content, "  第一段。");
+/// ```
+pub fn compute_diff_with(old: &str, new: &str, options: DiffOptions) -> Vec<DiffLine> {
+    if !options.ignore_whitespace {
+        return exact_diff(old, new);
+    }
+
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let key = |line: &&str| comparison_key(line, options);
+    let old_keys: Vec<String> = old_lines.iter().map(key).collect();
+    let new_keys: Vec<String> = new_lines.iter().map(key).collect();
+
+    let line = |line_type: DiffLineType, content: &str| DiffLine {
+        line_type,
+        content: content.trim_end().to_string(),
+    };
+    let mut diff_lines = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, &old_keys, &new_keys) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            diff_lines.extend(
+                new_lines[new_range]
+                    .iter()
+                    .map(|content| line(DiffLineType::Unchanged, content)),
+            );
+            continue;
+        }
+        // Removed lines go before added ones, as in the exact diff
+        diff_lines.extend(
+            old_lines[old_range]
+                .iter()
+                .map(|content| line(DiffLineType::Removed, content)),
+        );
+        diff_lines.extend(
+            new_lines[new_range]
+                .iter()
+                .map(|content| line(DiffLineType::Added, content)),
+        );
+    }
+
+    diff_lines
+}
+
+/// [`compute_diff_with`] on the first [`MAX_DIFF_LINES`] lines of each text,
+/// and whether either text had more
+pub fn compute_diff_capped(old: &str, new: &str, options: DiffOptions) -> (Vec<DiffLine>, bool) {
+    let (old, old_cut) = first_lines(old, MAX_DIFF_LINES);
+    let (new, new_cut) = first_lines(new, MAX_DIFF_LINES);
+    (compute_diff_with(old, new, options), old_cut || new_cut)
+}
+
+/// The first `count` lines of `text`, and whether it had more
+pub fn first_lines(text: &str, count: usize) -> (&str, bool) {
+    if count == 0 {
+        return ("", !text.is_empty());
+    }
+    match text.match_indices('\n').nth(count - 1) {
+        Some((index, _)) if index + 1 < text.len() => (&text[..=index], true),
+        _ => (text, false),
+    }
+}
+
+/// Character diff that stops looking for the smallest answer after
+/// [`CHAR_DIFF_TIMEOUT`]
+pub fn char_diff<'a>(old: &'a str, new: &'a str) -> TextDiff<'a, 'a, 'a, str> {
+    TextDiff::configure()
+        .timeout(CHAR_DIFF_TIMEOUT)
+        .diff_chars(old, new)
+}
+
+/// The character changes between a removed and an added line, or `None`
+/// when the lines are too long or too unlike for highlighting them to help
+pub fn char_changes<'a>(old: &'a str, new: &'a str) -> Option<Vec<(ChangeTag, &'a str)>> {
+    if old.chars().count() > MAX_CHAR_DIFF_LEN || new.chars().count() > MAX_CHAR_DIFF_LEN {
+        return None;
+    }
+    let diff = char_diff(old, new);
+    if diff.ratio() < MIN_CHAR_DIFF_SIMILARITY {
+        return None;
+    }
+    Some(
+        diff.iter_all_changes()
+            .map(|change| (change.tag(), change.value()))
+            .collect(),
+    )
+}
+
+/// What lines are compared by when whitespace is ignored
+fn comparison_key(line: &str, options: DiffOptions) -> String {
+    let line = if options.ignore_full_width_indent {
+        line.trim_start_matches(char::is_whitespace)
+    } else {
+        line
+    };
+    line.split(|c: char| c.is_whitespace() && c != FULL_WIDTH_SPACE)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn exact_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut diff_lines = Vec::new();
+
+    for change in diff.iter_all_changes() {
+        let line_type = match change.tag() {
+            ChangeTag::Delete => DiffLineType::Removed,
+            ChangeTag::Insert => DiffLineType::Added,
+            ChangeTag::Equal => DiffLineType::Unchanged,
+        };
+
+        diff_lines.push(DiffLine {
+            line_type,
+            content: change.to_string().trim_end().to_string(),
+        });
+    }
+
+    diff_lines
+}
+
+/// Group raw diff lines into rows where unchanged identical lines are single rows,
+/// and contiguous removed/added blocks become paired rows.
+pub fn group_into_rows(diff_lines: &[DiffLine]) -> Vec<DiffRow> {
+    let mut rows = Vec::new();
+    let mut i = 0usize;
+
+    while i < diff_lines.len() {
+        match &diff_lines[i].line_type {
+            DiffLineType::Unchanged => {
+                // Collect contiguous unchanged lines and emit each as Unchanged row
+                rows.push(DiffRow::Unchanged(diff_lines[i].content.clone()));
+                i += 1;
+            }
+            DiffLineType::Removed => {
+                // collect removed block
+                let mut removed_block = Vec::new();
+                removed_block.push(diff_lines[i].clone());
+                i += 1;
+                while i < diff_lines.len() && diff_lines[i].line_type == DiffLineType::Removed {
+                    removed_block.push(diff_lines[i].clone());
+                    i += 1;
+                }
+
+                // collect following added block (if any)
+                let mut added_block = Vec::new();
+                let mut j = i;
+                while j < diff_lines.len() && diff_lines[j].line_type == DiffLineType::Added {
+                    added_block.push(diff_lines[j].clone());
+                    j += 1;
+                }
+
+                if !added_block.is_empty() {
+                    // pair removed and added blocks
+                    rows.push(DiffRow::Pair(removed_block, added_block));
+                    i = j;
+                } else {
+                    // no added block - show removed lines as left-only pairs
+                    rows.push(DiffRow::Pair(removed_block, Vec::new()));
+                }
+            }
+            DiffLineType::Added => {
+                // added without preceding removal -> right-only
+                rows.push(DiffRow::Pair(Vec::new(), vec![diff_lines[i].clone()]));
+                i += 1;
+            }
+        }
+    }
+
+    rows
+}
+
+/// Turn added blocks that closely match a block removed elsewhere, as when
+/// scenes are reordered, into `DiffRow::Moved` rows, and drop the removed
+/// block from its place.
+///
+/// Blocks are the sides of `DiffRow::Pair` rows from `group_into_rows`; each
+/// removed block goes to the added block most like it.
+pub fn detect_moves(rows: Vec<DiffRow>) -> Vec<DiffRow> {
+    struct Block {
+        row: usize,
+        text: String,
+        chars: usize,
+        paragraph: usize,
+    }
+
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    // Non-blank lines of the old text so far
+    let mut paragraph = 0usize;
+    for (row, diff_row) in rows.iter().enumerate() {
+        match diff_row {
+            DiffRow::Unchanged(text) => {
+                if !text.trim().is_empty() {
+                    paragraph += 1;
+                }
+            }
+            DiffRow::Pair(left, right) => {
+                if !left.is_empty() {
+                    let text = block_text(left);
+                    removed.push(Block {
+                        row,
+                        chars: text.chars().count(),
+                        text,
+                        paragraph: paragraph + 1,
+                    });
+                }
+                paragraph += left
+                    .iter()
+                    .filter(|line| !line.content.trim().is_empty())
+                    .count();
+                if !right.is_empty() {
+                    let text = block_text(right);
+                    added.push(Block {
+                        row,
+                        chars: text.chars().count(),
+                        text,
+                        paragraph: 0,
+                    });
+                }
+            }
+            DiffRow::Moved { .. } => {}
+        }
+    }
+
+    let long_enough = |block: &Block| block.chars >= MIN_MOVED_CHARS;
+    removed.retain(long_enough);
+    added.retain(long_enough);
+    if removed.len() * added.len() > MAX_MOVE_COMPARISONS {
+        return rows;
+    }
+    // Blocks of too different lengths cannot be alike enough, so they are
+    // not diffed at all
+    let sizes_allow = |a: &Block, b: &Block| {
+        2.0 * a.chars.min(b.chars) as f32 / (a.chars + b.chars) as f32 >= MOVE_SIMILARITY
+    };
+    let mut source = vec![false; rows.len()];
+    let mut target: Vec<Option<(usize, bool)>> = vec![None; rows.len()];
+    let mut used = vec![false; removed.len()];
+    for block in &added {
+        let best = removed
+            .iter()
+            .enumerate()
+            .filter(|(index, from)| {
+                !used[*index] && from.row != block.row && sizes_allow(from, block)
+            })
+            .map(|(index, from)| {
+                let ratio = char_diff(&from.text, &block.text).ratio();
+                (index, ratio)
+            })
+            .filter(|(_, ratio)| *ratio >= MOVE_SIMILARITY)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, _)) = best {
+            let from = &removed[index];
+            used[index] = true;
+            source[from.row] = true;
+            target[block.row] = Some((from.paragraph, from.text == block.text));
+        }
+    }
+
+    let mut moved_rows = Vec::with_capacity(rows.len());
+    for (row, diff_row) in rows.into_iter().enumerate() {
+        let DiffRow::Pair(left, right) = diff_row else {
+            moved_rows.push(diff_row);
+            continue;
+        };
+        let left = if source[row] { Vec::new() } else { left };
+        match target[row] {
+            Some((from_paragraph, exact)) => {
+                if !left.is_empty() {
+                    moved_rows.push(DiffRow::Pair(left, Vec::new()));
+                }
+                moved_rows.push(DiffRow::Moved {
+                    lines: right,
+                    from_paragraph,
+                    exact,
+                });
+            }
+            None if left.is_empty() && right.is_empty() => {}
+            None => moved_rows.push(DiffRow::Pair(left, right)),
+        }
+    }
+
+    moved_rows
+}
+
+/// The lines of a block as compared for moves, indentation aside
+fn block_text(lines: &[DiffLine]) -> String {
+    lines
+        .iter()
+        .map(|line| line.content.trim())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Check if diff lines contain meaningful changes (non-empty added or removed content),
+/// judging emptiness the way `options` compare lines
+pub fn has_meaningful_changes(diff_lines: &[DiffLine], options: DiffOptions) -> bool {
+    diff_lines.iter().any(|line| {
+        let is_empty = if options.ignore_whitespace {
+            comparison_key(&line.content, options).is_empty()
+        } else {
+            line.content.trim().is_empty()
+        };
+        matches!(line.line_type, DiffLineType::Added | DiffLineType::Removed) && !is_empty
+    })
+}
+
+/// Number of hunks (paired removed/added blocks) in a grouped diff
+pub fn hunk_count(rows: &[DiffRow]) -> usize {
+    rows.iter()
+        .filter(|row| matches!(row, DiffRow::Pair(_, _)))
+        .count()
+}
+
+/// Rebuild text from `original` applying only the accepted hunks.
+///
+/// `rows` must come from `group_into_rows(&compute_diff(original, _))`, and
+/// `accepted[i]` decides the i-th `DiffRow::Pair` (missing entries count as
+/// rejected). Unchanged and rejected lines are copied verbatim from `original`,
+/// so their whitespace and line endings survive; accepted lines come from the
+/// diff, which has trailing whitespace trimmed. The result ends with a newline
+/// exactly when `original` does.
+pub fn merge_accepted_hunks(original: &str, rows: &[DiffRow], accepted: &[bool]) -> String {
+    let mut lines = original.split_inclusive('\n');
+    let mut merged = String::with_capacity(original.len());
+    let mut hunk = 0usize;
+
+    for row in rows {
+        match row {
+            DiffRow::Unchanged(_) => {
+                if let Some(line) = lines.next() {
+                    merged.push_str(line);
+                }
+            }
+            DiffRow::Pair(removed, added) => {
+                let consumed: Vec<&str> = lines.by_ref().take(removed.len()).collect();
+                if accepted.get(hunk).copied().unwrap_or(false) {
+                    if !merged.is_empty() && !merged.ends_with('\n') {
+                        merged.push('\n');
+                    }
+                    for line in added {
+                        merged.push_str(&line.content);
+                        merged.push('\n');
+                    }
+                } else {
+                    for line in consumed {
+                        if !merged.is_empty() && !merged.ends_with('\n') {
+                            merged.push('\n');
+                        }
+                        merged.push_str(line);
+                    }
+                }
+                hunk += 1;
+            }
+            // Only `detect_moves` makes these, for display
+            DiffRow::Moved { .. } => {}
+        }
+    }
+
+    // Anything the rows did not account for is kept as-is
+    for line in lines {
+        merged.push_str(line);
+    }
+
+    if !original.ends_with('\n') && merged.ends_with('\n') {
+        merged.pop();
+        if merged.ends_with('\r') {
+            merged.pop();
+        }
+    } else if original.ends_with('\n') && !merged.is_empty() && !merged.ends_with('\n') {
+        merged.push('\n');
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grouping_unchanged_lines() {
+        let old = "a\nb\n";
+        let new = "a\nb\n";
+        let diff = compute_diff(old, new);
+        let rows = group_into_rows(&diff);
+        assert_eq!(rows.len(), 2);
+        match &rows[0] {
+            DiffRow::Unchanged(s) => assert_eq!(s, "a"),
+            _ => panic!(),
+        }
+        match &rows[1] {
+            DiffRow::Unchanged(s) => assert_eq!(s, "b"),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn grouping_removed_added_pair() {
+        let old = "a\nold\nc\n";
+        let new = "a\nnew\nc\n";
+        let diff = compute_diff(old, new);
+        let rows = group_into_rows(&diff);
+        // rows: a (unchanged), pair(old,new), c (unchanged)
+        assert_eq!(rows.len(), 3);
+        match &rows[1] {
+            DiffRow::Pair(l, r) => {
+                assert_eq!(l.len(), 1);
+                assert_eq!(r.len(), 1);
+            }
+            _ => panic!(),
+        }
+    }
+
+    fn merge(original: &str, suggestion: &str, accepted: &[bool]) -> String {
+        let rows = group_into_rows(&compute_diff(original, suggestion));
+        merge_accepted_hunks(original, &rows, accepted)
+    }
+
+    #[test]
+    fn merge_accepting_all_hunks_yields_suggestion() {
+        let original = "one\ntwo\nthree\nfour\n";
+        let suggestion = "one\n2\nthree\nfour\nfive\n";
+        let rows = group_into_rows(&compute_diff(original, suggestion));
+        assert_eq!(hunk_count(&rows), 2);
+        assert_eq!(
+            merge_accepted_hunks(original, &rows, &[true, true]),
+            suggestion
+        );
+    }
+
+    #[test]
+    fn merge_rejecting_all_hunks_yields_original() {
+        let original = "one  \ntwo\r\nthree\n";
+        let suggestion = "one\nTWO\nthree\nextra\n";
+        assert_eq!(merge(original, suggestion, &[false, false]), original);
+        assert_eq!(merge(original, suggestion, &[]), original);
+    }
+
+    #[test]
+    fn merge_applies_only_selected_hunks() {
+        let original = "a\nb\nc\nd\ne\n";
+        let suggestion = "A\nb\nc\nD\ne\n";
+        assert_eq!(
+            merge(original, suggestion, &[true, false]),
+            "A\nb\nc\nd\ne\n"
+        );
+        assert_eq!(
+            merge(original, suggestion, &[false, true]),
+            "a\nb\nc\nD\ne\n"
+        );
+    }
+
+    #[test]
+    fn merge_handles_pure_insertions_and_deletions() {
+        let original = "a\nb\nc\n";
+        let suggestion = "a\nnew\nc\n";
+        // b -> new is one hunk
+        assert_eq!(merge(original, suggestion, &[true]), suggestion);
+
+        let deleted = "a\nc\n";
+        assert_eq!(merge(original, deleted, &[true]), deleted);
+        assert_eq!(merge(original, deleted, &[false]), original);
+
+        let inserted = "start\na\nb\nc\n";
+        assert_eq!(merge(original, inserted, &[true]), inserted);
+        assert_eq!(merge(original, inserted, &[false]), original);
+    }
+
+    #[test]
+    fn merge_keeps_original_trailing_newline_state() {
+        // Last line changes in a text without a trailing newline
+        assert_eq!(merge("a\nb", "a\nc", &[true]), "a\nc");
+        assert_eq!(merge("a\nb", "a\nc", &[false]), "a\nb");
+        // Appending after an unterminated last line
+        assert_eq!(merge("a", "a\nb", &[true]), "a\nb");
+        assert_eq!(merge("a\n", "a\nb", &[true]), "a\nb\n");
+    }
+
+    #[test]
+    fn merge_handles_empty_inputs() {
+        assert_eq!(merge("", "new text", &[true]), "new text");
+        assert_eq!(merge("", "new text", &[false]), "");
+        assert_eq!(merge("old\n", "", &[true]), "");
+        assert_eq!(merge("old\n", "", &[false]), "old\n");
+    }
+
+    #[test]
+    fn merge_preserves_cjk_text() {
+        let original = "第一段。\n第二段有错别子。\n第三段。";
+        let suggestion = "第一段。\n第二段有错别字。\n第三段。";
+        assert_eq!(merge(original, suggestion, &[true]), suggestion);
+    }
+
+    fn changed_lines(diff: &[DiffLine]) -> usize {
+        diff.iter()
+            .filter(|line| line.line_type != DiffLineType::Unchanged)
+            .count()
+    }
+
+    #[test]
+    fn ignoring_whitespace_hides_reformatting() {
+        let plain = "第一段。\n\nSecond  paragraph.\t\n";
+        let formatted = "  第一段。\n\n  Second paragraph.\n";
+        let ignore = DiffOptions {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+
+        let exact = compute_diff(plain, formatted);
+        assert_eq!(changed_lines(&exact), 4);
+        assert!(has_meaningful_changes(&exact, DiffOptions::default()));
+
+        let diff = compute_diff_with(plain, formatted, ignore);
+        assert_eq!(changed_lines(&diff), 0);
+        assert!(!has_meaningful_changes(&diff, ignore));
+        // The lines still read as in the new text
+        assert_eq!(diff[0].content, "  第一段。");
+
+        // A real edit still shows, and only that line
+        let edited = "  第一段。\n\n  Second paragraph, edited.\n";
+        let diff = compute_diff_with(plain, edited, ignore);
+        assert_eq!(changed_lines(&diff), 2);
+        assert!(has_meaningful_changes(&diff, ignore));
+    }
+
+    fn moved_rows(old: &str, new: &str) -> Vec<DiffRow> {
+        detect_moves(group_into_rows(&compute_diff(old, new)))
+    }
+
+    #[test]
+    fn identical_moved_block_becomes_one_moved_row() {
+        let old = "第一场：清晨，他推门出去。\n\n第二场：中午，他在街角吃面。\n第三场：晚上，他独自回家。\n";
+        let new = "第二场：中午，他在街角吃面。\n第一场：清晨，他推门出去。\n\n第三场：晚上，他独自回家。\n";
+        let rows = moved_rows(old, new);
+
+        let moved: Vec<_> = rows
+            .iter()
+            .filter_map(|row| match row {
+                DiffRow::Moved {
+                    lines,
+                    from_paragraph,
+                    exact,
+                } => Some((lines[0].content.as_str(), *from_paragraph, *exact)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(moved.len(), 1);
+        let (_, from_paragraph, exact) = moved[0];
+        assert!(exact);
+        // Whichever scene the diff took as moved, it names its old place
+        let expected = old
+            .lines()
+            .filter(|line| !line.is_empty())
+            .position(|line| line == moved[0].0)
+            .unwrap()
+            + 1;
+        assert_eq!(from_paragraph, expected);
+        // Nothing is left as removed or added but the blank line
+        assert!(rows.iter().all(|row| match row {
+            DiffRow::Pair(left, right) =>
+                left.iter().chain(right).all(|line| line.content.is_empty()),
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn near_identical_moved_block_is_matched_but_marked_edited() {
+        let old = "第一场：清晨，他推门出去。\n第二场：中午，他在街角吃面。\n第三场：晚上，他独自回家。\n";
+        let new = "第二场：中午，他在街角吃面。\n第三场：晚上，他独自回家。\n第一场：清晨，他匆匆推门出去。\n";
+        let rows = moved_rows(old, new);
+        assert!(matches!(
+            rows.last(),
+            Some(DiffRow::Moved {
+                from_paragraph: 1,
+                exact: false,
+                ..
+            })
+        ));
+        assert_eq!(hunk_count(&rows), 0);
+    }
+
+    #[test]
+    fn edits_in_place_and_unrelated_blocks_are_not_moves() {
+        // Rewritten where it stands
+        let rows = moved_rows(
+            "第一场：清晨，他推门出去。\n不变的一行字在这里。\n",
+            "第一场：清晨，他匆匆推门出去。\n不变的一行字在这里。\n",
+        );
+        assert_eq!(hunk_count(&rows), 1);
+
+        // One block dropped and another written elsewhere
+        let rows = moved_rows(
+            "第一场：清晨，他推门出去。\n不变的一行字在这里。\n",
+            "不变的一行字在这里。\n第九场：深夜，雨下个不停。\n",
+        );
+        assert_eq!(hunk_count(&rows), 2);
+
+        // Too short to tell a move from chance
+        let rows = moved_rows("好。\n对。\n", "对。\n好。\n");
+        assert_eq!(hunk_count(&rows), 2);
+    }
+
+    #[test]
+    fn char_changes_fall_back_for_long_or_unlike_lines() {
+        let changes = char_changes("第二段有错别子。", "第二段有错别字。").unwrap();
+        assert!(changes.contains(&(ChangeTag::Delete, "子")));
+        assert!(changes.contains(&(ChangeTag::Insert, "字")));
+
+        // A full rewrite shares next to nothing
+        assert_eq!(
+            char_changes("春眠不觉晓，处处闻啼鸟。", "The quick brown fox."),
+            None
+        );
+
+        // Too long, however alike
+        let long = "字".repeat(MAX_CHAR_DIFF_LEN + 1);
+        let edited = format!("{}改", long);
+        assert_eq!(char_changes(&long, &edited), None);
+        let short = "字".repeat(MAX_CHAR_DIFF_LEN);
+        assert!(char_changes(&short, &short).is_some());
+    }
+
+    #[test]
+    fn capped_diff_compares_only_the_first_lines() {
+        assert_eq!(first_lines("a\nb\nc\n", 2), ("a\nb\n", true));
+        assert_eq!(first_lines("a\nb\n", 2), ("a\nb\n", false));
+        assert_eq!(first_lines("a\nb", 2), ("a\nb", false));
+        assert_eq!(first_lines("a", 0), ("", true));
+
+        let old: String = (0..MAX_DIFF_LINES + 10)
+            .map(|i| format!("第 {} 行\n", i))
+            .collect();
+        let new = old.replace("第 0 行", "第零行");
+        let (diff, truncated) = compute_diff_capped(&old, &new, DiffOptions::default());
+        assert!(truncated);
+        assert_eq!(diff.len(), MAX_DIFF_LINES + 1);
+        assert_eq!(changed_lines(&diff), 2);
+
+        let (_, truncated) = compute_diff_capped("a\n", "b\n", DiffOptions::default());
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn full_width_indent_is_ignored_only_when_asked() {
+        let plain = "第一段。\n第二段。\n";
+        let formatted = "\u{3000}\u{3000}第一段。\n\u{3000}\u{3000}第二段。\n";
+        let ignore = DiffOptions {
+            ignore_whitespace: true,
+            ignore_full_width_indent: false,
+        };
+        let diff = compute_diff_with(plain, formatted, ignore);
+        assert!(has_meaningful_changes(&diff, ignore));
+
+        let ignore = DiffOptions {
+            ignore_whitespace: true,
+            ignore_full_width_indent: true,
+        };
+        let diff = compute_diff_with(plain, formatted, ignore);
+        assert_eq!(changed_lines(&diff), 0);
+        assert!(!has_meaningful_changes(&diff, ignore));
+    }
+}
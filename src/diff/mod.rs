@@ -0,0 +1,135 @@
+//! Line diffs of texts, grouped for showing side by side.
+//!
+//! Texts are compared line by line, and each pair of changed lines again
+//! character by character, which suits CJK text with no spaces between
+//! words. The history window and the AI review draw what these functions
+//! give; nothing here depends on the UI.
+//!
+//! ```
+//! use paper_shell::diff::{DiffRow, calculate_stats, compute_diff, group_into_rows};
+//!
+//! let old = "第一段。\n第二段有错别子。\n";
+//! let new = "第一段。\n第二段有错别字。\n";
+//! let rows = group_into_rows(&compute_diff(old, new));
+//!
+//! assert!(matches!(&rows[0], DiffRow::Unchanged(line) if line == "第一段。"));
+//! assert!(matches!(&rows[1], DiffRow::Pair(removed, added) if removed.len() == 1 && added.len() == 1));
+//! let stats = calculate_stats(&rows);
+//! assert_eq!((stats.added_count, stats.removed_count), (1, 1));
+//! ```
+
+mod lines;
+mod patch;
+mod stats;
+mod types;
+
+pub use lines::{
+    DiffOptions, MAX_DIFF_LINES, char_changes, char_diff, compute_diff, compute_diff_capped,
+    compute_diff_with, detect_moves, first_lines, group_into_rows, has_meaningful_changes,
+    hunk_count, merge_accepted_hunks,
+};
+pub use patch::{PATCH_CONTEXT, unified_patch};
+pub use stats::{DiffStats, calculate_stats};
+pub use types::{DiffLine, DiffLineType, DiffRow};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Random texts from a few short lines, so that pairs share many of them
+    fn random_texts(count: usize) -> Vec<String> {
+        const LINES: [&str; 6] = [
+            "春眠不觉晓",
+            "处处闻啼鸟",
+            "夜来风雨声",
+            "花落知多少",
+            "",
+            "the end",
+        ];
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        (0..count)
+            .map(|_| {
+                let len = (next() % 8) as usize;
+                (0..len)
+                    .map(|_| {
+                        let mut line = LINES[(next() % 6) as usize].to_string();
+                        if next() % 3 == 0 {
+                            line.push('！');
+                        }
+                        line + "\n"
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn side(diff: &[DiffLine], kept: DiffLineType) -> Vec<&str> {
+        diff.iter()
+            .filter(|line| line.line_type == DiffLineType::Unchanged || line.line_type == kept)
+            .map(|line| line.content.as_str())
+            .collect()
+    }
+
+    fn changed_lines(diff: &[DiffLine]) -> (usize, usize) {
+        let count = |kind| diff.iter().filter(|line| line.line_type == kind).count();
+        (count(DiffLineType::Added), count(DiffLineType::Removed))
+    }
+
+    /// Characters counted the way the stats count them, without line breaks
+    fn chars(text: &str) -> i64 {
+        text.lines().map(|line| line.chars().count() as i64).sum()
+    }
+
+    #[test]
+    fn test_diffs_of_random_pairs_hold_together() {
+        let texts = random_texts(40);
+        for old in &texts {
+            for new in &texts {
+                let diff = compute_diff(old, new);
+                // Each side of the diff reads as its text
+                assert_eq!(
+                    side(&diff, DiffLineType::Removed),
+                    old.lines().collect::<Vec<_>>()
+                );
+                assert_eq!(
+                    side(&diff, DiffLineType::Added),
+                    new.lines().collect::<Vec<_>>()
+                );
+
+                // Taking every hunk turns the old text into the new one
+                let rows = group_into_rows(&diff);
+                let all = vec![true; hunk_count(&rows)];
+                if !old.is_empty() {
+                    assert_eq!(&merge_accepted_hunks(old, &rows, &all), new);
+                }
+
+                // Swapping the texts swaps the lines added and removed. Which
+                // characters in a rewritten line count as changed depends on
+                // how the line lines up, but never what they net to.
+                let swapped = compute_diff(new, old);
+                assert_eq!(
+                    changed_lines(&diff),
+                    (changed_lines(&swapped).1, changed_lines(&swapped).0)
+                );
+                let stats = calculate_stats(&rows);
+                let swapped = calculate_stats(&group_into_rows(&swapped));
+                let net = stats.added_count as i64 - stats.removed_count as i64;
+                assert_eq!(
+                    net,
+                    swapped.removed_count as i64 - swapped.added_count as i64
+                );
+                assert_eq!(net, chars(new) - chars(old));
+
+                if old == new {
+                    assert!(!has_meaningful_changes(&diff, DiffOptions::default()));
+                }
+            }
+        }
+    }
+}
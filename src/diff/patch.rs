@@ -0,0 +1,181 @@
+//! Diffs as unified patches, for sharing changes outside the app.
+//!
+//! Diff lines carry no trailing whitespace and no line endings, so a patch
+//! of lines that had trailing whitespace will not apply cleanly.
+
+use super::types::{DiffLine, DiffLineType};
+use std::fmt::Write;
+use std::ops::Range;
+
+/// Unchanged lines shown around each change, as `diff -u` does
+pub const PATCH_CONTEXT: usize = 3;
+
+/// `diff_lines` as a unified diff between files labelled `old_label` and
+/// `new_label`, with `context` unchanged lines around each change. Empty
+/// when nothing changed.
+///
+/// ```
+/// use paper_shell::diff::{PATCH_CONTEXT, compute_diff, unified_patch};
+///
+/// let diff = compute_diff("一\n二\n", "一\n两\n");
+/// assert_eq!(
+///     unified_patch(&diff, "a/草稿.txt", "b/草稿.txt", PATCH_CONTEXT),
+///     "--- a/草稿.txt\n+++ b/草稿.txt\n@@ -1,2 +1,2 @@\n 一\n-二\n+两\n"
+/// );
+/// ```
+pub fn unified_patch(
+    diff_lines: &[DiffLine],
+    old_label: &str,
+    new_label: &str,
+    context: usize,
+) -> String {
+    // Lines of the old and new text before each diff line
+    let mut old_before = Vec::with_capacity(diff_lines.len() + 1);
+    let mut new_before = Vec::with_capacity(diff_lines.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for line in diff_lines {
+        old_before.push(old_line);
+        new_before.push(new_line);
+        match line.line_type {
+            DiffLineType::Unchanged => {
+                old_line += 1;
+                new_line += 1;
+            }
+            DiffLineType::Removed => old_line += 1,
+            DiffLineType::Added => new_line += 1,
+        }
+    }
+    old_before.push(old_line);
+    new_before.push(new_line);
+
+    // Changes whose context touches go into one hunk
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for (index, line) in diff_lines.iter().enumerate() {
+        if line.line_type == DiffLineType::Unchanged {
+            continue;
+        }
+        let start = index.saturating_sub(context);
+        let end = (index + 1 + context).min(diff_lines.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.end => hunk.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut patch = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for hunk in hunks {
+        let old_len = old_before[hunk.end] - old_before[hunk.start];
+        let new_len = new_before[hunk.end] - new_before[hunk.start];
+        let _ = writeln!(
+            patch,
+            "@@ -{} +{} @@",
+            hunk_range(old_before[hunk.start], old_len),
+            hunk_range(new_before[hunk.start], new_len)
+        );
+        for line in &diff_lines[hunk] {
+            let prefix = match line.line_type {
+                DiffLineType::Unchanged => ' ',
+                DiffLineType::Removed => '-',
+                DiffLineType::Added => '+',
+            };
+            patch.push(prefix);
+            patch.push_str(&line.content);
+            patch.push('\n');
+        }
+    }
+    patch
+}
+
+/// `start,len` of a hunk whose lines follow line `before`; an empty range
+/// names the line it follows, and a single line goes without its length
+fn hunk_range(before: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_diff;
+
+    fn patch(old: &str, new: &str) -> String {
+        unified_patch(
+            &compute_diff(old, new),
+            "a/草稿.txt",
+            "b/草稿.txt",
+            PATCH_CONTEXT,
+        )
+    }
+
+    fn numbered(lines: std::ops::RangeInclusive<usize>) -> String {
+        lines.map(|i| format!("{}\n", i)).collect()
+    }
+
+    #[test]
+    fn test_patch_of_separate_and_nearby_changes() {
+        let old = numbered(1..=20);
+        let new: String = old
+            .lines()
+            .filter_map(|line| match line {
+                "2" => Some("二\n".to_string()),
+                "10" => None,
+                "12" => Some("12\n十二半\n".to_string()),
+                "20" => Some("二十\n".to_string()),
+                _ => Some(format!("{}\n", line)),
+            })
+            .collect();
+        assert_eq!(
+            patch(&old, &new),
+            "--- a/草稿.txt\n\
+             +++ b/草稿.txt\n\
+             @@ -1,5 +1,5 @@\n \
+             1\n\
+             -2\n\
+             +二\n \
+             3\n \
+             4\n \
+             5\n\
+             @@ -7,9 +7,9 @@\n \
+             7\n \
+             8\n \
+             9\n\
+             -10\n \
+             11\n \
+             12\n\
+             +十二半\n \
+             13\n \
+             14\n \
+             15\n\
+             @@ -17,4 +17,4 @@\n \
+             17\n \
+             18\n \
+             19\n\
+             -20\n\
+             +二十\n"
+        );
+    }
+
+    #[test]
+    fn test_patch_of_whole_texts_and_single_lines() {
+        assert_eq!(patch("同一行\n", "同一行\n"), "");
+        assert_eq!(
+            patch("", "第一行\n第二行\n"),
+            "--- a/草稿.txt\n+++ b/草稿.txt\n@@ -0,0 +1,2 @@\n+第一行\n+第二行\n"
+        );
+        assert_eq!(
+            patch("旧的\n", ""),
+            "--- a/草稿.txt\n+++ b/草稿.txt\n@@ -1 +0,0 @@\n-旧的\n"
+        );
+        // Inserted lines with no context after them
+        assert_eq!(
+            patch("一\n", "一\n二\n"),
+            "--- a/草稿.txt\n+++ b/草稿.txt\n@@ -1 +1,2 @@\n 一\n+二\n"
+        );
+    }
+}
@@ -1,13 +1,21 @@
+use super::lines::char_diff;
 use super::types::DiffRow;
+use crate::segment::tokens;
 use similar::{ChangeTag, TextDiff};
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct DiffStats {
+    /// Characters added and removed
     pub added_count: usize,
     pub removed_count: usize,
+    /// Words added and removed, each CJK character counted as one. Reads
+    /// better for Western text, where rewording a sentence changes many
+    /// characters but few words.
+    pub added_words: usize,
+    pub removed_words: usize,
 }
 
-/// Calculate character-level statistics from diff rows
+/// Calculate character- and word-level statistics from diff rows
 pub fn calculate_stats(rows: &[DiffRow]) -> DiffStats {
     let mut stats = DiffStats::default();
 
@@ -17,7 +25,7 @@ pub fn calculate_stats(rows: &[DiffRow]) -> DiffStats {
                 let left_str: String = left.iter().map(|l| l.content.as_str()).collect();
                 let right_str: String = right.iter().map(|r| r.content.as_str()).collect();
 
-                let diff = TextDiff::from_chars(&left_str, &right_str);
+                let diff = char_diff(&left_str, &right_str);
                 for change in diff.iter_all_changes() {
                     match change.tag() {
                         ChangeTag::Insert => stats.added_count += change.value().chars().count(),
@@ -25,8 +33,20 @@ pub fn calculate_stats(rows: &[DiffRow]) -> DiffStats {
                         _ => {}
                     }
                 }
+
+                let left_words: Vec<&str> = tokens(&left_str).map(|token| token.text()).collect();
+                let right_words: Vec<&str> = tokens(&right_str).map(|token| token.text()).collect();
+                let diff = TextDiff::from_slices(&left_words, &right_words);
+                for change in diff.iter_all_changes() {
+                    match change.tag() {
+                        ChangeTag::Insert => stats.added_words += 1,
+                        ChangeTag::Delete => stats.removed_words += 1,
+                        _ => {}
+                    }
+                }
             }
-            DiffRow::Unchanged(_) => {}
+            // Moved lines were neither written nor deleted
+            DiffRow::Unchanged(_) | DiffRow::Moved { .. } => {}
         }
     }
 
@@ -35,7 +55,19 @@ pub fn calculate_stats(rows: &[DiffRow]) -> DiffStats {
 
 #[cfg(test)]
 mod tests {
-    use similar::{ChangeTag, TextDiff};
+    use super::*;
+    use crate::diff::{compute_diff, group_into_rows};
+
+    #[test]
+    fn stats_count_western_words_and_cjk_chars() {
+        let old = "The cat sat on the mat.\n猫坐在垫子上。\n";
+        let new = "The dog slept on the old mat.\n狗坐在垫子上。\n";
+        let stats = calculate_stats(&group_into_rows(&compute_diff(old, new)));
+
+        // cat sat -> dog slept, + old; 猫 -> 狗
+        assert_eq!((stats.added_words, stats.removed_words), (4, 3));
+        assert!(stats.added_count > stats.added_words);
+    }
 
     #[test]
     fn stats_counting_english() {
@@ -0,0 +1,34 @@
+/// One line of a line diff, as [`compute_diff`](super::compute_diff) gives
+/// them
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub line_type: DiffLineType,
+    /// The line without its ending or trailing whitespace
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLineType {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+/// Diff lines grouped for showing side by side, see
+/// [`group_into_rows`](super::group_into_rows)
+#[derive(Debug, Clone)]
+pub enum DiffRow {
+    Unchanged(String),
+    /// Removed lines and the added lines that replace them; either side may
+    /// be empty
+    Pair(Vec<DiffLine>, Vec<DiffLine>),
+    /// Added lines that were removed elsewhere, see
+    /// [`detect_moves`](super::detect_moves)
+    Moved {
+        lines: Vec<DiffLine>,
+        /// Where the lines were, counting non-blank lines of the old text from 1
+        from_paragraph: usize,
+        /// Whether the lines came over unchanged
+        exact: bool,
+    },
+}
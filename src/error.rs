@@ -0,0 +1,262 @@
+//! Errors the user gets to see.
+//!
+//! Failures from the backends, the AI connection, plugins and the settings
+//! file are wrapped in [`AppError`] as they reach the app, shown as a toast
+//! and kept in an [`ErrorLog`] for the 最近错误 window, so they can still be
+//! read, and copied into a bug report, after the toast is gone.
+
+use crate::backend::ai_backend::AiError;
+use crate::backend::ai_panel_backend::AiPanelError;
+use crate::backend::daily_log::DailyLogError;
+use crate::backend::editor_backend::BackendError;
+use crate::backend::file_settings::FileSettingsError;
+use crate::backend::sidebar_backend::SidebarError;
+use crate::backend::usage_log::UsageLogError;
+use crate::config::ConfigError;
+use crate::deeplink::DeepLinkError;
+use crate::file_manager::FileManagerError;
+use crate::file_watcher::FileWatcherError;
+#[cfg(feature = "gui")]
+use crate::open_with::OpenWithError;
+use crate::plugin::PluginError;
+use crate::print::PrintError;
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors kept at once; the oldest go first
+const MAX_ERRORS: usize = 50;
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("无法读取 {}：{source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("无法写入 {}：{source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("历史记录：{0}")]
+    History(#[from] BackendError),
+
+    #[error("标记：{0}")]
+    Marks(#[from] SidebarError),
+
+    #[error("文件单独设置：{0}")]
+    FileSettings(#[from] FileSettingsError),
+
+    #[error("叙事地图：{0}")]
+    NarrativeMap(#[from] AiPanelError),
+
+    #[error("写作日志：{0}")]
+    DailyLog(#[from] DailyLogError),
+
+    #[error("AI 用量记录：{0}")]
+    UsageLog(#[from] UsageLogError),
+
+    #[error("{0}")]
+    Ai(#[from] AiError),
+
+    #[error("设置：{0}")]
+    Config(#[from] ConfigError),
+
+    #[error("{0}")]
+    Plugin(#[from] PluginError),
+
+    #[error("{0}")]
+    FileManager(#[from] FileManagerError),
+
+    #[error("打印：{0}")]
+    Print(#[from] PrintError),
+
+    #[error("{0}")]
+    NewWindow(#[source] io::Error),
+
+    #[cfg(feature = "gui")]
+    #[error("文件关联：{0}")]
+    OpenWith(#[from] OpenWithError),
+
+    #[error("文件监视：{0}")]
+    FileWatch(#[from] FileWatcherError),
+
+    #[error("{0}")]
+    DeepLink(#[from] DeepLinkError),
+}
+
+/// What an error is about, for sorting it out in the log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A document could not be read or written
+    File,
+    /// History, marks, logs and other data in the data dir
+    Data,
+    Ai,
+    Settings,
+    Plugin,
+    /// Handing something to another program failed
+    System,
+}
+
+impl ErrorKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::File => "文件",
+            ErrorKind::Data => "数据",
+            ErrorKind::Ai => "AI",
+            ErrorKind::Settings => "设置",
+            ErrorKind::Plugin => "插件",
+            ErrorKind::System => "系统",
+        }
+    }
+}
+
+impl AppError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AppError::Read { .. } | AppError::Write { .. } => ErrorKind::File,
+            AppError::History(_)
+            | AppError::Marks(_)
+            | AppError::FileSettings(_)
+            | AppError::NarrativeMap(_)
+            | AppError::DailyLog(_)
+            | AppError::UsageLog(_) => ErrorKind::Data,
+            AppError::Ai(_) => ErrorKind::Ai,
+            AppError::Config(_) => ErrorKind::Settings,
+            AppError::Plugin(_) => ErrorKind::Plugin,
+            AppError::FileManager(_)
+            | AppError::Print(_)
+            | AppError::NewWindow(_)
+            | AppError::FileWatch(_)
+            | AppError::DeepLink(_) => ErrorKind::System,
+            #[cfg(feature = "gui")]
+            AppError::OpenWith(_) => ErrorKind::System,
+        }
+    }
+}
+
+/// Read the document at `path`
+pub fn read_document(path: &std::path::Path) -> Result<String, AppError> {
+    std::fs::read_to_string(path).map_err(|source| AppError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Write `content` to the document at `path`
+pub fn write_document(path: &std::path::Path, content: &str) -> Result<(), AppError> {
+    std::fs::write(path, content).map_err(|source| AppError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub struct ErrorEntry {
+    pub time: DateTime<Local>,
+    pub kind: ErrorKind,
+    /// What failed, followed by the error
+    pub message: String,
+}
+
+/// The most recent errors, oldest first
+#[derive(Default)]
+pub struct ErrorLog {
+    entries: VecDeque<ErrorEntry>,
+    /// Entries added since the log was last looked at
+    unseen: usize,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note that `summary` failed with `error`; returns the message logged
+    pub fn push(&mut self, summary: &str, error: &AppError) -> String {
+        let message = format!("{}：{}", summary, error);
+        self.entries.push_back(ErrorEntry {
+            time: Local::now(),
+            kind: error.kind(),
+            message: message.clone(),
+        });
+        while self.entries.len() > MAX_ERRORS {
+            self.entries.pop_front();
+        }
+        self.unseen = (self.unseen + 1).min(self.entries.len());
+        message
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &ErrorEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn unseen(&self) -> usize {
+        self.unseen
+    }
+
+    pub fn mark_seen(&mut self) {
+        self.unseen = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.unseen = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_are_classified_by_what_failed() {
+        let missing = std::env::temp_dir().join("paper-shell-no-such-document.txt");
+        let error = read_document(&missing).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::File);
+        assert!(error.to_string().starts_with("无法读取"));
+
+        let json = serde_json::from_str::<u32>("{").unwrap_err();
+        assert_eq!(
+            AppError::from(SidebarError::from(json)).kind(),
+            ErrorKind::Data
+        );
+        let error = AppError::from(AiError::ConfigError("未设置 API Key".to_string()));
+        assert_eq!(error.kind(), ErrorKind::Ai);
+        assert_eq!(
+            AppError::from(PluginError::NoActiveFile).kind(),
+            ErrorKind::Plugin
+        );
+        assert_eq!(
+            AppError::from(ConfigError::NewerVersion(99)).kind(),
+            ErrorKind::Settings
+        );
+    }
+
+    #[test]
+    fn test_log_keeps_the_most_recent_errors() {
+        let mut log = ErrorLog::new();
+        let error = AppError::from(PluginError::NoActiveFile);
+        for i in 0..MAX_ERRORS + 5 {
+            log.push(&format!("第 {} 次", i), &error);
+        }
+        assert_eq!(log.len(), MAX_ERRORS);
+        assert_eq!(log.unseen(), MAX_ERRORS);
+        assert!(log.entries().next().unwrap().message.starts_with("第 5 次"));
+
+        log.mark_seen();
+        log.push("保存失败", &error);
+        assert_eq!(log.unseen(), 1);
+        assert_eq!(
+            log.entries().last().unwrap().message,
+            format!("保存失败：{}", error)
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 // the FileData is self-contained in the disk file
 // we use the trick called extended attributes to write metadata to a disk file.
@@ -8,3 +10,140 @@ pub struct FileData {
     pub total_time: u64,
     pub content: String,
 }
+
+/// What to do with a document when the app quits; nothing may ask the user
+/// at that point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitAction {
+    /// Nothing would be lost
+    Nothing,
+    /// Write the changes to the document's file
+    Save,
+    /// The document has no file yet; keep it in the recovery folder
+    Recover,
+}
+
+/// Decide what happens to a document on exit. A document with a file is
+/// saved even when it was emptied on purpose; an untitled one is only kept
+/// if there is something to keep.
+pub fn exit_action(has_path: bool, is_dirty: bool, is_blank: bool) -> ExitAction {
+    match (has_path, is_dirty) {
+        (_, false) => ExitAction::Nothing,
+        (true, true) => ExitAction::Save,
+        (false, true) if is_blank => ExitAction::Nothing,
+        (false, true) => ExitAction::Recover,
+    }
+}
+
+/// Write `content` to `path` so that the file holds either the old or the
+/// new content, never a part of it: the content goes to a file next to it
+/// first, which then replaces it.
+pub fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = std::fs::File::create(&temp_path).and_then(|mut file| {
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    });
+    let result = written.and_then(|()| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// What was read from a data file, and where an unreadable one went, so
+/// the user can be told
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loaded<T> {
+    pub value: T,
+    /// The file did not parse and was moved here
+    pub set_aside: Option<PathBuf>,
+}
+
+impl<T> Loaded<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Loaded<U> {
+        Loaded {
+            value: f(self.value),
+            set_aside: self.set_aside,
+        }
+    }
+}
+
+/// Read the JSON file at `path`; `None` if there is none. A file that does
+/// not parse, e.g. one cut short by a crash, counts as none too, after it
+/// is moved aside as `<name>.corrupt-<timestamp>` for a look later.
+pub fn read_json_or_set_aside<T: DeserializeOwned>(path: &Path) -> io::Result<Loaded<Option<T>>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Loaded {
+                value: None,
+                set_aside: None,
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Ok(Loaded {
+            value: Some(value),
+            set_aside: None,
+        }),
+        Err(e) => {
+            let mut corrupt_name = path.file_name().unwrap_or_default().to_os_string();
+            corrupt_name.push(format!(
+                ".corrupt-{}",
+                chrono::Local::now().format("%Y%m%d-%H%M%S%.3f")
+            ));
+            let corrupt_path = path.with_file_name(corrupt_name);
+            std::fs::rename(path, &corrupt_path)?;
+            tracing::warn!(
+                "Unreadable {} ({}), moved to {}",
+                path.display(),
+                e,
+                corrupt_path.display()
+            );
+            Ok(Loaded {
+                value: None,
+                set_aside: Some(corrupt_path),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_keeps_changes_without_asking() {
+        assert_eq!(exit_action(true, false, false), ExitAction::Nothing);
+        assert_eq!(exit_action(false, false, false), ExitAction::Nothing);
+        assert_eq!(exit_action(true, true, false), ExitAction::Save);
+        // Emptying a file on purpose is a change like any other
+        assert_eq!(exit_action(true, true, true), ExitAction::Save);
+        assert_eq!(exit_action(false, true, false), ExitAction::Recover);
+        assert_eq!(exit_action(false, true, true), ExitAction::Nothing);
+    }
+
+    #[test]
+    fn test_atomic_write_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("test_file_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("草稿.txt");
+        std::fs::write(&path, "旧的内容").unwrap();
+
+        write_atomic(&path, "").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        // Nothing is left beside it
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,134 @@
+//! Showing files and folders in the system file manager.
+//!
+//! Finder on macOS, Explorer on Windows and whatever `xdg-open` picks
+//! elsewhere. Linux file managers have no common way to select a file, so
+//! revealing a file there opens the folder it is in.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FileManagerError {
+    #[error("{} does not exist", .0.display())]
+    Missing(PathBuf),
+
+    #[error("failed to start {program}: {source}")]
+    Launch {
+        program: String,
+        source: std::io::Error,
+    },
+}
+
+/// Show `path` selected in its folder
+pub fn reveal_in_file_manager(path: &Path) -> Result<(), FileManagerError> {
+    if !path.exists() {
+        return Err(FileManagerError::Missing(path.to_path_buf()));
+    }
+    spawn(reveal_command(std::env::consts::OS, path))
+}
+
+/// Open the folder `dir`
+pub fn open_folder(dir: &Path) -> Result<(), FileManagerError> {
+    if !dir.is_dir() {
+        return Err(FileManagerError::Missing(dir.to_path_buf()));
+    }
+    spawn(open_command(std::env::consts::OS, dir))
+}
+
+fn spawn(mut command: Command) -> Result<(), FileManagerError> {
+    command
+        .spawn()
+        .map(|_| ())
+        .map_err(|source| FileManagerError::Launch {
+            program: command.get_program().to_string_lossy().into_owned(),
+            source,
+        })
+}
+
+/// The command revealing `path` on `os`, a value of `std::env::consts::OS`
+fn reveal_command(os: &str, path: &Path) -> Command {
+    match os {
+        "macos" => {
+            let mut command = Command::new("open");
+            command.arg("-R").arg(path);
+            command
+        }
+        "windows" => {
+            // Explorer wants the flag and the path as one argument
+            let mut arg = std::ffi::OsString::from("/select,");
+            arg.push(path);
+            let mut command = Command::new("explorer");
+            command.arg(arg);
+            command
+        }
+        _ => open_command(os, path.parent().unwrap_or(path)),
+    }
+}
+
+/// The command opening the folder `dir` on `os`
+fn open_command(os: &str, dir: &Path) -> Command {
+    let program = match os {
+        "macos" => "open",
+        "windows" => "explorer",
+        _ => "xdg-open",
+    };
+    let mut command = Command::new(program);
+    command.arg(dir);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(command: &Command) -> (String, Vec<String>) {
+        (
+            command.get_program().to_string_lossy().into_owned(),
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_reveal_command_per_platform() {
+        let path = Path::new("/notes/草稿.txt");
+        assert_eq!(
+            parts(&reveal_command("macos", path)),
+            (
+                "open".to_string(),
+                vec!["-R".to_string(), path.display().to_string()]
+            )
+        );
+        assert_eq!(
+            parts(&reveal_command("windows", path)),
+            (
+                "explorer".to_string(),
+                vec![format!("/select,{}", path.display())]
+            )
+        );
+        assert_eq!(
+            parts(&reveal_command("linux", path)),
+            ("xdg-open".to_string(), vec!["/notes".to_string()])
+        );
+        assert_eq!(
+            parts(&open_command("freebsd", Path::new("/data"))).0,
+            "xdg-open"
+        );
+    }
+
+    #[test]
+    fn test_missing_paths_are_reported() {
+        let missing = std::env::temp_dir().join("paper-shell-no-such-file.txt");
+        assert!(matches!(
+            reveal_in_file_manager(&missing),
+            Err(FileManagerError::Missing(_))
+        ));
+        assert!(matches!(
+            open_folder(&missing),
+            Err(FileManagerError::Missing(_))
+        ));
+    }
+}
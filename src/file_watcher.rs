@@ -0,0 +1,308 @@
+//! Noticing when another program changes, moves or deletes an open file.
+//!
+//! The folder of each open file is watched with the OS file watcher on its
+//! own thread. Events for the open files are held for [`DEBOUNCE`], since
+//! saving a file often causes several in a row, then posted to the app as
+//! [`ExternalChange`](crate::messages::ResponseMessage::ExternalChange).
+//! The app's own saves show up too; it tells them apart by the content on
+//! disk.
+//!
+//! Built with the `file-watch` feature; without it [`FileWatcher`] watches
+//! nothing.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long the events of a file are gathered before it is reported
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    /// The content may differ from what the app last read or wrote
+    Modified,
+    /// The file is gone, or moved somewhere not known
+    Removed,
+    /// The file was moved to this path
+    Renamed(PathBuf),
+}
+
+/// Changes seen but not reported yet, the latest of each file
+#[derive(Default)]
+pub struct PendingChanges {
+    changes: HashMap<PathBuf, (FileChange, Instant)>,
+}
+
+impl PendingChanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note `change` of `path` at `now`; it replaces what was noted for the
+    /// file before and restarts its wait
+    pub fn note(&mut self, path: PathBuf, change: FileChange, now: Instant) {
+        self.changes.insert(path, (change, now + DEBOUNCE));
+    }
+
+    /// Changes that waited long enough by `now`, taken out
+    pub fn take_due(&mut self, now: Instant) -> Vec<(PathBuf, FileChange)> {
+        let due: Vec<PathBuf> = self
+            .changes
+            .iter()
+            .filter(|(_, (_, at))| *at <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|path| {
+                let (change, _) = self.changes.remove(&path)?;
+                Some((path, change))
+            })
+            .collect()
+    }
+
+    /// When the next change is due, if any is waiting
+    pub fn next_due(&self) -> Option<Instant> {
+        self.changes.values().map(|(_, at)| *at).min()
+    }
+}
+
+#[cfg(feature = "file-watch")]
+pub use watcher::{FileWatcher, FileWatcherError};
+
+#[cfg(not(feature = "file-watch"))]
+pub use stub::{FileWatcher, FileWatcherError};
+
+#[cfg(feature = "file-watch")]
+mod watcher {
+    use super::{FileChange, PendingChanges};
+    use crate::messages::ResponseMessage;
+    use notify::event::{ModifyKind, RenameMode};
+    use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::Instant;
+    use thiserror::Error;
+
+    #[derive(Error, Debug)]
+    pub enum FileWatcherError {
+        #[error("Watcher error: {0}")]
+        Notify(#[from] notify::Error),
+    }
+
+    pub struct FileWatcher {
+        watcher: RecommendedWatcher,
+        /// The files events are reported for, shared with the watcher thread
+        files: Arc<Mutex<HashSet<PathBuf>>>,
+        /// Folders watched for them
+        dirs: HashSet<PathBuf>,
+    }
+
+    impl FileWatcher {
+        /// Start the watcher; nothing is watched until [`Self::watch_files`].
+        /// `wake` is called after changes were posted, e.g. to repaint.
+        pub fn new(
+            sender: Sender<ResponseMessage>,
+            wake: impl Fn() + Send + 'static,
+        ) -> Result<Self, FileWatcherError> {
+            let files: Arc<Mutex<HashSet<PathBuf>>> = Arc::default();
+            let (raw_sender, raw_receiver) = channel();
+            let watched = Arc::clone(&files);
+            let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        tracing::warn!("File watcher error: {}", e);
+                        return;
+                    }
+                };
+                let files = watched.lock().unwrap_or_else(PoisonError::into_inner);
+                for (path, change) in classify(&event) {
+                    if files.contains(&path) {
+                        let _ = raw_sender.send((path, change));
+                    }
+                }
+            })?;
+            // Ends once the watcher, and with it `raw_sender`, is dropped
+            std::thread::spawn(move || debounce(raw_receiver, sender, wake));
+            Ok(Self {
+                watcher,
+                files,
+                dirs: HashSet::new(),
+            })
+        }
+
+        /// Watch exactly `files` from now on
+        pub fn watch_files(&mut self, files: &[PathBuf]) {
+            let dirs: HashSet<PathBuf> = files
+                .iter()
+                .filter_map(|file| file.parent().map(Path::to_path_buf))
+                .collect();
+            for dir in self.dirs.difference(&dirs) {
+                if let Err(e) = self.watcher.unwatch(dir) {
+                    tracing::debug!("Failed to stop watching {:?}: {}", dir, e);
+                }
+            }
+            for dir in dirs.difference(&self.dirs) {
+                // The folder, not the file, so replacing the file is seen too
+                if let Err(e) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    tracing::warn!("Failed to watch {:?}: {}", dir, e);
+                }
+            }
+            self.dirs = dirs;
+            *self.files.lock().unwrap_or_else(PoisonError::into_inner) =
+                files.iter().cloned().collect();
+        }
+    }
+
+    /// What `event` means for the files it names
+    pub(super) fn classify(event: &Event) -> Vec<(PathBuf, FileChange)> {
+        match &event.kind {
+            // Editors often save by moving a new file over the old one, so
+            // the file moved to is new content as well
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match &event.paths[..] {
+                [from, to] => vec![
+                    (from.clone(), FileChange::Renamed(to.clone())),
+                    (to.clone(), FileChange::Modified),
+                ],
+                _ => Vec::new(),
+            },
+            // Moved away to somewhere not named
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+                changed(event, FileChange::Removed)
+            }
+            // Metadata changes, e.g. our own xattrs, leave the content alone
+            EventKind::Modify(ModifyKind::Metadata(_)) => Vec::new(),
+            // Written, or replaced by a file moved over it
+            EventKind::Modify(_) | EventKind::Create(_) => changed(event, FileChange::Modified),
+            EventKind::Access(_) | EventKind::Any | EventKind::Other => Vec::new(),
+        }
+    }
+
+    fn changed(event: &Event, change: FileChange) -> Vec<(PathBuf, FileChange)> {
+        event
+            .paths
+            .iter()
+            .map(|path| (path.clone(), change.clone()))
+            .collect()
+    }
+
+    fn debounce(
+        receiver: Receiver<(PathBuf, FileChange)>,
+        sender: Sender<ResponseMessage>,
+        wake: impl Fn(),
+    ) {
+        let mut pending = PendingChanges::new();
+        loop {
+            let received = match pending.next_due() {
+                Some(due) => receiver.recv_timeout(due.saturating_duration_since(Instant::now())),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok((path, change)) => pending.note(path, change, Instant::now()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            let due = pending.take_due(Instant::now());
+            if due.is_empty() {
+                continue;
+            }
+            for (path, change) in due {
+                let _ = sender.send(ResponseMessage::ExternalChange { path, change });
+            }
+            wake();
+        }
+    }
+}
+
+#[cfg(not(feature = "file-watch"))]
+mod stub {
+    use crate::messages::ResponseMessage;
+    use std::path::PathBuf;
+    use std::sync::mpsc::Sender;
+    use thiserror::Error;
+
+    #[derive(Error, Debug)]
+    pub enum FileWatcherError {}
+
+    /// Stands in for the watcher when the app is built without it
+    pub struct FileWatcher;
+
+    impl FileWatcher {
+        pub fn new(
+            _sender: Sender<ResponseMessage>,
+            _wake: impl Fn() + Send + 'static,
+        ) -> Result<Self, FileWatcherError> {
+            Ok(Self)
+        }
+
+        pub fn watch_files(&mut self, _files: &[PathBuf]) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_are_reported_once_things_settle() {
+        let mut pending = PendingChanges::new();
+        let start = Instant::now();
+        let file = PathBuf::from("/notes/草稿.txt");
+        pending.note(file.clone(), FileChange::Removed, start);
+        // Saved by replacing the file: removed, then written again
+        let later = start + DEBOUNCE / 2;
+        pending.note(file.clone(), FileChange::Modified, later);
+
+        assert!(pending.take_due(start + DEBOUNCE).is_empty());
+        assert_eq!(pending.next_due(), Some(later + DEBOUNCE));
+        assert_eq!(
+            pending.take_due(later + DEBOUNCE),
+            [(file, FileChange::Modified)]
+        );
+        assert_eq!(pending.next_due(), None);
+    }
+
+    #[cfg(feature = "file-watch")]
+    #[test]
+    fn test_events_are_classified() {
+        use notify::event::{CreateKind, MetadataKind, ModifyKind, RemoveKind, RenameMode};
+        use notify::{Event, EventKind};
+
+        let file = PathBuf::from("/notes/草稿.txt");
+        let moved = PathBuf::from("/notes/定稿.txt");
+        let event = |kind, paths: &[&PathBuf]| {
+            paths.iter().fold(Event::new(kind), |event, path| {
+                event.add_path((*path).clone())
+            })
+        };
+
+        let rename = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &[&file, &moved],
+        );
+        assert_eq!(
+            watcher::classify(&rename),
+            [
+                (file.clone(), FileChange::Renamed(moved.clone())),
+                (moved.clone(), FileChange::Modified)
+            ]
+        );
+        let removed = event(EventKind::Remove(RemoveKind::File), &[&file]);
+        assert_eq!(
+            watcher::classify(&removed),
+            [(file.clone(), FileChange::Removed)]
+        );
+        let replaced = event(EventKind::Create(CreateKind::File), &[&file]);
+        assert_eq!(
+            watcher::classify(&replaced),
+            [(file.clone(), FileChange::Modified)]
+        );
+        let xattr = event(
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::Extended)),
+            &[&file],
+        );
+        assert!(watcher::classify(&xattr).is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+//! Fonts as the settings and the font scan name them. Loading them and
+//! handing them to egui is up to [`crate::ui::font`], which needs the `gui`
+//! feature.
+
+use serde::{Deserialize, Serialize};
+
+/// Weights offered in the 字体 menu; a family without the chosen one uses
+/// its closest weight
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FontWeight {
+    Light,
+    #[default]
+    Regular,
+    Medium,
+    Bold,
+}
+
+impl FontWeight {
+    pub const ALL: [FontWeight; 4] = [
+        FontWeight::Light,
+        FontWeight::Regular,
+        FontWeight::Medium,
+        FontWeight::Bold,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FontWeight::Light => "细体",
+            FontWeight::Regular => "常规",
+            FontWeight::Medium => "中等",
+            FontWeight::Bold => "粗体",
+        }
+    }
+
+    /// The CSS / OpenType weight
+    pub fn value(self) -> f32 {
+        match self {
+            FontWeight::Light => 300.0,
+            FontWeight::Regular => 400.0,
+            FontWeight::Medium => 500.0,
+            FontWeight::Bold => 700.0,
+        }
+    }
+
+    /// The offered weight nearest to the OpenType weight `value`
+    pub fn closest(value: f32) -> Self {
+        Self::ALL
+            .into_iter()
+            .min_by(|a, b| {
+                (a.value() - value)
+                    .abs()
+                    .total_cmp(&(b.value() - value).abs())
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Font families installed on the system, split by what they are used for
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemFonts {
+    /// Families with CJK (Chinese, Japanese, Korean) support
+    pub chinese: Vec<String>,
+    /// Every other family, offered for Latin text and numerals
+    pub latin: Vec<String>,
+}
+
+impl SystemFonts {
+    /// The preferred fonts for this OS, offered until the full font scan is done
+    pub fn preferred() -> Self {
+        Self {
+            chinese: preferred_font_list(),
+            latin: Vec::new(),
+        }
+    }
+
+    /// Sort `families` into Chinese and Latin ones by name. The preferred
+    /// fonts for this OS always count as Chinese.
+    pub fn from_families(families: impl IntoIterator<Item = String>) -> Self {
+        let mut chinese = std::collections::BTreeSet::new();
+        let mut latin = std::collections::BTreeSet::new();
+        for family_name in families {
+            // Check if the font name contains common Chinese font indicators
+            if is_likely_chinese_font(&family_name) {
+                chinese.insert(family_name);
+            } else {
+                latin.insert(family_name);
+            }
+        }
+
+        // Also include our known preferred fonts for the current OS
+        for font_name in preferred_font_names() {
+            latin.remove(font_name);
+            chinese.insert(font_name.to_string());
+        }
+
+        Self {
+            chinese: chinese.into_iter().collect(),
+            latin: latin.into_iter().collect(),
+        }
+    }
+}
+
+/// The preferred fonts for this OS, offered until the full font scan is done
+pub fn preferred_font_list() -> Vec<String> {
+    preferred_font_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Get preferred font names based on the current operating system
+pub(crate) fn preferred_font_names() -> Vec<&'static str> {
+    match std::env::consts::OS {
+        "macos" => vec!["Hiragino Sans GB", "PingFang SC", "Heiti SC", "STSong"],
+        "windows" => vec!["Microsoft YaHei", "SimSun", "SimHei", "MS Gothic"],
+        "linux" => vec!["Noto Sans CJK TC"],
+        _ => vec![], // Empty for other OSes - we'll use generic fallback
+    }
+}
+
+/// Check if a font name is likely to be a Chinese font
+///
+/// This checks for common patterns in Chinese font names across different platforms
+fn is_likely_chinese_font(name: &str) -> bool {
+    let name_lower = name.to_lowercase();
+
+    // Common Chinese font name patterns
+    let chinese_indicators = [
+        // Simplified Chinese
+        "pingfang",
+        "hiragino",
+        "heiti",
+        "stheiti",
+        "stsong",
+        "stkaiti",
+        "stfangsong",
+        "songti",
+        "kaiti",
+        "fangsong",
+        "yahei",
+        "microsoft yahei",
+        "simsun",
+        "simhei",
+        "simkai",
+        "nsimsun",
+        "fangsong",
+        "lishu",
+        "deng",
+        "yuan",
+        // Traditional Chinese
+        "lihei",
+        "lisung",
+        "pmingliu",
+        "mingliu",
+        // Japanese (often have CJK support)
+        "gothic",
+        "mincho",
+        "meiryo",
+        "ms gothic",
+        "ms mincho",
+        "yu gothic",
+        "yu mincho",
+        // Generic CJK
+        "noto sans cjk",
+        "noto serif cjk",
+        "source han",
+        "han sans",
+        "han serif",
+        // Direct Chinese characters in name (some fonts have this)
+        "宋体",
+        "黑体",
+        "楷体",
+        "仿宋",
+    ];
+
+    chinese_indicators
+        .iter()
+        .any(|indicator| name_lower.contains(indicator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faces_count_as_the_closest_weight() {
+        assert_eq!(FontWeight::closest(100.0), FontWeight::Light);
+        assert_eq!(FontWeight::closest(350.0), FontWeight::Light);
+        assert_eq!(FontWeight::closest(400.0), FontWeight::Regular);
+        assert_eq!(FontWeight::closest(560.0), FontWeight::Medium);
+        assert_eq!(FontWeight::closest(900.0), FontWeight::Bold);
+    }
+
+    #[test]
+    fn test_families_are_split_by_script() {
+        let fonts = SystemFonts::from_families(
+            [
+                "Songti SC",
+                "Georgia",
+                "Arial",
+                "Noto Sans CJK SC",
+                "Georgia",
+            ]
+            .map(String::from),
+        );
+        assert!(fonts.chinese.contains(&"Songti SC".to_string()));
+        assert!(fonts.chinese.contains(&"Noto Sans CJK SC".to_string()));
+        assert_eq!(
+            fonts.latin,
+            vec!["Arial".to_string(), "Georgia".to_string()]
+        );
+    }
+}
@@ -1,15 +1,49 @@
 //! Paper Shell library
 //!
-//! This library exports the configuration module for use in examples and tests.
+//! Everything the binary runs on, exported so that examples and integration
+//! tests can drive the same code: the content-addressed version store in
+//! [`backend::editor_backend`], the AI and sidebar backends beside it, the
+//! [`diff`] of two versions and the `ui` built on top.
+//!
+//! The window, its fonts and dialogs come with the `gui` feature, on by
+//! default; without it the crate is the library alone.
 
+#[cfg(feature = "gui")]
 pub mod app;
 pub mod backend;
+pub mod busy;
+pub mod cli;
 pub mod config;
+pub mod config_migration;
+pub mod config_writer;
 pub mod constant;
+#[cfg(feature = "gui")]
+pub mod controller;
+pub mod crash_guard;
+pub mod datetime;
+pub mod deeplink;
+pub mod diff;
+pub mod error;
 pub mod file;
+pub mod file_manager;
+pub mod file_watcher;
+pub mod fonts;
+pub mod logging;
+pub mod maintenance;
 pub mod messages;
+#[cfg(feature = "gui")]
 pub mod open_with;
+pub mod paths;
 pub mod plugin;
+pub mod print;
 pub mod process_env;
+pub mod secrets;
+pub mod segment;
+pub mod shortcuts;
+pub mod single_instance;
+#[cfg(feature = "gui")]
 pub mod style;
+pub mod tabs;
+#[cfg(feature = "gui")]
 pub mod ui;
+pub mod word_frequency;
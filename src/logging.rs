@@ -0,0 +1,219 @@
+//! Where `tracing` output goes.
+//!
+//! Everything is written to a log file in `<data_dir>/logs/`, a new one
+//! each day with the last [`MAX_LOG_FILES`] kept, and in debug builds to
+//! the console as well. The level comes from `RUST_LOG` when it is set,
+//! otherwise from `Settings::log_level`, which [`set_level`] applies while
+//! the app runs.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, fmt, reload};
+
+/// Log files kept; older ones are deleted as new ones start
+pub const MAX_LOG_FILES: usize = 5;
+const FILE_PREFIX: &str = "paper-shell";
+const FILE_SUFFIX: &str = "log";
+
+/// How much the app logs. Other crates only log warnings and errors,
+/// whatever the level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogLevel::Error => "错误",
+            LogLevel::Warn => "警告",
+            LogLevel::Info => "信息",
+            LogLevel::Debug => "调试",
+            LogLevel::Trace => "全部",
+        }
+    }
+
+    /// The `EnvFilter` directive for this level
+    fn directive(self) -> String {
+        match self {
+            LogLevel::Error => "error".to_string(),
+            LogLevel::Warn => "warn".to_string(),
+            LogLevel::Info => "warn,paper_shell=info".to_string(),
+            LogLevel::Debug => "warn,paper_shell=debug".to_string(),
+            LogLevel::Trace => "warn,paper_shell=trace".to_string(),
+        }
+    }
+}
+
+/// Keeps the log file written; what is logged after it is dropped is lost,
+/// so `main` holds it until the app ends
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// `RUST_LOG` was set and decides the level
+    from_env: bool,
+}
+
+static FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// Folder of the log files in the data dir `data_dir`
+pub fn log_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("logs")
+}
+
+/// Send `tracing` output to the log files in the data dir `data_dir`, and
+/// the console in debug builds, at `level` unless `RUST_LOG` says otherwise.
+/// Only the first call does anything.
+pub fn init(level: LogLevel, data_dir: &Path) -> LogGuard {
+    let env = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|directives| !directives.trim().is_empty());
+    let (filter, handle) = reload::Layer::new(build_filter(level, env.as_deref()));
+
+    let dir = log_dir(data_dir);
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir);
+    let (file_layer, file_guard, file_error) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_thread_ids(true);
+            (Some(layer), Some(guard), None)
+        }
+        Err(e) => (None, None, Some(e)),
+    };
+    let console_layer = cfg!(debug_assertions).then(|| fmt::layer().with_thread_ids(true));
+
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("Logging already set up: {}", e);
+        return LogGuard { _file: None };
+    }
+    let _ = FILTER.set(LogFilter {
+        handle,
+        from_env: env.is_some(),
+    });
+    if let Some(e) = file_error {
+        tracing::warn!("Not logging to {:?}: {}", dir, e);
+    }
+    LogGuard { _file: file_guard }
+}
+
+/// Log at `level` from now on, unless `RUST_LOG` decides
+pub fn set_level(level: LogLevel) {
+    let Some(filter) = FILTER.get() else {
+        return;
+    };
+    if filter.from_env {
+        return;
+    }
+    if let Err(e) = filter.handle.reload(build_filter(level, None)) {
+        tracing::warn!("Failed to change the log level: {}", e);
+    }
+}
+
+/// Whether `RUST_LOG` decides the level instead of the settings
+pub fn level_from_env() -> bool {
+    FILTER.get().is_some_and(|filter| filter.from_env)
+}
+
+/// `env`, the value of `RUST_LOG`, if it is valid; otherwise `level`
+fn build_filter(level: LogLevel, env: Option<&str>) -> EnvFilter {
+    env.and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| EnvFilter::new(level.directive()))
+}
+
+/// The log file written to last in `dir`
+pub fn current_log_file(dir: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(FILE_PREFIX))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// The last `max_bytes` of the file at `path`, from the first whole line
+pub fn tail(path: &Path, max_bytes: u64) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(max_bytes);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    if start > 0
+        && let Some(newline) = bytes.iter().position(|&byte| byte == b'\n')
+    {
+        bytes.drain(..=newline);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_log_beats_the_settings() {
+        let filter = build_filter(LogLevel::Debug, None);
+        assert_eq!(filter.to_string(), "paper_shell=debug,warn");
+
+        let filter = build_filter(LogLevel::Debug, Some("paper_shell::app=trace"));
+        assert_eq!(filter.to_string(), "paper_shell::app=trace");
+
+        // A mistake in RUST_LOG does not leave the app without logs
+        let filter = build_filter(LogLevel::Error, Some("paper_shell=loud"));
+        assert_eq!(filter.to_string(), "error");
+    }
+
+    #[test]
+    fn test_tail_starts_at_a_whole_line() {
+        let dir = std::env::temp_dir().join(format!("test_logging_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("paper-shell.2026-10-16.log");
+        std::fs::write(&path, "第一行\nsecond line\nthird line\n").unwrap();
+
+        assert_eq!(current_log_file(&dir), Some(path.clone()));
+        assert_eq!(tail(&path, 16).unwrap(), "third line\n");
+        assert_eq!(
+            tail(&path, 1024).unwrap(),
+            "第一行\nsecond line\nthird line\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,12 @@
-use paper_shell::app::PaperShellApp;
+use paper_shell::app::{PaperShellApp, Startup};
+use paper_shell::backend::editor_backend::EditorBackend;
+use paper_shell::cli::{self, CliCommand, TaskArgs};
 use paper_shell::constant;
+use paper_shell::crash_guard;
+use paper_shell::maintenance;
+use paper_shell::messages::ResponseMessage;
+use paper_shell::single_instance::{self, Instance};
 use paper_shell::ui;
-use std::path::PathBuf;
 
 #[cfg(target_os = "macos")]
 use paper_shell::open_with::{complete_app_setup, install_open_with_delegate};
@@ -13,8 +18,60 @@ fn main() -> eframe::Result {
     #[cfg(target_os = "macos")]
     install_open_with_delegate();
 
-    let initial_file = std::env::args().nth(1).map(PathBuf::from);
-    let options = ui::viewport::build_viewport();
+    let launch = match cli::parse(std::env::args_os().skip(1)) {
+        Ok(CliCommand::Launch(launch)) => launch,
+        Ok(CliCommand::Run(args)) => std::process::exit(run_task(args)),
+        Ok(CliCommand::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(CliCommand::Version) => {
+            println!("paper-shell {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("paper-shell: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config = load_config(launch.data_dir.clone());
+    let settings = &config.settings;
+    let data_dir = config.data_dir();
+    // Held until the end so the last lines reach the log file
+    let _log_guard = paper_shell::logging::init(settings.log_level, &data_dir);
+
+    let mut instance_listener = None;
+    if settings.single_instance && !launch.new_document && launch.spawned_from.is_none() {
+        match single_instance::claim(&data_dir, &launch.files, &launch.links) {
+            Ok(Instance::Forwarded) => return Ok(()),
+            Ok(Instance::Primary(listener)) => instance_listener = Some(listener),
+            Err(e) => eprintln!("Single-instance mode unavailable: {}", e),
+        }
+    }
+
+    // The first file opens here, every other one in a window of its own
+    let mut files = launch.files.into_iter();
+    let startup = match files.next() {
+        Some(file) => Startup::File(file),
+        None if launch.new_document
+            || launch.spawned_from.is_some()
+            || !launch.links.is_empty() =>
+        {
+            Startup::Empty
+        }
+        None => Startup::LastSession,
+    };
+    for file in files {
+        let spawned = cli::window_command(Some(&file), launch.data_dir.as_deref())
+            .and_then(|mut command| command.spawn());
+        if let Err(e) = spawned {
+            eprintln!("Failed to open {} in a new window: {}", file.display(), e);
+        }
+    }
+    let options = ui::viewport::build_viewport(
+        &ui::viewport::initial_window_geometry(settings.window),
+        settings.native_decorations,
+    );
 
     eframe::run_native(
         constant::DEFAULT_WINDOW_TITLE,
@@ -24,7 +81,19 @@ fn main() -> eframe::Result {
             let fonts = ui::font::setup_fonts();
             cc.egui_ctx.set_fonts(fonts);
 
-            let app = PaperShellApp::new(cc, initial_file);
+            let app = PaperShellApp::new(cc, startup, launch.data_dir);
+            for link in launch.links {
+                let _ = app.response_sender.send(ResponseMessage::OpenLink(link));
+            }
+            crash_guard::install_panic_hook(app.buffer_mirror.clone());
+            if let Some(listener) = instance_listener {
+                let ctx = cc.egui_ctx.clone();
+                listener.start(app.response_sender.clone(), move || {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                    ctx.request_repaint();
+                });
+            }
 
             // On macOS, set up our app delegate NOW (after winit has initialized NSApplication)
             // This is the critical timing - after EventLoop creation but before processing events
@@ -37,3 +106,25 @@ fn main() -> eframe::Result {
         }),
     )
 }
+
+/// The settings, with the data dir from `data_dir_arg` or from them in use
+fn load_config(data_dir_arg: Option<std::path::PathBuf>) -> paper_shell::config::Config {
+    let mut config = paper_shell::config::Config::default();
+    config.set_data_dir_arg(data_dir_arg);
+    config
+}
+
+/// Run a maintenance task without a window; returns the exit code
+fn run_task(args: TaskArgs) -> i32 {
+    let data_dir = load_config(args.data_dir).data_dir();
+    let result = EditorBackend::with_data_dir(data_dir)
+        .map_err(Into::into)
+        .and_then(|backend| maintenance::run(&args.task, &backend, &mut std::io::stdout().lock()));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("paper-shell: {}", e);
+            1
+        }
+    }
+}
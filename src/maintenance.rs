@@ -0,0 +1,157 @@
+//! Maintenance tasks run from the command line without a window.
+//!
+//! Each [`Task`] works on the history store through [`EditorBackend`] and
+//! prints its results for people and scripts alike: one line per item,
+//! fields separated by two spaces.
+
+use crate::backend::editor_backend::{BackendError, EditorBackend, HistoryEntry};
+use crate::cli::Task;
+use crate::datetime::{DEFAULT_DATETIME_FORMAT, format_local};
+use crate::file::write_atomic;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MaintenanceError {
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+
+    #[error("no saved versions of {}", .0.display())]
+    NoHistory(PathBuf),
+
+    #[error("{hash} is not a saved version of {}", file.display())]
+    UnknownVersion { file: PathBuf, hash: String },
+
+    #[error("{hash} could be any of {count} saved versions of {}", file.display())]
+    AmbiguousVersion {
+        file: PathBuf,
+        hash: String,
+        count: usize,
+    },
+
+    #[error("failed to write {}: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("failed to print: {0}")]
+    Output(#[from] io::Error),
+
+    #[error("found {0} problem(s)")]
+    Problems(usize),
+}
+
+/// Run `task` on `backend`, printing to `out`
+pub fn run(
+    task: &Task,
+    backend: &EditorBackend,
+    out: &mut dyn Write,
+) -> Result<(), MaintenanceError> {
+    match task {
+        Task::History(file) => history(backend, file, out),
+        Task::Restore { file, hash, output } => {
+            restore(backend, file, hash, output.as_deref(), out)
+        }
+        Task::Gc => {
+            let report = backend.collect_garbage()?;
+            writeln!(
+                out,
+                "removed {} blob(s), freed {} bytes",
+                report.removed_blobs, report.freed_bytes
+            )?;
+            Ok(())
+        }
+        Task::Verify => {
+            let report = backend.verify()?;
+            for problem in &report.problems {
+                writeln!(out, "{}", problem)?;
+            }
+            writeln!(
+                out,
+                "checked {} history file(s), {} version(s), {} blob(s)",
+                report.histories, report.versions, report.blobs
+            )?;
+            match report.problems.len() {
+                0 => Ok(()),
+                count => Err(MaintenanceError::Problems(count)),
+            }
+        }
+    }
+}
+
+/// Print the saved versions of `file`, oldest first
+fn history(
+    backend: &EditorBackend,
+    file: &Path,
+    out: &mut dyn Write,
+) -> Result<(), MaintenanceError> {
+    for entry in saved_versions(backend, file)? {
+        let path = entry
+            .file_path
+            .as_deref()
+            .map_or_else(|| "-".to_string(), |path| path.display().to_string());
+        writeln!(
+            out,
+            "{}  {}  {}",
+            entry.hash,
+            format_local(&entry.timestamp, DEFAULT_DATETIME_FORMAT),
+            path
+        )?;
+    }
+    Ok(())
+}
+
+/// Print the version of `file` whose hash starts with `hash`, or write it to
+/// `output`
+fn restore(
+    backend: &EditorBackend,
+    file: &Path,
+    hash: &str,
+    output: Option<&Path>,
+    out: &mut dyn Write,
+) -> Result<(), MaintenanceError> {
+    let mut matches: Vec<String> = saved_versions(backend, file)?
+        .into_iter()
+        .map(|entry| entry.hash)
+        .filter(|saved| !hash.is_empty() && saved.starts_with(hash))
+        .collect();
+    matches.sort();
+    matches.dedup();
+    let full_hash = match &matches[..] {
+        [full_hash] => full_hash,
+        [] => {
+            return Err(MaintenanceError::UnknownVersion {
+                file: file.to_path_buf(),
+                hash: hash.to_string(),
+            });
+        }
+        _ => {
+            return Err(MaintenanceError::AmbiguousVersion {
+                file: file.to_path_buf(),
+                hash: hash.to_string(),
+                count: matches.len(),
+            });
+        }
+    };
+
+    let content = backend.restore_version(full_hash)?;
+    match output {
+        Some(path) => write_atomic(path, &content).map_err(|source| MaintenanceError::Write {
+            path: path.to_path_buf(),
+            source,
+        }),
+        None => Ok(out.write_all(content.as_bytes())?),
+    }
+}
+
+fn saved_versions(
+    backend: &EditorBackend,
+    file: &Path,
+) -> Result<Vec<HistoryEntry>, MaintenanceError> {
+    match backend.load_history(file) {
+        Ok(entries) if !entries.is_empty() => Ok(entries),
+        Ok(_) | Err(BackendError::FileNotFound(_)) => {
+            Err(MaintenanceError::NoHistory(file.to_path_buf()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
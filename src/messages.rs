@@ -1,17 +1,73 @@
-use crate::backend::ai_backend::{AiAgentResponse, AiError, AiProgressEvent, AiRequestId};
+use crate::backend::ai_backend::{
+    AiAgentResponse, AiError, AiProgressEvent, AiRequestId, ProofreadIssue,
+};
+use crate::backend::daily_log::DayTotals;
 use crate::backend::editor_backend::HistoryEntry;
+use crate::backend::global_search::{FileHits, SearchSummary};
+use crate::backend::marked_files::MarkedFile;
 use crate::backend::sidebar_backend::Mark;
-use crate::file::FileData;
-use std::collections::HashMap;
+use crate::error::AppError;
+use crate::file::{FileData, Loaded};
+use crate::file_watcher::FileChange;
+use crate::fonts::{FontWeight, SystemFonts};
+use crate::plugin::PluginError;
+use crate::tabs::TabId;
+use crate::word_frequency::FrequentWord;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
 
-/// Response messages from background operations
+/// Response messages from background operations.
+///
+/// The one definition for every producer: the app's background threads, the
+/// single-instance listener and the macOS open-with handler. The app handles
+/// them in a match without a catch-all arm, so a new variant does not build
+/// until it is handled.
 pub enum ResponseMessage {
-    FileSaved(Result<(String, u64), String>), // (uuid, total_time), error
-    FileLoaded(Result<FileData, String>),     // FileData, error
-    HistoryLoaded(Result<Vec<HistoryEntry>, String>),
-    MarksLoaded(Result<HashMap<usize, Mark>, String>),
+    /// A document was saved for tab `tab`: Ok((uuid, total_time)) | Err(error).
+    FileSaved {
+        tab: TabId,
+        result: Result<(String, u64), AppError>,
+    },
+    FileLoaded {
+        tab: TabId,
+        result: Result<FileData, AppError>,
+    },
+    /// Save As gave the document of tab `tab` the new path `path`; the save
+    /// itself follows as `FileSaved`.
+    FileRenamed {
+        tab: TabId,
+        path: PathBuf,
+    },
+    HistoryLoaded(Result<Vec<HistoryEntry>, AppError>),
+    MarksLoaded {
+        tab: TabId,
+        result: Result<Loaded<HashMap<usize, Mark>>, AppError>,
+    },
+    DailyLogLoaded(Result<BTreeMap<NaiveDate, DayTotals>, AppError>),
+    /// Writing time taken for the daily log could not be written; it goes
+    /// back to the time still to log.
+    DailyLogNotWritten {
+        focused_secs: u64,
+        typing_secs: u64,
+        error: AppError,
+    },
+    /// Every document with marks, for the 所有批注 window.
+    AllMarksLoaded(Result<Vec<MarkedFile>, AppError>),
+    /// A document with hits for global search `search`, sent as it is found.
+    GlobalSearchHits {
+        search: u64,
+        hits: FileHits,
+    },
+    /// Global search `search` read its last document or was cancelled.
+    GlobalSearchFinished {
+        search: u64,
+        result: Result<SearchSummary, AppError>,
+    },
     OpenFile(PathBuf),
+    /// A `papershell:` link to open, not yet checked; see [`crate::deeplink`].
+    OpenLink(String),
     AiProgress {
         request_id: AiRequestId,
         event: AiProgressEvent,
@@ -20,9 +76,91 @@ pub enum ResponseMessage {
         request_id: AiRequestId,
         result: Result<AiAgentResponse, AiError>,
     },
+    /// Stored narrative map for the file with `uuid`; `None` if there is none yet.
+    NarrativeMapLoaded {
+        uuid: String,
+        result: Result<Loaded<Option<Vec<String>>>, AppError>,
+    },
+    NarrativeMapExtracted {
+        request_id: AiRequestId,
+        result: Result<Vec<String>, AiError>,
+    },
+    ProofreadChecked {
+        request_id: AiRequestId,
+        result: Result<Vec<ProofreadIssue>, AiError>,
+    },
+    /// Result of the settings window's connection check: Ok(message) | Err(error).
+    AiConnectionTested(Result<String, AiError>),
+    /// Background work nobody waits for failed, e.g. writing the settings
+    /// file or saving marks; `what` says what failed, for the user.
+    Failed {
+        what: &'static str,
+        error: AppError,
+    },
+    /// The background font scan finished with these font families.
+    FontsEnumerated(SystemFonts),
+    /// The faces of the font `family` come in these weights.
+    FontWeightsFound {
+        family: String,
+        weights: Vec<FontWeight>,
+    },
+    /// A font file was picked in the 加载字体文件… dialog.
+    FontFilePicked(PathBuf),
+    /// Another program changed, moved or deleted the open file `path`.
+    ExternalChange {
+        path: PathBuf,
+        change: FileChange,
+    },
     /// A plugin finished running: (plugin display name, Ok(message) | Err(error)).
     PluginFinished {
         name: String,
-        result: Result<String, String>,
+        result: Result<String, PluginError>,
+    },
+    /// The most repeated words of the document of tab `tab`, for the 高频词 window.
+    FrequentWordsCounted {
+        tab: TabId,
+        words: Vec<FrequentWord>,
     },
+    /// A print job reached the spooler: Ok(message) | Err(error).
+    Printed(Result<String, AppError>),
+}
+
+/// Replies handled in one frame at most, so a burst cannot stall the UI;
+/// the rest wait for the next frame
+pub const MAX_MESSAGES_PER_FRAME: usize = 64;
+
+/// Take up to `limit` messages waiting in `receiver`, oldest first, without
+/// blocking
+pub fn drain_messages<T>(receiver: &Receiver<T>, limit: usize) -> Vec<T> {
+    receiver.try_iter().take(limit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_queued_messages_are_taken_together_in_order() {
+        let (sender, receiver) = channel();
+        for i in 0..5 {
+            sender
+                .send(ResponseMessage::OpenFile(PathBuf::from(i.to_string())))
+                .unwrap();
+        }
+        let taken: Vec<PathBuf> = drain_messages(&receiver, 3)
+            .into_iter()
+            .map(|message| match message {
+                ResponseMessage::OpenFile(path) => path,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            taken,
+            [PathBuf::from("0"), PathBuf::from("1"), PathBuf::from("2")]
+        );
+        // The rest stay queued for the next frame
+        assert_eq!(drain_messages(&receiver, 3).len(), 2);
+        assert!(drain_messages(&receiver, 3).is_empty());
+    }
 }
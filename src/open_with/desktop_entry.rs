@@ -0,0 +1,103 @@
+//! File association on Linux: a desktop entry in the user's applications
+//! folder, which file managers read for their "open with" menus.
+//!
+//! The entry starts the app with `%U`, all selected files at once, which
+//! [`crate::cli`] spreads over windows or hands to the running one. Some
+//! file managers pass them as `file://` URLs; `papershell:` links come the
+//! same way.
+
+use super::OpenWithError;
+use crate::deeplink::SCHEME;
+use crate::file::write_atomic;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const DESKTOP_FILE: &str = "paper-shell.desktop";
+const MIME_TYPES: &[&str] = &["text/plain", "text/markdown", "text/x-markdown"];
+
+/// The desktop entry for `exe`
+pub fn desktop_entry(exe: &Path) -> String {
+    let mime_types: String = MIME_TYPES
+        .iter()
+        .map(|mime| format!("{};", mime))
+        .chain([format!("x-scheme-handler/{};", SCHEME)])
+        .collect();
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Paper Shell\n\
+         Name[zh_CN]=纸壳\n\
+         Comment=A simple, compact and informative text editor for everyday writing\n\
+         Exec={} %U\n\
+         Terminal=false\n\
+         Categories=Office;TextEditor;\n\
+         MimeType={}\n",
+        quote_exec_arg(&exe.to_string_lossy()),
+        mime_types
+    )
+}
+
+/// Quote `arg` for the Exec key: quoted as the desktop entry spec says,
+/// then escaped again as a string value, which doubles the backslashes
+fn quote_exec_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(c);
+            }
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The user's applications folder, e.g. `~/.local/share/applications`
+fn applications_dir() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|dirs| dirs.data_dir().join("applications"))
+}
+
+/// Write the desktop entry for `exe` and refresh the MIME cache where the
+/// tool for it is installed
+pub fn install(exe: &Path) -> Result<String, OpenWithError> {
+    let dir = applications_dir()
+        .ok_or_else(|| OpenWithError::Command("home directory unknown".to_string()))?;
+    std::fs::create_dir_all(&dir)?;
+    write_atomic(&dir.join(DESKTOP_FILE), &desktop_entry(exe))?;
+    if let Err(e) = Command::new("update-desktop-database").arg(&dir).status() {
+        tracing::info!("Could not run update-desktop-database: {}", e);
+    }
+    if let Err(e) = Command::new("xdg-mime")
+        .args(["default", DESKTOP_FILE])
+        .arg(format!("x-scheme-handler/{}", SCHEME))
+        .status()
+    {
+        tracing::info!("Could not run xdg-mime: {}", e);
+    }
+    Ok("已将纸壳加入应用程序菜单和文本文件的“打开方式”，并用它打开 papershell 链接".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_passes_all_selected_files() {
+        let entry = desktop_entry(Path::new("/opt/paper-shell/paper-shell"));
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+        assert!(entry.contains("\nExec=\"/opt/paper-shell/paper-shell\" %U\n"));
+        assert!(entry.contains(
+            "\nMimeType=text/plain;text/markdown;text/x-markdown;x-scheme-handler/papershell;\n"
+        ));
+
+        // Characters with a meaning in Exec are escaped
+        assert_eq!(
+            quote_exec_arg(r#"/home/me/100% "$HOME"\bin"#),
+            r#""/home/me/100%% \\"\\$HOME\\"\\\\bin""#
+        );
+    }
+}
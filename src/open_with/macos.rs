@@ -4,7 +4,7 @@ use objc2::runtime::{AnyClass, AnyObject, ClassBuilder, Sel};
 use objc2::{MainThreadMarker, msg_send, sel};
 use objc2_app_kit::NSApplication;
 use objc2_foundation::{
-    NSArray, NSDictionary, NSNotification, NSNotificationCenter, NSString, NSUserDefaults,
+    NSArray, NSDictionary, NSNotification, NSNotificationCenter, NSString, NSURL, NSUserDefaults,
     ns_string,
 };
 use std::ffi::CString;
@@ -17,7 +17,8 @@ use std::sync::{Mutex, OnceLock};
 
 // --- Global State ---
 
-static PENDING_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+/// Files and links that arrived before the app could take them
+static PENDING: Mutex<Vec<ResponseMessage>> = Mutex::new(Vec::new());
 static SENDER: Mutex<Option<Sender<ResponseMessage>>> = Mutex::new(None);
 static REGISTER_ONCE: OnceLock<()> = OnceLock::new();
 
@@ -76,16 +77,28 @@ pub fn complete_app_setup(sender: Sender<ResponseMessage>) {
         *s = Some(sender.clone());
     }
 
-    if let Ok(mut pending) = PENDING_FILES.lock()
+    if let Ok(mut pending) = PENDING.lock()
         && !pending.is_empty()
     {
         println!("[Paper Shell] Flushing {} pending files...", pending.len());
-        for path in pending.drain(..) {
-            let _ = sender.send(ResponseMessage::OpenFile(path));
+        for message in pending.drain(..) {
+            let _ = sender.send(message);
         }
     }
 }
 
+/// Hand `message` to the app, or keep it until the app is set up
+fn deliver(message: ResponseMessage) {
+    let mut pending_lock = PENDING.lock().unwrap();
+    let sender_lock = SENDER.lock().unwrap();
+    match &*sender_lock {
+        Some(s) => {
+            let _ = s.send(message);
+        }
+        None => pending_lock.push(message),
+    }
+}
+
 unsafe extern "C-unwind" fn on_will_finish_launching(
     _this: NonNull<AnyObject>,
     _sel: Sel,
@@ -116,6 +129,12 @@ unsafe extern "C-unwind" fn on_will_finish_launching(
                 sel!(application:openFile:),
                 handle_open_file as unsafe extern "C-unwind" fn(_, _, _, _) -> c_uchar,
             );
+            // Takes over from the two above when present: files arrive as
+            // file URLs, `papershell:` links as any other URL
+            builder.add_method(
+                sel!(application:openURLs:),
+                handle_open_urls as unsafe extern "C-unwind" fn(_, _, _, _),
+            );
 
             let new_class = builder.register();
             AnyObject::set_class(delegate.as_ref(), new_class);
@@ -134,17 +153,10 @@ unsafe extern "C-unwind" fn handle_open_files(
     filenames: NonNull<NSArray<NSString>>,
 ) {
     unsafe {
-        let filenames = filenames.as_ref();
-        let mut pending_lock = PENDING_FILES.lock().unwrap();
-        let sender_lock = SENDER.lock().unwrap();
-
-        for filename in filenames.iter() {
-            let path = PathBuf::from(filename.to_string());
-            if let Some(s) = &*sender_lock {
-                let _ = s.send(ResponseMessage::OpenFile(path));
-            } else {
-                pending_lock.push(path);
-            }
+        for filename in filenames.as_ref().iter() {
+            deliver(ResponseMessage::OpenFile(PathBuf::from(
+                filename.to_string(),
+            )));
         }
     }
 }
@@ -156,15 +168,32 @@ unsafe extern "C-unwind" fn handle_open_file(
     filename: NonNull<NSString>,
 ) -> c_uchar {
     unsafe {
-        let path = PathBuf::from(filename.as_ref().to_string());
-        let mut pending_lock = PENDING_FILES.lock().unwrap();
-        let sender_lock = SENDER.lock().unwrap();
-
-        if let Some(s) = &*sender_lock {
-            let _ = s.send(ResponseMessage::OpenFile(path));
-        } else {
-            pending_lock.push(path);
-        }
+        deliver(ResponseMessage::OpenFile(PathBuf::from(
+            filename.as_ref().to_string(),
+        )));
         1
     }
 }
+
+unsafe extern "C-unwind" fn handle_open_urls(
+    _this: NonNull<AnyObject>,
+    _cmd: Sel,
+    _sender: NonNull<AnyObject>,
+    urls: NonNull<NSArray<NSURL>>,
+) {
+    unsafe {
+        for url in urls.as_ref().iter() {
+            let message = if url.isFileURL() {
+                url.path()
+                    .map(|path| ResponseMessage::OpenFile(PathBuf::from(path.to_string())))
+            } else {
+                url.absoluteString()
+                    .map(|url| ResponseMessage::OpenLink(url.to_string()))
+            };
+            match message {
+                Some(message) => deliver(message),
+                None => println!("[Paper Shell] Ignored a URL without a path"),
+            }
+        }
+    }
+}
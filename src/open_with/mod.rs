@@ -0,0 +1,56 @@
+//! Files handed to the app by the system: a double click in the file
+//! manager, or its "open with" menu. `papershell:` links, see
+//! [`crate::deeplink`], take the same ways.
+//!
+//! macOS delivers them as Apple events once the app runs, see `macos`.
+//! Windows and Linux start the app with the files as arguments instead,
+//! which [`crate::cli`] takes apart: the first opens in the new window and
+//! every other one in a window of its own, or in single-instance mode all
+//! of them go to the window already running. For that the system has to
+//! know about the app, which [`register_file_association`] arranges
+//! without an installer.
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+pub use macos::{complete_app_setup, install_open_with_delegate};
+
+mod desktop_entry;
+mod windows_registry;
+
+use std::io;
+use thiserror::Error;
+
+pub use crate::constant::DOCUMENT_EXTENSIONS;
+
+#[derive(Error, Debug)]
+pub enum OpenWithError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("{0}")]
+    Command(String),
+
+    #[error("this system registers file types from the app bundle")]
+    Unsupported,
+}
+
+/// Whether [`register_file_association`] does anything on this system
+pub fn can_register() -> bool {
+    cfg!(any(target_os = "windows", target_os = "linux"))
+}
+
+/// Make the system offer this executable for text and Markdown files, and
+/// open `papershell:` links with it.
+///
+/// Returns what was done, for the user.
+pub fn register_file_association() -> Result<String, OpenWithError> {
+    let exe = std::env::current_exe()?;
+    if cfg!(target_os = "windows") {
+        windows_registry::register(&exe)
+    } else if cfg!(target_os = "linux") {
+        desktop_entry::install(&exe)
+    } else {
+        Err(OpenWithError::Unsupported)
+    }
+}
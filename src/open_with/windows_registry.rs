@@ -0,0 +1,169 @@
+//! File association on Windows, written to the user's part of the registry
+//! (HKCU) so no installer or administrator rights are needed.
+//!
+//! Explorer starts one process per file with `"<exe>" "%1"`; with several
+//! files selected, single-instance mode gathers them into one window.
+//! `papershell:` links start the app the same way.
+
+use super::{DOCUMENT_EXTENSIONS, OpenWithError};
+use crate::deeplink::SCHEME;
+use std::path::Path;
+use std::process::Command;
+
+/// Root of the per-user file type registrations
+const CLASSES_ROOT: &str = r"HKCU\Software\Classes";
+const PROG_ID: &str = "PaperShell.Document";
+
+/// One value to write below [`CLASSES_ROOT`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegistryValue {
+    pub key: String,
+    /// `None` for the key's default value
+    pub name: Option<String>,
+    pub data: String,
+}
+
+impl RegistryValue {
+    fn new(key: impl Into<String>, name: Option<&str>, data: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            name: name.map(str::to_string),
+            data: data.into(),
+        }
+    }
+}
+
+/// Everything that makes Explorer offer `exe` for the document extensions:
+/// a ProgID that opens files with it, listed under each extension's
+/// 打开方式, and the executable's own entry naming the types it takes.
+/// The `papershell` URL scheme opens with it as well.
+pub fn registry_values(exe: &Path) -> Vec<RegistryValue> {
+    let exe_path = exe.display();
+    let command = format!("\"{}\" \"%1\"", exe_path);
+    // Split by hand, so the registration can be built and tested anywhere
+    let exe_string = exe.to_string_lossy();
+    let exe_name = exe_string.rsplit(['\\', '/']).next().unwrap_or_default();
+    let application = format!(r"Applications\{}", exe_name);
+
+    let mut values = vec![
+        RegistryValue::new(PROG_ID, None, "纸壳文档"),
+        RegistryValue::new(
+            format!(r"{}\DefaultIcon", PROG_ID),
+            None,
+            format!("\"{}\",0", exe_path),
+        ),
+        RegistryValue::new(
+            format!(r"{}\shell\open\command", PROG_ID),
+            None,
+            command.clone(),
+        ),
+        RegistryValue::new(&application, Some("FriendlyAppName"), "纸壳"),
+        RegistryValue::new(
+            format!(r"{}\shell\open\command", application),
+            None,
+            command.clone(),
+        ),
+        RegistryValue::new(SCHEME, None, "URL:纸壳链接"),
+        RegistryValue::new(SCHEME, Some("URL Protocol"), ""),
+        RegistryValue::new(format!(r"{}\shell\open\command", SCHEME), None, command),
+    ];
+    for extension in DOCUMENT_EXTENSIONS {
+        values.push(RegistryValue::new(
+            format!(r".{}\OpenWithProgids", extension),
+            Some(PROG_ID),
+            "",
+        ));
+        values.push(RegistryValue::new(
+            format!(r"{}\SupportedTypes", application),
+            Some(&format!(".{}", extension)),
+            "",
+        ));
+    }
+    values
+}
+
+/// Arguments for `reg.exe` to write `value`
+fn reg_args(value: &RegistryValue) -> Vec<String> {
+    let mut args = vec![
+        "add".to_string(),
+        format!(r"{}\{}", CLASSES_ROOT, value.key),
+    ];
+    match &value.name {
+        Some(name) => args.extend(["/v".to_string(), name.clone()]),
+        None => args.push("/ve".to_string()),
+    }
+    args.extend([
+        "/t".to_string(),
+        "REG_SZ".to_string(),
+        "/d".to_string(),
+        value.data.clone(),
+        "/f".to_string(),
+    ]);
+    args
+}
+
+/// Write the registration for `exe` with `reg.exe`, which every Windows
+/// has, instead of pulling in a registry crate for a handful of values
+pub fn register(exe: &Path) -> Result<String, OpenWithError> {
+    for value in registry_values(exe) {
+        let output = Command::new("reg").args(reg_args(&value)).output()?;
+        if !output.status.success() {
+            return Err(OpenWithError::Command(format!(
+                "reg add {} failed: {}",
+                value.key,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+    }
+    Ok("已将纸壳加入文本和 Markdown 文件的“打开方式”，并用它打开 papershell 链接".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_opens_documents_with_this_executable() {
+        let exe = Path::new(r"C:\Tools\Paper Shell\paper-shell.exe");
+        let values = registry_values(exe);
+
+        let command = r#""C:\Tools\Paper Shell\paper-shell.exe" "%1""#;
+        assert!(values.contains(&RegistryValue::new(
+            r"PaperShell.Document\shell\open\command",
+            None,
+            command
+        )));
+        assert!(values.contains(&RegistryValue::new(
+            r"Applications\paper-shell.exe\shell\open\command",
+            None,
+            command
+        )));
+        for extension in DOCUMENT_EXTENSIONS {
+            assert!(values.contains(&RegistryValue::new(
+                format!(r".{}\OpenWithProgids", extension),
+                Some(PROG_ID),
+                ""
+            )));
+        }
+        assert!(values.contains(&RegistryValue::new("papershell", Some("URL Protocol"), "")));
+        assert!(values.contains(&RegistryValue::new(
+            r"papershell\shell\open\command",
+            None,
+            command
+        )));
+
+        assert_eq!(
+            reg_args(&values[0]),
+            [
+                "add",
+                r"HKCU\Software\Classes\PaperShell.Document",
+                "/ve",
+                "/t",
+                "REG_SZ",
+                "/d",
+                "纸壳文档",
+                "/f"
+            ]
+        );
+    }
+}
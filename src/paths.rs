@@ -0,0 +1,181 @@
+//! Where the app keeps its settings and data.
+//!
+//! Normally that is the platform's per-user directories. In portable mode,
+//! switched on by a `portable.flag` file next to the executable or the
+//! `--portable` argument, everything lives in `paper-shell-data/` beside the
+//! executable instead, e.g. to run from a USB stick. Everything that stores
+//! files resolves its location through [`storage`] or [`data_dir`].
+//!
+//! Outside portable mode the data dir can be moved with
+//! `Settings::data_dir`; the settings file stays put. `--data-dir` on the
+//! command line beats both, see [`data_dir`]. The dir in use is passed to
+//! each backend when it is opened.
+
+use crate::constant::{
+    APP_NAME, APP_ORGANIZATION, APP_QUALIFIER, PORTABLE_ARG, PORTABLE_DATA_DIR, PORTABLE_FLAG_FILE,
+};
+use directories::ProjectDirs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Settings file name inside the portable data dir
+const PORTABLE_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageDirs {
+    /// History, marks, logs and plugins
+    pub data_dir: PathBuf,
+    /// The settings file
+    pub config_file: PathBuf,
+    pub portable: bool,
+}
+
+/// Storage locations of this process, resolved once on first use
+pub fn storage() -> &'static StorageDirs {
+    static STORAGE: OnceLock<StorageDirs> = OnceLock::new();
+    STORAGE.get_or_init(|| {
+        let exe_dir = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf));
+        let dirs = resolve(
+            std::env::args().any(|arg| arg == PORTABLE_ARG),
+            exe_dir.as_deref(),
+            platform_dirs(),
+        );
+        tracing::info!("Storage: {:?}", dirs);
+        dirs
+    })
+}
+
+/// Where history, marks, logs and plugins are kept, given the `--data-dir`
+/// argument and the data dir chosen in the settings, if any
+pub fn data_dir(arg: Option<&Path>, chosen: Option<&Path>) -> PathBuf {
+    pick_data_dir(
+        storage(),
+        arg.map(Path::to_path_buf),
+        chosen.map(Path::to_path_buf),
+    )
+}
+
+/// The command line wins; a chosen data dir applies unless running portable
+fn pick_data_dir(storage: &StorageDirs, arg: Option<PathBuf>, chosen: Option<PathBuf>) -> PathBuf {
+    if let Some(dir) = arg {
+        return dir;
+    }
+    match chosen {
+        Some(dir) if !storage.portable => dir,
+        _ => storage.data_dir.clone(),
+    }
+}
+
+/// The platform's data dir and confy's settings file, where available
+fn platform_dirs() -> Option<StorageDirs> {
+    let data_dir = ProjectDirs::from(APP_QUALIFIER, APP_ORGANIZATION, APP_NAME)?
+        .data_dir()
+        .to_path_buf();
+    let config_file = confy::get_configuration_file_path(APP_NAME, None).ok()?;
+    Some(StorageDirs {
+        data_dir,
+        config_file,
+        portable: false,
+    })
+}
+
+/// Pick the storage locations.
+///
+/// Portable mode wins when asked for by argument or by a flag file in
+/// `exe_dir`; otherwise the platform dirs are used, with a local `data`
+/// dir as the last resort.
+fn resolve(
+    portable_arg: bool,
+    exe_dir: Option<&Path>,
+    platform: Option<StorageDirs>,
+) -> StorageDirs {
+    if let Some(exe_dir) = exe_dir
+        && (portable_arg || exe_dir.join(PORTABLE_FLAG_FILE).is_file())
+    {
+        let data_dir = exe_dir.join(PORTABLE_DATA_DIR);
+        return StorageDirs {
+            config_file: data_dir.join(PORTABLE_CONFIG_FILE),
+            data_dir,
+            portable: true,
+        };
+    }
+    if portable_arg {
+        tracing::warn!("Portable mode requested but the executable's folder is unknown");
+    }
+    platform.unwrap_or_else(|| {
+        let data_dir = PathBuf::from("data");
+        StorageDirs {
+            config_file: data_dir.join(PORTABLE_CONFIG_FILE),
+            data_dir,
+            portable: false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn platform() -> StorageDirs {
+        StorageDirs {
+            data_dir: PathBuf::from("/home/me/.local/share/paper-shell"),
+            config_file: PathBuf::from("/home/me/.config/paper-shell/default-config.toml"),
+            portable: false,
+        }
+    }
+
+    #[test]
+    fn test_portable_flag_file_or_argument_wins_over_platform_dirs() {
+        let exe_dir = std::env::temp_dir().join(format!("test_paths_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&exe_dir).unwrap();
+
+        assert_eq!(resolve(false, Some(&exe_dir), Some(platform())), platform());
+
+        let portable = resolve(true, Some(&exe_dir), Some(platform()));
+        assert!(portable.portable);
+        assert_eq!(portable.data_dir, exe_dir.join(PORTABLE_DATA_DIR));
+        assert!(portable.config_file.starts_with(&portable.data_dir));
+
+        fs::write(exe_dir.join(PORTABLE_FLAG_FILE), "").unwrap();
+        assert_eq!(resolve(false, Some(&exe_dir), Some(platform())), portable);
+
+        let _ = fs::remove_dir_all(exe_dir);
+    }
+
+    #[test]
+    fn test_chosen_data_dir_applies_outside_portable_mode() {
+        let chosen = PathBuf::from("/mnt/notes/paper-shell");
+        assert_eq!(
+            pick_data_dir(&platform(), None, Some(chosen.clone())),
+            chosen
+        );
+        assert_eq!(pick_data_dir(&platform(), None, None), platform().data_dir);
+
+        let portable = resolve(true, Some(Path::new("/media/usb")), Some(platform()));
+        assert_eq!(
+            pick_data_dir(&portable, None, Some(chosen.clone())),
+            portable.data_dir
+        );
+
+        // The command line beats the settings and portable mode
+        let arg = PathBuf::from("/tmp/scratch");
+        assert_eq!(
+            pick_data_dir(&platform(), Some(arg.clone()), Some(chosen)),
+            arg
+        );
+        assert_eq!(pick_data_dir(&portable, Some(arg.clone()), None), arg);
+    }
+
+    #[test]
+    fn test_falls_back_without_exe_dir_or_platform_dirs() {
+        // Portable mode needs to know where the executable is
+        assert_eq!(resolve(true, None, Some(platform())), platform());
+
+        let fallback = resolve(false, None, None);
+        assert!(!fallback.portable);
+        assert_eq!(fallback.data_dir, PathBuf::from("data"));
+    }
+}
@@ -1,13 +1,10 @@
 //! Print plugin: send the current editor content to a system printer.
+//!
+//! Lays the pages out with [`crate::print`] in the page setup from the
+//! settings.
 
 use crate::plugin::{Plugin, PluginContext, PluginError, PluginMetadata};
-use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-const DEFAULT_MARGIN_POINTS: u16 = 72;
-static PRINT_JOB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+use crate::print::{self, PageLayout, PrintJob};
 
 pub struct PrintPlugin;
 
@@ -17,6 +14,12 @@ impl PrintPlugin {
     }
 }
 
+impl Default for PrintPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Plugin for PrintPlugin {
     fn metadata(&self) -> PluginMetadata {
         PluginMetadata {
@@ -33,42 +36,28 @@ impl Plugin for PrintPlugin {
             return Err(PluginError::Config("当前文档为空，无法打印".to_string()));
         }
 
-        let print_file = write_print_snapshot(ctx)?;
-        let document_name = document_name(ctx);
-        let margin = ctx.print_margin_points.unwrap_or(DEFAULT_MARGIN_POINTS);
-
-        let print_result =
-            print_with_system_command(&print_file, &document_name, ctx.printer.as_deref(), margin);
-        let _ = fs::remove_file(&print_file);
-        print_result?;
+        let job = print_job(ctx);
+        print::print(&job).map_err(|e| PluginError::Execution(e.to_string()))?;
 
         let printer = ctx.printer.as_deref().unwrap_or("默认打印机");
-        Ok(format!("已发送到 {printer}：{document_name}"))
+        Ok(format!("已发送到 {printer}：{}", job.document_name))
     }
 }
 
-pub fn available_printers() -> Vec<String> {
-    available_printers_from_system()
-}
-
-fn write_print_snapshot(ctx: &PluginContext) -> Result<PathBuf, PluginError> {
-    let extension = ctx
-        .file_path
-        .as_ref()
-        .and_then(|path| path.extension())
-        .and_then(|extension| extension.to_str())
-        .unwrap_or("txt");
-    let job_id = PRINT_JOB_COUNTER.fetch_add(1, Ordering::Relaxed);
-    let file_name = format!(
-        "paper-shell-print-{}-{}.{}",
-        std::process::id(),
-        job_id,
-        extension
-    );
-    let path = std::env::temp_dir().join(file_name);
-
-    fs::write(&path, &ctx.content)?;
-    Ok(path)
+/// The document in `ctx` laid out in its page setup
+fn print_job(ctx: &PluginContext) -> PrintJob {
+    let settings = ctx.print;
+    let layout = PageLayout::new(settings.page_size, settings.margin_points);
+    let indent = settings
+        .indent_paragraphs
+        .then_some(ctx.format_indent.as_str());
+    PrintJob {
+        document_name: document_name(ctx),
+        pages: print::paginate(&ctx.content, layout, indent),
+        page_size: settings.page_size,
+        margin_points: settings.margin_points,
+        printer: ctx.printer.clone(),
+    }
 }
 
 fn document_name(ctx: &PluginContext) -> String {
@@ -80,118 +69,45 @@ fn document_name(ctx: &PluginContext) -> String {
         .to_string()
 }
 
-#[cfg(target_os = "macos")]
-fn print_with_system_command(
-    path: &PathBuf,
-    document_name: &str,
-    printer: Option<&str>,
-    margin_points: u16,
-) -> Result<(), PluginError> {
-    let mut command = Command::new("lp");
-    command.arg("-t").arg(document_name);
-    if let Some(printer) = printer.filter(|value| !value.trim().is_empty()) {
-        command.arg("-d").arg(printer);
-    }
-    add_margin_options(&mut command, margin_points);
-    let output = command
-        .arg(path)
-        .output()
-        .map_err(|_| PluginError::Config("未找到 lp 打印命令".to_string()))?;
-
-    if output.status.success() {
-        return Ok(());
-    }
-
-    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-    Err(PluginError::Execution(if stderr.is_empty() {
-        "打印命令执行失败".to_string()
-    } else {
-        stderr
-    }))
-}
-
-#[cfg(all(unix, not(target_os = "macos")))]
-fn print_with_system_command(
-    path: &PathBuf,
-    document_name: &str,
-    printer: Option<&str>,
-    margin_points: u16,
-) -> Result<(), PluginError> {
-    let mut command = Command::new("lpr");
-    command.arg("-T").arg(document_name);
-    if let Some(printer) = printer.filter(|value| !value.trim().is_empty()) {
-        command.arg("-P").arg(printer);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FormatIndent, PrintSettings};
+    use crate::print::PageSize;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_print_job_follows_the_page_setup_from_the_settings() {
+        let ctx = PluginContext {
+            file_path: Some(PathBuf::from("/notes/雨夜.txt")),
+            content: "他推开门。".to_string(),
+            data_dir: PathBuf::new(),
+            title: None,
+            description: None,
+            collection: None,
+            printer: None,
+            print: PrintSettings {
+                page_size: PageSize::Letter,
+                margin_points: 36,
+                indent_paragraphs: true,
+            },
+            format_indent: FormatIndent::FullWidth,
+        };
+        let job = print_job(&ctx);
+        assert_eq!(job.document_name, "雨夜.txt");
+        assert_eq!((job.page_size, job.margin_points), (PageSize::Letter, 36));
+        assert_eq!(job.pages, [vec!["\u{3000}\u{3000}他推开门。".to_string()]]);
+
+        let flush_left = PluginContext {
+            print: PrintSettings {
+                indent_paragraphs: false,
+                ..ctx.print
+            },
+            ..ctx
+        };
+        assert_eq!(
+            print_job(&flush_left).pages,
+            [vec!["他推开门。".to_string()]]
+        );
     }
-    add_margin_options(&mut command, margin_points);
-    let output = command
-        .arg(path)
-        .output()
-        .map_err(|_| PluginError::Config("未找到 lpr 打印命令".to_string()))?;
-
-    if output.status.success() {
-        return Ok(());
-    }
-
-    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-    Err(PluginError::Execution(if stderr.is_empty() {
-        "打印命令执行失败".to_string()
-    } else {
-        stderr
-    }))
-}
-
-#[cfg(windows)]
-fn print_with_system_command(
-    path: &PathBuf,
-    _document_name: &str,
-    _printer: Option<&str>,
-    _margin_points: u16,
-) -> Result<(), PluginError> {
-    let output = Command::new("notepad")
-        .arg("/p")
-        .arg(path)
-        .output()
-        .map_err(|_| PluginError::Config("未找到 notepad 打印命令".to_string()))?;
-
-    if output.status.success() {
-        return Ok(());
-    }
-
-    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-    Err(PluginError::Execution(if stderr.is_empty() {
-        "打印命令执行失败".to_string()
-    } else {
-        stderr
-    }))
-}
-
-#[cfg(any(target_os = "macos", all(unix, not(target_os = "macos"))))]
-fn add_margin_options(command: &mut Command, margin_points: u16) {
-    let margin = margin_points.to_string();
-    for option in ["page-left", "page-right", "page-top", "page-bottom"] {
-        command.arg("-o").arg(format!("{option}={margin}"));
-    }
-}
-
-#[cfg(any(target_os = "macos", all(unix, not(target_os = "macos"))))]
-fn available_printers_from_system() -> Vec<String> {
-    let output = Command::new("lpstat").arg("-e").output();
-    let Ok(output) = output else {
-        return Vec::new();
-    };
-    if !output.status.success() {
-        return Vec::new();
-    }
-
-    String::from_utf8_lossy(&output.stdout)
-        .lines()
-        .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .map(str::to_string)
-        .collect()
-}
-
-#[cfg(windows)]
-fn available_printers_from_system() -> Vec<String> {
-    Vec::new()
 }
@@ -20,6 +20,7 @@
 pub mod builtin;
 pub mod external;
 
+use crate::config::{FormatIndent, PrintSettings};
 use std::path::PathBuf;
 use std::sync::Arc;
 use thiserror::Error;
@@ -59,7 +60,10 @@ pub struct PluginContext {
     pub description: Option<String>,
     pub collection: Option<String>,
     pub printer: Option<String>,
-    pub print_margin_points: Option<u16>,
+    /// Page setup from the settings.
+    pub print: PrintSettings,
+    /// The 格式化 indent of the document, for printed paragraphs.
+    pub format_indent: FormatIndent,
 }
 
 /// Errors a plugin can report while running.
@@ -0,0 +1,579 @@
+//! Printing a document.
+//!
+//! [`paginate`] lays the text out in pages of fixed-width rows, following
+//! the line-breaking rules of Chinese typesetting: closing punctuation never
+//! starts a row and opening brackets never end one. Neither the first nor
+//! the last row of a paragraph is left alone on a page.
+//!
+//! The pages go to the system as plain text with a form feed before each
+//! new page: `lp` on macOS and Linux prints it at [`CHARS_PER_INCH`] and
+//! [`LINES_PER_INCH`], which the layout is computed for. Windows prints the
+//! file with its shell print verb, which lays it out again on its own.
+
+use crate::segment::is_cjk;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use thiserror::Error;
+
+/// Characters per inch `lp` prints plain text at; a CJK character takes two
+pub const CHARS_PER_INCH: f32 = 10.0;
+pub const LINES_PER_INCH: f32 = 6.0;
+
+/// Narrowest row laid out, whatever the margins, so that a CJK character
+/// and the punctuation kept with it always fit
+const MIN_COLUMNS: usize = 8;
+
+/// Punctuation that may not start a row
+const NO_ROW_START: &str = "，。、；：？！）》」』】〉〕”’…—·％,.;:?!)]}%";
+
+/// Brackets and quotes that may not end a row
+const NO_ROW_END: &str = "（《「『【〈〔“‘([{";
+
+static PRINT_JOB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Error, Debug)]
+pub enum PrintError {
+    #[error("文档为空，无法打印")]
+    Empty,
+
+    #[error("无法写入打印文件：{0}")]
+    Io(#[from] io::Error),
+
+    #[error("无法启动 {program}：{source}")]
+    Launch { program: String, source: io::Error },
+
+    #[error("{program} 打印失败：{message}")]
+    Failed { program: String, message: String },
+}
+
+/// Paper the pages are laid out for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    pub const ALL: [PageSize; 2] = [PageSize::A4, PageSize::Letter];
+
+    /// Width and height in points
+    pub fn points(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.0, 842.0),
+            PageSize::Letter => (612.0, 792.0),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PageSize::A4 => "A4",
+            PageSize::Letter => "Letter",
+        }
+    }
+}
+
+/// Rows on a page and columns in a row; CJK characters take two columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageLayout {
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl PageLayout {
+    /// The layout `lp` prints on `page_size` with `margin_points` on every side
+    pub fn new(page_size: PageSize, margin_points: u16) -> Self {
+        let (width, height) = page_size.points();
+        let margins = 2.0 * f32::from(margin_points);
+        let columns = ((width - margins).max(0.0) / 72.0 * CHARS_PER_INCH) as usize;
+        let rows = ((height - margins).max(0.0) / 72.0 * LINES_PER_INCH) as usize;
+        Self {
+            columns: columns.max(MIN_COLUMNS),
+            rows: rows.max(1),
+        }
+    }
+}
+
+/// A page of rows, top to bottom
+pub type Page = Vec<String>;
+
+/// Lay `text` out in pages of `layout`.
+///
+/// With `indent`, each paragraph starts with it instead of whatever
+/// indentation it has; without, paragraphs start flush left. Blank lines
+/// between paragraphs are kept, except at the top of a page.
+pub fn paginate(text: &str, layout: PageLayout, indent: Option<&str>) -> Vec<Page> {
+    let mut pages = Vec::new();
+    let mut page = Page::new();
+    for line in text.lines() {
+        let line = line.replace('\t', "    ");
+        let paragraph = line.trim_start();
+        if paragraph.is_empty() {
+            if !page.is_empty() {
+                page.push(String::new());
+                if page.len() == layout.rows {
+                    pages.push(std::mem::take(&mut page));
+                }
+            }
+            continue;
+        }
+
+        let rows = wrap(
+            &format!("{}{}", indent.unwrap_or(""), paragraph.trim_end()),
+            layout.columns,
+        );
+        let mut placed = 0;
+        while placed < rows.len() {
+            let remaining = rows.len() - placed;
+            let mut take = remaining.min(layout.rows - page.len());
+            if take < remaining {
+                if placed == 0 && take == 1 && !page.is_empty() {
+                    // The first row goes with the rest on the next page
+                    take = 0;
+                } else if remaining - take == 1 && take > if placed == 0 { 2 } else { 1 } {
+                    // Take a row along with the last one
+                    take -= 1;
+                }
+            }
+            page.extend_from_slice(&rows[placed..placed + take]);
+            placed += take;
+            if page.len() == layout.rows || placed < rows.len() {
+                pages.push(std::mem::take(&mut page));
+            }
+        }
+    }
+
+    if page.iter().any(|row| !row.is_empty()) {
+        pages.push(page);
+    }
+    for page in &mut pages {
+        while page.last().is_some_and(|row| row.is_empty()) {
+            page.pop();
+        }
+    }
+    pages
+}
+
+/// The pages as plain text for the spooler, a form feed starting each new page
+pub fn render(pages: &[Page]) -> String {
+    pages
+        .iter()
+        .map(|page| page.join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n\x0c")
+}
+
+/// Columns `c` takes in a row
+fn char_columns(c: char) -> usize {
+    let wide = is_cjk(c)
+        || ('\u{3000}'..='\u{30FF}').contains(&c)
+        || ('\u{AC00}'..='\u{D7AF}').contains(&c)
+        || ('\u{FF00}'..='\u{FF60}').contains(&c)
+        || ('\u{FFE0}'..='\u{FFE6}').contains(&c)
+        || ['“', '”', '‘', '’', '…', '—', '·'].contains(&c);
+    if wide {
+        2
+    } else if c.is_control() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Part of a run of Latin text, which is not broken across rows
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && char_columns(c) == 1
+}
+
+/// Break the paragraph `text` into rows of at most `columns`
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = start;
+        let mut width = 0;
+        while end < chars.len() && width + char_columns(chars[end]) <= columns {
+            width += char_columns(chars[end]);
+            end += 1;
+        }
+        let end = if end == start {
+            start + 1
+        } else if end < chars.len() {
+            break_before(&chars, start, end)
+        } else {
+            end
+        };
+        rows.push(
+            chars[start..end]
+                .iter()
+                .collect::<String>()
+                .trim_end()
+                .to_string(),
+        );
+        // A wrapped row does not start with the space it broke at
+        start = end;
+        while chars.get(start) == Some(&' ') {
+            start += 1;
+        }
+    }
+    rows
+}
+
+/// Where to end a row starting at `start` that is full before `end`
+fn break_before(chars: &[char], start: usize, end: usize) -> usize {
+    let mut at = end;
+    if is_word_char(chars[at]) && is_word_char(chars[at - 1]) {
+        // Break at the space before the word, unless the word fills the row
+        if let Some(space) = chars[start..at].iter().rposition(|c| *c == ' ')
+            && space > 0
+        {
+            at = start + space + 1;
+        }
+    }
+    while at > start + 1 && (NO_ROW_START.contains(chars[at]) || NO_ROW_END.contains(chars[at - 1]))
+    {
+        at -= 1;
+    }
+    at
+}
+
+/// What to print and where
+#[derive(Debug, Clone)]
+pub struct PrintJob {
+    /// Shown in the printer queue
+    pub document_name: String,
+    pub pages: Vec<Page>,
+    pub page_size: PageSize,
+    pub margin_points: u16,
+    /// `None` for the system's default printer
+    pub printer: Option<String>,
+}
+
+/// Hand `job` to the system's print spooler, waiting until it took it
+pub fn print(job: &PrintJob) -> Result<(), PrintError> {
+    if job.pages.is_empty() {
+        return Err(PrintError::Empty);
+    }
+    let job_id = PRINT_JOB_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "paper-shell-print-{}-{}.txt",
+        std::process::id(),
+        job_id
+    ));
+    fs::write(&path, render(&job.pages))?;
+    let result = run(print_command(std::env::consts::OS, job, &path));
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn run(mut command: Command) -> Result<(), PrintError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|source| PrintError::Launch {
+        program: program.clone(),
+        source,
+    })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(PrintError::Failed {
+        program,
+        message: if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            stderr
+        },
+    })
+}
+
+/// The command printing the file at `path` for `job` on `os`, a value of
+/// `std::env::consts::OS`
+fn print_command(os: &str, job: &PrintJob, path: &Path) -> Command {
+    if os == "windows" {
+        // Start-Process quotes nothing itself; single quotes are doubled
+        let quote = |value: &str| format!("'{}'", value.replace('\'', "''"));
+        let file = quote(&path.to_string_lossy());
+        let script = match job
+            .printer
+            .as_deref()
+            .filter(|name| !name.trim().is_empty())
+        {
+            Some(printer) => format!(
+                "Start-Process -FilePath {file} -Verb PrintTo -ArgumentList {} -Wait",
+                quote(&format!("\"{printer}\""))
+            ),
+            None => format!("Start-Process -FilePath {file} -Verb Print -Wait"),
+        };
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+        return command;
+    }
+
+    let mut command = Command::new("lp");
+    command.arg("-t").arg(&job.document_name);
+    if let Some(printer) = job
+        .printer
+        .as_deref()
+        .filter(|name| !name.trim().is_empty())
+    {
+        command.arg("-d").arg(printer);
+    }
+    let margin = job.margin_points.to_string();
+    let mut options = vec![
+        format!("media={}", job.page_size.label()),
+        format!("cpi={CHARS_PER_INCH}"),
+        format!("lpi={LINES_PER_INCH}"),
+    ];
+    for side in ["left", "right", "top", "bottom"] {
+        options.push(format!("page-{side}={margin}"));
+    }
+    for option in options {
+        command.arg("-o").arg(option);
+    }
+    command.arg(path);
+    command
+}
+
+/// Printers the system knows, apart from its default one
+pub fn available_printers() -> Vec<String> {
+    if cfg!(windows) {
+        return Vec::new();
+    }
+    let Ok(output) = Command::new("lpstat").arg("-e").output() else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Width of `row` in columns
+    fn columns(row: &str) -> usize {
+        row.chars().map(char_columns).sum()
+    }
+
+    #[test]
+    fn test_layout_follows_page_size_and_margins() {
+        let a4 = PageLayout::new(PageSize::A4, 72);
+        assert_eq!(
+            a4,
+            PageLayout {
+                columns: 62,
+                rows: 58
+            }
+        );
+        let letter = PageLayout::new(PageSize::Letter, 54);
+        assert_eq!(
+            letter,
+            PageLayout {
+                columns: 70,
+                rows: 57
+            }
+        );
+        // Margins wider than the page still leave something to print on
+        let squeezed = PageLayout::new(PageSize::A4, 420);
+        assert_eq!(
+            squeezed,
+            PageLayout {
+                columns: 8,
+                rows: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_rows_never_start_with_closing_punctuation() {
+        // Ten columns hold five CJK characters; the comma would open row two
+        let rows = wrap("他走进房间，看了一眼。“你来了？”她问。", 10);
+        assert_eq!(
+            rows,
+            ["他走进房", "间，看了一", "眼。“你来", "了？”她", "问。"]
+        );
+        for row in &rows {
+            assert!(columns(row) <= 10, "{row} is too wide");
+            let first = row.chars().next().unwrap();
+            assert!(!NO_ROW_START.contains(first), "{row} starts with {first}");
+            let last = row.chars().last().unwrap();
+            assert!(!NO_ROW_END.contains(last), "{row} ends with {last}");
+        }
+        assert_eq!(rows.concat(), "他走进房间，看了一眼。“你来了？”她问。");
+    }
+
+    #[test]
+    fn test_opening_quote_moves_to_the_next_row() {
+        // The quote would end row one
+        assert_eq!(wrap("四个汉字“引文”", 10), ["四个汉字", "“引文”"]);
+    }
+
+    #[test]
+    fn test_latin_words_stay_whole() {
+        let rows = wrap("用 iPhone 15 拍照，然后 upload", 12);
+        assert_eq!(rows, ["用 iPhone 15", "拍照，然后", "upload"]);
+        // A word longer than a row is cut where the row ends
+        assert_eq!(wrap("abcdefghijkl", 8), ["abcdefgh", "ijkl"]);
+    }
+
+    #[test]
+    fn test_paragraphs_take_the_indent_from_settings() {
+        let layout = PageLayout {
+            columns: 20,
+            rows: 10,
+        };
+        let text = "  第一段。\n\u{3000}\u{3000}第二段。\n\n\n第三段。";
+
+        let indented = paginate(text, layout, Some("\u{3000}\u{3000}"));
+        assert_eq!(
+            indented,
+            [vec![
+                "\u{3000}\u{3000}第一段。",
+                "\u{3000}\u{3000}第二段。",
+                "",
+                "",
+                "\u{3000}\u{3000}第三段。",
+            ]]
+        );
+        let flush = paginate(text, layout, None);
+        assert_eq!(flush[0][0], "第一段。");
+        assert_eq!(flush[0][1], "第二段。");
+    }
+
+    #[test]
+    fn test_page_breaks_keep_first_and_last_rows_with_their_paragraph() {
+        let layout = PageLayout {
+            columns: 10,
+            rows: 4,
+        };
+        // Three rows of five CJK characters each
+        let three_rows = "一二三四五六七八九十甲乙丙丁戊";
+
+        // After three single-row paragraphs one row is left: the first row
+        // of the long paragraph does not stay there alone
+        let text = format!("第一段\n第二段\n第三段\n{three_rows}");
+        let pages = paginate(&text, layout, None);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0], ["第一段", "第二段", "第三段"]);
+        assert_eq!(pages[1], ["一二三四五", "六七八九十", "甲乙丙丁戊"]);
+
+        // Five rows after nothing: four fit, but the last row would be
+        // alone, so it takes one along
+        let five_rows = format!("{three_rows}子丑寅卯辰巳午未申酉");
+        let pages = paginate(&five_rows, layout, None);
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [3, 2]);
+        assert_eq!(pages[1], ["子丑寅卯辰", "巳午未申酉"]);
+    }
+
+    #[test]
+    fn test_pages_never_start_with_punctuation_or_blank_rows() {
+        let layout = PageLayout {
+            columns: 12,
+            rows: 5,
+        };
+        let text = "他说，我们明天出发。她点头，没有说话。\n\n".repeat(20);
+        let pages = paginate(&text, layout, Some("\u{3000}\u{3000}"));
+
+        assert!(pages.len() > 1);
+        let mut printed = String::new();
+        for page in &pages {
+            assert!(page.len() <= layout.rows);
+            let first = page.first().unwrap();
+            assert!(!first.is_empty());
+            assert!(!NO_ROW_START.contains(first.chars().next().unwrap()));
+            assert!(!page.last().unwrap().is_empty());
+            for row in page {
+                assert!(columns(row) <= layout.columns);
+                printed.push_str(row.trim_start_matches('\u{3000}'));
+            }
+        }
+        // Every character is printed once, in order
+        assert_eq!(printed, text.replace('\n', ""));
+    }
+
+    #[test]
+    fn test_render_puts_a_form_feed_between_pages() {
+        let pages = vec![
+            vec!["甲".to_string(), "乙".to_string()],
+            vec!["丙".to_string()],
+        ];
+        assert_eq!(render(&pages), "甲\n乙\n\x0c丙");
+        assert!(
+            paginate(
+                " \n\n",
+                PageLayout {
+                    columns: 10,
+                    rows: 3
+                },
+                None
+            )
+            .is_empty()
+        );
+    }
+
+    fn parts(command: &Command) -> (String, Vec<String>) {
+        (
+            command.get_program().to_string_lossy().into_owned(),
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_print_command_per_platform() {
+        let mut job = PrintJob {
+            document_name: "草稿.txt".to_string(),
+            pages: Vec::new(),
+            page_size: PageSize::Letter,
+            margin_points: 54,
+            printer: None,
+        };
+        let path = Path::new("/tmp/print-job.txt");
+
+        let (program, args) = parts(&print_command("linux", &job, path));
+        assert_eq!(program, "lp");
+        assert_eq!(args[..2], ["-t", "草稿.txt"]);
+        assert!(args.contains(&"media=Letter".to_string()));
+        assert!(args.contains(&"cpi=10".to_string()));
+        assert!(args.contains(&"page-top=54".to_string()));
+        assert_eq!(args.last().unwrap(), "/tmp/print-job.txt");
+
+        job.printer = Some("Office".to_string());
+        let (_, args) = parts(&print_command("macos", &job, path));
+        assert!(args.windows(2).any(|pair| pair == ["-d", "Office"]));
+
+        let (program, args) = parts(&print_command("windows", &job, path));
+        assert_eq!(program, "powershell");
+        assert_eq!(
+            args.last().unwrap(),
+            "Start-Process -FilePath '/tmp/print-job.txt' -Verb PrintTo -ArgumentList '\"Office\"' -Wait"
+        );
+    }
+
+    #[test]
+    fn test_empty_document_is_not_printed() {
+        let job = PrintJob {
+            document_name: "空白".to_string(),
+            pages: paginate("\n\n", PageLayout::new(PageSize::A4, 72), None),
+            page_size: PageSize::A4,
+            margin_points: 72,
+            printer: None,
+        };
+        assert!(matches!(print(&job), Err(PrintError::Empty)));
+    }
+}
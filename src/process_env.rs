@@ -2,6 +2,7 @@
 
 #[cfg(target_os = "macos")]
 use std::collections::HashSet;
+#[cfg(target_os = "macos")]
 use std::env;
 #[cfg(target_os = "macos")]
 use std::path::Path;
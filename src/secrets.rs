@@ -0,0 +1,136 @@
+//! Storage for secrets kept out of the settings file.
+//!
+//! With the `keyring` feature the AI API key can live in the OS credential
+//! store (Keychain, Credential Manager, kernel keyring), and the settings
+//! file only records that it does. [`SecretStore`] hides which store is used,
+//! so tests can use a [`MemoryStore`].
+
+use std::sync::{Arc, Mutex, PoisonError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("this build has no keychain support")]
+    Unavailable,
+
+    #[error("keychain error: {0}")]
+    Backend(String),
+}
+
+/// A place a single secret can be kept
+pub trait SecretStore: Send {
+    /// The stored secret; `None` if nothing is stored
+    fn get(&self) -> Result<Option<String>, SecretError>;
+    fn set(&self, secret: &str) -> Result<(), SecretError>;
+    /// Remove the secret; removing a missing secret is not an error
+    fn delete(&self) -> Result<(), SecretError>;
+}
+
+/// The OS credential store entry for the AI API key, if this build has one
+pub fn api_key_store() -> Option<Box<dyn SecretStore>> {
+    #[cfg(feature = "keyring")]
+    {
+        match KeychainStore::new(crate::constant::APP_NAME, "ai_api_key") {
+            Ok(store) => Some(Box::new(store)),
+            Err(e) => {
+                tracing::warn!("Keychain unavailable: {}", e);
+                None
+            }
+        }
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        None
+    }
+}
+
+#[cfg(feature = "keyring")]
+pub struct KeychainStore {
+    entry: keyring::Entry,
+}
+
+#[cfg(feature = "keyring")]
+impl KeychainStore {
+    pub fn new(service: &str, user: &str) -> Result<Self, SecretError> {
+        let entry = keyring::Entry::new(service, user).map_err(backend_error)?;
+        Ok(Self { entry })
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn backend_error(e: keyring::Error) -> SecretError {
+    SecretError::Backend(e.to_string())
+}
+
+#[cfg(feature = "keyring")]
+impl SecretStore for KeychainStore {
+    fn get(&self) -> Result<Option<String>, SecretError> {
+        match self.entry.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(backend_error(e)),
+        }
+    }
+
+    fn set(&self, secret: &str) -> Result<(), SecretError> {
+        self.entry.set_password(secret).map_err(backend_error)
+    }
+
+    fn delete(&self) -> Result<(), SecretError> {
+        match self.entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(backend_error(e)),
+        }
+    }
+}
+
+/// Keeps the secret in memory; clones share it
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    secret: Arc<Mutex<Option<String>>>,
+    failing: bool,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store where every call fails, like a locked keychain
+    pub fn failing() -> Self {
+        Self {
+            failing: true,
+            ..Self::default()
+        }
+    }
+
+    fn check(&self) -> Result<(), SecretError> {
+        if self.failing {
+            return Err(SecretError::Backend("store is locked".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl SecretStore for MemoryStore {
+    fn get(&self) -> Result<Option<String>, SecretError> {
+        self.check()?;
+        Ok(self
+            .secret
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    fn set(&self, secret: &str) -> Result<(), SecretError> {
+        self.check()?;
+        *self.secret.lock().unwrap_or_else(PoisonError::into_inner) = Some(secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<(), SecretError> {
+        self.check()?;
+        *self.secret.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Splitting text into the units words are counted in.
+//!
+//! Each CJK character is a unit of its own; any other text counts in words,
+//! runs of characters between whitespace and CJK characters. The word
+//! count in the status bar and the sidebar, and the word statistics of the
+//! history window, all go by these [`tokens`].
+
+/// One unit of text, borrowed from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// A single CJK character
+    Cjk(&'a str),
+    /// A run of other characters without whitespace, e.g. `don't,`
+    Word(&'a str),
+}
+
+impl<'a> Token<'a> {
+    pub fn text(&self) -> &'a str {
+        match self {
+            Token::Cjk(text) | Token::Word(text) => text,
+        }
+    }
+}
+
+/// The tokens of `text` in order; whitespace belongs to none
+pub fn tokens(text: &str) -> Tokens<'_> {
+    Tokens { rest: text }
+}
+
+/// Words in `text`, each CJK character counted as one
+pub fn word_count(text: &str) -> usize {
+    tokens(text).count()
+}
+
+pub struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let text = self.rest.trim_start();
+        let first = text.chars().next()?;
+        if is_cjk(first) {
+            let (token, rest) = text.split_at(first.len_utf8());
+            self.rest = rest;
+            return Some(Token::Cjk(token));
+        }
+        let end = text
+            .find(|c: char| c.is_whitespace() || is_cjk(c))
+            .unwrap_or(text.len());
+        let (token, rest) = text.split_at(end);
+        self.rest = rest;
+        Some(Token::Word(token))
+    }
+}
+
+pub fn is_cjk(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c)
+        || ('\u{3400}'..='\u{4DBF}').contains(&c)
+        || ('\u{20000}'..='\u{2A6DF}').contains(&c)
+        || ('\u{F900}'..='\u{FAFF}').contains(&c)
+        || ('\u{2F800}'..='\u{2FA1F}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixed_text_splits_into_cjk_chars_and_words() {
+        let text = "  他说：“Hello, world!” 然后用iPhone 15拍照。\n";
+        let tokens: Vec<Token> = tokens(text).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Cjk("他"),
+                Token::Cjk("说"),
+                Token::Word("：“Hello,"),
+                Token::Word("world!”"),
+                Token::Cjk("然"),
+                Token::Cjk("后"),
+                Token::Cjk("用"),
+                Token::Word("iPhone"),
+                Token::Word("15"),
+                Token::Cjk("拍"),
+                Token::Cjk("照"),
+                Token::Word("。"),
+            ]
+        );
+        assert_eq!(word_count(text), tokens.len());
+        assert_eq!(word_count(" \n\t"), 0);
+        // Rarer CJK blocks count too
+        assert_eq!(word_count("𠀀豈"), 2);
+    }
+}
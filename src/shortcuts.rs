@@ -0,0 +1,419 @@
+//! User-configurable keyboard shortcuts.
+//!
+//! Bindings live in [`Settings::keybindings`](crate::config::Settings) and are
+//! stored as readable strings such as `"Cmd+Shift+F"`, where `Cmd` means
+//! Command on macOS and Ctrl elsewhere. [`match_action`] is the single place
+//! that turns key presses into app actions.
+//!
+//! Keys are known by the names egui gives them, so bindings can be read and
+//! checked without the `gui` feature; matching key presses needs it.
+
+#[cfg(feature = "gui")]
+use egui::{InputState, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Something a shortcut can trigger
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Save,
+    Open,
+    NewWindow,
+    Print,
+    Find,
+    /// Search every document the app has saved
+    GlobalSearch,
+    Format,
+    History,
+    ToggleAi,
+    /// Add a mark to the caret's line, or remove it again
+    ToggleMark,
+    /// Enter or leave distraction-free fullscreen writing
+    FocusMode,
+    /// Switch to the tab right of the active one
+    NextTab,
+    PreviousTab,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::Save,
+        Action::Open,
+        Action::NewWindow,
+        Action::Print,
+        Action::Find,
+        Action::GlobalSearch,
+        Action::Format,
+        Action::History,
+        Action::ToggleAi,
+        Action::ToggleMark,
+        Action::FocusMode,
+        Action::NextTab,
+        Action::PreviousTab,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Save => "保存",
+            Action::Open => "打开文件",
+            Action::NewWindow => "新窗口",
+            Action::Print => "打印",
+            Action::Find => "查找替换",
+            Action::GlobalSearch => "全局搜索",
+            Action::Format => "格式化",
+            Action::History => "历史",
+            Action::ToggleAi => "显示/隐藏 AI 面板",
+            Action::ToggleMark => "标记当前行",
+            Action::FocusMode => "专注写作模式",
+            Action::NextTab => "下一个标签页",
+            Action::PreviousTab => "上一个标签页",
+        }
+    }
+
+    pub fn default_combo(self) -> KeyCombo {
+        let (command, shift, key) = match self {
+            Action::Save => (true, false, "S"),
+            Action::Open => (true, false, "O"),
+            Action::NewWindow => (true, false, "N"),
+            Action::Print => (true, false, "P"),
+            Action::Find => (true, false, "F"),
+            Action::GlobalSearch => (true, true, "F"),
+            // Format Document in VS Code on Windows and Linux
+            Action::Format => (true, true, "I"),
+            Action::History => (true, true, "H"),
+            Action::ToggleAi => (true, true, "A"),
+            Action::ToggleMark => (true, true, "M"),
+            // The usual fullscreen key
+            Action::FocusMode => (false, false, "F11"),
+            Action::NextTab => (true, false, "Tab"),
+            Action::PreviousTab => (true, true, "Tab"),
+        };
+        KeyCombo {
+            command,
+            alt: false,
+            shift,
+            key,
+        }
+    }
+}
+
+/// Shortcut assigned to each action
+pub type Keybindings = BTreeMap<Action, KeyCombo>;
+
+pub fn default_keybindings() -> Keybindings {
+    Action::ALL
+        .into_iter()
+        .map(|action| (action, action.default_combo()))
+        .collect()
+}
+
+/// Names of the keys a shortcut can use, as egui's `Key::name` gives them
+const KEY_NAMES: &str = "Down Left Right Up Escape Tab Backspace Enter Insert Delete Home \
+    End PageUp PageDown Copy Cut Paste Space Colon Comma Minus Period Plus \
+    Equals Semicolon Backslash Slash Pipe Questionmark Exclamationmark \
+    OpenBracket CloseBracket OpenCurlyBracket CloseCurlyBracket Backtick \
+    Quote 0 1 2 3 4 5 6 7 8 9 A B C D E F G H I J K L M N O P Q R S T U V \
+    W X Y Z F1 F2 F3 F4 F5 F6 F7 F8 F9 F10 F11 F12 F13 F14 F15 F16 F17 F18 \
+    F19 F20 F21 F22 F23 F24 F25 F26 F27 F28 F29 F30 F31 F32 F33 F34 F35 \
+    BrowserBack";
+
+/// Other names egui takes for a key in [`KEY_NAMES`]
+const KEY_ALIASES: [(&str, &str); 8] = [
+    ("ArrowDown", "Down"),
+    ("ArrowLeft", "Left"),
+    ("ArrowRight", "Right"),
+    ("ArrowUp", "Up"),
+    ("Esc", "Escape"),
+    ("Return", "Enter"),
+    ("Help", "Insert"),
+    ("Equal", "Equals"),
+];
+
+/// The key in [`KEY_NAMES`] called `name`; letters in either case
+fn key_name(name: &str) -> Option<&'static str> {
+    if let Some((_, key)) = KEY_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        return Some(key);
+    }
+    KEY_NAMES
+        .split_whitespace()
+        .find(|key| *key == name || (key.len() == 1 && key.eq_ignore_ascii_case(name)))
+}
+
+/// A key together with the modifiers held with it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    /// Command on macOS, Ctrl elsewhere
+    pub command: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// Name of the key as egui's `Key::name` gives it
+    pub key: &'static str,
+}
+
+/// Shortcuts the editor already uses; assigning one of them is flagged
+const RESERVED: [(&str, &str); 7] = [
+    ("Cmd+Z", "撤销"),
+    ("Cmd+C", "复制"),
+    ("Cmd+V", "粘贴"),
+    ("Cmd+X", "剪切"),
+    ("Cmd+A", "全选"),
+    ("Cmd+Enter", "发送"),
+    ("Cmd+Alt+Shift+T", "计时诊断"),
+];
+
+impl KeyCombo {
+    /// The combo pressed in `modifiers` + `key`, if it can serve as a shortcut.
+    ///
+    /// Without Cmd or Alt only function keys qualify, so ordinary typing is
+    /// never taken over.
+    #[cfg(feature = "gui")]
+    pub fn from_press(modifiers: Modifiers, key: Key) -> Option<KeyCombo> {
+        let combo = KeyCombo {
+            command: modifiers.command,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+            key: key_name(key.name())?,
+        };
+        let is_function_key = combo
+            .key
+            .strip_prefix('F')
+            .is_some_and(|number| number.parse::<u8>().is_ok());
+        (combo.command || combo.alt || is_function_key).then_some(combo)
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn shortcut(&self) -> KeyboardShortcut {
+        let mut modifiers = Modifiers::NONE;
+        if self.command {
+            modifiers = modifiers.plus(Modifiers::COMMAND);
+        }
+        if self.alt {
+            modifiers = modifiers.plus(Modifiers::ALT);
+        }
+        if self.shift {
+            modifiers = modifiers.plus(Modifiers::SHIFT);
+        }
+        let key = Key::from_name(self.key).expect("KEY_NAMES are egui's key names");
+        KeyboardShortcut::new(modifiers, key)
+    }
+
+    /// What the editor itself uses this combo for, if anything
+    pub fn reserved_for(&self) -> Option<&'static str> {
+        RESERVED
+            .iter()
+            .find(|(combo, _)| combo.parse::<KeyCombo>().is_ok_and(|combo| combo == *self))
+            .map(|(_, label)| *label)
+    }
+
+    #[cfg(feature = "gui")]
+    fn modifier_count(&self) -> usize {
+        [self.command, self.alt, self.shift]
+            .iter()
+            .filter(|held| **held)
+            .count()
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.command, "Cmd"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        f.write_str(self.key)
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let name = parts.pop().filter(|name| !name.is_empty());
+        let key = name
+            .and_then(key_name)
+            .ok_or_else(|| format!("unknown key in shortcut '{}'", s))?;
+        let mut combo = KeyCombo {
+            command: false,
+            alt: false,
+            shift: false,
+            key,
+        };
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "cmd" | "command" | "ctrl" => combo.command = true,
+                "alt" | "option" => combo.alt = true,
+                "shift" => combo.shift = true,
+                _ => return Err(format!("unknown modifier '{}' in shortcut '{}'", part, s)),
+            }
+        }
+        Ok(combo)
+    }
+}
+
+impl Serialize for KeyCombo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCombo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Fill in defaults for actions without a binding, e.g. ones added in a
+/// newer version.
+pub fn complete_keybindings(bindings: &mut Keybindings) {
+    for action in Action::ALL {
+        bindings
+            .entry(action)
+            .or_insert_with(|| action.default_combo());
+    }
+}
+
+/// Actions that share their shortcut with another action
+pub fn conflicts(bindings: &Keybindings) -> BTreeMap<Action, Vec<Action>> {
+    let mut conflicts = BTreeMap::new();
+    for (action, combo) in bindings {
+        let others: Vec<Action> = bindings
+            .iter()
+            .filter(|(other, other_combo)| *other != action && *other_combo == combo)
+            .map(|(other, _)| *other)
+            .collect();
+        if !others.is_empty() {
+            conflicts.insert(*action, others);
+        }
+    }
+    conflicts
+}
+
+/// The action whose shortcut was pressed this frame, consuming the key press
+/// so the text editor does not see it too.
+///
+/// Shortcuts with more modifiers are tried first, so `Cmd+Shift+F` is not
+/// mistaken for `Cmd+F`.
+#[cfg(feature = "gui")]
+pub fn match_action(input: &mut InputState, bindings: &Keybindings) -> Option<Action> {
+    let mut candidates: Vec<(&Action, &KeyCombo)> = bindings.iter().collect();
+    candidates.sort_by_key(|(_, combo)| std::cmp::Reverse(combo.modifier_count()));
+    candidates
+        .into_iter()
+        .find(|(_, combo)| input.consume_shortcut(&combo.shortcut()))
+        .map(|(action, _)| *action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "gui")]
+    fn press(input: &mut InputState, modifiers: Modifiers, key: Key) {
+        input.modifiers = modifiers;
+        input.events.push(egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        });
+    }
+
+    #[test]
+    fn test_combos_round_trip_through_strings() {
+        for action in Action::ALL {
+            let combo = action.default_combo();
+            assert_eq!(combo.to_string().parse::<KeyCombo>(), Ok(combo));
+        }
+        let combo: KeyCombo = "ctrl + option + F5".parse().unwrap();
+        assert!(combo.command && combo.alt && !combo.shift);
+        assert_eq!(combo.to_string(), "Cmd+Alt+F5");
+        assert!("Cmd+Hyper+S".parse::<KeyCombo>().is_err());
+        assert!("Cmd+".parse::<KeyCombo>().is_err());
+        assert!("Cmd+Hyper".parse::<KeyCombo>().is_err());
+        // Lower-case letters and egui's other names are read as well
+        let combo: KeyCombo = "cmd+s".parse().unwrap();
+        assert_eq!(combo.to_string(), "Cmd+S");
+        assert_eq!(
+            "Alt+Esc".parse::<KeyCombo>().map(|combo| combo.key),
+            Ok("Escape")
+        );
+
+        let bindings = default_keybindings();
+        let text = toml::to_string(&bindings).unwrap();
+        assert!(text.contains("global_search = \"Cmd+Shift+F\""));
+        assert!(text.contains("format = \"Cmd+Shift+I\""));
+        assert!(text.contains("focus_mode = \"F11\""));
+        assert_eq!(toml::from_str::<Keybindings>(&text).unwrap(), bindings);
+    }
+
+    #[test]
+    fn test_detects_conflicts_and_reserved_combos() {
+        let mut bindings = default_keybindings();
+        assert!(conflicts(&bindings).is_empty());
+
+        bindings.insert(Action::History, Action::Save.default_combo());
+        let found = conflicts(&bindings);
+        assert_eq!(found[&Action::Save], [Action::History]);
+        assert_eq!(found[&Action::History], [Action::Save]);
+        assert_eq!(found.len(), 2);
+
+        let copy: KeyCombo = "Cmd+C".parse().unwrap();
+        assert_eq!(copy.reserved_for(), Some("复制"));
+        assert_eq!(Action::Save.default_combo().reserved_for(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn test_match_action_prefers_the_more_specific_shortcut() {
+        let bindings = default_keybindings();
+
+        let mut input = InputState::default();
+        press(&mut input, Modifiers::COMMAND | Modifiers::SHIFT, Key::F);
+        assert_eq!(
+            match_action(&mut input, &bindings),
+            Some(Action::GlobalSearch)
+        );
+        // The press is consumed
+        assert_eq!(match_action(&mut input, &bindings), None);
+
+        let mut input = InputState::default();
+        press(&mut input, Modifiers::COMMAND, Key::F);
+        assert_eq!(match_action(&mut input, &bindings), Some(Action::Find));
+
+        let mut input = InputState::default();
+        press(&mut input, Modifiers::NONE, Key::F);
+        assert_eq!(match_action(&mut input, &bindings), None);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn test_key_names_are_egui_s() {
+        assert_eq!(KEY_NAMES.split_whitespace().count(), Key::ALL.len());
+        for key in Key::ALL {
+            assert_eq!(key_name(key.name()), Some(key.name()));
+        }
+        for (alias, name) in KEY_ALIASES {
+            assert_eq!(Key::from_name(alias).map(Key::name), Some(name));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn test_plain_keys_cannot_be_shortcuts() {
+        assert_eq!(KeyCombo::from_press(Modifiers::SHIFT, Key::S), None);
+        assert!(KeyCombo::from_press(Modifiers::NONE, Key::F2).is_some());
+        assert!(KeyCombo::from_press(Modifiers::ALT, Key::S).is_some());
+    }
+}
@@ -0,0 +1,218 @@
+//! One app process per data dir, when `Settings::single_instance` is on.
+//!
+//! The first process listens on a loopback port and notes the port, with a
+//! random token, in [`INSTANCE_FILE`] in the data dir. A later process sends
+//! it the files and `papershell:` links it was asked to open and exits; the
+//! first one opens them and comes to the front. A note left behind by a
+//! crashed process is noticed when nothing answers on its port, and
+//! replaced.
+//!
+//! Loopback TCP stands in for a Unix socket or named pipe so the same code
+//! works on every platform; the token keeps other local programs from
+//! handing the app files.
+
+use crate::deeplink;
+use crate::messages::ResponseMessage;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use thiserror::Error;
+
+const INSTANCE_FILE: &str = "instance.lock";
+/// How long either side waits on the other
+const TIMEOUT: Duration = Duration::from_millis(500);
+/// Reply of the first instance once it has the files
+const ACK: &str = "ok";
+
+#[derive(Error, Debug)]
+pub enum SingleInstanceError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// What starting up found
+pub enum Instance {
+    /// No other instance runs; this one takes the files of later ones
+    Primary(InstanceListener),
+    /// The instance already running took the files; this one can exit
+    Forwarded,
+}
+
+/// Hand `files` and `links` to the instance running on `data_dir`, or
+/// become that instance if there is none
+pub fn claim(
+    data_dir: &Path,
+    files: &[PathBuf],
+    links: &[String],
+) -> Result<Instance, SingleInstanceError> {
+    let note = data_dir.join(INSTANCE_FILE);
+    if let Some((port, token)) = read_note(&note) {
+        match forward(port, &token, files, links) {
+            Ok(()) => return Ok(Instance::Forwarded),
+            Err(e) => tracing::info!("Replacing stale instance note on port {}: {}", port, e),
+        }
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let port = listener.local_addr()?.port();
+    let token = uuid::Uuid::new_v4().to_string();
+    std::fs::create_dir_all(data_dir)?;
+    std::fs::write(&note, format!("{} {}", port, token))?;
+    Ok(Instance::Primary(InstanceListener { listener, token }))
+}
+
+/// Port and token of the instance that wrote `path`
+fn read_note(path: &Path) -> Option<(u16, String)> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let (port, token) = content.trim().split_once(' ')?;
+    Some((port.parse().ok()?, token.to_string()))
+}
+
+/// Send `files` and `links` to the instance on `port`, one per line after
+/// the token. The paths are absolute, so they cannot be mistaken for links.
+fn forward(port: u16, token: &str, files: &[PathBuf], links: &[String]) -> io::Result<()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut request = format!("{}\n", token);
+    for file in files {
+        // The other instance runs in another working directory
+        let file = std::path::absolute(file)?;
+        request.push_str(&file.to_string_lossy());
+        request.push('\n');
+    }
+    for link in links {
+        request.push_str(link);
+        request.push('\n');
+    }
+    stream.write_all(request.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.trim() == ACK {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "another program answered",
+        ))
+    }
+}
+
+pub struct InstanceListener {
+    listener: TcpListener,
+    token: String,
+}
+
+impl InstanceListener {
+    /// Take files from later instances on a background thread. Each file
+    /// arrives as [`ResponseMessage::OpenFile`], each link as
+    /// [`ResponseMessage::OpenLink`], and `raise` is called after each
+    /// request to bring the window to the front.
+    pub fn start(self, sender: Sender<ResponseMessage>, raise: impl Fn() + Send + 'static) {
+        std::thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                let requests = match stream.and_then(|stream| self.receive(&stream)) {
+                    Ok(Some(requests)) => requests,
+                    Ok(None) => {
+                        tracing::warn!("Ignored a request without the instance token");
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to take files from another instance: {}", e);
+                        continue;
+                    }
+                };
+                tracing::info!("Another instance handed over {} files", requests.len());
+                for request in requests {
+                    let message = if deeplink::is_link(&request) {
+                        ResponseMessage::OpenLink(request)
+                    } else {
+                        ResponseMessage::OpenFile(PathBuf::from(request))
+                    };
+                    let _ = sender.send(message);
+                }
+                raise();
+            }
+        });
+    }
+
+    /// The files and links of one request; `None` if it lacks the token
+    fn receive(&self, stream: &TcpStream) -> io::Result<Option<Vec<String>>> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut lines = BufReader::new(stream).lines();
+        if lines.next().transpose()?.as_deref() != Some(self.token.as_str()) {
+            return Ok(None);
+        }
+        let requests = lines
+            .filter(|line| !line.as_ref().is_ok_and(String::is_empty))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut stream = stream;
+        stream.write_all(format!("{}\n", ACK).as_bytes())?;
+        Ok(Some(requests))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_later_instances_hand_over_their_files() {
+        let dir = std::env::temp_dir().join(format!("test_instance_{}", Uuid::new_v4()));
+        let Ok(Instance::Primary(listener)) = claim(&dir, &[], &[]) else {
+            panic!("first instance should be the primary");
+        };
+        let (sender, receiver) = channel();
+        listener.start(sender, || {});
+
+        let file = dir.join("草稿.txt");
+        let link = "papershell://open?path=%2Fa.txt".to_string();
+        assert!(matches!(
+            claim(
+                &dir,
+                std::slice::from_ref(&file),
+                std::slice::from_ref(&link)
+            ),
+            Ok(Instance::Forwarded)
+        ));
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok(ResponseMessage::OpenFile(path)) => assert_eq!(path, file),
+            _ => panic!("file was not handed over"),
+        }
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok(ResponseMessage::OpenLink(url)) => assert_eq!(url, link),
+            _ => panic!("link was not handed over"),
+        }
+
+        // A token that does not match is not taken
+        let (port, _) = read_note(&dir.join(INSTANCE_FILE)).unwrap();
+        assert!(forward(port, "wrong", &[], &[]).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_stale_note_is_replaced() {
+        let dir = std::env::temp_dir().join(format!("test_instance_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A port nothing listens on any more
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let note = dir.join(INSTANCE_FILE);
+        std::fs::write(&note, format!("{} crashed", port)).unwrap();
+
+        assert!(matches!(claim(&dir, &[], &[]), Ok(Instance::Primary(_))));
+        assert_ne!(read_note(&note).unwrap().1, "crashed");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,7 @@
 use egui::{Color32, Context, Stroke, Style, Visuals};
 
-pub fn configure_style(ctx: &Context) {
+/// Apply the look for `theme`, one of `config::THEMES`
+pub fn configure_style(ctx: &Context, theme: &str) {
     let mut style = Style::default();
 
     // Elegant visual settings
@@ -25,5 +26,33 @@ pub fn configure_style(ctx: &Context) {
     visuals.selection.bg_fill = Color32::from_rgb(200, 220, 255);
     visuals.selection.stroke = Stroke::new(1.0, Color32::from_rgb(100, 100, 100));
 
+    if theme == "sepia" {
+        // Warm paper tones; text colors stay those of the light theme
+        visuals.panel_fill = Color32::from_rgb(246, 240, 226);
+        visuals.window_fill = Color32::from_rgb(249, 244, 232);
+        visuals.extreme_bg_color = Color32::from_rgb(251, 247, 238);
+        visuals.faint_bg_color = Color32::from_rgb(240, 233, 217);
+        visuals.widgets.hovered.bg_fill = Color32::from_rgb(236, 228, 210);
+        visuals.widgets.active.bg_fill = Color32::from_rgb(228, 219, 199);
+    }
+
     ctx.set_visuals(visuals);
 }
+
+/// Corner radius of the main window when it draws its own frame
+pub const WINDOW_CORNER_RADIUS: u8 = 10;
+
+/// Background of the frameless main window: the panel color with rounded
+/// corners, a hairline border and a soft drop shadow
+pub fn window_frame(visuals: &Visuals) -> egui::Frame {
+    egui::Frame::new()
+        .fill(visuals.panel_fill)
+        .corner_radius(WINDOW_CORNER_RADIUS)
+        .stroke(Stroke::new(1.0, Color32::from_black_alpha(40)))
+        .shadow(egui::epaint::Shadow {
+            offset: [0, 2],
+            blur: 12,
+            spread: 0,
+            color: Color32::from_black_alpha(50),
+        })
+}
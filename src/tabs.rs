@@ -0,0 +1,182 @@
+//! The documents open in one window, one tab each.
+//!
+//! The app works on the active document directly; the others wait here in
+//! the order their tabs are shown. Switching swaps the active document with
+//! the one stored for the new tab, so everything that follows the open file
+//! keeps working on the active one without knowing about tabs.
+
+/// Identifies a tab for as long as it is open. Background work started for
+/// a document carries the id, so its result reaches that document even if
+/// another tab is active by then.
+pub type TabId = u64;
+
+pub struct Tabs<T> {
+    /// Every tab in strip order; the active one holds `None`, its document
+    /// is with the app
+    slots: Vec<(TabId, Option<T>)>,
+    active: TabId,
+    next_id: TabId,
+}
+
+impl<T> Default for Tabs<T> {
+    fn default() -> Self {
+        Self {
+            slots: vec![(0, None)],
+            active: 0,
+            next_id: 1,
+        }
+    }
+}
+
+impl<T> Tabs<T> {
+    /// A single tab, for the document the app starts with
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn active(&self) -> TabId {
+        self.active
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn contains(&self, id: TabId) -> bool {
+        self.position(id).is_some()
+    }
+
+    /// Every tab in strip order with its document; `None` for the active tab
+    pub fn iter(&self) -> impl Iterator<Item = (TabId, Option<&T>)> {
+        self.slots.iter().map(|(id, doc)| (*id, doc.as_ref()))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (TabId, Option<&mut T>)> {
+        self.slots.iter_mut().map(|(id, doc)| (*id, doc.as_mut()))
+    }
+
+    /// The first inactive tab whose document matches `predicate`
+    pub fn find(&self, mut predicate: impl FnMut(&T) -> bool) -> Option<TabId> {
+        self.slots
+            .iter()
+            .find(|(_, doc)| doc.as_ref().is_some_and(&mut predicate))
+            .map(|(id, _)| *id)
+    }
+
+    /// Add a tab right after the active one and make it active. `current`
+    /// is stored as the document of the tab that was active, and replaced
+    /// with `document`.
+    pub fn open(&mut self, current: &mut T, document: T) -> TabId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let index = self
+            .position(self.active)
+            .map_or(self.slots.len(), |i| i + 1);
+        let previous = std::mem::replace(current, document);
+        self.store_active(previous);
+        self.slots.insert(index, (id, None));
+        self.active = id;
+        id
+    }
+
+    /// Make tab `id` active, swapping its document into `current`.
+    ///
+    /// Returns false if `id` is already active or not open.
+    pub fn activate(&mut self, id: TabId, current: &mut T) -> bool {
+        if id == self.active {
+            return false;
+        }
+        let Some(document) = self
+            .position(id)
+            .and_then(|index| self.slots[index].1.take())
+        else {
+            return false;
+        };
+        let previous = std::mem::replace(current, document);
+        self.store_active(previous);
+        self.active = id;
+        true
+    }
+
+    /// Close the active tab, making its neighbour active: the one to the
+    /// right, or to the left for the last tab.
+    ///
+    /// Returns the closed tab's document, or `None` if it is the only tab.
+    pub fn close_active(&mut self, current: &mut T) -> Option<T> {
+        let index = self.position(self.active)?;
+        let neighbour = match self.slots.get(index + 1) {
+            Some((id, _)) => *id,
+            None => self.slots.get(index.checked_sub(1)?)?.0,
+        };
+        let neighbour_index = self.position(neighbour)?;
+        let document = self.slots[neighbour_index].1.take()?;
+        self.slots.remove(index);
+        self.active = neighbour;
+        Some(std::mem::replace(current, document))
+    }
+
+    /// The tab `offset` places from the active one, wrapping around the ends
+    pub fn cycled(&self, offset: isize) -> TabId {
+        let Some(index) = self.position(self.active) else {
+            return self.active;
+        };
+        let len = self.slots.len() as isize;
+        let target = (index as isize + offset).rem_euclid(len);
+        self.slots[target as usize].0
+    }
+
+    fn position(&self, id: TabId) -> Option<usize> {
+        self.slots.iter().position(|(slot, _)| *slot == id)
+    }
+
+    fn store_active(&mut self, document: T) {
+        if let Some(index) = self.position(self.active) {
+            self.slots[index].1 = Some(document);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(tabs: &Tabs<&'static str>, active: &'static str) -> Vec<&'static str> {
+        tabs.iter()
+            .map(|(_, doc)| doc.copied().unwrap_or(active))
+            .collect()
+    }
+
+    #[test]
+    fn test_tabs_swap_documents_in_and_out() {
+        let mut tabs = Tabs::new();
+        let mut current = "a";
+        let first = tabs.active();
+        let second = tabs.open(&mut current, "b");
+        assert_eq!(current, "b");
+        tabs.activate(first, &mut current);
+        // New tabs go right after the active one
+        let third = tabs.open(&mut current, "c");
+        assert_eq!(names(&tabs, current), ["a", "c", "b"]);
+        assert_eq!(tabs.find(|doc| *doc == "b"), Some(second));
+        assert_eq!(tabs.find(|doc| *doc == "c"), None);
+
+        assert!(!tabs.activate(third, &mut current));
+        assert_eq!(tabs.cycled(1), second);
+        assert_eq!(tabs.cycled(-2), second);
+        assert!(tabs.activate(tabs.cycled(1), &mut current));
+        assert_eq!(current, "b");
+
+        // Closing the last tab goes to its left neighbour
+        assert_eq!(tabs.close_active(&mut current), Some("b"));
+        assert_eq!((tabs.active(), current), (third, "c"));
+        assert_eq!(tabs.close_active(&mut current), Some("c"));
+        assert_eq!((tabs.active(), current), (first, "a"));
+        assert_eq!(tabs.close_active(&mut current), None);
+        assert_eq!(tabs.len(), 1);
+        assert!(!tabs.contains(second));
+    }
+}
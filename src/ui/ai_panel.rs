@@ -1,12 +1,16 @@
 use crate::backend::ai_backend::{
     AiAgentResponse, AiChatMessage, AiError, AiProgressEvent, AiRequestId, AiSelectionContext,
-    AiToolCall,
+    AiToolCall, NARRATIVE_BEAT_SEPARATOR, ProofreadIssue,
 };
+use crate::config::{PROMPT_WHOLE_DOCUMENT, PromptTemplate};
+use crate::ui::markdown::render_markdown;
 use egui::{Align, Color32, FontId, Frame, Layout, RichText, Sense, UiBuilder};
 
 const COMPOSER_HEIGHT: f32 = 112.0;
 const COMPOSER_HEIGHT_WITH_CONTEXT: f32 = 148.0;
 const PANEL_GAP: f32 = 8.0;
+const NARRATIVE_HEADER_HEIGHT: f32 = 26.0;
+const NARRATIVE_LIST_HEIGHT: f32 = 168.0;
 
 #[derive(Default)]
 pub struct AiPanel {
@@ -25,25 +29,85 @@ pub struct AiPanel {
     request_selection: Option<AiSelectionContext>,
     last_request: Option<PendingRequest>,
     last_error: Option<PanelError>,
+    unavailable_reason: Option<String>,
+    prompt_templates: Vec<PromptTemplate>,
+    narrative_map: NarrativeMapState,
+    /// Assistant message a regenerated reply is added to as a candidate
+    regenerate_target: Option<usize>,
+    /// Today's token usage line, e.g. "今日: 12.3k tokens"
+    usage_summary: Option<String>,
+}
+
+#[derive(Default)]
+struct NarrativeMapState {
+    beats: Vec<String>,
+    expanded: bool,
+    loading: bool,
+    error: Option<String>,
 }
 
 enum AiPanelEntry {
     Message(PanelMessage),
     EditProposal(EditProposal),
     Mindmap(MindmapArtifact),
-    ToolError { name: String, reason: String },
+    ToolError {
+        name: String,
+        reason: String,
+    },
+    /// How the backend adjusted the request, e.g. truncating a long selection
+    Notice(String),
+    Proofread(ProofreadList),
 }
 
 #[derive(Clone)]
 struct PanelMessage {
     chat: AiChatMessage,
     selection: Option<AiSelectionContext>,
+    /// Show the reply as typed instead of rendering its markdown
+    show_raw: bool,
+    /// Every reply generated for this turn, once it has been regenerated;
+    /// `chat.content` holds the one being shown
+    candidates: Vec<String>,
+    candidate_index: usize,
+}
+
+impl PanelMessage {
+    fn new(role: &str, content: String, selection: Option<AiSelectionContext>) -> Self {
+        Self {
+            chat: AiChatMessage {
+                role: role.to_string(),
+                content,
+            },
+            selection,
+            show_raw: false,
+            candidates: Vec::new(),
+            candidate_index: 0,
+        }
+    }
+
+    /// Keep the current reply and show `content` as the newest candidate
+    fn add_candidate(&mut self, content: String) {
+        if self.candidates.is_empty() {
+            self.candidates.push(self.chat.content.clone());
+        }
+        self.candidates.push(content);
+        self.show_candidate(self.candidates.len() - 1);
+    }
+
+    fn show_candidate(&mut self, index: usize) {
+        if let Some(content) = self.candidates.get(index) {
+            self.chat.content = content.clone();
+            self.candidate_index = index;
+        }
+    }
 }
 
 #[derive(Clone)]
 struct PendingRequest {
     conversation: Vec<AiChatMessage>,
     selection: Option<AiSelectionContext>,
+    /// A proofreading pass rather than a chat turn
+    proofread: bool,
 }
 
 struct PanelError {
@@ -77,6 +141,24 @@ enum EditStatus {
     Failed(String),
 }
 
+struct ProofreadList {
+    items: Vec<ProofreadItem>,
+    /// Char offset of the proofread selection; matches from here on are
+    /// preferred when replacing
+    search_from: usize,
+}
+
+struct ProofreadItem {
+    issue: ProofreadIssue,
+    status: ProofreadStatus,
+}
+
+enum ProofreadStatus {
+    Pending,
+    Applied,
+    Failed(String),
+}
+
 struct MindmapArtifact {
     title: String,
     mermaid: String,
@@ -90,7 +172,9 @@ struct MindmapNode {
 }
 
 impl AiPanel {
-    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<AiPanelAction> {
+    /// Render the panel. `document` is the current editor text, used to check
+    /// whether a selection-based reply can still replace its selection.
+    pub fn show(&mut self, ui: &mut egui::Ui, document: &str) -> Option<AiPanelAction> {
         let mut action = None;
         let panel_frame = Frame::new()
             .fill(Color32::from_gray(247))
@@ -107,10 +191,31 @@ impl AiPanel {
             let composer_height = requested_composer_height.min(full_rect.height());
             let history_bottom =
                 (full_rect.max.y - composer_height - PANEL_GAP).max(full_rect.min.y);
-            let history_rect = egui::Rect::from_min_max(
+            let narrative_height = if self.narrative_map.expanded {
+                NARRATIVE_HEADER_HEIGHT + NARRATIVE_LIST_HEIGHT
+            } else {
+                NARRATIVE_HEADER_HEIGHT
+            };
+            let narrative_bottom = (full_rect.min.y + narrative_height).min(history_bottom);
+            let narrative_rect = egui::Rect::from_min_max(
                 full_rect.min,
+                egui::pos2(full_rect.max.x, narrative_bottom),
+            );
+            let history_rect = egui::Rect::from_min_max(
+                egui::pos2(
+                    full_rect.min.x,
+                    (narrative_bottom + PANEL_GAP).min(history_bottom),
+                ),
                 egui::pos2(full_rect.max.x, history_bottom),
             );
+
+            let mut narrative_ui = ui.new_child(
+                UiBuilder::new()
+                    .id_salt("ai_panel_narrative_map")
+                    .max_rect(narrative_rect)
+                    .layout(Layout::top_down(Align::Min)),
+            );
+            self.show_narrative_map(&mut narrative_ui, &mut action);
             let composer_rect = egui::Rect::from_min_max(
                 egui::pos2(full_rect.min.x, history_bottom + PANEL_GAP),
                 full_rect.max,
@@ -122,7 +227,7 @@ impl AiPanel {
                     .max_rect(history_rect)
                     .layout(Layout::top_down(Align::Min)),
             );
-            self.show_history(&mut history_ui, &mut action);
+            self.show_history(&mut history_ui, document, &mut action);
 
             let mut composer_ui = ui.new_child(
                 UiBuilder::new()
@@ -140,7 +245,99 @@ impl AiPanel {
         action
     }
 
-    fn show_history(&mut self, ui: &mut egui::Ui, action: &mut Option<AiPanelAction>) {
+    fn show_narrative_map(&mut self, ui: &mut egui::Ui, action: &mut Option<AiPanelAction>) {
+        let map = &mut self.narrative_map;
+        ui.horizontal(|ui| {
+            let arrow = if map.expanded { "▾" } else { "▸" };
+            let title = if map.beats.is_empty() {
+                format!("{} 叙事地图", arrow)
+            } else {
+                format!("{} 叙事地图（{}）", arrow, map.beats.len())
+            };
+            if ui
+                .add(
+                    egui::Label::new(RichText::new(title).size(12.0).strong())
+                        .sense(Sense::click()),
+                )
+                .clicked()
+            {
+                map.expanded = !map.expanded;
+            }
+
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if map.loading {
+                    ui.spinner();
+                } else {
+                    let label = if map.beats.is_empty() {
+                        "生成"
+                    } else {
+                        "重新生成"
+                    };
+                    let button = ui.add_enabled(
+                        self.unavailable_reason.is_none(),
+                        egui::Button::new(RichText::new(label).size(12.0)).small(),
+                    );
+                    let button = match &self.unavailable_reason {
+                        Some(reason) => button.on_disabled_hover_text(reason),
+                        None => button.on_hover_text("让模型按节拍提炼全文大纲"),
+                    };
+                    if button.clicked() {
+                        *action = Some(AiPanelAction::ExtractNarrativeMap);
+                    }
+                }
+            });
+        });
+
+        if !map.expanded {
+            return;
+        }
+
+        egui::ScrollArea::vertical()
+            .id_salt("ai_panel_narrative_scroll")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                if let Some(error) = &map.error {
+                    ui.label(
+                        RichText::new(error)
+                            .size(12.0)
+                            .color(Color32::from_rgb(180, 70, 60)),
+                    );
+                }
+                if map.beats.is_empty() && !map.loading && map.error.is_none() {
+                    ui.label(
+                        RichText::new("还没有叙事地图。生成后点击节拍可跳到正文对应位置。")
+                            .size(12.0)
+                            .color(Color32::from_gray(112)),
+                    );
+                }
+                for (index, beat) in map.beats.iter().enumerate() {
+                    let summary = beat
+                        .split(NARRATIVE_BEAT_SEPARATOR)
+                        .next()
+                        .unwrap_or(beat)
+                        .trim();
+                    let response = ui
+                        .add(
+                            egui::Label::new(
+                                RichText::new(format!("{}. {}", index + 1, summary)).size(12.5),
+                            )
+                            .sense(Sense::click())
+                            .wrap(),
+                        )
+                        .on_hover_text(beat);
+                    if response.clicked() {
+                        *action = Some(AiPanelAction::JumpToBeat { beat: beat.clone() });
+                    }
+                }
+            });
+    }
+
+    fn show_history(
+        &mut self,
+        ui: &mut egui::Ui,
+        document: &str,
+        action: &mut Option<AiPanelAction>,
+    ) {
         egui::ScrollArea::vertical()
             .id_salt("ai_panel_history_scroll")
             .auto_shrink([false, false])
@@ -166,10 +363,21 @@ impl AiPanel {
                     );
                 }
 
+                let regenerable = self.regenerable_entry();
+                let mut regenerate = None;
                 for (index, entry) in self.entries.iter_mut().enumerate() {
                     ui.add_space(6.0);
                     match entry {
-                        AiPanelEntry::Message(message) => show_message(ui, message),
+                        AiPanelEntry::Message(message) => {
+                            let can_regenerate = regenerable == Some(index);
+                            match show_message(ui, message, document, can_regenerate) {
+                                Some(AiPanelAction::Regenerate) => regenerate = Some(index),
+                                Some(message_action) if action.is_none() => {
+                                    *action = Some(message_action);
+                                }
+                                _ => {}
+                            }
+                        }
                         AiPanelEntry::EditProposal(proposal) => {
                             let is_active = self.active_edit_proposal == Some(index);
                             if action.is_none() {
@@ -182,9 +390,33 @@ impl AiPanel {
                         AiPanelEntry::ToolError { name, reason } => {
                             show_tool_error(ui, name, reason)
                         }
+                        AiPanelEntry::Notice(notice) => {
+                            ui.label(
+                                RichText::new(format!("ⓘ {}", notice))
+                                    .size(11.0)
+                                    .italics()
+                                    .color(Color32::from_gray(120)),
+                            );
+                        }
+                        AiPanelEntry::Proofread(list) => {
+                            if let Some(proofread_action) =
+                                show_proofread(ui, index, list, document)
+                                && action.is_none()
+                            {
+                                *action = Some(proofread_action);
+                            }
+                        }
                     }
                 }
 
+                if let Some(index) = regenerate
+                    && action.is_none()
+                {
+                    self.regenerate_target = Some(index);
+                    self.last_error = None;
+                    *action = Some(AiPanelAction::Regenerate);
+                }
+
                 if !self.partial_response.trim().is_empty() {
                     ui.add_space(6.0);
                     show_streaming_message(ui, &self.partial_response);
@@ -193,14 +425,8 @@ impl AiPanel {
                 if let Some(error) = &self.last_error {
                     ui.add_space(8.0);
                     let should_retry = show_request_error(ui, error);
-                    if should_retry
-                        && action.is_none()
-                        && let Some(last_request) = &self.last_request
-                    {
-                        *action = Some(AiPanelAction::SendRequest {
-                            conversation: last_request.conversation.clone(),
-                            selection: last_request.selection.clone(),
-                        });
+                    if should_retry && action.is_none() {
+                        *action = self.retry_action();
                     }
                 }
 
@@ -243,10 +469,13 @@ impl AiPanel {
                 .show(ui, |ui| {
                     ui.horizontal(|ui| {
                         ui.label(
-                            RichText::new("当前选区")
-                                .size(9.0)
-                                .strong()
-                                .color(Color32::from_rgb(64, 72, 61)),
+                            RichText::new(format!(
+                                "对选中的 {} 字进行处理",
+                                selection.text.chars().count()
+                            ))
+                            .size(9.0)
+                            .strong()
+                            .color(Color32::from_rgb(64, 72, 61)),
                         );
                         ui.label(
                             RichText::new(preview_inline(&selection.text, 36))
@@ -279,6 +508,14 @@ impl AiPanel {
                         .size(9.0)
                         .color(Color32::from_gray(145)),
                 );
+                if let Some(summary) = &self.usage_summary {
+                    ui.label(
+                        RichText::new(summary)
+                            .size(9.0)
+                            .color(Color32::from_gray(125)),
+                    )
+                    .on_hover_text("设置 → 用量与费用 中可查看历史");
+                }
             });
         });
 
@@ -289,10 +526,12 @@ impl AiPanel {
         let input_response = ui.add_sized([ui.available_width(), 48.0], input);
         let shortcut_pressed = input_response.has_focus()
             && !self.is_processing
+            && self.unavailable_reason.is_none()
             && ui.input(|input| input.modifiers.command && input.key_pressed(egui::Key::Enter));
 
         let mut should_send = shortcut_pressed;
         let mut should_stop = false;
+        let mut should_proofread = false;
         ui.horizontal(|ui| {
             if self.is_processing {
                 if ui
@@ -302,6 +541,9 @@ impl AiPanel {
                 {
                     should_stop = true;
                 }
+            } else if let Some(reason) = &self.unavailable_reason {
+                ui.add_enabled(false, egui::Button::new(RichText::new("发送").size(11.0)))
+                    .on_disabled_hover_text(reason);
             } else if ui.button(RichText::new("发送").size(11.0)).clicked() {
                 should_send = true;
             }
@@ -314,20 +556,48 @@ impl AiPanel {
                 )
                 .clicked()
             {
-                self.draft_message.clear();
-                self.entries.clear();
-                self.request_snapshot = None;
-                self.active_edit_proposal = None;
-                self.composer_selection = None;
-                self.last_request = None;
-                self.last_error = None;
-                self.partial_response.clear();
+                self.clear_conversation();
+            }
+
+            if !self.is_processing
+                && ui
+                    .add_enabled(
+                        self.unavailable_reason.is_none(),
+                        egui::Button::new(RichText::new("校对").size(11.0)),
+                    )
+                    .on_hover_text("逐条列出选区（或全文）中的错字、语病和标点问题")
+                    .clicked()
+            {
+                should_proofread = true;
+            }
+
+            if !self.prompt_templates.is_empty() && !self.is_processing {
+                let mut picked = None;
+                egui::ComboBox::from_id_salt("ai_prompt_template")
+                    .selected_text(RichText::new("模板").size(11.0))
+                    .width(64.0)
+                    .show_ui(ui, |ui| {
+                        for (index, template) in self.prompt_templates.iter().enumerate() {
+                            if ui.selectable_label(false, &template.name).clicked() {
+                                picked = Some(index);
+                            }
+                        }
+                    });
+                if let Some(index) = picked {
+                    let text = self
+                        .composer_selection
+                        .as_ref()
+                        .map_or(PROMPT_WHOLE_DOCUMENT, |selection| selection.text.as_str());
+                    self.draft_message = self.prompt_templates[index].render(text);
+                }
             }
         });
 
         if should_stop {
             self.active_request_id
                 .map(|request_id| AiPanelAction::CancelRequest { request_id })
+        } else if should_proofread {
+            Some(self.queue_proofread(self.composer_selection.clone()))
         } else if should_send && !self.is_processing {
             let user_message = if self.draft_message.trim().is_empty() {
                 if self.composer_selection.is_some() {
@@ -350,17 +620,24 @@ impl AiPanel {
         content: String,
         selection: Option<AiSelectionContext>,
     ) -> AiPanelAction {
-        self.entries.push(AiPanelEntry::Message(PanelMessage {
-            chat: AiChatMessage {
-                role: "user".to_string(),
-                content,
-            },
-            selection: selection.clone(),
-        }));
+        // A new prompt settles on whichever candidate is being shown
+        for entry in &mut self.entries {
+            if let AiPanelEntry::Message(message) = entry {
+                message.candidates.clear();
+                message.candidate_index = 0;
+            }
+        }
+        self.regenerate_target = None;
+        self.entries.push(AiPanelEntry::Message(PanelMessage::new(
+            "user",
+            content,
+            selection.clone(),
+        )));
         let conversation = self.conversation_for(selection.as_ref());
         self.last_request = Some(PendingRequest {
             conversation: conversation.clone(),
             selection: selection.clone(),
+            proofread: false,
         });
         self.last_error = None;
         AiPanelAction::SendRequest {
@@ -369,6 +646,21 @@ impl AiPanel {
         }
     }
 
+    fn queue_proofread(&mut self, selection: Option<AiSelectionContext>) -> AiPanelAction {
+        let notice = match &selection {
+            Some(selection) => format!("校对选中的 {} 字", selection.text.chars().count()),
+            None => "校对全文".to_string(),
+        };
+        self.entries.push(AiPanelEntry::Notice(notice));
+        self.last_request = Some(PendingRequest {
+            conversation: Vec::new(),
+            selection: selection.clone(),
+            proofread: true,
+        });
+        self.last_error = None;
+        AiPanelAction::Proofread { selection }
+    }
+
     fn conversation_for(&self, selection: Option<&AiSelectionContext>) -> Vec<AiChatMessage> {
         self.entries
             .iter()
@@ -383,6 +675,62 @@ impl AiPanel {
             .collect()
     }
 
+    /// Drop the conversation and the draft, e.g. when the document is closed
+    pub fn clear_conversation(&mut self) {
+        self.draft_message.clear();
+        self.entries.clear();
+        self.regenerate_target = None;
+        self.request_snapshot = None;
+        self.active_edit_proposal = None;
+        self.composer_selection = None;
+        self.last_request = None;
+        self.last_error = None;
+        self.partial_response.clear();
+    }
+
+    /// Disable sending while the AI backend is misconfigured, with `reason`
+    /// shown as the explanation.
+    pub fn set_unavailable_reason(&mut self, reason: Option<String>) {
+        self.unavailable_reason = reason;
+    }
+
+    pub fn set_usage_summary(&mut self, summary: Option<String>) {
+        self.usage_summary = summary;
+    }
+
+    pub fn set_prompt_templates(&mut self, templates: Vec<PromptTemplate>) {
+        self.prompt_templates = templates;
+    }
+
+    /// Show the stored narrative map of a newly opened file (or none).
+    pub fn set_narrative_map(&mut self, beats: Option<Vec<String>>) {
+        self.narrative_map = NarrativeMapState {
+            expanded: self.narrative_map.expanded,
+            beats: beats.unwrap_or_default(),
+            ..Default::default()
+        };
+    }
+
+    pub fn begin_narrative_map(&mut self) {
+        self.narrative_map.loading = true;
+        self.narrative_map.expanded = true;
+        self.narrative_map.error = None;
+    }
+
+    /// Apply an extraction result; a failure keeps the previous beats.
+    pub fn set_narrative_map_result(&mut self, result: Result<Vec<String>, AiError>) {
+        self.narrative_map.loading = false;
+        match result {
+            Ok(beats) => self.narrative_map.beats = beats,
+            Err(AiError::Cancelled) => {}
+            Err(error) => self.narrative_map.error = Some(error.to_string()),
+        }
+    }
+
+    pub fn unavailable_reason(&self) -> Option<&str> {
+        self.unavailable_reason.as_deref()
+    }
+
     pub fn attach_selection(&mut self, selection: AiSelectionContext) {
         self.composer_selection = Some(selection);
     }
@@ -503,9 +851,16 @@ impl AiPanel {
                 self.searched_chunks = searched_chunks;
                 self.read_chunks = read_chunks;
             }
-            AiProgressEvent::Retrying { attempt, reason } => {
-                self.progress_stage = format!("第 {} 次重试：{}", attempt, reason);
+            AiProgressEvent::Retrying {
+                attempt,
+                max_attempts,
+                reason,
+            } => {
+                self.progress_stage = format!("重试中 ({}/{})…{}", attempt, max_attempts, reason);
             }
+            AiProgressEvent::Notice(notice) => self.entries.push(AiPanelEntry::Notice(notice)),
+            // Recorded by the app
+            AiProgressEvent::Usage { .. } => {}
         }
     }
 
@@ -554,34 +909,134 @@ impl AiPanel {
             }
         }
         if !content.trim().is_empty() {
-            self.entries.push(AiPanelEntry::Message(PanelMessage {
-                chat: AiChatMessage {
-                    role: "assistant".to_string(),
-                    content,
-                },
-                selection: response_selection,
-            }));
+            self.push_assistant_reply(content, response_selection);
+        }
+        self.regenerate_target = None;
+        self.is_processing = false;
+        self.active_request_id = None;
+        self.progress_stage.clear();
+        self.partial_response.clear();
+        self.last_error = None;
+    }
+
+    /// Show the issues a proofreading pass found, or its error.
+    pub fn set_proofread_result(
+        &mut self,
+        request_id: AiRequestId,
+        result: Result<Vec<ProofreadIssue>, AiError>,
+    ) {
+        if self.active_request_id != Some(request_id) {
+            return;
         }
+        let issues = match result {
+            Ok(issues) => issues,
+            Err(error) => {
+                self.set_error(request_id, error);
+                return;
+            }
+        };
+        let search_from = self
+            .request_selection
+            .take()
+            .map_or(0, |selection| selection.start_char);
+        self.entries.push(AiPanelEntry::Proofread(ProofreadList {
+            items: issues
+                .into_iter()
+                .map(|issue| ProofreadItem {
+                    issue,
+                    status: ProofreadStatus::Pending,
+                })
+                .collect(),
+            search_from,
+        }));
         self.is_processing = false;
+        self.request_snapshot = None;
         self.active_request_id = None;
         self.progress_stage.clear();
         self.partial_response.clear();
         self.last_error = None;
     }
 
+    pub fn set_proofread_item_result(
+        &mut self,
+        entry_index: usize,
+        issue_index: usize,
+        result: Result<(), String>,
+    ) {
+        let Some(AiPanelEntry::Proofread(list)) = self.entries.get_mut(entry_index) else {
+            return;
+        };
+        if let Some(item) = list.items.get_mut(issue_index) {
+            item.status = match result {
+                Ok(()) => ProofreadStatus::Applied,
+                Err(error) => ProofreadStatus::Failed(error),
+            };
+        }
+    }
+
+    /// Add a reply, as a new candidate when it was regenerated
+    fn push_assistant_reply(&mut self, content: String, selection: Option<AiSelectionContext>) {
+        if let Some(AiPanelEntry::Message(message)) = self
+            .regenerate_target
+            .and_then(|index| self.entries.get_mut(index))
+        {
+            message.add_candidate(content);
+        } else {
+            self.entries.push(AiPanelEntry::Message(PanelMessage::new(
+                "assistant",
+                content,
+                selection,
+            )));
+        }
+    }
+
+    /// The request "重试请求" sends again after a failure
+    fn retry_action(&self) -> Option<AiPanelAction> {
+        let last_request = self.last_request.as_ref()?;
+        Some(if last_request.proofread {
+            AiPanelAction::Proofread {
+                selection: last_request.selection.clone(),
+            }
+        } else {
+            AiPanelAction::SendRequest {
+                conversation: last_request.conversation.clone(),
+                selection: last_request.selection.clone(),
+            }
+        })
+    }
+
+    /// Index of the reply that "重新生成" would replace: the last assistant
+    /// message, provided the last request was a chat turn that has finished
+    fn regenerable_entry(&self) -> Option<usize> {
+        if self.is_processing
+            || self
+                .last_request
+                .as_ref()
+                .is_none_or(|request| request.proofread)
+        {
+            return None;
+        }
+        let (index, message) =
+            self.entries
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(index, entry)| match entry {
+                    AiPanelEntry::Message(message) => Some((index, message)),
+                    _ => None,
+                })?;
+        (message.chat.role == "assistant").then_some(index)
+    }
+
     pub fn set_error(&mut self, request_id: AiRequestId, error: AiError) {
         if self.active_request_id != Some(request_id) {
             return;
         }
         if !self.partial_response.trim().is_empty() {
-            self.entries.push(AiPanelEntry::Message(PanelMessage {
-                chat: AiChatMessage {
-                    role: "assistant".to_string(),
-                    content: std::mem::take(&mut self.partial_response),
-                },
-                selection: self.request_selection.clone(),
-            }));
+            let partial = std::mem::take(&mut self.partial_response);
+            self.push_assistant_reply(partial, self.request_selection.clone());
         }
+        self.regenerate_target = None;
         if !matches!(error, AiError::Cancelled) {
             self.last_error = Some(PanelError {
                 message: error.to_string(),
@@ -600,14 +1055,10 @@ impl AiPanel {
             return;
         }
         if !self.partial_response.trim().is_empty() {
-            self.entries.push(AiPanelEntry::Message(PanelMessage {
-                chat: AiChatMessage {
-                    role: "assistant".to_string(),
-                    content: std::mem::take(&mut self.partial_response),
-                },
-                selection: self.request_selection.clone(),
-            }));
+            let partial = std::mem::take(&mut self.partial_response);
+            self.push_assistant_reply(partial, self.request_selection.clone());
         }
+        self.regenerate_target = None;
         self.is_processing = false;
         self.request_snapshot = None;
         self.request_selection = None;
@@ -805,7 +1256,7 @@ fn preview_inline(text: &str, limit: usize) -> String {
     let mut char_count = 0;
     let mut last_was_whitespace = true;
 
-    while let Some(c) = chars.next() {
+    for c in chars.by_ref() {
         if c.is_whitespace() {
             if last_was_whitespace || char_count == 0 {
                 last_was_whitespace = true;
@@ -831,19 +1282,45 @@ fn preview_inline(text: &str, limit: usize) -> String {
     }
 }
 
-fn show_message(ui: &mut egui::Ui, message: &PanelMessage) {
+fn show_message(
+    ui: &mut egui::Ui,
+    message: &mut PanelMessage,
+    document: &str,
+    can_regenerate: bool,
+) -> Option<AiPanelAction> {
     let is_user = message.chat.role == "user";
-    ui.label(
-        RichText::new(match (is_user, message.selection.is_some()) {
-            (true, true) => "你 · 当前选区",
-            (false, true) => "AI · 当前选区",
-            (true, false) => "你",
-            (false, false) => "AI",
-        })
-        .size(10.0)
-        .strong()
-        .color(Color32::from_gray(if is_user { 72 } else { 120 })),
-    );
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new(match (is_user, message.selection.is_some()) {
+                (true, true) => "你 · 当前选区",
+                (false, true) => "AI · 当前选区",
+                (true, false) => "你",
+                (false, false) => "AI",
+            })
+            .size(10.0)
+            .strong()
+            .color(Color32::from_gray(if is_user { 72 } else { 120 })),
+        );
+        if !is_user {
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                let label = if message.show_raw { "排版" } else { "原文" };
+                if ui
+                    .add(
+                        egui::Label::new(
+                            RichText::new(label)
+                                .size(10.0)
+                                .color(Color32::from_gray(130)),
+                        )
+                        .sense(Sense::click()),
+                    )
+                    .on_hover_text("切换 Markdown 排版 / 原始文本")
+                    .clicked()
+                {
+                    message.show_raw = !message.show_raw;
+                }
+            });
+        }
+    });
     Frame::new()
         .fill(if is_user {
             Color32::from_gray(238)
@@ -854,12 +1331,103 @@ fn show_message(ui: &mut egui::Ui, message: &PanelMessage) {
         .inner_margin(egui::Margin::same(7))
         .show(ui, |ui| {
             ui.set_width(ui.available_width());
+            if is_user || message.show_raw {
+                ui.label(
+                    RichText::new(&message.chat.content)
+                        .size(12.0)
+                        .color(Color32::from_gray(48)),
+                );
+            } else {
+                render_markdown(ui, &message.chat.content, 12.0, Color32::from_gray(48));
+            }
+        });
+    if is_user || message.chat.content.trim().is_empty() {
+        return None;
+    }
+
+    let mut action = None;
+    if message.candidates.len() > 1 {
+        ui.horizontal(|ui| {
+            let index = message.candidate_index;
+            if ui
+                .add_enabled(index > 0, egui::Button::new("◀").small())
+                .on_hover_text("上一个回复")
+                .clicked()
+            {
+                message.show_candidate(index - 1);
+            }
             ui.label(
-                RichText::new(&message.chat.content)
-                    .size(12.0)
-                    .color(Color32::from_gray(48)),
+                RichText::new(format!("{}/{}", index + 1, message.candidates.len()))
+                    .size(10.0)
+                    .color(Color32::from_gray(110)),
             );
+            if ui
+                .add_enabled(
+                    index + 1 < message.candidates.len(),
+                    egui::Button::new("▶").small(),
+                )
+                .on_hover_text("下一个回复")
+                .clicked()
+            {
+                message.show_candidate(index + 1);
+            }
         });
+    }
+    ui.horizontal_wrapped(|ui| {
+        if ui
+            .small_button("插入到光标处")
+            .on_hover_text("在正文光标位置插入这段回复")
+            .clicked()
+        {
+            action = Some(AiPanelAction::InsertResponse {
+                text: message.chat.content.trim().to_string(),
+            });
+        }
+        let intact_selection = message
+            .selection
+            .as_ref()
+            .filter(|selection| selection_is_intact(document, selection));
+        let replace = ui
+            .add_enabled(
+                intact_selection.is_some(),
+                egui::Button::new("替换选中内容").small(),
+            )
+            .on_disabled_hover_text(if message.selection.is_some() {
+                "原选区已被修改，无法安全替换"
+            } else {
+                "这条回复不是针对选区的"
+            });
+        if replace.clicked()
+            && let Some(selection) = intact_selection
+        {
+            action = Some(AiPanelAction::ReplaceSelection {
+                selection: selection.clone(),
+                text: message.chat.content.trim().to_string(),
+            });
+        }
+        if ui.small_button("复制").clicked() {
+            ui.ctx().copy_text(message.chat.content.trim().to_string());
+        }
+        if can_regenerate
+            && ui
+                .small_button("重新生成")
+                .on_hover_text("用同一个问题再要一个回复，之前的回复会保留")
+                .clicked()
+        {
+            action = Some(AiPanelAction::Regenerate);
+        }
+    });
+    action
+}
+
+/// Whether `selection` still covers exactly the text it was taken from
+fn selection_is_intact(document: &str, selection: &AiSelectionContext) -> bool {
+    selection.start_char < selection.end_char
+        && document
+            .chars()
+            .skip(selection.start_char)
+            .take(selection.end_char - selection.start_char)
+            .eq(selection.text.chars())
 }
 
 fn show_streaming_message(ui: &mut egui::Ui, content: &str) {
@@ -1178,6 +1746,96 @@ fn elide_label(label: &str, width: f32) -> String {
     }
 }
 
+fn show_proofread(
+    ui: &mut egui::Ui,
+    entry_index: usize,
+    list: &ProofreadList,
+    document: &str,
+) -> Option<AiPanelAction> {
+    let mut action = None;
+    Frame::new()
+        .fill(Color32::from_gray(243))
+        .stroke(egui::Stroke::new(1.0, Color32::from_gray(218)))
+        .corner_radius(6.0)
+        .inner_margin(egui::Margin::same(8))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            if list.items.is_empty() {
+                ui.label(RichText::new("校对完成，没有发现问题").size(11.0).strong());
+                return;
+            }
+            ui.label(
+                RichText::new(format!("校对结果（{} 处）", list.items.len()))
+                    .size(11.0)
+                    .strong(),
+            );
+
+            for (issue_index, item) in list.items.iter().enumerate() {
+                ui.add_space(4.0);
+                ui.separator();
+                let issue = &item.issue;
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(
+                        RichText::new(&issue.original)
+                            .size(12.0)
+                            .strikethrough()
+                            .color(Color32::from_rgb(150, 70, 62)),
+                    );
+                    ui.label(RichText::new("→").size(12.0).color(Color32::from_gray(120)));
+                    let suggestion = if issue.suggestion.is_empty() {
+                        RichText::new("（删除）").italics()
+                    } else {
+                        RichText::new(&issue.suggestion)
+                    };
+                    ui.label(suggestion.size(12.0).color(Color32::from_rgb(48, 104, 62)));
+                });
+                if !issue.reason.is_empty() {
+                    ui.label(
+                        RichText::new(&issue.reason)
+                            .size(10.0)
+                            .color(Color32::from_gray(104)),
+                    );
+                }
+
+                match &item.status {
+                    ProofreadStatus::Pending if document.contains(&issue.original) => {
+                        if ui
+                            .push_id(issue_index, |ui| ui.small_button("替换"))
+                            .inner
+                            .clicked()
+                        {
+                            action = Some(AiPanelAction::ApplyProofread {
+                                entry_index,
+                                issue_index,
+                                original: issue.original.clone(),
+                                suggestion: issue.suggestion.clone(),
+                                search_from: list.search_from,
+                            });
+                        }
+                    }
+                    ProofreadStatus::Pending => {
+                        ui.label(
+                            RichText::new("原文已不存在，已跳过")
+                                .size(10.0)
+                                .color(Color32::from_gray(120)),
+                        );
+                    }
+                    ProofreadStatus::Applied => {
+                        ui.label(RichText::new("已替换").size(10.0).strong());
+                    }
+                    ProofreadStatus::Failed(error) => {
+                        ui.label(
+                            RichText::new(format!("未替换：{}", error))
+                                .size(10.0)
+                                .color(Color32::from_rgb(136, 58, 58)),
+                        );
+                    }
+                }
+            }
+        });
+    action
+}
+
 fn show_tool_error(ui: &mut egui::Ui, name: &str, reason: &str) {
     Frame::new()
         .fill(Color32::from_gray(243))
@@ -1249,12 +1907,57 @@ pub enum AiPanelAction {
     },
     ApplyAllEdits,
     RejectAllEdits,
+    /// Insert a reply at the editor caret.
+    InsertResponse {
+        text: String,
+    },
+    /// Extract a narrative map for the whole document.
+    ExtractNarrativeMap,
+    /// Move the editor to the passage a narrative beat refers to.
+    JumpToBeat {
+        beat: String,
+    },
+    /// Proofread the selection, or the whole document without one.
+    Proofread {
+        selection: Option<AiSelectionContext>,
+    },
+    /// Swap one proofreading issue's original text for its suggestion.
+    ApplyProofread {
+        entry_index: usize,
+        issue_index: usize,
+        original: String,
+        suggestion: String,
+        search_from: usize,
+    },
+    /// Re-send the last chat request; the reply becomes another candidate.
+    Regenerate,
+    /// Replace the selection a reply was about, if it is unchanged.
+    ReplaceSelection {
+        selection: AiSelectionContext,
+        text: String,
+    },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn selection_is_intact_compares_original_text() {
+        let selection = AiSelectionContext {
+            anchor_id: 1,
+            start_char: 2,
+            end_char: 4,
+            text: "中间".to_string(),
+            context_before: String::new(),
+            context_after: String::new(),
+        };
+
+        assert!(selection_is_intact("开头中间结尾", &selection));
+        assert!(!selection_is_intact("开头中问结尾", &selection));
+        assert!(!selection_is_intact("开头", &selection));
+    }
+
     #[test]
     fn parses_mermaid_mindmap_indentation_and_shapes() {
         let nodes = parse_mindmap(
@@ -1340,12 +2043,16 @@ mod tests {
             start_char: 0,
             end_char: 2,
             text: "第一".to_string(),
+            context_before: String::new(),
+            context_after: String::new(),
         };
         let second = AiSelectionContext {
             anchor_id: 2,
             start_char: 3,
             end_char: 5,
             text: "第二".to_string(),
+            context_before: String::new(),
+            context_after: String::new(),
         };
         let first_action = panel
             .send_selection_message("谈第一处".to_string(), first.clone())
@@ -1365,4 +2072,69 @@ mod tests {
                 if conversation.len() == 1 && conversation[0].content == "谈第二处"
         ));
     }
+
+    #[test]
+    fn regenerated_replies_become_candidates_until_the_next_prompt() {
+        let reply = |content: &str| AiAgentResponse {
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+        };
+        let mut panel = AiPanel::default();
+        panel.queue_user_message("起个标题".to_string(), None);
+        panel.begin_request(1, String::new(), None);
+        panel.set_response(1, reply("标题甲"));
+        assert_eq!(panel.regenerable_entry(), Some(1));
+
+        panel.regenerate_target = Some(1);
+        panel.begin_request(2, String::new(), None);
+        assert_eq!(panel.regenerable_entry(), None);
+        panel.set_response(2, reply("标题乙"));
+
+        assert_eq!(panel.entries.len(), 2);
+        let AiPanelEntry::Message(message) = &mut panel.entries[1] else {
+            panic!("expected the assistant reply");
+        };
+        assert_eq!(message.candidates, ["标题甲", "标题乙"]);
+        assert_eq!(message.chat.content, "标题乙");
+        message.show_candidate(0);
+
+        // The shown candidate is what the conversation continues from
+        let AiPanelAction::SendRequest { conversation, .. } =
+            panel.queue_user_message("再短一点".to_string(), None)
+        else {
+            panic!("expected a chat request");
+        };
+        assert_eq!(conversation[1].content, "标题甲");
+        let AiPanelEntry::Message(message) = &panel.entries[1] else {
+            panic!("expected the assistant reply");
+        };
+        assert!(message.candidates.is_empty());
+        assert_eq!(panel.regenerable_entry(), None);
+    }
+
+    #[test]
+    fn failed_requests_are_shown_as_errors_not_replies() {
+        let mut panel = AiPanel::default();
+        panel.queue_user_message("总结一下".to_string(), None);
+        panel.begin_request(1, String::new(), None);
+        panel.set_error(1, AiError::ApiError("模型服务不可用".to_string()));
+
+        // Nothing is added to the conversation, so nothing can be inserted
+        assert_eq!(panel.entries.len(), 1);
+        assert_eq!(panel.regenerable_entry(), None);
+        let error = panel.last_error.as_ref().unwrap();
+        assert!(error.retryable);
+        assert!(error.message.contains("模型服务不可用"));
+        assert!(matches!(
+            panel.retry_action(),
+            Some(AiPanelAction::SendRequest { conversation, .. })
+                if conversation.len() == 1 && conversation[0].content == "总结一下"
+        ));
+
+        // Stopping a request is not an error
+        panel.queue_user_message("再试一次".to_string(), None);
+        panel.begin_request(2, String::new(), None);
+        panel.set_error(2, AiError::Cancelled);
+        assert!(panel.last_error.is_none());
+    }
 }
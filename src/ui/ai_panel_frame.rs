@@ -0,0 +1,145 @@
+//! Placement of the AI panel inside the main window.
+//!
+//! The panel is either docked to the right edge or floats as an `egui::Window`
+//! kept inside the main viewport; in both modes it can collapse to a small
+//! pill. Geometry is read from and written back to [`AiPanelLayout`] so the
+//! app can persist it.
+
+use crate::config::{AI_PANEL_MIN_HEIGHT, AI_PANEL_WIDTH_RANGE, AiPanelLayout};
+use crate::ui::window_frame::WindowFrame;
+use egui::{Align, Align2, Color32, Context, Layout, RichText, Sense, Ui};
+
+/// Gap between a newly placed floating panel and the window edge
+const FLOATING_MARGIN: f32 = 16.0;
+/// Roughly the custom title bar's height
+const FLOATING_TOP_OFFSET: f32 = 48.0;
+
+/// Renders the panel chrome around `add_contents` according to `layout`.
+///
+/// Returns true when the layout changed (moved, resized, docked, collapsed).
+pub fn show_ai_panel_frame(
+    ctx: &Context,
+    window_frame: &WindowFrame,
+    layout: &mut AiPanelLayout,
+    is_processing: bool,
+    add_contents: impl FnOnce(&mut Ui),
+) -> bool {
+    let before = *layout;
+
+    if layout.collapsed {
+        show_pill(ctx, layout, is_processing);
+    } else if layout.floating {
+        show_floating(ctx, layout, add_contents);
+    } else {
+        show_docked(ctx, window_frame, layout, add_contents);
+    }
+
+    *layout != before
+}
+
+fn show_docked(
+    ctx: &Context,
+    window_frame: &WindowFrame,
+    layout: &mut AiPanelLayout,
+    add_contents: impl FnOnce(&mut Ui),
+) {
+    let response = egui::SidePanel::right("ai_panel_side")
+        .frame(window_frame.panel(ctx, egui::Frame::side_top_panel(&ctx.style())))
+        .default_width(layout.docked_width())
+        .min_width(*AI_PANEL_WIDTH_RANGE.start())
+        .max_width(*AI_PANEL_WIDTH_RANGE.end())
+        .resizable(true)
+        .show(ctx, |ui| {
+            show_header(ui, layout);
+            add_contents(ui);
+        });
+
+    let width = response.response.rect.width().round();
+    if !layout.collapsed && !layout.floating && (width - layout.docked_width()).abs() >= 1.0 {
+        layout.set_docked_width(width);
+    }
+}
+
+fn show_floating(ctx: &Context, layout: &mut AiPanelLayout, add_contents: impl FnOnce(&mut Ui)) {
+    let screen = ctx.content_rect();
+    let default_pos = layout.position.unwrap_or([
+        screen.max.x - layout.size[0] - FLOATING_MARGIN,
+        screen.min.y + FLOATING_TOP_OFFSET,
+    ]);
+
+    let response = egui::Window::new("AI 助手")
+        .id(egui::Id::new("ai_panel_window"))
+        .title_bar(false)
+        .resizable(true)
+        .constrain(true)
+        .min_width(*AI_PANEL_WIDTH_RANGE.start())
+        .min_height(AI_PANEL_MIN_HEIGHT)
+        .default_pos(default_pos)
+        .default_size(layout.size)
+        .show(ctx, |ui| {
+            show_header(ui, layout);
+            add_contents(ui);
+        });
+
+    if let Some(response) = response
+        && !layout.collapsed
+        && layout.floating
+    {
+        let rect = response.response.rect;
+        layout.position = Some([rect.min.x.round(), rect.min.y.round()]);
+        layout.size = [rect.width().round(), rect.height().round()];
+    }
+}
+
+fn show_pill(ctx: &Context, layout: &mut AiPanelLayout, is_processing: bool) {
+    let area = egui::Area::new(egui::Id::new("ai_panel_pill")).constrain(true);
+    let area = match (layout.floating, layout.position) {
+        (true, Some(position)) => area.current_pos(position),
+        _ => area.anchor(Align2::RIGHT_TOP, [-FLOATING_MARGIN, FLOATING_TOP_OFFSET]),
+    };
+
+    area.show(ctx, |ui| {
+        let response = egui::Frame::new()
+            .fill(Color32::from_gray(247))
+            .stroke(egui::Stroke::new(1.0, Color32::from_gray(210)))
+            .corner_radius(14.0)
+            .inner_margin(egui::Margin::symmetric(10, 4))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    if is_processing {
+                        ui.spinner();
+                    }
+                    ui.label(RichText::new("AI").strong().color(Color32::from_gray(70)));
+                });
+            })
+            .response
+            .interact(Sense::click())
+            .on_hover_text("展开 AI 助手");
+        if response.clicked() {
+            layout.collapsed = false;
+        }
+    });
+}
+
+fn show_header(ui: &mut Ui, layout: &mut AiPanelLayout) {
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new("AI 助手")
+                .size(12.0)
+                .color(Color32::from_gray(90)),
+        );
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            if ui.small_button("—").on_hover_text("收起为小标签").clicked() {
+                layout.collapsed = true;
+            }
+            let (icon, hint) = if layout.floating {
+                ("⇥", "停靠到右侧")
+            } else {
+                ("🗗", "浮动窗口")
+            };
+            if ui.small_button(icon).on_hover_text(hint).clicked() {
+                layout.floating = !layout.floating;
+            }
+        });
+    });
+}
@@ -0,0 +1,115 @@
+//! Review window for AI rewrites of a selection.
+//!
+//! Shows the original selection and the suggested text as a side-by-side
+//! diff with one checkbox per hunk; only the accepted hunks are applied.
+
+use crate::backend::ai_backend::AiSelectionContext;
+use crate::config::DEFAULT_FONT_SIZE;
+use crate::diff::{DiffRow, compute_diff, group_into_rows, hunk_count, merge_accepted_hunks};
+use crate::ui::history::render_hunk_review;
+use egui::{Context, RichText, ScrollArea};
+
+struct PendingReview {
+    selection: AiSelectionContext,
+    rows: Vec<DiffRow>,
+    accepted: Vec<bool>,
+}
+
+pub struct AiReviewWindow {
+    pending: Option<PendingReview>,
+    font_size: f32,
+}
+
+impl Default for AiReviewWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AiReviewWindow {
+    pub fn new() -> Self {
+        Self {
+            pending: None,
+            font_size: DEFAULT_FONT_SIZE,
+        }
+    }
+
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.font_size = font_size;
+    }
+
+    /// Opens the window comparing `selection` with `suggestion`; every hunk
+    /// starts out accepted.
+    pub fn open(&mut self, selection: AiSelectionContext, suggestion: &str) {
+        let rows = group_into_rows(&compute_diff(&selection.text, suggestion));
+        let accepted = vec![true; hunk_count(&rows)];
+        self.pending = Some(PendingReview {
+            selection,
+            rows,
+            accepted,
+        });
+    }
+
+    /// Renders the window; returns the selection and its merged replacement
+    /// when the user applies the review.
+    pub fn show(&mut self, ctx: &Context) -> Option<(AiSelectionContext, String)> {
+        let font_size = self.font_size;
+        let review = self.pending.as_mut()?;
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        egui::Window::new("审阅 AI 修改")
+            .open(&mut open)
+            .collapsible(false)
+            .default_size([720.0, 480.0])
+            .show(ctx, |ui| {
+                let total = review.accepted.len();
+                let chosen = review.accepted.iter().filter(|a| **a).count();
+                ui.horizontal(|ui| {
+                    ui.label(format!("已采纳 {} / {} 处修改", chosen, total));
+                    if ui.small_button("全选").clicked() {
+                        review.accepted.iter_mut().for_each(|a| *a = true);
+                    }
+                    if ui.small_button("全不选").clicked() {
+                        review.accepted.iter_mut().for_each(|a| *a = false);
+                    }
+                });
+                ui.separator();
+
+                ScrollArea::vertical()
+                    .max_height(ui.available_height() - 40.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        if total == 0 {
+                            ui.label(RichText::new("建议内容与原文相同").weak());
+                        }
+                        render_hunk_review(ui, &review.rows, &mut review.accepted, font_size);
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(chosen > 0, egui::Button::new("应用所选修改"))
+                        .clicked()
+                    {
+                        apply = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+
+        if apply {
+            let review = self.pending.take()?;
+            let merged =
+                merge_accepted_hunks(&review.selection.text, &review.rows, &review.accepted);
+            return Some((review.selection, merged));
+        }
+        if cancel || !open {
+            self.pending = None;
+        }
+        None
+    }
+}
@@ -0,0 +1,173 @@
+//! The 所有批注 window: every document with marks, each expandable to its
+//! notes, which open the document at the marked line. Marks left behind by
+//! documents no history knows can be deleted from here all at once.
+//!
+//! The list is loaded in the background; the window shows a spinner until
+//! [`AllMarksWindow::set_files`] is called.
+
+use crate::backend::marked_files::MarkedFile;
+use crate::datetime::format_local;
+use crate::ui::title_bar::document_label;
+use chrono::{DateTime, Local};
+use egui::{Context, RichText};
+use std::path::PathBuf;
+
+pub enum AllMarksAction {
+    /// Open the document at `path` and go to `line`
+    Open { path: PathBuf, line: usize },
+    /// Delete the marks of these UUIDs, which belong to no document
+    DeleteOrphans(Vec<String>),
+}
+
+#[derive(Default)]
+pub struct AllMarksWindow {
+    open: bool,
+    files: Option<Vec<MarkedFile>>,
+    error: Option<String>,
+}
+
+impl AllMarksWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the window in its loading state
+    pub fn open(&mut self) {
+        self.open = true;
+        self.files = None;
+        self.error = None;
+    }
+
+    pub fn set_files(&mut self, result: Result<Vec<MarkedFile>, String>) {
+        match result {
+            Ok(files) => self.files = Some(files),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Renders the window; returns what the user asked for
+    pub fn show(&mut self, ctx: &Context, datetime_format: &str) -> Option<AllMarksAction> {
+        if !self.open {
+            return None;
+        }
+
+        let mut action = None;
+        let mut open = self.open;
+        egui::Window::new("所有批注")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .default_width(420.0)
+            .default_height(480.0)
+            .show(ctx, |ui| {
+                if let Some(error) = &self.error {
+                    ui.label(RichText::new(format!("读取批注失败：{}", error)).weak());
+                    return;
+                }
+                let Some(files) = &self.files else {
+                    ui.spinner();
+                    return;
+                };
+                let (orphans, documents): (Vec<&MarkedFile>, Vec<&MarkedFile>) =
+                    files.iter().partition(|file| file.orphaned);
+                if documents.is_empty() {
+                    ui.label(RichText::new("还没有批注").weak());
+                }
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, true])
+                    .max_height(ui.available_height() - 40.0)
+                    .show(ui, |ui| {
+                        for file in documents {
+                            Self::document(ui, file, datetime_format, &mut action);
+                        }
+                    });
+
+                if !orphans.is_empty() {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let notes: usize = orphans.iter().map(|file| file.marks.len()).sum();
+                        ui.label(
+                            RichText::new(format!(
+                                "{} 个批注文件找不到对应的文档（共 {} 条批注）",
+                                orphans.len(),
+                                notes
+                            ))
+                            .weak(),
+                        );
+                        if ui
+                            .button("全部删除")
+                            .on_hover_text("这些文档的历史记录已不存在")
+                            .clicked()
+                        {
+                            action = Some(AllMarksAction::DeleteOrphans(
+                                orphans.iter().map(|file| file.uuid.clone()).collect(),
+                            ));
+                        }
+                    });
+                }
+            });
+        self.open = open;
+        action
+    }
+
+    fn document(
+        ui: &mut egui::Ui,
+        file: &MarkedFile,
+        datetime_format: &str,
+        action: &mut Option<AllMarksAction>,
+    ) {
+        let modified = format_local(
+            &DateTime::<Local>::from(file.last_modified),
+            datetime_format,
+        );
+        let header = format!(
+            "{}  ·  {} 条批注",
+            document_label(file.path.as_deref(), false),
+            file.marks.len()
+        );
+        let response = egui::CollapsingHeader::new(header)
+            .id_salt(&file.uuid)
+            .show(ui, |ui| {
+                ui.label(
+                    RichText::new(format!("最后修改：{}", modified))
+                        .small()
+                        .weak(),
+                );
+                for (line, mark) in &file.marks {
+                    let note = if mark.note.trim().is_empty() {
+                        "（无备注）"
+                    } else {
+                        mark.note.as_str()
+                    };
+                    ui.horizontal_wrapped(|ui| {
+                        let label = RichText::new(format!("第 {} 行", line + 1)).small();
+                        match &file.path {
+                            Some(path) => {
+                                if ui.link(label).on_hover_text("打开并跳到这一行").clicked()
+                                {
+                                    *action = Some(AllMarksAction::Open {
+                                        path: path.clone(),
+                                        line: *line,
+                                    });
+                                }
+                            }
+                            None => {
+                                ui.label(label.weak());
+                            }
+                        }
+                        if mark.private {
+                            ui.label(RichText::new("🔒").small())
+                                .on_hover_text("不发送给 AI");
+                        }
+                        ui.label(note);
+                    });
+                }
+            });
+        let hover = match &file.path {
+            Some(path) => path.display().to_string(),
+            None => "不知道这份文档保存在哪里".to_string(),
+        };
+        response.header_response.on_hover_text(hover);
+    }
+}
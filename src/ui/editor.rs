@@ -8,8 +8,11 @@ use super::ai_panel::{AiEditPreview, AiPanel, AiPanelAction};
 use super::sidebar::Sidebar;
 use crate::backend::ai_backend::{
     AiAgentResponse, AiError, AiProgressEvent, AiRequestId, AiSelectionContext,
+    NARRATIVE_BEAT_SEPARATOR, ProofreadIssue,
 };
 use crate::backend::sidebar_backend::Mark;
+use crate::config::{DEFAULT_FONT_SIZE, FormatIndent};
+use crate::segment::{self, Token};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -61,6 +64,34 @@ struct AiUndoEntry {
     after: String,
 }
 
+/// How the main text is laid out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EditorAppearance {
+    pub font_size: f32,
+    /// Row height as a multiple of the font's natural line height
+    pub line_spacing: f32,
+    /// Widest the text column gets in points; 0 fills the available width
+    pub line_width: f32,
+}
+
+impl EditorAppearance {
+    /// Height of one laid-out row of the main text
+    pub fn row_height(&self, ui: &Ui) -> f32 {
+        let font_id = egui::FontId::monospace(self.font_size);
+        ui.fonts_mut(|fonts| fonts.row_height(&font_id)) * self.line_spacing.max(1.0)
+    }
+}
+
+impl Default for EditorAppearance {
+    fn default() -> Self {
+        Self {
+            font_size: DEFAULT_FONT_SIZE,
+            line_spacing: 1.0,
+            line_width: 0.0,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Editor {
     content: String,
@@ -69,15 +100,27 @@ pub struct Editor {
     sidebar: Sidebar,
     ai_panel: AiPanel,
     is_focused: bool,
+    typing_activity: bool,
     current_file: Option<PathBuf>,
     current_file_total_time: u64,
-    cached_word_count: Option<usize>,
+    cached_stats: Option<TextStats>,
+    /// Hash of the content as last loaded or saved; `None` for a new buffer
+    saved_content_hash: Option<u64>,
+    cached_dirty: Option<bool>,
     ai_preview_scrolled_to: Option<usize>,
     selection_anchor: Option<SelectionAnchor>,
     next_selection_anchor_id: u64,
+    /// Caret position (in chars) to apply to the text edit on the next frame
+    pending_cursor: Option<usize>,
+    /// Char range to select and scroll into view on the next frame
+    pending_reveal: Option<(usize, usize)>,
     inline_ai_open: bool,
     inline_ai_draft: String,
     ai_undo_stack: Vec<AiUndoEntry>,
+    appearance: EditorAppearance,
+    format_indent: FormatIndent,
+    /// Leave out the marks column, e.g. in distraction-free mode
+    marks_hidden: bool,
     // Search and replace state
     search_replace: SearchReplaceState,
 }
@@ -97,7 +140,7 @@ impl Editor {
             && let Some(entry) = self.ai_undo_stack.pop()
         {
             self.content = entry.before;
-            self.cached_word_count = None;
+            self.content_changed();
         }
     }
 
@@ -114,13 +157,44 @@ impl Editor {
             .and_then(|result| result.as_ref().ok())
             .cloned();
         let id = ui.make_persistent_id("main_editor");
+        if let Some(cursor) = self.pending_cursor.take() {
+            // A document opened this frame has no state yet
+            let mut state = egui::TextEdit::load_state(ui.ctx(), id).unwrap_or_default();
+            state
+                .cursor
+                .set_char_range(Some(egui::text::CCursorRange::one(
+                    egui::text::CCursor::new(cursor),
+                )));
+            state.store(ui.ctx(), id);
+        }
+        if let Some((start, end)) = self.pending_reveal {
+            if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), id) {
+                state
+                    .cursor
+                    .set_char_range(Some(egui::text::CCursorRange::two(
+                        egui::text::CCursor::new(start),
+                        egui::text::CCursor::new(end),
+                    )));
+                state.store(ui.ctx(), id);
+            }
+            ui.memory_mut(|memory| memory.request_focus(id));
+        }
 
         // Sidebar width
         let sidebar_width = 20.0;
-        let available_width = ui.available_width() - sidebar_width;
+        let full_width = ui.available_width() - sidebar_width;
+        let available_width = if self.appearance.line_width > 0.0 {
+            full_width.min(self.appearance.line_width)
+        } else {
+            full_width
+        };
 
         // Use horizontal layout with top-to-bottom alignment
         ui.horizontal_top(|ui| {
+            // Center a text column narrower than the window
+            if available_width < full_width {
+                ui.add_space((full_width - available_width) / 2.0);
+            }
             // 1. Reserve space for sidebar (so editor is pushed right)
             let sidebar_origin = ui.cursor().min;
             ui.allocate_rect(
@@ -131,12 +205,14 @@ impl Editor {
             // 2. Editor Area. A pending AI edit only changes the layouter and adds
             // an anchored review surface; the actual text editor stays interactive.
             let diff_range = diff_range_for_layout.clone();
+            let appearance = self.appearance;
             let mut layouter = move |ui: &Ui, string: &dyn egui::TextBuffer, wrap_width: f32| {
                 ui.painter().layout_job(ai_live_diff_layout_job(
                     ui,
                     string.as_str(),
                     diff_range.as_ref(),
                     wrap_width,
+                    appearance,
                 ))
             };
 
@@ -149,6 +225,11 @@ impl Editor {
                 .show(ui);
 
             Self::enable_scroll_to_cursor(ui, &output);
+            if let Some((start, end)) = self.pending_reveal.take()
+                && let Some(rect) = text_range_screen_rect(&output, start, end)
+            {
+                ui.scroll_to_rect(rect.expand2(egui::vec2(8.0, 64.0)), Some(Align::Center));
+            }
             Self::fix_macos_ime(&output, ui);
             self.draw_underline_decoration_at_focus_line(&output, ui);
             self.highlight_matches(&output, ui, &content);
@@ -184,7 +265,7 @@ impl Editor {
 
             let editor_response = &output.response;
             if editor_response.changed() {
-                self.cached_word_count = None;
+                self.content_changed();
                 self.search_replace.matches.clear();
                 self.search_replace.current_match = None;
                 self.search_replace.match_index = 0;
@@ -192,15 +273,21 @@ impl Editor {
             if editor_response.clicked() {
                 editor_response.request_focus();
             }
+            if editor_response.has_focus() && Self::has_typing_input(ui) {
+                self.typing_activity = true;
+            }
 
-            let content_height = editor_response.rect.height();
-            self.render_sidebar(
-                sidebar_origin,
-                sidebar_width,
-                content_height,
-                output.galley_pos,
-                ui,
-            );
+            // The column stays reserved so the text does not shift
+            if !self.marks_hidden {
+                let content_height = editor_response.rect.height();
+                self.render_sidebar(
+                    sidebar_origin,
+                    sidebar_width,
+                    content_height,
+                    output.galley_pos,
+                    ui,
+                );
+            }
         });
 
         if active_preview.is_none() && ai_action.is_none() {
@@ -219,36 +306,86 @@ impl Editor {
 
     pub fn set_content(&mut self, content: String) {
         self.content = content;
-        self.cached_word_count = None; // 清除缓存
+        self.content_changed();
         self.ai_undo_stack.clear();
     }
 
-    pub fn get_word_count(&mut self) -> usize {
-        if let Some(count) = self.cached_word_count {
-            return count;
-        }
+    /// Start over with an empty, untitled document.
+    ///
+    /// The appearance and the AI panel stay; the content, file, marks,
+    /// caret and undo history of the old document go.
+    pub fn reset(&mut self) {
+        let mut sidebar = Sidebar::default();
+        sidebar.set_appearance(self.appearance);
+        *self = Self {
+            sidebar,
+            ai_panel: std::mem::take(&mut self.ai_panel),
+            appearance: self.appearance,
+            format_indent: self.format_indent,
+            marks_hidden: self.marks_hidden,
+            ..Self::default()
+        };
+    }
 
-        // 原有的计算逻辑
-        let count = self.calculate_word_count_internal();
-        self.cached_word_count = Some(count);
-        count
+    /// Forget everything derived from the content after it changed
+    fn content_changed(&mut self) {
+        self.cached_stats = None;
+        self.cached_dirty = None;
     }
 
-    fn calculate_word_count_internal(&self) -> usize {
-        let mut count = 0;
-        let mut in_word = false;
-        for c in self.content.chars() {
-            if c.is_whitespace() {
-                in_word = false;
-            } else if is_cjk(c) {
-                count += 1;
-                in_word = false;
-            } else if !in_word {
-                count += 1;
-                in_word = true;
-            }
+    /// Note that the content with hash `hash` is what is on disk now
+    pub fn mark_saved(&mut self, hash: u64) {
+        self.saved_content_hash = Some(hash);
+        self.cached_dirty = None;
+    }
+
+    /// Hash of the content last loaded or saved, if any was
+    pub fn saved_content_hash(&self) -> Option<u64> {
+        self.saved_content_hash
+    }
+
+    /// Whether the content differs from what was last loaded or saved
+    pub fn is_dirty(&mut self) -> bool {
+        if let Some(dirty) = self.cached_dirty {
+            return dirty;
         }
-        count
+        let dirty = match self.saved_content_hash {
+            Some(hash) => content_hash(&self.content) != hash,
+            None => !self.content.is_empty(),
+        };
+        self.cached_dirty = Some(dirty);
+        dirty
+    }
+
+    /// An untitled document with nothing but whitespace, which there is no
+    /// point saving. A blank document with a file is still saved: the file
+    /// may have been emptied on purpose.
+    pub fn is_blank_untitled(&self) -> bool {
+        self.current_file.is_none() && self.content.trim().is_empty()
+    }
+
+    pub fn get_word_count(&mut self) -> usize {
+        self.get_text_stats().words
+    }
+
+    /// Counts for the whole document, cached until the content changes
+    pub fn get_text_stats(&mut self) -> TextStats {
+        *self
+            .cached_stats
+            .get_or_insert_with(|| TextStats::of(&self.content))
+    }
+
+    /// Caret position in chars, as of the last frame
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor_index
+    }
+
+    /// Put the caret at char `cursor`, e.g. where it was in the last
+    /// session; past the end puts it at the end
+    pub fn set_cursor(&mut self, cursor: usize) {
+        let cursor = cursor.min(self.content.chars().count());
+        self.cursor_index = Some(cursor);
+        self.pending_cursor = Some(cursor);
     }
 
     pub fn get_cursor_word_count(&self) -> Option<usize> {
@@ -262,22 +399,7 @@ impl Editor {
             .map(|(byte_idx, _)| byte_idx)
             .unwrap_or(self.content.len());
 
-        let text_before_cursor = &self.content[..byte_index];
-
-        let mut count = 0;
-        let mut in_word = false;
-        for c in text_before_cursor.chars() {
-            if c.is_whitespace() {
-                in_word = false;
-            } else if is_cjk(c) {
-                count += 1;
-                in_word = false;
-            } else if !in_word {
-                count += 1;
-                in_word = true;
-            }
-        }
-        Some(count)
+        Some(segment::word_count(&self.content[..byte_index]))
     }
 
     pub fn get_stats(&mut self) -> (usize, usize) {
@@ -311,6 +433,23 @@ impl Editor {
         self.sidebar.reset_marks_changed();
     }
 
+    pub fn set_marks_hidden(&mut self, hidden: bool) {
+        self.marks_hidden = hidden;
+    }
+
+    /// Mark the caret's line, or unmark it, see [`Sidebar::toggle_mark`]
+    pub fn toggle_mark_at_cursor(&mut self) {
+        if let Some(cursor) = self.cursor_index {
+            let line = self
+                .content
+                .chars()
+                .take(cursor)
+                .filter(|c| *c == '\n')
+                .count();
+            self.sidebar.toggle_mark(line);
+        }
+    }
+
     /// Get the current file path
     pub fn get_current_file(&self) -> Option<&PathBuf> {
         self.current_file.as_ref()
@@ -321,6 +460,15 @@ impl Editor {
         self.current_file = path;
     }
 
+    pub fn set_appearance(&mut self, appearance: EditorAppearance) {
+        self.appearance = appearance;
+        self.sidebar.set_appearance(appearance);
+    }
+
+    pub fn set_format_indent(&mut self, indent: FormatIndent) {
+        self.format_indent = indent;
+    }
+
     /// Get the current file total time
     pub fn get_current_file_total_time(&self) -> u64 {
         self.current_file_total_time
@@ -336,15 +484,56 @@ impl Editor {
         self.is_focused
     }
 
-    /// Format the content by adding two spaces at the beginning of each line.
-    /// Blank lines are preserved as is.
+    /// The text currently selected in the editor, if it is still intact.
+    ///
+    /// The selection survives focus moving to the AI panel; it is dropped once
+    /// the underlying text changes.
+    pub fn get_selected_text(&self) -> Option<String> {
+        self.selection_context().map(|context| context.text)
+    }
+
+    /// The live selection as AI request context, if any
+    pub fn selection_context(&self) -> Option<AiSelectionContext> {
+        let anchor = self
+            .selection_anchor
+            .as_ref()
+            .filter(|anchor| !anchor.stale)?;
+        let current = char_range_text(
+            &self.content,
+            anchor.context.start_char,
+            anchor.context.end_char,
+        )?;
+        (current == anchor.context.text).then(|| anchor.context.clone())
+    }
+
+    /// Whether the user typed into the editor since the last call
+    pub fn take_typing_activity(&mut self) -> bool {
+        std::mem::take(&mut self.typing_activity)
+    }
+
+    fn has_typing_input(ui: &Ui) -> bool {
+        ui.input(|input| {
+            input.events.iter().any(|event| {
+                matches!(
+                    event,
+                    egui::Event::Text(_)
+                        | egui::Event::Paste(_)
+                        | egui::Event::Ime(egui::ImeEvent::Commit(_))
+                        | egui::Event::Key { pressed: true, .. }
+                )
+            })
+        })
+    }
+
+    /// Format the content by putting the configured indent at the beginning
+    /// of each line. Blank lines are preserved as is.
     pub fn format(&mut self) {
-        let formatted = Self::add_paragraph_indentation(&self.content);
+        let formatted = Self::add_paragraph_indentation(&self.content, self.format_indent.as_str());
         self.content = formatted;
     }
 
-    /// Helper function to add two spaces at the beginning of each line
-    fn add_paragraph_indentation(text: &str) -> String {
+    /// Helper function to put `indent` at the beginning of each line
+    fn add_paragraph_indentation(text: &str, indent: &str) -> String {
         let mut result = String::with_capacity(text.len() + 128);
 
         for (i, line) in text.lines().enumerate() {
@@ -356,8 +545,8 @@ impl Editor {
                 // Preserve blank lines as is
                 result.push_str(line);
             } else {
-                // Always add exactly two spaces after trimming leading whitespace
-                result.push_str("  ");
+                // Always add exactly one indent after trimming leading whitespace
+                result.push_str(indent);
                 result.push_str(line.trim_start());
             }
         }
@@ -787,12 +976,15 @@ impl Editor {
             return;
         }
         self.next_selection_anchor_id = self.next_selection_anchor_id.wrapping_add(1).max(1);
+        let (context_before, context_after) = surrounding_sentences(content, start, end);
         self.selection_anchor = Some(SelectionAnchor {
             context: AiSelectionContext {
                 anchor_id: self.next_selection_anchor_id,
                 start_char: start,
                 end_char: end,
                 text: selected_text,
+                context_before,
+                context_after,
             },
             screen_rect,
             stale: false,
@@ -1068,6 +1260,10 @@ impl Editor {
     }
 
     // AI Panel control methods
+    pub fn get_ai_panel(&self) -> &AiPanel {
+        &self.ai_panel
+    }
+
     pub fn get_ai_panel_mut(&mut self) -> &mut AiPanel {
         &mut self.ai_panel
     }
@@ -1149,13 +1345,147 @@ impl Editor {
         let range = locate_ai_edit_range(&self.content, base_content, original_text)?;
         let before = self.content.clone();
         self.content.replace_range(range, replacement_text);
+        self.push_ai_undo(before);
+        self.content_changed();
+        Ok(())
+    }
+
+    /// Render the AI panel against the current document.
+    pub fn show_ai_panel(&mut self, ui: &mut Ui) -> Option<AiPanelAction> {
+        self.ai_panel.show(ui, &self.content)
+    }
+
+    /// Insert `text` at the caret (or at the end when there is none) and move
+    /// the caret past it. Undoable like other AI edits.
+    pub fn insert_at_cursor(&mut self, text: &str) {
+        let char_count = self.content.chars().count();
+        let cursor = self.cursor_index.unwrap_or(char_count).min(char_count);
+        let byte_index = self
+            .content
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.content.len(), |(index, _)| index);
+
+        let before = self.content.clone();
+        self.content.insert_str(byte_index, text);
+        self.push_ai_undo(before);
+
+        let new_cursor = cursor + text.chars().count();
+        self.cursor_index = Some(new_cursor);
+        self.pending_cursor = Some(new_cursor);
+        self.content_changed();
+    }
+
+    /// Select and scroll to line `line`, counted from 0 as marks count it.
+    ///
+    /// Returns false when the text has fewer lines.
+    pub fn reveal_line(&mut self, line: usize) -> bool {
+        let Some(text) = self.content.split('\n').nth(line) else {
+            return false;
+        };
+        let start: usize = self
+            .content
+            .split('\n')
+            .take(line)
+            .map(|before| before.chars().count() + 1)
+            .sum();
+        let end = start + text.chars().count();
+        self.pending_reveal = Some((start, end));
+        self.cursor_index = Some(end);
+        true
+    }
+
+    /// Select and scroll to the passage a narrative beat points at.
+    ///
+    /// Returns false when no part of the beat can be found in the text.
+    pub fn reveal_beat(&mut self, beat: &str) -> bool {
+        match locate_beat(&self.content, beat) {
+            Some(range) => {
+                self.pending_reveal = Some(range);
+                self.cursor_index = Some(range.1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace `selection` with `text`, provided the selected text is unchanged.
+    pub fn replace_selection(
+        &mut self,
+        selection: &AiSelectionContext,
+        text: &str,
+    ) -> Result<(), String> {
+        let current = char_range_text(&self.content, selection.start_char, selection.end_char);
+        if current.as_deref() != Some(selection.text.as_str()) {
+            return Err("原选区已被修改，未替换".to_string());
+        }
+        let start = self
+            .content
+            .char_indices()
+            .nth(selection.start_char)
+            .map_or(self.content.len(), |(index, _)| index);
+        let end = start + selection.text.len();
+
+        let before = self.content.clone();
+        self.content.replace_range(start..end, text);
+        self.push_ai_undo(before);
+
+        let new_cursor = selection.start_char + text.chars().count();
+        self.cursor_index = Some(new_cursor);
+        self.pending_cursor = Some(new_cursor);
+        self.selection_anchor = None;
+        self.content_changed();
+        Ok(())
+    }
+
+    /// Swap a proofreading issue's `original` text for `suggestion`, preferring
+    /// the first match at or after char offset `search_from`. The replaced
+    /// text is selected so the change is easy to spot.
+    pub fn apply_proofread(
+        &mut self,
+        original: &str,
+        suggestion: &str,
+        search_from: usize,
+    ) -> Result<(), String> {
+        let range = locate_proofread_span(&self.content, original, search_from)
+            .ok_or_else(|| "原文已不存在".to_string())?;
+        let start = self.content[..range.start].chars().count();
+
+        let before = self.content.clone();
+        self.content.replace_range(range, suggestion);
+        self.push_ai_undo(before);
+
+        let end = start + suggestion.chars().count();
+        self.pending_reveal = Some((start, end));
+        self.cursor_index = Some(end);
+        self.content_changed();
+        Ok(())
+    }
+
+    pub fn set_proofread_result(
+        &mut self,
+        request_id: AiRequestId,
+        result: Result<Vec<ProofreadIssue>, AiError>,
+    ) {
+        self.ai_panel.set_proofread_result(request_id, result);
+    }
+
+    pub fn set_proofread_item_result(
+        &mut self,
+        entry_index: usize,
+        issue_index: usize,
+        result: Result<(), String>,
+    ) {
+        self.ai_panel
+            .set_proofread_item_result(entry_index, issue_index, result);
+    }
+
+    fn push_ai_undo(&mut self, before: String) {
         let after = self.content.clone();
         self.ai_undo_stack.push(AiUndoEntry { before, after });
         if self.ai_undo_stack.len() > 20 {
             self.ai_undo_stack.remove(0);
         }
-        self.cached_word_count = None;
-        Ok(())
     }
 
     pub fn set_ai_edit_result(&mut self, proposal_index: usize, result: Result<(), String>) {
@@ -1167,6 +1497,23 @@ impl Editor {
         self.search_replace.show_dialog = true;
     }
 
+    /// Highlight every occurrence of `text` in the find dialog, ignoring
+    /// case, and scroll to the first one. Returns how many there are.
+    pub fn find_all(&mut self, text: &str, whole_word: bool) -> usize {
+        self.search_replace.show_dialog = true;
+        self.search_replace.search_text = text.to_string();
+        self.search_replace.case_sensitive = false;
+        self.search_replace.whole_word = whole_word;
+        self.find_matches();
+        if let Some((start, end)) = self.search_replace.current_match {
+            let start_char = self.content[..start].chars().count();
+            let end_char = start_char + self.content[start..end].chars().count();
+            self.pending_reveal = Some((start_char, end_char));
+            self.cursor_index = Some(end_char);
+        }
+        self.search_replace.matches.len()
+    }
+
     fn show_search_replace_dialog(&mut self, ui: &mut Ui) {
         if !self.search_replace.show_dialog {
             // Clear search matches when content changes
@@ -1268,31 +1615,23 @@ impl Editor {
         }
     }
 
+    /// Byte offset of the first match of `search` at or after byte `start`
+    /// that is not part of a longer word
     fn find_whole_word(&self, content: &str, search: &str, start: usize) -> Option<usize> {
-        let chars: Vec<char> = content.chars().collect();
-        let search_chars: Vec<char> = search.chars().collect();
-
-        for i in start..chars.len().saturating_sub(search_chars.len()) {
-            // Check if this is a word boundary at start
-            let is_word_start = i == 0 || !chars[i - 1].is_alphanumeric();
-            // Check if this is a word boundary at end
-            let is_word_end = i + search_chars.len() == chars.len()
-                || !chars[i + search_chars.len()].is_alphanumeric();
-
-            if is_word_start && is_word_end {
-                let mut matches = true;
-                for (j, &ch) in search_chars.iter().enumerate() {
-                    if chars[i + j] != ch {
-                        matches = false;
-                        break;
-                    }
-                }
-                if matches {
-                    return Some(i);
-                }
-            }
-        }
-        None
+        content[start..]
+            .match_indices(search)
+            .map(|(pos, _)| pos + start)
+            .find(|&pos| {
+                let is_word_start = content[..pos]
+                    .chars()
+                    .next_back()
+                    .is_none_or(|c| !c.is_alphanumeric());
+                let is_word_end = content[pos + search.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| !c.is_alphanumeric());
+                is_word_start && is_word_end
+            })
     }
 
     fn next_match(&mut self) {
@@ -1354,8 +1693,120 @@ impl Editor {
         self.search_replace.matches.clear();
         self.search_replace.current_match = None;
         self.search_replace.match_index = 0;
-        self.cached_word_count = None; // Invalidate cache
+        self.content_changed();
+    }
+}
+
+/// Longest surrounding context kept on either side of a selection
+const SELECTION_CONTEXT_MAX_CHARS: usize = 200;
+/// Shortest key-phrase prefix still tried when locating a narrative beat
+const MIN_BEAT_PHRASE_CHARS: usize = 4;
+
+/// Char range of the passage a narrative beat refers to.
+///
+/// Tries the verbatim key phrase after the separator, then any quoted text,
+/// then the summary itself; a key phrase the model misquoted is retried with
+/// progressively shorter prefixes.
+/// Cheap fingerprint of a document's text
+pub fn content_hash(content: &str) -> u64 {
+    xxhash_rust::xxh64::xxh64(content.as_bytes(), 0)
+}
+
+fn locate_beat(content: &str, beat: &str) -> Option<(usize, usize)> {
+    let (summary, phrase) = match beat.split_once(NARRATIVE_BEAT_SEPARATOR) {
+        Some((summary, phrase)) => (summary, Some(phrase)),
+        None => (beat, None),
+    };
+    let strip_quotes = |text: &str| {
+        text.trim()
+            .trim_matches(|c| matches!(c, '「' | '」' | '“' | '”' | '"' | '\'' | '《' | '》'))
+            .trim()
+            .to_string()
+    };
+
+    let mut candidates = Vec::new();
+    if let Some(phrase) = phrase {
+        let phrase = strip_quotes(phrase);
+        let chars: Vec<char> = phrase.chars().collect();
+        for len in (MIN_BEAT_PHRASE_CHARS..=chars.len()).rev() {
+            candidates.push(chars[..len].iter().collect::<String>());
+        }
+        if chars.len() < MIN_BEAT_PHRASE_CHARS {
+            candidates.push(phrase);
+        }
+    }
+    for quoted in beat.split(['「', '」', '“', '”']).skip(1).step_by(2) {
+        candidates.push(strip_quotes(quoted));
+    }
+    candidates.push(strip_quotes(summary));
+
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.is_empty())
+        .find_map(|candidate| {
+            let byte_start = content.find(&candidate)?;
+            let start = content[..byte_start].chars().count();
+            Some((start, start + candidate.chars().count()))
+        })
+}
+
+/// Byte range of `original` in `content`: the first match starting at or after
+/// char offset `search_from`, else the first match anywhere.
+fn locate_proofread_span(
+    content: &str,
+    original: &str,
+    search_from: usize,
+) -> Option<std::ops::Range<usize>> {
+    if original.is_empty() {
+        return None;
     }
+    let from = content
+        .char_indices()
+        .nth(search_from)
+        .map_or(content.len(), |(index, _)| index);
+    let start = content[from..]
+        .find(original)
+        .map(|offset| from + offset)
+        .or_else(|| content.find(original))?;
+    Some(start..start + original.len())
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '!' | '?' | '.' | '\n')
+}
+
+/// The sentence before and the sentence after the character range `start..end`.
+fn surrounding_sentences(content: &str, start: usize, end: usize) -> (String, String) {
+    let chars: Vec<char> = content.chars().collect();
+    let start = start.min(chars.len());
+    let end = end.clamp(start, chars.len());
+
+    // Skip the terminator that closes the previous sentence, then walk back to
+    // the one before it.
+    let mut before_start = start;
+    while before_start > 0 && chars[before_start - 1].is_whitespace() {
+        before_start -= 1;
+    }
+    let before_end = before_start;
+    before_start = before_start.saturating_sub(1);
+    while before_start > 0
+        && !is_sentence_end(chars[before_start - 1])
+        && before_end - before_start < SELECTION_CONTEXT_MAX_CHARS
+    {
+        before_start -= 1;
+    }
+
+    let mut after_end = end;
+    while after_end < chars.len() && after_end - end < SELECTION_CONTEXT_MAX_CHARS {
+        after_end += 1;
+        if is_sentence_end(chars[after_end - 1]) {
+            break;
+        }
+    }
+
+    let before: String = chars[before_start..start].iter().collect();
+    let after: String = chars[end..after_end].iter().collect();
+    (before.trim().to_string(), after.trim().to_string())
 }
 
 fn char_range_text(content: &str, start: usize, end: usize) -> Option<String> {
@@ -1416,7 +1867,7 @@ fn preview_selection_text(text: &str, limit: usize) -> String {
     let mut char_count = 0;
     let mut last_was_whitespace = true;
 
-    while let Some(c) = chars.next() {
+    for c in chars.by_ref() {
         if c.is_whitespace() {
             if last_was_whitespace || char_count == 0 {
                 last_was_whitespace = true;
@@ -1541,10 +1992,13 @@ fn ai_live_diff_layout_job(
     text: &str,
     removed_range: Option<&Range<usize>>,
     wrap_width: f32,
+    appearance: EditorAppearance,
 ) -> egui::text::LayoutJob {
-    let font_id = egui::FontId::monospace(14.0);
+    let font_id = egui::FontId::monospace(appearance.font_size);
+    let line_height = (appearance.line_spacing > 1.0).then(|| appearance.row_height(ui));
     let normal = egui::TextFormat {
         font_id: font_id.clone(),
+        line_height,
         color: ui.visuals().text_color(),
         ..Default::default()
     };
@@ -1562,6 +2016,7 @@ fn ai_live_diff_layout_job(
             0.0,
             egui::TextFormat {
                 font_id: font_id.clone(),
+                line_height,
                 color: Color32::from_rgb(126, 52, 52),
                 background: Color32::from_rgb(250, 226, 224),
                 strikethrough: egui::Stroke::new(1.0, Color32::from_rgb(126, 52, 52)),
@@ -1729,18 +2184,201 @@ fn is_valid_text_byte_range(text: &str, range: &Range<usize>) -> bool {
         && text.is_char_boundary(range.end)
 }
 
-fn is_cjk(c: char) -> bool {
-    ('\u{4E00}'..='\u{9FFF}').contains(&c)
-        || ('\u{3400}'..='\u{4DBF}').contains(&c)
-        || ('\u{20000}'..='\u{2A6DF}').contains(&c)
-        || ('\u{F900}'..='\u{FAFF}').contains(&c)
-        || ('\u{2F800}'..='\u{2FA1F}').contains(&c)
+/// Counts derived from a text
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextStats {
+    /// CJK characters plus runs of other non-space characters
+    pub words: usize,
+    pub cjk_chars: usize,
+    /// Runs of non-CJK, non-space characters
+    pub western_words: usize,
+    /// Characters apart from line breaks
+    pub chars: usize,
+    pub chars_without_spaces: usize,
+    /// Lines with any text on them
+    pub paragraphs: usize,
+    /// Stretches of text ended by sentence punctuation, or by the end
+    pub sentences: usize,
+}
+
+/// Words on a double-spaced manuscript page
+pub const WORDS_PER_MANUSCRIPT_PAGE: usize = 250;
+/// Average reading speeds, in CJK characters and Western words a minute
+const CJK_CHARS_PER_MINUTE: f32 = 400.0;
+const WESTERN_WORDS_PER_MINUTE: f32 = 230.0;
+
+impl TextStats {
+    pub fn of(text: &str) -> Self {
+        let mut stats = Self::default();
+        for token in segment::tokens(text) {
+            match token {
+                Token::Cjk(_) => stats.cjk_chars += 1,
+                Token::Word(_) => stats.western_words += 1,
+            }
+        }
+        let mut in_sentence = false;
+        for c in text.chars() {
+            if c != '\n' && c != '\r' {
+                stats.chars += 1;
+            }
+            if c.is_whitespace() {
+                continue;
+            }
+            stats.chars_without_spaces += 1;
+            if is_sentence_end(c) {
+                if in_sentence {
+                    stats.sentences += 1;
+                    in_sentence = false;
+                }
+            } else if !is_closing_punctuation(c) {
+                in_sentence = true;
+            }
+        }
+        if in_sentence {
+            stats.sentences += 1;
+        }
+        stats.words = stats.cjk_chars + stats.western_words;
+        stats.paragraphs = text.lines().filter(|line| !line.trim().is_empty()).count();
+        stats
+    }
+
+    /// Minutes an average reader needs for the text
+    pub fn reading_minutes(&self) -> f32 {
+        self.cjk_chars as f32 / CJK_CHARS_PER_MINUTE
+            + self.western_words as f32 / WESTERN_WORDS_PER_MINUTE
+    }
+
+    /// Estimated pages at `chars_per_page` characters a page
+    pub fn pages(&self, chars_per_page: usize) -> f32 {
+        self.words as f32 / chars_per_page.max(1) as f32
+    }
+
+    /// Estimated manuscript pages of the Western text
+    pub fn manuscript_pages(&self) -> f32 {
+        self.western_words as f32 / WORDS_PER_MANUSCRIPT_PAGE as f32
+    }
+}
+
+/// Quotes and brackets that close a sentence rather than start one
+fn is_closing_punctuation(c: char) -> bool {
+    matches!(c, '”' | '’' | '」' | '』' | '）' | ')' | '"' | '\'')
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn surrounding_sentences_take_one_sentence_each_side() {
+        let text = "第一句。第二句前半选中部分后半。第三句。";
+        let start = text.chars().position(|c| c == '选').unwrap();
+        let end = start + 4;
+
+        let (before, after) = surrounding_sentences(text, start, end);
+        assert_eq!(before, "第二句前半");
+        assert_eq!(after, "后半。");
+    }
+
+    #[test]
+    fn locate_beat_prefers_the_key_phrase() {
+        let text = "雨夜。他推开门，看见桌上的信。第二天清晨离开。";
+        assert_eq!(locate_beat(text, "发现信件｜看见桌上的信"), Some((8, 14)));
+        // A misquoted phrase still matches through its prefix
+        assert_eq!(
+            locate_beat(text, "发现信件｜看见桌上的那封信"),
+            Some((8, 13))
+        );
+    }
+
+    #[test]
+    fn locate_beat_falls_back_to_quotes_and_summary() {
+        let text = "Prologue. The storm hits the harbour.";
+        assert_eq!(
+            locate_beat(text, "风暴来临：“The storm hits”"),
+            Some((10, 24))
+        );
+        assert_eq!(locate_beat(text, "Prologue"), Some((0, 8)));
+        assert_eq!(locate_beat(text, "不存在的节拍｜完全不同"), None);
+    }
+
+    #[test]
+    fn locate_proofread_span_prefers_matches_after_the_selection() {
+        let text = "在次出发。后来在次见面。";
+        // Char 5 is the start of the second sentence
+        assert_eq!(locate_proofread_span(text, "在次", 5), Some(21..27));
+        assert_eq!(locate_proofread_span(text, "在次", 0), Some(0..6));
+        // Nothing after the offset falls back to the first match
+        assert_eq!(locate_proofread_span(text, "出发", 9), Some(6..12));
+        assert_eq!(locate_proofread_span(text, "出发", 100), Some(6..12));
+        assert_eq!(locate_proofread_span(text, "再次", 0), None);
+        assert_eq!(locate_proofread_span(text, "", 0), None);
+    }
+
+    #[test]
+    fn reveal_line_selects_the_marked_line() {
+        let mut editor = Editor::default();
+        editor.set_content("第一行\n\n第三行写得长一些\n".to_string());
+
+        assert!(editor.reveal_line(2));
+        assert_eq!(editor.pending_reveal, Some((5, 13)));
+        assert!(editor.reveal_line(1));
+        assert_eq!(editor.pending_reveal, Some((4, 4)));
+        // The empty line after the last line break is still a line
+        assert!(editor.reveal_line(3));
+        assert!(!editor.reveal_line(4));
+    }
+
+    #[test]
+    fn find_all_highlights_whole_words_in_cjk_text() {
+        let mut editor = Editor::default();
+        editor.set_content("雨停了。Rain again，rainy 天，又是 rain".to_string());
+
+        assert_eq!(editor.find_all("rain", true), 2);
+        assert_eq!(editor.pending_reveal, Some((4, 8)));
+        let (start, end) = editor.search_replace.matches[1];
+        assert_eq!(&editor.content[start..end], "rain");
+        assert!(editor.content[end..].is_empty());
+
+        // Phrases match anywhere, also inside longer ones
+        assert_eq!(editor.find_all("rain", false), 3);
+        assert_eq!(editor.find_all("雪", false), 0);
+        assert_eq!(editor.search_replace.current_match, None);
+    }
+
+    #[test]
+    fn apply_proofread_replaces_span_and_skips_missing_text() {
+        let mut editor = Editor::default();
+        editor.set_content("他在次来到这里，在次离开。".to_string());
+
+        editor.apply_proofread("在次离开", "再次离开", 8).unwrap();
+        assert_eq!(editor.content, "他在次来到这里，再次离开。");
+        assert_eq!(editor.pending_reveal, Some((8, 12)));
+        assert_eq!(editor.ai_undo_stack.len(), 1);
+
+        // Deleting text is an empty suggestion
+        editor.apply_proofread("这里，", "这里", 0).unwrap();
+        assert_eq!(editor.content, "他在次来到这里再次离开。");
+
+        assert!(editor.apply_proofread("不存在", "x", 0).is_err());
+        assert_eq!(editor.content, "他在次来到这里再次离开。");
+        assert_eq!(editor.ai_undo_stack.len(), 2);
+    }
+
+    #[test]
+    fn surrounding_sentences_at_sentence_boundaries() {
+        let text = "Alpha one. Beta two. Gamma three.";
+        let start = text.find("Beta").unwrap();
+        let end = text.find(" Gamma").unwrap();
+
+        let (before, after) = surrounding_sentences(text, start, end);
+        assert_eq!(before, "Alpha one.");
+        assert_eq!(after, "Gamma three.");
+
+        let (before, after) = surrounding_sentences(text, 0, 3);
+        assert_eq!(before, "");
+        assert_eq!(after, "ha one.");
+    }
+
     #[test]
     fn test_word_count() {
         let mut editor = Editor::default();
@@ -1754,6 +2392,112 @@ mod tests {
         assert_eq!(editor.get_word_count(), 3);
     }
 
+    #[test]
+    fn test_new_content_clears_the_cached_counts() {
+        let mut editor = Editor::default();
+        editor.set_content("Hello world".to_string());
+        assert_eq!(editor.get_word_count(), 2);
+        assert!(editor.cached_stats.is_some());
+
+        editor.set_content("你好世界".to_string());
+        assert!(editor.cached_stats.is_none());
+        assert_eq!(editor.get_word_count(), 4);
+    }
+
+    #[test]
+    fn test_reset_leaves_an_empty_untitled_document() {
+        let mut editor = Editor::default();
+        let appearance = EditorAppearance {
+            font_size: 20.0,
+            ..EditorAppearance::default()
+        };
+        editor.set_appearance(appearance);
+        editor.set_content("第一章\n第二章".to_string());
+        editor.set_current_file(Some(PathBuf::from("/tmp/novel.txt")));
+        editor.set_current_file_total_time(600);
+        editor.set_uuid("uuid-1".to_string());
+        editor.apply_marks(HashMap::from([(1, Mark::default())]));
+        editor.toggle_mark_at_cursor();
+        editor.mark_saved(content_hash("第一章"));
+
+        editor.reset();
+
+        assert_eq!(editor.get_content(), "");
+        assert_eq!(editor.get_word_count(), 0);
+        assert_eq!(editor.get_current_file(), None);
+        assert_eq!(editor.get_current_file_total_time(), 0);
+        assert_eq!(editor.get_sidebar_uuid(), None);
+        assert!(editor.get_marks().is_empty());
+        assert!(!editor.marks_changed());
+        assert!(!editor.is_dirty());
+        assert_eq!(editor.appearance, appearance);
+    }
+
+    #[test]
+    fn test_text_stats_split_cjk_and_western_words() {
+        let stats = TextStats::of("第一章 Chapter one\n他说 hello");
+        assert_eq!(stats.cjk_chars, 5);
+        assert_eq!(stats.western_words, 3);
+        assert_eq!(stats.words, 8);
+
+        let stats = TextStats {
+            words: 2500,
+            western_words: 500,
+            ..TextStats::default()
+        };
+        assert_eq!(stats.pages(1000), 2.5);
+        assert_eq!(stats.manuscript_pages(), 2.0);
+        assert_eq!(stats.pages(0), 2500.0);
+    }
+
+    #[test]
+    fn test_text_stats_count_characters_paragraphs_and_sentences() {
+        let stats = TextStats::of("“你好。”他说。Hi there!\n\n  \n第二段没有句号");
+        assert_eq!(stats.paragraphs, 2);
+        assert_eq!(stats.sentences, 4);
+        assert_eq!(stats.chars, 26);
+        assert_eq!(stats.chars_without_spaces, 23);
+        assert_eq!(TextStats::of("  ”").sentences, 0);
+
+        let stats = TextStats {
+            cjk_chars: 800,
+            western_words: 230,
+            ..TextStats::default()
+        };
+        assert_eq!(stats.reading_minutes(), 3.0);
+    }
+
+    #[test]
+    fn test_dirty_until_content_matches_what_was_saved() {
+        let mut editor = Editor::default();
+        assert!(!editor.is_dirty());
+        editor.set_content("初稿".to_string());
+        assert!(editor.is_dirty());
+
+        editor.mark_saved(content_hash("初稿"));
+        assert!(!editor.is_dirty());
+        editor.insert_at_cursor("，续写");
+        assert!(editor.is_dirty());
+        // Typing it back to the saved text is clean again
+        editor.set_content("初稿".to_string());
+        assert!(!editor.is_dirty());
+    }
+
+    #[test]
+    fn test_only_untitled_blank_documents_count_as_blank() {
+        let mut editor = Editor::default();
+        assert!(editor.is_blank_untitled());
+        editor.set_content(" \n　".to_string());
+        assert!(editor.is_blank_untitled());
+        editor.set_content("初稿".to_string());
+        assert!(!editor.is_blank_untitled());
+
+        // A file emptied by the user is worth saving
+        editor.set_current_file(Some(PathBuf::from("/tmp/novel.txt")));
+        editor.set_content(String::new());
+        assert!(!editor.is_blank_untitled());
+    }
+
     #[test]
     fn test_format_basic() {
         let mut editor = Editor::default();
@@ -1808,22 +2552,31 @@ mod tests {
     #[test]
     fn test_add_paragraph_indentation() {
         assert_eq!(
-            Editor::add_paragraph_indentation("First paragraph.\n\nSecond paragraph."),
+            Editor::add_paragraph_indentation("First paragraph.\n\nSecond paragraph.", "  "),
             "  First paragraph.\n\n  Second paragraph."
         );
         assert_eq!(
-            Editor::add_paragraph_indentation("Already indented.\n\nNot indented."),
+            Editor::add_paragraph_indentation("Already indented.\n\nNot indented.", "  "),
             "  Already indented.\n\n  Not indented."
         );
         assert_eq!(
-            Editor::add_paragraph_indentation("Single line."),
+            Editor::add_paragraph_indentation("Single line.", "  "),
             "  Single line."
         );
-        assert_eq!(Editor::add_paragraph_indentation(""), "");
+        assert_eq!(Editor::add_paragraph_indentation("", "  "), "");
         assert_eq!(
-            Editor::add_paragraph_indentation("    Extra spaces."),
+            Editor::add_paragraph_indentation("    Extra spaces.", "  "),
             "  Extra spaces."
         );
+        assert_eq!(
+            Editor::add_paragraph_indentation("  段落一\n\n段落二", "\u{3000}\u{3000}"),
+            "\u{3000}\u{3000}段落一\n\n\u{3000}\u{3000}段落二"
+        );
+        // Full-width indentation is recognized when formatting again
+        assert_eq!(
+            Editor::add_paragraph_indentation("\u{3000}\u{3000}段落", "  "),
+            "  段落"
+        );
     }
 
     #[test]
@@ -1837,6 +2590,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::reversed_empty_ranges)]
     fn text_byte_range_validation_rejects_stale_or_split_ranges() {
         let text = "a你b";
 
@@ -1951,4 +2705,79 @@ mod tests {
         assert_eq!(editor.get_content(), "第一处。第二处。");
         assert_eq!(editor.ai_undo_stack.len(), 2);
     }
+
+    #[test]
+    fn insert_at_cursor_uses_char_positions_and_advances_caret() {
+        let mut editor = Editor::default();
+        editor.set_content("你好世界".to_string());
+        editor.cursor_index = Some(2);
+
+        editor.insert_at_cursor("，");
+
+        assert_eq!(editor.get_content(), "你好，世界");
+        assert_eq!(editor.cursor_index, Some(3));
+        assert!(editor.cached_stats.is_none());
+        assert_eq!(editor.ai_undo_stack.last().unwrap().before, "你好世界");
+    }
+
+    #[test]
+    fn set_cursor_stays_within_the_text() {
+        let mut editor = Editor::default();
+        editor.set_content("你好世界".to_string());
+
+        editor.set_cursor(2);
+        assert_eq!(editor.cursor(), Some(2));
+        assert_eq!(editor.pending_cursor, Some(2));
+
+        // The file may have become shorter since the caret was saved
+        editor.set_cursor(40);
+        assert_eq!(editor.cursor(), Some(4));
+    }
+
+    #[test]
+    fn toggle_mark_at_cursor_marks_the_caret_line_and_keeps_notes() {
+        let mut editor = Editor::default();
+        editor.set_content("第一行\n第二行\n第三行".to_string());
+        editor.cursor_index = Some(5);
+
+        editor.toggle_mark_at_cursor();
+        assert!(editor.get_marks().contains_key(&1));
+        assert!(editor.marks_changed());
+
+        editor.toggle_mark_at_cursor();
+        assert!(editor.get_marks().is_empty());
+
+        let mut marks = HashMap::new();
+        marks.insert(
+            1,
+            Mark {
+                note: "伏笔".to_string(),
+                ..Default::default()
+            },
+        );
+        editor.apply_marks(marks);
+        editor.toggle_mark_at_cursor();
+        assert_eq!(editor.get_marks()[&1].note, "伏笔");
+    }
+
+    #[test]
+    fn replace_selection_requires_unchanged_text() {
+        let selection = AiSelectionContext {
+            anchor_id: 1,
+            start_char: 2,
+            end_char: 4,
+            text: "世界".to_string(),
+            context_before: String::new(),
+            context_after: String::new(),
+        };
+        let mut editor = Editor::default();
+        editor.set_content("你好世界！".to_string());
+
+        editor.replace_selection(&selection, "朋友").unwrap();
+        assert_eq!(editor.get_content(), "你好朋友！");
+
+        // The selection no longer matches, so a second replace is refused
+        assert!(editor.replace_selection(&selection, "大家").is_err());
+        assert_eq!(editor.get_content(), "你好朋友！");
+    }
 }
@@ -0,0 +1,65 @@
+//! The 最近错误 window: errors of this session, newest first, each with
+//! the time it happened and a button to copy it into a bug report.
+
+use crate::datetime::format_local;
+use crate::error::ErrorLog;
+use egui::{Context, RichText};
+
+#[derive(Default)]
+pub struct ErrorLogWindow {
+    open: bool,
+}
+
+impl ErrorLogWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Renders the window; errors count as seen while it is open
+    pub fn show(&mut self, ctx: &Context, log: &mut ErrorLog, datetime_format: &str) {
+        if !self.open {
+            return;
+        }
+        log.mark_seen();
+
+        let mut open = self.open;
+        egui::Window::new("最近错误")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                if log.is_empty() {
+                    ui.label(RichText::new("没有错误").weak());
+                    return;
+                }
+                ui.horizontal(|ui| {
+                    ui.label(format!("共 {} 条", log.len()));
+                    if ui.button("清空").clicked() {
+                        log.clear();
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for entry in log.entries().rev() {
+                        let time = format_local(&entry.time, datetime_format);
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new(&time).small().weak());
+                            ui.label(RichText::new(entry.kind.label()).small().strong());
+                            if ui.small_button("复制").clicked() {
+                                ctx.copy_text(format!("[{}] {}", time, entry.message));
+                            }
+                        });
+                        ui.label(&entry.message);
+                        ui.separator();
+                    }
+                });
+            });
+        self.open = open;
+    }
+}
@@ -2,6 +2,63 @@
 ///
 /// Handles system font loading with CJK (Chinese, Japanese, Korean) support
 use eframe::egui::{FontData, FontDefinitions, FontFamily};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use thiserror::Error;
+
+use crate::fonts::preferred_font_names;
+pub use crate::fonts::{FontWeight, SystemFonts, preferred_font_list};
+
+/// Extensions offered when picking a font file
+pub const FONT_FILE_EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+/// Vertical offsets, as a fraction of the row height, that line up the CJK
+/// glyphs of known families with the rest of the text. Other families get
+/// 0.0; users fine-tune in Settings, see [`y_offset`].
+const BUILTIN_Y_OFFSETS: [(&str, f32); 4] = [
+    ("Hiragino Sans GB", 0.3),
+    ("PingFang SC", 0.3),
+    ("PingFang TC", 0.3),
+    ("PingFang HK", 0.3),
+];
+
+/// Font data already loaded, by family and weight
+type FontDataCache = HashMap<(String, FontWeight), Arc<FontData>>;
+
+/// Loading a family means matching every face in it and then reading a file
+/// that is often 15–30 MB for CJK fonts; switching back to a family skips
+/// all of that.
+static FONT_DATA_CACHE: OnceLock<Mutex<FontDataCache>> = OnceLock::new();
+
+fn font_data_cache() -> MutexGuard<'static, FontDataCache> {
+    FONT_DATA_CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Number of cached fonts and the bytes they take
+pub fn font_cache_stats() -> (usize, usize) {
+    let cache = font_data_cache();
+    let bytes = cache.values().map(|data| data.font.len()).sum();
+    (cache.len(), bytes)
+}
+
+/// Forget the cached font data; fonts in use stay loaded in egui
+pub fn clear_font_cache() {
+    font_data_cache().clear();
+    tracing::info!("Cleared the font data cache");
+}
+
+#[derive(Error, Debug)]
+pub enum FontFileError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("not a usable font file: {0}")]
+    Invalid(String),
+}
 
 /// Setup fonts for the application with CJK support
 ///
@@ -16,19 +73,22 @@ use eframe::egui::{FontData, FontDefinitions, FontFamily};
 /// # Returns
 /// A `FontDefinitions` instance configured with the best available system font
 pub fn setup_fonts() -> FontDefinitions {
+    setup_fonts_with(FontWeight::default(), &BTreeMap::new())
+}
+
+/// [`setup_fonts`] in the face of `weight`, or the one closest to it, with
+/// the user's `y_offsets`
+fn setup_fonts_with(weight: FontWeight, y_offsets: &BTreeMap<String, f32>) -> FontDefinitions {
     // Create font definitions - start with defaults so we have fallbacks
     let mut fonts = FontDefinitions::default();
 
-    // Try to find a system font with CJK support
-    let source = font_kit::source::SystemSource::new();
-
     // Define font names to try based on OS for better CJK support
-    let font_names: Vec<&str> = get_preferred_font_names();
+    let font_names: Vec<&str> = preferred_font_names();
 
     // Try to find one of the preferred fonts
     let mut found_font = false;
     for font_name in font_names {
-        if try_load_font(&mut fonts, &source, font_name) {
+        if try_load_font(&mut fonts, font_name, weight, y_offsets) {
             tracing::info!("Using system font '{}' for CJK support", font_name);
             found_font = true;
             break;
@@ -37,20 +97,47 @@ pub fn setup_fonts() -> FontDefinitions {
 
     // If we couldn't find any preferred fonts, try a generic sans-serif as backup
     if !found_font {
-        load_fallback_font(&mut fonts, &source);
+        load_fallback_font(&mut fonts);
     }
 
     fonts
 }
 
-/// Get preferred font names based on the current operating system
-fn get_preferred_font_names() -> Vec<&'static str> {
-    match std::env::consts::OS {
-        "macos" => vec!["Hiragino Sans GB", "PingFang SC", "Heiti SC", "STSong"],
-        "windows" => vec!["Microsoft YaHei", "SimSun", "SimHei", "MS Gothic"],
-        "linux" => vec!["Noto Sans CJK TC"],
-        _ => vec![], // Empty for other OSes - we'll use generic fallback
-    }
+/// What font-kit matches faces of `weight` by
+fn properties(weight: FontWeight) -> font_kit::properties::Properties {
+    let mut properties = font_kit::properties::Properties::new();
+    properties.weight = font_kit::properties::Weight(weight.value());
+    properties
+}
+
+/// The family [`setup_fonts`] picks on this system, if any preferred one is
+/// installed
+pub fn default_font_family() -> Option<&'static str> {
+    static DEFAULT_FAMILY: OnceLock<Option<&'static str>> = OnceLock::new();
+    *DEFAULT_FAMILY.get_or_init(|| {
+        preferred_font_names()
+            .into_iter()
+            .find(|font_name| load_family_data(font_name, FontWeight::default()).is_some())
+    })
+}
+
+/// How far to move the CJK glyphs of `family` down, as a fraction of the
+/// row height: the user's own offset from `overrides`, else the built-in one
+pub fn y_offset(family: &str, overrides: &BTreeMap<String, f32>) -> f32 {
+    overrides.get(family).copied().unwrap_or_else(|| {
+        BUILTIN_Y_OFFSETS
+            .iter()
+            .find(|(name, _)| *name == family)
+            .map_or(0.0, |(_, offset)| *offset)
+    })
+}
+
+/// Name a font file's vertical offset is kept under
+pub fn font_file_key(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
 }
 
 /// Try to load a specific font by name
@@ -59,51 +146,164 @@ fn get_preferred_font_names() -> Vec<&'static str> {
 /// `true` if the font was successfully loaded and registered, `false` otherwise
 fn try_load_font(
     fonts: &mut FontDefinitions,
-    source: &font_kit::source::SystemSource,
     font_name: &str,
+    weight: FontWeight,
+    y_offsets: &BTreeMap<String, f32>,
 ) -> bool {
-    // Get family by name
-    if let Ok(family_handle) = source.select_family_by_name(font_name)
-        && let Some(font_handle) = family_handle.fonts().first()
-        && let Ok(font_data) = match font_handle {
-            font_kit::handle::Handle::Memory { bytes, .. } => Ok(bytes.to_vec()),
-            font_kit::handle::Handle::Path { path, .. } => std::fs::read(path),
-        }
-    {
-        // Register the font with egui
-        const SYSTEM_FONT_NAME: &str = "SystemCJKFont";
-        fonts.font_data.insert(
-            SYSTEM_FONT_NAME.to_owned(),
-            FontData::from_owned(font_data)
-                .tweak(eframe::egui::FontTweak {
-                    y_offset_factor: 0.3, // Adjust this value to fix vertical alignment (e.g. -0.2 or 0.2)
-                    ..Default::default()
-                })
-                .into(),
-        );
+    if let Some(font_data) = load_family_data(font_name, weight) {
+        register_primary_font(fonts, font_data, y_offset(font_name, y_offsets));
+        return true;
+    }
 
-        // Add as primary font for proportional text (at the beginning)
-        fonts
-            .families
-            .get_mut(&FontFamily::Proportional)
-            .unwrap()
-            .insert(0, SYSTEM_FONT_NAME.to_owned());
+    false
+}
 
-        // Also add to monospace as a fallback
-        fonts
-            .families
-            .get_mut(&FontFamily::Monospace)
-            .unwrap()
-            .push(SYSTEM_FONT_NAME.to_owned());
+/// The regular face of the installed family `font_name`
+pub fn family_font_data(font_name: &str) -> Option<Arc<FontData>> {
+    load_family_data(font_name, FontWeight::Regular)
+}
 
-        return true;
+/// The face of the family `font_name` closest to `weight`, from the cache
+/// if it was loaded before
+fn load_family_data(font_name: &str, weight: FontWeight) -> Option<Arc<FontData>> {
+    let key = (font_name.to_string(), weight);
+    if let Some(font_data) = font_data_cache().get(&key) {
+        return Some(Arc::clone(font_data));
     }
 
-    false
+    // Connecting to the system's font database takes a few milliseconds
+    // itself, so only a cache miss does it
+    let font_handle = font_kit::source::SystemSource::new()
+        .select_best_match(
+            &[font_kit::family_name::FamilyName::Title(
+                font_name.to_string(),
+            )],
+            &properties(weight),
+        )
+        .ok()?;
+    // Collections hold several faces; the handle says which one matched
+    let (font_data, index) = match font_handle {
+        font_kit::handle::Handle::Memory { bytes, font_index } => (bytes.to_vec(), font_index),
+        font_kit::handle::Handle::Path { path, font_index } => {
+            (std::fs::read(path).ok()?, font_index)
+        }
+    };
+    let font_data = Arc::new(FontData {
+        index,
+        ..FontData::from_owned(font_data)
+    });
+    font_data_cache().insert(key, Arc::clone(&font_data));
+    Some(font_data)
+}
+
+/// The weights the installed family `font_name` has, each face counted as
+/// the offered weight closest to it. Loads every face of the family, so
+/// call it off the UI thread.
+pub fn family_weights(font_name: &str) -> Vec<FontWeight> {
+    let source = font_kit::source::SystemSource::new();
+    let Ok(family_handle) = source.select_family_by_name(font_name) else {
+        return Vec::new();
+    };
+    let mut weights: Vec<FontWeight> = family_handle
+        .fonts()
+        .iter()
+        .filter_map(|handle| handle.load().ok())
+        .map(|font| font.properties())
+        .filter(|properties| properties.style == font_kit::properties::Style::Normal)
+        .map(|properties| FontWeight::closest(properties.weight.0))
+        .collect();
+    weights.sort();
+    weights.dedup();
+    weights
+}
+
+/// Put the family `font_name` in front of the fonts already in
+/// `fonts`, so it draws the characters it has and the rest fall through
+/// to the CJK font
+fn add_latin_font(fonts: &mut FontDefinitions, font_name: &str, weight: FontWeight) {
+    const LATIN_FONT_NAME: &str = "LatinFont";
+    let Some(font_data) = load_family_data(font_name, weight) else {
+        tracing::warn!("Failed to load Latin font '{}'", font_name);
+        return;
+    };
+    fonts
+        .font_data
+        .insert(LATIN_FONT_NAME.to_owned(), font_data);
+    fonts
+        .families
+        .get_mut(&FontFamily::Proportional)
+        .unwrap()
+        .insert(0, LATIN_FONT_NAME.to_owned());
+    tracing::info!("Applied Latin font: {}", font_name);
+}
+
+/// Register `font_data` with egui as the primary proportional font and as a
+/// monospace fallback, moved down by `y_offset` (see [`y_offset`])
+fn register_primary_font(fonts: &mut FontDefinitions, font_data: Arc<FontData>, y_offset: f32) {
+    const SYSTEM_FONT_NAME: &str = "SystemCJKFont";
+    let font_data = if y_offset == 0.0 {
+        font_data
+    } else {
+        // The tweak needs a copy of the data; still far quicker than loading
+        FontData::clone(&font_data)
+            .tweak(eframe::egui::FontTweak {
+                y_offset_factor: y_offset,
+                ..Default::default()
+            })
+            .into()
+    };
+    fonts
+        .font_data
+        .insert(SYSTEM_FONT_NAME.to_owned(), font_data);
+
+    // Add as primary font for proportional text (at the beginning)
+    fonts
+        .families
+        .get_mut(&FontFamily::Proportional)
+        .unwrap()
+        .insert(0, SYSTEM_FONT_NAME.to_owned());
+
+    // Also add to monospace as a fallback
+    fonts
+        .families
+        .get_mut(&FontFamily::Monospace)
+        .unwrap()
+        .push(SYSTEM_FONT_NAME.to_owned());
+}
+
+/// Use the font file at `path` as the CJK font, like [`apply_font_pair`]
+/// does for an installed family.
+///
+/// The file is checked before egui sees it, since egui cannot recover from
+/// a broken font. Of a collection (.ttc) the first face is used.
+pub fn apply_font_file(
+    path: &Path,
+    latin: Option<&str>,
+    weight: FontWeight,
+    y_offsets: &BTreeMap<String, f32>,
+) -> Result<FontDefinitions, FontFileError> {
+    let font_data = std::fs::read(path)?;
+    let font_data = Arc::new(font_data);
+    font_kit::font::Font::from_bytes(Arc::clone(&font_data), 0)
+        .map_err(|e| FontFileError::Invalid(e.to_string()))?;
+    let font_data = Arc::try_unwrap(font_data).unwrap_or_else(|data| data.to_vec());
+
+    let mut fonts = FontDefinitions::default();
+    register_primary_font(
+        &mut fonts,
+        Arc::new(FontData::from_owned(font_data)),
+        y_offset(&font_file_key(path), y_offsets),
+    );
+    tracing::info!("Applied font file: {:?}", path);
+    if let Some(latin) = latin {
+        add_latin_font(&mut fonts, latin, weight);
+    }
+    Ok(fonts)
 }
 
 /// Load a fallback font (generic sans-serif) when no preferred font is available
-fn load_fallback_font(fonts: &mut FontDefinitions, source: &font_kit::source::SystemSource) {
+fn load_fallback_font(fonts: &mut FontDefinitions) {
+    let source = font_kit::source::SystemSource::new();
     if let Ok(font_handle) = source.select_best_match(
         &[font_kit::family_name::FamilyName::SansSerif],
         &font_kit::properties::Properties::new(),
@@ -139,124 +339,153 @@ fn load_fallback_font(fonts: &mut FontDefinitions, source: &font_kit::source::Sy
     }
 }
 
-/// Enumerate all available Chinese fonts from the system
+/// Characters a font has to draw to be listed as Chinese
+const CJK_PROBES: [char; 5] = ['的', '是', '一', '写', '永'];
+
+/// Whether `font` has a glyph for every one of `chars`
+fn covers(font: &font_kit::font::Font, chars: &[char]) -> bool {
+    chars.iter().all(|&c| font.glyph_for_char(c).is_some())
+}
+
+/// Whether the first face of the installed family `font_name` draws all of
+/// [`CJK_PROBES`]; `None` if it cannot be loaded
+fn family_covers_cjk(source: &font_kit::source::SystemSource, font_name: &str) -> Option<bool> {
+    let family_handle = source.select_family_by_name(font_name).ok()?;
+    let font = family_handle.fonts().first()?.load().ok()?;
+    Some(covers(&font, &CJK_PROBES))
+}
+
+/// Enumerate all available fonts from the system
+///
+/// This function scans the system for fonts and sorts the family names into
+/// ones that have CJK (Chinese, Japanese, Korean) support and the rest. The
+/// detection is based on:
+/// - Font family name patterns (common Chinese font names) and operating
+///   system defaults, to pick candidates
+/// - Glyph coverage of [`CJK_PROBES`], to confirm them
 ///
-/// This function scans the system for fonts and returns a list of font family names
-/// that have CJK (Chinese, Japanese, Korean) support. The detection is based on:
-/// - Font family name patterns (common Chinese font names)
-/// - Operating system defaults
+/// Loading every candidate takes a while, so call it off the UI thread.
 ///
 /// # Returns
-/// A sorted vector of unique font family names that support Chinese characters
-pub fn enumerate_chinese_fonts() -> Vec<String> {
+/// Sorted lists of unique font family names
+pub fn enumerate_fonts() -> SystemFonts {
     let source = font_kit::source::SystemSource::new();
-    let mut chinese_fonts = std::collections::HashSet::new();
+    let mut fonts = SystemFonts::from_families(source.all_families().unwrap_or_default());
 
-    // Get all font families
-    let families = source.all_families().unwrap_or_default();
-
-    for family_name in families {
-        // Check if the font name contains common Chinese font indicators
-        if is_likely_chinese_font(&family_name) {
-            chinese_fonts.insert(family_name);
+    // Names only hint at coverage: "gothic" also matches Latin fonts
+    for font_name in std::mem::take(&mut fonts.chinese) {
+        match family_covers_cjk(&source, &font_name) {
+            Some(true) => fonts.chinese.push(font_name),
+            Some(false) => {
+                tracing::debug!("Font '{}' lacks Chinese glyphs", font_name);
+                fonts.latin.push(font_name);
+            }
+            // Not installed, e.g. a preferred font of another OS version
+            None => {}
         }
     }
+    fonts.latin.sort();
 
-    // Also include our known preferred fonts for the current OS
-    for font_name in get_preferred_font_names() {
-        chinese_fonts.insert(font_name.to_string());
-    }
-
-    // Convert to sorted vector for consistent ordering
-    let mut result: Vec<String> = chinese_fonts.into_iter().collect();
-    result.sort();
-
-    tracing::info!("Found {} Chinese fonts on the system", result.len());
-    result
-}
-
-/// Check if a font name is likely to be a Chinese font
-///
-/// This checks for common patterns in Chinese font names across different platforms
-fn is_likely_chinese_font(name: &str) -> bool {
-    let name_lower = name.to_lowercase();
-
-    // Common Chinese font name patterns
-    let chinese_indicators = [
-        // Simplified Chinese
-        "pingfang",
-        "hiragino",
-        "heiti",
-        "stheiti",
-        "stsong",
-        "stkaiti",
-        "stfangsong",
-        "songti",
-        "kaiti",
-        "fangsong",
-        "yahei",
-        "microsoft yahei",
-        "simsun",
-        "simhei",
-        "simkai",
-        "nsimsun",
-        "fangsong",
-        "lishu",
-        "deng",
-        "yuan",
-        // Traditional Chinese
-        "lihei",
-        "lisung",
-        "pmingliu",
-        "mingliu",
-        // Japanese (often have CJK support)
-        "gothic",
-        "mincho",
-        "meiryo",
-        "ms gothic",
-        "ms mincho",
-        "yu gothic",
-        "yu mincho",
-        // Generic CJK
-        "noto sans cjk",
-        "noto serif cjk",
-        "source han",
-        "han sans",
-        "han serif",
-        // Direct Chinese characters in name (some fonts have this)
-        "宋体",
-        "黑体",
-        "楷体",
-        "仿宋",
-    ];
-
-    chinese_indicators
-        .iter()
-        .any(|indicator| name_lower.contains(indicator))
+    tracing::info!(
+        "Found {} Chinese and {} other fonts on the system",
+        fonts.chinese.len(),
+        fonts.latin.len()
+    );
+    fonts
 }
 
-/// Apply a specific font to the application
+/// Apply a Latin and a CJK font to the application
 ///
-/// This function loads the specified font and configures it as the primary font
-/// for the entire application. It follows the same loading pattern as `setup_fonts()`.
+/// The Latin font comes first, so it draws ASCII text and numerals, and
+/// everything it lacks falls through to the CJK font. The CJK font follows
+/// the same loading pattern as `setup_fonts()`.
 ///
 /// # Arguments
-/// * `font_name` - The family name of the font to apply
+/// * `latin` - The family for Latin text; `None` leaves it to the CJK font
+/// * `cjk` - The family for Chinese text; `None` uses the system default
+/// * `weight` - The weight of both, or the closest one each family has
+/// * `y_offsets` - The user's vertical offsets by family, see [`y_offset`]
 ///
 /// # Returns
-/// A configured `FontDefinitions` instance with the specified font, or defaults if loading fails
-pub fn apply_font(font_name: &str) -> FontDefinitions {
-    let mut fonts = FontDefinitions::default();
-    let source = font_kit::source::SystemSource::new();
+/// A configured `FontDefinitions` instance with the specified fonts, or defaults if loading fails
+pub fn apply_font_pair(
+    latin: Option<&str>,
+    cjk: Option<&str>,
+    weight: FontWeight,
+    y_offsets: &BTreeMap<String, f32>,
+) -> FontDefinitions {
+    let mut fonts = match cjk {
+        Some(font_name) => {
+            let mut fonts = FontDefinitions::default();
 
-    // Try to load the requested font
-    if try_load_font(&mut fonts, &source, font_name) {
-        tracing::info!("Applied font: {}", font_name);
-    } else {
-        // If the specific font fails, try fallback
-        tracing::warn!("Failed to load font '{}', using fallback", font_name);
-        load_fallback_font(&mut fonts, &source);
-    }
+            // Try to load the requested font
+            if try_load_font(&mut fonts, font_name, weight, y_offsets) {
+                tracing::info!("Applied font: {}", font_name);
+            } else {
+                // If the specific font fails, try fallback
+                tracing::warn!("Failed to load font '{}', using fallback", font_name);
+                load_fallback_font(&mut fonts);
+            }
+            fonts
+        }
+        None => setup_fonts_with(weight, y_offsets),
+    };
 
+    if let Some(latin) = latin {
+        add_latin_font(&mut fonts, latin, weight);
+    }
     fonts
 }
+
+/// Mixed text shown in the 字体 menu to judge a pairing
+pub const PAIRING_PREVIEW: &str = "天地玄黄 The quick brown fox 2024";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broken_font_files_are_rejected() {
+        let path =
+            std::env::temp_dir().join(format!("paper_shell_font_{}.ttf", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"not a font").unwrap();
+        assert!(matches!(
+            apply_font_file(&path, None, FontWeight::Regular, &BTreeMap::new()),
+            Err(FontFileError::Invalid(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            apply_font_file(&path, None, FontWeight::Regular, &BTreeMap::new()),
+            Err(FontFileError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_user_offsets_win_over_built_in_ones() {
+        let mut overrides = BTreeMap::new();
+        assert_eq!(y_offset("PingFang SC", &overrides), 0.3);
+        assert_eq!(y_offset("Songti SC", &overrides), 0.0);
+
+        overrides.insert("PingFang SC".to_string(), 0.1);
+        overrides.insert("Songti SC".to_string(), -0.05);
+        assert_eq!(y_offset("PingFang SC", &overrides), 0.1);
+        assert_eq!(y_offset("Songti SC", &overrides), -0.05);
+        assert_eq!(
+            font_file_key(Path::new("/fonts/思源宋体.otf")),
+            "思源宋体.otf"
+        );
+    }
+
+    #[test]
+    fn test_cjk_coverage_is_probed_by_glyph() {
+        // egui's bundled Ubuntu Light has Latin glyphs only
+        let bundled = FontDefinitions::default();
+        let data = bundled.font_data["Ubuntu-Light"].font.to_vec();
+        let font = font_kit::font::Font::from_bytes(Arc::new(data), 0).unwrap();
+
+        assert!(covers(&font, &['A', 'g', '1']));
+        assert!(!covers(&font, &CJK_PROBES));
+        assert!(!covers(&font, &['A', '永']));
+        assert!(covers(&font, &[]));
+    }
+}
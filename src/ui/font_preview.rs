@@ -0,0 +1,141 @@
+//! Font families drawn in their own typeface in the 字体 menu.
+//!
+//! A family is loaded the first time its entry is hovered and registered
+//! with egui under a name of its own, in front of the regular proportional
+//! fonts so glyphs it lacks still show. Only the most recently hovered
+//! [`MAX_PREVIEWS`] stay loaded; CJK fonts run to tens of megabytes.
+//!
+//! Every font change of the app goes through [`FontPreviews::set_fonts`],
+//! which keeps the loaded previews on top of the app's fonts.
+
+use egui::{Context, FontData, FontDefinitions, FontFamily};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+/// Preview fonts kept loaded at once
+const MAX_PREVIEWS: usize = 10;
+/// Start of the names previews are registered under
+const PREFIX: &str = "preview:";
+
+/// Sentence shown in the typeface of the hovered family
+pub const PREVIEW_SENTENCE: &str = "永远的春天 AaBb 123";
+
+#[derive(Default)]
+pub struct FontPreviews {
+    /// Fonts set by the app; `None` until the first change, while egui still
+    /// has the fonts set at start
+    base: Option<FontDefinitions>,
+    /// Loaded families with their font data, least recently hovered first
+    loaded: VecDeque<(String, Arc<FontData>)>,
+    /// Families that could not be loaded; not tried again
+    failed: HashSet<String>,
+}
+
+impl FontPreviews {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `fonts` for the app, keeping the loaded previews
+    pub fn set_fonts(&mut self, ctx: &Context, fonts: FontDefinitions) {
+        self.base = Some(fonts);
+        self.apply(ctx);
+    }
+
+    /// The family to draw `family`'s entry in, once its preview is loaded
+    /// and egui has picked it up
+    pub fn family(&self, ctx: &Context, family: &str) -> Option<FontFamily> {
+        let preview = FontFamily::Name(preview_name(family).into());
+        ctx.fonts(|fonts| fonts.definitions().families.contains_key(&preview))
+            .then_some(preview)
+    }
+
+    /// `family` is hovered: load its preview, or keep it from being the
+    /// next to go
+    pub fn request(&mut self, ctx: &Context, family: &str) {
+        if self.touch(family) || self.failed.contains(family) {
+            return;
+        }
+        match crate::ui::font::family_font_data(family) {
+            Some(data) => {
+                self.remember(family, data);
+                self.apply(ctx);
+                ctx.request_repaint();
+            }
+            None => {
+                tracing::warn!("Failed to load preview of font '{}'", family);
+                self.failed.insert(family.to_string());
+            }
+        }
+    }
+
+    /// Move `family` to the back of the queue if it is loaded
+    fn touch(&mut self, family: &str) -> bool {
+        let Some(index) = self.loaded.iter().position(|(name, _)| name == family) else {
+            return false;
+        };
+        if let Some(entry) = self.loaded.remove(index) {
+            self.loaded.push_back(entry);
+        }
+        true
+    }
+
+    fn remember(&mut self, family: &str, data: Arc<FontData>) {
+        self.loaded.push_back((family.to_string(), data));
+        while self.loaded.len() > MAX_PREVIEWS {
+            self.loaded.pop_front();
+        }
+    }
+
+    fn apply(&self, ctx: &Context) {
+        let mut fonts = self
+            .base
+            .clone()
+            .unwrap_or_else(|| ctx.fonts(|fonts| fonts.definitions().clone()));
+        // Fonts set at start may still carry previews from before
+        fonts.families.retain(
+            |family, _| !matches!(family, FontFamily::Name(name) if name.starts_with(PREFIX)),
+        );
+        fonts.font_data.retain(|name, _| !name.starts_with(PREFIX));
+
+        let fallbacks = fonts
+            .families
+            .get(&FontFamily::Proportional)
+            .cloned()
+            .unwrap_or_default();
+        for (family, data) in &self.loaded {
+            let name = preview_name(family);
+            fonts.font_data.insert(name.clone(), Arc::clone(data));
+            let mut chain = vec![name.clone()];
+            chain.extend(fallbacks.iter().cloned());
+            fonts.families.insert(FontFamily::Name(name.into()), chain);
+        }
+        ctx.set_fonts(fonts);
+    }
+}
+
+fn preview_name(family: &str) -> String {
+    format!("{}{}", PREFIX, family)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_hovered_preview_goes_first() {
+        let mut previews = FontPreviews::new();
+        let data = Arc::new(FontData::from_static(&[]));
+        for i in 0..MAX_PREVIEWS {
+            previews.remember(&format!("字体{}", i), Arc::clone(&data));
+        }
+        assert!(previews.touch("字体0"));
+        previews.remember("新字体", data);
+
+        let names: Vec<&str> = previews.loaded.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names.len(), MAX_PREVIEWS);
+        assert!(!names.contains(&"字体1"));
+        assert_eq!(names[names.len() - 2..], ["字体0", "新字体"]);
+        assert!(!previews.touch("字体1"));
+    }
+}
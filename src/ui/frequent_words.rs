@@ -0,0 +1,111 @@
+//! The 高频词 window: the words and phrases a document repeats most, see
+//! [`crate::word_frequency`]. Clicking one highlights it in the editor.
+//!
+//! Long documents are counted in the background; the window shows a
+//! spinner until [`FrequentWordsWindow::set_words`] is called.
+
+use crate::tabs::TabId;
+use crate::word_frequency::FrequentWord;
+use egui::{Context, RichText};
+
+pub enum FrequentWordsAction {
+    /// Highlight `word` in the document of tab `tab`
+    Find { tab: TabId, word: FrequentWord },
+    /// Count the active document again
+    Refresh,
+}
+
+#[derive(Default)]
+pub struct FrequentWordsWindow {
+    open: bool,
+    /// Tab of the document counted
+    tab: TabId,
+    document_name: String,
+    words: Option<Vec<FrequentWord>>,
+}
+
+impl FrequentWordsWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the window in its loading state, for the document of `tab`
+    pub fn open(&mut self, tab: TabId, document_name: String) {
+        self.open = true;
+        self.tab = tab;
+        self.document_name = document_name;
+        self.words = None;
+    }
+
+    /// Show the words counted for `tab`; results for a document counted
+    /// earlier are dropped
+    pub fn set_words(&mut self, tab: TabId, words: Vec<FrequentWord>) {
+        if tab == self.tab {
+            self.words = Some(words);
+        }
+    }
+
+    /// Renders the window; returns what the user asked for
+    pub fn show(&mut self, ctx: &Context) -> Option<FrequentWordsAction> {
+        if !self.open {
+            return None;
+        }
+
+        let mut action = None;
+        let mut open = self.open;
+        egui::Window::new("高频词")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .default_width(280.0)
+            .default_height(420.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&self.document_name).strong());
+                    if ui
+                        .small_button("刷新")
+                        .on_hover_text("重新统计当前文档")
+                        .clicked()
+                    {
+                        action = Some(FrequentWordsAction::Refresh);
+                    }
+                });
+                ui.label(
+                    RichText::new("重复出现的词语，不含“的”“我们”等虚词；点击在文中标出")
+                        .small()
+                        .weak(),
+                );
+                ui.separator();
+
+                let Some(words) = &self.words else {
+                    ui.spinner();
+                    return;
+                };
+                if words.is_empty() {
+                    ui.label(RichText::new("没有重复的词语").weak());
+                    return;
+                }
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        egui::Grid::new("frequent_words")
+                            .num_columns(2)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for word in words {
+                                    if ui.link(&word.text).clicked() {
+                                        action = Some(FrequentWordsAction::Find {
+                                            tab: self.tab,
+                                            word: word.clone(),
+                                        });
+                                    }
+                                    ui.label(RichText::new(format!("{} 次", word.count)).weak());
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        self.open = open;
+        action
+    }
+}
@@ -0,0 +1,223 @@
+//! The 全局搜索 palette: searches the saved versions of every document, see
+//! [`crate::backend::global_search`]. Hits stream in file by file while the
+//! search runs; clicking one opens the document at that line.
+
+use crate::backend::global_search::{FileHits, MAX_HITS_PER_FILE, SearchHit, SearchSummary};
+use crate::ui::title_bar::document_label;
+use egui::text::{LayoutJob, TextFormat};
+use egui::{Align2, Context, CursorIcon, Key, RichText, Sense, TextStyle};
+use std::path::PathBuf;
+
+pub enum GlobalSearchAction {
+    /// Search every document for the query
+    Search(String),
+    /// Stop the running search
+    Cancel,
+    /// Open the document at `path` and go to `line`
+    Open { path: PathBuf, line: usize },
+}
+
+#[derive(Default)]
+pub struct GlobalSearchWindow {
+    open: bool,
+    /// Focus the query field on the next frame
+    focus_query: bool,
+    query: String,
+    /// Id of the search shown; hits of any other are dropped
+    search: u64,
+    running: bool,
+    files: Vec<FileHits>,
+    summary: Option<SearchSummary>,
+    error: Option<String>,
+}
+
+impl GlobalSearchWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the palette with the query field focused; the last results stay
+    pub fn open(&mut self) {
+        self.open = true;
+        self.focus_query = true;
+    }
+
+    /// Clears the results for a new search; returns its id
+    pub fn start(&mut self) -> u64 {
+        self.search += 1;
+        self.running = true;
+        self.files.clear();
+        self.summary = None;
+        self.error = None;
+        self.search
+    }
+
+    pub fn add_hits(&mut self, search: u64, hits: FileHits) {
+        if search == self.search {
+            self.files.push(hits);
+        }
+    }
+
+    pub fn finish(&mut self, search: u64, result: Result<SearchSummary, String>) {
+        if search != self.search {
+            return;
+        }
+        self.running = false;
+        match result {
+            Ok(summary) => self.summary = Some(summary),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Renders the palette; returns what the user asked for
+    pub fn show(&mut self, ctx: &Context) -> Option<GlobalSearchAction> {
+        if !self.open {
+            return None;
+        }
+
+        let mut action = None;
+        let mut open = self.open;
+        egui::Window::new("全局搜索")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .anchor(Align2::CENTER_TOP, [0.0, 48.0])
+            .default_width(520.0)
+            .default_height(460.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.query)
+                            .hint_text("在所有文档中搜索…")
+                            .desired_width(ui.available_width() - 56.0),
+                    );
+                    if std::mem::take(&mut self.focus_query) {
+                        response.request_focus();
+                    }
+                    let submitted =
+                        response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+                    if self.running {
+                        if ui.button("取消").clicked() {
+                            action = Some(GlobalSearchAction::Cancel);
+                        }
+                    } else if (ui.button("搜索").clicked() || submitted)
+                        && !self.query.trim().is_empty()
+                    {
+                        action = Some(GlobalSearchAction::Search(self.query.trim().to_string()));
+                    }
+                });
+                self.status(ui);
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for file in &self.files {
+                            Self::file(ui, file, &mut action);
+                        }
+                    });
+            });
+        // Closing the palette stops the search it was showing
+        if !open && self.running {
+            action = Some(GlobalSearchAction::Cancel);
+        }
+        self.open = open;
+        action
+    }
+
+    fn status(&self, ui: &mut egui::Ui) {
+        let hits: usize = self.files.iter().map(|file| file.hits.len()).sum();
+        if self.running {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(RichText::new(format!("搜索中… 已找到 {} 处", hits)).weak());
+            });
+        } else if let Some(error) = &self.error {
+            ui.label(RichText::new(format!("搜索失败：{}", error)).weak());
+        } else if let Some(summary) = &self.summary {
+            let mut text = if hits == 0 {
+                format!("{} 个文档中没有找到", summary.searched)
+            } else {
+                format!("{} 个文档中有 {} 处", self.files.len(), hits)
+            };
+            if summary.skipped > 0 {
+                text.push_str(&format!(
+                    "，{} 个过大或无法读取的文档未搜索",
+                    summary.skipped
+                ));
+            }
+            if summary.cancelled {
+                text.push_str("（已取消）");
+            }
+            ui.label(RichText::new(text).weak());
+        } else {
+            ui.label(
+                RichText::new("搜索各文档最近保存的版本；点击结果打开文档并跳到该行")
+                    .small()
+                    .weak(),
+            );
+        }
+    }
+
+    fn file(ui: &mut egui::Ui, file: &FileHits, action: &mut Option<GlobalSearchAction>) {
+        ui.add_space(4.0);
+        ui.label(RichText::new(document_label(file.path.as_deref(), false)).strong());
+        for hit in &file.hits {
+            let response = ui.add(egui::Label::new(snippet_job(ui, hit)).sense(Sense::click()));
+            match &file.path {
+                Some(path) => {
+                    if response
+                        .on_hover_cursor(CursorIcon::PointingHand)
+                        .on_hover_text("打开并跳到这一行")
+                        .clicked()
+                    {
+                        *action = Some(GlobalSearchAction::Open {
+                            path: path.clone(),
+                            line: hit.line,
+                        });
+                    }
+                }
+                None => {
+                    response.on_hover_text("不知道这个文档保存在哪里");
+                }
+            }
+        }
+        if file.truncated {
+            ui.label(
+                RichText::new(format!("只列出前 {} 行", MAX_HITS_PER_FILE))
+                    .small()
+                    .weak(),
+            );
+        }
+    }
+}
+
+/// The line number, then the snippet with the match highlighted
+fn snippet_job(ui: &egui::Ui, hit: &SearchHit) -> LayoutJob {
+    let format = TextFormat {
+        font_id: TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let mut job = LayoutJob::default();
+    job.append(
+        &format!("第 {} 行  ", hit.line + 1),
+        0.0,
+        TextFormat {
+            color: ui.visuals().weak_text_color(),
+            ..format.clone()
+        },
+    );
+    let snippet = &hit.snippet;
+    job.append(&snippet[..hit.highlight.start], 0.0, format.clone());
+    job.append(
+        &snippet[hit.highlight.clone()],
+        0.0,
+        TextFormat {
+            background: ui.visuals().selection.bg_fill,
+            ..format.clone()
+        },
+    );
+    job.append(&snippet[hit.highlight.end..], 0.0, format);
+    job
+}
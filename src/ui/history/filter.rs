@@ -0,0 +1,87 @@
+//! Which saved versions the history window shows.
+
+use crate::diff::{self, DiffLine, DiffOptions, calculate_stats};
+
+/// Versions left out of the history as not worth a look
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChangeFilter {
+    /// Every saved version is shown
+    Off,
+    /// Versions that only touched whitespace or blank lines are left out
+    #[default]
+    WhitespaceOnly,
+    /// Versions changing fewer characters than this are left out as well,
+    /// e.g. when only punctuation was fixed
+    Under(usize),
+}
+
+impl ChangeFilter {
+    pub const ALL: [ChangeFilter; 6] = [
+        ChangeFilter::Off,
+        ChangeFilter::WhitespaceOnly,
+        ChangeFilter::Under(5),
+        ChangeFilter::Under(10),
+        ChangeFilter::Under(20),
+        ChangeFilter::Under(50),
+    ];
+
+    pub fn label(self) -> String {
+        match self {
+            ChangeFilter::Off => "显示全部版本".to_string(),
+            ChangeFilter::WhitespaceOnly => "隐藏只改空白的版本".to_string(),
+            ChangeFilter::Under(chars) => format!("隐藏改动少于 {} 字的版本", chars),
+        }
+    }
+}
+
+/// Whether a version whose diff with the version before is `diff_lines`
+/// is shown under `filter`; lines are judged the way `options` compare them
+pub fn shows_version(diff_lines: &[DiffLine], filter: ChangeFilter, options: DiffOptions) -> bool {
+    match filter {
+        ChangeFilter::Off => true,
+        ChangeFilter::WhitespaceOnly => diff::has_meaningful_changes(diff_lines, options),
+        ChangeFilter::Under(min_chars) => {
+            if !diff::has_meaningful_changes(diff_lines, options) {
+                return false;
+            }
+            // Counted as in the stats, so moved paragraphs do not count
+            let rows = diff::detect_moves(diff::group_into_rows(diff_lines));
+            let stats = calculate_stats(&rows);
+            stats.added_count + stats.removed_count >= min_chars
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_diff;
+
+    fn shown(old: &str, new: &str, filter: ChangeFilter) -> bool {
+        shows_version(&compute_diff(old, new), filter, DiffOptions::default())
+    }
+
+    #[test]
+    fn test_each_filter_policy() {
+        let draft = "第一段。\n第二段。\n";
+        let blank_line = "第一段。\n\n第二段。\n";
+        let punctuation = "第一段！\n第二段。\n";
+        let rewrite = "第一段写得更长了一些。\n第二段。\n";
+
+        // Nothing changed at all
+        assert!(shown(draft, draft, ChangeFilter::Off));
+        assert!(!shown(draft, draft, ChangeFilter::WhitespaceOnly));
+
+        assert!(shown(draft, blank_line, ChangeFilter::Off));
+        assert!(!shown(draft, blank_line, ChangeFilter::WhitespaceOnly));
+        assert!(!shown(draft, blank_line, ChangeFilter::Under(5)));
+
+        // One character out, one in
+        assert!(shown(draft, punctuation, ChangeFilter::WhitespaceOnly));
+        assert!(shown(draft, punctuation, ChangeFilter::Under(2)));
+        assert!(!shown(draft, punctuation, ChangeFilter::Under(5)));
+
+        assert!(shown(draft, rewrite, ChangeFilter::Under(5)));
+        assert!(!shown(draft, rewrite, ChangeFilter::Under(50)));
+    }
+}
@@ -0,0 +1,127 @@
+//! The shape of a draft over its history: the words of each shown version
+//! against the time it was saved, and the words it added or removed on
+//! balance. Worked out once per history, with the per-version stats.
+
+use super::types::HistoryVersionData;
+use crate::segment::word_count;
+
+/// One shown version in the graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphPoint {
+    /// When the version was saved, from 0.0 for the first to 1.0 for the last
+    pub x: f32,
+    /// Words in the version
+    pub words: usize,
+    /// Words added less words removed since the version before
+    pub net_words: i64,
+}
+
+/// The points of the graph, one per shown version and in the same order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WordGraph {
+    pub points: Vec<GraphPoint>,
+    pub max_words: usize,
+    pub max_net_words: u64,
+}
+
+impl WordGraph {
+    pub fn from_versions(versions: &[HistoryVersionData]) -> Self {
+        let (Some(first), Some(last)) = (versions.first(), versions.last()) else {
+            return Self::default();
+        };
+        let start = first.entry.timestamp;
+        let span = (last.entry.timestamp - start).num_milliseconds();
+        let last_index = versions.len().saturating_sub(1).max(1);
+
+        let points: Vec<GraphPoint> = versions
+            .iter()
+            .enumerate()
+            .map(|(index, version)| {
+                let x = if span > 0 {
+                    (version.entry.timestamp - start).num_milliseconds() as f32 / span as f32
+                } else if versions.len() == 1 {
+                    0.5
+                } else {
+                    // All saved at once, so spread them out evenly
+                    index as f32 / last_index as f32
+                };
+                GraphPoint {
+                    x: x.clamp(0.0, 1.0),
+                    words: word_count(&version.content),
+                    net_words: version.added_words as i64 - version.removed_words as i64,
+                }
+            })
+            .collect();
+
+        Self {
+            max_words: points.iter().map(|point| point.words).max().unwrap_or(0),
+            max_net_words: points
+                .iter()
+                .map(|point| point.net_words.unsigned_abs())
+                .max()
+                .unwrap_or(0),
+            points,
+        }
+    }
+
+    /// The point nearest to `x`, on the same 0.0 to 1.0 scale
+    pub fn nearest(&self, x: f32) -> Option<usize> {
+        self.points
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (a.x - x).abs().total_cmp(&(b.x - x).abs()))
+            .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::editor_backend::HistoryEntry;
+    use chrono::{TimeZone, Utc};
+
+    fn version(minute: u32, content: &str, added: usize, removed: usize) -> HistoryVersionData {
+        HistoryVersionData {
+            entry: HistoryEntry {
+                hash: format!("{:016x}", minute),
+                timestamp: Utc.with_ymd_and_hms(2026, 3, 1, 9, minute, 0).unwrap(),
+                file_path: None,
+                time_spent: None,
+            },
+            content: content.to_string(),
+            rows: Vec::new(),
+            truncated: false,
+            added_count: 0,
+            removed_count: 0,
+            added_words: added,
+            removed_words: removed,
+        }
+    }
+
+    #[test]
+    fn test_graph_follows_words_over_time() {
+        let graph = WordGraph::from_versions(&[
+            version(0, "第一稿", 0, 0),
+            version(10, "第一稿 and more words", 3, 0),
+            version(40, "第二稿", 0, 4),
+        ]);
+
+        let xs: Vec<f32> = graph.points.iter().map(|point| point.x).collect();
+        assert_eq!(xs, [0.0, 0.25, 1.0]);
+        let words: Vec<usize> = graph.points.iter().map(|point| point.words).collect();
+        assert_eq!(words, [3, 6, 3]);
+        let nets: Vec<i64> = graph.points.iter().map(|point| point.net_words).collect();
+        assert_eq!(nets, [0, 3, -4]);
+        assert_eq!((graph.max_words, graph.max_net_words), (6, 4));
+
+        assert_eq!(graph.nearest(0.1), Some(0));
+        assert_eq!(graph.nearest(0.2), Some(1));
+        assert_eq!(graph.nearest(0.9), Some(2));
+        assert_eq!(WordGraph::default().nearest(0.5), None);
+
+        // Versions saved within the same instant are still told apart
+        let graph = WordGraph::from_versions(&[version(5, "a", 0, 0), version(5, "a b", 1, 0)]);
+        assert_eq!(graph.points[0].x, 0.0);
+        assert_eq!(graph.points[1].x, 1.0);
+    }
+}
@@ -1,13 +1,22 @@
-mod diff;
-mod stats;
+mod filter;
+mod graph;
 mod types;
 mod ui;
+mod viewport;
 
 use crate::backend::editor_backend::{EditorBackend, HistoryEntry};
+use crate::config::DEFAULT_FONT_SIZE;
+use crate::datetime::{DEFAULT_DATETIME_FORMAT, format_local};
+use crate::diff::{self, DiffLine, DiffLineType, DiffOptions};
+use crate::ui::markdown::is_markdown_path;
 use egui::{Color32, Context, RichText, ScrollArea, Ui};
+use filter::ChangeFilter;
+use graph::WordGraph;
+use viewport::RowHeights;
 
 // Re-export public types
-pub use types::{DiffLine, DiffLineType, HistoryVersionData};
+pub use types::HistoryVersionData;
+pub use ui::render_hunk_review;
 
 #[derive(Debug)]
 pub enum HistoryAction {
@@ -16,10 +25,24 @@ pub enum HistoryAction {
 
 pub struct HistoryWindow {
     open: bool,
+    /// Every saved version with its content, oldest first
+    versions: Vec<(HistoryEntry, String)>,
+    /// The versions with changes of their own, as shown
     history_data: Option<Vec<HistoryVersionData>>,
+    graph: WordGraph,
     selected_index: Option<usize>,
+    /// The version under the pointer in the graph, highlighted in the list
+    hovered_index: Option<usize>,
+    /// Row heights of the diff of the selected version, and its index
+    row_heights: Option<(usize, RowHeights)>,
     viewport_id: egui::ViewportId,
     pending_action: Option<HistoryAction>,
+    font_size: f32,
+    datetime_format: String,
+    /// Start of the hash of the version to select once the history arrives
+    wanted_version: Option<String>,
+    diff_options: DiffOptions,
+    change_filter: ChangeFilter,
 }
 
 impl Default for HistoryWindow {
@@ -32,10 +55,19 @@ impl HistoryWindow {
     pub fn new() -> Self {
         Self {
             open: false,
+            versions: Vec::new(),
             history_data: None,
+            graph: WordGraph::default(),
             selected_index: None,
+            hovered_index: None,
+            row_heights: None,
             viewport_id: egui::ViewportId::from_hash_of("history_window"),
             pending_action: None,
+            font_size: DEFAULT_FONT_SIZE,
+            datetime_format: DEFAULT_DATETIME_FORMAT.to_string(),
+            wanted_version: None,
+            diff_options: DiffOptions::default(),
+            change_filter: ChangeFilter::default(),
         }
     }
 
@@ -43,56 +75,136 @@ impl HistoryWindow {
         self.open = true;
     }
 
+    /// Select the version whose hash starts with `hash` when the history is
+    /// set next, instead of the latest
+    pub fn select_version(&mut self, hash: String) {
+        self.wanted_version = Some(hash);
+    }
+
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.font_size = font_size;
+    }
+
+    pub fn set_datetime_format(&mut self, format: &str) {
+        self.datetime_format = format.to_string();
+    }
+
     pub fn set_history(
         &mut self,
         entries: Vec<HistoryEntry>,
         backend: &EditorBackend,
     ) -> Result<(), String> {
-        let mut history_data: Vec<HistoryVersionData> = Vec::new();
+        self.versions = entries
+            .into_iter()
+            .map(|entry| {
+                // Load content for this version
+                let content = backend
+                    .restore_version(&entry.hash)
+                    .map_err(|e| e.to_string())?;
+                Ok((entry, content))
+            })
+            .collect::<Result<_, String>>()?;
+        let wanted = self.wanted_version.take();
+        self.compare_versions(wanted);
+        Ok(())
+    }
 
-        for entry in entries.iter() {
-            // Load content for this version
-            let content = backend
-                .restore_version(&entry.hash)
-                .map_err(|e| e.to_string())?;
+    /// Diff the versions with the options set, leaving out those the change
+    /// filter hides, and select the version whose hash starts with
+    /// `wanted`, or the latest
+    fn compare_versions(&mut self, wanted: Option<String>) {
+        let options = self.diff_options;
+        let change_filter = self.change_filter;
+        let mut history_data: Vec<HistoryVersionData> = Vec::new();
 
+        for (entry, content) in &self.versions {
             // Calculate diff with previous meaningful version
-            let diff_lines = if !history_data.is_empty() {
+            let (diff_lines, truncated) = if !history_data.is_empty() {
                 let prev_content = &history_data.last().unwrap().content;
-                diff::compute_diff(prev_content, &content)
+                diff::compute_diff_capped(prev_content, content, options)
             } else {
                 // First version - show full content as unchanged
-                content
+                let (shown, truncated) = diff::first_lines(content, diff::MAX_DIFF_LINES);
+                let lines = shown
                     .lines()
                     .map(|line| DiffLine {
                         line_type: DiffLineType::Unchanged,
                         content: line.to_string(),
                     })
-                    .collect()
+                    .collect();
+                (lines, truncated)
             };
 
-            // Check if this version has meaningful changes
-            let has_changes = history_data.is_empty() || diff::has_meaningful_changes(&diff_lines);
+            // Check if this version has changes worth showing
+            let has_changes = history_data.is_empty()
+                || filter::shows_version(&diff_lines, change_filter, options);
 
             if has_changes {
                 // Calculate stats
-                let rows = diff::group_into_rows(&diff_lines);
-                let stats = stats::calculate_stats(&rows);
+                let rows = diff::detect_moves(diff::group_into_rows(&diff_lines));
+                let stats = diff::calculate_stats(&rows);
 
                 history_data.push(HistoryVersionData {
                     entry: entry.clone(),
-                    content,
-                    diff_lines,
+                    content: content.clone(),
                     added_count: stats.added_count,
                     removed_count: stats.removed_count,
+                    added_words: stats.added_words,
+                    removed_words: stats.removed_words,
+                    rows,
+                    truncated,
                 });
             }
         }
 
-        let data_len = history_data.len();
+        // The wanted version, or the latest; a version without changes of
+        // its own is shown as the one before it
+        let latest = history_data.len().saturating_sub(1);
+        let entries = &self.versions;
+        self.selected_index = Some(
+            wanted
+                .and_then(|hash| {
+                    let wanted = entries
+                        .iter()
+                        .position(|(entry, _)| entry.hash.starts_with(&hash))?;
+                    history_data.iter().rposition(|data| {
+                        entries[..=wanted]
+                            .iter()
+                            .any(|(entry, _)| entry.hash == data.entry.hash)
+                    })
+                })
+                .unwrap_or(latest),
+        );
+        self.graph = WordGraph::from_versions(&history_data);
+        self.hovered_index = None;
+        self.row_heights = None;
         self.history_data = Some(history_data);
-        self.selected_index = Some(data_len.saturating_sub(1)); // Select latest
-        Ok(())
+    }
+
+    fn set_diff_options(&mut self, options: DiffOptions) {
+        if options != self.diff_options {
+            self.diff_options = options;
+            self.recompare();
+        }
+    }
+
+    fn set_change_filter(&mut self, change_filter: ChangeFilter) {
+        if change_filter != self.change_filter {
+            self.change_filter = change_filter;
+            self.recompare();
+        }
+    }
+
+    /// Compare the loaded versions again after the options changed
+    fn recompare(&mut self) {
+        if let Some(history_data) = &self.history_data {
+            // Stay on the version shown, or the one before it if it goes
+            let selected = self
+                .selected_index
+                .and_then(|index| history_data.get(index))
+                .map(|data| data.entry.hash.clone());
+            self.compare_versions(selected);
+        }
     }
 
     pub fn show(&mut self, ctx: &Context) {
@@ -151,6 +263,26 @@ impl HistoryWindow {
             // Title
             ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
                 ui.label("📜 History");
+                ui.add_space(16.0);
+
+                let mut options = self.diff_options;
+                ui.checkbox(&mut options.ignore_whitespace, "忽略空白差异")
+                    .on_hover_text("比较时把连续的空白当作一个，不计行首行尾的空白");
+                if options.ignore_whitespace {
+                    ui.checkbox(&mut options.ignore_full_width_indent, "含全角缩进")
+                        .on_hover_text("行首的全角空格也不算差异");
+                }
+                self.set_diff_options(options);
+
+                let mut change_filter = self.change_filter;
+                egui::ComboBox::from_id_salt("history_change_filter")
+                    .selected_text(change_filter.label())
+                    .show_ui(ui, |ui| {
+                        for option in ChangeFilter::ALL {
+                            ui.selectable_value(&mut change_filter, option, option.label());
+                        }
+                    });
+                self.set_change_filter(change_filter);
             });
 
             // Window Controls
@@ -179,6 +311,7 @@ impl HistoryWindow {
     }
 
     fn show_content(&mut self, ui: &mut Ui) {
+        let font_size = self.font_size;
         if let Some(history_data) = &self.history_data {
             if history_data.is_empty() {
                 ui.vertical_centered(|ui| {
@@ -190,6 +323,17 @@ impl HistoryWindow {
                 return;
             }
 
+            if history_data.len() > 1 {
+                egui::TopBottomPanel::top("history_graph").show_inside(ui, |ui| {
+                    let (hovered, clicked) =
+                        ui::render_word_graph(ui, &self.graph, self.selected_index);
+                    self.hovered_index = hovered;
+                    if clicked && hovered.is_some() {
+                        self.selected_index = hovered;
+                    }
+                });
+            }
+
             // Use SidePanel for better layout (left panel for versions)
             egui::SidePanel::left("version_list_panel")
                 .resizable(true)
@@ -198,15 +342,14 @@ impl HistoryWindow {
                         // Show in reverse order (newest first)
                         for (i, version_data) in history_data.iter().enumerate().rev() {
                             let is_selected = self.selected_index == Some(i);
-                            let timestamp = version_data
-                                .entry
-                                .timestamp
-                                .format("%Y-%m-%d %H:%M:%S")
-                                .to_string();
-
-                            let version_label = timestamp.to_string();
+                            let version_label =
+                                format_local(&version_data.entry.timestamp, &self.datetime_format);
 
-                            if ui.selectable_label(is_selected, version_label).clicked() {
+                            let mut response = ui.selectable_label(is_selected, version_label);
+                            if self.hovered_index == Some(i) {
+                                response = response.highlight();
+                            }
+                            if response.clicked() {
                                 self.selected_index = Some(i);
                             }
                         }
@@ -222,13 +365,30 @@ impl HistoryWindow {
                             ui.with_layout(
                                 egui::Layout::left_to_right(egui::Align::Center),
                                 |ui| {
+                                    let added = Color32::from_rgb(0, 100, 0);
+                                    let removed = Color32::from_rgb(150, 0, 0);
+                                    ui.label(
+                                        RichText::new(format!("+{} 字", version_data.added_count))
+                                            .color(added),
+                                    );
                                     ui.label(
-                                        RichText::new(format!("+{}", version_data.added_count))
-                                            .color(Color32::from_rgb(0, 100, 0)),
+                                        RichText::new(format!(
+                                            "-{} 字",
+                                            version_data.removed_count
+                                        ))
+                                        .color(removed),
                                     );
                                     ui.label(
-                                        RichText::new(format!("-{}", version_data.removed_count))
-                                            .color(Color32::from_rgb(150, 0, 0)),
+                                        RichText::new(format!("+{} 词", version_data.added_words))
+                                            .color(added),
+                                    )
+                                    .on_hover_text("按词计：中文每字一词，其他文字按空格分词");
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "-{} 词",
+                                            version_data.removed_words
+                                        ))
+                                        .color(removed),
                                     );
                                     let time = version_data.entry.time_spent.unwrap_or(0);
                                     let hours = time / 3600;
@@ -259,6 +419,18 @@ impl HistoryWindow {
                                                         ));
                                                     self.open = false; // Close the window after rollback
                                                 }
+                                                if ui
+                                                    .button("📋 复制为补丁")
+                                                    .on_hover_text(
+                                                        "把与上一版本的差异复制为统一格式补丁",
+                                                    )
+                                                    .clicked()
+                                                {
+                                                    ui.ctx().copy_text(version_patch(
+                                                        history_data,
+                                                        selected_idx,
+                                                    ));
+                                                }
                                             },
                                         );
                                     });
@@ -274,11 +446,33 @@ impl HistoryWindow {
                         ui.separator();
                         ui.add_space(8.0);
 
-                        ScrollArea::vertical()
-                            .auto_shrink([false, false])
-                            .show(ui, |ui| {
-                                ui::render_diff_view(ui, &version_data.diff_lines);
-                            });
+                        if version_data.truncated {
+                            ui.label(
+                                RichText::new(format!(
+                                    "文件过大，仅显示前 {} 行差异",
+                                    diff::MAX_DIFF_LINES
+                                ))
+                                .color(ui.visuals().warn_fg_color),
+                            );
+                            ui.add_space(4.0);
+                        }
+
+                        let markdown = version_data
+                            .entry
+                            .file_path
+                            .as_deref()
+                            .is_some_and(is_markdown_path);
+                        if self
+                            .row_heights
+                            .as_ref()
+                            .is_some_and(|(index, _)| *index != selected_idx)
+                        {
+                            self.row_heights = None;
+                        }
+                        let (_, heights) = self.row_heights.get_or_insert_with(|| {
+                            (selected_idx, RowHeights::new(&version_data.rows))
+                        });
+                        ui::render_diff_view(ui, &version_data.rows, heights, font_size, markdown);
                     }
                 } else {
                     ui.vertical_centered(|ui| {
@@ -299,3 +493,29 @@ impl HistoryWindow {
         }
     }
 }
+
+/// The changes of `history_data[index]` since the version shown before it,
+/// as a unified patch. Compared line for line whatever the diff options, so
+/// the patch applies.
+fn version_patch(history_data: &[HistoryVersionData], index: usize) -> String {
+    let new = &history_data[index];
+    let old_content = index
+        .checked_sub(1)
+        .and_then(|before| history_data.get(before))
+        .map_or("", |old| old.content.as_str());
+    let name = new
+        .entry
+        .file_path
+        .as_deref()
+        .and_then(|path| path.file_name())
+        .map_or_else(
+            || "untitled".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+    diff::unified_patch(
+        &diff::compute_diff(old_content, &new.content),
+        &format!("a/{}", name),
+        &format!("b/{}", name),
+        diff::PATCH_CONTEXT,
+    )
+}
@@ -1,29 +1,16 @@
 use crate::backend::editor_backend::HistoryEntry;
-
-#[derive(Debug, Clone)]
-pub enum DiffRow {
-    Unchanged(String),
-    Pair(Vec<DiffLine>, Vec<DiffLine>),
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum DiffLineType {
-    Added,
-    Removed,
-    Unchanged,
-}
-
-#[derive(Debug, Clone)]
-pub struct DiffLine {
-    pub line_type: DiffLineType,
-    pub content: String,
-}
+use crate::diff::DiffRow;
 
 #[derive(Debug, Clone)]
 pub struct HistoryVersionData {
     pub entry: HistoryEntry,
     pub content: String,
-    pub diff_lines: Vec<DiffLine>,
+    /// The diff with the version before, moved blocks found
+    pub rows: Vec<DiffRow>,
+    /// Whether the texts were too long to compare in full
+    pub truncated: bool,
     pub added_count: usize,
     pub removed_count: usize,
+    pub added_words: usize,
+    pub removed_words: usize,
 }
@@ -1,7 +1,12 @@
-use super::diff;
-use super::types::{DiffLine, DiffRow};
-use egui::{Color32, FontId, RichText, TextFormat, Ui, Vec2, text::LayoutJob};
-use similar::{ChangeTag, TextDiff};
+use super::graph::WordGraph;
+use super::viewport::RowHeights;
+use crate::diff::{self, DiffLine, DiffRow};
+use crate::ui::markdown::{LineKind, line_kind};
+use egui::{
+    Color32, FontId, Pos2, Rect, RichText, ScrollArea, Sense, Shape, Stroke, TextFormat, Ui, Vec2,
+    text::LayoutJob,
+};
+use similar::ChangeTag;
 
 // Color constants for better maintainability
 const REMOVED_LINE_BG: Color32 = Color32::from_rgb(255, 230, 230);
@@ -10,75 +15,328 @@ const REMOVED_WORD_BG: Color32 = Color32::from_rgb(255, 170, 170);
 const ADDED_WORD_BG: Color32 = Color32::from_rgb(170, 255, 170);
 const REMOVED_TEXT_COLOR: Color32 = Color32::from_rgb(150, 0, 0);
 const ADDED_TEXT_COLOR: Color32 = Color32::from_rgb(0, 100, 0);
+const MOVED_LINE_BG: Color32 = Color32::from_rgb(225, 235, 255);
+const MOVED_TEXT_COLOR: Color32 = Color32::from_rgb(20, 60, 150);
+const LIST_MARKER_COLOR: Color32 = Color32::from_rgb(180, 110, 20);
+const GRAPH_LINE_COLOR: Color32 = Color32::from_rgb(70, 110, 190);
+const GRAPH_HEIGHT: f32 = 80.0;
+/// Share of the graph's height taken by the added/removed bars below the line
+const GRAPH_BAR_SHARE: f32 = 0.3;
+/// Diff row height relative to the font size, for better spacing
+const LINE_HEIGHT_RATIO: f32 = 24.0 / 14.0;
 
-/// Render the diff view with word-level highlighting
-pub fn render_diff_view(ui: &mut Ui, diff_lines: &[DiffLine]) {
-    ui.style_mut().spacing.item_spacing.y = 1.0;
+/// Render the diff view with word-level highlighting, styling headings,
+/// quotes and list markers of `markdown` documents.
+///
+/// Only the rows in view are laid out, so long diffs scroll smoothly;
+/// `heights` stands in for the others.
+pub fn render_diff_view(
+    ui: &mut Ui,
+    rows: &[DiffRow],
+    heights: &mut RowHeights,
+    font_size: f32,
+    markdown: bool,
+) {
+    ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .show_viewport(ui, |ui, viewport| {
+            ui.style_mut().spacing.item_spacing.y = 1.0;
+            let spacing = ui.spacing().item_spacing.y;
+
+            // Calculate column width based on current available space
+            let total_available = ui.available_width();
+            // Subtract a little padding to prevent horizontal scrollbar jitter
+            // We need space for 2 columns + separator (approx 1.0 width + spacing)
+            let col_w = (total_available / 2.0 - 15.0).max(100.0);
+
+            heights.set_layout(total_available, font_size);
+            let line_height = font_size * LINE_HEIGHT_RATIO;
+            let (visible, top) = heights.visible(viewport.min.y, viewport.max.y, line_height);
+
+            // Room for the rows above and below the view
+            ui.add_space(top);
+            let mut bottom = top;
+            for row_idx in visible {
+                let drawn = ui.scope(|ui| {
+                    render_row(ui, row_idx, &rows[row_idx], col_w, font_size, markdown);
+                });
+                let height = drawn.response.rect.height() + spacing;
+                heights.measure(row_idx, height);
+                bottom += height;
+            }
+            ui.add_space((heights.total(line_height) - bottom).max(0.0));
+        });
+}
+
+/// Render one row of the diff
+fn render_row(
+    ui: &mut Ui,
+    row_idx: usize,
+    row: &DiffRow,
+    col_w: f32,
+    font_size: f32,
+    markdown: bool,
+) {
+    match row {
+        DiffRow::Unchanged(text) if markdown => {
+            let mut job = LayoutJob::default();
+            let format = TextFormat {
+                font_id: FontId::monospace(font_size),
+                color: ui.visuals().text_color(),
+                ..Default::default()
+            };
+            let strong = ui.visuals().strong_text_color();
+            append_markdown(&mut job, text, 0, line_kind(text), format, strong);
+            ui.add(egui::Label::new(job).wrap());
+        }
+        DiffRow::Unchanged(text) => {
+            // full-width single row for unchanged content
+            ui.add(egui::Label::new(RichText::new(text).monospace().size(font_size)).wrap());
+        }
+        DiffRow::Pair(left_block, right_block) => {
+            render_pair(
+                ui,
+                row_idx,
+                left_block,
+                right_block,
+                col_w,
+                font_size,
+                markdown,
+            );
+        }
+        DiffRow::Moved {
+            lines,
+            from_paragraph,
+            exact,
+        } => {
+            render_moved(ui, lines, *from_paragraph, *exact, font_size);
+        }
+    }
+}
+
+/// Render the word count of each version against time, with the words it
+/// added or removed on balance as bars below. Returns the version under the
+/// pointer, and whether it was clicked.
+pub fn render_word_graph(
+    ui: &mut Ui,
+    graph: &WordGraph,
+    selected: Option<usize>,
+) -> (Option<usize>, bool) {
+    let (rect, response) = ui.allocate_exact_size(
+        Vec2::new(ui.available_width(), GRAPH_HEIGHT),
+        Sense::click(),
+    );
+    let painter = ui.painter_at(rect);
+    let plot = rect.shrink2(Vec2::new(8.0, 6.0));
+    let faint = ui.visuals().weak_text_color();
+
+    let bar_half = plot.height() * GRAPH_BAR_SHARE / 2.0;
+    let baseline = plot.bottom() - bar_half;
+    let line_bottom = baseline - bar_half - 4.0;
+    let x_of = |x: f32| plot.left() + x * plot.width();
+    let y_of = |words: usize| {
+        let share = words as f32 / graph.max_words.max(1) as f32;
+        line_bottom - share * (line_bottom - plot.top())
+    };
+
+    painter.line_segment(
+        [
+            Pos2::new(plot.left(), baseline),
+            Pos2::new(plot.right(), baseline),
+        ],
+        Stroke::new(1.0, faint.gamma_multiply(0.4)),
+    );
+    for point in &graph.points {
+        if point.net_words == 0 {
+            continue;
+        }
+        let height = point.net_words as f32 / graph.max_net_words.max(1) as f32 * bar_half;
+        let x = x_of(point.x);
+        let color = if point.net_words > 0 {
+            ADDED_TEXT_COLOR
+        } else {
+            REMOVED_TEXT_COLOR
+        };
+        painter.rect_filled(
+            Rect::from_two_pos(
+                Pos2::new(x - 2.0, baseline),
+                Pos2::new(x + 2.0, baseline - height),
+            ),
+            0.0,
+            color.gamma_multiply(0.6),
+        );
+    }
 
-    let rows = diff::group_into_rows(diff_lines);
+    let line: Vec<Pos2> = graph
+        .points
+        .iter()
+        .map(|point| Pos2::new(x_of(point.x), y_of(point.words)))
+        .collect();
+    painter.add(Shape::line(
+        line.clone(),
+        Stroke::new(1.5, GRAPH_LINE_COLOR),
+    ));
+
+    let hovered = response
+        .hover_pos()
+        .and_then(|pos| graph.nearest((pos.x - plot.left()) / plot.width()));
+    for (index, center) in line.iter().enumerate() {
+        if selected == Some(index) {
+            painter.circle_filled(*center, 4.0, GRAPH_LINE_COLOR);
+        } else {
+            painter.circle_filled(*center, 2.5, GRAPH_LINE_COLOR);
+        }
+        if hovered == Some(index) {
+            painter.circle_stroke(*center, 6.0, Stroke::new(1.5, GRAPH_LINE_COLOR));
+        }
+    }
+
+    let clicked = response.clicked();
+    if let Some(point) = hovered.and_then(|index| graph.points.get(index)) {
+        response.on_hover_text(format!("{} 词（{:+}）", point.words, point.net_words));
+    }
+    (hovered, clicked)
+}
+
+/// Render grouped diff rows with an accept checkbox above each hunk.
+///
+/// `accepted` holds one flag per `DiffRow::Pair`, in order.
+pub fn render_hunk_review(ui: &mut Ui, rows: &[DiffRow], accepted: &mut [bool], font_size: f32) {
+    ui.style_mut().spacing.item_spacing.y = 1.0;
 
-    // Calculate column width based on current available space
     let total_available = ui.available_width();
-    // Subtract a little padding to prevent horizontal scrollbar jitter
-    // We need space for 2 columns + separator (approx 1.0 width + spacing)
     let col_w = (total_available / 2.0 - 15.0).max(100.0);
 
+    let mut hunk = 0usize;
     for (row_idx, row) in rows.iter().enumerate() {
         match row {
             DiffRow::Unchanged(text) => {
-                // full-width single row for unchanged content
-                ui.add(egui::Label::new(RichText::new(text).monospace().size(14.0)).wrap());
+                ui.add(egui::Label::new(RichText::new(text).monospace().size(font_size)).wrap());
             }
             DiffRow::Pair(left_block, right_block) => {
-                // CRITICAL FIX: Use push_id to ensure every Grid has a unique ID
-                ui.push_id(row_idx, |ui| {
-                    egui::Grid::new("diff_pair_grid")
-                        .num_columns(3) // Left, Separator, Right
-                        .min_col_width(0.0)
-                        .spacing(Vec2::new(0.0, 0.0)) // Tight spacing, we handle padding in Frame
-                        .show(ui, |ui| {
-                            let max = left_block.len().max(right_block.len());
-
-                            for i in 0..max {
-                                let left_content = left_block.get(i).map(|l| l.content.as_str());
-                                let right_content = right_block.get(i).map(|r| r.content.as_str());
-
-                                // Left Column
-                                render_word_highlight(
-                                    ui,
-                                    left_content,
-                                    right_content,
-                                    true, // is_left
-                                    col_w,
-                                );
-
-                                // Right Column
-                                render_word_highlight(
-                                    ui,
-                                    left_content,
-                                    right_content,
-                                    false, // is_right
-                                    col_w,
-                                );
-
-                                ui.end_row();
-                            }
-                        });
-                });
+                if let Some(flag) = accepted.get_mut(hunk) {
+                    ui.add_space(4.0);
+                    ui.checkbox(flag, format!("采纳修改 {}", hunk + 1));
+                }
+                render_pair(
+                    ui,
+                    row_idx,
+                    left_block,
+                    right_block,
+                    col_w,
+                    font_size,
+                    false,
+                );
+                hunk += 1;
+            }
+            DiffRow::Moved {
+                lines,
+                from_paragraph,
+                exact,
+            } => {
+                render_moved(ui, lines, *from_paragraph, *exact, font_size);
             }
         }
     }
 }
 
-/// Render a single cell with word-level highlighting
+/// Render lines moved from elsewhere at full width, with where they were
+fn render_moved(
+    ui: &mut Ui,
+    lines: &[DiffLine],
+    from_paragraph: usize,
+    exact: bool,
+    font_size: f32,
+) {
+    egui::Frame::default()
+        .fill(MOVED_LINE_BG)
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.set_min_width(ui.available_width());
+            let hint = if exact {
+                format!("↕ 原位置：第 {} 段", from_paragraph)
+            } else {
+                format!("↕ 原位置：第 {} 段（略有改动）", from_paragraph)
+            };
+            ui.label(RichText::new(hint).small().color(MOVED_TEXT_COLOR));
+            for line in lines {
+                ui.add(
+                    egui::Label::new(
+                        RichText::new(&line.content)
+                            .monospace()
+                            .size(font_size)
+                            .color(MOVED_TEXT_COLOR),
+                    )
+                    .wrap(),
+                );
+            }
+        });
+}
+
+/// Render a removed/added block side by side
+fn render_pair(
+    ui: &mut Ui,
+    row_idx: usize,
+    left_block: &[DiffLine],
+    right_block: &[DiffLine],
+    col_w: f32,
+    font_size: f32,
+    markdown: bool,
+) {
+    // CRITICAL FIX: Use push_id to ensure every Grid has a unique ID
+    ui.push_id(row_idx, |ui| {
+        egui::Grid::new("diff_pair_grid")
+            .num_columns(3) // Left, Separator, Right
+            .min_col_width(0.0)
+            .spacing(Vec2::new(0.0, 0.0)) // Tight spacing, we handle padding in Frame
+            .show(ui, |ui| {
+                let max = left_block.len().max(right_block.len());
+
+                for i in 0..max {
+                    let left_content = left_block.get(i).map(|l| l.content.as_str());
+                    let right_content = right_block.get(i).map(|r| r.content.as_str());
+
+                    // Left Column
+                    render_word_highlight(
+                        ui,
+                        left_content,
+                        right_content,
+                        true, // is_left
+                        col_w,
+                        font_size,
+                        markdown,
+                    );
+
+                    // Right Column
+                    render_word_highlight(
+                        ui,
+                        left_content,
+                        right_content,
+                        false, // is_right
+                        col_w,
+                        font_size,
+                        markdown,
+                    );
+
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+/// Render a single cell with word-level highlighting; text in the base
+/// color is styled as markdown when `markdown` is set
 pub fn render_word_highlight(
     ui: &mut Ui,
     left: Option<&str>,
     right: Option<&str>,
     is_left: bool,
     width: f32,
+    font_size: f32,
+    markdown: bool,
 ) {
-    let font_id = FontId::monospace(14.0);
+    let font_id = FontId::monospace(font_size);
+    let line_height = Some(font_size * LINE_HEIGHT_RATIO);
 
     let (line_bg, prefix) = if is_left {
         (REMOVED_LINE_BG, "- ")
@@ -112,6 +370,12 @@ pub fn render_word_highlight(
 
             let mut job = LayoutJob::default();
             let base_text_color = ui.visuals().text_color();
+            let strong = ui.visuals().strong_text_color();
+            let own = if is_left { left } else { right };
+            let kind = match own {
+                Some(line) if markdown => line_kind(line),
+                _ => LineKind::Plain,
+            };
 
             // Add Prefix
             job.append(
@@ -120,87 +384,120 @@ pub fn render_word_highlight(
                 TextFormat {
                     font_id: font_id.clone(),
                     color: base_text_color.gamma_multiply(0.5),
-                    line_height: Some(24.0), // Add line height for better spacing
+                    line_height,
                     ..Default::default()
                 },
             );
 
             match (left, right) {
-                (Some(l), Some(r)) => {
-                    // Perform character-level diff (better for CJK)
-                    let diff = TextDiff::from_chars(l, r);
-
-                    for change in diff.iter_all_changes() {
-                        let text = change.value();
-                        match change.tag() {
-                            ChangeTag::Equal => {
-                                job.append(
-                                    text,
-                                    0.0,
-                                    TextFormat {
-                                        font_id: font_id.clone(),
-                                        color: base_text_color,
-                                        line_height: Some(24.0), // Add line height
-                                        ..Default::default()
-                                    },
-                                );
+                // Perform character-level diff (better for CJK)
+                (Some(l), Some(r)) => match diff::char_changes(l, r) {
+                    Some(changes) => {
+                        // Where in its own line the next piece starts
+                        let mut offset = 0;
+                        for (tag, text) in changes {
+                            let start = offset;
+                            if tag == ChangeTag::Equal || (tag == ChangeTag::Delete) == is_left {
+                                offset += text.len();
                             }
-                            ChangeTag::Delete => {
-                                if is_left {
-                                    job.append(
+                            match tag {
+                                ChangeTag::Equal => {
+                                    append_markdown(
+                                        &mut job,
                                         text,
-                                        0.0,
+                                        start,
+                                        kind,
                                         TextFormat {
                                             font_id: font_id.clone(),
-                                            color: REMOVED_TEXT_COLOR,
-                                            background: REMOVED_WORD_BG, // High contrast highlight ON TOP of frame
-                                            line_height: Some(24.0),     // Add line height
+                                            color: base_text_color,
+                                            line_height,
                                             ..Default::default()
                                         },
+                                        strong,
                                     );
                                 }
-                            }
-                            ChangeTag::Insert => {
-                                if !is_left {
-                                    job.append(
-                                        text,
-                                        0.0,
-                                        TextFormat {
-                                            font_id: font_id.clone(),
-                                            color: ADDED_TEXT_COLOR,
-                                            background: ADDED_WORD_BG, // High contrast highlight ON TOP of frame
-                                            line_height: Some(24.0),   // Add line height
-                                            ..Default::default()
-                                        },
-                                    );
+                                ChangeTag::Delete => {
+                                    if is_left {
+                                        job.append(
+                                            text,
+                                            0.0,
+                                            TextFormat {
+                                                font_id: font_id.clone(),
+                                                color: REMOVED_TEXT_COLOR,
+                                                background: REMOVED_WORD_BG, // High contrast highlight ON TOP of frame
+                                                line_height,
+                                                ..Default::default()
+                                            },
+                                        );
+                                    }
+                                }
+                                ChangeTag::Insert => {
+                                    if !is_left {
+                                        job.append(
+                                            text,
+                                            0.0,
+                                            TextFormat {
+                                                font_id: font_id.clone(),
+                                                color: ADDED_TEXT_COLOR,
+                                                background: ADDED_WORD_BG, // High contrast highlight ON TOP of frame
+                                                line_height,
+                                                ..Default::default()
+                                            },
+                                        );
+                                    }
                                 }
                             }
                         }
                     }
-                }
+                    // Too long or too unlike to compare: the whole line in
+                    // its side's color
+                    None => {
+                        let (text, color) = if is_left {
+                            (l, REMOVED_TEXT_COLOR)
+                        } else {
+                            (r, ADDED_TEXT_COLOR)
+                        };
+                        job.append(
+                            text,
+                            0.0,
+                            TextFormat {
+                                font_id: font_id.clone(),
+                                color,
+                                line_height,
+                                ..Default::default()
+                            },
+                        );
+                    }
+                },
                 // Fallback for purely added or purely removed lines (no pair match)
                 (Some(l), None) if is_left => {
-                    job.append(
+                    append_markdown(
+                        &mut job,
                         l,
-                        0.0,
+                        0,
+                        kind,
                         TextFormat {
                             font_id: font_id.clone(),
                             color: base_text_color,
-                            line_height: Some(24.0), // Add line height
+                            line_height,
                             ..Default::default()
                         },
+                        strong,
                     );
                 }
                 (None, Some(r)) if !is_left => {
-                    job.append(
+                    append_markdown(
+                        &mut job,
                         r,
-                        0.0,
+                        0,
+                        kind,
                         TextFormat {
                             font_id: font_id.clone(),
                             color: base_text_color,
-                            line_height: Some(24.0), // Add line height
+                            line_height,
                             ..Default::default()
                         },
+                        strong,
                     );
                 }
                 _ => {}
@@ -210,3 +507,33 @@ pub fn render_word_highlight(
             ui.add(egui::Label::new(job).wrap());
         });
 }
+
+/// Append `text`, which starts at byte `start` of a line of `kind`, over
+/// `format`: headings in `strong`, quotes dimmed, list markers colored
+fn append_markdown(
+    job: &mut LayoutJob,
+    text: &str,
+    start: usize,
+    kind: LineKind,
+    mut format: TextFormat,
+    strong: Color32,
+) {
+    match kind {
+        LineKind::Heading => format.color = strong,
+        LineKind::Quote => format.color = format.color.gamma_multiply(0.6),
+        LineKind::ListItem { marker_len } if start < marker_len => {
+            let split = (marker_len - start).min(text.len());
+            let marker = TextFormat {
+                color: LIST_MARKER_COLOR,
+                ..format.clone()
+            };
+            job.append(&text[..split], 0.0, marker);
+            if split < text.len() {
+                job.append(&text[split..], 0.0, format);
+            }
+            return;
+        }
+        LineKind::ListItem { .. } | LineKind::Plain => {}
+    }
+    job.append(text, 0.0, format);
+}
@@ -0,0 +1,135 @@
+//! Heights of diff rows, for drawing only the rows in view.
+//!
+//! Rows not drawn yet are guessed from their number of text lines; drawing
+//! a row records its real height, which holds until the width or the font
+//! size changes.
+
+use crate::diff::DiffRow;
+use std::ops::Range;
+
+pub struct RowHeights {
+    /// Text lines in each row, for guessing the rows not drawn yet
+    lines: Vec<usize>,
+    measured: Vec<Option<f32>>,
+    /// Width and font size the measured heights hold for
+    layout: (f32, f32),
+}
+
+impl RowHeights {
+    pub fn new(rows: &[DiffRow]) -> Self {
+        let lines: Vec<usize> = rows
+            .iter()
+            .map(|row| match row {
+                DiffRow::Unchanged(_) => 1,
+                DiffRow::Pair(left, right) => left.len().max(right.len()).max(1),
+                // The hint above the lines
+                DiffRow::Moved { lines, .. } => lines.len() + 1,
+            })
+            .collect();
+        Self {
+            measured: vec![None; lines.len()],
+            lines,
+            layout: (0.0, 0.0),
+        }
+    }
+
+    /// Forget the measured heights when the rows are laid out anew
+    pub fn set_layout(&mut self, width: f32, font_size: f32) {
+        if self.layout != (width, font_size) {
+            self.layout = (width, font_size);
+            self.measured.iter_mut().for_each(|height| *height = None);
+        }
+    }
+
+    pub fn measure(&mut self, index: usize, height: f32) {
+        if let Some(measured) = self.measured.get_mut(index) {
+            *measured = Some(height);
+        }
+    }
+
+    /// Height of row `index`, as drawn or guessed at `line_height` a line
+    pub fn height(&self, index: usize, line_height: f32) -> f32 {
+        self.measured[index].unwrap_or(self.lines[index] as f32 * line_height)
+    }
+
+    pub fn total(&self, line_height: f32) -> f32 {
+        (0..self.lines.len())
+            .map(|index| self.height(index, line_height))
+            .sum()
+    }
+
+    /// The rows reaching into `top..bottom`, and where the first one starts
+    pub fn visible(&self, top: f32, bottom: f32, line_height: f32) -> (Range<usize>, f32) {
+        let mut y = 0.0;
+        let mut first = None;
+        for index in 0..self.lines.len() {
+            let height = self.height(index, line_height);
+            match first {
+                None if y + height > top => first = Some((index, y)),
+                Some((start, start_y)) if y >= bottom => return (start..index, start_y),
+                _ => {}
+            }
+            y += height;
+        }
+        match first {
+            Some((start, start_y)) => (start..self.lines.len(), start_y),
+            None => (self.lines.len()..self.lines.len(), y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{compute_diff, detect_moves, group_into_rows};
+
+    #[test]
+    fn test_only_rows_in_view_of_a_long_diff_are_drawn() {
+        // Every fourth line of a long draft rewritten
+        let old: String = (0..8000)
+            .map(|i| format!("第 {} 行，原来的句子。\n", i))
+            .collect();
+        let new: String = old
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match i % 4 {
+                0 => format!("{}改过了\n", line),
+                _ => format!("{}\n", line),
+            })
+            .collect();
+        let rows = detect_moves(group_into_rows(&compute_diff(&old, &new)));
+        assert!(rows.len() >= 5000);
+
+        let line_height = 20.0;
+        let mut heights = RowHeights::new(&rows);
+        heights.set_layout(800.0, 14.0);
+        let guessed = heights.total(line_height);
+        let total = guessed;
+        let view = 600.0;
+        let most_rows = (view / line_height) as usize + 2;
+
+        let mut top = 0.0;
+        while top < total {
+            let (range, start_y) = heights.visible(top, top + view, line_height);
+            assert!(!range.is_empty() && range.len() <= most_rows);
+            assert!(start_y <= top);
+            // Drawn rows turn out taller than guessed
+            for index in range {
+                heights.measure(index, line_height * 1.5);
+            }
+            top += view;
+        }
+
+        // The last row is in view at the very bottom, however far it moved
+        let total = heights.total(line_height);
+        let (range, _) = heights.visible(total - 1.0, total, line_height);
+        assert_eq!(range.end, rows.len());
+        let (range, start_y) = heights.visible(total + 10.0, total + 20.0, line_height);
+        assert!(range.is_empty());
+        assert_eq!(start_y, total);
+
+        // A new width brings back the guesses
+        heights.set_layout(900.0, 14.0);
+        assert_eq!(heights.total(line_height), guessed);
+    }
+}
@@ -0,0 +1,96 @@
+//! Hidden window showing the end of the current log file.
+//!
+//! Toggled with Cmd/Ctrl + Shift + Alt + L, so a user reporting a problem
+//! can read or copy what the app logged without finding the log folder.
+
+use crate::logging::{current_log_file, tail};
+use egui::{Context, Key, KeyboardShortcut, Modifiers, RichText};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const TOGGLE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(
+    Modifiers::COMMAND
+        .plus(Modifiers::SHIFT)
+        .plus(Modifiers::ALT),
+    Key::L,
+);
+
+/// How much of the end of the log is shown
+const TAIL_BYTES: u64 = 64 * 1024;
+/// How often the log is read again while the window is open
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct LogViewerWindow {
+    open: bool,
+    file: Option<PathBuf>,
+    /// End of the log, or why it could not be read
+    text: String,
+    last_read: Option<Instant>,
+}
+
+impl LogViewerWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flips visibility when the toggle chord is pressed.
+    pub fn handle_shortcut(&mut self, ctx: &Context) {
+        if ctx.input_mut(|input| input.consume_shortcut(&TOGGLE_SHORTCUT)) {
+            self.open = !self.open;
+            self.last_read = None;
+        }
+    }
+
+    /// Renders the window, reading the newest log in `log_dir` again every
+    /// [`REFRESH_INTERVAL`].
+    pub fn show(&mut self, ctx: &Context, log_dir: &Path) {
+        if !self.open {
+            return;
+        }
+        if self
+            .last_read
+            .is_none_or(|read| read.elapsed() >= REFRESH_INTERVAL)
+        {
+            self.refresh(log_dir);
+        }
+        ctx.request_repaint_after(REFRESH_INTERVAL);
+
+        let mut open = self.open;
+        egui::Window::new("日志")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .default_size([560.0, 360.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let file = match &self.file {
+                        Some(file) => file.display().to_string(),
+                        None => "没有日志文件".to_string(),
+                    };
+                    ui.label(RichText::new(file).small().weak());
+                    if ui.small_button("复制").clicked() {
+                        ctx.copy_text(self.text.clone());
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::both()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        ui.label(RichText::new(&self.text).monospace().small());
+                    });
+            });
+        self.open = open;
+    }
+
+    fn refresh(&mut self, log_dir: &Path) {
+        self.last_read = Some(Instant::now());
+        self.file = current_log_file(log_dir);
+        self.text = match &self.file {
+            Some(file) => tail(file, TAIL_BYTES).unwrap_or_else(|e| format!("无法读取日志：{}", e)),
+            None => String::new(),
+        };
+    }
+}
@@ -0,0 +1,383 @@
+//! Minimal markdown rendering for model replies.
+//!
+//! Covers what chat models commonly emit: paragraphs, headings, ordered and
+//! unordered lists, fenced code blocks, and `**bold**`, `*italic*` and
+//! `` `code` `` spans. Anything else is shown as plain text.
+//!
+//! [`line_kind`] tells the same headings and list items apart line by line,
+//! plus blockquotes, for the history diff of markdown documents.
+
+use egui::text::LayoutJob;
+use egui::{Color32, FontFamily, FontId, RichText, TextFormat, Ui};
+use std::path::Path;
+
+/// Extensions of the documents written in markdown
+pub const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown"];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+}
+
+/// What a single line is in markdown, for styling it where it stands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    Heading,
+    Quote,
+    ListItem {
+        /// Bytes taken by the indentation, the marker and the space after it
+        marker_len: usize,
+    },
+    Plain,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Span>),
+    Heading(Vec<Span>),
+    ListItem {
+        /// Number shown for ordered items, `None` for bullets
+        number: Option<u64>,
+        depth: usize,
+        spans: Vec<Span>,
+        /// The item text without its marker, for copying
+        raw: String,
+    },
+    CodeBlock(String),
+}
+
+/// Split `text` into blocks. Consecutive plain lines form one paragraph.
+pub fn parse_blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+
+    let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(parse_inline(&paragraph.join("\n"))));
+            paragraph.clear();
+        }
+    };
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            match code.take() {
+                Some(lines) => blocks.push(Block::CodeBlock(lines.join("\n"))),
+                None => {
+                    flush(&mut paragraph, &mut blocks);
+                    code = Some(Vec::new());
+                }
+            }
+            continue;
+        }
+        if let Some(lines) = code.as_mut() {
+            lines.push(line);
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+        } else if let Some(heading) = heading_text(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Heading(parse_inline(heading)));
+        } else if let Some((number, item)) = list_item(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            let indent = line.len() - trimmed.len();
+            blocks.push(Block::ListItem {
+                number,
+                depth: indent / 2,
+                spans: parse_inline(item),
+                raw: item.to_string(),
+            });
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    // An unterminated fence still shows its contents
+    if let Some(lines) = code {
+        blocks.push(Block::CodeBlock(lines.join("\n")));
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+pub fn is_markdown_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            MARKDOWN_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Classify one line on its own, without the lines around it
+pub fn line_kind(line: &str) -> LineKind {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    if heading_text(trimmed).is_some() {
+        LineKind::Heading
+    } else if trimmed.starts_with('>') {
+        LineKind::Quote
+    } else if let Some((_, marker_len)) = list_marker(trimmed) {
+        LineKind::ListItem {
+            marker_len: indent + marker_len,
+        }
+    } else {
+        LineKind::Plain
+    }
+}
+
+fn heading_text(line: &str) -> Option<&str> {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) {
+        line[hashes..].strip_prefix(' ').map(str::trim)
+    } else {
+        None
+    }
+}
+
+fn list_item(line: &str) -> Option<(Option<u64>, &str)> {
+    let (number, marker_len) = list_marker(line)?;
+    Some((number, line[marker_len..].trim()))
+}
+
+/// The number of an ordered item, `None` for bullets, and the bytes taken by
+/// the marker and the space after it
+fn list_marker(line: &str) -> Option<(Option<u64>, usize)> {
+    for bullet in ["- ", "* ", "+ ", "• "] {
+        if line.starts_with(bullet) {
+            return Some((None, bullet.len()));
+        }
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    let number = line[..digits].parse().ok()?;
+    let rest = &line[digits..];
+    (rest.starts_with(". ") || rest.starts_with(") ")).then_some((Some(number), digits + 2))
+}
+
+/// Split a line into styled spans. Unmatched markers are kept as text.
+pub fn parse_inline(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let styled = [
+            ("`", false, false, true),
+            ("**", true, false, false),
+            ("__", true, false, false),
+            ("*", false, true, false),
+        ]
+        .into_iter()
+        .find_map(|(marker, bold, italic, code)| {
+            let inner = rest.strip_prefix(marker)?;
+            let end = inner.find(marker)?;
+            (end > 0).then(|| {
+                (
+                    Span {
+                        text: inner[..end].to_string(),
+                        bold,
+                        italic,
+                        code,
+                    },
+                    &inner[end + marker.len()..],
+                )
+            })
+        });
+
+        match styled {
+            Some((span, remaining)) => {
+                if !plain.is_empty() {
+                    spans.push(Span {
+                        text: std::mem::take(&mut plain),
+                        ..Default::default()
+                    });
+                }
+                spans.push(span);
+                rest = remaining;
+            }
+            None => {
+                let mut chars = rest.chars();
+                if let Some(c) = chars.next() {
+                    plain.push(c);
+                }
+                rest = chars.as_str();
+            }
+        }
+    }
+
+    if !plain.is_empty() {
+        spans.push(Span {
+            text: plain,
+            ..Default::default()
+        });
+    }
+    spans
+}
+
+/// Render `text` as markdown. Each list item gets a copy button.
+pub fn render_markdown(ui: &mut Ui, text: &str, size: f32, color: Color32) {
+    for (index, block) in parse_blocks(text).iter().enumerate() {
+        match block {
+            Block::Paragraph(spans) => {
+                ui.label(layout_spans(spans, size, color, false));
+            }
+            Block::Heading(spans) => {
+                ui.label(layout_spans(spans, size + 1.0, color, true));
+            }
+            Block::ListItem {
+                number,
+                depth,
+                spans,
+                raw,
+            } => {
+                ui.horizontal_top(|ui| {
+                    ui.add_space(*depth as f32 * 12.0);
+                    let marker = match number {
+                        Some(number) => format!("{}.", number),
+                        None => "•".to_string(),
+                    };
+                    ui.label(RichText::new(marker).size(size).color(color));
+                    let copy = ui
+                        .push_id(index, |ui| {
+                            ui.small_button(RichText::new("📋").size(size - 2.0))
+                                .on_hover_text("复制这一项")
+                        })
+                        .inner;
+                    if copy.clicked() {
+                        ui.ctx().copy_text(raw.clone());
+                    }
+                    ui.label(layout_spans(spans, size, color, false));
+                });
+            }
+            Block::CodeBlock(code) => {
+                egui::Frame::new()
+                    .fill(Color32::from_gray(232))
+                    .corner_radius(4.0)
+                    .inner_margin(egui::Margin::same(6))
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        ui.label(
+                            RichText::new(code)
+                                .monospace()
+                                .size(size - 1.0)
+                                .color(color),
+                        );
+                    });
+            }
+        }
+    }
+}
+
+fn layout_spans(spans: &[Span], size: f32, color: Color32, strong: bool) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    for span in spans {
+        let font_id = if span.code {
+            FontId::new(size - 1.0, FontFamily::Monospace)
+        } else {
+            FontId::proportional(size)
+        };
+        let format = TextFormat {
+            font_id,
+            color: if span.bold || strong {
+                Color32::from_gray(20)
+            } else {
+                color
+            },
+            italics: span.italic,
+            background: if span.code {
+                Color32::from_gray(230)
+            } else {
+                Color32::TRANSPARENT
+            },
+            ..Default::default()
+        };
+        job.append(&span.text, 0.0, format);
+    }
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Span {
+        Span {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_inline_styles() {
+        let spans = parse_inline("普通 **加粗** 和 *斜体* 以及 `code`");
+        assert_eq!(spans.len(), 6);
+        assert!(spans[1].bold && spans[1].text == "加粗");
+        assert!(spans[3].italic && spans[3].text == "斜体");
+        assert!(spans[5].code && spans[5].text == "code");
+    }
+
+    #[test]
+    fn unmatched_markers_stay_literal() {
+        assert_eq!(parse_inline("2 * 3 = 6"), vec![plain("2 * 3 = 6")]);
+        assert_eq!(parse_inline("**未闭合"), vec![plain("**未闭合")]);
+    }
+
+    #[test]
+    fn parses_lists_paragraphs_and_code() {
+        let text = "# 建议\n第一段\n继续\n\n1. 改标题\n2) 删掉**冗余**\n  - 子项\n```\nlet x = 1;\n```\n结尾";
+        let blocks = parse_blocks(text);
+
+        assert_eq!(blocks.len(), 7);
+        assert!(matches!(&blocks[0], Block::Heading(spans) if spans[0].text == "建议"));
+        assert!(matches!(&blocks[1], Block::Paragraph(spans) if spans[0].text == "第一段\n继续"));
+        assert!(matches!(
+            &blocks[2],
+            Block::ListItem { number: Some(1), depth: 0, raw, .. } if raw == "改标题"
+        ));
+        assert!(matches!(
+            &blocks[3],
+            Block::ListItem { number: Some(2), raw, .. } if raw == "删掉**冗余**"
+        ));
+        assert!(matches!(
+            &blocks[4],
+            Block::ListItem {
+                number: None,
+                depth: 1,
+                ..
+            }
+        ));
+        assert_eq!(blocks[5], Block::CodeBlock("let x = 1;".to_string()));
+        assert!(matches!(&blocks[6], Block::Paragraph(_)));
+    }
+
+    #[test]
+    fn classifies_single_lines() {
+        assert_eq!(line_kind("## 第二章"), LineKind::Heading);
+        assert_eq!(line_kind("#话题"), LineKind::Plain);
+        assert_eq!(line_kind("> 引文"), LineKind::Quote);
+        assert_eq!(line_kind("- 一项"), LineKind::ListItem { marker_len: 2 });
+        assert_eq!(
+            line_kind("  12. 一项"),
+            LineKind::ListItem { marker_len: 6 }
+        );
+        assert_eq!(line_kind("• 一项"), LineKind::ListItem { marker_len: 4 });
+        assert_eq!(line_kind("2024年"), LineKind::Plain);
+        assert_eq!(line_kind("**加粗**开头"), LineKind::Plain);
+
+        assert!(is_markdown_path(Path::new("草稿.MD")));
+        assert!(!is_markdown_path(Path::new("草稿.txt")));
+    }
+
+    #[test]
+    fn unterminated_code_fence_keeps_contents() {
+        let blocks = parse_blocks("```rust\nfn main() {}");
+        assert_eq!(blocks, vec![Block::CodeBlock("fn main() {}".to_string())]);
+    }
+}
@@ -1,9 +1,24 @@
 pub mod ai_panel;
+pub mod ai_panel_frame;
+pub mod ai_review;
+pub mod all_marks;
 pub mod editor;
+pub mod error_log;
 pub mod font;
+pub mod font_preview;
+pub mod frequent_words;
+pub mod global_search;
 pub mod history;
+pub mod log_viewer;
+pub mod markdown;
+pub mod onboarding;
 pub mod plugins;
 pub mod settings;
 pub mod sidebar;
+pub mod stats;
+pub mod tab_bar;
+pub mod time_debug;
 pub mod title_bar;
+pub mod toast;
 pub mod viewport;
+pub mod window_frame;
@@ -0,0 +1,207 @@
+//! First-run dialog: where data is kept, which font and which theme.
+//!
+//! The dialog only draws one step and reports what was clicked; the steps
+//! themselves are driven by the app.
+
+use crate::config::THEMES;
+use egui::{Align2, Context, RichText, Ui};
+use std::path::{Path, PathBuf};
+
+/// Sentence the font step renders so the choice can be judged
+const PREVIEW_TEXT: &str = "春眠不觉晓，处处闻啼鸟。The quick brown fox jumps over the lazy dog.";
+
+/// Steps of the first-run dialog, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnboardingStep {
+    DataDir,
+    Font,
+    Theme,
+}
+
+impl OnboardingStep {
+    pub const ALL: [OnboardingStep; 3] = [
+        OnboardingStep::DataDir,
+        OnboardingStep::Font,
+        OnboardingStep::Theme,
+    ];
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|step| *step == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Option<Self> {
+        Self::ALL.get(self.index() + 1).copied()
+    }
+
+    pub fn previous(self) -> Option<Self> {
+        self.index().checked_sub(1).map(|index| Self::ALL[index])
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            OnboardingStep::DataDir => "数据保存位置",
+            OnboardingStep::Font => "字体",
+            OnboardingStep::Theme => "主题",
+        }
+    }
+}
+
+/// What the user picked so far
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnboardingChoices {
+    /// `None` keeps the default data dir
+    pub data_dir: Option<PathBuf>,
+    /// `None` keeps the system font
+    pub font_family: Option<String>,
+    pub theme: String,
+}
+
+pub enum OnboardingAction {
+    Back,
+    Next,
+    Finish,
+    Skip,
+}
+
+/// What the dialog shows besides the choices
+pub struct OnboardingContext<'a> {
+    pub fonts: &'a [String],
+    pub default_data_dir: &'a Path,
+    /// Portable mode keeps data next to the executable; nothing to choose
+    pub portable: bool,
+}
+
+/// Draw `step`, letting the user change `choices`.
+pub fn show(
+    ctx: &Context,
+    step: OnboardingStep,
+    choices: &mut OnboardingChoices,
+    context: &OnboardingContext,
+) -> Option<OnboardingAction> {
+    let mut action = None;
+    egui::Window::new("欢迎使用纸壳")
+        .collapsible(false)
+        .resizable(false)
+        .default_width(380.0)
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label(
+                RichText::new(format!(
+                    "{} / {} · {}",
+                    step.index() + 1,
+                    OnboardingStep::ALL.len(),
+                    step.title()
+                ))
+                .strong(),
+            );
+            ui.add_space(8.0);
+
+            match step {
+                OnboardingStep::DataDir => show_data_dir(ui, choices, context),
+                OnboardingStep::Font => show_font(ui, choices, context.fonts),
+                OnboardingStep::Theme => show_theme(ui, choices),
+            }
+
+            ui.add_space(12.0);
+            ui.horizontal(|ui| {
+                if ui
+                    .button("跳过")
+                    .on_hover_text("全部使用默认设置，之后可在设置中修改")
+                    .clicked()
+                {
+                    action = Some(OnboardingAction::Skip);
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if step.next().is_some() {
+                        if ui.button("下一步").clicked() {
+                            action = Some(OnboardingAction::Next);
+                        }
+                    } else if ui.button("完成").clicked() {
+                        action = Some(OnboardingAction::Finish);
+                    }
+                    if step.previous().is_some() && ui.button("上一步").clicked() {
+                        action = Some(OnboardingAction::Back);
+                    }
+                });
+            });
+        });
+    action
+}
+
+fn show_data_dir(ui: &mut Ui, choices: &mut OnboardingChoices, context: &OnboardingContext) {
+    ui.label("历史版本、标记和写作记录将保存在：");
+    let dir = choices
+        .data_dir
+        .clone()
+        .unwrap_or_else(|| context.default_data_dir.to_path_buf());
+    ui.add(egui::Label::new(RichText::new(dir.display().to_string()).monospace()).truncate());
+
+    if context.portable {
+        ui.label(
+            RichText::new("便携模式：数据保存在程序所在的文件夹旁")
+                .small()
+                .weak(),
+        );
+        return;
+    }
+    ui.horizontal(|ui| {
+        if ui.button("选择其他文件夹…").clicked()
+            && let Some(dir) = rfd::FileDialog::new().set_directory(&dir).pick_folder()
+        {
+            choices.data_dir = Some(dir);
+        }
+        if choices.data_dir.is_some() && ui.button("使用默认位置").clicked() {
+            choices.data_dir = None;
+        }
+    });
+}
+
+fn show_font(ui: &mut Ui, choices: &mut OnboardingChoices, fonts: &[String]) {
+    ui.label("选择正文使用的中文字体：");
+    egui::ComboBox::from_id_salt("onboarding_font")
+        .width(240.0)
+        .selected_text(choices.font_family.as_deref().unwrap_or("系统默认"))
+        .show_ui(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(240.0)
+                .show(ui, |ui| {
+                    ui.selectable_value(&mut choices.font_family, None, "系统默认");
+                    for font in fonts {
+                        ui.selectable_value(&mut choices.font_family, Some(font.clone()), font);
+                    }
+                });
+        });
+    ui.add_space(8.0);
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.label(RichText::new(PREVIEW_TEXT).size(18.0));
+    });
+}
+
+fn show_theme(ui: &mut Ui, choices: &mut OnboardingChoices) {
+    ui.horizontal(|ui| {
+        for (value, label) in THEMES {
+            ui.radio_value(&mut choices.theme, value.to_string(), label);
+        }
+    });
+    ui.label(
+        RichText::new("字号、快捷键等更多选项在「设置」中")
+            .small()
+            .weak(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_go_forward_and_back_in_order() {
+        assert_eq!(OnboardingStep::DataDir.next(), Some(OnboardingStep::Font));
+        assert_eq!(OnboardingStep::Theme.next(), None);
+        assert_eq!(
+            OnboardingStep::Font.previous(),
+            Some(OnboardingStep::DataDir)
+        );
+        assert_eq!(OnboardingStep::DataDir.previous(), None);
+    }
+}
@@ -5,6 +5,8 @@
 //! by calling [`PluginOutputWindow::start`] when launching a plugin and
 //! [`PluginOutputWindow::finish`] when the result arrives.
 
+use crate::config::{PRINT_MARGIN_RANGE, PrintSettings};
+use crate::print::{self, Page, PageLayout, PageSize, PrintJob};
 use egui::{Color32, Context, RichText, Vec2};
 
 #[derive(Default)]
@@ -232,24 +234,33 @@ impl GithubPublishConfigWindow {
     }
 }
 
+impl Default for GithubPublishConfigWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct PublishParams {
     pub title: String,
     pub description: Option<String>,
     pub collection_dir: String,
 }
 
-pub struct PrintParams {
-    pub printer: Option<String>,
-    pub margin_points: u16,
-}
-
+/// The 打印预览 window: pick a printer and page setup, see the pages as
+/// they will print
 pub struct PrintDialog {
     open: bool,
     document_name: String,
-    preview: String,
+    content: String,
+    /// Put before each paragraph; `None` prints paragraphs flush left
+    indent: Option<String>,
+    page_size: PageSize,
+    margin_points: u16,
+    /// `content` laid out for `laid_out_for`
+    pages: Vec<Page>,
+    laid_out_for: Option<(PageSize, u16)>,
     printers: Vec<String>,
     selected_printer: usize,
-    margin_points: u16,
     viewport_id: egui::ViewportId,
 }
 
@@ -258,28 +269,48 @@ impl PrintDialog {
         Self {
             open: false,
             document_name: String::new(),
-            preview: String::new(),
+            content: String::new(),
+            indent: None,
+            page_size: PageSize::default(),
+            margin_points: 72,
+            pages: Vec::new(),
+            laid_out_for: None,
             printers: Vec::new(),
             selected_printer: 0,
-            margin_points: 72,
             viewport_id: egui::ViewportId::from_hash_of("print_preview_window"),
         }
     }
 
-    pub fn open(&mut self, document_name: String, preview: String) {
+    /// Preview `content` with the page setup of `settings`
+    pub fn open(
+        &mut self,
+        document_name: String,
+        content: String,
+        settings: &PrintSettings,
+        indent: Option<&str>,
+    ) {
         self.document_name = document_name;
-        self.preview = preview;
-        self.printers = crate::plugin::builtin::print::available_printers();
+        self.content = content;
+        self.indent = indent.map(str::to_string);
+        self.page_size = settings.page_size;
+        self.margin_points = settings.margin_points;
+        self.laid_out_for = None;
+        self.printers = print::available_printers();
         self.selected_printer = 0;
-        self.margin_points = 72;
         self.open = true;
     }
 
-    pub fn show(&mut self, ctx: &Context) -> Option<PrintParams> {
+    pub fn show(&mut self, ctx: &Context) -> Option<PrintJob> {
         if !self.open {
             return None;
         }
 
+        if self.laid_out_for != Some((self.page_size, self.margin_points)) {
+            let layout = PageLayout::new(self.page_size, self.margin_points);
+            self.pages = print::paginate(&self.content, layout, self.indent.as_deref());
+            self.laid_out_for = Some((self.page_size, self.margin_points));
+        }
+
         let mut submitted = None;
 
         ctx.show_viewport_immediate(
@@ -308,14 +339,22 @@ impl PrintDialog {
                             });
 
                         ui.add_space(12.0);
-                        ui.label("边距");
-                        egui::ComboBox::from_id_salt("print_margin")
-                            .selected_text(format!("{} pt", self.margin_points))
+                        ui.label("纸张");
+                        egui::ComboBox::from_id_salt("print_page_size")
+                            .selected_text(self.page_size.label())
                             .show_ui(ui, |ui| {
-                                ui.selectable_value(&mut self.margin_points, 54, "54 pt");
-                                ui.selectable_value(&mut self.margin_points, 72, "72 pt");
-                                ui.selectable_value(&mut self.margin_points, 90, "90 pt");
+                                for size in PageSize::ALL {
+                                    ui.selectable_value(&mut self.page_size, size, size.label());
+                                }
                             });
+
+                        ui.add_space(12.0);
+                        ui.label("边距");
+                        ui.add(
+                            egui::DragValue::new(&mut self.margin_points)
+                                .range(PRINT_MARGIN_RANGE)
+                                .suffix(" pt"),
+                        );
                     });
 
                     if self.printers.is_empty() {
@@ -326,28 +365,51 @@ impl PrintDialog {
                     ui.separator();
                     ui.add_space(8.0);
 
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(&self.document_name).strong());
+                        ui.label(RichText::new(format!("共 {} 页", self.pages.len())).weak());
+                    });
+                    ui.add_space(8.0);
                     let margin = self.margin_points as f32 / 3.0;
-                    egui::Frame::new()
-                        .fill(Color32::from_rgb(248, 247, 244))
-                        .stroke(egui::Stroke::new(1.0, Color32::from_rgb(205, 202, 194)))
-                        .inner_margin(egui::Margin::same(margin as i8))
+                    egui::ScrollArea::vertical()
+                        .max_height(460.0)
+                        .auto_shrink([false, true])
                         .show(ui, |ui| {
-                            ui.set_min_height(420.0);
-                            ui.label(RichText::new(&self.document_name).strong());
-                            ui.add_space(8.0);
-                            egui::ScrollArea::vertical()
-                                .max_height(460.0)
-                                .show(ui, |ui| {
-                                    ui.label(RichText::new(&self.preview).monospace());
+                            for (index, page) in self.pages.iter().enumerate() {
+                                egui::Frame::new()
+                                    .fill(Color32::from_rgb(248, 247, 244))
+                                    .stroke(egui::Stroke::new(
+                                        1.0,
+                                        Color32::from_rgb(205, 202, 194),
+                                    ))
+                                    .inner_margin(egui::Margin::same(margin as i8))
+                                    .show(ui, |ui| {
+                                        ui.set_width(ui.available_width());
+                                        ui.label(RichText::new(page.join("\n")).monospace());
+                                    });
+                                ui.vertical_centered(|ui| {
+                                    ui.label(
+                                        RichText::new(format!("第 {} 页", index + 1))
+                                            .small()
+                                            .weak(),
+                                    );
                                 });
+                                ui.add_space(8.0);
+                            }
                         });
 
                     ui.add_space(10.0);
                     ui.horizontal(|ui| {
-                        if ui.button("打印").clicked() {
-                            submitted = Some(PrintParams {
-                                printer: self.selected_printer_name(),
+                        if ui
+                            .add_enabled(!self.pages.is_empty(), egui::Button::new("打印"))
+                            .clicked()
+                        {
+                            submitted = Some(PrintJob {
+                                document_name: self.document_name.clone(),
+                                pages: self.pages.clone(),
+                                page_size: self.page_size,
                                 margin_points: self.margin_points,
+                                printer: self.selected_printer_name(),
                             });
                             self.open = false;
                             ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
@@ -388,6 +450,12 @@ impl PrintDialog {
     }
 }
 
+impl Default for PrintDialog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct PublishDialog {
     open: bool,
     title: String,
@@ -483,3 +551,9 @@ impl PublishDialog {
         published
     }
 }
+
+impl Default for PublishDialog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,122 +1,1284 @@
-use crate::config::AiPanelConfig;
+use crate::backend::ai_backend::AiProvider;
+use crate::backend::file_settings::FileSettings;
+use crate::backend::usage_log::{UsageLog, day_total, estimate_cost, format_tokens};
+use crate::config::{
+    AUTOSAVE_RANGE, AiPanelConfig, CHARS_PER_PAGE_RANGE, Config, ConfigError,
+    DEFAULT_SYSTEM_INSTRUCTION, FONT_SIZE_RANGE, FONT_Y_OFFSET_RANGE, FormatIndent,
+    LINE_SPACING_RANGE, LINE_WIDTH_RANGE, ModelPrice, OversizeStrategy, PRINT_MARGIN_RANGE,
+    PromptTemplate, RECENT_FILES_RANGE, Settings, THEMES, export_settings, import_settings,
+};
+use crate::datetime::{
+    DEFAULT_DATETIME_FORMAT, PLACEHOLDERS, expand_placeholders, format_local, validate_format,
+};
+use crate::logging::LogLevel;
+use crate::print::PageSize;
+use crate::shortcuts::{self, Action, KeyCombo};
+use egui::{Context, RichText, Ui};
+use std::path::PathBuf;
+
+/// Days shown in the usage history table
+const USAGE_HISTORY_DAYS: usize = 30;
+
+pub enum SettingsAction {
+    /// Persist the edited settings and apply what can change while running.
+    ///
+    /// `file` holds the overrides of the open file, if one is open.
+    Apply {
+        settings: Box<Settings>,
+        file: Option<FileSettings>,
+    },
+    /// Check the draft AI configuration against the provider.
+    TestConnection(AiPanelConfig),
+    /// Replace all settings with imported ones, persist and apply them.
+    Import(Box<Settings>),
+    /// Open the data directory in the system file manager.
+    OpenDataFolder,
+    /// Open the folder of the log files in the system file manager.
+    OpenLogFolder,
+    /// Drop the fonts kept in memory for quick switching.
+    ClearFontCache,
+    /// Make the system's "open with" menu offer the app.
+    RegisterFileAssociation,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SettingsSection {
+    #[default]
+    Appearance,
+    Editing,
+    Shortcuts,
+    Data,
+    Ai,
+    CurrentFile,
+}
+
+impl SettingsSection {
+    const ALL: [SettingsSection; 6] = [
+        SettingsSection::Appearance,
+        SettingsSection::Editing,
+        SettingsSection::Shortcuts,
+        SettingsSection::Data,
+        SettingsSection::Ai,
+        SettingsSection::CurrentFile,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SettingsSection::Appearance => "外观",
+            SettingsSection::Editing => "编辑",
+            SettingsSection::Shortcuts => "快捷键",
+            SettingsSection::Data => "数据",
+            SettingsSection::Ai => "AI 助手",
+            SettingsSection::CurrentFile => "此文件的设置",
+        }
+    }
+}
 
-#[derive(Default)]
 pub struct SettingsWindow {
     is_open: bool,
-    draft: AiPanelConfig,
+    section: SettingsSection,
+    draft: Settings,
+    /// The draft as it was when the window opened, to tell if it was edited
+    opened_with: String,
+    /// Name and overrides of the open file; `None` while it is unsaved
+    file_draft: Option<(String, FileSettings)>,
+    /// Fonts offered for a file's font override
+    fonts: Vec<String>,
+    data_dir: PathBuf,
+    config_path: Option<PathBuf>,
+    testing_connection: bool,
+    connection_result: Option<Result<String, String>>,
+    /// Whether the API key can be moved to the OS keychain
+    keychain_available: bool,
+    /// Whether the saved API key is in the keychain
+    key_in_keychain: bool,
+    usage: UsageLog,
+    /// Action waiting for a key combo to be pressed
+    capturing: Option<Action>,
+    /// Why the last pressed combo was not taken
+    capture_hint: Option<&'static str>,
+    /// Put recent files, the save folder and window placement in exports
+    export_machine_specific: bool,
+    /// Outcome of the last export or import
+    transfer_result: Option<Result<String, String>>,
+    viewport_id: egui::ViewportId,
+}
+
+impl Default for SettingsWindow {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SettingsWindow {
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            is_open: false,
+            section: SettingsSection::default(),
+            draft: Settings::default(),
+            opened_with: String::new(),
+            file_draft: None,
+            fonts: Vec::new(),
+            data_dir: PathBuf::new(),
+            config_path: None,
+            testing_connection: false,
+            connection_result: None,
+            keychain_available: false,
+            key_in_keychain: false,
+            usage: UsageLog::new(),
+            capturing: None,
+            capture_hint: None,
+            export_machine_specific: false,
+            transfer_result: None,
+            viewport_id: egui::ViewportId::from_hash_of("settings_window"),
+        }
+    }
+
+    pub fn set_available_fonts(&mut self, fonts: Vec<String>) {
+        self.fonts = fonts;
     }
 
-    pub fn open(&mut self, ai_config: &AiPanelConfig) {
-        self.draft = ai_config.clone();
+    /// `file` is the open file's name and overrides, if it has been saved.
+    pub fn open(
+        &mut self,
+        config: &Config,
+        usage: &UsageLog,
+        file: Option<(String, FileSettings)>,
+    ) {
+        self.draft = config.settings.clone();
+        self.opened_with = toml::to_string(&self.draft).unwrap_or_default();
+        self.file_draft = file;
+        self.data_dir = config.data_dir();
+        self.config_path = Config::config_path().ok();
+        self.keychain_available = config.keychain_available();
+        self.key_in_keychain = config.settings.ai_panel.api_key_in_keychain;
+        self.usage = usage.clone();
         self.is_open = true;
+        self.testing_connection = false;
+        self.connection_result = None;
+        self.capturing = None;
+        self.capture_hint = None;
+        self.transfer_result = None;
+    }
+
+    /// Whether the window is open with changes not yet saved
+    pub fn has_unsaved_edits(&self) -> bool {
+        self.is_open && toml::to_string(&self.draft).ok().as_ref() != Some(&self.opened_with)
     }
 
-    pub fn show(&mut self, ctx: &egui::Context) -> Option<AiPanelConfig> {
+    /// Whether key presses are being recorded for a new shortcut
+    pub fn is_capturing_shortcut(&self) -> bool {
+        self.is_open && self.capturing.is_some()
+    }
+
+    /// Shows the outcome of a "测试连接" check.
+    pub fn set_connection_result(&mut self, result: Result<String, String>) {
+        self.testing_connection = false;
+        self.connection_result = Some(result);
+    }
+
+    pub fn show(&mut self, ctx: &Context) -> Option<SettingsAction> {
         if !self.is_open {
             return None;
         }
 
-        let mut saved = None;
-        let mut is_open = self.is_open;
-        let mut should_close = false;
-
-        egui::Window::new("设置")
-            .open(&mut is_open)
-            .collapsible(false)
-            .resizable(false)
-            .default_width(420.0)
-            .show(ctx, |ui| {
-                ui.label(egui::RichText::new("AI 助手").strong());
-                ui.add_space(8.0);
+        let mut action = None;
+        ctx.show_viewport_immediate(
+            self.viewport_id,
+            egui::ViewportBuilder::default()
+                .with_title("设置")
+                .with_inner_size([600.0, 520.0])
+                .with_min_inner_size([480.0, 360.0])
+                .with_decorations(false)
+                .with_resizable(true)
+                .with_transparent(true),
+            |ctx, _class| {
+                egui::TopBottomPanel::top("settings_title_bar").show(ctx, |ui| {
+                    self.show_title_bar(ui);
+                });
 
-                egui::ComboBox::from_label("Provider")
-                    .selected_text(provider_label(&self.draft.provider))
-                    .show_ui(ui, |ui| {
-                        if ui
-                            .selectable_value(
-                                &mut self.draft.provider,
-                                "ollama".to_string(),
-                                "Ollama 本地",
-                            )
-                            .clicked()
-                        {
-                            apply_provider_defaults(&mut self.draft);
+                egui::TopBottomPanel::bottom("settings_buttons").show(ctx, |ui| {
+                    ui.add_space(4.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("保存").clicked() {
+                            action = Some(self.apply_action());
+                            self.is_open = false;
                         }
                         if ui
-                            .selectable_value(
-                                &mut self.draft.provider,
-                                "kimi".to_string(),
-                                "Kimi for Coding",
-                            )
+                            .button("应用")
+                            .on_hover_text("保存并立即生效，窗口保持打开")
                             .clicked()
                         {
-                            apply_provider_defaults(&mut self.draft);
+                            action = Some(self.apply_action());
+                            self.opened_with = toml::to_string(&self.draft).unwrap_or_default();
+                        }
+                        if ui.button("取消").clicked() {
+                            self.is_open = false;
                         }
                     });
+                    ui.add_space(4.0);
+                });
 
-                ui.add_space(8.0);
+                egui::SidePanel::left("settings_sections")
+                    .resizable(false)
+                    .exact_width(96.0)
+                    .show(ctx, |ui| {
+                        ui.add_space(4.0);
+                        for section in SettingsSection::ALL {
+                            ui.selectable_value(&mut self.section, section, section.label());
+                        }
+                    });
 
-                ui.horizontal(|ui| {
-                    ui.label("API URL");
-                    ui.text_edit_singleline(&mut self.draft.api_url);
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| match self.section {
+                            SettingsSection::Appearance => self.show_appearance(ui),
+                            SettingsSection::Editing => self.show_editing(ui, &mut action),
+                            SettingsSection::Shortcuts => self.show_shortcuts(ui),
+                            SettingsSection::Data => self.show_data(ui, &mut action),
+                            SettingsSection::Ai => self.show_ai(ui, &mut action),
+                            SettingsSection::CurrentFile => self.show_current_file(ui),
+                        });
                 });
 
-                ui.horizontal(|ui| {
-                    ui.label("Model");
-                    ui.text_edit_singleline(&mut self.draft.model_name);
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.is_open = false;
+                }
+            },
+        );
+        action
+    }
+
+    fn apply_action(&self) -> SettingsAction {
+        SettingsAction::Apply {
+            settings: Box::new(self.draft.clone()),
+            file: self.file_draft.as_ref().map(|(_, file)| file.clone()),
+        }
+    }
+
+    fn show_title_bar(&mut self, ui: &mut Ui) {
+        let title_bar_rect = ui.available_rect_before_wrap();
+        let interact = ui.interact(
+            title_bar_rect,
+            ui.id().with("settings_title_bar_drag"),
+            egui::Sense::click_and_drag(),
+        );
+        if interact.dragged() {
+            ui.ctx().send_viewport_cmd(egui::ViewportCommand::StartDrag);
+        }
+
+        ui.horizontal(|ui| {
+            ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
+                ui.label("⚙ 设置");
+            });
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("❌").on_hover_text("关闭").clicked() {
+                    self.is_open = false;
+                }
+            });
+        });
+    }
+
+    /// Slider moving the CJK glyphs of the chosen font up or down
+    fn show_font_y_offset(&mut self, ui: &mut Ui) {
+        let family = match &self.draft.custom_font_path {
+            Some(path) => crate::ui::font::font_file_key(path),
+            None => {
+                let family = self
+                    .draft
+                    .font_family
+                    .clone()
+                    .or_else(|| crate::ui::font::default_font_family().map(str::to_string));
+                match family {
+                    Some(family) => family,
+                    None => return,
+                }
+            }
+        };
+        let offsets = &mut self.draft.font_y_offsets;
+        ui.horizontal(|ui| {
+            ui.label("中文垂直位置");
+            let mut offset = crate::ui::font::y_offset(&family, offsets);
+            if ui
+                .add(egui::Slider::new(&mut offset, FONT_Y_OFFSET_RANGE).step_by(0.01))
+                .changed()
+            {
+                offsets.insert(family.clone(), offset);
+            }
+            if offsets.contains_key(&family) && ui.small_button("恢复默认").clicked() {
+                offsets.remove(&family);
+            }
+        });
+        range_hint(ui, format!("{}：汉字偏上时调大，偏下时调小", family));
+    }
+
+    fn show_appearance(&mut self, ui: &mut Ui) {
+        ui.label(RichText::new("外观").strong());
+        ui.add_space(8.0);
+
+        ui.horizontal(|ui| {
+            ui.label("主题");
+            for (value, label) in THEMES {
+                ui.radio_value(&mut self.draft.theme, value.to_string(), label);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("字号");
+            ui.add(
+                egui::Slider::new(&mut self.draft.font_size, FONT_SIZE_RANGE)
+                    .step_by(1.0)
+                    .suffix(" pt"),
+            );
+        });
+
+        self.show_font_y_offset(ui);
+
+        ui.horizontal(|ui| {
+            ui.label("窗口标题后缀");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.draft.window_title_suffix)
+                    .hint_text("留空只显示文档名")
+                    .desired_width(180.0),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("日期时间格式");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.draft.datetime_format)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(180.0),
+            );
+            if self.draft.datetime_format != DEFAULT_DATETIME_FORMAT
+                && ui.small_button("恢复默认").clicked()
+            {
+                self.draft.datetime_format = DEFAULT_DATETIME_FORMAT.to_string();
+            }
+        });
+        match validate_format(&self.draft.datetime_format) {
+            Ok(()) => range_hint(
+                ui,
+                format!(
+                    "预览：{}",
+                    format_local(&chrono::Local::now(), &self.draft.datetime_format)
+                ),
+            ),
+            Err(e) => {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("{}，保存时将恢复默认格式", e),
+                );
+            }
+        }
+        ui.label(
+            RichText::new("用 chrono 格式书写，如 %Y 年、%m 月、%d 日、%H:%M 时分")
+                .small()
+                .weak(),
+        );
+
+        ui.horizontal(|ui| {
+            ui.label("行距");
+            ui.add(
+                egui::Slider::new(&mut self.draft.line_spacing, LINE_SPACING_RANGE)
+                    .step_by(0.05)
+                    .suffix(" 倍"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            let mut limited = self.draft.line_width > 0.0;
+            if ui.checkbox(&mut limited, "限制行宽").changed() {
+                self.draft.line_width = if limited { 720.0 } else { 0.0 };
+            }
+            if limited {
+                line_width_slider(ui, &mut self.draft.line_width);
+            }
+        });
+
+        ui.checkbox(&mut self.draft.native_decorations, "使用系统标题栏");
+        ui.label(
+            RichText::new(
+                "重启后生效。没有窗口合成器的 Linux 桌面上窗口四角可能显示为黑色，可改用系统标题栏",
+            )
+            .small()
+            .weak(),
+        );
+    }
+
+    fn show_current_file(&mut self, ui: &mut Ui) {
+        ui.label(RichText::new("此文件的设置").strong());
+        ui.add_space(8.0);
+
+        let global = &self.draft;
+        let Some((name, file)) = &mut self.file_draft else {
+            ui.label("当前文档尚未保存，保存后才能单独设置");
+            return;
+        };
+        range_hint(ui, format!("只对「{}」生效，未勾选的项沿用全局设置", name));
+        ui.add_space(4.0);
+
+        override_row(
+            ui,
+            "格式化缩进",
+            &mut file.format_indent,
+            global.format_indent,
+            global.format_indent.label().to_string(),
+            |ui, indent| {
+                egui::ComboBox::from_id_salt("file_format_indent")
+                    .selected_text(indent.label())
+                    .show_ui(ui, |ui| {
+                        for option in FormatIndent::ALL {
+                            ui.selectable_value(indent, option, option.label());
+                        }
+                    });
+            },
+        );
+
+        let global_font = global.font_family.clone();
+        let fonts = &self.fonts;
+        override_row(
+            ui,
+            "字体",
+            &mut file.font_family,
+            global_font
+                .clone()
+                .or_else(|| fonts.first().cloned())
+                .unwrap_or_default(),
+            global_font.unwrap_or_else(|| "系统默认".to_string()),
+            |ui, family| {
+                egui::ComboBox::from_id_salt("file_font_family")
+                    .selected_text(family.as_str())
+                    .show_ui(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .max_height(240.0)
+                            .show(ui, |ui| {
+                                for font in fonts {
+                                    ui.selectable_value(family, font.clone(), font);
+                                }
+                            });
+                    });
+            },
+        );
+
+        override_row(
+            ui,
+            "字号",
+            &mut file.font_size,
+            global.font_size,
+            format!("{} pt", global.font_size),
+            |ui, size| {
+                ui.add(
+                    egui::Slider::new(size, FONT_SIZE_RANGE)
+                        .step_by(1.0)
+                        .suffix(" pt"),
+                );
+            },
+        );
+
+        override_row(
+            ui,
+            "行宽",
+            &mut file.line_width,
+            if global.line_width > 0.0 {
+                global.line_width
+            } else {
+                720.0
+            },
+            if global.line_width > 0.0 {
+                format!("{} pt", global.line_width)
+            } else {
+                "不限".to_string()
+            },
+            line_width_slider,
+        );
+
+        if !file.is_empty() {
+            ui.add_space(8.0);
+            if ui.button("全部恢复为全局设置").clicked() {
+                *file = FileSettings::default();
+            }
+        }
+    }
+
+    fn show_editing(&mut self, ui: &mut Ui, action: &mut Option<SettingsAction>) {
+        ui.label(RichText::new("编辑").strong());
+        ui.add_space(8.0);
+
+        ui.horizontal(|ui| {
+            ui.label("格式化缩进");
+            egui::ComboBox::from_id_salt("format_indent")
+                .selected_text(self.draft.format_indent.label())
+                .show_ui(ui, |ui| {
+                    for indent in FormatIndent::ALL {
+                        ui.selectable_value(&mut self.draft.format_indent, indent, indent.label());
+                    }
                 });
+        });
 
-                ui.horizontal(|ui| {
-                    ui.label("API Key");
-                    ui.add(
-                        egui::TextEdit::singleline(&mut self.draft.api_key)
-                            .password(true)
-                            .hint_text("Ollama 可留空"),
+        ui.horizontal(|ui| {
+            let mut enabled = self.draft.autosave_interval != 0;
+            if ui.checkbox(&mut enabled, "自动保存").changed() {
+                self.draft.autosave_interval = if enabled { 300 } else { 0 };
+            }
+            if enabled {
+                ui.label("每");
+                ui.add(
+                    egui::DragValue::new(&mut self.draft.autosave_interval)
+                        .range(1..=*AUTOSAVE_RANGE.end())
+                        .suffix(" 秒"),
+                );
+                if self.draft.autosave_interval < *AUTOSAVE_RANGE.start() {
+                    range_hint(
+                        ui,
+                        format!("最短 {} 秒，保存时会调整", AUTOSAVE_RANGE.start()),
                     );
+                }
+            }
+        });
+        ui.label(
+            RichText::new("只保存已有文件；新文件仍需手动选择位置")
+                .small()
+                .weak(),
+        );
+
+        ui.label("新文档模板");
+        ui.add(
+            egui::TextEdit::multiline(&mut self.draft.new_file_template)
+                .hint_text("留空则新文档为空白")
+                .desired_rows(3)
+                .desired_width(f32::INFINITY),
+        );
+        let placeholders: Vec<&str> = PLACEHOLDERS.iter().map(|(name, _)| *name).collect();
+        ui.label(
+            RichText::new(format!(
+                "新建文档时填入；{} 替换为当天日期和当前时间。打开已有文件不受影响",
+                placeholders.join("、")
+            ))
+            .small()
+            .weak(),
+        );
+        if !self.draft.new_file_template.is_empty() {
+            let preview = expand_placeholders(
+                &self.draft.new_file_template,
+                chrono::Local::now().naive_local(),
+            );
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.label(RichText::new("预览").small().weak());
+                ui.label(preview);
+            });
+        }
+
+        ui.checkbox(
+            &mut self.draft.auto_reload_external_changes,
+            "文件在外部修改后自动重新载入",
+        );
+        ui.label(
+            RichText::new("只在没有未保存的修改时载入；否则先询问")
+                .small()
+                .weak(),
+        );
+
+        ui.checkbox(&mut self.draft.restore_session, "启动时打开上次的文件");
+        ui.label(
+            RichText::new("没有指定文件时，恢复上次关闭时的标签页和光标位置")
+                .small()
+                .weak(),
+        );
+
+        ui.checkbox(&mut self.draft.single_instance, "在已打开的窗口中打开文件");
+        ui.label(
+            RichText::new("从文件管理器打开的文件交给正在运行的纸壳，不再另开进程。重启后生效")
+                .small()
+                .weak(),
+        );
+        if crate::open_with::can_register() {
+            ui.horizontal(|ui| {
+                ui.label("文件关联");
+                if ui.small_button("加入“打开方式”").clicked() {
+                    *action = Some(SettingsAction::RegisterFileAssociation);
+                }
+            });
+            ui.label(
+                RichText::new(
+                    "让文件管理器用纸壳打开 .txt 和 .md 文件，并打开 papershell:// 链接；移动程序后需重新加入",
+                )
+                    .small()
+                    .weak(),
+            );
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("页数估算");
+            ui.add(
+                egui::DragValue::new(&mut self.draft.chars_per_page)
+                    .range(CHARS_PER_PAGE_RANGE)
+                    .speed(10)
+                    .suffix(" 字/页"),
+            );
+        })
+        .response
+        .on_hover_text("悬停字数统计时显示的页数按此计算");
+
+        ui.horizontal(|ui| {
+            ui.label("打印纸张");
+            egui::ComboBox::from_id_salt("print_page_size")
+                .selected_text(self.draft.print.page_size.label())
+                .show_ui(ui, |ui| {
+                    for size in PageSize::ALL {
+                        ui.selectable_value(&mut self.draft.print.page_size, size, size.label());
+                    }
                 });
+            ui.label("边距");
+            ui.add(
+                egui::DragValue::new(&mut self.draft.print.margin_points)
+                    .range(PRINT_MARGIN_RANGE)
+                    .suffix(" pt"),
+            );
+        });
+        ui.checkbox(&mut self.draft.print.indent_paragraphs, "打印时段首缩进");
+        ui.label(
+            RichText::new("按“格式化缩进”缩进每段；不勾选则段首顶格")
+                .small()
+                .weak(),
+        );
+    }
 
-                ui.add_space(12.0);
-                ui.horizontal(|ui| {
-                    if ui.button("保存").clicked() {
-                        saved = Some(self.draft.clone());
-                        should_close = true;
+    fn show_shortcuts(&mut self, ui: &mut Ui) {
+        ui.label(RichText::new("快捷键").strong());
+        ui.add_space(8.0);
+
+        if let Some(action) = self.capturing {
+            self.capture_combo(ui, action);
+        }
+
+        let conflicts = shortcuts::conflicts(&self.draft.keybindings);
+        egui::Grid::new("keybindings")
+            .num_columns(3)
+            .spacing([12.0, 6.0])
+            .show(ui, |ui| {
+                for action in Action::ALL {
+                    let combo = self.draft.keybindings.get(&action).copied();
+                    ui.label(action.label());
+
+                    let text = match combo {
+                        _ if self.capturing == Some(action) => "请按下组合键…".to_string(),
+                        Some(combo) => ui.ctx().format_shortcut(&combo.shortcut()),
+                        None => "未设置".to_string(),
+                    };
+                    if ui
+                        .selectable_label(self.capturing == Some(action), text)
+                        .on_hover_text("点击后按下新的组合键，Esc 取消")
+                        .clicked()
+                    {
+                        self.capturing = Some(action);
+                        self.capture_hint = None;
                     }
-                    if ui.button("取消").clicked() {
-                        should_close = true;
+
+                    ui.horizontal(|ui| {
+                        if let Some(others) = conflicts.get(&action) {
+                            let names: Vec<&str> = others.iter().map(|a| a.label()).collect();
+                            ui.colored_label(
+                                ui.visuals().error_fg_color,
+                                format!("与「{}」冲突", names.join("」「")),
+                            );
+                        } else if let Some(reserved) = combo.and_then(|c| c.reserved_for()) {
+                            ui.colored_label(
+                                ui.visuals().warn_fg_color,
+                                format!("会覆盖编辑器的「{}」", reserved),
+                            );
+                        }
+                        if combo != Some(action.default_combo())
+                            && ui.small_button("恢复默认").clicked()
+                        {
+                            self.draft
+                                .keybindings
+                                .insert(action, action.default_combo());
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+        if let Some(hint) = self.capture_hint {
+            range_hint(ui, hint.to_string());
+        }
+        ui.add_space(8.0);
+        ui.label(
+            RichText::new("Cmd 在 macOS 上是 Command 键，在其他系统上是 Ctrl 键")
+                .small()
+                .weak(),
+        );
+    }
+
+    /// Take the first key pressed this frame as the new shortcut for `action`.
+    fn capture_combo(&mut self, ui: &mut Ui, action: Action) {
+        let pressed = ui.input_mut(|input| {
+            let pressed = input.events.iter().find_map(|event| match event {
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => Some((*key, *modifiers)),
+                _ => None,
+            });
+            if pressed.is_some() {
+                // Keep Tab or Enter from also moving focus or clicking
+                input.events.clear();
+            }
+            pressed
+        });
+        let Some((key, modifiers)) = pressed else {
+            return;
+        };
+        if key == egui::Key::Escape && modifiers.is_none() {
+            self.capturing = None;
+            self.capture_hint = None;
+        } else if let Some(combo) = KeyCombo::from_press(modifiers, key) {
+            self.draft.keybindings.insert(action, combo);
+            self.capturing = None;
+            self.capture_hint = None;
+        } else {
+            self.capture_hint = Some("需要同时按住 Cmd 或 Alt（F1–F20 除外）");
+        }
+    }
+
+    fn show_data(&mut self, ui: &mut Ui, action: &mut Option<SettingsAction>) {
+        ui.label(RichText::new("数据").strong());
+        ui.add_space(8.0);
+
+        ui.label("新文件默认保存到：");
+        ui.horizontal(|ui| {
+            let current = match &self.draft.default_save_dir {
+                Some(dir) => RichText::new(dir.display().to_string()).monospace(),
+                None => RichText::new("文稿文件夹（默认）").weak(),
+            };
+            ui.add(egui::Label::new(current).truncate());
+            if ui.small_button("选择…").clicked() {
+                let mut dialog = rfd::FileDialog::new();
+                if let Some(dir) = &self.draft.default_save_dir {
+                    dialog = dialog.set_directory(dir);
+                }
+                if let Some(dir) = dialog.pick_folder() {
+                    self.draft.default_save_dir = Some(dir);
+                }
+            }
+            if self.draft.default_save_dir.is_some() && ui.small_button("恢复默认").clicked() {
+                self.draft.default_save_dir = None;
+            }
+        });
+        if self
+            .draft
+            .default_save_dir
+            .as_ref()
+            .is_some_and(|dir| !dir.is_dir())
+        {
+            range_hint(ui, "文件夹不存在，将使用文稿文件夹".to_string());
+        }
+        ui.label(
+            RichText::new("打开和保存对话框会先停在本次最后使用的文件夹")
+                .small()
+                .weak(),
+        );
+        ui.add_space(8.0);
+
+        ui.horizontal(|ui| {
+            ui.label("最近文件最多保留");
+            ui.add(
+                egui::DragValue::new(&mut self.draft.max_recent_files)
+                    .range(RECENT_FILES_RANGE)
+                    .suffix(" 个"),
+            );
+        });
+        ui.add_space(12.0);
+
+        ui.label("历史版本、标记和 AI 记录保存在：");
+        ui.horizontal(|ui| {
+            path_row(ui, &self.data_dir);
+            if ui.small_button("打开").clicked() {
+                *action = Some(SettingsAction::OpenDataFolder);
+            }
+        });
+        ui.add_space(8.0);
+        if let Some(config_path) = &self.config_path {
+            ui.label("设置文件：");
+            path_row(ui, config_path);
+        }
+        ui.add_space(12.0);
+
+        ui.horizontal(|ui| {
+            ui.label("日志级别");
+            ui.add_enabled_ui(!crate::logging::level_from_env(), |ui| {
+                egui::ComboBox::from_id_salt("log_level")
+                    .selected_text(self.draft.log_level.label())
+                    .show_ui(ui, |ui| {
+                        for level in LogLevel::ALL {
+                            ui.selectable_value(&mut self.draft.log_level, level, level.label());
+                        }
+                    });
+            });
+            if ui.small_button("打开日志文件夹").clicked() {
+                *action = Some(SettingsAction::OpenLogFolder);
+            }
+        });
+        let hint = if crate::logging::level_from_env() {
+            "日志级别由环境变量 RUST_LOG 决定"
+        } else {
+            "反馈问题时可附上日志；按 Cmd/Ctrl+Shift+Alt+L 在应用内查看"
+        };
+        ui.label(RichText::new(hint).small().weak());
+        ui.add_space(12.0);
+
+        ui.label("维护：");
+        ui.horizontal(|ui| {
+            let (count, bytes) = crate::ui::font::font_cache_stats();
+            ui.label(format!(
+                "已缓存 {} 个字体（{:.1} MB）",
+                count,
+                bytes as f64 / (1024.0 * 1024.0)
+            ));
+            if ui
+                .add_enabled(count > 0, egui::Button::new("清除字体缓存").small())
+                .clicked()
+            {
+                *action = Some(SettingsAction::ClearFontCache);
+            }
+        });
+        ui.add_space(12.0);
+
+        ui.label("迁移到其他电脑：");
+        ui.horizontal(|ui| {
+            if ui.button("导出设置…").clicked()
+                && let Some(result) = self.export()
+            {
+                self.transfer_result = Some(result.map(|()| "已导出设置".to_string()));
+            }
+            if ui.button("导入设置…").clicked() {
+                match self.import() {
+                    Ok(Some(settings)) => {
+                        self.draft = settings.clone();
+                        *action = Some(SettingsAction::Import(Box::new(settings)));
+                        self.transfer_result = Some(Ok("已导入并应用设置".to_string()));
                     }
-                });
+                    Ok(None) => {}
+                    Err(e) => self.transfer_result = Some(Err(e)),
+                }
+            }
+        });
+        ui.checkbox(
+            &mut self.export_machine_specific,
+            "导出时包含最近文件、默认保存位置和窗口位置",
+        );
+        range_hint(ui, "API Key 不会被导出，导入时保留本机的设置".to_string());
+        match &self.transfer_result {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+            None => {}
+        }
+    }
+
+    /// Write the draft to a file the user picks; `None` if they cancel.
+    fn export(&self) -> Option<Result<(), String>> {
+        let path = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name("paper-shell-settings.json")
+            .save_file()?;
+        let result = export_settings(&self.draft, self.export_machine_specific)
+            .map_err(|e| e.to_string())
+            .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+        Some(result.map_err(|e| format!("导出失败：{}", e)))
+    }
+
+    /// Read settings from a file the user picks; `None` if they cancel.
+    fn import(&self) -> Result<Option<Settings>, String> {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+        else {
+            return Ok(None);
+        };
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("导入失败：{}", e))?;
+        import_settings(&text, &self.draft)
+            .map(Some)
+            .map_err(|e| match e {
+                ConfigError::IncompatibleVersion(version) => format!(
+                    "导入失败：该文件来自更新的版本（设置版本 {}），请先升级 Paper Shell",
+                    version
+                ),
+                e => format!("导入失败：{}", e),
+            })
+    }
+
+    /// Where the API key is kept, with a choice to move it on save
+    fn show_api_key_location(&mut self, ui: &mut Ui) {
+        let ai_panel = &mut self.draft.ai_panel;
+        ui.horizontal(|ui| {
+            ui.label("保存位置");
+            ui.radio_value(&mut ai_panel.api_key_in_keychain, false, "设置文件");
+            ui.add_enabled_ui(self.keychain_available, |ui| {
+                ui.radio_value(&mut ai_panel.api_key_in_keychain, true, "系统钥匙串")
+                    .on_disabled_hover_text("此版本未启用钥匙串支持，或系统钥匙串不可用");
             });
+        });
+        let hint = match (self.key_in_keychain, ai_panel.api_key_in_keychain) {
+            (false, true) => Some("保存后 API Key 将移到系统钥匙串，并从配置文件中删除"),
+            (true, false) => Some("保存后 API Key 将从系统钥匙串移回配置文件"),
+            (true, true) => Some("API Key 保存在系统钥匙串中"),
+            (false, false) if !ai_panel.api_key.is_empty() => {
+                Some("API Key 会以明文保存在本机配置文件中")
+            }
+            (false, false) => None,
+        };
+        if let Some(hint) = hint {
+            let color = if ai_panel.api_key_in_keychain {
+                ui.visuals().weak_text_color()
+            } else {
+                egui::Color32::from_rgb(180, 120, 40)
+            };
+            ui.label(RichText::new(hint).small().color(color));
+        }
+    }
 
-        if should_close {
-            is_open = false;
+    fn show_ai(&mut self, ui: &mut Ui, action: &mut Option<SettingsAction>) {
+        ui.label(RichText::new("AI 助手").strong());
+        ui.add_space(8.0);
+
+        egui::ComboBox::from_label("Provider")
+            .selected_text(provider_label(&self.draft.ai_panel.provider))
+            .show_ui(ui, |ui| {
+                for provider in AiProvider::ALL {
+                    if ui
+                        .selectable_value(
+                            &mut self.draft.ai_panel.provider,
+                            provider.config_value().to_string(),
+                            provider.label(),
+                        )
+                        .clicked()
+                    {
+                        apply_provider_defaults(&mut self.draft.ai_panel);
+                    }
+                }
+            });
+
+        ui.add_space(8.0);
+
+        ui.horizontal(|ui| {
+            ui.label("API URL");
+            ui.text_edit_singleline(&mut self.draft.ai_panel.api_url);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Model");
+            ui.text_edit_singleline(&mut self.draft.ai_panel.model_name);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("API Key");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.draft.ai_panel.api_key)
+                    .password(true)
+                    .hint_text("Ollama 可留空"),
+            );
+        });
+        self.show_api_key_location(ui);
+
+        ui.horizontal(|ui| {
+            let mut custom = self.draft.ai_panel.timeout_secs.is_some();
+            if ui.checkbox(&mut custom, "超时").changed() {
+                self.draft.ai_panel.timeout_secs = custom.then_some(300);
+            }
+            match &mut self.draft.ai_panel.timeout_secs {
+                Some(secs) => {
+                    ui.add(egui::DragValue::new(secs).range(10..=3600).suffix(" 秒"));
+                }
+                None => {
+                    ui.label(RichText::new("本地 Ollama 600 秒，其他 300 秒").weak());
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("选区上限");
+            ui.add(
+                egui::DragValue::new(&mut self.draft.ai_panel.max_prompt_chars)
+                    .range(500..=200_000)
+                    .speed(100)
+                    .suffix(" 字"),
+            );
+            ui.label("超出时");
+            ui.radio_value(
+                &mut self.draft.ai_panel.oversize_strategy,
+                OversizeStrategy::Truncate,
+                "截断",
+            );
+            ui.radio_value(
+                &mut self.draft.ai_panel.oversize_strategy,
+                OversizeStrategy::Chunk,
+                "分段处理",
+            );
+        });
+
+        ui.horizontal(|ui| {
+            let mut custom = self.draft.ai_panel.temperature.is_some();
+            if ui.checkbox(&mut custom, "温度").changed() {
+                self.draft.ai_panel.temperature = custom.then_some(0.7);
+            }
+            match &mut self.draft.ai_panel.temperature {
+                Some(temperature) => {
+                    ui.add(egui::Slider::new(temperature, 0.0..=2.0).step_by(0.05));
+                }
+                None => {
+                    ui.label(RichText::new("服务默认").weak());
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut custom = self.draft.ai_panel.max_output_tokens.is_some();
+            if ui.checkbox(&mut custom, "回复长度上限").changed() {
+                self.draft.ai_panel.max_output_tokens = custom.then_some(1536);
+            }
+            match &mut self.draft.ai_panel.max_output_tokens {
+                Some(tokens) => {
+                    ui.add(
+                        egui::DragValue::new(tokens)
+                            .range(64..=32_768)
+                            .speed(16)
+                            .suffix(" tokens"),
+                    );
+                }
+                None => {
+                    ui.label(RichText::new("服务默认").weak());
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("角色设定");
+            if ui
+                .small_button("恢复默认")
+                .on_hover_text("恢复内置的写作伙伴设定")
+                .clicked()
+            {
+                self.draft.ai_panel.system_instruction = DEFAULT_SYSTEM_INSTRUCTION.to_string();
+            }
+        });
+        ui.add(
+            egui::TextEdit::multiline(&mut self.draft.ai_panel.system_instruction)
+                .desired_rows(3)
+                .desired_width(f32::INFINITY)
+                .hint_text("放在系统提示词开头，留空则使用默认设定"),
+        );
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.testing_connection, egui::Button::new("测试连接"))
+                .clicked()
+            {
+                self.testing_connection = true;
+                self.connection_result = None;
+                *action = Some(SettingsAction::TestConnection(self.draft.ai_panel.clone()));
+            }
+            if self.testing_connection {
+                ui.spinner();
+            } else if let Some(result) = &self.connection_result {
+                match result {
+                    Ok(message) => {
+                        ui.colored_label(egui::Color32::from_rgb(60, 140, 80), message);
+                    }
+                    Err(error) => {
+                        ui.colored_label(egui::Color32::from_rgb(200, 80, 80), error);
+                    }
+                }
+            }
+        });
+
+        ui.add_space(12.0);
+        ui.label(RichText::new("提示词模板").strong());
+        ui.label(RichText::new("用 {text} 表示选中的文字").small().weak());
+        let mut removed = None;
+        egui::ScrollArea::vertical()
+            .max_height(220.0)
+            .show(ui, |ui| {
+                for (index, template) in self.draft.ai_panel.prompt_templates.iter_mut().enumerate()
+                {
+                    ui.push_id(index, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut template.name)
+                                    .desired_width(120.0)
+                                    .hint_text("名称"),
+                            );
+                            if ui.small_button("删除").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                        ui.add(
+                            egui::TextEdit::multiline(&mut template.template)
+                                .desired_rows(2)
+                                .desired_width(f32::INFINITY),
+                        );
+                    });
+                    ui.add_space(4.0);
+                }
+            });
+        if let Some(index) = removed {
+            self.draft.ai_panel.prompt_templates.remove(index);
         }
-        self.is_open = is_open;
-        saved
+        if ui.button("添加模板").clicked() {
+            self.draft
+                .ai_panel
+                .prompt_templates
+                .push(PromptTemplate::new("新模板", "{text}"));
+        }
+
+        ui.add_space(8.0);
+        egui::CollapsingHeader::new("用量与费用").show(ui, |ui| {
+            self.show_usage(ui);
+        });
     }
 }
 
-fn provider_label(provider: &str) -> &'static str {
-    match provider {
-        "kimi" => "Kimi for Coding",
-        _ => "Ollama 本地",
-    }
+/// Valid range or correction note shown next to a field
+fn line_width_slider(ui: &mut Ui, width: &mut f32) {
+    ui.add(
+        egui::Slider::new(width, LINE_WIDTH_RANGE)
+            .step_by(10.0)
+            .suffix(" pt"),
+    );
 }
 
-fn apply_provider_defaults(config: &mut AiPanelConfig) {
-    match config.provider.as_str() {
-        "kimi" => {
-            config.api_url = "https://api.moonshot.ai/v1/chat/completions".to_string();
-            config.model_name = "kimi-k2.7-code".to_string();
+/// A setting the open file can override: a checkbox to override it, then
+/// either the file's value in `edit` or the global value it falls back to.
+fn override_row<T>(
+    ui: &mut Ui,
+    label: &str,
+    value: &mut Option<T>,
+    global: T,
+    global_text: String,
+    edit: impl FnOnce(&mut Ui, &mut T),
+) {
+    ui.horizontal(|ui| {
+        let mut overridden = value.is_some();
+        if ui.checkbox(&mut overridden, label).changed() {
+            *value = overridden.then_some(global);
+        }
+        match value {
+            Some(value) => edit(ui, value),
+            None => {
+                ui.label(RichText::new(format!("沿用全局：{}", global_text)).weak());
+            }
+        }
+    });
+}
+
+fn range_hint(ui: &mut Ui, text: String) {
+    ui.label(RichText::new(text).small().weak());
+}
+
+fn path_row(ui: &mut Ui, path: &std::path::Path) {
+    ui.horizontal(|ui| {
+        ui.add(egui::Label::new(RichText::new(path.display().to_string()).monospace()).truncate());
+        if ui.small_button("复制").clicked() {
+            ui.ctx().copy_text(path.display().to_string());
+        }
+    });
+}
+
+impl SettingsWindow {
+    fn show_usage(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            egui::RichText::new("每百万 tokens 单价，用于估算费用")
+                .small()
+                .weak(),
+        );
+        let mut removed = None;
+        for (index, price) in self.draft.ai_panel.model_prices.iter_mut().enumerate() {
+            ui.push_id(("price", index), |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut price.model)
+                            .desired_width(120.0)
+                            .hint_text("模型名"),
+                    );
+                    ui.label("输入");
+                    ui.add(
+                        egui::DragValue::new(&mut price.input_per_million)
+                            .range(0.0..=1000.0)
+                            .speed(0.01),
+                    );
+                    ui.label("输出");
+                    ui.add(
+                        egui::DragValue::new(&mut price.output_per_million)
+                            .range(0.0..=1000.0)
+                            .speed(0.01),
+                    );
+                    if ui.small_button("删除").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            });
+        }
+        if let Some(index) = removed {
+            self.draft.ai_panel.model_prices.remove(index);
         }
-        _ => {
-            config.provider = "ollama".to_string();
-            config.api_url = "http://localhost:11434/api/chat".to_string();
-            config.model_name = "qwen3:8b".to_string();
+        if ui.button("添加单价").clicked() {
+            self.draft.ai_panel.model_prices.push(ModelPrice {
+                model: self.draft.ai_panel.model_name.clone(),
+                input_per_million: 0.0,
+                output_per_million: 0.0,
+            });
+        }
+
+        ui.add_space(6.0);
+        if self.usage.is_empty() {
+            ui.label(egui::RichText::new("还没有用量记录").weak());
+            return;
         }
+        egui::ScrollArea::vertical()
+            .id_salt("usage_history")
+            .max_height(180.0)
+            .show(ui, |ui| {
+                egui::Grid::new("usage_history_grid")
+                    .striped(true)
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        for header in ["日期", "输入", "输出", "合计", "费用"] {
+                            ui.label(egui::RichText::new(header).strong());
+                        }
+                        ui.end_row();
+
+                        for (date, models) in self.usage.iter().rev().take(USAGE_HISTORY_DAYS) {
+                            let total = day_total(models);
+                            ui.label(date.format("%Y-%m-%d").to_string());
+                            ui.label(format_tokens(total.prompt_tokens));
+                            ui.label(format_tokens(total.completion_tokens));
+                            ui.label(format_tokens(total.total()));
+                            ui.label(
+                                estimate_cost(models, &self.draft.ai_panel.model_prices)
+                                    .map_or("—".to_string(), |cost| format!("{:.2}", cost)),
+                            );
+                            ui.end_row();
+                        }
+                    });
+            });
     }
 }
+
+fn provider_label(provider: &str) -> &'static str {
+    AiProvider::from_config_value(provider)
+        .unwrap_or(AiProvider::Ollama)
+        .label()
+}
+
+fn apply_provider_defaults(config: &mut AiPanelConfig) {
+    let provider = AiProvider::from_config_value(&config.provider).unwrap_or(AiProvider::Ollama);
+    config.provider = provider.config_value().to_string();
+    config.api_url = provider.default_api_url().to_string();
+    config.model_name = provider.default_model().to_string();
+}
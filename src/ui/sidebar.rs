@@ -1,4 +1,5 @@
 use crate::backend::sidebar_backend::Mark;
+use crate::ui::editor::EditorAppearance;
 use egui::{Color32, Galley, Pos2, Rect, Sense, Ui};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -9,6 +10,7 @@ pub struct Sidebar {
     popup_mark: Option<usize>,
     current_uuid: Option<String>,
     marks_changed: bool,
+    appearance: EditorAppearance,
 }
 
 impl Sidebar {
@@ -42,6 +44,31 @@ impl Sidebar {
         self.marks_changed = false;
     }
 
+    /// Add a mark to `line_idx`, or remove its mark again.
+    ///
+    /// A mark with a note or the private flag is not thrown away; its popup
+    /// is opened instead.
+    pub fn toggle_mark(&mut self, line_idx: usize) {
+        match self.marks.get(&line_idx) {
+            None => {
+                self.marks.insert(line_idx, Mark::default());
+                self.marks_changed = true;
+            }
+            Some(mark) if mark.note.trim().is_empty() && !mark.private => {
+                self.marks.remove(&line_idx);
+                if self.popup_mark == Some(line_idx) {
+                    self.popup_mark = None;
+                }
+                self.marks_changed = true;
+            }
+            Some(_) => self.popup_mark = Some(line_idx),
+        }
+    }
+
+    pub fn set_appearance(&mut self, appearance: EditorAppearance) {
+        self.appearance = appearance;
+    }
+
     pub fn show(
         &mut self,
         ui: &mut Ui,
@@ -135,12 +162,11 @@ impl Sidebar {
         // 处理特殊的边界情况：文件末尾有换行符，导致最后有一个空的逻辑行
         // 这个空行在 galley.rows 里通常没有对应的 row
         if is_start_of_logical_line && content.ends_with('\n') {
-            // 估算空行的位置（假设高度和最后一行一样，或者默认值）
-            let line_height = if !galley.rows.is_empty() {
-                galley.rows[0].rect().height()
-            } else {
-                14.0
-            };
+            // 估算空行的位置（假设高度和最后一行一样，否则按当前字号的行高）
+            let line_height = galley
+                .rows
+                .last()
+                .map_or_else(|| self.appearance.row_height(ui), |row| row.rect().height());
             let center_y = last_row_bottom_y + line_height / 2.0;
 
             // 同样检查可见性
@@ -153,8 +179,13 @@ impl Sidebar {
                     egui::Stroke::new(1.0, ui.visuals().text_color().gamma_multiply(0.3)),
                 );
 
-                if self.marks.contains_key(&logical_line_idx) {
-                    painter.circle_filled(center, 4.0, Color32::from_rgb(200, 100, 100));
+                if let Some(mark) = self.marks.get(&logical_line_idx) {
+                    let color = if mark.private {
+                        Color32::from_rgb(90, 110, 150)
+                    } else {
+                        Color32::from_rgb(200, 100, 100)
+                    };
+                    painter.circle_filled(center, 4.0, color);
                 }
 
                 if response.clicked()
@@ -194,9 +225,7 @@ impl Sidebar {
 
             let mut changed = false;
             {
-                let mark_note = self.marks.get_mut(&line_idx).map(|m| &mut m.note);
-
-                if let Some(note) = mark_note {
+                if let Some(mark) = self.marks.get_mut(&line_idx) {
                     egui::Window::new(
                         egui::RichText::new(format!("{} words", words_before)).size(11.0),
                     )
@@ -211,7 +240,8 @@ impl Sidebar {
 
                         if ui
                             .add(
-                                egui::TextEdit::multiline(note)
+                                egui::TextEdit::multiline(&mut mark.note)
+                                    .font(egui::FontId::proportional(self.appearance.font_size))
                                     .desired_rows(8)
                                     .desired_width(f32::INFINITY),
                             )
@@ -219,6 +249,12 @@ impl Sidebar {
                         {
                             changed = true;
                         }
+                        if ui
+                            .checkbox(&mut mark.private, "私密：这一行不发送给 AI")
+                            .changed()
+                        {
+                            changed = true;
+                        }
                     });
                 }
             }
@@ -243,29 +279,7 @@ impl Sidebar {
             byte_count += line.len();
         }
 
-        // Use the same word counting logic
-        let text_before = &content[..byte_count.min(content.len())];
-        let mut count = 0;
-        let mut in_word = false;
-        for c in text_before.chars() {
-            if c.is_whitespace() {
-                in_word = false;
-            } else if is_cjk(c) {
-                count += 1;
-                in_word = false;
-            } else if !in_word {
-                count += 1;
-                in_word = true;
-            }
-        }
-        count
+        // Counted as in the status bar
+        crate::segment::word_count(&content[..byte_count.min(content.len())])
     }
 }
-
-fn is_cjk(c: char) -> bool {
-    ('\u{4E00}'..='\u{9FFF}').contains(&c)
-        || ('\u{3400}'..='\u{4DBF}').contains(&c)
-        || ('\u{20000}'..='\u{2A6DF}').contains(&c)
-        || ('\u{F900}'..='\u{FAFF}').contains(&c)
-        || ('\u{2F800}'..='\u{2FA1F}').contains(&c)
-}
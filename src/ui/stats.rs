@@ -0,0 +1,119 @@
+//! Writing statistics window.
+//!
+//! Shows this week's per-day writing time from the daily log and lets the
+//! user adjust their daily/weekly goals. The log is loaded in the background;
+//! the window shows a spinner until [`StatsWindow::set_log`] is called.
+
+use crate::backend::daily_log::{DayTotals, WeekSummary, week_summary};
+use crate::config::WritingGoals;
+use chrono::NaiveDate;
+use egui::{Context, RichText};
+use std::collections::BTreeMap;
+
+#[derive(Default)]
+pub struct StatsWindow {
+    open: bool,
+    week: Option<WeekSummary>,
+    error: Option<String>,
+    goals: WritingGoals,
+}
+
+impl StatsWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the window in its loading state.
+    pub fn open(&mut self, goals: WritingGoals) {
+        self.open = true;
+        self.week = None;
+        self.error = None;
+        self.goals = goals;
+    }
+
+    /// Fills the window with the loaded daily log.
+    pub fn set_log(
+        &mut self,
+        result: Result<BTreeMap<NaiveDate, DayTotals>, String>,
+        today: NaiveDate,
+    ) {
+        match result {
+            Ok(log) => self.week = Some(week_summary(&log, today)),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Renders the window; returns the goals when the user saves new ones.
+    pub fn show(&mut self, ctx: &Context) -> Option<WritingGoals> {
+        if !self.open {
+            return None;
+        }
+
+        let mut saved = None;
+        let mut open = self.open;
+        egui::Window::new("写作统计")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::from_rgb(200, 80, 80), error);
+                } else if let Some(week) = &self.week {
+                    ui.label(RichText::new("本周").strong());
+                    egui::Grid::new("stats_week_grid")
+                        .num_columns(3)
+                        .spacing([16.0, 4.0])
+                        .show(ui, |ui| {
+                            for (date, totals) in &week.days {
+                                ui.label(date.format("%m-%d %a").to_string());
+                                ui.label(format!("{} 分钟", totals.focused_secs / 60));
+                                ui.label(
+                                    RichText::new(format!("打字 {} 分钟", totals.typing_secs / 60))
+                                        .weak(),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                    ui.separator();
+                    let total_minutes = week.total.focused_secs / 60;
+                    if self.goals.weekly_minutes > 0 {
+                        ui.label(format!(
+                            "合计 {} 分钟 / 目标 {} 分钟",
+                            total_minutes, self.goals.weekly_minutes
+                        ));
+                    } else {
+                        ui.label(format!("合计 {} 分钟", total_minutes));
+                    }
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("正在读取…");
+                    });
+                }
+
+                ui.add_space(8.0);
+                ui.label(RichText::new("目标（0 表示不设）").strong());
+                ui.horizontal(|ui| {
+                    ui.label("每日");
+                    ui.add(
+                        egui::DragValue::new(&mut self.goals.daily_minutes)
+                            .range(0..=1440)
+                            .suffix(" 分钟"),
+                    );
+                    ui.label("每周");
+                    ui.add(
+                        egui::DragValue::new(&mut self.goals.weekly_minutes)
+                            .range(0..=10080)
+                            .suffix(" 分钟"),
+                    );
+                });
+                if ui.button("保存目标").clicked() {
+                    saved = Some(self.goals);
+                }
+            });
+        self.open = open;
+
+        saved
+    }
+}
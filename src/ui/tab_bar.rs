@@ -0,0 +1,61 @@
+//! The strip of open documents under the title bar.
+//!
+//! Each tab shows the document's name with a • while it has unsaved
+//! changes. Clicking a tab switches to it; the ✕ or a middle click closes
+//! it.
+
+use crate::tabs::TabId;
+use crate::ui::title_bar::document_label;
+use egui::{RichText, Ui};
+use std::path::PathBuf;
+
+pub enum TabBarAction {
+    Select(TabId),
+    Close(TabId),
+    /// Open an empty document in a new tab
+    New,
+}
+
+/// What the strip shows for one tab
+pub struct TabInfo {
+    pub id: TabId,
+    pub path: Option<PathBuf>,
+    pub is_dirty: bool,
+}
+
+pub fn show(ui: &mut Ui, tabs: &[TabInfo], active: TabId) -> Option<TabBarAction> {
+    let mut action = None;
+    egui::ScrollArea::horizontal()
+        .id_salt("tab_bar_scroll")
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for tab in tabs {
+                    let label = document_label(tab.path.as_deref(), tab.is_dirty);
+                    let is_active = tab.id == active;
+                    let response = ui.selectable_label(is_active, label);
+                    let response = match &tab.path {
+                        Some(path) => response.on_hover_text(path.display().to_string()),
+                        None => response,
+                    };
+                    if response.clicked() && !is_active {
+                        action = Some(TabBarAction::Select(tab.id));
+                    }
+                    if response.middle_clicked() {
+                        action = Some(TabBarAction::Close(tab.id));
+                    }
+                    if ui
+                        .small_button(RichText::new("✕").small())
+                        .on_hover_text("关闭标签页")
+                        .clicked()
+                    {
+                        action = Some(TabBarAction::Close(tab.id));
+                    }
+                    ui.separator();
+                }
+                if ui.small_button("+").on_hover_text("新建标签页").clicked() {
+                    action = Some(TabBarAction::New);
+                }
+            });
+        });
+    action
+}
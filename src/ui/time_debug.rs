@@ -0,0 +1,83 @@
+//! Hidden diagnostics window for the writing-time tracker.
+//!
+//! Toggled with Cmd/Ctrl + Shift + Alt + T. It lists the tracking thread's
+//! recent decisions so a "the timer shows the wrong number" report can be
+//! traced back to the focus updates and accumulations that produced it.
+
+use crate::backend::time_backend::{TimeBackend, TimeEvent, TimeEventKind};
+use egui::{Context, Key, KeyboardShortcut, Modifiers, RichText};
+
+const TOGGLE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(
+    Modifiers::COMMAND
+        .plus(Modifiers::SHIFT)
+        .plus(Modifiers::ALT),
+    Key::T,
+);
+
+#[derive(Default)]
+pub struct TimeDebugWindow {
+    open: bool,
+    events: Vec<TimeEvent>,
+}
+
+impl TimeDebugWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flips visibility when the toggle chord is pressed.
+    pub fn handle_shortcut(&mut self, ctx: &Context) {
+        if ctx.input_mut(|input| input.consume_shortcut(&TOGGLE_SHORTCUT)) {
+            self.open = !self.open;
+        }
+    }
+
+    /// Renders the window, refreshing the event list from the backend.
+    pub fn show(&mut self, ctx: &Context, time_backend: &TimeBackend) {
+        if !self.open {
+            return;
+        }
+
+        self.events = time_backend.recent_events();
+
+        let mut open = self.open;
+        egui::Window::new("计时诊断")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "未保存计时: {} 秒 · 最近 {} 条事件",
+                    time_backend.get_writing_time(),
+                    self.events.len()
+                ));
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for event in &self.events {
+                            ui.label(
+                                RichText::new(format!(
+                                    "{}  {}",
+                                    event.timestamp.format("%H:%M:%S%.3f"),
+                                    describe_event(event.kind)
+                                ))
+                                .monospace(),
+                            );
+                        }
+                    });
+            });
+        self.open = open;
+    }
+}
+
+fn describe_event(kind: TimeEventKind) -> String {
+    match kind {
+        TimeEventKind::FocusUpdate(true) => "focus gained".to_string(),
+        TimeEventKind::FocusUpdate(false) => "focus lost".to_string(),
+        TimeEventKind::Accumulate(ms) => format!("accumulate +{} ms", ms),
+        TimeEventKind::Flush(ms) => format!("flush {} ms", ms),
+    }
+}
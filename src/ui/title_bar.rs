@@ -1,39 +1,220 @@
+use crate::backend::productivity::ProductivityMetrics;
 use crate::plugin::PluginMetadata;
+use crate::ui::editor::TextStats;
+use crate::ui::font::FontWeight;
+use crate::ui::font_preview::{FontPreviews, PREVIEW_SENTENCE};
+use crate::ui::window_frame::drag_region;
 use egui::{Align, Layout, Ui};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum TitleBarAction {
+    /// Start an empty document in this window
+    NewFile,
+    /// Close the open document, leaving an empty one
+    CloseFile,
     NewWindow,
     Save,
     Open,
     OpenFile(PathBuf),
+    RemoveRecentFile(PathBuf),
+    /// Pin a recent file to the top of the menu, or unpin it
+    TogglePinnedFile(PathBuf),
+    /// Show a file selected in the system file manager
+    RevealFile(PathBuf),
+    ClearRecentFiles,
     History,
     Settings,
     Format,
     FontChange(String),
+    /// A font is hovered in the 字体 menu; load its preview
+    PreviewFont(String),
+    FontWeightChange(FontWeight),
+    /// Pick the font for Latin text; `None` leaves it to the Chinese font
+    LatinFontChange(Option<String>),
+    /// Pick a font file to use instead of an installed font
+    LoadFontFile,
     ToggleAiPanel,
     SearchReplace,
+    /// Open the 全局搜索 palette.
+    GlobalSearch,
     /// Run an installed plugin by its id.
     RunPlugin(String),
     /// Open the configuration window for a built-in plugin.
     ConfigurePlugin(String),
     /// Open the plugins directory in the system file manager.
     OpenPluginsFolder,
+    /// Open the writing statistics window.
+    ShowStats,
+    /// Open the 最近错误 window.
+    ShowErrors,
+    /// Open the 所有批注 window.
+    ShowAllMarks,
+    /// Preview the current document for printing.
+    Print,
+    /// Open the 高频词 window for the current document.
+    ShowFrequentWords,
 }
 
 pub struct TitleBar;
 
+/// What the 字体 menu offers and has selected, taken from [`TitleBarState`]
+#[derive(Clone, Copy)]
+struct FontMenu<'a> {
+    chinese_fonts: &'a [String],
+    fonts_loading: bool,
+    current_font: &'a str,
+    latin_fonts: &'a [String],
+    current_latin_font: Option<&'a str>,
+    font_weight: FontWeight,
+    font_weights: Option<&'a [FontWeight]>,
+    font_file: Option<&'a Path>,
+    previews: &'a FontPreviews,
+}
+
+/// Title bar items that move into the ⋯ menu when the window is too narrow
+/// for them, in the order they move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Collapsible {
+    AiToggle,
+    Font,
+    Edit,
+    Stats,
+}
+
+/// Widths of the title bar's parts as laid out on earlier frames
+#[derive(Clone, Copy, Debug, Default)]
+struct BarWidths {
+    /// Everything that always stays in the bar
+    fixed: f32,
+    /// Each [`Collapsible`] item; 0 until it has been shown once
+    items: [f32; 4],
+    /// The ⋯ button
+    overflow: f32,
+}
+
+impl BarWidths {
+    /// How many items, in [`Collapsible`] order, go into the ⋯ menu so the
+    /// bar fits in `available`
+    fn collapsed(&self, available: f32) -> usize {
+        let mut width = self.fixed + self.items.iter().sum::<f32>();
+        if width <= available {
+            return 0;
+        }
+        width += self.overflow;
+        for (count, item) in self.items.iter().enumerate() {
+            if width <= available {
+                return count;
+            }
+            width -= item;
+        }
+        self.items.len()
+    }
+}
+
+/// Everything the stats popover shows
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DetailedStats {
+    pub text: TextStats,
+    /// Counts for the selected text, if there is a selection
+    pub selection: Option<TextStats>,
+    pub cursor_words: usize,
+    /// Seconds written in this file, and in any file today
+    pub writing_time: u64,
+    pub today_writing_time: u64,
+}
+
+impl DetailedStats {
+    /// Label and value of each line in the popover
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        let text = &self.text;
+        let mut rows = vec![
+            ("字数", text.words.to_string()),
+            ("字符（含空格）", text.chars.to_string()),
+            ("字符（不含空格）", text.chars_without_spaces.to_string()),
+            ("段落", text.paragraphs.to_string()),
+            ("句子", text.sentences.to_string()),
+            ("阅读时间", format_reading_time(text.reading_minutes())),
+            ("光标前字数", self.cursor_words.to_string()),
+        ];
+        if let Some(selection) = &self.selection {
+            rows.push((
+                "选中",
+                format!("{} 字 · {} 字符", selection.words, selection.chars),
+            ));
+        }
+        rows.push((
+            "本文写作时间",
+            TitleBar::format_writing_time(self.writing_time),
+        ));
+        rows.push((
+            "今日写作时间",
+            TitleBar::format_writing_time(self.today_writing_time),
+        ));
+        rows
+    }
+
+    /// The popover's contents as plain text, one figure a line
+    pub fn summary(&self) -> String {
+        self.rows()
+            .into_iter()
+            .map(|(label, value)| format!("{}：{}", label, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn format_reading_time(minutes: f32) -> String {
+    if minutes < 1.0 {
+        "不到 1 分钟".to_string()
+    } else {
+        format!("约 {} 分钟", minutes.round())
+    }
+}
+
+/// Id of the popover opened from the stats label, to check whether it is open
+pub fn stats_popover_id() -> egui::Id {
+    egui::Id::new("title_bar_stats_popover")
+}
+
 pub struct TitleBarState<'a> {
     pub title: &'a str,
-    pub word_count: usize,
-    pub cursor_word_count: usize,
-    pub writing_time: u64,
+    pub stats: DetailedStats,
+    /// Characters a page for the page estimate
+    pub chars_per_page: usize,
+    pub productivity: ProductivityMetrics,
+    pub session_writing_time: u64,
+    pub session_typing_time: u64,
+    /// Fraction of today's goal reached, if a daily goal is set
+    pub goal_progress: Option<f32>,
     pub has_current_file: bool,
+    pub current_file: Option<&'a Path>,
+    /// The document has changes that are not saved
+    pub is_dirty: bool,
+    /// What is running in the background, if anything
+    pub busy_status: Option<String>,
+    /// Errors in the 最近错误 log, and how many of them are new
+    pub errors: usize,
+    pub unseen_errors: usize,
     pub chinese_fonts: &'a [String],
+    /// The font scan is still running; `chinese_fonts` holds the defaults
+    pub fonts_loading: bool,
     pub current_font: &'a str,
+    pub latin_fonts: &'a [String],
+    pub current_latin_font: Option<&'a str>,
+    pub font_weight: FontWeight,
+    /// Weights the current family has; `None` while they are not known
+    pub font_weights: Option<&'a [FontWeight]>,
+    /// Font file in use instead of `current_font`
+    pub font_file: Option<&'a Path>,
+    pub font_previews: &'a FontPreviews,
     pub recent_files: &'a [PathBuf],
+    pub pinned_files: &'a [PathBuf],
     pub is_ai_panel_visible: bool,
     pub plugins: &'a [PluginMetadata],
+    /// The window has no system title bar; the bar moves the window and
+    /// shows its controls
+    pub frameless: bool,
 }
 
 impl TitleBar {
@@ -44,64 +225,137 @@ impl TitleBar {
     ) -> Option<TitleBarAction> {
         let TitleBarState {
             title,
-            word_count,
-            cursor_word_count,
-            writing_time,
+            stats,
+            chars_per_page,
+            productivity,
+            session_writing_time,
+            session_typing_time,
+            goal_progress,
             has_current_file,
+            current_file,
+            is_dirty,
+            busy_status,
+            errors,
+            unseen_errors,
             chinese_fonts,
+            fonts_loading,
             current_font,
+            latin_fonts,
+            current_latin_font,
+            font_weight,
+            font_weights,
+            font_file,
+            font_previews,
             recent_files,
+            pinned_files,
             is_ai_panel_visible,
             plugins,
+            frameless,
         } = state;
+        let fonts = FontMenu {
+            chinese_fonts,
+            fonts_loading,
+            current_font,
+            latin_fonts,
+            current_latin_font,
+            font_weight,
+            font_weights,
+            font_file,
+            previews: font_previews,
+        };
 
         let mut action = None;
         let title_bar_rect = ui.available_rect_before_wrap();
 
-        // Dragging logic - registered BEFORE widgets so they can steal input
-        let interact = ui.interact(
-            title_bar_rect,
-            ui.id().with("title_bar_drag"),
-            egui::Sense::click_and_drag(),
-        );
-        if interact.dragged() {
-            ui.ctx().send_viewport_cmd(egui::ViewportCommand::StartDrag);
-        }
-        if interact.double_clicked() {
-            let is_fullscreen = ui.input(|i| i.viewport().fullscreen.unwrap_or(false));
-            ui.ctx()
-                .send_viewport_cmd(egui::ViewportCommand::Fullscreen(!is_fullscreen));
+        if frameless {
+            // Registered before the widgets so they stay on top of it
+            drag_region(ui, title_bar_rect);
         }
 
+        let spacing = ui.spacing().item_spacing.x;
+        let widths_id = ui.id().with("title_bar_widths");
+        let widths: BarWidths = ui.data(|d| d.get_temp(widths_id)).unwrap_or_default();
+        let collapsed_count = widths.collapsed(title_bar_rect.width());
+        let collapsed = |item: Collapsible| (item as usize) < collapsed_count;
+        let mut measured = widths;
+        let measure = |response: &egui::Response| response.rect.width() + spacing;
+
+        let stats_text = format!(
+            "{} / {} | {}",
+            stats.cursor_words,
+            stats.text.words,
+            Self::format_writing_time(stats.writing_time)
+        );
+        let stats_hover = format!(
+            "本次启动: 专注 {} · 打字 {}\n{}\n{}",
+            Self::format_writing_time(session_writing_time),
+            Self::format_writing_time(session_typing_time),
+            Self::format_productivity(&productivity),
+            Self::format_pages(
+                stats.text.pages(chars_per_page),
+                stats.text.manuscript_pages(),
+                chars_per_page
+            )
+        );
+
         ui.horizontal(|ui| {
             // Title label and actions
-            ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+            let left = ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                let mut collapsible_width = 0.0;
                 ui.label(title);
+                // Leave most of the bar to the buttons; long names are cut short
+                let name_width = (ui.available_width() * 0.25).clamp(48.0, 240.0);
+                ui.scope(|ui| {
+                    ui.set_max_width(name_width);
+                    let name = egui::Label::new(
+                        egui::RichText::new(document_label(current_file, is_dirty)).weak(),
+                    )
+                    .truncate();
+                    let response = ui.add(name);
+                    if let Some(path) = current_file {
+                        response.on_hover_text(path.display().to_string());
+                    }
+                });
                 ui.add_space(16.0);
 
                 ui.menu_button("📂", |ui| {
+                    for path in pinned_files {
+                        Self::file_entry(ui, path, true, &mut action);
+                    }
+                    if !pinned_files.is_empty() {
+                        ui.separator();
+                    }
                     for path in recent_files {
-                        let file_name = path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("Unknown");
-                        let path_str = path.to_string_lossy();
-                        if ui
-                            .button(file_name)
-                            .on_hover_text(path_str.as_ref())
-                            .clicked()
-                        {
-                            action = Some(TitleBarAction::OpenFile(path.clone()));
-                            ui.close();
-                        }
+                        Self::file_entry(ui, path, false, &mut action);
                     }
                     if !recent_files.is_empty() {
+                        if ui.button("清空最近文件").clicked() {
+                            action = Some(TitleBarAction::ClearRecentFiles);
+                            ui.close();
+                        }
                         ui.separator();
                     }
                     if ui.button("Open File...").clicked() {
                         action = Some(TitleBarAction::Open);
                         ui.close();
                     }
+                    if ui
+                        .add_enabled(has_current_file || is_dirty, egui::Button::new("关闭文件"))
+                        .clicked()
+                    {
+                        action = Some(TitleBarAction::CloseFile);
+                        ui.close();
+                    }
+                    if ui.button("打印…").clicked() {
+                        action = Some(TitleBarAction::Print);
+                        ui.close();
+                    }
+                    if let Some(path) = current_file
+                        && ui.button("在文件管理器中显示").clicked()
+                    {
+                        action = Some(TitleBarAction::RevealFile(path.to_path_buf()));
+                        ui.close();
+                    }
                 })
                 .response
                 .on_hover_text("Open");
@@ -111,34 +365,30 @@ impl TitleBar {
                 if ui.button("⚙").on_hover_text("Settings").clicked() {
                     action = Some(TitleBarAction::Settings);
                 }
-                if ui.button("新建").on_hover_text("New Window").clicked() {
-                    action = Some(TitleBarAction::NewWindow);
-                }
-                ui.menu_button("编辑", |ui| {
-                    if ui.button("查找替换").clicked() {
-                        action = Some(TitleBarAction::SearchReplace);
+                ui.menu_button("新建", |ui| {
+                    if ui.button("新建文件").clicked() {
+                        action = Some(TitleBarAction::NewFile);
                         ui.close();
                     }
-                    if ui.button("格式化").clicked() {
-                        action = Some(TitleBarAction::Format);
+                    if ui.button("新窗口").clicked() {
+                        action = Some(TitleBarAction::NewWindow);
                         ui.close();
                     }
                 });
-                ui.menu_button("字体", |ui| {
-                    ui.label("中文:");
-                    ui.separator();
-                    egui::ScrollArea::vertical()
-                        .max_height(300.0)
-                        .show(ui, |ui| {
-                            for font_name in chinese_fonts {
-                                let is_selected = font_name == current_font;
-                                if ui.selectable_label(is_selected, font_name).clicked() {
-                                    action = Some(TitleBarAction::FontChange(font_name.clone()));
-                                    ui.close();
-                                }
-                            }
-                        });
-                });
+                if !collapsed(Collapsible::Edit) {
+                    let response = ui
+                        .menu_button("编辑", |ui| Self::edit_menu(ui, &mut action))
+                        .response;
+                    measured.items[Collapsible::Edit as usize] = measure(&response);
+                    collapsible_width += measure(&response);
+                }
+                if !collapsed(Collapsible::Font) {
+                    let response = ui
+                        .menu_button("字体", |ui| Self::font_menu(ui, &fonts, &mut action))
+                        .response;
+                    measured.items[Collapsible::Font as usize] = measure(&response);
+                    collapsible_width += measure(&response);
+                }
                 ui.menu_button("插件", |ui| {
                     if plugins.is_empty() {
                         ui.label("暂无已安装插件");
@@ -154,13 +404,13 @@ impl TitleBar {
                             }
                         }
 
-                        if plugins.iter().any(|p| p.id == "github_publish") {
-                            if ui.button("配置 GitHub 发布…").clicked() {
-                                action = Some(TitleBarAction::ConfigurePlugin(
-                                    "github_publish".to_string(),
-                                ));
-                                ui.close();
-                            }
+                        if plugins.iter().any(|p| p.id == "github_publish")
+                            && ui.button("配置 GitHub 发布…").clicked()
+                        {
+                            action = Some(TitleBarAction::ConfigurePlugin(
+                                "github_publish".to_string(),
+                            ));
+                            ui.close();
                         }
                     }
                     ui.separator();
@@ -181,14 +431,53 @@ impl TitleBar {
                 {
                     action = Some(TitleBarAction::History);
                 }
+
+                // Whatever did not fit
+                if collapsed_count > 0 {
+                    let response = ui
+                        .menu_button("⋯", |ui| {
+                            if collapsed(Collapsible::AiToggle) {
+                                let label = if is_ai_panel_visible {
+                                    "隐藏 AI 面板"
+                                } else {
+                                    "显示 AI 面板"
+                                };
+                                if ui.button(label).clicked() {
+                                    action = Some(TitleBarAction::ToggleAiPanel);
+                                    ui.close();
+                                }
+                            }
+                            if collapsed(Collapsible::Font) {
+                                ui.menu_button("字体", |ui| {
+                                    Self::font_menu(ui, &fonts, &mut action)
+                                });
+                            }
+                            if collapsed(Collapsible::Edit) {
+                                ui.menu_button("编辑", |ui| Self::edit_menu(ui, &mut action));
+                            }
+                            if collapsed(Collapsible::Stats) {
+                                ui.separator();
+                                if ui.button(&stats_text).on_hover_text(&stats_hover).clicked() {
+                                    action = Some(TitleBarAction::ShowStats);
+                                    ui.close();
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("更多");
+                    measured.overflow = measure(&response);
+                    collapsible_width += measure(&response);
+                }
+                collapsible_width
             });
 
             // Window Controls
-            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            let right = ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                let mut collapsible_width = 0.0;
                 ui.spacing_mut().item_spacing.x = 8.0;
 
-                // Close button
-                if ui.button("❌").on_hover_text("Close").clicked() {
+                // Close button; the system title bar has its own
+                if frameless && ui.button("❌").on_hover_text("Close").clicked() {
                     ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
                 }
 
@@ -200,30 +489,326 @@ impl TitleBar {
                 }
 
                 // Minimize button
-                if ui.button("➖").on_hover_text("Minimize").clicked() {
+                if frameless && ui.button("➖").on_hover_text("Minimize").clicked() {
                     ui.ctx()
                         .send_viewport_cmd(egui::ViewportCommand::Minimized(true));
                 }
 
                 // Stats and AI toggle
                 ui.add_space(16.0);
-                let ai_icon = if is_ai_panel_visible { "[|]" } else { "[ ]" };
-                if ui.label(egui::RichText::new(ai_icon).small()).clicked() {
-                    action = Some(TitleBarAction::ToggleAiPanel);
+                if !collapsed(Collapsible::AiToggle) {
+                    let ai_icon = if is_ai_panel_visible { "[|]" } else { "[ ]" };
+                    let response = ui.label(egui::RichText::new(ai_icon).small());
+                    let width = response.rect.width() + ui.spacing().item_spacing.x;
+                    measured.items[Collapsible::AiToggle as usize] = width;
+                    collapsible_width += width;
+                    if response.clicked() {
+                        action = Some(TitleBarAction::ToggleAiPanel);
+                    }
+                }
+
+                if !collapsed(Collapsible::Stats) {
+                    let stats_response = ui.add(
+                        egui::Label::new(egui::RichText::new(&stats_text).small())
+                            .sense(egui::Sense::click()),
+                    );
+                    let width = stats_response.rect.width() + ui.spacing().item_spacing.x;
+                    measured.items[Collapsible::Stats as usize] = width;
+                    collapsible_width += width;
+                    if let Some(progress) = goal_progress {
+                        // Thin underline that fills up towards today's goal
+                        let rect = stats_response.rect;
+                        let y = rect.bottom() + 1.0;
+                        let painter = ui.painter();
+                        painter.line_segment(
+                            [egui::pos2(rect.left(), y), egui::pos2(rect.right(), y)],
+                            egui::Stroke::new(
+                                1.0,
+                                ui.visuals().widgets.noninteractive.bg_stroke.color,
+                            ),
+                        );
+                        painter.line_segment(
+                            [
+                                egui::pos2(rect.left(), y),
+                                egui::pos2(rect.left() + rect.width() * progress, y),
+                            ],
+                            egui::Stroke::new(1.0, ui.visuals().selection.bg_fill),
+                        );
+                    }
+                    egui::Popup::from_toggle_button_response(&stats_response)
+                        .id(stats_popover_id())
+                        .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside)
+                        .show(|ui| {
+                            if Self::stats_popover(ui, &stats) {
+                                action = Some(TitleBarAction::ShowStats);
+                            }
+                        });
+                    stats_response.on_hover_text(&stats_hover);
+                }
+
+                if let Some(status) = busy_status {
+                    ui.label(egui::RichText::new(status).small().weak());
+                    ui.add(egui::Spinner::new().size(12.0));
                 }
+                if errors > 0 {
+                    let text = egui::RichText::new(format!("⚠ {}", errors)).small();
+                    let text = if unseen_errors > 0 {
+                        text.color(ui.visuals().warn_fg_color)
+                    } else {
+                        text.weak()
+                    };
+                    if ui
+                        .add(egui::Button::new(text).frame(false))
+                        .on_hover_text("最近错误")
+                        .clicked()
+                    {
+                        action = Some(TitleBarAction::ShowErrors);
+                    }
+                }
+                collapsible_width
+            });
 
-                let time_str = Self::format_writing_time(writing_time);
+            measured.fixed = left.response.rect.width() - left.inner + right.response.rect.width()
+                - right.inner
+                + spacing;
+        });
+        ui.data_mut(|d| d.insert_temp(widths_id, measured));
+
+        action
+    }
+
+    /// The detailed figures behind the stats label; true when the full
+    /// statistics window was asked for
+    fn stats_popover(ui: &mut Ui, stats: &DetailedStats) -> bool {
+        let mut show_window = false;
+        egui::Grid::new("stats_popover_grid")
+            .num_columns(2)
+            .spacing([16.0, 4.0])
+            .show(ui, |ui| {
+                for (label, value) in stats.rows() {
+                    ui.label(egui::RichText::new(label).weak());
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("复制").on_hover_text("复制为纯文本").clicked() {
+                ui.ctx().copy_text(stats.summary());
+                egui::Popup::close_id(ui.ctx(), stats_popover_id());
+            }
+            if ui.button("写作统计…").clicked() {
+                show_window = true;
+                egui::Popup::close_id(ui.ctx(), stats_popover_id());
+            }
+        });
+        show_window
+    }
+
+    fn edit_menu(ui: &mut Ui, action: &mut Option<TitleBarAction>) {
+        if ui.button("查找替换").clicked() {
+            *action = Some(TitleBarAction::SearchReplace);
+            ui.close();
+        }
+        if ui.button("全局搜索…").clicked() {
+            *action = Some(TitleBarAction::GlobalSearch);
+            ui.close();
+        }
+        if ui.button("格式化").clicked() {
+            *action = Some(TitleBarAction::Format);
+            ui.close();
+        }
+        if ui.button("所有批注…").clicked() {
+            *action = Some(TitleBarAction::ShowAllMarks);
+            ui.close();
+        }
+        if ui.button("高频词…").clicked() {
+            *action = Some(TitleBarAction::ShowFrequentWords);
+            ui.close();
+        }
+    }
+
+    fn font_menu(ui: &mut Ui, fonts: &FontMenu<'_>, action: &mut Option<TitleBarAction>) {
+        let FontMenu {
+            chinese_fonts,
+            fonts_loading,
+            current_font,
+            latin_fonts,
+            current_latin_font,
+            font_weight,
+            font_weights,
+            font_file,
+            previews,
+        } = *fonts;
+        // Drawn with the fonts in use, so it shows how the pair mixes
+        ui.label(egui::RichText::new(crate::ui::font::PAIRING_PREVIEW).size(16.0));
+        ui.separator();
+        ui.menu_button(
+            format!("西文: {}", current_latin_font.unwrap_or("同中文字体")),
+            |ui| {
+                if ui
+                    .selectable_label(current_latin_font.is_none(), "同中文字体")
+                    .clicked()
+                {
+                    *action = Some(TitleBarAction::LatinFontChange(None));
+                    ui.close();
+                }
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for font_name in latin_fonts {
+                            let is_selected = Some(font_name.as_str()) == current_latin_font;
+                            if Self::font_entry(ui, previews, font_name, is_selected, action) {
+                                *action =
+                                    Some(TitleBarAction::LatinFontChange(Some(font_name.clone())));
+                                ui.close();
+                            }
+                        }
+                    });
+            },
+        );
+        ui.menu_button(format!("字重: {}", font_weight.label()), |ui| {
+            for weight in FontWeight::ALL {
+                let missing = font_weights.is_some_and(|weights| !weights.contains(&weight));
+                let mut label = egui::RichText::new(weight.label());
+                if missing {
+                    label = label.weak();
+                }
+                let mut response = ui.selectable_label(weight == font_weight, label);
+                if missing {
+                    response = response.on_hover_text("此字体没有这个字重，将使用最接近的字重");
+                }
+                if response.clicked() {
+                    *action = Some(TitleBarAction::FontWeightChange(weight));
+                    ui.close();
+                }
+            }
+        });
+        ui.label("中文:");
+        if fonts_loading {
+            ui.horizontal(|ui| {
+                ui.add(egui::Spinner::new().size(12.0));
+                ui.label(egui::RichText::new("正在扫描字体…").weak());
+            });
+        }
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for font_name in chinese_fonts {
+                    let is_selected = font_name == current_font;
+                    if Self::font_entry(ui, previews, font_name, is_selected, action) {
+                        *action = Some(TitleBarAction::FontChange(font_name.clone()));
+                        ui.close();
+                    }
+                }
+            });
+        ui.separator();
+        if let Some(path) = font_file {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            ui.add_enabled(false, egui::Button::selectable(true, file_name))
+                .on_disabled_hover_text(path.to_string_lossy());
+        }
+        if ui.button("加载字体文件…").clicked() {
+            *action = Some(TitleBarAction::LoadFontFile);
+            ui.close();
+        }
+    }
+
+    /// One family in the 字体 menu, drawn in its own typeface once its
+    /// preview is loaded; returns whether it was clicked
+    fn font_entry(
+        ui: &mut Ui,
+        previews: &FontPreviews,
+        font_name: &str,
+        is_selected: bool,
+        action: &mut Option<TitleBarAction>,
+    ) -> bool {
+        let preview = previews.family(ui.ctx(), font_name);
+        let mut text = egui::RichText::new(font_name);
+        if let Some(family) = &preview {
+            text = text.family(family.clone());
+        }
+        let mut response = ui.selectable_label(is_selected, text);
+        if response.hovered() && action.is_none() {
+            *action = Some(TitleBarAction::PreviewFont(font_name.to_string()));
+        }
+        if let Some(family) = preview {
+            response = response.on_hover_ui(|ui| {
                 ui.label(
-                    egui::RichText::new(format!(
-                        "{} / {} | {}",
-                        cursor_word_count, word_count, time_str
-                    ))
-                    .small(),
+                    egui::RichText::new(PREVIEW_SENTENCE)
+                        .family(family)
+                        .size(20.0),
                 );
             });
+        }
+        response.clicked()
+    }
+
+    /// One file in the 📂 menu, with a toggle to pin it to the top
+    fn file_entry(ui: &mut Ui, path: &Path, pinned: bool, action: &mut Option<TitleBarAction>) {
+        ui.horizontal(|ui| {
+            let (star, pin_label) = if pinned {
+                ("★", "取消固定")
+            } else {
+                ("☆", "固定到顶部")
+            };
+            if ui.small_button(star).on_hover_text(pin_label).clicked() {
+                *action = Some(TitleBarAction::TogglePinnedFile(path.to_path_buf()));
+            }
+
+            let file_name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown");
+            let path_str = path.to_string_lossy();
+            // Files on an unmounted drive may come back; keep them, greyed out
+            let exists = path.exists();
+            let response = if exists {
+                ui.button(file_name).on_hover_text(path_str.as_ref())
+            } else {
+                ui.button(egui::RichText::new(file_name).weak())
+                    .on_hover_text(format!("文件不存在：{}", path_str))
+            };
+            if exists && response.clicked() {
+                *action = Some(TitleBarAction::OpenFile(path.to_path_buf()));
+                ui.close();
+            }
+            response.context_menu(|ui| {
+                if ui.button(pin_label).clicked() {
+                    *action = Some(TitleBarAction::TogglePinnedFile(path.to_path_buf()));
+                    ui.close();
+                }
+                if exists && ui.button("在文件管理器中显示").clicked() {
+                    *action = Some(TitleBarAction::RevealFile(path.to_path_buf()));
+                    ui.close();
+                }
+                if ui.button("从列表中移除").clicked() {
+                    *action = Some(TitleBarAction::RemoveRecentFile(path.to_path_buf()));
+                    ui.close();
+                }
+            });
         });
+    }
 
-        action
+    /// Page estimates for the stats label's hover text
+    fn format_pages(pages: f32, manuscript_pages: f32, chars_per_page: usize) -> String {
+        format!(
+            "约 {:.1} 页（每页 {} 字）· 英文稿约 {:.1} 页（每页 {} 词）",
+            pages,
+            chars_per_page,
+            manuscript_pages,
+            crate::ui::editor::WORDS_PER_MANUSCRIPT_PAGE
+        )
+    }
+
+    /// Hover text for the stats label
+    fn format_productivity(metrics: &ProductivityMetrics) -> String {
+        format!(
+            "本次专注: {:+} 字 · {:.0} 字/分钟\n最近 10 分钟: {:.0} 字/分钟",
+            metrics.session_net_words, metrics.session_wpm, metrics.rolling_wpm
+        )
     }
 
     /// Format writing time in seconds to a readable string (MM:SS or HH:MM:SS)
@@ -239,3 +824,66 @@ impl TitleBar {
         }
     }
 }
+
+/// The open document's name as shown in the title bar and window title:
+/// the file stem, followed by a • while there are unsaved changes
+pub fn document_label(path: Option<&Path>, is_dirty: bool) -> String {
+    let name = path
+        .and_then(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "未命名".to_string());
+    if is_dirty {
+        format!("{} •", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_collapse_in_order_as_the_bar_narrows() {
+        let widths = BarWidths {
+            fixed: 400.0,
+            items: [40.0, 50.0, 50.0, 120.0],
+            overflow: 30.0,
+        };
+        assert_eq!(widths.collapsed(660.0), 0);
+        // The AI toggle makes room for ⋯ first
+        assert_eq!(widths.collapsed(659.0), 1);
+        assert_eq!(widths.collapsed(649.0), 2);
+        assert_eq!(widths.collapsed(550.0), 3);
+        assert_eq!(widths.collapsed(549.0), 4);
+        assert_eq!(widths.collapsed(300.0), 4);
+        // Nothing measured yet: show everything
+        assert_eq!(BarWidths::default().collapsed(300.0), 0);
+    }
+
+    #[test]
+    fn test_stats_summary_lists_every_figure() {
+        let stats = DetailedStats {
+            text: TextStats::of("第一段。\n第二段"),
+            selection: Some(TextStats::of("第一")),
+            cursor_words: 3,
+            writing_time: 90,
+            today_writing_time: 3700,
+        };
+        assert_eq!(
+            stats.summary(),
+            "字数：7\n字符（含空格）：7\n字符（不含空格）：7\n段落：2\n句子：2\n\
+             阅读时间：不到 1 分钟\n光标前字数：3\n选中：2 字 · 2 字符\n\
+             本文写作时间：01:30\n今日写作时间：01:01:40"
+        );
+    }
+
+    #[test]
+    fn test_document_label_uses_stem_and_dirty_marker() {
+        let path = Path::new("/home/me/草稿.final.txt");
+        assert_eq!(document_label(Some(path), false), "草稿.final");
+        assert_eq!(document_label(Some(path), true), "草稿.final •");
+        assert_eq!(document_label(None, false), "未命名");
+        assert_eq!(document_label(None, true), "未命名 •");
+    }
+}
@@ -0,0 +1,196 @@
+//! Short notifications about background work.
+//!
+//! Toasts stack in the bottom right corner of the main window. Info and
+//! success toasts fade out after [`TOAST_DURATION`]; errors stay until they
+//! are dismissed, and clicking one copies its message.
+
+use egui::{Align2, Color32, Context, RichText};
+use std::time::{Duration, Instant};
+
+/// How long an info or success toast stays
+pub const TOAST_DURATION: Duration = Duration::from_secs(3);
+/// Part of [`TOAST_DURATION`] spent fading out
+const FADE_DURATION: Duration = Duration::from_millis(500);
+/// Toasts shown at once; the oldest go first
+const MAX_TOASTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastKind {
+    Info,
+    Success,
+    Error,
+}
+
+struct Toast {
+    id: u64,
+    kind: ToastKind,
+    message: String,
+    shown: Instant,
+}
+
+impl Toast {
+    /// Time left before the toast goes; `None` for errors, which stay
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        (self.kind != ToastKind::Error)
+            .then(|| TOAST_DURATION.saturating_sub(now.saturating_duration_since(self.shown)))
+    }
+}
+
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+    next_id: u64,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(ToastKind::Info, message.into());
+    }
+
+    pub fn success(&mut self, message: impl Into<String>) {
+        self.push(ToastKind::Success, message.into());
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.push(ToastKind::Error, message.into());
+    }
+
+    fn push(&mut self, kind: ToastKind, message: String) {
+        // The same error again, e.g. every auto-save failing, is shown once
+        if let Some(toast) = self
+            .toasts
+            .iter_mut()
+            .find(|toast| toast.kind == kind && toast.message == message)
+        {
+            toast.shown = Instant::now();
+            return;
+        }
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.toasts.push(Toast {
+            id: self.next_id,
+            kind,
+            message,
+            shown: Instant::now(),
+        });
+        self.next_id += 1;
+    }
+
+    /// Drop toasts whose time is up
+    fn expire(&mut self, now: Instant) {
+        self.toasts
+            .retain(|toast| toast.remaining(now) != Some(Duration::ZERO));
+    }
+
+    fn dismiss(&mut self, id: u64) {
+        self.toasts.retain(|toast| toast.id != id);
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        self.expire(now);
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        let mut copied = false;
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+            .order(egui::Order::Foreground)
+            .interactable(true)
+            .show(ctx, |ui| {
+                ui.set_max_width(320.0);
+                for toast in &self.toasts {
+                    let remaining = toast.remaining(now);
+                    let opacity = remaining.map_or(1.0, |remaining| {
+                        (remaining.as_secs_f32() / FADE_DURATION.as_secs_f32()).min(1.0)
+                    });
+                    let (icon, color) = match toast.kind {
+                        ToastKind::Info => ("ℹ", ui.visuals().text_color()),
+                        ToastKind::Success => ("✔", Color32::from_rgb(60, 150, 80)),
+                        ToastKind::Error => ("⚠", ui.visuals().error_fg_color),
+                    };
+                    ui.scope(|ui| {
+                        ui.set_opacity(opacity);
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new(icon).color(color));
+                                if toast.kind != ToastKind::Error {
+                                    ui.label(&toast.message);
+                                    return;
+                                }
+                                let message = ui
+                                    .add(
+                                        egui::Label::new(&toast.message)
+                                            .sense(egui::Sense::click()),
+                                    )
+                                    .on_hover_text("点击复制错误信息");
+                                if message.clicked() {
+                                    ctx.copy_text(toast.message.clone());
+                                    copied = true;
+                                }
+                                if ui.small_button("✕").clicked() {
+                                    dismissed = Some(toast.id);
+                                }
+                            });
+                        });
+                    });
+                    ui.add_space(4.0);
+                }
+            });
+
+        if let Some(id) = dismissed {
+            self.dismiss(id);
+        }
+        if copied {
+            self.success("已复制错误信息");
+        }
+        if let Some(next) = self.toasts.iter().filter_map(|t| t.remaining(now)).min() {
+            // Wake up when the next toast starts fading, then animate the fade
+            let wait = next
+                .checked_sub(FADE_DURATION)
+                .filter(|wait| !wait.is_zero())
+                .unwrap_or(Duration::from_millis(30));
+            ctx.request_repaint_after(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_stay_until_dismissed() {
+        let mut toasts = Toasts::new();
+        toasts.success("已保存");
+        toasts.error("保存失败：磁盘已满");
+        toasts.error("保存失败：磁盘已满");
+        assert_eq!(toasts.toasts.len(), 2);
+
+        toasts.expire(Instant::now() + TOAST_DURATION);
+        assert_eq!(toasts.toasts.len(), 1);
+        assert_eq!(toasts.toasts[0].kind, ToastKind::Error);
+
+        toasts.expire(Instant::now() + TOAST_DURATION * 100);
+        assert_eq!(toasts.toasts.len(), 1);
+        toasts.dismiss(toasts.toasts[0].id);
+        assert!(toasts.toasts.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_toast_makes_room() {
+        let mut toasts = Toasts::new();
+        for i in 0..=MAX_TOASTS {
+            toasts.info(format!("第 {} 条", i));
+        }
+        assert_eq!(toasts.toasts.len(), MAX_TOASTS);
+        assert_eq!(toasts.toasts[0].message, "第 1 条");
+    }
+}
@@ -1,20 +1,53 @@
-use crate::constant::{DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_WIDTH};
+use crate::config::WindowGeometry;
+use crate::constant::{MIN_WINDOW_WIDTH, NEW_WINDOW_OFFSET, NEW_WINDOW_POSITION_ENV};
 
 const APP_ICON_RGBA: &[u8] = include_bytes!("../../assets/app-icon-rgba.bin");
 
-pub fn build_viewport() -> eframe::NativeOptions {
+/// Options for the main window; without `native_decorations` it is a
+/// transparent, borderless window that draws its own frame.
+pub fn build_viewport(window: &WindowGeometry, native_decorations: bool) -> eframe::NativeOptions {
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_icon(egui::IconData {
+            rgba: APP_ICON_RGBA.to_vec(),
+            width: 256,
+            height: 256,
+        })
+        .with_inner_size(window.size)
+        .with_min_inner_size([MIN_WINDOW_WIDTH, 0.0])
+        .with_maximized(window.maximized)
+        .with_decorations(native_decorations)
+        .with_transparent(!native_decorations)
+        .with_resizable(true);
+    if let Some(position) = window.position {
+        viewport = viewport.with_position(position);
+    }
+
     eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_icon(egui::IconData {
-                rgba: APP_ICON_RGBA.to_vec(),
-                width: 256,
-                height: 256,
-            })
-            .with_inner_size([DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT])
-            .with_min_inner_size([300.0, 0.0])
-            .with_decorations(false)
-            .with_transparent(true)
-            .with_resizable(true),
+        viewport,
         ..Default::default()
     }
 }
+
+/// Geometry for this launch: the saved one, moved down and to the right of
+/// the opening window when started through 新窗口 so the two don't stack.
+pub fn initial_window_geometry(saved: WindowGeometry) -> WindowGeometry {
+    let mut window = saved;
+    if let Some([x, y]) = std::env::var(NEW_WINDOW_POSITION_ENV)
+        .ok()
+        .as_deref()
+        .and_then(parse_position)
+    {
+        window.position = Some([x + NEW_WINDOW_OFFSET, y + NEW_WINDOW_OFFSET]);
+        window.maximized = false;
+    }
+    window
+}
+
+fn parse_position(value: &str) -> Option<[f32; 2]> {
+    let (x, y) = value.split_once(',')?;
+    let position = [x.trim().parse().ok()?, y.trim().parse().ok()?];
+    position
+        .iter()
+        .all(|value: &f32| value.is_finite())
+        .then_some(position)
+}
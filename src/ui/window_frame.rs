@@ -0,0 +1,222 @@
+//! The main window's own frame when it runs without system decorations.
+//!
+//! The window is transparent and draws a rounded background itself. Apart
+//! from macOS, which shadows and resizes borderless windows on its own, a
+//! thin margin is kept around that background for a drop shadow, and the
+//! margin doubles as the edge that resizes the window. Maximized and
+//! fullscreen windows fill the screen without either.
+
+use crate::style::window_frame;
+use egui::viewport::ResizeDirection;
+use egui::{Color32, Context, CursorIcon, Frame, Pos2, Rect, Sense, Ui, ViewportCommand};
+
+/// Whether the app has to shadow and resize the window itself
+const DRAWS_EDGES: bool = !cfg!(target_os = "macos");
+/// Room around the window background for the shadow and the resize edges
+const EDGE_MARGIN: f32 = 10.0;
+/// How far along an edge from a corner the pointer resizes diagonally
+const CORNER_GRIP: f32 = 16.0;
+
+pub struct WindowFrame {
+    /// The window was created without system decorations
+    frameless: bool,
+}
+
+impl WindowFrame {
+    pub fn new(native_decorations: bool) -> Self {
+        Self {
+            frameless: !native_decorations,
+        }
+    }
+
+    /// The window has no system title bar, so the app provides its controls
+    pub fn is_frameless(&self) -> bool {
+        self.frameless
+    }
+
+    /// The app paints the window background this frame
+    fn is_drawn(&self, ctx: &Context) -> bool {
+        self.frameless
+            && !ctx.input(|i| {
+                let viewport = i.viewport();
+                viewport.maximized.unwrap_or(false) || viewport.fullscreen.unwrap_or(false)
+            })
+    }
+
+    /// Color the surface under the window is cleared to
+    pub fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        if self.frameless {
+            [0.0; 4]
+        } else {
+            visuals.panel_fill.to_normalized_gamma_f32()
+        }
+    }
+
+    /// Paint the window background and handle the resize edges.
+    ///
+    /// Call before any other panel: the margin is reserved with empty panels
+    /// so the app's own panels are laid out inside the background.
+    pub fn show(&self, ctx: &Context) {
+        if !self.is_drawn(ctx) {
+            return;
+        }
+
+        let screen = ctx.content_rect();
+        let background = if DRAWS_EDGES {
+            reserve_margin(ctx);
+            screen.shrink(EDGE_MARGIN)
+        } else {
+            screen
+        };
+        let mut frame = window_frame(&ctx.style().visuals);
+        if !DRAWS_EDGES {
+            frame = frame.shadow(egui::epaint::Shadow::NONE);
+        }
+        ctx.layer_painter(egui::LayerId::background())
+            .add(frame.paint(background));
+
+        if DRAWS_EDGES {
+            handle_resize(ctx, screen);
+        }
+    }
+
+    /// `frame` for one of the main window's panels, without its fill while
+    /// the rounded background shows through
+    pub fn panel(&self, ctx: &Context, frame: Frame) -> Frame {
+        if self.is_drawn(ctx) {
+            frame.fill(Color32::TRANSPARENT)
+        } else {
+            frame
+        }
+    }
+}
+
+fn reserve_margin(ctx: &Context) {
+    egui::TopBottomPanel::top("window_edge_top")
+        .exact_height(EDGE_MARGIN)
+        .frame(Frame::NONE)
+        .show_separator_line(false)
+        .show(ctx, |_| {});
+    egui::TopBottomPanel::bottom("window_edge_bottom")
+        .exact_height(EDGE_MARGIN)
+        .frame(Frame::NONE)
+        .show_separator_line(false)
+        .show(ctx, |_| {});
+    egui::SidePanel::left("window_edge_left")
+        .exact_width(EDGE_MARGIN)
+        .resizable(false)
+        .frame(Frame::NONE)
+        .show_separator_line(false)
+        .show(ctx, |_| {});
+    egui::SidePanel::right("window_edge_right")
+        .exact_width(EDGE_MARGIN)
+        .resizable(false)
+        .frame(Frame::NONE)
+        .show_separator_line(false)
+        .show(ctx, |_| {});
+}
+
+fn handle_resize(ctx: &Context, screen: Rect) {
+    let Some(pos) = ctx.input(|i| i.pointer.hover_pos()) else {
+        return;
+    };
+    // Leave the pointer alone while it drags something, e.g. a splitter
+    if ctx.dragged_id().is_some() {
+        return;
+    }
+    let Some(direction) = resize_direction(screen, pos, EDGE_MARGIN) else {
+        return;
+    };
+    ctx.set_cursor_icon(resize_cursor(direction));
+    if ctx.input(|i| i.pointer.primary_pressed()) {
+        ctx.send_viewport_cmd(ViewportCommand::BeginResize(direction));
+    }
+}
+
+/// Which way the window resizes when the pointer at `pos` is pressed; only
+/// the outer `margin` of `screen` resizes
+fn resize_direction(screen: Rect, pos: Pos2, margin: f32) -> Option<ResizeDirection> {
+    if !screen.contains(pos) || screen.shrink(margin).contains(pos) {
+        return None;
+    }
+    let grip = CORNER_GRIP.max(margin);
+    let west = pos.x < screen.left() + grip;
+    let east = pos.x > screen.right() - grip;
+    let north = pos.y < screen.top() + grip;
+    let south = pos.y > screen.bottom() - grip;
+    Some(match (north, south, west, east) {
+        (true, _, true, _) => ResizeDirection::NorthWest,
+        (true, _, _, true) => ResizeDirection::NorthEast,
+        (_, true, true, _) => ResizeDirection::SouthWest,
+        (_, true, _, true) => ResizeDirection::SouthEast,
+        _ if pos.y < screen.top() + margin => ResizeDirection::North,
+        _ if pos.y > screen.bottom() - margin => ResizeDirection::South,
+        _ if pos.x < screen.left() + margin => ResizeDirection::West,
+        _ => ResizeDirection::East,
+    })
+}
+
+fn resize_cursor(direction: ResizeDirection) -> CursorIcon {
+    match direction {
+        ResizeDirection::North => CursorIcon::ResizeNorth,
+        ResizeDirection::South => CursorIcon::ResizeSouth,
+        ResizeDirection::East => CursorIcon::ResizeEast,
+        ResizeDirection::West => CursorIcon::ResizeWest,
+        ResizeDirection::NorthEast => CursorIcon::ResizeNorthEast,
+        ResizeDirection::SouthEast => CursorIcon::ResizeSouthEast,
+        ResizeDirection::NorthWest => CursorIcon::ResizeNorthWest,
+        ResizeDirection::SouthWest => CursorIcon::ResizeSouthWest,
+    }
+}
+
+/// Make `rect` move the window when dragged and maximize it when
+/// double-clicked.
+///
+/// Call before adding the widgets that sit on top of it. A press that lands
+/// on any of them, including ones that only take clicks, never moves the
+/// window.
+pub fn drag_region(ui: &mut Ui, rect: Rect) {
+    let id = ui.id().with("window_drag_region");
+    let response = ui.interact(rect, id, Sense::click_and_drag());
+    if !(response.drag_started() || response.double_clicked()) {
+        return;
+    }
+    let ctx = ui.ctx();
+    let on_widget = ctx
+        .interaction_snapshot(|i| i.contains_pointer.clone())
+        .into_iter()
+        .filter(|other| *other != id)
+        .filter_map(|other| ctx.read_response(other))
+        .any(|other| other.sense.senses_click() || other.sense.senses_drag());
+    if on_widget {
+        return;
+    }
+    if response.drag_started() {
+        ctx.send_viewport_cmd(ViewportCommand::StartDrag);
+    } else {
+        let maximized = ctx.input(|i| i.viewport().maximized.unwrap_or(false));
+        ctx.send_viewport_cmd(ViewportCommand::Maximized(!maximized));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::pos2;
+
+    #[test]
+    fn test_resize_direction_follows_the_edges() {
+        let screen = Rect::from_min_max(pos2(0.0, 0.0), pos2(800.0, 600.0));
+        let at = |x, y| resize_direction(screen, pos2(x, y), EDGE_MARGIN);
+
+        assert_eq!(at(400.0, 300.0), None);
+        assert_eq!(at(400.0, 2.0), Some(ResizeDirection::North));
+        assert_eq!(at(798.0, 300.0), Some(ResizeDirection::East));
+        assert_eq!(at(400.0, 595.0), Some(ResizeDirection::South));
+        assert_eq!(at(3.0, 300.0), Some(ResizeDirection::West));
+        // Near a corner the edge resizes both ways
+        assert_eq!(at(12.0, 4.0), Some(ResizeDirection::NorthWest));
+        assert_eq!(at(795.0, 590.0), Some(ResizeDirection::SouthEast));
+        assert_eq!(at(900.0, 300.0), None);
+    }
+}
@@ -0,0 +1,239 @@
+//! Words and phrases a draft repeats, for spotting overused ones.
+//!
+//! Latin text counts in words, case folded. CJK text has no spaces to find
+//! words by, so every run of 2 to 4 characters in it is counted, and a
+//! phrase that only ever appears as part of a longer repeated one is left
+//! to the longer one. Function words such as 的 and "the" are left out.
+
+use crate::segment::{Token, is_cjk, tokens};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+/// Shortest and longest CJK phrase counted, in characters
+pub const PHRASE_LENGTHS: RangeInclusive<usize> = 2..=4;
+
+/// Fewest occurrences that make a repeat
+const MIN_COUNT: usize = 2;
+
+/// Particles that end a phrase rather than belong to one; no phrase
+/// containing them is counted
+const CJK_BREAK_CHARS: &str = "的了吗呢吧啊呀嘛哦嗯";
+
+/// Conjunctions and adverbs a phrase does not start or end with
+const CJK_EDGE_CHARS: &str = "和与及或也都就又还很把被让给对从";
+
+const CJK_STOPWORDS: &[&str] = &[
+    "我们", "你们", "他们", "她们", "它们", "自己", "一个", "这个", "那个", "这些", "那些", "这样",
+    "那样", "这么", "那么", "这里", "那里", "什么", "怎么", "没有", "不是", "就是", "还是", "但是",
+    "可是", "因为", "所以", "如果", "虽然", "然后", "已经", "时候", "一些", "可以", "为了", "之后",
+    "以后", "之前", "以前",
+];
+
+const LATIN_STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he",
+    "her", "him", "his", "how", "i", "if", "in", "into", "is", "it", "its", "just", "me", "my",
+    "no", "not", "of", "on", "or", "our", "out", "she", "so", "than", "that", "the", "their",
+    "them", "then", "there", "they", "this", "to", "up", "was", "we", "were", "what", "when",
+    "which", "who", "will", "with", "would", "you", "your",
+];
+
+/// A word or phrase and how often the text has it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrequentWord {
+    pub text: String,
+    pub count: usize,
+    /// A Latin word, which only matches as a whole word; otherwise a CJK
+    /// phrase, which matches anywhere
+    pub latin: bool,
+}
+
+struct Tally {
+    count: usize,
+    /// Byte offset of the first occurrence, for ordering equal counts
+    first: usize,
+}
+
+/// The `limit` most repeated words and phrases of `text`, most frequent first.
+///
+/// Equal counts keep the order the words first appear in.
+pub fn frequent_words(text: &str, limit: usize) -> Vec<FrequentWord> {
+    let mut phrases: HashMap<String, Tally> = HashMap::new();
+    for (offset, run) in cjk_runs(text) {
+        for start in 0..run.len() {
+            for length in PHRASE_LENGTHS {
+                let Some(phrase) = run.get(start..start + length) else {
+                    break;
+                };
+                let tally = phrases.entry(phrase.iter().collect()).or_insert(Tally {
+                    count: 0,
+                    first: offset + phrase_offset(&run[..start]),
+                });
+                tally.count += 1;
+            }
+        }
+    }
+
+    let mut words: HashMap<String, Tally> = HashMap::new();
+    for token in tokens(text) {
+        let Token::Word(word) = token else {
+            continue;
+        };
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if word.chars().count() < 2 || word.chars().all(|c| c.is_numeric()) {
+            continue;
+        }
+        let tally = words.entry(word.to_lowercase()).or_insert(Tally {
+            count: 0,
+            first: word.as_ptr() as usize - text.as_ptr() as usize,
+        });
+        tally.count += 1;
+    }
+
+    let is_content_phrase = |phrase: &str| {
+        let first = phrase.chars().next().unwrap_or_default();
+        let last = phrase.chars().last().unwrap_or_default();
+        !phrase.chars().any(|c| CJK_BREAK_CHARS.contains(c))
+            && !CJK_EDGE_CHARS.contains(first)
+            && !CJK_EDGE_CHARS.contains(last)
+            && !CJK_STOPWORDS.contains(&phrase)
+    };
+    let repeated: HashSet<&str> = phrases
+        .iter()
+        .filter(|(phrase, tally)| tally.count >= MIN_COUNT && is_content_phrase(phrase))
+        .map(|(phrase, _)| phrase.as_str())
+        .collect();
+    // A phrase found exactly as often as a longer one starting or ending
+    // with it only ever appears inside that one
+    let mut subsumed = HashSet::new();
+    for longer in &repeated {
+        let chars: Vec<char> = longer.chars().collect();
+        if chars.len() <= *PHRASE_LENGTHS.start() {
+            continue;
+        }
+        for part in [&chars[1..], &chars[..chars.len() - 1]] {
+            let part: String = part.iter().collect();
+            if phrases[&part].count == phrases[*longer].count {
+                subsumed.insert(part);
+            }
+        }
+    }
+
+    let mut ranked: Vec<(FrequentWord, usize)> = repeated
+        .iter()
+        .filter(|phrase| !subsumed.contains(**phrase))
+        .map(|phrase| {
+            let tally = &phrases[*phrase];
+            (
+                FrequentWord {
+                    text: phrase.to_string(),
+                    count: tally.count,
+                    latin: false,
+                },
+                tally.first,
+            )
+        })
+        .chain(
+            words
+                .into_iter()
+                .filter(|(word, tally)| {
+                    tally.count >= MIN_COUNT && !LATIN_STOPWORDS.contains(&word.as_str())
+                })
+                .map(|(text, tally)| {
+                    (
+                        FrequentWord {
+                            text,
+                            count: tally.count,
+                            latin: true,
+                        },
+                        tally.first,
+                    )
+                }),
+        )
+        .collect();
+    ranked.sort_by(|(a, a_first), (b, b_first)| b.count.cmp(&a.count).then(a_first.cmp(b_first)));
+    ranked.truncate(limit);
+    ranked.into_iter().map(|(word, _)| word).collect()
+}
+
+/// Runs of CJK characters in `text`, with the byte offset each starts at
+fn cjk_runs(text: &str) -> Vec<(usize, Vec<char>)> {
+    let mut runs = Vec::new();
+    let mut current: Option<(usize, Vec<char>)> = None;
+    for (offset, c) in text.char_indices() {
+        if is_cjk(c) {
+            current
+                .get_or_insert_with(|| (offset, Vec::new()))
+                .1
+                .push(c);
+        } else if let Some(run) = current.take() {
+            runs.push(run);
+        }
+    }
+    runs.extend(current);
+    runs
+}
+
+/// Length in bytes of `chars`
+fn phrase_offset(chars: &[char]) -> usize {
+    chars.iter().map(|c| c.len_utf8()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking(text: &str) -> Vec<(String, usize)> {
+        frequent_words(text, 10)
+            .into_iter()
+            .map(|word| (word.text, word.count))
+            .collect()
+    }
+
+    #[test]
+    fn test_ranks_repeated_words_of_a_known_text() {
+        let text = "他说，今天的天气真好。他说，明天的天气也很好。\
+                    她看着窗外，看着远处的山。The rain, the RAIN and the wind. \
+                    天气预报说天气会变。他不由得笑了，她不由得想起 rain。";
+
+        // 不由 and 由得 only ever appear inside 不由得
+        assert_eq!(
+            ranking(text),
+            [
+                ("天气".to_string(), 4),
+                ("rain".to_string(), 3),
+                ("他说".to_string(), 2),
+                ("看着".to_string(), 2),
+                ("不由得".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_words_are_left_out() {
+        // 的 splits phrases, 我们 is a stopword, "the" too
+        let text = "我们的房子，我们的车。the house, the car";
+        assert!(ranking(text).is_empty());
+    }
+
+    #[test]
+    fn test_latin_words_match_whole_and_phrases_anywhere() {
+        let words = frequent_words("Snow, snow! 雪花飘，雪花落。", 10);
+        assert_eq!(
+            words,
+            [
+                FrequentWord {
+                    text: "snow".to_string(),
+                    count: 2,
+                    latin: true,
+                },
+                FrequentWord {
+                    text: "雪花".to_string(),
+                    count: 2,
+                    latin: false,
+                },
+            ]
+        );
+        assert_eq!(frequent_words("雪花飘，雪花落。", 0), []);
+    }
+}
@@ -0,0 +1,123 @@
+//! The maintenance commands, run through the binary on a temporary data dir.
+//! The binary needs the `gui` feature.
+#![cfg(feature = "gui")]
+
+use paper_shell::backend::editor_backend::EditorBackend;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("test_cli_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn paper_shell(data_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_paper-shell"))
+        .arg("--data-dir")
+        .arg(data_dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// A data dir and a file in it with the versions `versions`, oldest first
+fn saved_file(versions: &[&str]) -> (TempDir, PathBuf, Vec<String>) {
+    let temp = TempDir::new();
+    let data_dir = temp.0.join("data");
+    let backend = EditorBackend::with_data_dir(data_dir).unwrap();
+    let file = temp.0.join("草稿.txt");
+    for version in versions {
+        std::fs::write(&file, version).unwrap();
+        backend.save(&file, version, 60).unwrap();
+    }
+    let hashes = backend
+        .load_history(&file)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.hash)
+        .collect();
+    (temp, file, hashes)
+}
+
+#[test]
+fn test_history_and_restore() {
+    let (temp, file, hashes) = saved_file(&["第一稿", "第二稿"]);
+    let data_dir = temp.0.join("data");
+    let file_arg = file.to_str().unwrap();
+
+    let output = paper_shell(&data_dir, &["history", file_arg]);
+    assert!(output.status.success());
+    let lines: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with(&format!("{}  ", hashes[0])));
+    assert!(lines[1].ends_with(file_arg));
+
+    // A unique start of the hash is enough
+    let output = paper_shell(&data_dir, &["restore", file_arg, &hashes[0][..8]]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "第一稿");
+
+    let restored = temp.0.join("restored.txt");
+    let output = paper_shell(
+        &data_dir,
+        &[
+            "restore",
+            file_arg,
+            &hashes[0],
+            "-o",
+            restored.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_to_string(&restored).unwrap(), "第一稿");
+
+    let output = paper_shell(&data_dir, &["restore", file_arg, "zzzz"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a saved version"));
+}
+
+#[test]
+fn test_verify_and_gc() {
+    let (temp, _, hashes) = saved_file(&["只有一稿"]);
+    let data_dir = temp.0.join("data");
+
+    let output = paper_shell(&data_dir, &["verify"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "checked 1 history file(s), 1 version(s), 1 blob(s)\n"
+    );
+
+    std::fs::write(data_dir.join("blobs").join(&hashes[0]), "改过了").unwrap();
+    let output = paper_shell(&data_dir, &["verify"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).starts_with(&format!("corrupt blob {}\n", hashes[0])));
+
+    // The blob is in use, so nothing goes
+    let output = paper_shell(&data_dir, &["gc"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "removed 0 blob(s), freed 0 bytes\n");
+}
+
+#[test]
+fn test_usage_mistakes_exit_with_2() {
+    let temp = TempDir::new();
+    let output = paper_shell(&temp.0, &["restore", "a.txt"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("restore needs <file> <hash>"));
+}
@@ -0,0 +1,69 @@
+//! Saving, listing and restoring versions through the library alone, as
+//! the editor does.
+
+use paper_shell::backend::editor_backend::EditorBackend;
+use paper_shell::diff::{calculate_stats, compute_diff, group_into_rows};
+use std::path::PathBuf;
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("test_history_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_save_history_restore() {
+    let temp = TempDir::new();
+    let data_dir = temp.0.join("data");
+    let file = temp.0.join("草稿.txt");
+    let versions = [
+        "第一稿。\n",
+        "第一稿。\n第二段。\n",
+        "改过的第一稿。\n第二段。\n",
+    ];
+
+    let backend = EditorBackend::with_data_dir(data_dir.clone()).unwrap();
+    for version in versions {
+        std::fs::write(&file, version).unwrap();
+        backend.save(&file, version, 30).unwrap();
+    }
+
+    // The history outlives the backend that wrote it
+    drop(backend);
+    let backend = EditorBackend::with_data_dir(data_dir).unwrap();
+    let history = backend.load_history(&file).unwrap();
+    assert_eq!(history.len(), versions.len());
+    assert!(
+        history
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp)
+    );
+    for (entry, version) in history.iter().zip(versions) {
+        assert_eq!(backend.restore_version(&entry.hash).unwrap(), version);
+        assert_eq!(entry.file_path.as_deref(), Some(file.as_path()));
+    }
+
+    // Rolling back to the first version and saving it again
+    let first = backend.restore_version(&history[0].hash).unwrap();
+    let current = std::fs::read_to_string(&file).unwrap();
+    let stats = calculate_stats(&group_into_rows(&compute_diff(&current, &first)));
+    assert!(stats.removed_count > 0);
+    std::fs::write(&file, &first).unwrap();
+    backend.save(&file, &first, 5).unwrap();
+
+    let history = backend.load_history(&file).unwrap();
+    assert_eq!(history.len(), versions.len() + 1);
+    assert_eq!(history.last().unwrap().hash, history[0].hash);
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), versions[0]);
+    assert!(backend.restore_version("0000000000000000").is_err());
+}